@@ -0,0 +1,106 @@
+use bitcoin::hashes::Hash;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use key_manager::key_type::BitcoinKeyType;
+use protocol_builder::{
+    builder::{Protocol, ProtocolBuilder},
+    scripts::{self, SignMode},
+    testing::TestContext,
+    types::{
+        connection::{InputSpec, OutputSpec},
+        input::SpendMode,
+        output::OutputType,
+    },
+};
+
+// Transactions in the benchmarked protocols, from a short dispute to a large DRP instance
+const SIZES: [usize; 3] = [10, 100, 500];
+
+// EXT -> T0 -> T1 -> ..., each transaction spends a leaf of the taproot output of the previous
+// one, which has a signed leaf and a timelocked leaf as in a dispute round.
+fn chain(tc: &TestContext, size: usize) -> Protocol {
+    let public_key = tc
+        .key_manager()
+        .derive_keypair(BitcoinKeyType::P2wpkh, 0)
+        .unwrap();
+    let taproot_key = tc
+        .key_manager()
+        .derive_keypair(BitcoinKeyType::P2tr, 0)
+        .unwrap();
+    let leaves = [
+        scripts::check_signature(&taproot_key, SignMode::Single),
+        scripts::timelock(144, &taproot_key, SignMode::Skip),
+    ];
+    let output = OutputType::taproot(10_000, &taproot_key, &leaves).unwrap();
+
+    let mut protocol = Protocol::new("bench");
+    ProtocolBuilder {}
+        .add_external_connection(
+            &mut protocol,
+            "EXT",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::segwit_key(20_000, &public_key).unwrap()),
+            "T0",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+        )
+        .unwrap();
+
+    for index in 1..size {
+        protocol
+            .add_connection(
+                &format!("T{}_T{}", index - 1, index),
+                &format!("T{}", index - 1),
+                OutputSpec::Auto(output.clone()),
+                &format!("T{}", index),
+                InputSpec::Auto(tc.tr_sighash_type(), SpendMode::Script { leaf: 0 }),
+                None,
+                None,
+            )
+            .unwrap();
+    }
+
+    protocol
+}
+
+fn bench_build(c: &mut Criterion) {
+    let tc = TestContext::new("bench_build").unwrap();
+    let mut group = c.benchmark_group("build");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let protocol = chain(&tc, size);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(size),
+            &protocol,
+            |b, protocol| b.iter(|| protocol.clone().build(tc.key_manager(), "").unwrap()),
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_build_and_sign(c: &mut Criterion) {
+    let tc = TestContext::new("bench_build_and_sign").unwrap();
+    let mut group = c.benchmark_group("build_and_sign");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let protocol = chain(&tc, size);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(size),
+            &protocol,
+            |b, protocol| {
+                b.iter(|| {
+                    protocol
+                        .clone()
+                        .build_and_sign(tc.key_manager(), "")
+                        .unwrap()
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_build, bench_build_and_sign);
+criterion_main!(benches);
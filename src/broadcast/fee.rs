@@ -0,0 +1,29 @@
+use bitcoincore_rpc::{Client, RpcApi};
+use serde_json::Value;
+
+use crate::errors::BroadcastError;
+
+/// Source of fee rate estimates, used to target a confirmation time when speeding up
+/// transactions, see `ProtocolBuilder::speedup_transactions_for_target`.
+pub trait FeeEstimator {
+    /// Fee rate in sat/vB for a transaction to confirm within the given number of blocks.
+    fn estimate_fee_rate(&self, target_blocks: u16) -> Result<u64, BroadcastError>;
+}
+
+/// Estimates from `estimatesmartfee`, in BTC/kvB, rounded up to whole sat/vB. Nodes without
+/// enough data for the target return no estimate, which is reported as unavailable.
+impl FeeEstimator for Client {
+    fn estimate_fee_rate(&self, target_blocks: u16) -> Result<u64, BroadcastError> {
+        let estimate = self
+            .call::<Value>("estimatesmartfee", &[target_blocks.into()])
+            .map_err(|e| BroadcastError::Unavailable(e.to_string()))?;
+
+        match estimate["feerate"].as_f64() {
+            Some(btc_per_kvb) => Ok((btc_per_kvb * 100_000.0).ceil() as u64),
+            None => Err(BroadcastError::Unavailable(format!(
+                "no fee estimate for {} blocks: {}",
+                target_blocks, estimate["errors"]
+            ))),
+        }
+    }
+}
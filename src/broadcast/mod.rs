@@ -0,0 +1,41 @@
+pub mod fee;
+pub mod package;
+pub mod queue;
+pub mod rules;
+pub mod time;
+
+use bitcoin::{Transaction, Txid};
+use bitvmx_bitcoin_rpc::bitcoin_client::BitcoinClient;
+
+use crate::errors::BroadcastError;
+
+pub use fee::FeeEstimator;
+pub use package::broadcast_sequentially;
+pub use queue::{BroadcastPolicy, BroadcastQueue, BroadcastStatus, QueuedTransaction};
+pub use rules::{enqueue_triggered, triggered_transactions, ChainState};
+pub use time::ChainTime;
+
+/// Node connection used to broadcast protocol transactions.
+pub trait Broadcaster {
+    fn broadcast(&self, transaction: &Transaction) -> Result<Txid, BroadcastError>;
+
+    /// Broadcasts a package of transactions in topological order, e.g. a zero-fee parent and its
+    /// CPFP child. Nodes with package relay accept or reject the whole package at once, by
+    /// default the transactions are broadcast one by one.
+    fn broadcast_package(&self, transactions: &[Transaction]) -> Result<Vec<Txid>, BroadcastError> {
+        broadcast_sequentially(self, transactions)
+    }
+}
+
+/// Broadcasts through the RPC connection of the client, so reject codes of the node are told
+/// apart from connection errors and timeouts, see `Broadcaster for Client`.
+impl Broadcaster for BitcoinClient {
+    fn broadcast(&self, transaction: &Transaction) -> Result<Txid, BroadcastError> {
+        self.client.broadcast(transaction)
+    }
+
+    /// Submits the package with `submitpackage`, see `Broadcaster for Client`.
+    fn broadcast_package(&self, transactions: &[Transaction]) -> Result<Vec<Txid>, BroadcastError> {
+        self.client.broadcast_package(transactions)
+    }
+}
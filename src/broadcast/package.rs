@@ -0,0 +1,78 @@
+use bitcoin::{consensus::encode::serialize_hex, Transaction, Txid};
+use bitcoincore_rpc::{jsonrpc, Client, RpcApi};
+use serde_json::Value;
+
+use crate::errors::BroadcastError;
+
+use super::Broadcaster;
+
+// JSON-RPC error code returned by nodes without the submitpackage RPC
+const RPC_METHOD_NOT_FOUND: i32 = -32601;
+
+/// Broadcasts the transactions of a package one by one, in order. A zero-fee parent is rejected
+/// this way, so it is only a fallback for nodes without package relay.
+pub fn broadcast_sequentially<B: Broadcaster + ?Sized>(
+    broadcaster: &B,
+    transactions: &[Transaction],
+) -> Result<Vec<Txid>, BroadcastError> {
+    transactions
+        .iter()
+        .map(|transaction| broadcaster.broadcast(transaction))
+        .collect()
+}
+
+impl Broadcaster for Client {
+    fn broadcast(&self, transaction: &Transaction) -> Result<Txid, BroadcastError> {
+        self.send_raw_transaction(serialize_hex(transaction))
+            .map(|_| transaction.compute_txid())
+            .map_err(rpc_error)
+    }
+
+    /// Submits the package with `submitpackage`, falling back to a sequential broadcast when the
+    /// node does not support it.
+    fn broadcast_package(&self, transactions: &[Transaction]) -> Result<Vec<Txid>, BroadcastError> {
+        let raw: Vec<String> = transactions.iter().map(serialize_hex).collect();
+
+        match self.call::<Value>("submitpackage", &[raw.into()]) {
+            Ok(result) => package_result(transactions, &result),
+            Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::error::Error::Rpc(error)))
+                if error.code == RPC_METHOD_NOT_FOUND =>
+            {
+                broadcast_sequentially(self, transactions)
+            }
+            Err(e) => Err(rpc_error(e)),
+        }
+    }
+}
+
+// Errors reported by the node are rejections, anything else means it could not be reached
+fn rpc_error(error: bitcoincore_rpc::Error) -> BroadcastError {
+    match error {
+        bitcoincore_rpc::Error::JsonRpc(jsonrpc::error::Error::Rpc(error)) => {
+            BroadcastError::Rejected(error.message)
+        }
+        e => BroadcastError::Unavailable(e.to_string()),
+    }
+}
+
+fn package_result(
+    transactions: &[Transaction],
+    result: &Value,
+) -> Result<Vec<Txid>, BroadcastError> {
+    let message = result["package_msg"].as_str().unwrap_or_default();
+    if message == "success" {
+        return Ok(transactions.iter().map(|tx| tx.compute_txid()).collect());
+    }
+
+    let mut reasons = vec![message.to_string()];
+    if let Some(results) = result["tx-results"].as_object() {
+        reasons.extend(
+            results
+                .values()
+                .filter_map(|tx_result| tx_result["error"].as_str())
+                .map(str::to_string),
+        );
+    }
+
+    Err(BroadcastError::Rejected(reasons.join(", ")))
+}
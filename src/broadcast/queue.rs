@@ -0,0 +1,324 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bitcoin::{Transaction, Txid};
+use serde::{Deserialize, Serialize};
+use storage_backend::storage::{KeyValueStore, Storage};
+use tracing::{debug, warn};
+
+use crate::errors::{BroadcastError, ProtocolBuilderError};
+
+use super::Broadcaster;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum BroadcastStatus {
+    /// Waiting for its next broadcast attempt.
+    Pending,
+    /// Accepted by the node.
+    Broadcasted,
+    /// Rejected by the node more times than allowed by the policy.
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedTransaction {
+    pub txid: Txid,
+    pub transaction: Transaction,
+    pub status: BroadcastStatus,
+    /// Number of attempts rejected by the node.
+    pub attempts: u32,
+    /// Unix time (in seconds) before which the transaction is not broadcasted again.
+    pub next_attempt: u64,
+    /// Rejection reasons returned by the node, in order.
+    pub rejections: Vec<String>,
+    /// Transactions broadcast together with this one, in order, see
+    /// `BroadcastQueue::enqueue_package`. Empty if it is broadcast on its own.
+    #[serde(default)]
+    pub package: Vec<Txid>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BroadcastPolicy {
+    /// Rejections allowed before a transaction is marked as failed.
+    pub max_attempts: u32,
+    /// Seconds to wait after the first rejection, doubled after each following one.
+    pub initial_backoff: u64,
+    pub max_backoff: u64,
+    /// Maximum number of transactions sent to the node on each call to `process`.
+    pub max_per_round: usize,
+}
+
+impl Default for BroadcastPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_backoff: 5,
+            max_backoff: 600,
+            max_per_round: 20,
+        }
+    }
+}
+
+impl BroadcastPolicy {
+    fn backoff(&self, attempts: u32) -> u64 {
+        let exponent = attempts.saturating_sub(1).min(32);
+        self.initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff)
+    }
+}
+
+/// Queue of transactions to broadcast, persisted in the storage backend so pending broadcasts
+/// survive restarts. Transactions are deduplicated by txid and retried with exponential backoff
+/// until the node accepts them or the policy attempts are exhausted.
+pub struct BroadcastQueue {
+    name: String,
+    storage: Rc<Storage>,
+    policy: BroadcastPolicy,
+}
+
+impl BroadcastQueue {
+    pub fn new(name: &str, storage: Rc<Storage>, policy: BroadcastPolicy) -> Self {
+        Self {
+            name: name.to_string(),
+            storage,
+            policy,
+        }
+    }
+
+    /// Adds a transaction to the queue. Returns false if a transaction with the same txid was
+    /// already queued.
+    pub fn enqueue(&self, transaction: &Transaction) -> Result<bool, ProtocolBuilderError> {
+        let mut entries = self.load()?;
+        let txid = transaction.compute_txid();
+
+        if entries.contains_key(&txid) {
+            return Ok(false);
+        }
+
+        entries.insert(
+            txid,
+            QueuedTransaction {
+                txid,
+                transaction: transaction.clone(),
+                status: BroadcastStatus::Pending,
+                attempts: 0,
+                next_attempt: 0,
+                rejections: vec![],
+                package: vec![],
+            },
+        );
+
+        self.save(&entries)?;
+        Ok(true)
+    }
+
+    /// Adds a package of transactions that are broadcast together, such as a zero-fee parent and
+    /// the CPFP child that pays for it. The child goes last and must spend every other
+    /// transaction of the package. Returns false if any of them was already queued.
+    pub fn enqueue_package(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<bool, ProtocolBuilderError> {
+        let (child, parents) = transactions
+            .split_last()
+            .ok_or(ProtocolBuilderError::EmptyBroadcastPackage)?;
+
+        for parent in parents {
+            let txid = parent.compute_txid();
+            if !child
+                .input
+                .iter()
+                .any(|input| input.previous_output.txid == txid)
+            {
+                return Err(ProtocolBuilderError::UnrelatedPackageTransaction(txid));
+            }
+        }
+
+        let mut entries = self.load()?;
+        let package: Vec<Txid> = transactions.iter().map(|tx| tx.compute_txid()).collect();
+        if package.iter().any(|txid| entries.contains_key(txid)) {
+            return Ok(false);
+        }
+
+        for (txid, transaction) in package.iter().zip(transactions) {
+            entries.insert(
+                *txid,
+                QueuedTransaction {
+                    txid: *txid,
+                    transaction: transaction.clone(),
+                    status: BroadcastStatus::Pending,
+                    attempts: 0,
+                    next_attempt: 0,
+                    rejections: vec![],
+                    package: package.clone(),
+                },
+            );
+        }
+
+        self.save(&entries)?;
+        Ok(true)
+    }
+
+    /// Broadcasts the pending transactions whose backoff expired. See `process_at`.
+    pub fn process(&self, broadcaster: &dyn Broadcaster) -> Result<usize, ProtocolBuilderError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        self.process_at(broadcaster, now)
+    }
+
+    /// Broadcasts the pending transactions whose backoff expired at the given unix time, up to
+    /// `max_per_round` of them. Returns the number of transactions accepted by the node. When the
+    /// node is unavailable the round stops and the transaction is retried without counting the
+    /// attempt. The transactions of a package are sent together with `broadcast_package`.
+    pub fn process_at(
+        &self,
+        broadcaster: &dyn Broadcaster,
+        now: u64,
+    ) -> Result<usize, ProtocolBuilderError> {
+        let mut entries = self.load()?;
+        let mut broadcasted = 0;
+
+        let ready: Vec<Txid> = entries
+            .values()
+            .filter(|entry| entry.status == BroadcastStatus::Pending && entry.next_attempt <= now)
+            .take(self.policy.max_per_round)
+            .map(|entry| entry.txid)
+            .collect();
+
+        let mut processed = HashSet::new();
+        for txid in ready {
+            if processed.contains(&txid) {
+                continue;
+            }
+
+            let package = match entries[&txid].package.is_empty() {
+                true => vec![txid],
+                false => entries[&txid].package.clone(),
+            };
+            processed.extend(package.iter().copied());
+
+            let result = match package.len() {
+                1 => broadcaster
+                    .broadcast(&entries[&txid].transaction)
+                    .map(|_| ()),
+                _ => {
+                    let transactions: Vec<Transaction> = package
+                        .iter()
+                        .filter_map(|txid| entries.get(txid))
+                        .map(|entry| entry.transaction.clone())
+                        .collect();
+                    broadcaster.broadcast_package(&transactions).map(|_| ())
+                }
+            };
+
+            match result {
+                Ok(()) => {
+                    debug!("Transactions {:?} broadcasted", package);
+                    for entry in entries
+                        .values_mut()
+                        .filter(|entry| package.contains(&entry.txid))
+                    {
+                        entry.status = BroadcastStatus::Broadcasted;
+                        broadcasted += 1;
+                    }
+                }
+                Err(BroadcastError::Rejected(reason)) => {
+                    warn!("Transactions {:?} rejected: {}", package, reason);
+                    for entry in entries
+                        .values_mut()
+                        .filter(|entry| package.contains(&entry.txid))
+                    {
+                        entry.attempts += 1;
+                        entry.rejections.push(reason.clone());
+                        if entry.attempts >= self.policy.max_attempts {
+                            entry.status = BroadcastStatus::Failed;
+                        } else {
+                            entry.next_attempt = now + self.policy.backoff(entry.attempts);
+                        }
+                    }
+                }
+                Err(BroadcastError::Unavailable(reason)) => {
+                    warn!("Node unavailable broadcasting {:?}: {}", package, reason);
+                    for entry in entries
+                        .values_mut()
+                        .filter(|entry| package.contains(&entry.txid))
+                    {
+                        entry.next_attempt = now + self.policy.initial_backoff;
+                    }
+                    break;
+                }
+            }
+        }
+
+        self.save(&entries)?;
+        Ok(broadcasted)
+    }
+
+    pub fn status(&self, txid: &Txid) -> Result<Option<QueuedTransaction>, ProtocolBuilderError> {
+        Ok(self.load()?.remove(txid))
+    }
+
+    pub fn entries(&self) -> Result<Vec<QueuedTransaction>, ProtocolBuilderError> {
+        Ok(self.load()?.into_values().collect())
+    }
+
+    pub fn pending(&self) -> Result<Vec<QueuedTransaction>, ProtocolBuilderError> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|entry| entry.status == BroadcastStatus::Pending)
+            .collect())
+    }
+
+    /// Marks a failed transaction as pending again, resetting its attempts. The rest of its
+    /// package, if any, is retried with it.
+    pub fn retry(&self, txid: &Txid) -> Result<(), ProtocolBuilderError> {
+        let mut entries = self.load()?;
+        let package = match entries.get(txid) {
+            Some(entry) if entry.package.is_empty() => vec![*txid],
+            Some(entry) => entry.package.clone(),
+            None => Err(ProtocolBuilderError::MissingQueuedTransaction(*txid))?,
+        };
+
+        for entry in entries
+            .values_mut()
+            .filter(|entry| package.contains(&entry.txid))
+        {
+            entry.status = BroadcastStatus::Pending;
+            entry.attempts = 0;
+            entry.next_attempt = 0;
+        }
+
+        self.save(&entries)
+    }
+
+    pub fn remove(&self, txid: &Txid) -> Result<Option<QueuedTransaction>, ProtocolBuilderError> {
+        let mut entries = self.load()?;
+        let removed = entries.remove(txid);
+        self.save(&entries)?;
+        Ok(removed)
+    }
+
+    fn key(&self) -> String {
+        format!("broadcast_queue/{}", self.name)
+    }
+
+    fn load(&self) -> Result<BTreeMap<Txid, QueuedTransaction>, ProtocolBuilderError> {
+        Ok(self.storage.get(self.key())?.unwrap_or_default())
+    }
+
+    fn save(
+        &self,
+        entries: &BTreeMap<Txid, QueuedTransaction>,
+    ) -> Result<(), ProtocolBuilderError> {
+        self.storage.set(self.key(), entries, None)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use bitcoin::Txid;
+use bitcoincore_rpc::{jsonrpc, Client, RpcApi};
+use bitvmx_bitcoin_rpc::bitcoin_client::BitcoinClient;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::{
+    builder::Protocol,
+    errors::{BroadcastError, ProtocolBuilderError},
+    types::{broadcast_rule::BroadcastCondition, InputArgs},
+};
+
+use super::{BroadcastQueue, ChainTime};
+
+/// View of the chain used to evaluate broadcast rules, usually backed by the node or an indexer.
+pub trait ChainState: ChainTime {
+    /// Returns the confirmations of a transaction, `Some(0)` if it is only in the mempool and
+    /// `None` if it has not been seen.
+    fn confirmations(&self, txid: &Txid) -> Result<Option<u32>, BroadcastError>;
+}
+
+// RPC error code of `getrawtransaction` for transactions the node does not know
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+/// Confirmations from `getrawtransaction`, which only finds mined transactions that are not in
+/// the mempool if the node runs with `-txindex`.
+impl ChainState for Client {
+    fn confirmations(&self, txid: &Txid) -> Result<Option<u32>, BroadcastError> {
+        let params = [txid.to_string().into(), true.into()];
+        match self.call::<Value>("getrawtransaction", &params) {
+            Ok(info) => Ok(Some(
+                info["confirmations"].as_u64().unwrap_or_default() as u32
+            )),
+            Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::error::Error::Rpc(error)))
+                if error.code == RPC_INVALID_ADDRESS_OR_KEY =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(BroadcastError::Unavailable(e.to_string())),
+        }
+    }
+}
+
+/// Confirmations from the RPC connection of the client, see `ChainState for Client`.
+impl ChainState for BitcoinClient {
+    fn confirmations(&self, txid: &Txid) -> Result<Option<u32>, BroadcastError> {
+        self.client.confirmations(txid)
+    }
+}
+
+/// Returns the names of the transactions whose broadcast rule conditions hold, whose timelocks
+/// allow them in the next block (see `Protocol::ready_to_send`) and that have not been seen by the
+/// chain yet. Transactions in an alternative branch of a transaction already
+/// seen, or of one triggered before them, are skipped, as at most one branch can be mined.
+pub fn triggered_transactions(
+    protocol: &Protocol,
+    chain: &dyn ChainState,
+) -> Result<Vec<String>, ProtocolBuilderError> {
+    let plan = protocol.execution_plan()?;
+    let mut triggered: Vec<String> = vec![];
+
+    for (name, rule) in protocol.broadcast_rules() {
+        if chain.confirmations(&txid(protocol, name)?)?.is_some() {
+            continue;
+        }
+
+        if let Some(step) = plan.step(name) {
+            let mut seen = false;
+            for other in step.excludes.iter() {
+                if triggered.contains(other)
+                    || chain.confirmations(&txid(protocol, other)?)?.is_some()
+                {
+                    seen = true;
+                    break;
+                }
+            }
+            if seen {
+                debug!(
+                    "Broadcast rule of {} skipped, an alternative branch was chosen",
+                    name
+                );
+                continue;
+            }
+        }
+
+        let mut satisfied = true;
+        for condition in rule.conditions() {
+            if !is_satisfied(protocol, chain, condition)? {
+                satisfied = false;
+                break;
+            }
+        }
+
+        if satisfied && !protocol.ready_to_send(name, chain)? {
+            debug!("Broadcast rule of {} waits for its timelocks", name);
+            satisfied = false;
+        }
+
+        if satisfied {
+            debug!("Broadcast rule of {} triggered", name);
+            triggered.push(name.clone());
+        }
+    }
+
+    Ok(triggered)
+}
+
+/// Adds the triggered transactions to the broadcast queue, built with the input arguments given
+/// for each of them. Returns the names of the transactions that were not already queued.
+pub fn enqueue_triggered(
+    queue: &BroadcastQueue,
+    protocol: &Protocol,
+    chain: &dyn ChainState,
+    args: &HashMap<String, Vec<InputArgs>>,
+) -> Result<Vec<String>, ProtocolBuilderError> {
+    let mut enqueued = vec![];
+
+    for name in triggered_transactions(protocol, chain)? {
+        let input_args = args
+            .get(&name)
+            .ok_or(ProtocolBuilderError::MissingBroadcastArgs(name.clone()))?;
+
+        let transaction = protocol.transaction_to_send(&name, input_args)?;
+        if queue.enqueue(&transaction)? {
+            enqueued.push(name);
+        }
+    }
+
+    Ok(enqueued)
+}
+
+fn is_satisfied(
+    protocol: &Protocol,
+    chain: &dyn ChainState,
+    condition: &BroadcastCondition,
+) -> Result<bool, ProtocolBuilderError> {
+    let satisfied = match condition {
+        BroadcastCondition::Confirmations(name, required) => {
+            matches!(chain.confirmations(&txid(protocol, name)?)?, Some(confirmations) if confirmations >= *required)
+        }
+        BroadcastCondition::NotObserved(name) => {
+            chain.confirmations(&txid(protocol, name)?)?.is_none()
+        }
+        BroadcastCondition::Height(height) => chain.current_height()? >= *height,
+    };
+
+    Ok(satisfied)
+}
+
+/// Txid of a protocol transaction, external ones included.
+pub(crate) fn txid(
+    protocol: &Protocol,
+    transaction_name: &str,
+) -> Result<Txid, ProtocolBuilderError> {
+    match protocol.external_transaction(transaction_name) {
+        Some(external) => Ok(external.txid()),
+        None => Ok(protocol
+            .transaction_by_name(transaction_name)?
+            .compute_txid()),
+    }
+}
@@ -0,0 +1,41 @@
+use bitcoincore_rpc::{Client, RpcApi};
+use bitvmx_bitcoin_rpc::bitcoin_client::BitcoinClient;
+
+use crate::errors::BroadcastError;
+
+/// Source of the chain height and time used by the timelock logic, so it can be backed by the
+/// node or replaced by a mock in tests and simulations.
+pub trait ChainTime {
+    /// Height of the chain tip.
+    fn current_height(&self) -> Result<u32, BroadcastError>;
+
+    /// Median time past of the chain tip (BIP113), the unix time absolute time locks are checked
+    /// against.
+    fn median_time(&self) -> Result<u32, BroadcastError>;
+}
+
+impl ChainTime for Client {
+    fn current_height(&self) -> Result<u32, BroadcastError> {
+        let info = self
+            .get_blockchain_info()
+            .map_err(|e| BroadcastError::Unavailable(e.to_string()))?;
+        Ok(info.blocks as u32)
+    }
+
+    fn median_time(&self) -> Result<u32, BroadcastError> {
+        let info = self
+            .get_blockchain_info()
+            .map_err(|e| BroadcastError::Unavailable(e.to_string()))?;
+        Ok(info.median_time as u32)
+    }
+}
+
+impl ChainTime for BitcoinClient {
+    fn current_height(&self) -> Result<u32, BroadcastError> {
+        self.client.current_height()
+    }
+
+    fn median_time(&self) -> Result<u32, BroadcastError> {
+        self.client.median_time()
+    }
+}
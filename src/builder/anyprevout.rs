@@ -0,0 +1,95 @@
+use bitcoin::secp256k1::Message;
+
+use crate::{
+    errors::ProtocolBuilderError,
+    helpers::anyprevout::{anyprevout_script_sighash, AnyPrevoutSighashType, AnyPrevoutSignature},
+    scripts::ProtocolScript,
+    types::{OutputType, Signer},
+};
+
+use super::Protocol;
+
+impl Protocol {
+    /// BIP118 digest of a leaf of the taproot output spent by an input. Experimental, see
+    /// `helpers::anyprevout`.
+    pub fn anyprevout_sighash(
+        &self,
+        transaction_name: &str,
+        input_index: usize,
+        leaf_index: usize,
+        sighash_type: AnyPrevoutSighashType,
+    ) -> Result<Message, ProtocolBuilderError> {
+        let transaction = self.transaction_by_name(transaction_name)?;
+        let leaf = self.anyprevout_leaf(transaction_name, input_index, leaf_index)?;
+        let prevout = self
+            .graph()
+            .get_prevouts(transaction_name)?
+            .get(input_index)
+            .cloned()
+            .ok_or(ProtocolBuilderError::MissingInput(
+                transaction_name.to_string(),
+                input_index,
+            ))?;
+
+        anyprevout_script_sighash(
+            transaction,
+            input_index,
+            &prevout,
+            leaf.get_script(),
+            sighash_type,
+        )
+    }
+
+    /// Signs a leaf with its verifying key using a BIP118 sighash type. The signature is returned
+    /// and not stored, as the protocol only stores signatures of the regular sighash types.
+    pub fn sign_anyprevout<S: Signer + ?Sized>(
+        &self,
+        transaction_name: &str,
+        input_index: usize,
+        leaf_index: usize,
+        sighash_type: AnyPrevoutSighashType,
+        signer: &S,
+    ) -> Result<AnyPrevoutSignature, ProtocolBuilderError> {
+        let message =
+            self.anyprevout_sighash(transaction_name, input_index, leaf_index, sighash_type)?;
+        let verifying_key = self
+            .anyprevout_leaf(transaction_name, input_index, leaf_index)?
+            .get_verifying_key()
+            .ok_or(ProtocolBuilderError::ScriptSpendSignatureGenerationFailed(
+                transaction_name.to_string(),
+                input_index,
+                leaf_index,
+            ))?;
+
+        Ok(AnyPrevoutSignature {
+            signature: signer.sign_schnorr(&message, &verifying_key)?,
+            sighash_type,
+        })
+    }
+
+    fn anyprevout_leaf(
+        &self,
+        transaction_name: &str,
+        input_index: usize,
+        leaf_index: usize,
+    ) -> Result<ProtocolScript, ProtocolBuilderError> {
+        let inputs = self.inputs(transaction_name)?;
+        let input = inputs
+            .get(input_index)
+            .ok_or(ProtocolBuilderError::MissingInput(
+                transaction_name.to_string(),
+                input_index,
+            ))?;
+
+        match input.output_type()? {
+            OutputType::Taproot { leaves, .. } => leaves
+                .get(leaf_index)
+                .cloned()
+                .ok_or(ProtocolBuilderError::InvalidLeaf(leaf_index)),
+            output => Err(ProtocolBuilderError::InvalidOutputType(
+                "Taproot".to_string(),
+                output.get_name().to_string(),
+            )),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+
+use futures::future::join_all;
+
+use crate::{
+    errors::ProtocolBuilderError,
+    types::{input::Signature, signer::AsyncSigner},
+};
+
+use super::{
+    signing_plan::{InputSigningPlan, JobSignature, SignatureChecks, SignatureJob, SignatureSlot},
+    Protocol,
+};
+
+impl Protocol {
+    /// Async variant of `sign`. The signatures of all the inputs of the protocol are requested
+    /// concurrently and stored once every request completes, so a failing request leaves the
+    /// stored signatures untouched. The protocol must be built. Inputs spending custom outputs
+    /// are skipped, as their outputs sign them with `sign`.
+    pub async fn sign_async<A: AsyncSigner + ?Sized>(
+        &mut self,
+        signer: &A,
+        id: &str,
+    ) -> Result<Self, ProtocolBuilderError> {
+        let plans = self.signing_plans()?;
+        let results = join_all(
+            plans
+                .iter()
+                .map(|plan| request_signatures(plan, signer, id)),
+        )
+        .await;
+
+        let mut checks = SignatureChecks::default();
+        let mut signed = vec![];
+        for (plan, results) in plans.iter().zip(results) {
+            signed.push(plan.assemble(results, &mut checks)?);
+        }
+        checks.verify()?;
+
+        for (plan, (signatures, multisig_signatures)) in plans.iter().zip(signed) {
+            self.store_plan_signatures(plan, signatures, multisig_signatures)?;
+        }
+
+        Ok(self.clone())
+    }
+
+    /// Async variant of `sign_taproot_input` and `sign_ecdsa_input`, signing one input with the
+    /// spend mode it was connected with. Returns the signatures stored for the input.
+    pub async fn sign_input_async<A: AsyncSigner + ?Sized>(
+        &mut self,
+        transaction_name: &str,
+        input_index: usize,
+        signer: &A,
+        id: &str,
+    ) -> Result<Vec<Option<Signature>>, ProtocolBuilderError> {
+        let input = self
+            .graph()
+            .get_inputs(transaction_name)?
+            .get(input_index)
+            .cloned()
+            .ok_or(ProtocolBuilderError::MissingInput(
+                transaction_name.to_string(),
+                input_index,
+            ))?;
+        let plan =
+            InputSigningPlan::new(transaction_name, input_index, &input)?.ok_or_else(|| {
+                ProtocolBuilderError::ExternalSignerError(format!(
+                    "input {} of {} spends a custom output, which signs on its own",
+                    input_index, transaction_name
+                ))
+            })?;
+
+        let mut checks = SignatureChecks::default();
+        let results = request_signatures(&plan, signer, id).await;
+        let (signatures, multisig_signatures) = plan.assemble(results, &mut checks)?;
+        checks.verify()?;
+        self.store_plan_signatures(&plan, signatures.clone(), multisig_signatures)?;
+
+        Ok(signatures)
+    }
+
+    fn store_plan_signatures(
+        &mut self,
+        plan: &InputSigningPlan,
+        signatures: Vec<Option<Signature>>,
+        multisig_signatures: BTreeMap<usize, Vec<Option<Signature>>>,
+    ) -> Result<(), ProtocolBuilderError> {
+        self.store_input_signatures(
+            &plan.transaction_name,
+            plan.input_index,
+            signatures,
+            plan.taproot.then_some(multisig_signatures),
+        )
+    }
+}
+
+// Requests the signatures of the plan concurrently, returning the results in plan order.
+// Multisig slots are only requested for the keys the signer holds.
+#[allow(clippy::type_complexity)]
+async fn request_signatures<A: AsyncSigner + ?Sized>(
+    plan: &InputSigningPlan,
+    signer: &A,
+    id: &str,
+) -> Vec<Result<Option<JobSignature>, ProtocolBuilderError>> {
+    join_all(plan.signatures.iter().map(|planned| async move {
+        if let (SignatureSlot::Multisig { .. }, SignatureJob::Schnorr { key, .. }) =
+            (planned.slot, &planned.job)
+        {
+            if !signer.holds_key(key).await? {
+                return Ok(None);
+            }
+        }
+
+        match &planned.job {
+            SignatureJob::Schnorr { message, key } => signer
+                .sign_schnorr(message, key)
+                .await
+                .map(JobSignature::Schnorr),
+            SignatureJob::SchnorrWithTweak {
+                message,
+                internal_key,
+                merkle_root,
+            } => signer
+                .sign_schnorr_with_tweak(message, internal_key, *merkle_root)
+                .await
+                .map(|(signature, output_key)| {
+                    JobSignature::SchnorrWithTweak(signature, output_key)
+                }),
+            SignatureJob::Aggregated {
+                aggregated_key,
+                message_id,
+            } => signer
+                .get_aggregated_signature(aggregated_key, id, message_id)
+                .await
+                .map(JobSignature::Schnorr),
+            SignatureJob::Ecdsa { message, key } => signer
+                .sign_ecdsa(message, key)
+                .await
+                .map(JobSignature::Ecdsa),
+        }
+        .map(Some)
+    }))
+    .await
+}
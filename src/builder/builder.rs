@@ -1,22 +1,24 @@
-use std::rc::Rc;
-
 use bitcoin::{
-    hashes::Hash, secp256k1::Message, sighash::SighashCache, Address, Amount, EcdsaSighashType,
-    OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+    absolute::LockTime,
+    hashes::{sha256, Hash},
+    secp256k1::Message,
+    sighash::SighashCache,
+    Address, Amount, EcdsaSighashType, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn,
+    TxOut, Txid, Witness,
 };
 use bitcoin_scriptexec::scriptint_vec;
-use key_manager::key_manager::KeyManager;
 use tracing::debug;
 
 use crate::{
-    errors::ProtocolBuilderError,
+    broadcast::FeeEstimator,
+    errors::{GraphError, ProtocolBuilderError, ScriptError},
     graph::graph::GraphOptions,
-    scripts::{self, ProtocolScript},
+    scripts::{self, OpReturnPolicy, ProtocolScript, SignMode},
     types::{
         connection::{InputSpec, OutputSpec},
         input::{SighashType, SpendMode},
-        output::{OutputType, SpeedupData},
-        InputArgs, Utxo,
+        output::{OutputType, SpeedupData, SpeedupKind, UnconfirmedParent},
+        InputArgs, Signer, Utxo,
     },
 };
 
@@ -63,6 +65,8 @@ impl ProtocolBuilder {
         Ok(self)
     }
 
+    /// Adds a P2WPKH output to speed up the transaction, recorded so `Protocol::speedup_data`
+    /// finds it by transaction name.
     pub fn add_speedup_output(
         &self,
         protocol: &mut Protocol,
@@ -70,17 +74,103 @@ impl ProtocolBuilder {
         value: u64,
         speedup_public_key: &PublicKey,
     ) -> Result<&Self, ProtocolBuilderError> {
-        self.add_p2wpkh_output(protocol, transaction_name, value, speedup_public_key)
+        self.add_p2wpkh_output(protocol, transaction_name, value, speedup_public_key)?;
+        record_last_output(protocol, transaction_name)?;
+        Ok(self)
+    }
+
+    /// Adds a speedup output to every transaction spent by another one, skipping external
+    /// transactions, so any of them can be sped up by name with `Protocol::speedup_data`.
+    /// Returns the transactions that got an output, in topological order.
+    pub fn add_speedup_outputs_everywhere(
+        &self,
+        protocol: &mut Protocol,
+        speedup_public_key: &PublicKey,
+        value: u64,
+    ) -> Result<Vec<String>, ProtocolBuilderError> {
+        let leaves = protocol.graph().leaves();
+        let transaction_names: Vec<String> = protocol
+            .graph()
+            .sort()?
+            .into_iter()
+            .filter(|name| !leaves.contains(name))
+            .filter(|name| {
+                protocol
+                    .graph()
+                    .nodes()
+                    .any(|node| node.name == *name && !node.external)
+            })
+            .collect();
+
+        for transaction_name in transaction_names.iter() {
+            self.add_speedup_output(protocol, transaction_name, value, speedup_public_key)?;
+        }
+
+        Ok(transaction_names)
     }
 
+    /// Adds a zero value P2A anchor to speed up the transaction with `SpeedupData::new_anchor`.
+    /// Unlike keyed speedup outputs the anchor needs no key and no signature to be spent. The
+    /// anchor is recorded as the speedup output of the transaction.
+    pub fn add_anchor_output(
+        &self,
+        protocol: &mut Protocol,
+        transaction_name: &str,
+    ) -> Result<&Self, ProtocolBuilderError> {
+        protocol.add_transaction_output(transaction_name, &OutputType::pay_to_anchor())?;
+        record_last_output(protocol, transaction_name)?;
+        Ok(self)
+    }
+
+    /// Adds the data as a single OP_RETURN output, following the relay policy of Bitcoin Core
+    /// before v30. See `add_op_return_output_with_policy` to split larger data.
     pub fn add_op_return_output(
         &self,
         protocol: &mut Protocol,
         transaction_name: &str,
         data: Vec<u8>,
     ) -> Result<&Self, ProtocolBuilderError> {
-        let output_type = OutputType::segwit_unspendable(scripts::op_return(data))?;
-        protocol.add_transaction_output(transaction_name, &output_type)?;
+        self.add_op_return_output_with_policy(
+            protocol,
+            transaction_name,
+            data,
+            OpReturnPolicy::default(),
+        )
+    }
+
+    /// Adds the data in as many OP_RETURN outputs as needed, see `scripts::op_return_chunks`.
+    /// Fails if the OP_RETURN outputs of the transaction would not be standard under `policy`.
+    /// The data can be read back with `scripts::op_return_data`.
+    pub fn add_op_return_output_with_policy(
+        &self,
+        protocol: &mut Protocol,
+        transaction_name: &str,
+        data: Vec<u8>,
+        policy: OpReturnPolicy,
+    ) -> Result<&Self, ProtocolBuilderError> {
+        let (count, used) = protocol
+            .transaction_by_name(transaction_name)
+            .map(|transaction| {
+                transaction
+                    .output
+                    .iter()
+                    .filter(|output| output.script_pubkey.is_op_return())
+                    .fold((0, 0), |(count, used), output| {
+                        (count + 1, used + output.script_pubkey.len())
+                    })
+            })
+            .unwrap_or((0, 0));
+        let outputs = count + data.len().div_ceil(scripts::OP_RETURN_MAX_DATA_SIZE).max(1);
+        if outputs > policy.max_outputs() {
+            return Err(ScriptError::TooManyOpReturnOutputs(outputs, policy.max_outputs()).into());
+        }
+
+        let available = policy.max_total_size().saturating_sub(used);
+        let chunks = scripts::op_return_chunks(&data, available)?;
+        for script in chunks {
+            let output_type = OutputType::segwit_unspendable(script)?;
+            protocol.add_transaction_output(transaction_name, &output_type)?;
+        }
         Ok(self)
     }
 
@@ -128,13 +218,13 @@ impl ProtocolBuilder {
         Ok(self)
     }
 
-    pub fn speedup_transactions(
+    pub fn speedup_transactions<S: Signer + ?Sized>(
         &self,
         speedups_data: &[SpeedupData],
         funding_transaction_utxo: Utxo,
         change_address: &PublicKey,
         speedup_fee: u64,
-        key_manager: &Rc<KeyManager>,
+        signer: &S,
     ) -> Result<Transaction, ProtocolBuilderError> {
         let mut protocol = Protocol::new("speedup_tx");
         debug!(
@@ -142,40 +232,75 @@ impl ProtocolBuilder {
             speedups_data, funding_transaction_utxo, speedup_fee
         );
 
-        for (idx, speedup_data) in speedups_data.iter().enumerate() {
+        let kinds = speedups_data
+            .iter()
+            .map(SpeedupData::kind)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (idx, (speedup_data, kind)) in speedups_data.iter().zip(kinds.iter()).enumerate() {
             let tx_name = &format!("tx_to_speedup_{idx}");
             protocol.add_external_transaction(&tx_name)?;
 
-            if let Some(utxo) = &speedup_data.utxo {
-                protocol.add_unknown_outputs(&tx_name, utxo.vout)?;
-                let external_output = OutputType::segwit_key(utxo.amount, &utxo.pub_key)?;
-                protocol.add_connection(
-                    &format!("speedup_{idx}"),
-                    &tx_name,
-                    external_output.into(),
-                    "cpfp",
+            let (txid, vout, output, input) = match (kind, speedup_data) {
+                (
+                    SpeedupKind::KeyedAnchor,
+                    SpeedupData {
+                        utxo: Some(utxo), ..
+                    },
+                ) => (
+                    utxo.txid,
+                    utxo.vout,
+                    OutputType::segwit_key(utxo.amount, &utxo.pub_key)?,
                     InputSpec::Auto(SighashType::ecdsa_all(), SpendMode::Segwit),
-                    None,
-                    Some(utxo.txid),
-                )?;
-            } else {
-                let partial_utxo = speedup_data.partial_utxo.as_ref().unwrap();
-                protocol.add_unknown_outputs(&tx_name, partial_utxo.1)?;
-                protocol.add_connection(
-                    &format!("speedup_{idx}"),
-                    &tx_name,
-                    speedup_data.output_type.as_ref().unwrap().clone().into(),
-                    "cpfp",
+                ),
+                (
+                    SpeedupKind::PayToAnchor,
+                    SpeedupData {
+                        partial_utxo: Some((txid, vout, _)),
+                        output_type: Some(output_type),
+                        ..
+                    },
+                ) => (
+                    *txid,
+                    *vout,
+                    output_type.clone(),
+                    InputSpec::Auto(SighashType::ecdsa_all(), SpendMode::None),
+                ),
+                (
+                    SpeedupKind::TaprootLeafAnchor,
+                    SpeedupData {
+                        partial_utxo: Some((txid, vout, _)),
+                        output_type: Some(output_type),
+                        leaf_index: Some(leaf),
+                        ..
+                    },
+                ) => (
+                    *txid,
+                    *vout,
+                    output_type.clone(),
                     InputSpec::Auto(
                         SighashType::taproot_all(),
-                        SpendMode::Script {
-                            leaf: speedup_data.leaf_index.unwrap(),
-                        },
+                        SpendMode::Script { leaf: *leaf },
                     ),
-                    None,
-                    Some(partial_utxo.0),
-                )?;
-            }
+                ),
+                _ => {
+                    return Err(ProtocolBuilderError::InvalidSpeedupData(format!(
+                        "speedup {} does not match its kind",
+                        idx
+                    )))
+                }
+            };
+
+            protocol.add_unknown_outputs(&tx_name, vout)?;
+            protocol.add_connection(
+                &format!("speedup_{idx}"),
+                tx_name,
+                output.into(),
+                "cpfp",
+                input,
+                None,
+                Some(txid),
+            )?;
         }
 
         protocol.add_external_transaction("funding")?;
@@ -197,56 +322,121 @@ impl ProtocolBuilder {
         protocol.add_transaction_output(
             "cpfp",
             &OutputType::segwit_key(
-                funding_transaction_utxo.amount - speedup_fee,
+                funding_transaction_utxo
+                    .amount
+                    .checked_sub(speedup_fee)
+                    .ok_or(ProtocolBuilderError::InsufficientFunds(
+                        funding_transaction_utxo.amount,
+                        speedup_fee,
+                    ))?,
                 change_address,
             )?,
         )?;
 
-        protocol.build_and_sign(key_manager, "id")?;
+        protocol.build_and_sign(signer, "id")?;
 
         let mut args_for_all_inputs = vec![];
 
-        let total = speedups_data.len() + 1; // +1 for the funding input
-
-        for idx in 0..total {
-            if idx < speedups_data.len() {
-                let speedup_data = &speedups_data[idx];
-                if speedup_data.utxo.is_none() {
-                    let leaf_index = speedup_data.leaf_index.unwrap();
+        for (idx, (speedup_data, kind)) in speedups_data.iter().zip(kinds.iter()).enumerate() {
+            let spending_args = match (kind, speedup_data.leaf_index) {
+                // P2A anchors are spent with an empty witness
+                (SpeedupKind::PayToAnchor, _) => InputArgs::new_segwit_args(),
+                (SpeedupKind::TaprootLeafAnchor, Some(leaf_index)) => {
                     let signature = protocol
                         .input_taproot_script_spend_signature("cpfp", idx, leaf_index)?
-                        .unwrap();
+                        .ok_or(GraphError::MissingSignature)?;
                     let mut spending_args = InputArgs::new_taproot_script_args(leaf_index);
-                    for wots in speedup_data.wots_sigs.as_ref().unwrap().iter() {
+                    for wots in speedup_data.wots_sigs.iter().flatten() {
                         spending_args.push_winternitz_signature(wots.clone());
                     }
                     spending_args.push_taproot_signature(signature)?;
                     if speedup_data.leaf_identification {
                         spending_args.push_slice(scriptint_vec(leaf_index as i64).as_slice());
                     }
-                    args_for_all_inputs.push(spending_args);
-
-                    continue;
+                    spending_args
                 }
-            }
-            let signature = protocol.input_ecdsa_signature("cpfp", idx)?.unwrap();
-            let mut spending_args = InputArgs::new_segwit_args();
-            spending_args.push_ecdsa_signature(signature)?;
+                _ => InputArgs::from_protocol(&protocol, "cpfp", idx, None)?,
+            };
             args_for_all_inputs.push(spending_args);
         }
+
+        // The funding input follows the speedup inputs
+        args_for_all_inputs.push(InputArgs::from_protocol(
+            &protocol,
+            "cpfp",
+            speedups_data.len(),
+            None,
+        )?);
         debug!("{}", protocol.visualize(GraphOptions::Default)?);
 
         let result = protocol.transaction_to_send("cpfp", &args_for_all_inputs)?;
         Ok(result)
     }
 
-    pub fn speedup_transactions_old(
+    /// Same as `speedup_transactions`, with the fee set so the package of the CPFP child and its
+    /// unconfirmed parents pays the fee rate the estimator gives to confirm within
+    /// `target_blocks`. The child pays the fee missing from its parents, and at least 1 sat/vB
+    /// of its own size.
+    #[allow(clippy::too_many_arguments)]
+    pub fn speedup_transactions_for_target<S: Signer + ?Sized, E: FeeEstimator + ?Sized>(
+        &self,
+        speedups_data: &[SpeedupData],
+        parents: &[UnconfirmedParent],
+        funding_transaction_utxo: Utxo,
+        change_address: &PublicKey,
+        target_blocks: u16,
+        estimator: &E,
+        signer: &S,
+    ) -> Result<Transaction, ProtocolBuilderError> {
+        let fee_rate = estimator.estimate_fee_rate(target_blocks)?;
+
+        // The size of the child does not depend on its fee, so it is measured with no fee
+        let child_vsize = self
+            .speedup_transactions(
+                speedups_data,
+                funding_transaction_utxo.clone(),
+                change_address,
+                0,
+                signer,
+            )?
+            .vsize() as u64;
+
+        let parents_vsize: u64 = parents.iter().map(|parent| parent.vsize).sum();
+        let parents_fee: u64 = parents.iter().map(|parent| parent.fee).sum();
+        let speedup_fee = (fee_rate * (parents_vsize + child_vsize))
+            .saturating_sub(parents_fee)
+            .max(child_vsize);
+        debug!(
+            "Speedup fee {} for {} sat/vB within {} blocks, package of {} vB",
+            speedup_fee,
+            fee_rate,
+            target_blocks,
+            parents_vsize + child_vsize
+        );
+
+        if speedup_fee >= funding_transaction_utxo.amount {
+            return Err(ProtocolBuilderError::InsufficientFunds(
+                funding_transaction_utxo.amount,
+                speedup_fee,
+            ));
+        }
+
+        self.speedup_transactions(
+            speedups_data,
+            funding_transaction_utxo,
+            change_address,
+            speedup_fee,
+            signer,
+        )
+    }
+
+    pub fn speedup_transactions_old<S: Signer + ?Sized>(
         &self,
         speedups_data: &[SpeedupData],
         funding_transaction_utxo: Utxo,
         change_address: Address,
         speedup_fee: u64,
-        key_manager: &KeyManager,
+        signer: &S,
     ) -> Result<Transaction, ProtocolBuilderError> {
         //let transaction_to_speedup = protocol.transaction_by_id(&transaction_to_speedup_utxo.txid)?;
         let mut speedup_transaction = Protocol::transaction_template();
@@ -278,7 +468,7 @@ impl ProtocolBuilder {
                 &mut speedup_transaction,
                 speedup_data.utxo.as_ref().unwrap().clone(),
                 index,
-                key_manager,
+                signer,
                 &mut sighasher,
             )?;
         }
@@ -288,7 +478,7 @@ impl ProtocolBuilder {
             &mut speedup_transaction,
             funding_transaction_utxo,
             speedups_data.len(),
-            key_manager,
+            signer,
             &mut sighasher,
         )?;
 
@@ -398,6 +588,142 @@ impl ProtocolBuilder {
         Ok(self)
     }
 
+    /// Same as `add_timelock_connection` in a connection named `connection_name`, also creating a
+    /// transaction named `expire_to` that spends the expired leaf once the `expired_blocks`
+    /// relative timelock is satisfied, so it is sighashed and signed along with the rest of the
+    /// protocol. The expire connection is named after `connection_name` with an `_expire` suffix.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_timelock_connection_with_expire(
+        &self,
+        protocol: &mut Protocol,
+        connection_name: &str,
+        from: &str,
+        value: u64,
+        internal_key: &PublicKey,
+        expired_script: &ProtocolScript,
+        renew_script: &ProtocolScript,
+        spend_mode: &SpendMode,
+        to: &str,
+        expired_blocks: u16,
+        sighash_type: &SighashType,
+        expire_to: &str,
+    ) -> Result<&Self, ProtocolBuilderError> {
+        protocol.add_connection(
+            connection_name,
+            from,
+            OutputSpec::Auto(OutputType::taproot(
+                value,
+                internal_key,
+                &[expired_script.clone(), renew_script.clone()],
+            )?),
+            to,
+            InputSpec::Auto(sighash_type.clone(), spend_mode.clone()),
+            Some(expired_blocks),
+            None,
+        )?;
+
+        // The expired script is the first leaf of the timelock output
+        protocol.add_connection(
+            &format!("{}_expire", connection_name),
+            from,
+            OutputSpec::Last,
+            expire_to,
+            InputSpec::Auto(sighash_type.clone(), SpendMode::Script { leaf: 0 }),
+            Some(expired_blocks),
+            None,
+        )?;
+
+        Ok(self)
+    }
+
+    /// Connects two transactions through a taproot output with the leaves of `scripts::htlc`. The
+    /// `to` transaction spends the claim leaf. When `refund_to` is given, an additional transaction
+    /// with that name is created spending the refund leaf once `timeout_blocks` have passed, in a
+    /// connection named after `connection_name` with a `_refund` suffix.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_htlc_connection(
+        &self,
+        protocol: &mut Protocol,
+        connection_name: &str,
+        from: &str,
+        value: u64,
+        internal_key: &PublicKey,
+        hash: sha256::Hash,
+        recipient_key: &PublicKey,
+        refund_key: &PublicKey,
+        timeout_blocks: u16,
+        sign_mode: SignMode,
+        to: &str,
+        sighash_type: &SighashType,
+        refund_to: Option<&str>,
+    ) -> Result<&Self, ProtocolBuilderError> {
+        let (claim, refund) =
+            scripts::htlc(hash, recipient_key, refund_key, timeout_blocks, sign_mode)?;
+
+        protocol.add_connection(
+            connection_name,
+            from,
+            OutputSpec::Auto(OutputType::taproot(value, internal_key, &[claim, refund])?),
+            to,
+            InputSpec::Auto(
+                sighash_type.clone(),
+                SpendMode::ScriptByName(scripts::HTLC_CLAIM_LEAF.to_string()),
+            ),
+            None,
+            None,
+        )?;
+
+        if let Some(refund_to) = refund_to {
+            protocol.add_connection(
+                &format!("{}_refund", connection_name),
+                from,
+                OutputSpec::Last,
+                refund_to,
+                InputSpec::Auto(
+                    sighash_type.clone(),
+                    SpendMode::ScriptByName(scripts::HTLC_REFUND_LEAF.to_string()),
+                ),
+                Some(timeout_blocks),
+                None,
+            )?;
+        }
+
+        Ok(self)
+    }
+
+    /// Connects two transactions through a taproot output whose leaves are locked with an absolute
+    /// timelock (see `scripts::timelock_absolute`), and sets the locktime of the spending transaction
+    /// so it cannot be mined before the given height or time.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_cltv_connection(
+        &self,
+        protocol: &mut Protocol,
+        connection_name: &str,
+        from: &str,
+        value: u64,
+        internal_key: &PublicKey,
+        leaves: &[ProtocolScript],
+        spend_mode: &SpendMode,
+        to: &str,
+        lock_time: LockTime,
+        sighash_type: &SighashType,
+    ) -> Result<&Self, ProtocolBuilderError> {
+        self.add_taproot_connection(
+            protocol,
+            connection_name,
+            from,
+            value,
+            internal_key,
+            leaves,
+            spend_mode,
+            to,
+            sighash_type,
+        )?;
+        protocol.set_locktime(to, lock_time)?;
+
+        Ok(self)
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn add_external_connection(
         &self,
@@ -473,6 +799,37 @@ impl ProtocolBuilder {
         leaves_to: &[ProtocolScript],
         spend_mode: &SpendMode,
         sighash_type: &SighashType,
+    ) -> Result<(String, String), ProtocolBuilderError> {
+        self.connect_taproot_rounds_with(
+            protocol,
+            connection_name,
+            rounds,
+            from,
+            to,
+            value,
+            internal_key,
+            |_| (leaves_from.to_vec(), leaves_to.to_vec()),
+            spend_mode,
+            sighash_type,
+        )
+    }
+
+    /// Same as `connect_taproot_rounds`, with the leaves of each round given by `leaves(round)`
+    /// as `(leaves_from, leaves_to)`, e.g. to commit each round to its own Winternitz keys. The
+    /// `leaves_to` of the last round are not used, as there is no reverse connection after it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn connect_taproot_rounds_with(
+        &self,
+        protocol: &mut Protocol,
+        connection_name: &str,
+        rounds: u32,
+        from: &str,
+        to: &str,
+        value: u64,
+        internal_key: &PublicKey,
+        leaves: impl Fn(u32) -> (Vec<ProtocolScript>, Vec<ProtocolScript>),
+        spend_mode: &SpendMode,
+        sighash_type: &SighashType,
     ) -> Result<(String, String), ProtocolBuilderError> {
         check_zero_rounds(rounds)?;
         // To create the names for the intermediate transactions in the rounds. We will use the following format: {name}_{round}.
@@ -482,6 +839,8 @@ impl ProtocolBuilder {
         // In each round we will connect the from transaction to the to transaction and then the to transaction to the from transaction.
         // we need to do this because the transactions are connected in a DAG.
         for round in 0..rounds - 1 {
+            let (leaves_from, leaves_to) = leaves(round);
+
             // Create the new names for the intermediate transactions in the direct connection (from -> to).
             from_round = format!("{0}_{1}", from, round);
             to_round = format!("{0}_{1}", to, round);
@@ -490,7 +849,7 @@ impl ProtocolBuilder {
             protocol.add_connection(
                 connection_name,
                 &from_round,
-                OutputSpec::Auto(OutputType::taproot(value, internal_key, leaves_from)?),
+                OutputSpec::Auto(OutputType::taproot(value, internal_key, &leaves_from)?),
                 &to_round,
                 InputSpec::Auto(sighash_type.clone(), spend_mode.clone()),
                 None,
@@ -505,7 +864,7 @@ impl ProtocolBuilder {
             protocol.add_connection(
                 connection_name,
                 &to_round,
-                OutputSpec::Auto(OutputType::taproot(value, internal_key, leaves_to)?),
+                OutputSpec::Auto(OutputType::taproot(value, internal_key, &leaves_to)?),
                 &from_round,
                 InputSpec::Auto(sighash_type.clone(), spend_mode.clone()),
                 None,
@@ -518,11 +877,12 @@ impl ProtocolBuilder {
         from_round = format!("{0}_{1}", from, rounds - 1);
         to_round = format!("{0}_{1}", to, rounds - 1);
 
-        // Last direct connection using leaves_from.
+        // Last direct connection using the leaves_from of the last round.
+        let (leaves_from, _) = leaves(rounds - 1);
         protocol.add_connection(
             connection_name,
             &from_round,
-            OutputSpec::Auto(OutputType::taproot(value, internal_key, leaves_from)?),
+            OutputSpec::Auto(OutputType::taproot(value, internal_key, &leaves_from)?),
             &to_round,
             InputSpec::Auto(sighash_type.clone(), spend_mode.clone()),
             None,
@@ -533,6 +893,15 @@ impl ProtocolBuilder {
     }
 }
 
+fn record_last_output(
+    protocol: &mut Protocol,
+    transaction_name: &str,
+) -> Result<(), ProtocolBuilderError> {
+    let output_index = protocol.transaction_by_name(transaction_name)?.output.len() - 1;
+    protocol.set_speedup_output(transaction_name, output_index)?;
+    Ok(())
+}
+
 fn push_input(transaction: &mut Transaction, utxo: &Utxo) {
     transaction.input.push(TxIn {
         previous_output: OutPoint {
@@ -568,11 +937,11 @@ fn push_output(
     Ok(())
 }
 
-fn push_witness(
+fn push_witness<S: Signer + ?Sized>(
     transaction: &mut Transaction,
     utxo: Utxo,
     input_index: usize,
-    key_manager: &KeyManager,
+    signer: &S,
     sighasher: &mut SighashCache<Transaction>,
 ) -> Result<(), ProtocolBuilderError> {
     let value = Amount::from_sat(utxo.amount);
@@ -585,7 +954,7 @@ fn push_witness(
         EcdsaSighashType::All,
     )?);
     let input_signature = bitcoin::ecdsa::Signature {
-        signature: key_manager.sign_ecdsa_message(&input_hash, &utxo.pub_key)?,
+        signature: signer.sign_ecdsa(&input_hash, &utxo.pub_key)?,
         sighash_type: EcdsaSighashType::All,
     };
     let witness = Witness::p2wpkh(&input_signature, &utxo.pub_key.inner);
@@ -0,0 +1,243 @@
+use std::{collections::HashMap, rc::Rc};
+
+use bitcoin::secp256k1;
+use storage_backend::storage::Storage;
+
+use crate::{
+    errors::{GraphError, ProtocolBuilderError},
+    types::{
+        bundle::{BundledSignature, SignatureBundle, SignatureFilter},
+        input::{SighashType, Signature},
+        output::OutputType,
+    },
+};
+
+use super::{
+    verification::{signing_key, verify_signature},
+    Protocol, SignatureStatus,
+};
+
+impl Protocol {
+    /// Exports the stored signatures selected by the filter, attributed to the key that produced
+    /// them. Signatures of custom outputs have no known key and are not exported.
+    pub fn export_signatures(
+        &self,
+        filter: &SignatureFilter,
+    ) -> Result<SignatureBundle, ProtocolBuilderError> {
+        let mut bundle = SignatureBundle::new(self.name());
+
+        for node in self.graph().nodes().filter(|node| !node.external) {
+            for (input_index, input) in node.inputs.iter().enumerate() {
+                let output = input.output_type()?;
+
+                for (signature_index, signature) in input.signatures().iter().enumerate() {
+                    let (Some(signature), Some(public_key)) =
+                        (signature, signing_key(output, signature_index))
+                    else {
+                        continue;
+                    };
+
+                    if filter.matches(&node.name, &public_key) {
+                        bundle.signatures.push(BundledSignature {
+                            transaction_name: node.name.clone(),
+                            input_index,
+                            signature_index,
+                            public_key,
+                            signature: signature.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(bundle)
+    }
+
+    /// Stores the signatures of a bundle received from another party. The protocol must be built,
+    /// so sighashes can be re-derived from its transactions. Every signature is checked against
+    /// the key expected for its input and verified before any of them is stored, so an invalid
+    /// bundle leaves the protocol unchanged.
+    pub fn import_signatures(
+        &mut self,
+        bundle: &SignatureBundle,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        if bundle.protocol_name != self.name() {
+            return Err(ProtocolBuilderError::SignatureBundleMismatch(
+                self.name().to_string(),
+                bundle.protocol_name.clone(),
+            ));
+        }
+
+        let secp = secp256k1::Secp256k1::verification_only();
+        let mut updates: HashMap<(String, usize), Vec<Option<Signature>>> = HashMap::new();
+
+        for bundled in bundle.signatures.iter() {
+            let name = &bundled.transaction_name;
+            let transaction = self.transaction_by_name(name)?;
+            let prevouts = self.graph().get_prevouts(name)?;
+            let inputs = self.graph().get_inputs(name)?;
+            let input = inputs
+                .get(bundled.input_index)
+                .ok_or(GraphError::MissingInputInfo(
+                    name.clone(),
+                    bundled.input_index,
+                ))?;
+            let output = input.output_type()?;
+
+            match signing_key(output, bundled.signature_index) {
+                Some(public_key) if public_key == bundled.public_key => {}
+                Some(_) => {
+                    return Err(ProtocolBuilderError::UnexpectedSigningKey(
+                        name.clone(),
+                        bundled.input_index,
+                        bundled.signature_index,
+                    ))
+                }
+                None => Err(GraphError::InvalidSignatureIndex(bundled.signature_index))?,
+            }
+
+            let status = verify_signature(
+                &secp,
+                transaction,
+                &prevouts,
+                bundled.input_index,
+                input,
+                bundled.signature_index,
+                &bundled.signature,
+            )?
+            .map(|(_, status)| status);
+            if status != Some(SignatureStatus::Valid) {
+                return Err(ProtocolBuilderError::InvalidImportedSignature(
+                    name.clone(),
+                    bundled.input_index,
+                    bundled.signature_index,
+                ));
+            }
+
+            // Inputs of a protocol built but not signed have no signature slots yet
+            let signatures = updates
+                .entry((name.clone(), bundled.input_index))
+                .or_insert_with(|| {
+                    let mut signatures = input.signatures().clone();
+                    signatures.resize(signatures.len().max(signature_slots(output)), None);
+                    signatures
+                });
+            signatures[bundled.signature_index] = Some(bundled.signature.clone());
+        }
+
+        for ((name, input_index), signatures) in updates {
+            self.store_input_signatures(&name, input_index, signatures, None)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Resolves a `RevisionConflict` of `save_checked`: reads the stored protocol, imports the
+    /// signatures it has for inputs this protocol has not signed yet, and takes its revision so
+    /// the next `save_checked` succeeds. Signatures stored by both are kept as they are in this
+    /// protocol.
+    pub fn reload_and_merge_signatures(
+        &mut self,
+        storage: Rc<Storage>,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        let Some(stored) = Protocol::load(self.name(), storage.clone())? else {
+            return Ok(self);
+        };
+
+        let mut bundle = stored.export_signatures(&SignatureFilter::all())?;
+        bundle.signatures.retain(|bundled| {
+            self.graph()
+                .get_input(&bundled.transaction_name, bundled.input_index)
+                .map(|input| {
+                    !matches!(
+                        input.signatures().get(bundled.signature_index),
+                        Some(Some(_))
+                    )
+                })
+                .unwrap_or(true)
+        });
+
+        if !bundle.is_empty() {
+            self.import_signatures(&bundle)?;
+        }
+
+        self.set_revision(stored.revision());
+        Ok(self)
+    }
+
+    /// Stores the signature of another party for a slot of a multisig leaf, see
+    /// `input_multisig_signatures`. The protocol must be signed, so the leaf message is known.
+    /// The signature is verified against the key of the slot and must use the sighash type of
+    /// the leaf. Slot 0 is also stored as the signature of the leaf.
+    pub fn import_multisig_signature(
+        &mut self,
+        transaction_name: &str,
+        input_index: usize,
+        leaf_index: usize,
+        slot: usize,
+        signature: bitcoin::taproot::Signature,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        let input = self.graph().get_input(transaction_name, input_index)?;
+        let OutputType::Taproot { leaves, .. } = input.output_type()? else {
+            return Err(ProtocolBuilderError::InvalidLeaf(leaf_index));
+        };
+        let leaf = leaves
+            .get(leaf_index)
+            .filter(|leaf| leaf.is_multisig())
+            .ok_or(ProtocolBuilderError::InvalidLeaf(leaf_index))?;
+        let key = *leaf
+            .get_signers()
+            .get(slot)
+            .ok_or(GraphError::InvalidSignatureIndex(slot))?;
+        let Some(Some(message)) = input.hashed_messages().get(leaf_index).copied() else {
+            return Err(ProtocolBuilderError::MissingTaprootLeaf(
+                leaf_index,
+                input_index,
+            ));
+        };
+
+        let expected_sighash_type = match input.sighash_type() {
+            SighashType::Taproot(tap_sighash_type) => leaf.sighash_type_or(*tap_sighash_type),
+            SighashType::Ecdsa(_) => return Err(ProtocolBuilderError::InvalidLeaf(leaf_index)),
+        };
+        let secp = secp256k1::Secp256k1::verification_only();
+        let valid = signature.sighash_type == expected_sighash_type
+            && secp
+                .verify_schnorr(&signature.signature, &message, &key.into())
+                .is_ok();
+        if !valid {
+            return Err(ProtocolBuilderError::InvalidMultisigSlotSignature(
+                leaf_index, slot,
+            ));
+        }
+
+        let mut multisig_signatures = input.multisig_signatures().clone();
+        let slots = multisig_signatures.entry(leaf_index).or_default();
+        slots.resize(slots.len().max(leaf.get_signers().len()), None);
+        slots[slot] = Some(Signature::Taproot(signature));
+
+        let mut signatures = input.signatures().clone();
+        if slot == 0 {
+            signatures.resize(signatures.len().max(leaves.len() + 1), None);
+            signatures[leaf_index] = Some(Signature::Taproot(signature));
+        }
+
+        self.update_input_multisig_signatures(
+            transaction_name,
+            input_index as u32,
+            multisig_signatures,
+        )?;
+        if slot == 0 {
+            self.store_input_signatures(transaction_name, input_index, signatures, None)?;
+        }
+
+        Ok(self)
+    }
+}
+
+fn signature_slots(output: &OutputType) -> usize {
+    match output {
+        OutputType::Taproot { leaves, .. } => leaves.len() + 1,
+        _ => 1,
+    }
+}
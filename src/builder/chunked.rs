@@ -0,0 +1,195 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use bitcoin::Transaction;
+use serde::{Deserialize, Serialize};
+use storage_backend::storage::{KeyValueStore, Storage};
+
+use crate::{
+    errors::{GraphError, ProtocolBuilderError},
+    graph::graph::{Node, StoredConnection, TransactionGraph},
+    types::{input::InputType, output::OutputType},
+};
+
+use super::protocol::{Protocol, ProtocolMetadata};
+
+/// Transaction names and connections of a protocol saved with `Protocol::save_chunked`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ProtocolManifest {
+    transactions: Vec<String>,
+    connections: Vec<StoredConnection>,
+}
+
+fn metadata_key(protocol_name: &str) -> String {
+    format!("{}/chunks/metadata", protocol_name)
+}
+
+fn manifest_key(protocol_name: &str) -> String {
+    format!("{}/chunks/manifest", protocol_name)
+}
+
+fn node_key(protocol_name: &str, transaction_name: &str) -> String {
+    format!("{}/chunks/node/{}", protocol_name, transaction_name)
+}
+
+impl Protocol {
+    /// Persists the protocol as separate storage keys: one per transaction, one with the
+    /// connections and one with the rest of the protocol data. Unlike `save`, large protocols are
+    /// never serialized as a single blob, and they can be read lazily with `LazyProtocol`.
+    pub fn save_chunked(&self, storage: Rc<Storage>) -> Result<(), ProtocolBuilderError> {
+        let name = self.name();
+        let previous: Option<ProtocolManifest> = storage.get(manifest_key(name))?;
+
+        let mut transactions = vec![];
+        for node in self.graph().nodes() {
+            storage.set(node_key(name, &node.name), node, None)?;
+            transactions.push(node.name.clone());
+        }
+
+        // Remove the chunks of transactions that are no longer part of the protocol
+        if let Some(previous) = previous {
+            for transaction_name in previous.transactions {
+                if !self.graph().contains_transaction(&transaction_name) {
+                    storage.delete(&node_key(name, &transaction_name))?;
+                }
+            }
+        }
+
+        let manifest = ProtocolManifest {
+            transactions,
+            connections: self.graph().stored_connections(),
+        };
+
+        storage.set(manifest_key(name), &manifest, None)?;
+        storage.set(metadata_key(name), self.metadata(), None)?;
+        Ok(())
+    }
+
+    /// Loads a protocol saved with `save_chunked`, reading all its transactions.
+    pub fn load_chunked(
+        name: &str,
+        storage: Rc<Storage>,
+    ) -> Result<Option<Self>, ProtocolBuilderError> {
+        match LazyProtocol::open(name, storage)? {
+            Some(lazy) => Ok(Some(lazy.load()?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Read-only view of a protocol saved with `Protocol::save_chunked`. Only the transaction names
+/// and connections are read when opened, each transaction is read from the storage the first
+/// time it is accessed.
+pub struct LazyProtocol {
+    name: String,
+    storage: Rc<Storage>,
+    metadata: ProtocolMetadata,
+    manifest: ProtocolManifest,
+    nodes: RefCell<HashMap<String, Rc<Node>>>,
+}
+
+impl LazyProtocol {
+    pub fn open(name: &str, storage: Rc<Storage>) -> Result<Option<Self>, ProtocolBuilderError> {
+        let manifest: Option<ProtocolManifest> = storage.get(manifest_key(name))?;
+        let metadata: Option<ProtocolMetadata> = storage.get(metadata_key(name))?;
+
+        match (manifest, metadata) {
+            (Some(manifest), Some(metadata)) => Ok(Some(Self {
+                name: name.to_string(),
+                storage,
+                metadata,
+                manifest,
+                nodes: RefCell::new(HashMap::new()),
+            })),
+            (None, None) => Ok(None),
+            _ => Err(ProtocolBuilderError::MissingProtocolChunk(name.to_string())),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn transaction_names(&self) -> &[String] {
+        &self.manifest.transactions
+    }
+
+    pub fn contains_transaction(&self, transaction_name: &str) -> bool {
+        self.manifest
+            .transactions
+            .iter()
+            .any(|name| name == transaction_name)
+    }
+
+    pub fn transaction_by_name(
+        &self,
+        transaction_name: &str,
+    ) -> Result<Transaction, ProtocolBuilderError> {
+        Ok(self.node(transaction_name)?.transaction.clone())
+    }
+
+    pub fn inputs(&self, transaction_name: &str) -> Result<Vec<InputType>, ProtocolBuilderError> {
+        Ok(self.node(transaction_name)?.inputs.clone())
+    }
+
+    pub fn outputs(&self, transaction_name: &str) -> Result<Vec<OutputType>, ProtocolBuilderError> {
+        Ok(self.node(transaction_name)?.outputs.clone())
+    }
+
+    /// Returns the transactions spending outputs of the given transaction, without reading them.
+    pub fn next_transactions(
+        &self,
+        transaction_name: &str,
+    ) -> Result<Vec<String>, ProtocolBuilderError> {
+        if !self.contains_transaction(transaction_name) {
+            return Err(GraphError::MissingTransaction(transaction_name.to_string()).into());
+        }
+
+        Ok(self
+            .manifest
+            .connections
+            .iter()
+            .filter(|stored| stored.from == transaction_name)
+            .map(|stored| stored.to.clone())
+            .collect())
+    }
+
+    /// Number of transactions read from the storage so far.
+    pub fn loaded_transactions(&self) -> usize {
+        self.nodes.borrow().len()
+    }
+
+    /// Reads the remaining transactions and returns the complete protocol.
+    pub fn load(self) -> Result<Protocol, ProtocolBuilderError> {
+        let nodes = self
+            .manifest
+            .transactions
+            .iter()
+            .map(|name| Ok(self.node(name)?.as_ref().clone()))
+            .collect::<Result<Vec<_>, ProtocolBuilderError>>()?;
+
+        let graph = TransactionGraph::from_parts(nodes, self.manifest.connections)?;
+        Ok(Protocol::from_metadata(self.metadata, graph))
+    }
+
+    fn node(&self, transaction_name: &str) -> Result<Rc<Node>, ProtocolBuilderError> {
+        if let Some(node) = self.nodes.borrow().get(transaction_name) {
+            return Ok(node.clone());
+        }
+
+        if !self.contains_transaction(transaction_name) {
+            return Err(GraphError::MissingTransaction(transaction_name.to_string()).into());
+        }
+
+        let key = node_key(&self.name, transaction_name);
+        let node: Node = self
+            .storage
+            .get(&key)?
+            .ok_or(ProtocolBuilderError::MissingProtocolChunk(key))?;
+
+        let node = Rc::new(node);
+        self.nodes
+            .borrow_mut()
+            .insert(transaction_name.to_string(), node.clone());
+        Ok(node)
+    }
+}
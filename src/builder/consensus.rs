@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use bitcoin::{
+    consensus::{encode::VarInt, Decodable, Encodable},
+    io, Transaction, Txid,
+};
+
+use crate::errors::{GraphError, ProtocolBuilderError};
+
+use super::Protocol;
+
+impl Protocol {
+    /// Writes the transactions of the protocol in consensus wire format, in topological order,
+    /// without building an intermediate representation. The stream starts with the number of
+    /// transactions, and each transaction is prefixed by its size, both as compact size integers,
+    /// so readers can skip transactions without decoding them. External transactions are not
+    /// included. Returns the number of bytes written.
+    pub fn write_consensus_stream<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<usize, ProtocolBuilderError> {
+        let writer = io::from_std_mut(writer);
+        let transaction_names = self.graph().sort()?;
+
+        let mut written = VarInt(transaction_names.len() as u64)
+            .consensus_encode(writer)
+            .map_err(stream_error)?;
+
+        for transaction_name in transaction_names.iter() {
+            let transaction = self.transaction_by_name(transaction_name)?;
+            written += VarInt(transaction.total_size() as u64)
+                .consensus_encode(writer)
+                .map_err(stream_error)?;
+            written += transaction.consensus_encode(writer).map_err(stream_error)?;
+        }
+
+        Ok(written)
+    }
+
+    /// Reads a stream written by `write_consensus_stream` (possibly with the transactions signed
+    /// or reordered) and matches each transaction to the protocol transaction with the same txid.
+    pub fn read_consensus_stream<R: std::io::BufRead>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Vec<(String, Transaction)>, ProtocolBuilderError> {
+        let reader = io::from_std_mut(reader);
+        let names: HashMap<Txid, &String> = self
+            .graph()
+            .nodes()
+            .filter(|node| !node.external)
+            .map(|node| (node.transaction.compute_txid(), &node.name))
+            .collect();
+
+        let count = VarInt::consensus_decode(reader).map_err(stream_error)?.0;
+        let mut transactions = vec![];
+
+        for _ in 0..count {
+            let size = VarInt::consensus_decode(reader).map_err(stream_error)?.0;
+            let transaction = Transaction::consensus_decode(reader).map_err(stream_error)?;
+            if transaction.total_size() as u64 != size {
+                return Err(ProtocolBuilderError::ConsensusStreamError(format!(
+                    "transaction {} has {} bytes, expected {}",
+                    transaction.compute_txid(),
+                    transaction.total_size(),
+                    size
+                )));
+            }
+
+            let txid = transaction.compute_txid();
+            let name = names
+                .get(&txid)
+                .ok_or(GraphError::TransactionNotFound(txid.to_string()))?;
+            transactions.push((name.to_string(), transaction));
+        }
+
+        Ok(transactions)
+    }
+}
+
+fn stream_error<E: std::fmt::Display>(error: E) -> ProtocolBuilderError {
+    ProtocolBuilderError::ConsensusStreamError(error.to_string())
+}
@@ -0,0 +1,329 @@
+use std::{collections::HashMap, fmt::Write};
+
+use bitcoin::PublicKey;
+
+use crate::{
+    errors::ProtocolBuilderError,
+    graph::graph::{Node, StoredConnection},
+    types::{
+        diff::{ProtocolDiff, SpentOutput, TransactionChange, TransactionDiff},
+        output::OutputType,
+    },
+};
+
+use super::Protocol;
+
+const ADDED_COLOR: &str = "darkgreen";
+const REMOVED_COLOR: &str = "red";
+const CHANGED_COLOR: &str = "orange";
+
+impl Protocol {
+    /// Structural differences from this protocol to another version of it: added and removed
+    /// transactions, and for transactions in both the changes to their locktime, inputs
+    /// (connections and sequences) and outputs (values, keys and scripts). Txids and signatures
+    /// are not compared, as they change with any other difference.
+    pub fn diff(&self, other: &Protocol) -> Result<ProtocolDiff, ProtocolBuilderError> {
+        let old_spent = spent_outputs(self);
+        let new_spent = spent_outputs(other);
+        let mut diff = ProtocolDiff::default();
+
+        for node in self.graph().nodes() {
+            if !other.graph().contains_transaction(&node.name) {
+                diff.removed_transactions.push(node.name.clone());
+            }
+        }
+
+        for new in other.graph().nodes() {
+            let Some(old) = self.graph().nodes().find(|old| old.name == new.name) else {
+                diff.added_transactions.push(new.name.clone());
+                continue;
+            };
+
+            let mut changes = vec![];
+            let (old_lock_time, new_lock_time) = (
+                old.transaction.lock_time.to_consensus_u32(),
+                new.transaction.lock_time.to_consensus_u32(),
+            );
+            if old_lock_time != new_lock_time {
+                changes.push(TransactionChange::LockTime {
+                    old: old_lock_time,
+                    new: new_lock_time,
+                });
+            }
+
+            input_changes(old, new, &old_spent, &new_spent, &mut changes);
+            output_changes(old, new, &mut changes);
+
+            if !changes.is_empty() {
+                diff.changed_transactions.push(TransactionDiff {
+                    transaction_name: new.name.clone(),
+                    changes,
+                });
+            }
+        }
+
+        Ok(diff)
+    }
+
+    /// Exports the union of both versions of the protocol as a DOT graph, with added
+    /// transactions and connections in green, removed ones dashed in red and changed
+    /// transactions in orange.
+    pub fn visualize_diff(&self, other: &Protocol) -> Result<String, ProtocolBuilderError> {
+        let diff = self.diff(other)?;
+        let mut result = "digraph {\ngraph [rankdir=LR]\nnode [shape=box]\n".to_owned();
+
+        let names = self
+            .graph()
+            .nodes()
+            .chain(other.graph().nodes())
+            .map(|node| node.name.as_str());
+        let mut written: Vec<&str> = vec![];
+        for name in names {
+            if written.contains(&name) {
+                continue;
+            }
+            written.push(name);
+
+            let style = if diff.added_transactions.iter().any(|added| added == name) {
+                color_style(ADDED_COLOR, false)
+            } else if diff
+                .removed_transactions
+                .iter()
+                .any(|removed| removed == name)
+            {
+                color_style(REMOVED_COLOR, true)
+            } else if diff
+                .changed_transactions
+                .iter()
+                .any(|changed| changed.transaction_name == name)
+            {
+                color_style(CHANGED_COLOR, false)
+            } else {
+                String::new()
+            };
+            writeln!(result, "{} [label=\"{}\"{}]", name, name, style).unwrap();
+        }
+
+        let old_connections = self.graph().stored_connections();
+        let new_connections = other.graph().stored_connections();
+        for stored in new_connections.iter() {
+            let style = if old_connections.iter().any(|old| same_edge(old, stored)) {
+                String::new()
+            } else {
+                color_style(ADDED_COLOR, false)
+            };
+            writeln!(
+                result,
+                "{} -> {} [label={}{}]",
+                stored.from, stored.to, stored.connection.name, style
+            )
+            .unwrap();
+        }
+        for stored in old_connections.iter() {
+            if !new_connections.iter().any(|new| same_edge(new, stored)) {
+                writeln!(
+                    result,
+                    "{} -> {} [label={}{}]",
+                    stored.from,
+                    stored.to,
+                    stored.connection.name,
+                    color_style(REMOVED_COLOR, true)
+                )
+                .unwrap();
+            }
+        }
+
+        result.push('}');
+        Ok(result)
+    }
+}
+
+// Connections are matched by their endpoints, as names are not unique.
+fn same_edge(a: &StoredConnection, b: &StoredConnection) -> bool {
+    a.from == b.from
+        && a.to == b.to
+        && a.connection.output_index == b.connection.output_index
+        && a.connection.input_index == b.connection.input_index
+}
+
+fn color_style(color: &str, dashed: bool) -> String {
+    let style = if dashed { " style=dashed" } else { "" };
+    format!(" color={} fontcolor={}{}", color, color, style)
+}
+
+// Output spent by each connected input, by (transaction name, input index).
+fn spent_outputs(protocol: &Protocol) -> HashMap<(String, usize), SpentOutput> {
+    protocol
+        .graph()
+        .stored_connections()
+        .into_iter()
+        .map(|stored| {
+            (
+                (stored.to, stored.connection.input_index as usize),
+                (stored.from, stored.connection.output_index as usize),
+            )
+        })
+        .collect()
+}
+
+fn input_changes(
+    old: &Node,
+    new: &Node,
+    old_spent: &HashMap<(String, usize), SpentOutput>,
+    new_spent: &HashMap<(String, usize), SpentOutput>,
+    changes: &mut Vec<TransactionChange>,
+) {
+    let (old_inputs, new_inputs) = (&old.transaction.input, &new.transaction.input);
+
+    for input_index in 0..old_inputs.len().min(new_inputs.len()) {
+        let key = (new.name.clone(), input_index);
+        let (old_output, new_output) = (old_spent.get(&key), new_spent.get(&key));
+        if old_output != new_output {
+            changes.push(TransactionChange::InputSpends {
+                input_index,
+                old: old_output.cloned(),
+                new: new_output.cloned(),
+            });
+        }
+
+        let (old_sequence, new_sequence) = (
+            old_inputs[input_index].sequence.to_consensus_u32(),
+            new_inputs[input_index].sequence.to_consensus_u32(),
+        );
+        if old_sequence != new_sequence {
+            changes.push(TransactionChange::Sequence {
+                input_index,
+                old: old_sequence,
+                new: new_sequence,
+            });
+        }
+    }
+
+    for input_index in new_inputs.len()..old_inputs.len() {
+        changes.push(TransactionChange::InputRemoved(input_index));
+    }
+    for input_index in old_inputs.len()..new_inputs.len() {
+        changes.push(TransactionChange::InputAdded(input_index));
+    }
+}
+
+fn output_changes(old: &Node, new: &Node, changes: &mut Vec<TransactionChange>) {
+    for (output_index, (old_output, new_output)) in
+        old.outputs.iter().zip(new.outputs.iter()).enumerate()
+    {
+        if old_output.get_name() != new_output.get_name() {
+            changes.push(TransactionChange::OutputKind {
+                output_index,
+                old: old_output.get_name().to_string(),
+                new: new_output.get_name().to_string(),
+            });
+            continue;
+        }
+
+        let (old_value, new_value) = (
+            old_output.get_value().to_sat(),
+            new_output.get_value().to_sat(),
+        );
+        if old_value != new_value {
+            changes.push(TransactionChange::OutputValue {
+                output_index,
+                old: old_value,
+                new: new_value,
+            });
+        }
+
+        let (old_key, new_key) = (output_key(old_output), output_key(new_output));
+        if old_key != new_key {
+            changes.push(TransactionChange::OutputKey {
+                output_index,
+                old: old_key,
+                new: new_key,
+            });
+        }
+
+        match (old_output, new_output) {
+            (
+                OutputType::Taproot {
+                    leaves: old_leaves, ..
+                },
+                OutputType::Taproot {
+                    leaves: new_leaves, ..
+                },
+            ) => {
+                for (leaf, (old_leaf, new_leaf)) in
+                    old_leaves.iter().zip(new_leaves.iter()).enumerate()
+                {
+                    if old_leaf.get_script() != new_leaf.get_script() {
+                        changes.push(TransactionChange::LeafScript {
+                            output_index,
+                            leaf,
+                            old: old_leaf.get_script().clone(),
+                            new: new_leaf.get_script().clone(),
+                        });
+                    }
+                    if old_leaf.get_verifying_key() != new_leaf.get_verifying_key() {
+                        changes.push(TransactionChange::LeafKey {
+                            output_index,
+                            leaf,
+                            old: old_leaf.get_verifying_key(),
+                            new: new_leaf.get_verifying_key(),
+                        });
+                    }
+                }
+                for leaf in new_leaves.len()..old_leaves.len() {
+                    changes.push(TransactionChange::LeafRemoved { output_index, leaf });
+                }
+                for leaf in old_leaves.len()..new_leaves.len() {
+                    changes.push(TransactionChange::LeafAdded { output_index, leaf });
+                }
+            }
+            (
+                OutputType::SegwitScript {
+                    script: old_script, ..
+                },
+                OutputType::SegwitScript {
+                    script: new_script, ..
+                },
+            ) => {
+                if old_script.get_script() != new_script.get_script() {
+                    changes.push(TransactionChange::OutputScript {
+                        output_index,
+                        old: old_script.get_script().clone(),
+                        new: new_script.get_script().clone(),
+                    });
+                }
+            }
+            _ => {
+                if old_output.get_script_pubkey() != new_output.get_script_pubkey() {
+                    changes.push(TransactionChange::OutputScript {
+                        output_index,
+                        old: old_output.get_script_pubkey().clone(),
+                        new: new_output.get_script_pubkey().clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    for output_index in new.outputs.len()..old.outputs.len() {
+        changes.push(TransactionChange::OutputRemoved {
+            output_index,
+            value: old.outputs[output_index].get_value().to_sat(),
+        });
+    }
+    for output_index in old.outputs.len()..new.outputs.len() {
+        changes.push(TransactionChange::OutputAdded {
+            output_index,
+            value: new.outputs[output_index].get_value().to_sat(),
+        });
+    }
+}
+
+fn output_key(output: &OutputType) -> Option<PublicKey> {
+    match output {
+        OutputType::Taproot { internal_key, .. } => Some(*internal_key),
+        OutputType::SegwitPublicKey { public_key, .. } => Some(*public_key),
+        OutputType::SegwitScript { script, .. } => script.get_verifying_key(),
+        _ => None,
+    }
+}
@@ -0,0 +1,224 @@
+use bitcoin::PublicKey;
+
+use crate::{
+    errors::ProtocolBuilderError,
+    scripts::ProtocolScript,
+    types::{
+        connection::{InputSpec, OutputSpec},
+        input::{SighashType, SpendMode},
+        nary_rounds::NaryRounds,
+        trace_step::{StepCommitments, TraceStep},
+        OutputType,
+    },
+};
+
+use super::{check_params::check_zero_rounds, Protocol, ProtocolBuilder};
+
+impl ProtocolBuilder {
+    /// Chains a transaction per step of an execution trace after `from`, named
+    /// `{connection_name}_step_{index}`, each one committing its step through the commitment
+    /// leaves supplied by the prover. The output of each step transaction holds the commitment
+    /// leaves of the next step followed by the challenge leaves of its own step, so it is spent
+    /// either by the next step or by `{connection_name}_challenge_{index}`. The output of the last
+    /// step only holds its challenge leaves.
+    #[allow(clippy::too_many_arguments)]
+    pub fn connect_trace_steps(
+        &self,
+        protocol: &mut Protocol,
+        connection_name: &str,
+        from: &str,
+        value: u64,
+        internal_key: &PublicKey,
+        commitments: &dyn StepCommitments,
+        spend_mode: &SpendMode,
+        sighash_type: &SighashType,
+    ) -> Result<Vec<TraceStep>, ProtocolBuilderError> {
+        let step_count = commitments.step_count();
+        if step_count == 0 {
+            return Err(ProtocolBuilderError::EmptyTrace);
+        }
+
+        let mut steps = Vec::with_capacity(step_count);
+        let mut previous = from.to_string();
+        let mut commitment_leaves = step_commitments(commitments, 0)?;
+
+        for index in 0..step_count {
+            let transaction_name = format!("{}_step_{}", connection_name, index);
+
+            protocol.add_connection(
+                connection_name,
+                &previous,
+                OutputSpec::Auto(OutputType::taproot(
+                    value,
+                    internal_key,
+                    &commitment_leaves.scripts,
+                )?),
+                &transaction_name,
+                InputSpec::Auto(sighash_type.clone(), spend_mode.clone()),
+                None,
+                None,
+            )?;
+
+            // The output of the step is created by the connection to the next step, or here for
+            // the last one.
+            let next_commitments = if index + 1 < step_count {
+                step_commitments(commitments, index + 1)?
+            } else {
+                StepLeaves::default()
+            };
+            let challenges = commitments.challenge_leaves(index)?;
+            let offset = next_commitments.scripts.len();
+
+            steps.push(TraceStep {
+                index,
+                step_number: commitments.step_number(index),
+                transaction_name: transaction_name.clone(),
+                challenge_name: None,
+                commitment_leaves: commitment_leaves.indexes(),
+                challenge_leaves: (offset..offset + challenges.len()).collect(),
+            });
+
+            commitment_leaves = StepLeaves {
+                scripts: next_commitments
+                    .scripts
+                    .into_iter()
+                    .chain(challenges.iter().cloned())
+                    .collect(),
+                count: offset,
+            };
+            previous = transaction_name;
+        }
+
+        // Challenges spend the output of each step, which for every step but the last is also
+        // the one spent by the next step.
+        for step in steps.iter_mut() {
+            if step.challenge_leaves.is_empty() {
+                continue;
+            }
+
+            let output = if step.index + 1 < step_count {
+                OutputSpec::Index(0)
+            } else {
+                OutputSpec::Auto(OutputType::taproot(
+                    value,
+                    internal_key,
+                    &commitment_leaves.scripts,
+                )?)
+            };
+
+            let challenge_name = format!("{}_challenge_{}", connection_name, step.index);
+            protocol.add_connection(
+                connection_name,
+                &step.transaction_name,
+                output,
+                &challenge_name,
+                InputSpec::Auto(sighash_type.clone(), spend_mode.clone()),
+                None,
+                None,
+            )?;
+            step.challenge_name = Some(challenge_name);
+        }
+
+        Ok(steps)
+    }
+}
+
+impl ProtocolBuilder {
+    /// Creates the transactions of an N-ary search after `from`: in each round every transaction
+    /// of the previous round gets an output with `arity` leaves, and each leaf is spent by its
+    /// own transaction of the next round. The leaves of the output of a transaction are given by
+    /// `leaves(path)`, see `NaryRounds` for the paths, so each round can commit to its own keys.
+    /// The transactions of the last round have no outputs. Creates `arity^1 + ... +
+    /// arity^rounds` transactions, named by `NaryRounds::transaction_name`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn connect_nary_rounds(
+        &self,
+        protocol: &mut Protocol,
+        connection_name: &str,
+        from: &str,
+        arity: usize,
+        rounds: u32,
+        value: u64,
+        internal_key: &PublicKey,
+        leaves: impl Fn(&[usize]) -> Result<Vec<ProtocolScript>, ProtocolBuilderError>,
+        sighash_type: &SighashType,
+    ) -> Result<NaryRounds, ProtocolBuilderError> {
+        check_zero_rounds(rounds)?;
+        if arity < 2 {
+            return Err(ProtocolBuilderError::InvalidArity(arity));
+        }
+
+        let mut index = NaryRounds::new(from, arity, rounds);
+        let mut previous_round = vec![(vec![], from.to_string())];
+
+        for _ in 0..rounds {
+            let mut round = Vec::with_capacity(previous_round.len() * arity);
+
+            for (path, transaction_name) in previous_round {
+                let scripts = leaves(&path)?;
+                if scripts.len() != arity {
+                    return Err(ProtocolBuilderError::InvalidBranchLeaves(
+                        transaction_name,
+                        arity,
+                        scripts.len(),
+                    ));
+                }
+
+                // The first branch adds the output, the others spend the same one.
+                let mut output =
+                    OutputSpec::Auto(OutputType::taproot(value, internal_key, &scripts)?);
+                for branch in 0..arity {
+                    let mut child = path.clone();
+                    child.push(branch);
+                    let child_name = NaryRounds::transaction_name(connection_name, &child);
+
+                    protocol.add_connection(
+                        connection_name,
+                        &transaction_name,
+                        std::mem::replace(&mut output, OutputSpec::Last),
+                        &child_name,
+                        InputSpec::Auto(sighash_type.clone(), SpendMode::Script { leaf: branch }),
+                        None,
+                        None,
+                    )?;
+
+                    index.insert(child.clone(), child_name.clone());
+                    round.push((child, child_name));
+                }
+            }
+
+            previous_round = round;
+        }
+
+        Ok(index)
+    }
+}
+
+// Leaves of an output spent by a step transaction, where the first `count` are the commitments
+// of the step.
+#[derive(Default)]
+struct StepLeaves {
+    scripts: Vec<ProtocolScript>,
+    count: usize,
+}
+
+impl StepLeaves {
+    fn indexes(&self) -> Vec<usize> {
+        (0..self.count).collect()
+    }
+}
+
+fn step_commitments(
+    commitments: &dyn StepCommitments,
+    index: usize,
+) -> Result<StepLeaves, ProtocolBuilderError> {
+    let scripts = commitments.commitment_leaves(index)?;
+    if scripts.is_empty() {
+        return Err(ProtocolBuilderError::MissingStepCommitments(index));
+    }
+
+    Ok(StepLeaves {
+        count: scripts.len(),
+        scripts,
+    })
+}
@@ -0,0 +1,109 @@
+use bitcoin::{Address, Txid};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    types::explorer::{Explorer, ExplorerLinks, ExplorerOutput, ExplorerTransaction},
+};
+
+use super::Protocol;
+
+impl Protocol {
+    /// Maps every transaction of the protocol, in topological order, to its explorer url and the
+    /// expected address of each of its outputs, to be consumed by monitoring dashboards. External
+    /// transactions are included with the txid they were declared with. The protocol must be
+    /// built so txids are final.
+    pub fn explorer_links(
+        &self,
+        explorer: &Explorer,
+    ) -> Result<ExplorerLinks, ProtocolBuilderError> {
+        let mut links = ExplorerLinks {
+            protocol_name: self.name().to_string(),
+            network: explorer.network(),
+            transactions: vec![],
+        };
+
+        let nodes: Vec<_> = self.graph().nodes().collect();
+        let mut external: Vec<String> = nodes
+            .iter()
+            .filter(|node| node.external)
+            .map(|node| node.name.clone())
+            .collect();
+        external.sort();
+
+        // External transactions are always roots of the graph
+        for transaction_name in external.into_iter().chain(self.graph().sort()?) {
+            let Some(node) = nodes.iter().find(|node| node.name == transaction_name) else {
+                continue;
+            };
+
+            let txid = match node.external {
+                true => self.external_txid(&transaction_name)?,
+                false => node.transaction.compute_txid(),
+            };
+
+            let outputs = node
+                .transaction
+                .output
+                .iter()
+                .enumerate()
+                .map(|(output_index, txout)| {
+                    let address = Address::from_script(&txout.script_pubkey, explorer.network())
+                        .ok()
+                        .map(|address| address.to_string());
+
+                    let mut labels: Vec<String> = node
+                        .output_labels
+                        .iter()
+                        .filter(|(_, index)| **index == output_index)
+                        .map(|(label, _)| label.clone())
+                        .collect();
+                    labels.sort();
+
+                    ExplorerOutput {
+                        output_index,
+                        amount: txout.value.to_sat(),
+                        script_pubkey: hex::encode(txout.script_pubkey.as_bytes()),
+                        labels,
+                        address_url: address
+                            .as_deref()
+                            .map(|address| explorer.address_url(address)),
+                        address,
+                    }
+                })
+                .collect();
+
+            links.transactions.push(ExplorerTransaction {
+                name: transaction_name,
+                txid,
+                url: explorer.transaction_url(&txid),
+                external: node.external,
+                outputs,
+            });
+        }
+
+        Ok(links)
+    }
+
+    // External transactions are only known by their txid, taken from their declaration or from
+    // the inputs spending them.
+    fn external_txid(&self, transaction_name: &str) -> Result<Txid, ProtocolBuilderError> {
+        if let Some(external) = self.external_transaction(transaction_name) {
+            return Ok(external.txid());
+        }
+
+        match self
+            .graph()
+            .stored_connections()
+            .into_iter()
+            .find(|stored| stored.from == transaction_name)
+        {
+            Some(stored) => {
+                let spender = self.transaction_by_name(&stored.to)?;
+                Ok(spender.input[stored.connection.input_index as usize]
+                    .previous_output
+                    .txid)
+            }
+            None => Ok(self.transaction_by_name(transaction_name)?.compute_txid()),
+        }
+    }
+}
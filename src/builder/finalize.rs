@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use bitcoin::{consensus::encode::serialize_hex, Transaction};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    scripts::KeyType,
+    types::{
+        input::InputArgs,
+        witness_args::{WitnessArgs, WitnessItem},
+        OutputType, Signer,
+    },
+};
+
+use super::Protocol;
+
+impl Protocol {
+    /// Arguments to spend every input of a transaction with `transaction_to_send`, with the
+    /// signatures computed by the protocol. Taproot inputs spend the leaf given for the input, or
+    /// else `default_leaf`, or else the key path. Extra items are pushed in order before the
+    /// taproot signature, signing Winternitz messages with the signer.
+    pub fn input_args_for<S: Signer + ?Sized>(
+        &self,
+        transaction_name: &str,
+        default_leaf: Option<usize>,
+        witness_args: &WitnessArgs,
+        signer: &S,
+    ) -> Result<Vec<InputArgs>, ProtocolBuilderError> {
+        let mut all_args = vec![];
+
+        for (input_index, input) in self.inputs(transaction_name)?.iter().enumerate() {
+            let extra = witness_args.get(input_index);
+            let leaf = extra.and_then(|extra| extra.leaf).or(default_leaf);
+
+            let mut multisig_signatures = None;
+            let (mut args, signature) = match (input.output_type()?, leaf) {
+                (OutputType::Taproot { leaves, .. }, Some(leaf)) => {
+                    if leaf >= leaves.len() {
+                        return Err(ProtocolBuilderError::MissingTaprootLeaf(leaf, input_index));
+                    }
+                    let signature = if leaves[leaf].is_multisig() {
+                        multisig_signatures = Some(self.input_multisig_signatures(
+                            transaction_name,
+                            input_index,
+                            leaf,
+                        )?);
+                        None
+                    } else {
+                        self.input_taproot_script_spend_signature(
+                            transaction_name,
+                            input_index,
+                            leaf,
+                        )?
+                    };
+                    (InputArgs::new_taproot_script_args(leaf), signature)
+                }
+                (OutputType::Taproot { .. }, None) => (
+                    InputArgs::new_taproot_key_args(),
+                    self.input_taproot_key_spend_signature(transaction_name, input_index)?,
+                ),
+                _ => {
+                    let mut args = InputArgs::new_segwit_args();
+                    if let Some(signature) =
+                        self.input_ecdsa_signature(transaction_name, input_index)?
+                    {
+                        args.push_ecdsa_signature(signature)?;
+                    }
+                    (args, None)
+                }
+            };
+
+            for item in extra.iter().flat_map(|extra| extra.items.iter()) {
+                match item {
+                    WitnessItem::Hex { data } => {
+                        args.push_slice(&hex::decode(data).map_err(|error| {
+                            ProtocolBuilderError::InvalidWitnessArgs(error.to_string())
+                        })?);
+                    }
+                    WitnessItem::WinternitzSignature { signature } => {
+                        args.push_winternitz_signature(signature.clone());
+                    }
+                    WitnessItem::WinternitzMessage {
+                        message,
+                        index,
+                        key_type,
+                    } => {
+                        let message = hex::decode(message).map_err(|error| {
+                            ProtocolBuilderError::InvalidWitnessArgs(error.to_string())
+                        })?;
+                        let signature = signer.sign_winternitz(&message, *key_type, *index)?;
+                        args.push_winternitz_signature(signature);
+                    }
+                }
+            }
+
+            // Leaves check the signature first, so it goes on top of the stack
+            if let Some(signature) = signature {
+                args.push_taproot_signature(signature)?;
+            }
+            if let Some(signatures) = multisig_signatures {
+                args.push_multisig_signatures(&signatures)?;
+            }
+
+            all_args.push(args);
+        }
+
+        Ok(all_args)
+    }
+
+    /// Arguments to spend a leaf that checks Winternitz signatures, signing the value given for
+    /// each Winternitz key of the leaf, by key name, with its derivation index. The key with
+    /// position 0 is checked first, so its signature is pushed last, right below the taproot
+    /// signature of the leaf, which goes on top when the protocol has it.
+    pub fn sign_winternitz<S: Signer + ?Sized>(
+        &self,
+        transaction_name: &str,
+        input_index: usize,
+        leaf_index: usize,
+        values: &HashMap<String, Vec<u8>>,
+        signer: &S,
+    ) -> Result<InputArgs, ProtocolBuilderError> {
+        let input = self
+            .inputs(transaction_name)?
+            .get(input_index)
+            .cloned()
+            .ok_or(ProtocolBuilderError::MissingInput(
+                transaction_name.to_string(),
+                input_index,
+            ))?;
+        let leaf =
+            match input.output_type()? {
+                OutputType::Taproot { leaves, .. } => leaves.get(leaf_index).cloned().ok_or(
+                    ProtocolBuilderError::MissingTaprootLeaf(leaf_index, input_index),
+                )?,
+                output => {
+                    return Err(ProtocolBuilderError::InvalidOutputType(
+                        "Taproot".to_string(),
+                        output.get_name().to_string(),
+                    ))
+                }
+            };
+
+        let mut args = InputArgs::new_taproot_script_args(leaf_index);
+        for key in leaf.get_keys().iter().rev() {
+            let KeyType::WinternitzKey { key_type, .. } = key.key_type() else {
+                continue;
+            };
+            let value =
+                values
+                    .get(key.name())
+                    .ok_or(ProtocolBuilderError::MissingWinternitzValue(
+                        key.name().to_string(),
+                    ))?;
+
+            let signature = signer.sign_winternitz(value, key_type, key.derivation_index())?;
+            args.push_winternitz_signature(signature);
+        }
+
+        if let Some(signature) =
+            self.input_taproot_script_spend_signature(transaction_name, input_index, leaf_index)?
+        {
+            args.push_taproot_signature(signature)?;
+        }
+
+        Ok(args)
+    }
+
+    /// Transaction ready to broadcast with its witnesses, see `input_args_for`.
+    pub fn finalize<S: Signer + ?Sized>(
+        &self,
+        transaction_name: &str,
+        default_leaf: Option<usize>,
+        witness_args: &WitnessArgs,
+        signer: &S,
+    ) -> Result<Transaction, ProtocolBuilderError> {
+        let args = self.input_args_for(transaction_name, default_leaf, witness_args, signer)?;
+        self.transaction_to_send(transaction_name, &args)
+    }
+
+    /// Consensus encoding of `finalize`, as accepted by `sendrawtransaction`.
+    pub fn finalize_hex<S: Signer + ?Sized>(
+        &self,
+        transaction_name: &str,
+        default_leaf: Option<usize>,
+        witness_args: &WitnessArgs,
+        signer: &S,
+    ) -> Result<String, ProtocolBuilderError> {
+        Ok(serialize_hex(&self.finalize(
+            transaction_name,
+            default_leaf,
+            witness_args,
+            signer,
+        )?))
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::errors::{ProtocolBuilderError, SerializationError};
+
+use super::Protocol;
+
+/// Mutation recorded by `Protocol::record_history`. Only the parts of the protocol changed by
+/// the mutation are stored, so a long history costs the size of its changes rather than a copy
+/// of the protocol per step.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    step: usize,
+    operation: String,
+    delta: StateDelta,
+}
+
+impl Checkpoint {
+    /// Number of mutations recorded before this checkpoint. Step 0 is the state of the protocol
+    /// when recording started.
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn operation(&self) -> &str {
+        &self.operation
+    }
+}
+
+// Change between two JSON states of a protocol. Replaced values are kept as JSON text so the
+// history can be stored with the non self-describing formats.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum StateDelta {
+    Replace(String),
+    Object {
+        changed: BTreeMap<String, StateDelta>,
+        removed: Vec<String>,
+    },
+    Array {
+        len: usize,
+        changed: BTreeMap<usize, StateDelta>,
+    },
+}
+
+impl StateDelta {
+    fn between(old: &Value, new: &Value) -> Option<StateDelta> {
+        match (old, new) {
+            _ if old == new => None,
+            (Value::Object(old), Value::Object(new)) => Some(StateDelta::Object {
+                changed: new
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let delta = match old.get(key) {
+                            Some(old_value) => StateDelta::between(old_value, value)?,
+                            None => StateDelta::replace(value),
+                        };
+                        Some((key.clone(), delta))
+                    })
+                    .collect(),
+                removed: old
+                    .keys()
+                    .filter(|key| !new.contains_key(*key))
+                    .cloned()
+                    .collect(),
+            }),
+            (Value::Array(old), Value::Array(new)) => Some(StateDelta::Array {
+                len: new.len(),
+                changed: new
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, value)| {
+                        let delta = match old.get(index) {
+                            Some(old_value) => StateDelta::between(old_value, value)?,
+                            None => StateDelta::replace(value),
+                        };
+                        Some((index, delta))
+                    })
+                    .collect(),
+            }),
+            _ => Some(StateDelta::replace(new)),
+        }
+    }
+
+    fn replace(value: &Value) -> StateDelta {
+        StateDelta::Replace(value.to_string())
+    }
+
+    fn apply(&self, value: &mut Value) -> Result<(), SerializationError> {
+        match (self, value) {
+            (StateDelta::Replace(json), value) => {
+                *value = serde_json::from_str(json).map_err(decode_error)?
+            }
+            (StateDelta::Object { changed, removed }, Value::Object(object)) => {
+                for key in removed {
+                    object.remove(key);
+                }
+                for (key, delta) in changed {
+                    delta.apply(object.entry(key.clone()).or_insert(Value::Null))?;
+                }
+            }
+            (StateDelta::Array { len, changed }, Value::Array(array)) => {
+                array.resize(*len, Value::Null);
+                for (index, delta) in changed {
+                    delta.apply(&mut array[*index])?;
+                }
+            }
+            (_, value) => return Err(decode_error(format!("delta does not apply to {}", value))),
+        }
+        Ok(())
+    }
+}
+
+fn decode_error(error: impl ToString) -> SerializationError {
+    SerializationError::DecodeError("history".to_string(), error.to_string())
+}
+
+/// Checkpoints of a protocol, see `Protocol::record_history`. Serialized as the list of
+/// checkpoints; the last state is kept in memory to compute the next delta.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Checkpoint>", into = "Vec<Checkpoint>")]
+pub(super) struct History {
+    checkpoints: Vec<Checkpoint>,
+    head: Option<Value>,
+}
+
+impl From<Vec<Checkpoint>> for History {
+    fn from(checkpoints: Vec<Checkpoint>) -> Self {
+        Self {
+            checkpoints,
+            head: None,
+        }
+    }
+}
+
+impl From<History> for Vec<Checkpoint> {
+    fn from(history: History) -> Self {
+        history.checkpoints
+    }
+}
+
+impl History {
+    pub(super) fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// Records the state of the protocol after a mutation.
+    pub(super) fn record(&mut self, operation: &str, state: Value) {
+        // A history that cannot be replayed is recorded against the empty state, which makes
+        // the delta of this step the whole protocol, so it can be replayed from here on
+        let head = match self.head.take() {
+            Some(head) => head,
+            None => self.state_at(self.checkpoints.len()).unwrap_or_default(),
+        };
+
+        // Step 0 replaces the empty state with the whole protocol, and mutations leaving the
+        // protocol unchanged record an empty delta
+        let delta = StateDelta::between(&head, &state).unwrap_or(StateDelta::Object {
+            changed: BTreeMap::new(),
+            removed: vec![],
+        });
+        self.checkpoints.push(Checkpoint {
+            step: self.checkpoints.len(),
+            operation: operation.to_string(),
+            delta,
+        });
+        self.head = Some(state);
+    }
+
+    // JSON state of the protocol before the given step, rebuilt by applying the deltas of the
+    // previous ones.
+    fn state_at(&self, step: usize) -> Result<Value, SerializationError> {
+        let mut state = Value::Null;
+        for checkpoint in self.checkpoints[..step].iter() {
+            checkpoint.delta.apply(&mut state)?;
+        }
+        Ok(state)
+    }
+
+    fn truncated(&self, step: usize) -> History {
+        History::from(self.checkpoints[..=step].to_vec())
+    }
+}
+
+impl Protocol {
+    /// Rebuilds the protocol as it was after the N-th recorded mutation by replaying the
+    /// recorded changes from the start of the history, e.g. to compare the transactions
+    /// reviewed by a party with the ones it later signed, or to bisect the step of a long
+    /// construction sequence that introduced a bug. The replayed protocol keeps the history up
+    /// to that step, so it can be replayed again or keep recording from there.
+    pub fn replay_to(&self, step: usize) -> Result<Protocol, ProtocolBuilderError> {
+        let Some(history) = self.recorded_history() else {
+            return Err(ProtocolBuilderError::HistoryNotRecorded(
+                self.name().to_string(),
+            ));
+        };
+
+        let last_step = history.checkpoints().len() - 1;
+        if step > last_step {
+            return Err(ProtocolBuilderError::UnknownHistoryStep(step, last_step));
+        }
+
+        let mut protocol: Protocol =
+            serde_json::from_value(history.state_at(step + 1)?).map_err(decode_error)?;
+        protocol.restore_history(history.truncated(step));
+        Ok(protocol)
+    }
+}
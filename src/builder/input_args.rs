@@ -0,0 +1,339 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    scripts::{KeyType, ProtocolScript, StackItem},
+    types::{
+        input::{InputArgs, InputType},
+        output::OutputType,
+        witness_preview::WitnessItemRole,
+    },
+};
+
+use super::Protocol;
+
+// Witness element expected by a script, see `StackItem`
+enum Element {
+    Schnorr,
+    Ecdsa,
+    WinternitzHash(usize),
+    WinternitzDigit,
+    Raw(usize),
+}
+
+impl Element {
+    // Elements of a stack item in the order they are pushed
+    fn of(item: &StackItem) -> Vec<Element> {
+        match item {
+            StackItem::SchnorrSig { .. } => vec![Element::Schnorr],
+            StackItem::EcdsaSig { .. } => vec![Element::Ecdsa],
+            StackItem::WinternitzSig { .. } => (0..item.element_count() / 2)
+                .flat_map(|_| {
+                    [
+                        Element::WinternitzHash(item.max_element_size()),
+                        Element::WinternitzDigit,
+                    ]
+                })
+                .collect(),
+            StackItem::Raw { size } => vec![Element::Raw(*size)],
+        }
+    }
+
+    // Missing multisig signatures are pushed as empty items
+    fn accepts(&self, item: &[u8], multisig: bool) -> bool {
+        match self {
+            Element::Schnorr => matches!(item.len(), 64 | 65) || (multisig && item.is_empty()),
+            Element::Ecdsa => (9..=73).contains(&item.len()),
+            Element::WinternitzHash(size) => !item.is_empty() && item.len() <= *size,
+            Element::WinternitzDigit => item.len() <= 1,
+            Element::Raw(size) => item.len() <= *size,
+        }
+    }
+}
+
+impl From<&Element> for WitnessItemRole {
+    fn from(element: &Element) -> Self {
+        match element {
+            Element::Schnorr => WitnessItemRole::SchnorrSignature,
+            Element::Ecdsa => WitnessItemRole::EcdsaSignature,
+            Element::WinternitzHash(_) => WitnessItemRole::WinternitzHash,
+            Element::WinternitzDigit => WitnessItemRole::WinternitzDigit,
+            Element::Raw(_) => WitnessItemRole::Data,
+        }
+    }
+}
+
+impl Display for Element {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Element::Schnorr => write!(f, "schnorr signature"),
+            Element::Ecdsa => write!(f, "ecdsa signature"),
+            Element::WinternitzHash(_) => write!(f, "winternitz hash"),
+            Element::WinternitzDigit => write!(f, "winternitz digit"),
+            Element::Raw(size) => write!(f, "item of up to {} bytes", size),
+        }
+    }
+}
+
+impl Protocol {
+    /// Checks the args of each input against the witness it is expected to have before
+    /// `transaction_to_send` builds it: key spends take a single signature, anchors and
+    /// unspendable outputs no items, and scripts that declare their stack items take exactly
+    /// those, in order. Scripts without stack items only need the items of the signatures of
+    /// their Winternitz keys. Custom outputs check their own args.
+    pub fn check_input_args(
+        &self,
+        transaction_name: &str,
+        args: &[InputArgs],
+    ) -> Result<(), ProtocolBuilderError> {
+        let inputs = self.graph().get_inputs(transaction_name)?;
+        if inputs.len() != args.len() {
+            return Err(ProtocolBuilderError::InputArgsCountMismatch(
+                transaction_name.to_string(),
+                inputs.len(),
+                args.len(),
+            ));
+        }
+
+        for (input_index, (input, args)) in inputs.iter().zip(args).enumerate() {
+            if let Some(reason) = check_input(input, args)? {
+                return Err(ProtocolBuilderError::InvalidInputArgs(
+                    transaction_name.to_string(),
+                    input_index,
+                    reason,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl InputArgs {
+    /// Args to spend an input with the signature the protocol stored for it: the script spend
+    /// signature of `leaf`, the key spend signature of taproot outputs when no leaf is given, or
+    /// the ECDSA signature of segwit outputs. Multisig leaves get the signatures of their slots.
+    /// Anchors and unspendable outputs take no signature, so their args are empty. Items the
+    /// spend needs below the signature must be pushed by the caller before the signature, so
+    /// use this for spends where any extra items go on top.
+    pub fn from_protocol(
+        protocol: &Protocol,
+        transaction_name: &str,
+        input_index: usize,
+        leaf: Option<usize>,
+    ) -> Result<Self, ProtocolBuilderError> {
+        let input = protocol
+            .inputs(transaction_name)?
+            .get(input_index)
+            .cloned()
+            .ok_or(ProtocolBuilderError::MissingInput(
+                transaction_name.to_string(),
+                input_index,
+            ))?;
+        let missing = || {
+            ProtocolBuilderError::MissingInputSignature(transaction_name.to_string(), input_index)
+        };
+
+        let args = match (input.output_type()?, leaf) {
+            (OutputType::Taproot { leaves, .. }, Some(leaf)) => {
+                let script = leaves
+                    .get(leaf)
+                    .ok_or(ProtocolBuilderError::MissingTaprootLeaf(leaf, input_index))?;
+                let mut args = Self::new_taproot_script_args(leaf);
+                if script.is_multisig() {
+                    let signatures =
+                        protocol.input_multisig_signatures(transaction_name, input_index, leaf)?;
+                    if signatures.iter().all(Option::is_none) {
+                        return Err(missing());
+                    }
+                    args.push_multisig_signatures(&signatures)?;
+                } else {
+                    let signature = protocol
+                        .input_taproot_script_spend_signature(transaction_name, input_index, leaf)?
+                        .ok_or_else(missing)?;
+                    args.push_taproot_signature(signature)?;
+                }
+                args
+            }
+            (OutputType::Taproot { .. }, None) => {
+                let signature = protocol
+                    .input_taproot_key_spend_signature(transaction_name, input_index)?
+                    .ok_or_else(missing)?;
+                let mut args = Self::new_taproot_key_args();
+                args.push_taproot_signature(signature)?;
+                args
+            }
+            (output, Some(_)) => {
+                return Err(ProtocolBuilderError::InvalidOutputType(
+                    "Taproot".to_string(),
+                    output.get_name().to_string(),
+                ))
+            }
+            (OutputType::SegwitUnspendable { .. } | OutputType::PayToAnchor { .. }, None) => {
+                Self::new_segwit_args()
+            }
+            (_, None) => {
+                let signature = protocol
+                    .input_ecdsa_signature(transaction_name, input_index)?
+                    .ok_or_else(missing)?;
+                let mut args = Self::new_segwit_args();
+                args.push_ecdsa_signature(signature)?;
+                args
+            }
+        };
+
+        Ok(args)
+    }
+}
+
+// Returns why the args cannot spend the input, if they cannot. Args of the wrong kind for the
+// output are reported when the witness is built.
+fn check_input(
+    input: &InputType,
+    args: &InputArgs,
+) -> Result<Option<String>, ProtocolBuilderError> {
+    let items: Vec<&[u8]> = args.iter().map(|item| item.as_slice()).collect();
+
+    let reason = match (input.output_type()?, args) {
+        (OutputType::SegwitPublicKey { .. }, InputArgs::Segwit { .. }) => {
+            check_items(&items, &[Element::Ecdsa], false)
+        }
+        (OutputType::SegwitScript { script, .. }, InputArgs::Segwit { .. }) => {
+            check_script(script, &items)?
+        }
+        (OutputType::SegwitUnspendable { .. } | OutputType::PayToAnchor { .. }, _) => (!items
+            .is_empty())
+        .then(|| format!("expected an empty witness, got {} items", items.len())),
+        (OutputType::Taproot { .. }, InputArgs::TaprootKey { .. }) => {
+            check_items(&items, &[Element::Schnorr], false)
+        }
+        (OutputType::Taproot { leaves, .. }, _) => {
+            match args
+                .leaf_index(input.output_type()?)?
+                .and_then(|leaf| leaves.get(leaf))
+            {
+                Some(leaf) => check_script(leaf, &items)?,
+                None => None,
+            }
+        }
+        _ => None,
+    };
+
+    Ok(reason)
+}
+
+// Roles of the items of the args, bottom first, as expected by the output they spend. Items
+// beyond the expected ones are data. Scripts without declared stack items only describe the
+// Winternitz signatures of their keys, at the bottom, and signature sized items above them are
+// taken as signatures.
+pub(super) fn arg_roles(
+    input: &InputType,
+    args: &InputArgs,
+) -> Result<Vec<WitnessItemRole>, ProtocolBuilderError> {
+    let expected = match (input.output_type()?, args) {
+        (OutputType::SegwitPublicKey { .. }, _) => vec![WitnessItemRole::EcdsaSignature],
+        (OutputType::SegwitScript { script, .. }, _) => script_roles(script)?,
+        (OutputType::Taproot { .. }, InputArgs::TaprootKey { .. }) => {
+            vec![WitnessItemRole::SchnorrSignature]
+        }
+        (OutputType::Taproot { leaves, .. }, _) => match args
+            .leaf_index(input.output_type()?)?
+            .and_then(|leaf| leaves.get(leaf))
+        {
+            Some(leaf) => script_roles(leaf)?,
+            None => vec![],
+        },
+        _ => vec![],
+    };
+
+    let taproot = matches!(input.output_type()?, OutputType::Taproot { .. });
+    let roles = args
+        .iter()
+        .enumerate()
+        .map(|(index, item)| match expected.get(index) {
+            Some(role) => *role,
+            None if taproot && Element::Schnorr.accepts(item, false) => {
+                WitnessItemRole::SchnorrSignature
+            }
+            None => WitnessItemRole::Data,
+        })
+        .collect();
+
+    Ok(roles)
+}
+
+// Roles of the declared stack items of a script, bottom first, or else of the Winternitz
+// signatures of its keys.
+fn script_roles(script: &ProtocolScript) -> Result<Vec<WitnessItemRole>, ProtocolBuilderError> {
+    let stack_items = script.stack_items();
+    if !stack_items.is_empty() {
+        return Ok(stack_items
+            .iter()
+            .rev()
+            .flat_map(Element::of)
+            .map(|element| WitnessItemRole::from(&element))
+            .collect());
+    }
+
+    let mut roles = vec![];
+    for key in script.get_keys().iter().rev() {
+        if let KeyType::WinternitzKey { .. } = key.key_type() {
+            for _ in 0..key.key_type().winternitz_digits()? {
+                roles.push(WitnessItemRole::WinternitzHash);
+                roles.push(WitnessItemRole::WinternitzDigit);
+            }
+        }
+    }
+
+    Ok(roles)
+}
+
+fn check_script(
+    script: &ProtocolScript,
+    items: &[&[u8]],
+) -> Result<Option<String>, ProtocolBuilderError> {
+    let stack_items = script.stack_items();
+    if !stack_items.is_empty() {
+        // Stack items are declared top first
+        let expected: Vec<Element> = stack_items.iter().rev().flat_map(Element::of).collect();
+        return Ok(check_items(items, &expected, script.is_multisig()));
+    }
+
+    let mut winternitz_items = 0;
+    for key in script.get_keys() {
+        if let KeyType::WinternitzKey { .. } = key.key_type() {
+            winternitz_items += 2 * key.key_type().winternitz_digits()?;
+        }
+    }
+
+    Ok((items.len() < winternitz_items).then(|| {
+        format!(
+            "expected at least {} items for the winternitz signatures, got {}",
+            winternitz_items,
+            items.len()
+        )
+    }))
+}
+
+fn check_items(items: &[&[u8]], expected: &[Element], multisig: bool) -> Option<String> {
+    if items.len() != expected.len() {
+        return Some(format!(
+            "expected {} witness items, got {}",
+            expected.len(),
+            items.len()
+        ));
+    }
+
+    items
+        .iter()
+        .zip(expected)
+        .position(|(item, element)| !element.accepts(item, multisig))
+        .map(|position| {
+            format!(
+                "item {} ({} bytes) is not a valid {}",
+                position,
+                items[position].len(),
+                expected[position]
+            )
+        })
+}
@@ -0,0 +1,103 @@
+use crate::{
+    errors::ProtocolBuilderError,
+    graph::{estimate::estimate_vsize, graph::signature_slots},
+    helpers::weight_computing::get_transaction_hex,
+    types::{
+        inspect::{InspectedInput, InspectedOutput, TransactionInspection},
+        OutputType,
+    },
+};
+
+use super::Protocol;
+
+impl Protocol {
+    /// Summary of a transaction for debugging: its encoding, txid and weight, the spend mode,
+    /// sighash type and signatures of each input, and the kind and value of each output.
+    pub fn inspect(
+        &self,
+        transaction_name: &str,
+    ) -> Result<TransactionInspection, ProtocolBuilderError> {
+        let node = self
+            .graph()
+            .nodes()
+            .find(|node| node.name == transaction_name)
+            .ok_or(ProtocolBuilderError::MissingTransaction(
+                transaction_name.to_string(),
+                self.name().to_string(),
+            ))?;
+        let transaction = &node.transaction;
+        let connections = self.graph().stored_connections();
+
+        let inputs = transaction
+            .input
+            .iter()
+            .enumerate()
+            .map(|(index, txin)| {
+                let spends = connections
+                    .iter()
+                    .find(|stored| {
+                        stored.to == transaction_name
+                            && stored.connection.input_index as usize == index
+                    })
+                    .map(|stored| (stored.from.clone(), stored.connection.output_index as usize));
+
+                let input = node.inputs.get(index);
+                InspectedInput {
+                    index,
+                    previous_output: txin.previous_output,
+                    spends,
+                    value: input
+                        .and_then(|input| input.output_type().ok())
+                        .map(|output| output.get_value().to_sat()),
+                    spend_mode: input.map(|input| input.spend_mode().to_string()),
+                    sighash_type: input.map(|input| input.sighash_type().to_string()),
+                    signatures: input.map(signature_slots).unwrap_or_default(),
+                }
+            })
+            .collect();
+
+        let outputs = transaction
+            .output
+            .iter()
+            .enumerate()
+            .map(|(index, txout)| {
+                let output = node.outputs.get(index);
+                InspectedOutput {
+                    index,
+                    value: txout.value.to_sat(),
+                    kind: output
+                        .map(|output| output.get_name().to_string())
+                        .unwrap_or("unknown".to_string()),
+                    script_pubkey: txout.script_pubkey.to_hex_string(),
+                    leaves: match output {
+                        Some(OutputType::Taproot { leaves, .. }) => leaves.len(),
+                        _ => 0,
+                    },
+                    label: node
+                        .output_labels
+                        .iter()
+                        .find(|(_, output_index)| **output_index == index)
+                        .map(|(label, _)| label.clone()),
+                }
+            })
+            .collect();
+
+        // External transactions are not spent with the inputs of the protocol
+        let estimated_vsize = if node.external {
+            None
+        } else {
+            estimate_vsize(transaction, transaction_name, &node.inputs).ok()
+        };
+
+        Ok(TransactionInspection {
+            name: transaction_name.to_string(),
+            txid: transaction.compute_txid(),
+            external: node.external,
+            hex: get_transaction_hex(transaction),
+            weight: transaction.weight().to_wu(),
+            estimated_vsize,
+            inputs,
+            outputs,
+        })
+    }
+}
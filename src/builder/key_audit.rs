@@ -0,0 +1,103 @@
+use crate::{
+    errors::ProtocolBuilderError,
+    scripts::{KeyType, ProtocolScript},
+    types::{
+        key_usage::{KeyUsage, KeyUsageReport},
+        OutputType,
+    },
+};
+
+use super::{Protocol, ProtocolRegistry};
+
+impl Protocol {
+    /// Winternitz derivation indexes used by the scripts of the outputs of the protocol, taproot
+    /// leaves and P2WSH scripts, with the keys using each of them.
+    pub fn key_usage(&self) -> KeyUsageReport {
+        let mut report = KeyUsageReport::default();
+
+        for node in self.graph().nodes() {
+            for (output_index, output) in node.outputs.iter().enumerate() {
+                let scripts: Vec<(Option<usize>, &ProtocolScript)> = match output {
+                    OutputType::Taproot { leaves, .. } => leaves
+                        .iter()
+                        .enumerate()
+                        .map(|(leaf, script)| (Some(leaf), script))
+                        .collect(),
+                    OutputType::SegwitScript { script, .. } => vec![(None, script)],
+                    _ => vec![],
+                };
+
+                for (leaf_index, script) in scripts {
+                    for key in script.get_keys() {
+                        if !matches!(key.key_type(), KeyType::WinternitzKey { .. }) {
+                            continue;
+                        }
+
+                        report.add(
+                            key.derivation_index(),
+                            KeyUsage {
+                                protocol_name: self.name().to_string(),
+                                transaction_name: node.name.clone(),
+                                output_index,
+                                leaf_index,
+                                key_name: key.name().to_string(),
+                                key_type: key.key_type(),
+                                fingerprint: key.fingerprint().map(str::to_string),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Checks that no Winternitz key, identified by its derivation index, type and public key,
+    /// signs more than one message, see `KeyUsage::same_message`. Winternitz keys are one-time
+    /// keys, so signing two messages with the same key leaks it. Using a key in several leaves
+    /// of the same output, alternative spends of it, is allowed.
+    pub fn check_winternitz_key_reuse(&self) -> Result<(), ProtocolBuilderError> {
+        let Some(reuse) = self.key_usage().reuses().into_iter().next() else {
+            return Ok(());
+        };
+
+        let first = &reuse.usages[0];
+        let second = reuse
+            .usages
+            .iter()
+            .find(|usage| !usage.same_message(first))
+            .unwrap_or(first);
+        Err(ProtocolBuilderError::WinternitzKeyReuse(
+            reuse.derivation_index,
+            first.to_string(),
+            second.to_string(),
+        ))
+    }
+}
+
+impl KeyUsageReport {
+    /// Winternitz key usage of several protocols, e.g. every instance run by the same key
+    /// manager, so indexes reused across instances are flagged too.
+    pub fn from_protocols<'a>(protocols: impl IntoIterator<Item = &'a Protocol>) -> Self {
+        let mut report = KeyUsageReport::default();
+        for protocol in protocols {
+            report.merge(protocol.key_usage());
+        }
+        report
+    }
+}
+
+impl ProtocolRegistry {
+    /// Winternitz key usage of all the protocols in the registry, see
+    /// `KeyUsageReport::from_protocols`.
+    pub fn key_usage(&self) -> Result<KeyUsageReport, ProtocolBuilderError> {
+        let mut report = KeyUsageReport::default();
+        for record in self.list()? {
+            if let Some(protocol) = self.load(&record.name)? {
+                report.merge(protocol.key_usage());
+            }
+        }
+        Ok(report)
+    }
+}
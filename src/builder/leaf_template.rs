@@ -0,0 +1,136 @@
+use crate::{
+    errors::ProtocolBuilderError,
+    types::{
+        leaf_template::{LeafTemplate, TemplateManifest},
+        output::OutputType,
+    },
+};
+
+use super::Protocol;
+
+impl Protocol {
+    /// Template hashes of the scripts of every output of the protocol, to be exchanged with the
+    /// other parties during setup.
+    pub fn leaf_templates(&self) -> TemplateManifest {
+        let mut manifest = TemplateManifest::new(self.name());
+
+        for node in self.graph().nodes() {
+            for (output_index, output) in node.outputs.iter().enumerate() {
+                manifest
+                    .leaves
+                    .extend(output_templates(&node.name, output_index, output));
+            }
+        }
+
+        manifest
+    }
+
+    /// Checks that the protocol has exactly the scripts of a manifest received from another
+    /// party. A script with the same transaction, output and leaf index but a different template
+    /// (bytes, keys or witness items), a missing script or an unexpected one fails with
+    /// `LeafTemplateMismatch`.
+    pub fn verify_leaf_templates(
+        &self,
+        manifest: &TemplateManifest,
+    ) -> Result<(), ProtocolBuilderError> {
+        if manifest.protocol_name != self.name() {
+            return Err(ProtocolBuilderError::TemplateManifestMismatch(
+                self.name().to_string(),
+                manifest.protocol_name.clone(),
+            ));
+        }
+
+        let own = self.leaf_templates();
+        for template in own.leaves.iter().chain(manifest.leaves.iter()) {
+            let (ours, theirs) = (
+                own.get(
+                    &template.transaction_name,
+                    template.output_index,
+                    template.leaf,
+                ),
+                manifest.get(
+                    &template.transaction_name,
+                    template.output_index,
+                    template.leaf,
+                ),
+            );
+
+            if ours.map(|ours| ours.hash) != theirs.map(|theirs| theirs.hash) {
+                return Err(mismatch(template));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the manifest and keeps it, so `transaction_to_send` checks again that the scripts
+    /// spent by a transaction are the agreed ones right before it is broadcast.
+    pub fn agree_leaf_templates(
+        &mut self,
+        manifest: TemplateManifest,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.verify_leaf_templates(&manifest)?;
+        self.set_agreed_templates(manifest);
+        Ok(self)
+    }
+
+    // Checks the scripts of the outputs spent by the inputs of the transaction, as stored in the
+    // inputs that build its witnesses, against the agreed templates.
+    pub(super) fn check_agreed_templates(
+        &self,
+        transaction_name: &str,
+    ) -> Result<(), ProtocolBuilderError> {
+        let Some(manifest) = self.agreed_templates() else {
+            return Ok(());
+        };
+
+        let inputs = self.graph().get_inputs(transaction_name)?;
+        for stored in self.graph().stored_connections() {
+            if stored.to != transaction_name {
+                continue;
+            }
+
+            let Some(input) = inputs.get(stored.connection.input_index as usize) else {
+                continue;
+            };
+            let Ok(output) = input.output_type() else {
+                continue;
+            };
+
+            let output_index = stored.connection.output_index as usize;
+            for template in output_templates(&stored.from, output_index, output) {
+                let agreed = manifest.get(&stored.from, output_index, template.leaf);
+                if agreed.map(|agreed| agreed.hash) != Some(template.hash) {
+                    return Err(mismatch(&template));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn output_templates(
+    transaction_name: &str,
+    output_index: usize,
+    output: &OutputType,
+) -> Vec<LeafTemplate> {
+    output
+        .get_scripts()
+        .into_iter()
+        .map(|(leaf, script)| LeafTemplate {
+            transaction_name: transaction_name.to_string(),
+            output_index,
+            leaf,
+            hash: script.template_hash(),
+        })
+        .collect()
+}
+
+fn mismatch(template: &LeafTemplate) -> ProtocolBuilderError {
+    ProtocolBuilderError::LeafTemplateMismatch(
+        template.transaction_name.clone(),
+        template.output_index,
+        template.leaf,
+    )
+}
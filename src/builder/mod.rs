@@ -1,5 +1,48 @@
+#[cfg(feature = "anyprevout")]
+mod anyprevout;
+#[cfg(feature = "async")]
+mod async_signing;
 mod builder;
+mod bundle;
 mod check_params;
+mod chunked;
+mod consensus;
+mod diff;
+mod dispute;
+mod explorer;
+mod finalize;
+mod history;
+mod input_args;
+mod inspect;
+mod key_audit;
+mod leaf_template;
+mod nonces;
+mod offline_signing;
+mod ownership;
+mod plan;
 mod protocol;
+mod registry;
+mod scheduler;
+mod signing_plan;
+mod spec;
+mod template;
+mod timelock;
+mod trace;
+mod validation;
+mod verification;
+mod view;
+mod witness_decoder;
+mod witness_preview;
 
-pub use self::{builder::ProtocolBuilder, protocol::Protocol};
+pub use self::{
+    builder::ProtocolBuilder,
+    chunked::LazyProtocol,
+    history::Checkpoint,
+    protocol::Protocol,
+    registry::ProtocolRegistry,
+    scheduler::{AggregatedMessage, SigningScheduler, SigningStatus},
+    template::{Param, ProtocolTemplate, TemplateOutput, TemplateParams},
+    validation::{TransactionDiagnostics, ValidationIssue, ValidationReport},
+    verification::{SignatureCheck, SignaturePath, SignatureReport, SignatureStatus},
+    view::ProtocolView,
+};
@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use bitcoin::PublicKey;
+use musig2::{PartialSignature, PubNonce};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    types::{
+        nonces::{BundledNonce, BundledPartialSignature, NonceBundle, PartialSignatureBundle},
+        Signer,
+    },
+};
+
+use super::{scheduler::aggregated_messages, Protocol};
+
+// Values exchanged by the participants of the MuSig2 sessions of a protocol, one bundle per
+// participant. Nonces and partial signatures are validated the same way and only differ in the
+// values they carry and the errors they report.
+trait SessionBundle {
+    type Value;
+
+    fn protocol_name(&self) -> &str;
+    fn id(&self) -> &str;
+    fn participant(&self) -> PublicKey;
+    fn entries(&self) -> Vec<(PublicKey, &String, &[u8])>;
+    fn parse(bytes: &[u8]) -> Option<Self::Value>;
+
+    fn mismatch(expected: String, found: String) -> ProtocolBuilderError;
+    fn missing(message_id: String) -> ProtocolBuilderError;
+    fn unexpected(message_id: String) -> ProtocolBuilderError;
+    fn invalid(message_id: String) -> ProtocolBuilderError;
+}
+
+impl SessionBundle for NonceBundle {
+    type Value = PubNonce;
+
+    fn protocol_name(&self) -> &str {
+        &self.protocol_name
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn participant(&self) -> PublicKey {
+        self.participant
+    }
+
+    fn entries(&self) -> Vec<(PublicKey, &String, &[u8])> {
+        self.nonces
+            .iter()
+            .map(|nonce| {
+                (
+                    nonce.aggregated_key,
+                    &nonce.message_id,
+                    nonce.nonce.as_slice(),
+                )
+            })
+            .collect()
+    }
+
+    fn parse(bytes: &[u8]) -> Option<PubNonce> {
+        PubNonce::from_bytes(bytes).ok()
+    }
+
+    fn mismatch(expected: String, found: String) -> ProtocolBuilderError {
+        ProtocolBuilderError::NonceBundleMismatch(expected, found)
+    }
+
+    fn missing(message_id: String) -> ProtocolBuilderError {
+        ProtocolBuilderError::MissingNonce(message_id)
+    }
+
+    fn unexpected(message_id: String) -> ProtocolBuilderError {
+        ProtocolBuilderError::UnexpectedNonce(message_id)
+    }
+
+    fn invalid(message_id: String) -> ProtocolBuilderError {
+        ProtocolBuilderError::InvalidNonce(message_id)
+    }
+}
+
+impl SessionBundle for PartialSignatureBundle {
+    type Value = PartialSignature;
+
+    fn protocol_name(&self) -> &str {
+        &self.protocol_name
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn participant(&self) -> PublicKey {
+        self.participant
+    }
+
+    fn entries(&self) -> Vec<(PublicKey, &String, &[u8])> {
+        self.partial_signatures
+            .iter()
+            .map(|signature| {
+                (
+                    signature.aggregated_key,
+                    &signature.message_id,
+                    signature.partial_signature.as_slice(),
+                )
+            })
+            .collect()
+    }
+
+    fn parse(bytes: &[u8]) -> Option<PartialSignature> {
+        PartialSignature::from_slice(bytes).ok()
+    }
+
+    fn mismatch(expected: String, found: String) -> ProtocolBuilderError {
+        ProtocolBuilderError::PartialSignatureBundleMismatch(expected, found)
+    }
+
+    fn missing(message_id: String) -> ProtocolBuilderError {
+        ProtocolBuilderError::MissingPartialSignature(message_id)
+    }
+
+    fn unexpected(message_id: String) -> ProtocolBuilderError {
+        ProtocolBuilderError::UnexpectedPartialSignature(message_id)
+    }
+
+    fn invalid(message_id: String) -> ProtocolBuilderError {
+        ProtocolBuilderError::InvalidPartialSignature(message_id)
+    }
+}
+
+impl Protocol {
+    /// Exports the public nonces generated by the signer for every message of the protocol
+    /// signed with an aggregated key. The protocol must be built with the same session id, so the
+    /// nonces are generated while computing the sighashes.
+    pub fn export_nonces<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+        id: &str,
+        participant: &PublicKey,
+    ) -> Result<NonceBundle, ProtocolBuilderError> {
+        let nonces = self.own_values(
+            id,
+            |aggregated_key| signer.get_pub_nonces(aggregated_key, id),
+            ProtocolBuilderError::MissingNonce,
+        )?;
+
+        Ok(NonceBundle {
+            protocol_name: self.name().to_string(),
+            id: id.to_string(),
+            participant: *participant,
+            nonces: nonces
+                .into_iter()
+                .map(|(aggregated_key, message_id, nonce)| BundledNonce {
+                    aggregated_key,
+                    message_id,
+                    nonce: nonce.serialize().to_vec(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Passes the nonces received from the other participants to the signer, which
+    /// aggregates them to complete the MuSig2 sessions of the protocol. Every bundle must have a
+    /// nonce for each aggregated message of the protocol, and nothing else.
+    pub fn import_peer_nonces<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+        id: &str,
+        bundles: &[NonceBundle],
+    ) -> Result<(), ProtocolBuilderError> {
+        for (aggregated_key, nonces) in self.peer_values(id, bundles)? {
+            signer.aggregate_nonces(&aggregated_key, id, nonces.into_iter().collect())?;
+        }
+
+        Ok(())
+    }
+
+    /// Exports the partial signatures of the signer for every message of the protocol signed
+    /// with an aggregated key. The nonces of the other participants must be imported first, see
+    /// `import_peer_nonces`.
+    pub fn export_partial_signatures<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+        id: &str,
+        participant: &PublicKey,
+    ) -> Result<PartialSignatureBundle, ProtocolBuilderError> {
+        let partial_signatures = self.own_values(
+            id,
+            |aggregated_key| signer.get_partial_signatures(aggregated_key, id),
+            ProtocolBuilderError::MissingPartialSignature,
+        )?;
+
+        Ok(PartialSignatureBundle {
+            protocol_name: self.name().to_string(),
+            id: id.to_string(),
+            participant: *participant,
+            partial_signatures: partial_signatures
+                .into_iter()
+                .map(
+                    |(aggregated_key, message_id, partial_signature)| BundledPartialSignature {
+                        aggregated_key,
+                        message_id,
+                        partial_signature: partial_signature.serialize().to_vec(),
+                    },
+                )
+                .collect(),
+        })
+    }
+
+    /// Passes the partial signatures received from the other participants to the signer, so the
+    /// aggregated signatures can be computed by `sign`. Every bundle must have a partial
+    /// signature for each aggregated message of the protocol, and nothing else.
+    pub fn import_peer_partial_signatures<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+        id: &str,
+        bundles: &[PartialSignatureBundle],
+    ) -> Result<(), ProtocolBuilderError> {
+        for (aggregated_key, partial_signatures) in self.peer_values(id, bundles)? {
+            signer.aggregate_partial_signatures(
+                &aggregated_key,
+                id,
+                partial_signatures.into_iter().collect(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // Values of the signer for every aggregated message of the protocol, sorted by message id.
+    fn own_values<T>(
+        &self,
+        id: &str,
+        get: impl Fn(&PublicKey) -> Result<Vec<(String, T)>, ProtocolBuilderError>,
+        missing: fn(String) -> ProtocolBuilderError,
+    ) -> Result<Vec<(PublicKey, String, T)>, ProtocolBuilderError> {
+        let mut own = vec![];
+
+        for (aggregated_key, message_ids) in self.aggregated_message_ids(id)? {
+            let mut values: HashMap<String, T> = get(&aggregated_key)?.into_iter().collect();
+
+            for message_id in message_ids {
+                let value = values
+                    .remove(&message_id)
+                    .ok_or_else(|| missing(message_id.clone()))?;
+                own.push((aggregated_key, message_id, value));
+            }
+        }
+        own.sort_by(|a, b| a.1.cmp(&b.1));
+
+        Ok(own)
+    }
+
+    // Validates the bundles of the other participants and groups their values by aggregated key
+    // and participant. Sorted so sessions are aggregated in the same order on every run.
+    #[allow(clippy::type_complexity)]
+    fn peer_values<B: SessionBundle>(
+        &self,
+        id: &str,
+        bundles: &[B],
+    ) -> Result<
+        BTreeMap<PublicKey, BTreeMap<PublicKey, Vec<(String, B::Value)>>>,
+        ProtocolBuilderError,
+    > {
+        let expected = self.aggregated_message_ids(id)?;
+        let mut peer_values: BTreeMap<PublicKey, BTreeMap<PublicKey, Vec<(String, B::Value)>>> =
+            BTreeMap::new();
+
+        for bundle in bundles {
+            if bundle.protocol_name() != self.name() || bundle.id() != id {
+                return Err(B::mismatch(
+                    format!("{}:{}", self.name(), id),
+                    format!("{}:{}", bundle.protocol_name(), bundle.id()),
+                ));
+            }
+
+            let mut received = BTreeSet::new();
+            for (aggregated_key, message_id, bytes) in bundle.entries() {
+                let known = expected
+                    .get(&aggregated_key)
+                    .is_some_and(|message_ids| message_ids.contains(message_id));
+                if !known || !received.insert((aggregated_key, message_id)) {
+                    return Err(B::unexpected(message_id.clone()));
+                }
+
+                let value = B::parse(bytes).ok_or_else(|| B::invalid(message_id.clone()))?;
+
+                peer_values
+                    .entry(aggregated_key)
+                    .or_default()
+                    .entry(bundle.participant())
+                    .or_default()
+                    .push((message_id.clone(), value));
+            }
+
+            for (aggregated_key, message_ids) in expected.iter() {
+                if let Some(message_id) = message_ids
+                    .iter()
+                    .find(|message_id| !received.contains(&(*aggregated_key, *message_id)))
+                {
+                    return Err(B::missing(message_id.clone()));
+                }
+            }
+        }
+
+        Ok(peer_values)
+    }
+
+    // Ids of the messages signed with each aggregated key.
+    fn aggregated_message_ids(
+        &self,
+        id: &str,
+    ) -> Result<BTreeMap<PublicKey, Vec<String>>, ProtocolBuilderError> {
+        let mut message_ids: BTreeMap<PublicKey, Vec<String>> = BTreeMap::new();
+        for (key, message) in aggregated_messages(self, id)? {
+            message_ids.entry(key).or_default().push(message.message_id);
+        }
+        Ok(message_ids)
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bitcoin::{
+    key::{Secp256k1, TapTweak},
+    XOnlyPublicKey,
+};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    types::{
+        input::{SighashType, Signature},
+        signing_request::{
+            SignatureId, SigningKey, SigningPackage, SigningRequest, SigningResponses,
+        },
+    },
+};
+
+use super::{
+    signing_plan::{InputSigningPlan, PlannedSignature, SignatureJob, SignatureSlot},
+    Protocol,
+};
+
+impl Protocol {
+    /// Exports the sighashes of the protocol along with the key and sighash type each must be
+    /// signed with, so they can be signed by an air-gapped signer that knows nothing about the
+    /// protocol. Signatures made with aggregated keys need a MuSig2 session and are not
+    /// exported. The protocol must be built.
+    pub fn export_signing_requests(&self) -> Result<SigningPackage, ProtocolBuilderError> {
+        let mut package = SigningPackage {
+            protocol_name: self.name().to_string(),
+            requests: vec![],
+        };
+
+        for plan in self.signing_plans()? {
+            for planned in plan.signatures.iter() {
+                let (message, key) = match &planned.job {
+                    SignatureJob::Schnorr { message, key } => (message, SigningKey::Schnorr(*key)),
+                    SignatureJob::SchnorrWithTweak {
+                        message,
+                        internal_key,
+                        merkle_root,
+                    } => (
+                        message,
+                        SigningKey::TweakedSchnorr {
+                            internal_key: *internal_key,
+                            merkle_root: *merkle_root,
+                        },
+                    ),
+                    SignatureJob::Ecdsa { message, key } => (message, SigningKey::Ecdsa(*key)),
+                    SignatureJob::Aggregated { .. } => continue,
+                };
+
+                package.requests.push(SigningRequest {
+                    id: plan.signature_id(planned.slot),
+                    sighash: *message.as_ref(),
+                    key,
+                    sighash_type: planned.sighash_type.clone(),
+                });
+            }
+        }
+
+        Ok(package)
+    }
+
+    /// Stores the signatures made by an air-gapped signer for the requests of
+    /// `export_signing_requests`. Every response must answer a request of the protocol with a
+    /// valid signature of the requested sighash type, otherwise nothing is stored. Requests
+    /// left unanswered keep their current signatures.
+    pub fn import_signing_responses(
+        &mut self,
+        responses: &SigningResponses,
+    ) -> Result<(), ProtocolBuilderError> {
+        if responses.protocol_name != self.name() {
+            return Err(ProtocolBuilderError::SigningResponsesMismatch(
+                self.name().to_string(),
+                responses.protocol_name.clone(),
+            ));
+        }
+
+        let plans = self.signing_plans()?;
+        let mut planned: HashMap<SignatureId, (usize, &PlannedSignature)> = HashMap::new();
+        for (plan_index, plan) in plans.iter().enumerate() {
+            for signature in plan.signatures.iter() {
+                if !matches!(signature.job, SignatureJob::Aggregated { .. }) {
+                    planned.insert(plan.signature_id(signature.slot), (plan_index, signature));
+                }
+            }
+        }
+
+        let mut received: BTreeMap<usize, Vec<(SignatureSlot, Signature)>> = BTreeMap::new();
+        for response in responses.responses.iter() {
+            let Some((plan_index, signature)) = planned.remove(&response.id) else {
+                return Err(ProtocolBuilderError::UnexpectedSigningResponse(
+                    response.id.to_string(),
+                ));
+            };
+            if !is_valid_response(signature, &response.signature) {
+                return Err(ProtocolBuilderError::InvalidSigningResponse(
+                    response.id.to_string(),
+                ));
+            }
+            received
+                .entry(plan_index)
+                .or_default()
+                .push((signature.slot, response.signature.clone()));
+        }
+
+        for (plan_index, signatures) in received {
+            self.store_signing_responses(&plans[plan_index], signatures)?;
+        }
+
+        Ok(())
+    }
+
+    fn store_signing_responses(
+        &mut self,
+        plan: &InputSigningPlan,
+        received: Vec<(SignatureSlot, Signature)>,
+    ) -> Result<(), ProtocolBuilderError> {
+        let input = &self.graph().get_inputs(&plan.transaction_name)?[plan.input_index];
+        let mut signatures = input.signatures().clone();
+        signatures.resize(plan.signature_count, None);
+        let mut multisig_signatures = input.multisig_signatures().clone();
+
+        for (slot, signature) in received {
+            match slot {
+                SignatureSlot::Path(index) => signatures[index] = Some(signature),
+                SignatureSlot::Multisig { leaf_index, slot } => {
+                    let slots = multisig_signatures.entry(leaf_index).or_default();
+                    if slots.len() <= slot {
+                        slots.resize(slot + 1, None);
+                    }
+                    if slot == 0 {
+                        signatures[leaf_index] = Some(signature.clone());
+                    }
+                    slots[slot] = Some(signature);
+                }
+            }
+        }
+
+        self.store_input_signatures(
+            &plan.transaction_name,
+            plan.input_index,
+            signatures,
+            plan.taproot.then_some(multisig_signatures),
+        )
+    }
+}
+
+// Checks the signature has the planned sighash type and verifies against the planned key.
+fn is_valid_response(planned: &PlannedSignature, signature: &Signature) -> bool {
+    let secp = Secp256k1::verification_only();
+    match (&planned.job, &planned.sighash_type, signature) {
+        (
+            SignatureJob::Schnorr { message, key },
+            SighashType::Taproot(expected),
+            Signature::Taproot(signature),
+        ) => {
+            signature.sighash_type == *expected
+                && secp
+                    .verify_schnorr(&signature.signature, message, &XOnlyPublicKey::from(*key))
+                    .is_ok()
+        }
+        (
+            SignatureJob::SchnorrWithTweak {
+                message,
+                internal_key,
+                merkle_root,
+            },
+            SighashType::Taproot(expected),
+            Signature::Taproot(signature),
+        ) => {
+            let (output_key, _) =
+                XOnlyPublicKey::from(*internal_key).tap_tweak(&secp, *merkle_root);
+            signature.sighash_type == *expected
+                && secp
+                    .verify_schnorr(
+                        &signature.signature,
+                        message,
+                        &output_key.to_x_only_public_key(),
+                    )
+                    .is_ok()
+        }
+        (
+            SignatureJob::Ecdsa { message, key },
+            SighashType::Ecdsa(expected),
+            Signature::Ecdsa(signature),
+        ) => {
+            signature.sighash_type == *expected
+                && secp
+                    .verify_ecdsa(message, &signature.signature, &key.inner)
+                    .is_ok()
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use bitcoin::PublicKey;
+
+use crate::{
+    errors::ProtocolBuilderError,
+    graph::graph::GraphOptions,
+    scripts::SignMode,
+    types::{
+        output::{spend_mode_params, OutputType},
+        ownership::{SigningManifest, SigningTask},
+    },
+};
+
+use super::Protocol;
+
+/// Colors assigned to the parties of a protocol, in order of first appearance.
+const PARTY_COLORS: [&str; 8] = [
+    "blue",
+    "red",
+    "darkgreen",
+    "orange",
+    "purple",
+    "brown",
+    "deeppink",
+    "cyan4",
+];
+
+impl Protocol {
+    /// Signatures the party owns, either through the owner of their input or of their leaf.
+    pub fn signing_manifest(&self, party: &str) -> Result<SigningManifest, ProtocolBuilderError> {
+        let tasks = self
+            .signing_tasks()?
+            .into_iter()
+            .filter(|task| {
+                self.owner(&task.transaction_name, task.input_index, task.leaf) == Some(party)
+            })
+            .collect();
+
+        Ok(SigningManifest {
+            party: party.to_string(),
+            tasks,
+        })
+    }
+
+    /// Checks that every owned signature produced with `SignMode::Single` uses one of the keys
+    /// controlled by its owner. Aggregated signatures are produced by several parties and are not
+    /// checked.
+    pub fn check_ownership(
+        &self,
+        party_keys: &HashMap<String, Vec<PublicKey>>,
+    ) -> Result<(), ProtocolBuilderError> {
+        for task in self.signing_tasks()? {
+            if task.sign_mode != SignMode::Single {
+                continue;
+            }
+
+            let Some(party) = self.owner(&task.transaction_name, task.input_index, task.leaf)
+            else {
+                continue;
+            };
+
+            let controlled = match (party_keys.get(party), task.public_key) {
+                (Some(keys), Some(public_key)) => keys.contains(&public_key),
+                _ => false,
+            };
+
+            if !controlled {
+                return Err(ProtocolBuilderError::OwnerKeyMismatch(
+                    task.transaction_name,
+                    task.input_index,
+                    task.leaf,
+                    party.to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Exports the protocol as a DOT graph where the connection to each input is colored by the
+    /// party signing it. Inputs whose leaves belong to different parties are not colored.
+    pub fn visualize_by_owner(
+        &self,
+        options: GraphOptions,
+    ) -> Result<String, ProtocolBuilderError> {
+        let mut parties: Vec<&str> = vec![];
+        let mut colors = HashMap::new();
+
+        for node in self.graph().nodes() {
+            for input_index in 0..node.inputs.len() {
+                let Some(party) = self.input_party(&node.name, input_index)? else {
+                    continue;
+                };
+
+                let position = match parties.iter().position(|other| *other == party) {
+                    Some(position) => position,
+                    None => {
+                        parties.push(party);
+                        parties.len() - 1
+                    }
+                };
+
+                colors.insert(
+                    (node.name.clone(), input_index),
+                    PARTY_COLORS[position % PARTY_COLORS.len()].to_string(),
+                );
+            }
+        }
+
+        Ok(self.graph().visualize_with_colors(options, &colors)?)
+    }
+
+    // Owner of the input, or the single owner of all its signatures.
+    fn input_party(
+        &self,
+        transaction_name: &str,
+        input_index: usize,
+    ) -> Result<Option<&str>, ProtocolBuilderError> {
+        if let Some(party) = self.owner(transaction_name, input_index, None) {
+            return Ok(Some(party));
+        }
+
+        let mut parties = self
+            .owners()
+            .iter()
+            .filter(|owner| {
+                owner.transaction_name == transaction_name && owner.input_index == input_index
+            })
+            .map(|owner| owner.party.as_str());
+
+        let party = parties.next();
+        if parties.any(|other| Some(other) != party) {
+            return Ok(None);
+        }
+        Ok(party)
+    }
+
+    // Signatures required by the spend mode of every input of the protocol.
+    fn signing_tasks(&self) -> Result<Vec<SigningTask>, ProtocolBuilderError> {
+        let mut tasks = vec![];
+
+        for node in self.graph().nodes().filter(|node| !node.external) {
+            for (input_index, input) in node.inputs.iter().enumerate() {
+                let messages = input.hashed_messages();
+                let task = |leaf: Option<usize>,
+                            message_index: usize,
+                            public_key: Option<PublicKey>,
+                            sign_mode: SignMode| SigningTask {
+                    transaction_name: node.name.clone(),
+                    input_index,
+                    leaf,
+                    public_key,
+                    sign_mode,
+                    message: messages
+                        .get(message_index)
+                        .copied()
+                        .flatten()
+                        .map(|message| message.as_ref().to_vec()),
+                };
+
+                match input.output_type()? {
+                    OutputType::SegwitPublicKey { public_key, .. } => {
+                        tasks.push(task(None, 0, Some(*public_key), SignMode::Single));
+                    }
+                    OutputType::SegwitScript { script, .. } if !script.skip_signing() => {
+                        let sign_mode = if script.aggregate_signing() {
+                            SignMode::Aggregate
+                        } else {
+                            SignMode::Single
+                        };
+                        tasks.push(task(None, 0, script.get_verifying_key(), sign_mode));
+                    }
+                    OutputType::Taproot {
+                        internal_key,
+                        leaves,
+                        ..
+                    } => {
+                        let (key_path, _, key_path_sign_mode, selected_leaves) =
+                            spend_mode_params(leaves, input.spend_mode())?;
+
+                        for (leaf_index, leaf) in selected_leaves.unwrap_or_default() {
+                            if leaf.skip_signing() {
+                                continue;
+                            }
+                            let sign_mode = if leaf.aggregate_signing() {
+                                SignMode::Aggregate
+                            } else {
+                                SignMode::Single
+                            };
+                            tasks.push(task(
+                                Some(leaf_index),
+                                leaf_index,
+                                leaf.get_verifying_key(),
+                                sign_mode,
+                            ));
+                        }
+
+                        if let (true, Some(sign_mode)) = (key_path, key_path_sign_mode) {
+                            if sign_mode != SignMode::Skip {
+                                tasks.push(task(
+                                    None,
+                                    leaves.len(),
+                                    Some(*internal_key),
+                                    sign_mode,
+                                ));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(tasks)
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    types::plan::{ConflictSet, ExecutionPlan, PlanStep},
+};
+
+use super::Protocol;
+
+impl Protocol {
+    /// Orders the transactions of the protocol topologically, annotating each one with the
+    /// transactions it depends on and the ones it excludes: transactions spending the same output
+    /// (e.g. a response and a timeout) are alternative branches, so choosing one invalidates the
+    /// others and all their descendants. Exclusion is symmetric: a transaction excludes the
+    /// transactions excluded by its ancestors. External transactions are dependencies but not
+    /// steps.
+    pub fn execution_plan(&self) -> Result<ExecutionPlan, ProtocolBuilderError> {
+        let connections = self.graph().stored_connections();
+
+        let mut spenders: BTreeMap<(&str, usize), Vec<&str>> = BTreeMap::new();
+        for stored in connections.iter() {
+            let output = (
+                stored.from.as_str(),
+                stored.connection.output_index as usize,
+            );
+            let spenders = spenders.entry(output).or_default();
+            if !spenders.contains(&stored.to.as_str()) {
+                spenders.push(&stored.to);
+            }
+        }
+
+        let mut plan = ExecutionPlan::default();
+        let mut conflicts: HashMap<&str, Vec<&str>> = HashMap::new();
+        for ((transaction_name, output_index), spenders) in spenders {
+            if spenders.len() < 2 {
+                continue;
+            }
+
+            for spender in spenders.iter() {
+                let conflicting = conflicts.entry(spender).or_default();
+                for other in spenders.iter().filter(|other| *other != spender) {
+                    if !conflicting.contains(other) {
+                        conflicting.push(other);
+                    }
+                }
+            }
+
+            plan.conflicts.push(ConflictSet {
+                transaction_name: transaction_name.to_string(),
+                output_index,
+                spenders: spenders.iter().map(|name| name.to_string()).collect(),
+            });
+        }
+
+        // Transactions invalidated by each conflicting transaction: its alternatives and their
+        // descendants
+        let mut invalidated: HashMap<&str, HashSet<String>> = HashMap::new();
+        for (name, conflicting) in conflicts.iter() {
+            let mut names = HashSet::new();
+            for other in conflicting {
+                names.insert(other.to_string());
+                names.extend(self.descendants(other)?);
+            }
+            invalidated.insert(name, names);
+        }
+
+        let order = self.graph().sort()?;
+        for name in order.iter() {
+            let mut excluded = HashSet::new();
+            for ancestor in self.ancestors(name)?.iter().chain(Some(name)) {
+                if let Some(names) = invalidated.get(ancestor.as_str()) {
+                    excluded.extend(names.iter().cloned());
+                }
+            }
+
+            let mut depends_on = vec![];
+            for stored in connections.iter().filter(|stored| stored.to == *name) {
+                if !depends_on.contains(&stored.from) {
+                    depends_on.push(stored.from.clone());
+                }
+            }
+
+            plan.steps.push(PlanStep {
+                transaction_name: name.clone(),
+                depends_on,
+                conflicts_with: conflicts
+                    .get(name.as_str())
+                    .map(|names| names.iter().map(|name| name.to_string()).collect())
+                    .unwrap_or_default(),
+                excludes: order
+                    .iter()
+                    .filter(|other| *other != name && excluded.contains(*other))
+                    .cloned()
+                    .collect(),
+            });
+        }
+
+        Ok(plan)
+    }
+
+    /// Transactions spending the given output. Several spenders are alternative branches (e.g. a
+    /// response and a timeout), of which at most one can be mined.
+    pub fn spenders(
+        &self,
+        transaction_name: &str,
+        output_index: usize,
+    ) -> Result<Vec<String>, ProtocolBuilderError> {
+        Ok(self.graph().spenders(transaction_name, output_index)?)
+    }
+
+    /// Checks that all the given transactions can be mined together, i.e. none of them belongs to
+    /// an alternative branch of another one.
+    pub fn check_branch(&self, branch: &[String]) -> Result<(), ProtocolBuilderError> {
+        let plan = self.execution_plan()?;
+
+        for (index, name) in branch.iter().enumerate() {
+            if let Some(other) = branch[index + 1..]
+                .iter()
+                .find(|other| plan.are_exclusive(name, other))
+            {
+                return Err(ProtocolBuilderError::ConflictingTransactions(
+                    name.clone(),
+                    other.clone(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,81 +1,632 @@
 use bitcoin::{
+    absolute::LockTime,
+    hashes::sha256,
     locktime,
     secp256k1::{self, Message},
     taproot::LeafVersion,
-    transaction, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, Txid, Witness,
+    transaction, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxOut, Txid, Witness,
     XOnlyPublicKey,
 };
-use key_manager::key_manager::KeyManager;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, rc::Rc, vec};
+use std::{
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    sync::Arc,
+    vec,
+};
 use storage_backend::storage::{KeyValueStore, Storage};
 
 use crate::{
-    errors::ProtocolBuilderError,
-    graph::graph::{GraphOptions, TransactionGraph},
-    scripts::ProtocolScript,
+    errors::{GraphError, ProtocolBuilderError, ScriptError, SerializationError},
+    graph::{
+        estimate::{estimate_min_relay_fee, stripped_size_bytes},
+        graph::{prefixed_name, GraphOptions, TransactionGraph},
+        package::{check_package_limits, requires_confirmation, PackageLimitReport, PackageLimits},
+    },
+    helpers::{
+        malleability::{
+            leaf_malleability, winternitz_leaf_report, LeafMalleabilityReport, WinternitzLeafReport,
+        },
+        weight_computing::get_transaction_hex,
+    },
+    perf::{BuildTimer, BuildTimings},
+    scripts::{ConstantValue, ProtocolScript, ScriptTemplate, SignMode},
     types::{
+        audit::{AuditEntry, AuditEvent},
+        broadcast_rule::BroadcastRule,
+        change::{ChangeDestination, ChangeOutput},
+        commitment::{leaf_hash, merkle_path, merkle_root, InclusionProof},
         connection::{ConnectionType, InputSpec, OutputSpec},
+        dust::{DustAction, DustPolicy},
+        event::{Observers, ProtocolEvent},
+        external::{ExternalOutput, ExternalTx},
+        funding::{FundingSource, FundingUtxo},
+        handle::{OutputHandle, TxHandle},
         input::{InputArgs, InputSignatures, InputType, SighashType, Signature, SpendMode},
-        output::OutputType,
+        leaf_template::TemplateManifest,
+        limits::{ProtocolLimit, ProtocolLimits},
+        output::{ConstantUsage, LeafInfo, OutputDescriptor, OutputType, SpeedupData, Utxo},
+        ownership::InputOwner,
+        sequence::SequencePolicy,
+        serialization::{deserialize, serialize, SerializationFormat},
+        signer::Signer,
+        skeleton::{ResignedImport, SkeletonInput, SkeletonOutput, TransactionSkeleton},
+    },
+    unspendable::{
+        deterministic_unspendable_key, unspendable_key_with_derivation, verify_unspendable,
     },
-    unspendable::unspendable_key,
 };
 
-use super::check_params::{check_empty_connection_name, check_empty_transaction_name};
+use super::{
+    check_params::{check_empty_connection_name, check_empty_transaction_name},
+    history::{Checkpoint, History},
+    signing_plan::{InputSigningPlan, SignatureChecks},
+    verification::{check_witness, SignatureStatus},
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Protocol {
     name: String,
     graph: TransactionGraph,
+    #[serde(default)]
+    constants: BTreeMap<String, ConstantValue>,
+    #[serde(default)]
+    external_transactions: BTreeMap<String, ExternalTx>,
+    #[serde(default)]
+    broadcast_rules: BTreeMap<String, BroadcastRule>,
+    #[serde(default)]
+    require_unspendable_proofs: bool,
+    #[serde(default)]
+    unspendable_proofs: BTreeMap<XOnlyPublicKey, [u8; 32]>,
+    #[serde(default)]
+    dust_policy: Option<DustPolicy>,
+    #[serde(default)]
+    frozen: bool,
+    /// Whether the protocol was unfrozen since it was last built.
+    #[serde(default)]
+    unfrozen: bool,
+    #[serde(default)]
+    audit_trail: Vec<AuditEntry>,
+    #[serde(default)]
+    change: Option<ChangeOutput>,
+    #[serde(default)]
+    limits: ProtocolLimits,
+    #[serde(default)]
+    owners: Vec<InputOwner>,
+    #[serde(default)]
+    history: Option<History>,
+    #[serde(default)]
+    agreed_templates: Option<TemplateManifest>,
+    #[serde(default)]
+    fees: BTreeMap<String, u64>,
+    #[serde(default)]
+    require_amount_conservation: bool,
+    #[serde(default)]
+    sequence_policies: BTreeMap<String, SequencePolicy>,
+    #[serde(default)]
+    revision: u64,
+    #[serde(default)]
+    seed: Option<[u8; 32]>,
+    #[serde(default)]
+    speedup_outputs: BTreeMap<String, usize>,
+    #[serde(default)]
+    derived_unspendable_keys: usize,
+    #[serde(skip)]
+    observers: Observers,
+    #[serde(skip)]
+    build_timings: Option<BuildTimings>,
 }
 
+/// Protocol data other than the transaction graph, persisted as a single chunk by
+/// `Protocol::save_chunked`. It is the protocol itself with an empty graph, so every field of
+/// the protocol is persisted without listing them again.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct ProtocolMetadata(Protocol);
+
 impl Protocol {
     pub fn new(name: &str) -> Self {
         Protocol {
             name: name.to_string(),
             graph: TransactionGraph::new(),
+            constants: BTreeMap::new(),
+            external_transactions: BTreeMap::new(),
+            broadcast_rules: BTreeMap::new(),
+            require_unspendable_proofs: false,
+            unspendable_proofs: BTreeMap::new(),
+            dust_policy: None,
+            frozen: false,
+            unfrozen: false,
+            audit_trail: vec![],
+            change: None,
+            limits: ProtocolLimits::default(),
+            owners: vec![],
+            history: None,
+            agreed_templates: None,
+            fees: BTreeMap::new(),
+            require_amount_conservation: false,
+            sequence_policies: BTreeMap::new(),
+            revision: 0,
+            seed: None,
+            speedup_outputs: BTreeMap::new(),
+            derived_unspendable_keys: 0,
+            observers: Observers::default(),
+            build_timings: None,
         }
     }
 
+    /// Protocol whose unspendable keys are derived from `seed` instead of random, see
+    /// `create_unspendable_key`. Parties building the same protocol with the same seed get
+    /// byte-identical transactions.
+    pub fn new_deterministic(name: &str, seed: [u8; 32]) -> Self {
+        let mut protocol = Self::new(name);
+        protocol.seed = Some(seed);
+        protocol
+    }
+
+    pub fn seed(&self) -> Option<[u8; 32]> {
+        self.seed
+    }
+
+    /// Loads a protocol saved with `save` or `save_with_format`, in any format. Fails with
+    /// `GraphError::IndexDrift` if the stored node indexes do not match the transactions, see
+    /// `load_and_rebuild_indexes`.
     pub fn load(name: &str, storage: Rc<Storage>) -> Result<Option<Self>, ProtocolBuilderError> {
-        Ok(storage.get(&name)?)
+        let protocol = Self::load_unchecked(name, storage)?;
+        if let Some(protocol) = protocol.as_ref() {
+            protocol.graph.check_indexes()?;
+        }
+        Ok(protocol)
+    }
+
+    /// Loads a protocol as `load`, reconstructing its node indexes from the transaction names.
+    pub fn load_and_rebuild_indexes(
+        name: &str,
+        storage: Rc<Storage>,
+    ) -> Result<Option<Self>, ProtocolBuilderError> {
+        let mut protocol = Self::load_unchecked(name, storage)?;
+        if let Some(protocol) = protocol.as_mut() {
+            protocol.rebuild_indexes()?;
+        }
+        Ok(protocol)
+    }
+
+    fn load_unchecked(
+        name: &str,
+        storage: Rc<Storage>,
+    ) -> Result<Option<Self>, ProtocolBuilderError> {
+        let encoded: Option<String> = storage.get(encoded_key(name))?;
+        match encoded {
+            Some(encoded) => {
+                let bytes = hex::decode(encoded).map_err(|_| {
+                    ProtocolBuilderError::SerializationError(SerializationError::InvalidHeader)
+                })?;
+                Ok(Some(deserialize(&bytes)?))
+            }
+            None => Ok(storage.get(name)?),
+        }
+    }
+
+    /// Reconstructs the node indexes of the graph from the transaction names, repairing a
+    /// protocol that failed to load with `GraphError::IndexDrift`.
+    pub fn rebuild_indexes(&mut self) -> Result<&mut Self, ProtocolBuilderError> {
+        self.graph.rebuild_indexes()?;
+        Ok(self)
     }
 
     pub fn save(&self, storage: Rc<Storage>) -> Result<(), ProtocolBuilderError> {
-        storage.set(&self.name, &self, None)?;
+        self.save_with_format(storage, SerializationFormat::Json)
+    }
+
+    /// Saves the protocol with the given format. JSON protocols are stored as plain values, as
+    /// `save` always did; binary formats are stored hex-encoded under a separate key.
+    ///
+    /// The protocol keeps its revision, and saving fails with `RevisionConflict` if a newer
+    /// revision is stored, so a stale copy never overwrites a protocol saved with
+    /// `save_checked`. Processes sharing a storage should use `save_checked` so they do not
+    /// clobber each other's changes made at the same revision.
+    pub fn save_with_format(
+        &self,
+        storage: Rc<Storage>,
+        format: SerializationFormat,
+    ) -> Result<(), ProtocolBuilderError> {
+        self.write(storage, format, RevisionCheck::NotNewer(self.revision))
+    }
+
+    pub fn save_checked(&mut self, storage: Rc<Storage>) -> Result<(), ProtocolBuilderError> {
+        self.save_checked_with_format(storage, SerializationFormat::Json)
+    }
+
+    /// Saves the protocol as `save_with_format` and increments its revision. Saving fails with
+    /// `RevisionConflict` if the stored protocol was saved by someone else since this one was
+    /// loaded or saved, see `reload_and_merge_signatures`.
+    ///
+    /// The stored revision is read and compared in the same storage transaction that writes the
+    /// protocol and its new revision, so no other save can slip in between. The revision of this
+    /// protocol is left as it was if the save fails, so it can be retried.
+    pub fn save_checked_with_format(
+        &mut self,
+        storage: Rc<Storage>,
+        format: SerializationFormat,
+    ) -> Result<(), ProtocolBuilderError> {
+        let expected = self.revision;
+        self.revision += 1;
+        let result = self.write(storage, format, RevisionCheck::Equal(expected));
+        if result.is_err() {
+            self.revision = expected;
+        }
+        result
+    }
+
+    // Writes the protocol and its revision, and deletes the copy written in the other format by a
+    // previous save, all in one storage transaction. The stored revision is read in that
+    // transaction too, and the transaction is rolled back if it fails the check.
+    fn write(
+        &self,
+        storage: Rc<Storage>,
+        format: SerializationFormat,
+        check: RevisionCheck,
+    ) -> Result<(), ProtocolBuilderError> {
+        let transaction = storage.begin_transaction();
+        let write = || -> Result<(), ProtocolBuilderError> {
+            let stored = match storage.transactional_read(&revision_key(&self.name), transaction)? {
+                Some(value) => value.parse().map_err(|e: std::num::ParseIntError| {
+                    SerializationError::DecodeError("revision".to_string(), e.to_string())
+                })?,
+                None => 0,
+            };
+            let (expected, conflict) = match check {
+                RevisionCheck::Equal(expected) => (expected, stored != expected),
+                RevisionCheck::NotNewer(revision) => (revision, stored > revision),
+            };
+            if conflict {
+                return Err(ProtocolBuilderError::RevisionConflict(
+                    self.name.clone(),
+                    expected,
+                    stored,
+                ));
+            }
+
+            let stale = match format {
+                SerializationFormat::Json => {
+                    storage.set(&self.name, self, Some(transaction))?;
+                    encoded_key(&self.name)
+                }
+                _ => {
+                    storage.set(
+                        encoded_key(&self.name),
+                        hex::encode(self.to_bytes(format)?),
+                        Some(transaction),
+                    )?;
+                    self.name.clone()
+                }
+            };
+            storage.set(revision_key(&self.name), self.revision, Some(transaction))?;
+            storage.transactional_delete(&stale, transaction)?;
+            Ok(())
+        };
+
+        match write() {
+            Ok(()) => {
+                storage.commit_transaction(transaction)?;
+                Ok(())
+            }
+            Err(error) => {
+                storage.rollback_transaction(transaction)?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Revision of the protocol, incremented every time it is saved.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub(super) fn set_revision(&mut self, revision: u64) {
+        self.revision = revision;
+    }
+
+    /// Revision of the protocol last saved with the given name, 0 if it was never saved.
+    pub fn stored_revision(name: &str, storage: Rc<Storage>) -> Result<u64, ProtocolBuilderError> {
+        Ok(storage.get(revision_key(name))?.unwrap_or_default())
+    }
+
+    /// Removes a protocol saved with `save` or `save_with_format`, in any format.
+    pub fn delete(name: &str, storage: Rc<Storage>) -> Result<(), ProtocolBuilderError> {
+        storage.delete(name)?;
+        storage.delete(&encoded_key(name))?;
+        storage.delete(&revision_key(name))?;
+        Ok(())
+    }
+
+    /// Encodes the protocol to exchange it with other parties. The bytes carry a versioned
+    /// header with the format, so `from_bytes` reads any of them.
+    pub fn to_bytes(&self, format: SerializationFormat) -> Result<Vec<u8>, ProtocolBuilderError> {
+        let bytes = serialize(self, format)?;
+        self.check_limit(ProtocolLimit::SerializedSize, bytes.len())?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProtocolBuilderError> {
+        let protocol: Self = deserialize(bytes)?;
+        protocol.graph.check_indexes()?;
+        Ok(protocol)
+    }
+
+    /// Decodes a protocol received from an untrusted party, rejecting it before decoding when it
+    /// is larger than the maximum serialized size, and after decoding when it exceeds any other
+    /// limit. The limits are kept for any further change to the protocol.
+    pub fn from_bytes_with_limits(
+        bytes: &[u8],
+        limits: &ProtocolLimits,
+    ) -> Result<Self, ProtocolBuilderError> {
+        if let Some(max) = limits.max_serialized_size {
+            if bytes.len() > max {
+                return Err(ProtocolBuilderError::LimitExceeded(
+                    ProtocolLimit::SerializedSize,
+                    bytes.len(),
+                    max,
+                ));
+            }
+        }
+
+        let mut protocol = Self::from_bytes(bytes)?;
+        protocol.limits = limits.clone();
+        protocol.check_limits()?;
+        Ok(protocol)
+    }
+
+    /// Sets hard caps on the size of the protocol, enforced by every method adding transactions
+    /// or outputs, by the build methods and when the protocol is encoded. Fails, keeping the
+    /// previous limits, if the protocol already exceeds them.
+    pub fn set_limits(
+        &mut self,
+        limits: ProtocolLimits,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        let previous = std::mem::replace(&mut self.limits, limits);
+        if let Err(error) = self.check_limits() {
+            self.limits = previous;
+            return Err(error);
+        }
+        Ok(self)
+    }
+
+    pub fn limits(&self) -> &ProtocolLimits {
+        &self.limits
+    }
+
+    /// Checks the whole protocol against its limits.
+    pub fn check_limits(&self) -> Result<(), ProtocolBuilderError> {
+        self.check_limit(ProtocolLimit::Transactions, self.graph.nodes().count())?;
+
+        for node in self.graph.nodes() {
+            self.check_transaction_limits(node.transaction.input.len(), node.outputs.len())?;
+            for output in node.outputs.iter() {
+                self.check_limit(ProtocolLimit::LeavesPerOutput, leaf_count(output))?;
+            }
+        }
+        self.check_limit(ProtocolLimit::TotalLeaves, self.total_leaves())?;
+
+        if self.limits.max_serialized_size.is_some() {
+            let size = serialize(self, SerializationFormat::Json)?.len();
+            self.check_limit(ProtocolLimit::SerializedSize, size)?;
+        }
+
         Ok(())
     }
 
+    fn check_limit(&self, limit: ProtocolLimit, value: usize) -> Result<(), ProtocolBuilderError> {
+        match self.limits.get(&limit) {
+            Some(max) if value > max => Err(ProtocolBuilderError::LimitExceeded(limit, value, max)),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_transaction_limits(
+        &self,
+        inputs: usize,
+        outputs: usize,
+    ) -> Result<(), ProtocolBuilderError> {
+        self.check_limit(ProtocolLimit::InputsPerTransaction, inputs)?;
+        self.check_limit(ProtocolLimit::OutputsPerTransaction, outputs)
+    }
+
+    fn total_leaves(&self) -> usize {
+        self.graph
+            .nodes()
+            .flat_map(|node| node.outputs.iter())
+            .map(leaf_count)
+            .sum()
+    }
+
+    // Checks the leaf limits for an output added to the protocol, replacing `replaced` if any.
+    fn check_output_limits(
+        &self,
+        output: &OutputType,
+        replaced: Option<&OutputType>,
+    ) -> Result<(), ProtocolBuilderError> {
+        let leaves = leaf_count(output);
+        self.check_limit(ProtocolLimit::LeavesPerOutput, leaves)?;
+
+        let total = self.total_leaves() + leaves - replaced.map(leaf_count).unwrap_or_default();
+        self.check_limit(ProtocolLimit::TotalLeaves, total)
+    }
+
     pub fn add_transaction(
         &mut self,
         transaction_name: &str,
     ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
         self.get_or_create_transaction(transaction_name, false)?;
+        self.record_mutation(format!("add_transaction {}", transaction_name));
         Ok(self)
     }
 
+    /// Adds a transaction, returning a handle that can be passed instead of its name.
+    pub fn new_transaction(
+        &mut self,
+        transaction_name: &str,
+    ) -> Result<TxHandle, ProtocolBuilderError> {
+        self.add_transaction(transaction_name)?;
+        Ok(TxHandle::new(transaction_name))
+    }
+
+    /// Adds an external transaction, returning a handle that can be passed instead of its name.
+    pub fn new_external_transaction(
+        &mut self,
+        transaction_name: &str,
+    ) -> Result<TxHandle, ProtocolBuilderError> {
+        self.add_external_transaction(transaction_name)?;
+        Ok(TxHandle::new(transaction_name))
+    }
+
+    /// Handle of an existing transaction, e.g. one added with the string API.
+    pub fn handle(&self, transaction_name: &str) -> Result<TxHandle, ProtocolBuilderError> {
+        if !self.graph.contains_transaction(transaction_name) {
+            return Err(GraphError::MissingTransaction(transaction_name.to_string()).into());
+        }
+        Ok(TxHandle::new(transaction_name))
+    }
+
     pub fn add_external_transaction(
         &mut self,
         transaction_name: &str,
     ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
         self.get_or_create_transaction(transaction_name, true)?;
+        self.record_mutation(format!("add_external_transaction {}", transaction_name));
         Ok(self)
     }
 
+    /// Declares a transaction created outside the protocol. Its known outputs can be spent by
+    /// protocol transactions, while connections to its opaque outputs are rejected.
+    pub fn add_external_tx(
+        &mut self,
+        external: ExternalTx,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        check_empty_transaction_name(external.name())?;
+
+        if self.graph.contains_transaction(external.name()) {
+            return Err(GraphError::TransactionAlreadyExists(external.name().to_string()).into());
+        }
+
+        self.get_or_create_transaction(external.name(), true)?;
+
+        self.untracked(|protocol| {
+            for output in external.outputs() {
+                match output {
+                    ExternalOutput::Known(output_type) => {
+                        protocol.add_transaction_output(external.name(), output_type)?;
+                    }
+                    ExternalOutput::Opaque(count) => {
+                        protocol.add_unknown_outputs(external.name(), *count)?;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        self.record_mutation(format!("add_external_tx {}", external.name()));
+        self.external_transactions
+            .insert(external.name().to_string(), external);
+        Ok(self)
+    }
+
+    /// Funds a transaction from the UTXOs of a funding source, selected with its coin selection
+    /// strategy to gather at least `amount` sats. The UTXOs of each funding transaction are
+    /// declared as an external transaction named `{to}_funding_{n}`, connected to new inputs of
+    /// `to`. Returns the selected UTXOs.
+    pub fn add_funding(
+        &mut self,
+        source: &FundingSource,
+        to: &str,
+        amount: u64,
+    ) -> Result<Vec<FundingUtxo>, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        check_empty_transaction_name(to)?;
+
+        let selected = source.select(amount)?;
+
+        let mut txids: Vec<Txid> = vec![];
+        for utxo in selected.iter() {
+            if !txids.contains(&utxo.txid) {
+                txids.push(utxo.txid);
+            }
+        }
+
+        let prefix = format!("{}_funding_", to);
+        let funded = self
+            .external_transactions
+            .keys()
+            .filter(|name| name.starts_with(&prefix))
+            .count();
+
+        self.untracked(|protocol| {
+            for (index, txid) in txids.iter().enumerate() {
+                let mut utxos: Vec<&FundingUtxo> =
+                    selected.iter().filter(|utxo| utxo.txid == *txid).collect();
+                utxos.sort_by_key(|utxo| utxo.vout);
+
+                // Outputs that are not spent are declared opaque to preserve the output indexes
+                let name = format!("{}{}", prefix, funded + index);
+                let mut external = ExternalTx::new(&name, *txid);
+                let mut next_vout = 0;
+                for utxo in utxos.iter() {
+                    external = external
+                        .with_opaque_outputs(utxo.vout.saturating_sub(next_vout))
+                        .with_known_output(utxo.output.clone());
+                    next_vout = utxo.vout + 1;
+                }
+                protocol.add_external_tx(external)?;
+
+                for utxo in utxos {
+                    protocol.add_connection(
+                        &format!("{}_{}", name, utxo.vout),
+                        &name,
+                        OutputSpec::Index(utxo.vout as usize),
+                        to,
+                        InputSpec::Auto(utxo.sighash_type(), utxo.spend_mode()),
+                        None,
+                        Some(*txid),
+                    )?;
+                }
+            }
+            Ok(())
+        })?;
+
+        self.record_mutation(format!("add_funding {}", to));
+        Ok(selected)
+    }
+
+    pub fn external_transaction(&self, transaction_name: &str) -> Option<&ExternalTx> {
+        self.external_transactions.get(transaction_name)
+    }
+
+    pub fn external_transactions(&self) -> &BTreeMap<String, ExternalTx> {
+        &self.external_transactions
+    }
+
     pub fn add_unknown_outputs(
         &mut self,
         transaction_name: &str,
         count: u32,
     ) -> Result<&mut Self, ProtocolBuilderError> {
-        for _ in 0..count {
-            self.add_transaction_output(
-                transaction_name,
-                &OutputType::ExternalUnknown {
-                    script_pubkey: ScriptBuf::default(),
-                },
-            )?;
-        }
+        self.check_not_frozen()?;
+        self.untracked(|protocol| {
+            for _ in 0..count {
+                protocol.add_transaction_output(
+                    transaction_name,
+                    &OutputType::ExternalUnknown {
+                        script_pubkey: ScriptBuf::default(),
+                    },
+                )?;
+            }
+            Ok(())
+        })?;
+        self.record_mutation(format!("add_unknown_outputs {}", transaction_name));
         Ok(self)
     }
 
@@ -89,6 +640,12 @@ impl Protocol {
         sighash_type: &SighashType,
     ) -> Result<&mut Self, ProtocolBuilderError> {
         check_empty_transaction_name(transaction_name)?;
+        self.check_not_frozen()?;
+        let inputs = self
+            .transaction_by_name(transaction_name)
+            .map(|transaction| transaction.input.len())
+            .unwrap_or_default();
+        self.check_limit(ProtocolLimit::InputsPerTransaction, inputs + 1)?;
 
         let mut transaction = self.get_or_create_transaction(transaction_name, false)?;
 
@@ -109,6 +666,7 @@ impl Protocol {
             sighash_type,
         )?;
 
+        self.record_mutation(format!("add_transaction_input {}", transaction_name));
         Ok(self)
     }
 
@@ -118,6 +676,13 @@ impl Protocol {
         output_type: &OutputType,
     ) -> Result<&mut Self, ProtocolBuilderError> {
         check_empty_transaction_name(transaction_name)?;
+        self.check_not_frozen()?;
+        self.check_output_limits(output_type, None)?;
+        let outputs = self
+            .transaction_by_name(transaction_name)
+            .map(|transaction| transaction.output.len())
+            .unwrap_or_default();
+        self.check_limit(ProtocolLimit::OutputsPerTransaction, outputs + 1)?;
 
         let mut transaction = self.get_or_create_transaction(transaction_name, false)?;
 
@@ -129,9 +694,178 @@ impl Protocol {
         self.graph
             .add_transaction_output(transaction_name, transaction, output_type.clone())?;
 
+        self.record_mutation(format!("add_transaction_output {}", transaction_name));
+        Ok(self)
+    }
+
+    /// Sets the absolute locktime (nLockTime) of a transaction. The sequences of its inputs without
+    /// a relative timelock are recomputed from its sequence policy, so a `Final` policy switches
+    /// them to `ENABLE_LOCKTIME_NO_RBF` and the locktime is enforced.
+    pub fn set_locktime(
+        &mut self,
+        transaction_name: &str,
+        lock_time: LockTime,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        check_empty_transaction_name(transaction_name)?;
+
+        let mut transaction = self.transaction_by_name(transaction_name)?.clone();
+        transaction.lock_time = lock_time;
+        let sequence = self.sequence_policy(transaction_name).sequence(lock_time);
+        for txin in transaction.input.iter_mut() {
+            if !requires_confirmation(txin.sequence) {
+                txin.sequence = sequence;
+            }
+        }
+        self.graph
+            .update_transaction(transaction_name, transaction)?;
+
+        self.record_mutation(format!("set_locktime {}", transaction_name));
+        Ok(self)
+    }
+
+    /// Sets the version of a transaction: 1, 2 (the default, enforcing BIP 68 relative
+    /// timelocks) or 3 (TRUC, BIP 431). `validate` checks the topology constraints of TRUC
+    /// transactions and the relative timelocks of version 1 transactions against the graph.
+    pub fn set_transaction_version(
+        &mut self,
+        transaction_name: &str,
+        version: transaction::Version,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        check_empty_transaction_name(transaction_name)?;
+        if !(1..=3).contains(&version.0) {
+            return Err(ProtocolBuilderError::UnsupportedTransactionVersion(
+                transaction_name.to_string(),
+                version.0,
+            ));
+        }
+
+        let mut transaction = self.transaction_by_name(transaction_name)?.clone();
+        transaction.version = version;
+        self.graph
+            .update_transaction(transaction_name, transaction)?;
+
+        self.record_mutation(format!("set_transaction_version {}", transaction_name));
         Ok(self)
     }
 
+    pub fn transaction_version(
+        &self,
+        transaction_name: &str,
+    ) -> Result<transaction::Version, ProtocolBuilderError> {
+        Ok(self.transaction_by_name(transaction_name)?.version)
+    }
+
+    /// Sets the sequence of the inputs of a transaction without a relative timelock, both the
+    /// current ones and the ones added later by connections. Inputs added with
+    /// `add_transaction_input` keep the sequence they are given.
+    pub fn set_sequence_policy(
+        &mut self,
+        transaction_name: &str,
+        policy: SequencePolicy,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        check_empty_transaction_name(transaction_name)?;
+
+        let mut transaction = self.transaction_by_name(transaction_name)?.clone();
+        let sequence = policy.sequence(transaction.lock_time);
+        for txin in transaction.input.iter_mut() {
+            if !requires_confirmation(txin.sequence) {
+                txin.sequence = sequence;
+            }
+        }
+        self.graph
+            .update_transaction(transaction_name, transaction)?;
+        self.sequence_policies
+            .insert(transaction_name.to_string(), policy);
+
+        self.record_mutation(format!("set_sequence_policy {}", transaction_name));
+        Ok(self)
+    }
+
+    /// Sequence policy of a transaction, `Replaceable` unless set with `set_sequence_policy`.
+    pub fn sequence_policy(&self, transaction_name: &str) -> SequencePolicy {
+        self.sequence_policies
+            .get(transaction_name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Sequence of an input added by a connection, its relative timelock if any.
+    fn connection_sequence(
+        &self,
+        connection_type: &ConnectionType,
+    ) -> Result<Sequence, ProtocolBuilderError> {
+        let sequence = connection_type.sequence();
+        if sequence.is_relative_lock_time() {
+            return Ok(sequence);
+        }
+
+        let lock_time = self.transaction_by_name(connection_type.to())?.lock_time;
+        Ok(self
+            .sequence_policy(connection_type.to())
+            .sequence(lock_time))
+    }
+
+    /// Opts a transaction into (or out of) BIP 125 replaceability by switching the sequences of
+    /// its inputs between `ENABLE_RBF_NO_LOCKTIME` and a final sequence (`ENABLE_LOCKTIME_NO_RBF`
+    /// when the transaction has an absolute locktime, so it is still enforced). Inputs with a
+    /// relative timelock keep their sequence, which always signals replaceability, so opting out
+    /// fails if the transaction has any. The transaction and its descendants are rebuilt, returning
+    /// their names as `rebuild` does.
+    pub fn set_replaceable<S: Signer + ?Sized>(
+        &mut self,
+        transaction_name: &str,
+        replaceable: bool,
+        signer: &S,
+        id: &str,
+    ) -> Result<Vec<String>, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        check_empty_transaction_name(transaction_name)?;
+
+        let mut transaction = self.transaction_by_name(transaction_name)?.clone();
+        let final_sequence = if transaction.lock_time == LockTime::ZERO {
+            Sequence::MAX
+        } else {
+            Sequence::ENABLE_LOCKTIME_NO_RBF
+        };
+
+        for (input_index, txin) in transaction.input.iter_mut().enumerate() {
+            if requires_confirmation(txin.sequence) {
+                if !replaceable {
+                    return Err(ProtocolBuilderError::CsvConstrainedInput(
+                        transaction_name.to_string(),
+                        input_index,
+                    ));
+                }
+                continue;
+            }
+
+            txin.sequence = if replaceable {
+                Sequence::ENABLE_RBF_NO_LOCKTIME
+            } else {
+                final_sequence
+            };
+        }
+
+        self.graph
+            .update_transaction(transaction_name, transaction)?;
+        let rebuilt = self.rebuild(signer, id)?;
+
+        self.record_mutation(format!("set_replaceable {}", transaction_name));
+        Ok(rebuilt)
+    }
+
+    /// Returns true if any input of the transaction signals BIP 125 replaceability.
+    pub fn is_replaceable(&self, transaction_name: &str) -> Result<bool, ProtocolBuilderError> {
+        Ok(self
+            .transaction_by_name(transaction_name)?
+            .input
+            .iter()
+            .any(|txin| txin.sequence.is_rbf()))
+    }
+
     pub fn get_output_count(&self, transaction_name: &str) -> Result<u32, ProtocolBuilderError> {
         let transaction = self.transaction_by_name(transaction_name)?;
         Ok(transaction.output.len() as u32)
@@ -140,13 +874,33 @@ impl Protocol {
     pub fn add_connection(
         &mut self,
         connection_name: &str,
-        from: &str,
+        from: impl AsRef<str>,
         output: OutputSpec,
-        to: &str,
+        to: impl AsRef<str>,
         input: InputSpec,
         timelock: Option<u16>,
         txid: Option<Txid>,
     ) -> Result<&mut Self, ProtocolBuilderError> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+
+        // Connections from declared external transactions can only spend their known outputs
+        let txid = match self.external_transactions.get(from) {
+            Some(external) => {
+                if let Some(txid) = txid {
+                    if txid != external.txid() {
+                        return Err(ProtocolBuilderError::ExternalTxidMismatch(
+                            from.to_string(),
+                            external.txid(),
+                            txid,
+                        ));
+                    }
+                }
+                self.check_external_output(external, &output)?;
+                Some(external.txid())
+            }
+            None => txid,
+        };
+
         let connection_type = match txid {
             Some(txid) => ConnectionType::external(txid, from, output, to, input, timelock),
             None => ConnectionType::internal(from, output, to, input, timelock),
@@ -155,11 +909,42 @@ impl Protocol {
         self.add_connection_aux(connection_name, connection_type)
     }
 
+    /// Connects an existing output to a new (or existing) input of another transaction, as
+    /// `add_connection` with `OutputSpec::Index`.
+    pub fn connect_output(
+        &mut self,
+        connection_name: &str,
+        output: &OutputHandle,
+        to: &TxHandle,
+        input: InputSpec,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.add_connection(
+            connection_name,
+            output.transaction(),
+            OutputSpec::Index(output.index()),
+            to,
+            input,
+            None,
+            None,
+        )
+    }
+
     fn add_connection_aux(
         &mut self,
         connection_name: &str,
         connection_type: ConnectionType,
     ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.untracked(|protocol| protocol.connect(connection_name, connection_type))?;
+        self.record_mutation(format!("add_connection {}", connection_name));
+        Ok(self)
+    }
+
+    fn connect(
+        &mut self,
+        connection_name: &str,
+        connection_type: ConnectionType,
+    ) -> Result<(), ProtocolBuilderError> {
+        self.check_not_frozen()?;
         check_empty_connection_name(connection_name)?;
         check_empty_transaction_name(connection_type.from())?;
         check_empty_transaction_name(connection_type.to())?;
@@ -171,6 +956,35 @@ impl Protocol {
 
         let to_tx = self.get_or_create_transaction(connection_type.to(), false)?;
 
+        // Check label uniqueness before adding anything to the transactions
+        if let OutputSpec::Labeled(label, _) = connection_type.output() {
+            if self.graph.has_output_label(connection_type.from(), label)? {
+                return Err(GraphError::DuplicateOutputLabel(
+                    connection_type.from().to_string(),
+                    label.clone(),
+                )
+                .into());
+            }
+        }
+
+        if let InputSpec::Labeled(label, _, _) = connection_type.input() {
+            if self.graph.has_input_label(connection_type.to(), label)? {
+                return Err(GraphError::DuplicateInputLabel(
+                    connection_type.to().to_string(),
+                    label.clone(),
+                )
+                .into());
+            }
+        }
+
+        // Check the input limit before the output is added
+        if matches!(
+            connection_type.input(),
+            InputSpec::Auto(..) | InputSpec::Labeled(..)
+        ) {
+            self.check_limit(ProtocolLimit::InputsPerTransaction, to_tx.input.len() + 1)?;
+        }
+
         let output_index = match connection_type.output() {
             OutputSpec::Index(index) => {
                 // Check if the specified output index exists in the transaction
@@ -205,6 +1019,20 @@ impl Protocol {
                 }
                 len - 1
             }
+            OutputSpec::Labeled(label, output_type) => {
+                self.add_transaction_output(connection_type.from(), output_type)?;
+                let index = self
+                    .transaction_by_name(connection_type.from())?
+                    .output
+                    .len()
+                    - 1;
+                self.graph
+                    .label_output(connection_type.from(), label, index)?;
+                index
+            }
+            OutputSpec::Label(label) => self
+                .graph
+                .get_output_index_by_label(connection_type.from(), label)?,
         };
 
         let input_index = match connection_type.input() {
@@ -216,6 +1044,15 @@ impl Protocol {
                         *index,
                     ));
                 }
+
+                // Point the existing input to the connected output
+                let mut to_tx = to_tx.clone();
+                to_tx.input[*index].previous_output = OutPoint {
+                    txid: connection_type.txid(),
+                    vout: output_index as u32,
+                };
+                self.graph.update_transaction(connection_type.to(), to_tx)?;
+
                 *index
             }
             InputSpec::Auto(sighash_type, spend_mode) => {
@@ -224,12 +1061,28 @@ impl Protocol {
                     connection_type.txid(),
                     output_index,
                     connection_type.to(),
-                    connection_type.sequence(),
+                    self.connection_sequence(&connection_type)?,
                     spend_mode,
                     sighash_type,
                 )?;
                 self.transaction_by_name(connection_type.to())?.input.len() - 1
             }
+            InputSpec::Labeled(label, sighash_type, spend_mode) => {
+                self.add_transaction_input(
+                    connection_type.txid(),
+                    output_index,
+                    connection_type.to(),
+                    self.connection_sequence(&connection_type)?,
+                    spend_mode,
+                    sighash_type,
+                )?;
+                let index = self.transaction_by_name(connection_type.to())?.input.len() - 1;
+                self.graph.label_input(connection_type.to(), label, index)?;
+                index
+            }
+            InputSpec::Label(label) => self
+                .graph
+                .get_input_index_by_label(connection_type.to(), label)?,
         };
 
         self.graph.connect(
@@ -239,46 +1092,725 @@ impl Protocol {
             connection_type.to(),
             input_index,
         )?;
+        self.observers.emit(|| ProtocolEvent::ConnectionAdded {
+            connection_name: connection_name.to_string(),
+            from: connection_type.from().to_string(),
+            output_index,
+            to: connection_type.to().to_string(),
+            input_index,
+        });
+
+        Ok(())
+    }
+
+    /// Imports all the transactions and connections of another protocol, e.g. a dispute sub-DAG
+    /// built separately. Imported transactions and connections are renamed to `{prefix}_{name}`
+    /// (see `prefixed_name`). Outputs of the host protocol can then be connected to the open
+    /// inputs of the imported transactions with `InputSpec::Index`. Fees, sequence policies,
+    /// broadcast rules, speedup outputs, input owners and unspendable key proofs are imported
+    /// too.
+    ///
+    /// The policies of this protocol apply to the imported transactions, so the limits, dust
+    /// policy and `require_*` flags of the other protocol must be unset or match the ones of this
+    /// protocol. Its change output, agreed leaf templates, history and audit trail refer to its
+    /// own transactions, so it must have none; agree on the templates of the merged protocol with
+    /// `agree_leaf_templates`. Conflicting protocols are rejected with `MergePolicyConflict`.
+    pub fn merge(
+        &mut self,
+        other: Protocol,
+        prefix: &str,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        self.check_merge_policies(&other)?;
+        for (name, value) in other.constants.iter() {
+            if matches!(self.constants.get(name), Some(current) if current != value) {
+                return Err(ProtocolBuilderError::MergeConstantConflict(name.clone()));
+            }
+        }
+
+        self.check_limit(
+            ProtocolLimit::Transactions,
+            self.graph.nodes().count() + other.graph.nodes().count(),
+        )?;
+        self.check_limit(
+            ProtocolLimit::TotalLeaves,
+            self.total_leaves() + other.total_leaves(),
+        )?;
+        for node in other.graph.nodes() {
+            self.check_transaction_limits(node.transaction.input.len(), node.outputs.len())?;
+        }
+
+        self.graph.merge(&other.graph, prefix)?;
+
+        self.constants.extend(other.constants);
+        for (name, mut external) in other.external_transactions {
+            let name = prefixed_name(prefix, &name);
+            external.set_name(&name);
+            self.external_transactions.insert(name, external);
+        }
+        for (name, rule) in other.broadcast_rules {
+            self.broadcast_rules
+                .insert(prefixed_name(prefix, &name), rule.with_prefix(prefix));
+        }
+        for (name, fee) in other.fees {
+            self.fees.insert(prefixed_name(prefix, &name), fee);
+        }
+        for (name, policy) in other.sequence_policies {
+            self.sequence_policies
+                .insert(prefixed_name(prefix, &name), policy);
+        }
+        for (name, output_index) in other.speedup_outputs {
+            self.speedup_outputs
+                .insert(prefixed_name(prefix, &name), output_index);
+        }
+        self.owners
+            .extend(other.owners.into_iter().map(|owner| InputOwner {
+                transaction_name: prefixed_name(prefix, &owner.transaction_name),
+                ..owner
+            }));
+        self.unspendable_proofs.extend(other.unspendable_proofs);
+
+        self.record_mutation(format!("merge {}", prefix));
+        Ok(self)
+    }
+
+    fn check_merge_policies(&self, other: &Protocol) -> Result<(), ProtocolBuilderError> {
+        let conflicts = [
+            (
+                "limits",
+                other.limits != ProtocolLimits::default() && other.limits != self.limits,
+            ),
+            (
+                "dust policy",
+                other.dust_policy.is_some() && other.dust_policy != self.dust_policy,
+            ),
+            (
+                "amount conservation requirement",
+                other.require_amount_conservation && !self.require_amount_conservation,
+            ),
+            (
+                "unspendable proofs requirement",
+                other.require_unspendable_proofs && !self.require_unspendable_proofs,
+            ),
+            ("change output", other.change.is_some()),
+            ("agreed leaf templates", other.agreed_templates.is_some()),
+            ("history", other.history.is_some()),
+            ("audit trail", !other.audit_trail.is_empty()),
+        ];
+
+        match conflicts.iter().find(|(_, conflict)| *conflict) {
+            Some((policy, _)) => Err(ProtocolBuilderError::MergePolicyConflict(
+                policy.to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Copies a transaction and all its descendants under the names given by `rename`, for
+    /// sub-structures repeated per input block or per operator slot. Connections between the
+    /// copied transactions are copied with names also given by `rename`, while connections from
+    /// other transactions spend the same outputs as the originals, so the copies are alternative
+    /// spends until rewired. Fees, sequence policies, broadcast rules, speedup outputs and input
+    /// owners are copied too. Returns the names of the copies, in topological order.
+    pub fn duplicate_subtree(
+        &mut self,
+        root: &str,
+        rename: impl Fn(&str) -> String,
+    ) -> Result<Vec<String>, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        check_empty_transaction_name(root)?;
+
+        let mut subtree = vec![root.to_string()];
+        subtree.extend(self.graph.descendants(root)?);
+        let mut leaves = 0;
+        for name in subtree.iter() {
+            leaves += self
+                .graph
+                .get_outputs(name)?
+                .iter()
+                .map(leaf_count)
+                .sum::<usize>();
+        }
+        self.check_limit(
+            ProtocolLimit::Transactions,
+            self.graph.nodes().count() + subtree.len(),
+        )?;
+        self.check_limit(ProtocolLimit::TotalLeaves, self.total_leaves() + leaves)?;
+
+        let renamed: BTreeMap<String, String> = self
+            .graph
+            .duplicate_subtree(root, &rename)?
+            .into_iter()
+            .collect();
+        let rename_copied = |name: &str| {
+            renamed
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| name.to_string())
+        };
+
+        for (name, copy) in renamed.iter() {
+            if let Some(fee) = self.fees.get(name).copied() {
+                self.fees.insert(copy.clone(), fee);
+            }
+            if let Some(policy) = self.sequence_policies.get(name).cloned() {
+                self.sequence_policies.insert(copy.clone(), policy);
+            }
+            if let Some(output_index) = self.speedup_outputs.get(name).copied() {
+                self.speedup_outputs.insert(copy.clone(), output_index);
+            }
+            if let Some(rule) = self.broadcast_rules.get(name).cloned() {
+                self.broadcast_rules
+                    .insert(copy.clone(), rule.renamed(rename_copied));
+            }
+        }
+
+        let owners: Vec<InputOwner> = self
+            .owners
+            .iter()
+            .filter_map(|owner| {
+                renamed.get(&owner.transaction_name).map(|copy| InputOwner {
+                    transaction_name: copy.clone(),
+                    ..owner.clone()
+                })
+            })
+            .collect();
+        self.owners.extend(owners);
+
+        self.record_mutation(format!("duplicate_subtree {}", root));
+        Ok(subtree.iter().map(|name| renamed[name].clone()).collect())
+    }
+
+    /// Attaches a broadcast rule to a protocol transaction, replacing any previous rule of that
+    /// transaction. Rules are evaluated against the chain state by the `broadcast` module.
+    pub fn add_broadcast_rule(
+        &mut self,
+        rule: BroadcastRule,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+
+        let referenced = std::iter::once(rule.transaction()).chain(
+            rule.conditions()
+                .iter()
+                .filter_map(|c| c.transaction_name()),
+        );
+
+        for name in referenced {
+            if !self.graph.contains_transaction(name) {
+                return Err(GraphError::MissingTransaction(name.to_string()).into());
+            }
+        }
+
+        let transaction_name = rule.transaction().to_string();
+        self.broadcast_rules.insert(transaction_name.clone(), rule);
+
+        self.record_mutation(format!("add_broadcast_rule {}", transaction_name));
+        Ok(self)
+    }
+
+    pub fn broadcast_rules(&self) -> &BTreeMap<String, BroadcastRule> {
+        &self.broadcast_rules
+    }
+
+    /// Locks the structure of the protocol, typically once sighashes are exchanged with the other
+    /// parties. Adding or changing transactions, inputs, outputs, connections or constants fails
+    /// until the protocol is unfrozen.
+    pub fn freeze(&mut self) -> &mut Self {
+        if !self.frozen {
+            self.frozen = true;
+            self.audit_trail.push(AuditEntry::new(AuditEvent::Frozen));
+        }
+        self
+    }
+
+    /// Unlocks the structure of a frozen protocol, recording the reason in the audit trail.
+    /// Signatures are kept until the protocol is built again: the next build or rebuild discards
+    /// the signatures of the transactions changed since unfreezing and of their descendants, as
+    /// their txids and sighashes may no longer be the ones the signatures commit to, and records
+    /// them in the audit trail.
+    pub fn unfreeze(&mut self, reason: &str) -> Result<&mut Self, ProtocolBuilderError> {
+        if reason.trim().is_empty() {
+            return Err(ProtocolBuilderError::MissingUnfreezeReason);
+        }
+
+        if self.frozen {
+            self.frozen = false;
+            self.unfrozen = true;
+            self.audit_trail.push(AuditEntry::new(AuditEvent::Unfrozen {
+                reason: reason.to_string(),
+            }));
+        }
+
+        Ok(self)
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn audit_trail(&self) -> &[AuditEntry] {
+        &self.audit_trail
+    }
+
+    /// Registers a callback notified of every change to the protocol (see `ProtocolEvent`), so
+    /// coordination software can persist or forward them without polling. Callbacks run in
+    /// registration order, right after each change. They are not saved nor cloned with the
+    /// protocol.
+    pub fn on_event(
+        &mut self,
+        callback: impl Fn(&ProtocolEvent) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.observers.push(Arc::new(callback));
+        self
+    }
+
+    pub fn clear_observers(&mut self) -> &mut Self {
+        self.observers.clear();
+        self
+    }
+
+    /// Time spent in each phase of the last build or signing of this protocol instance. Not
+    /// saved with the protocol.
+    pub fn last_build_timings(&self) -> Option<&BuildTimings> {
+        self.build_timings.as_ref()
+    }
+
+    /// Starts recording a checkpoint of the protocol after every structural change (the ones
+    /// rejected while frozen), each one logged in the audit trail as a mutation, so the protocol
+    /// can be rebuilt at any step with `replay_to`. Checkpoints only keep what each mutation
+    /// changed.
+    pub fn record_history(&mut self) -> &mut Self {
+        if self.history.is_none() {
+            let mut history = History::default();
+            history.record("record_history", self.history_state());
+            self.history = Some(history);
+        }
+        self
+    }
+
+    /// Checkpoints recorded since `record_history`, indexed by step.
+    pub fn history(&self) -> &[Checkpoint] {
+        self.history
+            .as_ref()
+            .map(|history| history.checkpoints())
+            .unwrap_or_default()
+    }
+
+    pub(super) fn recorded_history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub(super) fn restore_history(&mut self, history: History) {
+        self.history = Some(history);
+    }
+
+    fn record_mutation(&mut self, operation: String) {
+        let Some(step) = self
+            .history
+            .as_ref()
+            .map(|history| history.checkpoints().len())
+        else {
+            return;
+        };
+
+        self.audit_trail.push(AuditEntry::new(AuditEvent::Mutation {
+            step,
+            operation: operation.clone(),
+        }));
+        let state = self.history_state();
+        if let Some(history) = self.history.as_mut() {
+            history.record(&operation, state);
+        }
+    }
+
+    // JSON state of the protocol recorded by the history, without the history itself.
+    fn history_state(&mut self) -> serde_json::Value {
+        let history = self.history.take();
+        // Protocols are saved as JSON, so they always convert to a JSON value
+        let state = serde_json::to_value(&*self).expect("protocol converts to JSON");
+        self.history = history;
+        state
+    }
 
+    // Runs the mutations made on behalf of another one, which is recorded as a single step.
+    fn untracked<T>(
+        &mut self,
+        mutation: impl FnOnce(&mut Self) -> Result<T, ProtocolBuilderError>,
+    ) -> Result<T, ProtocolBuilderError> {
+        let history = self.history.take();
+        let result = mutation(self);
+        self.history = history;
+        result
+    }
+
+    fn check_not_frozen(&self) -> Result<(), ProtocolBuilderError> {
+        if self.frozen {
+            return Err(ProtocolBuilderError::ProtocolFrozen(self.name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Defines (or redefines) a named protocol constant, e.g. CHALLENGE_BLOCKS or STAKE_AMOUNT.
+    pub fn set_constant<V: Into<ConstantValue>>(
+        &mut self,
+        name: &str,
+        value: V,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        if name.trim().is_empty() {
+            return Err(ScriptError::EmptyConstantName.into());
+        }
+        self.constants.insert(name.to_string(), value.into());
+        self.record_mutation(format!("set_constant {}", name));
         Ok(self)
     }
 
-    pub fn build(
+    /// Builds a leaf from a script template, replacing each placeholder with the value of the
+    /// named protocol constant. The placeholders are recorded in the leaf, so `constant_usages`
+    /// and `check_constants` find the constants a leaf embeds by name rather than by value.
+    pub fn build_script(
+        &self,
+        template: &ScriptTemplate,
+        verifying_key: &PublicKey,
+        sign_mode: SignMode,
+    ) -> Result<ProtocolScript, ProtocolBuilderError> {
+        let (script, placeholders) = template.resolve(|name| self.constant(name).cloned())?;
+
+        let mut leaf = ProtocolScript::new(script, verifying_key, sign_mode);
+        for (name, value, offset) in placeholders {
+            leaf.add_constant_placeholder(&name, value, offset)?;
+        }
+
+        Ok(leaf)
+    }
+
+    /// Resolves a named protocol constant.
+    pub fn constant(&self, name: &str) -> Result<&ConstantValue, ProtocolBuilderError> {
+        self.constants
+            .get(name)
+            .ok_or(ProtocolBuilderError::UndefinedConstant(name.to_string()))
+    }
+
+    pub fn constants(&self) -> &BTreeMap<String, ConstantValue> {
+        &self.constants
+    }
+
+    /// Returns every script in the protocol that embeds the given constant.
+    pub fn constant_usages(&self, name: &str) -> Result<Vec<ConstantUsage>, ProtocolBuilderError> {
+        self.constant(name)?;
+
+        let mut usages = vec![];
+        for transaction_name in self.transaction_names() {
+            for (output_index, output) in self
+                .graph
+                .get_outputs(&transaction_name)?
+                .iter()
+                .enumerate()
+            {
+                for (script_index, script) in output.get_scripts() {
+                    if script.get_constant(name).is_some() {
+                        usages.push(ConstantUsage {
+                            transaction_name: transaction_name.clone(),
+                            output_index,
+                            script_index,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(usages)
+    }
+
+    /// Checks that every constant embedded in the protocol scripts is defined in the protocol
+    /// constants table, that all scripts agree on its value and that each script still pushes
+    /// the value at its placeholders.
+    pub fn check_constants(&self) -> Result<(), ProtocolBuilderError> {
+        for transaction_name in self.transaction_names() {
+            for (output_index, output) in self
+                .graph
+                .get_outputs(&transaction_name)?
+                .iter()
+                .enumerate()
+            {
+                for (script_index, script) in output.get_scripts() {
+                    script.check_constant_placeholders()?;
+                    for (name, value) in script.get_constants() {
+                        let expected = self.constant(name)?;
+                        if expected != value {
+                            return Err(ProtocolBuilderError::ConstantMismatch(
+                                name.clone(),
+                                transaction_name.clone(),
+                                output_index,
+                                script_index,
+                                value.to_string(),
+                                expected.to_string(),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Checks the protocol and applies the dust policy and the change output before the txids
+    // are computed. Shared by all the build methods.
+    fn prepare_build(&mut self) -> Result<(), ProtocolBuilderError> {
+        self.check_constants()?;
+        self.check_limits()?;
+        self.apply_dust_policy()?;
+        self.apply_change_output()?;
+        if self.require_amount_conservation {
+            self.check_amounts()?;
+        }
+        Ok(())
+    }
+
+    // Discards the signatures of the transactions changed since the last build and of their
+    // descendants, which may no longer match their sighashes. Signatures discarded after an
+    // unfreeze are recorded in the audit trail. Returns the changed transactions and their
+    // descendants, in topological order.
+    fn discard_stale_signatures(&mut self) -> Result<Vec<String>, ProtocolBuilderError> {
+        let affected = self.graph.affected_transactions()?;
+        let cleared = self.graph.clear_transaction_signatures(&affected);
+        if std::mem::take(&mut self.unfrozen) {
+            self.audit_trail
+                .push(AuditEntry::new(AuditEvent::SignaturesCleared {
+                    transactions: cleared,
+                }));
+        }
+        Ok(affected)
+    }
+
+    pub fn build<S: Signer + ?Sized>(
         &mut self,
-        key_manager: &Rc<KeyManager>,
+        signer: &S,
         id: &str,
     ) -> Result<Self, ProtocolBuilderError> {
-        self.update_transaction_ids()?;
-        self.compute_sighashes(key_manager, id)?;
+        self.prepare_build()?;
+        if self.unfrozen {
+            self.discard_stale_signatures()?;
+        }
+        let transaction_names = self.graph.sort()?;
+        let mut timer = BuildTimer::new(transaction_names.len());
+        timer.txids(|| self.update_transaction_ids(&transaction_names))?;
+        timer.sighashes(|| self.compute_sighashes(&transaction_names, signer, id))?;
+        self.graph.clear_dirty();
+        self.build_timings = Some(timer.finish());
         Ok(self.clone())
     }
 
-    pub fn sign(
-        &mut self,
-        key_manager: &Rc<KeyManager>,
-        id: &str,
-    ) -> Result<Self, ProtocolBuilderError> {
-        self.compute_signatures(key_manager, id)?;
-        Ok(self.clone())
+    pub fn sign<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        id: &str,
+    ) -> Result<Self, ProtocolBuilderError> {
+        let transaction_names = self.graph.sort()?;
+        let mut timer = BuildTimer::new(transaction_names.len());
+        timer.signatures(|| self.compute_signatures(&transaction_names, signer, id))?;
+        self.build_timings = Some(timer.finish());
+        Ok(self.clone())
+    }
+
+    // To be used only when we don't need musig2
+    pub fn build_and_sign<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        id: &str,
+    ) -> Result<Self, ProtocolBuilderError> {
+        self.prepare_build()?;
+        if self.unfrozen {
+            self.discard_stale_signatures()?;
+        }
+        let transaction_names = self.graph.sort()?;
+        let mut timer = BuildTimer::new(transaction_names.len());
+        timer.txids(|| self.update_transaction_ids(&transaction_names))?;
+        timer.sighashes(|| self.compute_sighashes(&transaction_names, signer, id))?;
+        timer.signatures(|| self.compute_signatures(&transaction_names, signer, id))?;
+        self.graph.clear_dirty();
+        self.build_timings = Some(timer.finish());
+        Ok(self.clone())
+    }
+
+    /// Recomputes the txids and sighashes of the transactions changed since the last build and
+    /// of all their descendants, leaving the rest of the protocol untouched. The signatures of
+    /// the rebuilt transactions no longer match their sighashes and are discarded. Returns the
+    /// names of the rebuilt transactions.
+    pub fn rebuild<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        id: &str,
+    ) -> Result<Vec<String>, ProtocolBuilderError> {
+        self.prepare_build()?;
+        let transaction_names = self.discard_stale_signatures()?;
+        let mut timer = BuildTimer::new(transaction_names.len());
+        timer.txids(|| self.update_transaction_ids(&transaction_names))?;
+        timer.sighashes(|| self.compute_sighashes(&transaction_names, signer, id))?;
+        self.graph.clear_dirty();
+        self.build_timings = Some(timer.finish());
+        Ok(transaction_names)
+    }
+
+    /// Same as `rebuild`, also recomputing the signatures of the rebuilt transactions.
+    pub fn rebuild_and_sign<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        id: &str,
+    ) -> Result<Vec<String>, ProtocolBuilderError> {
+        self.prepare_build()?;
+        let transaction_names = self.discard_stale_signatures()?;
+        let mut timer = BuildTimer::new(transaction_names.len());
+        timer.txids(|| self.update_transaction_ids(&transaction_names))?;
+        timer.sighashes(|| self.compute_sighashes(&transaction_names, signer, id))?;
+        timer.signatures(|| self.compute_signatures(&transaction_names, signer, id))?;
+        self.graph.clear_dirty();
+        self.build_timings = Some(timer.finish());
+        Ok(transaction_names)
+    }
+
+    /// Replaces an output of a transaction, e.g. to change its value or its leaf scripts. The
+    /// transaction and its descendants are rebuilt on the next call to `rebuild`.
+    pub fn update_output(
+        &mut self,
+        transaction_name: &str,
+        output_index: usize,
+        output_type: &OutputType,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.replace_output_type(transaction_name, output_index, output_type)?;
+        Ok(self)
+    }
+
+    /// Replaces an output of a transaction, possibly with an output of another kind, and returns
+    /// the replaced one. Fails without changing anything if an input spending the output signs
+    /// with a sighash type the new output does not support. The transaction and its descendants
+    /// are rebuilt on the next call to `rebuild`.
+    pub fn replace_output_type(
+        &mut self,
+        transaction_name: &str,
+        output_index: usize,
+        output_type: &OutputType,
+    ) -> Result<OutputType, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        let replaced = self
+            .graph
+            .get_outputs(transaction_name)?
+            .get(output_index)
+            .cloned()
+            .ok_or_else(|| {
+                ProtocolBuilderError::MissingOutput(transaction_name.to_string(), output_index)
+            })?;
+        self.check_output_limits(output_type, Some(&replaced))?;
+        self.graph
+            .update_output(transaction_name, output_index, output_type.clone())?;
+        self.record_mutation(format!(
+            "update_output {}:{}",
+            transaction_name, output_index
+        ));
+        Ok(replaced)
+    }
+
+    /// Removes a connection and the input spending it. The later inputs of the spending
+    /// transaction shift down, along with their labels and owners, and the transaction and its
+    /// descendants are rebuilt on the next call to `rebuild`. The spent output is kept, so it
+    /// can be connected again with `OutputSpec::Index`.
+    pub fn remove_connection(
+        &mut self,
+        connection_name: &str,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        check_empty_connection_name(connection_name)?;
+        let removed = self.graph.remove_connection(connection_name)?;
+        self.remove_input_owners(&[removed]);
+        self.record_mutation(format!("remove_connection {}", connection_name));
+        Ok(self)
+    }
+
+    /// Removes a transaction, the inputs of other transactions spending its outputs (as
+    /// `remove_connection` does) and everything attached to its name: fee, sequence policy,
+    /// broadcast rule, speedup output, input owners, change output and external transaction
+    /// declaration. The outputs it spent are kept in their transactions.
+    pub fn remove_transaction(
+        &mut self,
+        transaction_name: &str,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        check_empty_transaction_name(transaction_name)?;
+        let removed = self.graph.remove_transaction(transaction_name)?;
+        self.remove_input_owners(&removed);
+
+        self.owners
+            .retain(|owner| owner.transaction_name != transaction_name);
+        self.fees.remove(transaction_name);
+        self.sequence_policies.remove(transaction_name);
+        self.broadcast_rules.remove(transaction_name);
+        self.speedup_outputs.remove(transaction_name);
+        self.external_transactions.remove(transaction_name);
+        if let Some(change) = self.change.as_mut() {
+            if matches!(&change.location, Some((name, _)) if name == transaction_name) {
+                change.location = None;
+            }
+        }
+
+        self.record_mutation(format!("remove_transaction {}", transaction_name));
+        Ok(self)
+    }
+
+    // Drops the owners of removed inputs and shifts the owners of the later inputs, in the
+    // order the inputs were removed.
+    fn remove_input_owners(&mut self, removed: &[(String, usize)]) {
+        for (transaction_name, input_index) in removed {
+            self.owners.retain(|owner| {
+                owner.transaction_name != *transaction_name || owner.input_index != *input_index
+            });
+            for owner in self.owners.iter_mut() {
+                if owner.transaction_name == *transaction_name && owner.input_index > *input_index {
+                    owner.input_index -= 1;
+                }
+            }
+        }
+    }
+
+    /// Rotates public keys across the protocol: the keys of the mapping are replaced in segwit
+    /// outputs, taproot internal keys and leaf scripts (both the verifying key and the keys pushed
+    /// by the script). Outputs whose script changed are recomputed, and their transactions and all
+    /// their descendants are rebuilt and signed with the new keys, returning their names as
+    /// `rebuild_and_sign` does. Outputs of external transactions are never changed.
+    pub fn resign_with<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        key_mapping: &HashMap<PublicKey, PublicKey>,
+        id: &str,
+    ) -> Result<Vec<String>, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+
+        for transaction_name in self.graph.sort()? {
+            let outputs = self.graph.get_outputs(&transaction_name)?;
+            for (output_index, output) in outputs.iter().enumerate() {
+                if let Some(rotated) = rotate_output_keys(output, key_mapping)? {
+                    self.graph
+                        .update_output(&transaction_name, output_index, rotated)?;
+                }
+            }
+        }
+
+        let rebuilt = self.rebuild_and_sign(signer, id)?;
+
+        self.record_mutation("resign_with".to_string());
+        Ok(rebuilt)
     }
 
-    // To be used only when we don't need musig2
-    pub fn build_and_sign(
-        &mut self,
-        key_manager: &Rc<KeyManager>,
-        id: &str,
-    ) -> Result<Self, ProtocolBuilderError> {
-        self.update_transaction_ids()?;
-        self.compute_sighashes(key_manager, id)?;
-        self.compute_signatures(key_manager, id)?;
-        Ok(self.clone())
+    /// Returns true if the transaction changed since the last build.
+    pub fn is_dirty(&self, transaction_name: &str) -> Result<bool, ProtocolBuilderError> {
+        Ok(self.graph.is_dirty(transaction_name)?)
     }
 
-    pub fn sign_ecdsa_input(
+    pub fn sign_ecdsa_input<S: Signer + ?Sized>(
         &mut self,
         transaction_name: &str,
         input_index: usize,
-        key_manager: &KeyManager,
+        signer: &S,
     ) -> Result<bitcoin::ecdsa::Signature, ProtocolBuilderError> {
         let input = &self.graph.get_inputs(transaction_name)?[input_index];
         let output_type = input.output_type().unwrap();
@@ -296,6 +1828,13 @@ impl Protocol {
             }
         };
 
+        check_single_output(
+            transaction_name,
+            transaction,
+            input_index,
+            input.sighash_type(),
+        )?;
+
         let hashed_messages = output_type.compute_ecdsa_sighash(
             transaction,
             transaction_name,
@@ -310,7 +1849,7 @@ impl Protocol {
             hashed_messages.as_slice(),
             &SpendMode::Segwit,
             ecdsa_sighash_type,
-            key_manager,
+            signer,
         )?[0]
             .clone();
 
@@ -322,6 +1861,11 @@ impl Protocol {
             signature.clone(),
             signature_index,
         )?;
+        self.emit_signature_added(
+            transaction_name,
+            input_index,
+            usize::from(signature.is_some()),
+        );
 
         match signature {
             Some(Signature::Ecdsa(ecdsa_signature)) => Ok(ecdsa_signature),
@@ -329,12 +1873,12 @@ impl Protocol {
         }
     }
 
-    pub fn sign_taproot_input(
+    pub fn sign_taproot_input<S: Signer + ?Sized>(
         &mut self,
         transaction_name: &str,
         input_index: usize,
         spend_mode: &SpendMode,
-        key_manager: &KeyManager,
+        signer: &S,
         id: &str,
     ) -> Result<Vec<Option<bitcoin::taproot::Signature>>, ProtocolBuilderError> {
         let input = &self.graph.get_inputs(transaction_name)?[input_index];
@@ -353,6 +1897,15 @@ impl Protocol {
             }
         };
 
+        check_single_outputs(
+            transaction_name,
+            transaction,
+            input_index,
+            output_type,
+            spend_mode,
+            input.sighash_type(),
+        )?;
+
         let prevouts = self.graph.get_prevouts(transaction_name)?;
         let hashed_messages = output_type.compute_taproot_sighash(
             transaction,
@@ -361,7 +1914,7 @@ impl Protocol {
             &prevouts,
             spend_mode,
             tap_sighash_type,
-            key_manager,
+            signer,
             id,
         )?;
 
@@ -371,7 +1924,7 @@ impl Protocol {
             hashed_messages.as_slice(),
             spend_mode,
             tap_sighash_type,
-            key_manager,
+            signer,
             id,
         )?;
 
@@ -380,6 +1933,23 @@ impl Protocol {
             input_index as u32,
             signatures.clone(),
         )?;
+        self.emit_signature_added(
+            transaction_name,
+            input_index,
+            signatures.iter().flatten().count(),
+        );
+
+        let multisig_signatures = output_type.compute_taproot_multisig_signatures(
+            hashed_messages.as_slice(),
+            spend_mode,
+            tap_sighash_type,
+            signer,
+        )?;
+        self.graph.update_input_multisig_signatures(
+            transaction_name,
+            input_index as u32,
+            multisig_signatures,
+        )?;
 
         let taproot_signatures = signatures
             .into_iter()
@@ -403,8 +1973,11 @@ impl Protocol {
         input_index: u32,
         signatures: Vec<Option<Signature>>,
     ) -> Result<(), ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        let count = signatures.iter().flatten().count();
         self.graph
             .update_input_signatures(transaction_name, input_index, signatures)?;
+        self.emit_signature_added(transaction_name, input_index as usize, count);
         Ok(())
     }
 
@@ -415,20 +1988,39 @@ impl Protocol {
         signature: Option<Signature>,
         signature_index: usize,
     ) -> Result<(), ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        let count = usize::from(signature.is_some());
         self.graph.update_input_signature(
             transaction_name,
             input_index,
             signature,
             signature_index,
         )?;
+        self.emit_signature_added(transaction_name, input_index as usize, count);
         Ok(())
     }
 
+    pub fn update_input_multisig_signatures(
+        &mut self,
+        transaction_name: &str,
+        input_index: u32,
+        signatures: BTreeMap<usize, Vec<Option<Signature>>>,
+    ) -> Result<(), ProtocolBuilderError> {
+        self.graph
+            .update_input_multisig_signatures(transaction_name, input_index, signatures)?;
+        Ok(())
+    }
+
+    /// Transaction with the witnesses built from the given arguments, one per input. If leaf
+    /// templates were agreed, the scripts spent by its inputs are checked against them first.
     pub fn transaction_to_send(
         &self,
         transaction_name: &str,
         args: &[InputArgs],
     ) -> Result<Transaction, ProtocolBuilderError> {
+        self.check_agreed_templates(transaction_name)?;
+        self.check_input_args(transaction_name, args)?;
+
         let mut transaction = self
             .graph
             .get_transaction_by_name(transaction_name)?
@@ -455,6 +2047,38 @@ impl Protocol {
         Ok(next_transactions)
     }
 
+    /// Transactions that must confirm before the given one, directly or indirectly, in
+    /// topological order. External transactions are included.
+    pub fn ancestors(&self, transaction_name: &str) -> Result<Vec<String>, ProtocolBuilderError> {
+        Ok(self.graph.ancestors(transaction_name)?)
+    }
+
+    /// Transactions spending the outputs of the given one, directly or indirectly, in
+    /// topological order.
+    pub fn descendants(&self, transaction_name: &str) -> Result<Vec<String>, ProtocolBuilderError> {
+        Ok(self.graph.descendants(transaction_name)?)
+    }
+
+    /// Every chain of transactions leading from one transaction to another, both included.
+    pub fn paths_between(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<Vec<String>>, ProtocolBuilderError> {
+        Ok(self.graph.paths_between(from, to)?)
+    }
+
+    /// Transactions that do not spend other transactions of the protocol, typically the external
+    /// ones funding it.
+    pub fn roots(&self) -> Vec<String> {
+        self.graph.roots()
+    }
+
+    /// Transactions whose outputs are not spent by other transactions of the protocol.
+    pub fn leaves(&self) -> Vec<String> {
+        self.graph.leaves()
+    }
+
     pub fn inputs(&self, transaction_name: &str) -> Result<Vec<InputType>, ProtocolBuilderError> {
         Ok(self.graph.get_inputs(transaction_name)?)
     }
@@ -523,6 +2147,23 @@ impl Protocol {
         Ok(input_signature)
     }
 
+    /// Signatures of the slots of a multisig leaf (see `scripts::checksigadd_multisig`), in the
+    /// order of its keys. Slots of keys not held by the signer are empty, to be filled with the
+    /// signatures of the other parties.
+    pub fn input_multisig_signatures(
+        &self,
+        transaction_name: &str,
+        input_index: usize,
+        leaf_index: usize,
+    ) -> Result<Vec<Option<bitcoin::taproot::Signature>>, ProtocolBuilderError> {
+        let signatures = self.graph.get_taproot_multisig_signatures(
+            transaction_name,
+            input_index,
+            leaf_index,
+        )?;
+        Ok(signatures)
+    }
+
     pub fn input_taproot_key_spend_signature(
         &self,
         transaction_name: &str,
@@ -556,6 +2197,22 @@ impl Protocol {
         ));
     }
 
+    /// Returns the leaves of an output keyed by name, with the leaf index, leaf hash and control
+    /// block of each, so higher layers can reference leaves without tracking their positions.
+    pub fn leaf_map(
+        &self,
+        transaction_name: &str,
+        output_index: usize,
+    ) -> Result<BTreeMap<String, LeafInfo>, ProtocolBuilderError> {
+        match self.graph.get_output(transaction_name, output_index)? {
+            Some(output_type) => output_type.leaf_map(),
+            None => Err(ProtocolBuilderError::MissingOutput(
+                transaction_name.to_string(),
+                output_index,
+            )),
+        }
+    }
+
     pub fn get_script_to_spend(
         &self,
         transaction_name: &str,
@@ -580,13 +2237,630 @@ impl Protocol {
             }
         };
 
-        Ok(script)
+        Ok(script)
+    }
+
+    /// Assigns a label to an existing output, so connections can refer to it with
+    /// `OutputSpec::Label`.
+    pub fn label_output(
+        &mut self,
+        transaction_name: &str,
+        output_index: usize,
+        label: &str,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        self.graph
+            .label_output(transaction_name, label, output_index)?;
+        Ok(self)
+    }
+
+    /// Assigns a label to an existing input, so connections can refer to it with
+    /// `InputSpec::Label`.
+    pub fn label_input(
+        &mut self,
+        transaction_name: &str,
+        input_index: usize,
+        label: &str,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        self.graph
+            .label_input(transaction_name, label, input_index)?;
+        Ok(self)
+    }
+
+    /// Annotates the party that signs an input: its segwit signature, its taproot key path
+    /// signature and the signatures of the leaves without their own owner.
+    pub fn set_input_owner(
+        &mut self,
+        transaction_name: &str,
+        input_index: usize,
+        party: &str,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        self.set_owner(transaction_name, input_index, None, party)
+    }
+
+    /// Annotates the party that signs a leaf of the taproot output spent by an input.
+    pub fn set_leaf_owner(
+        &mut self,
+        transaction_name: &str,
+        input_index: usize,
+        leaf: usize,
+        party: &str,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        let input = self.graph.get_input(transaction_name, input_index)?;
+        match input.output_type()? {
+            OutputType::Taproot { leaves, .. } if leaf < leaves.len() => {}
+            _ => return Err(ProtocolBuilderError::MissingTaprootLeaf(leaf, input_index)),
+        }
+
+        self.set_owner(transaction_name, input_index, Some(leaf), party)
+    }
+
+    /// Party signing the input, or the given leaf of the output it spends.
+    pub fn owner(
+        &self,
+        transaction_name: &str,
+        input_index: usize,
+        leaf: Option<usize>,
+    ) -> Option<&str> {
+        let find = |leaf: Option<usize>| {
+            self.owners
+                .iter()
+                .find(|owner| {
+                    owner.transaction_name == transaction_name
+                        && owner.input_index == input_index
+                        && owner.leaf == leaf
+                })
+                .map(|owner| owner.party.as_str())
+        };
+
+        leaf.and_then(|leaf| find(Some(leaf)))
+            .or_else(|| find(None))
+    }
+
+    pub fn owners(&self) -> &[InputOwner] {
+        &self.owners
+    }
+
+    /// Leaf templates agreed with `agree_leaf_templates`, re-checked before broadcast.
+    pub fn agreed_templates(&self) -> Option<&TemplateManifest> {
+        self.agreed_templates.as_ref()
+    }
+
+    pub(super) fn set_agreed_templates(&mut self, manifest: TemplateManifest) {
+        self.agreed_templates = Some(manifest);
+    }
+
+    fn set_owner(
+        &mut self,
+        transaction_name: &str,
+        input_index: usize,
+        leaf: Option<usize>,
+        party: &str,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.graph.get_input(transaction_name, input_index)?;
+
+        self.owners.retain(|owner| {
+            !(owner.transaction_name == transaction_name
+                && owner.input_index == input_index
+                && owner.leaf == leaf)
+        });
+        self.owners.push(InputOwner {
+            transaction_name: transaction_name.to_string(),
+            input_index,
+            leaf,
+            party: party.to_string(),
+        });
+
+        Ok(self)
+    }
+
+    pub fn output_index(
+        &self,
+        transaction_name: &str,
+        label: &str,
+    ) -> Result<usize, ProtocolBuilderError> {
+        Ok(self
+            .graph
+            .get_output_index_by_label(transaction_name, label)?)
+    }
+
+    pub fn input_index(
+        &self,
+        transaction_name: &str,
+        label: &str,
+    ) -> Result<usize, ProtocolBuilderError> {
+        Ok(self
+            .graph
+            .get_input_index_by_label(transaction_name, label)?)
+    }
+
+    pub fn get_script_from_labeled_output(
+        &self,
+        transaction_name: &str,
+        output_label: &str,
+    ) -> Result<(&OutputType, &Vec<ProtocolScript>), ProtocolBuilderError> {
+        let output_index = self.output_index(transaction_name, output_label)?;
+        self.get_script_from_output(transaction_name, output_index as u32)
+    }
+
+    pub fn get_script_to_spend_labeled(
+        &self,
+        transaction_name: &str,
+        input_label: &str,
+        script_index: u32,
+    ) -> Result<ProtocolScript, ProtocolBuilderError> {
+        let input_index = self.input_index(transaction_name, input_label)?;
+        self.get_script_to_spend(transaction_name, input_index as u32, script_index)
+    }
+
+    /// When required, the commitment hash is only produced if the internal key of every
+    /// script-only taproot output has a valid unspendable proof, see `check_unspendable_proofs`.
+    pub fn require_unspendable_proofs(
+        &mut self,
+        required: bool,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        self.require_unspendable_proofs = required;
+        Ok(self)
+    }
+
+    /// Registers the derivation data proving that a (usually counterparty-provided) internal key
+    /// is unspendable. Fails if the data does not pass the NUMS check.
+    pub fn add_unspendable_proof(
+        &mut self,
+        internal_key: &XOnlyPublicKey,
+        derivation_data: [u8; 32],
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        if !verify_unspendable(internal_key, &derivation_data)? {
+            return Err(ProtocolBuilderError::InvalidUnspendableProof(*internal_key));
+        }
+
+        self.unspendable_proofs
+            .insert(*internal_key, derivation_data);
+        Ok(self)
+    }
+
+    /// Checks that every script-only taproot output of the graph has an unspendable proof for its
+    /// internal key. An output is script-only if it has leaves and no protocol input spends it
+    /// through the key path, so outputs spent outside the protocol are checked too.
+    pub fn check_unspendable_proofs(&self) -> Result<(), ProtocolBuilderError> {
+        for transaction_name in self.graph.sort()? {
+            for (output_index, output) in self
+                .graph
+                .get_outputs(&transaction_name)?
+                .iter()
+                .enumerate()
+            {
+                let OutputType::Taproot {
+                    internal_key,
+                    leaves,
+                    ..
+                } = output
+                else {
+                    continue;
+                };
+
+                let mut key_path_spent = false;
+                for (spender, input_index) in self
+                    .graph
+                    .spending_inputs(&transaction_name, output_index)?
+                {
+                    let inputs = self.graph.get_inputs(&spender)?;
+                    let spend_mode = inputs[input_index].spend_mode();
+                    key_path_spent |= spend_mode.is_all() || spend_mode.is_key_only();
+                }
+
+                let internal_key = XOnlyPublicKey::from(*internal_key);
+                if !leaves.is_empty()
+                    && !key_path_spent
+                    && !self.unspendable_proofs.contains_key(&internal_key)
+                {
+                    return Err(ProtocolBuilderError::MissingUnspendableProof(
+                        transaction_name.clone(),
+                        output_index,
+                        internal_key,
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hash committing to every protocol transaction (external transactions are not committed),
+    /// computed as the Merkle root of the (name, txid) leaves sorted by name. The protocol must be
+    /// built so the txids are final.
+    pub fn commitment_hash(&self) -> Result<sha256::Hash, ProtocolBuilderError> {
+        let (_, leaves) = self.commitment_leaves()?;
+        Ok(merkle_root(&leaves))
+    }
+
+    /// Returns a proof that the transaction is part of this protocol, verifiable against
+    /// `commitment_hash` with the raw transaction only.
+    pub fn inclusion_proof(
+        &self,
+        transaction_name: &str,
+    ) -> Result<InclusionProof, ProtocolBuilderError> {
+        let (names, leaves) = self.commitment_leaves()?;
+        let leaf_index = names
+            .iter()
+            .position(|name| name == transaction_name)
+            .ok_or(ProtocolBuilderError::TransactionNotCommitted(
+                transaction_name.to_string(),
+            ))?;
+
+        Ok(InclusionProof {
+            transaction_name: transaction_name.to_string(),
+            leaf_index: leaf_index as u32,
+            leaf_count: leaves.len() as u32,
+            siblings: merkle_path(&leaves, leaf_index),
+        })
+    }
+
+    fn commitment_leaves(&self) -> Result<(Vec<String>, Vec<sha256::Hash>), ProtocolBuilderError> {
+        if self.require_unspendable_proofs {
+            self.check_unspendable_proofs()?;
+        }
+
+        let mut names = self.graph.sort()?;
+        names.sort();
+
+        let leaves = names
+            .iter()
+            .map(|name| {
+                Ok(leaf_hash(
+                    name,
+                    &self.transaction_by_name(name)?.compute_txid(),
+                ))
+            })
+            .collect::<Result<Vec<_>, ProtocolBuilderError>>()?;
+
+        Ok((names, leaves))
+    }
+
+    /// Reports the transactions that, by the protocol structure alone, can be part of an
+    /// unconfirmed chain longer than the mempool ancestor/descendant limits, and the inputs where
+    /// a relative timelock would act as a confirmation barrier.
+    pub fn check_package_limits(
+        &self,
+        limits: &PackageLimits,
+    ) -> Result<PackageLimitReport, ProtocolBuilderError> {
+        Ok(check_package_limits(&self.graph, limits)?)
+    }
+
+    /// Reports the taproot leaves with Winternitz keys whose witnesses can be malleated. When
+    /// `hardening_offset` is given, every leaf with non-canonical digits includes a hardened
+    /// variant built with `ProtocolScript::set_canonical_winternitz_digits`.
+    pub fn winternitz_malleability_report(
+        &self,
+        hardening_offset: Option<usize>,
+    ) -> Result<Vec<WinternitzLeafReport>, ProtocolBuilderError> {
+        let mut report = vec![];
+
+        for transaction_name in self.graph.sort()? {
+            for (output_index, output) in self
+                .graph
+                .get_outputs(&transaction_name)?
+                .iter()
+                .enumerate()
+            {
+                if let OutputType::Taproot { leaves, .. } = output {
+                    for (leaf_index, leaf) in leaves.iter().enumerate() {
+                        if let Some(entry) = winternitz_leaf_report(
+                            &transaction_name,
+                            output_index,
+                            leaf_index,
+                            leaf,
+                            hardening_offset,
+                        )? {
+                            report.push(entry);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Audits every taproot leaf and P2WSH script of the protocol for witnesses a third party
+    /// could alter without invalidating them. Only leaves with issues are reported.
+    pub fn audit_malleability(&self) -> Result<Vec<LeafMalleabilityReport>, ProtocolBuilderError> {
+        let mut report = vec![];
+
+        for transaction_name in self.graph.sort()? {
+            for (output_index, output) in self
+                .graph
+                .get_outputs(&transaction_name)?
+                .iter()
+                .enumerate()
+            {
+                let segwit_v0 = matches!(output, OutputType::SegwitScript { .. });
+                for (leaf_index, leaf) in output.get_scripts() {
+                    let issues = leaf_malleability(leaf, segwit_v0)?;
+                    if !issues.is_empty() {
+                        report.push(LeafMalleabilityReport {
+                            transaction_name: transaction_name.clone(),
+                            output_index,
+                            leaf_index,
+                            issues,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Exports a descriptor for every output of the protocol transactions (external transactions
+    /// are skipped), so the protocol UTXOs can be tracked by watch-only wallets.
+    pub fn export_descriptors(&self) -> Result<Vec<OutputDescriptor>, ProtocolBuilderError> {
+        let mut descriptors = vec![];
+
+        for transaction_name in self.graph.sort()? {
+            for (output_index, output) in self
+                .graph
+                .get_outputs(&transaction_name)?
+                .iter()
+                .enumerate()
+            {
+                if let Some(descriptor) = output.to_descriptor() {
+                    descriptors.push(OutputDescriptor {
+                        transaction_name: transaction_name.clone(),
+                        output_index: output_index as u32,
+                        descriptor,
+                    });
+                }
+            }
+        }
+
+        Ok(descriptors)
+    }
+
+    /// Exports every protocol transaction (external transactions are skipped) with empty witnesses
+    /// and the estimated witness weight of each input, to be handed to fee-bumping services.
+    pub fn export_skeletons(&self) -> Result<Vec<TransactionSkeleton>, ProtocolBuilderError> {
+        let mut skeletons = vec![];
+
+        for transaction_name in self.graph.sort()? {
+            let mut transaction = self.transaction_by_name(&transaction_name)?.clone();
+            for input in transaction.input.iter_mut() {
+                input.witness = Witness::default();
+            }
+
+            let witness_sizes = self.graph.estimate_witness_sizes(&transaction_name)?;
+            let inputs = self.graph.get_inputs(&transaction_name)?;
+
+            let skeleton_inputs = transaction
+                .input
+                .iter()
+                .zip(inputs.iter())
+                .zip(witness_sizes.iter())
+                .map(|((txin, input), witness_size)| {
+                    Ok(SkeletonInput {
+                        previous_output: txin.previous_output,
+                        sequence: txin.sequence,
+                        amount: input.output_type()?.get_value().to_sat(),
+                        sighash_type: input.sighash_type().to_string(),
+                        anyone_can_pay: input.sighash_type().is_anyone_can_pay(),
+                        witness_weight: *witness_size as u64,
+                    })
+                })
+                .collect::<Result<Vec<_>, ProtocolBuilderError>>()?;
+
+            let skeleton_outputs = transaction
+                .output
+                .iter()
+                .map(|txout| SkeletonOutput {
+                    amount: txout.value.to_sat(),
+                    script_pubkey: hex::encode(txout.script_pubkey.as_bytes()),
+                })
+                .collect();
+
+            // Marker and flag are only serialized when the transaction has witnesses.
+            let witness_weight = witness_sizes.iter().sum::<usize>();
+            let witness_weight = match witness_weight {
+                0 => 0,
+                weight => weight + 2,
+            };
+            let estimated_weight = (stripped_size_bytes(&transaction) * 4 + witness_weight) as u64;
+
+            skeletons.push(TransactionSkeleton {
+                name: transaction_name.clone(),
+                txid: transaction.compute_txid(),
+                version: transaction.version.0,
+                lock_time: transaction.lock_time.to_consensus_u32(),
+                unsigned_hex: get_transaction_hex(&transaction),
+                estimated_weight,
+                estimated_vsize: estimated_weight.div_ceil(4),
+                inputs: skeleton_inputs,
+                outputs: skeleton_outputs,
+            });
+        }
+
+        Ok(skeletons)
+    }
+
+    /// Imports a variant of a protocol transaction that was re-signed by an external service,
+    /// typically adding inputs to bump its fee. The variant must keep every protocol input, and
+    /// all of them must be signed with ANYONECANPAY when inputs were added. Outputs committed by
+    /// the protocol signatures must be left untouched. The sighash type of each protocol input
+    /// is the one of the leaf its witness spends, and the signature in the witness is verified
+    /// against the spent outputs. Witnesses of custom outputs are not checked.
+    ///
+    /// Inputs added to the variant spend outputs the protocol does not know, so they are not
+    /// verified at all. They are returned as `unverified_inputs` for the caller to check against
+    /// their own view of the chain.
+    pub fn import_resigned_transaction(
+        &mut self,
+        transaction_name: &str,
+        resigned: Transaction,
+    ) -> Result<ResignedImport, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        let secp = secp256k1::Secp256k1::verification_only();
+        let original = self.transaction_by_name(transaction_name)?;
+        let inputs = self.graph.get_inputs(transaction_name)?;
+        let original_prevouts = self.graph.get_prevouts(transaction_name)?;
+        let invalid = |reason: &str| {
+            ProtocolBuilderError::InvalidResignedTransaction(
+                transaction_name.to_string(),
+                reason.to_string(),
+            )
+        };
+
+        if resigned.version != original.version || resigned.lock_time != original.lock_time {
+            return Err(invalid("version and locktime must not change"));
+        }
+
+        let inputs_changed = resigned.input.len() != original.input.len()
+            || resigned
+                .input
+                .iter()
+                .zip(original.input.iter())
+                .any(|(a, b)| a.previous_output != b.previous_output);
+
+        // Outputs spent by added inputs are unknown. Only signatures without ANYONECANPAY commit
+        // to them, and those are rejected when inputs were added.
+        let mut unverified_inputs = vec![];
+        let prevouts: Vec<TxOut> = resigned
+            .input
+            .iter()
+            .enumerate()
+            .map(|(index, txin)| {
+                match original
+                    .input
+                    .iter()
+                    .position(|other| other.previous_output == txin.previous_output)
+                {
+                    Some(original_index) => original_prevouts[original_index].clone(),
+                    None => {
+                        unverified_inputs.push(index);
+                        TxOut::NULL
+                    }
+                }
+            })
+            .collect();
+
+        for (original_index, (txin, input)) in original.input.iter().zip(inputs.iter()).enumerate()
+        {
+            let resigned_index = resigned
+                .input
+                .iter()
+                .position(|other| other.previous_output == txin.previous_output)
+                .ok_or_else(|| invalid(&format!("missing input {}", original_index)))?;
+
+            if resigned.input[resigned_index].sequence != txin.sequence {
+                return Err(invalid(&format!(
+                    "sequence of input {} changed",
+                    original_index
+                )));
+            }
+
+            let output = input.output_type()?;
+            let check = match output {
+                OutputType::Custom { .. }
+                | OutputType::SegwitUnspendable { .. }
+                | OutputType::PayToAnchor { .. } => None,
+                _ => Some(
+                    check_witness(
+                        &secp,
+                        &resigned,
+                        &prevouts,
+                        resigned_index,
+                        output,
+                        input.sighash_type(),
+                    )?
+                    .ok_or_else(|| {
+                        invalid(&format!(
+                            "witness of input {} does not spend its output",
+                            original_index
+                        ))
+                    })?,
+                ),
+            };
+
+            // Leaves may be signed with their own sighash type
+            let sighash_type = check
+                .as_ref()
+                .map_or(input.sighash_type(), |check| &check.sighash_type);
+            if inputs_changed && !sighash_type.is_anyone_can_pay() {
+                return Err(invalid(&format!(
+                    "input {} is not signed with ANYONECANPAY",
+                    original_index
+                )));
+            }
+
+            if sighash_type.commits_all_outputs() && resigned.output != original.output {
+                return Err(invalid(&format!(
+                    "outputs committed by input {} changed",
+                    original_index
+                )));
+            }
+
+            if sighash_type.commits_single_output()
+                && resigned.output.get(resigned_index) != original.output.get(original_index)
+            {
+                return Err(invalid(&format!(
+                    "output committed by input {} changed",
+                    original_index
+                )));
+            }
+
+            match check.and_then(|check| check.status) {
+                None | Some(SignatureStatus::Valid) => {}
+                Some(status) => {
+                    return Err(invalid(&format!(
+                        "signature of input {} is not valid: {:?}",
+                        original_index, status
+                    )))
+                }
+            }
+        }
+
+        let txid = resigned.compute_txid();
+        self.graph
+            .add_resigned_transaction(transaction_name, resigned)?;
+
+        Ok(ResignedImport {
+            txid,
+            unverified_inputs,
+        })
+    }
+
+    /// Returns the externally re-signed variants imported for a transaction.
+    pub fn resigned_transactions(
+        &self,
+        transaction_name: &str,
+    ) -> Result<Vec<Transaction>, ProtocolBuilderError> {
+        Ok(self.graph.get_resigned_transactions(transaction_name)?)
     }
 
     pub fn visualize(&self, options: GraphOptions) -> Result<String, ProtocolBuilderError> {
         Ok(self.graph.visualize(options)?)
     }
 
+    pub(crate) fn graph(&self) -> &TransactionGraph {
+        &self.graph
+    }
+
+    pub(crate) fn metadata(&self) -> ProtocolMetadata {
+        ProtocolMetadata(Protocol {
+            graph: TransactionGraph::new(),
+            ..self.clone()
+        })
+    }
+
+    pub(crate) fn from_metadata(metadata: ProtocolMetadata, graph: TransactionGraph) -> Self {
+        Protocol {
+            graph,
+            ..metadata.0
+        }
+    }
+
     pub(crate) fn transaction_template() -> Transaction {
         Transaction {
             version: transaction::Version::TWO,            // Post BIP-68.
@@ -596,6 +2870,42 @@ impl Protocol {
         }
     }
 
+    fn check_external_output(
+        &self,
+        external: &ExternalTx,
+        output: &OutputSpec,
+    ) -> Result<(), ProtocolBuilderError> {
+        let output_index = match output {
+            OutputSpec::Index(index) => *index,
+            OutputSpec::Last => external.output_count().saturating_sub(1),
+            OutputSpec::Label(label) => self
+                .graph
+                .get_output_index_by_label(external.name(), label)?,
+            OutputSpec::Auto(_) | OutputSpec::Labeled(_, _) => {
+                return Err(ProtocolBuilderError::UndeclaredExternalOutput(
+                    external.name().to_string(),
+                    external.output_count(),
+                ))
+            }
+        };
+
+        if output_index >= external.output_count() {
+            return Err(ProtocolBuilderError::UndeclaredExternalOutput(
+                external.name().to_string(),
+                output_index,
+            ));
+        }
+
+        if external.known_output(output_index).is_none() {
+            return Err(ProtocolBuilderError::OpaqueExternalOutput(
+                external.name().to_string(),
+                output_index,
+            ));
+        }
+
+        Ok(())
+    }
+
     fn get_or_create_transaction(
         &mut self,
         transaction_name: &str,
@@ -604,9 +2914,14 @@ impl Protocol {
         check_empty_transaction_name(transaction_name)?;
 
         if !self.graph.contains_transaction(transaction_name) {
+            self.check_limit(ProtocolLimit::Transactions, self.graph.nodes().count() + 1)?;
             let transaction = Protocol::transaction_template();
             self.graph
                 .add_transaction(transaction_name, transaction, external)?;
+            self.observers.emit(|| ProtocolEvent::TransactionAdded {
+                transaction_name: transaction_name.to_string(),
+                external,
+            });
         };
 
         Ok(self
@@ -625,14 +2940,27 @@ impl Protocol {
 
     /// Updates the txids of each transaction in the DAG in topological order.
     /// It will update the txid of the transaction and the txid of the connected inputs.
-    fn update_transaction_ids(&mut self) -> Result<(), ProtocolBuilderError> {
+    /// Only the inputs of the given transactions are updated with the txids of the transactions
+    /// they spend.
+    fn update_transaction_ids(
+        &mut self,
+        transaction_names: &[String],
+    ) -> Result<(), ProtocolBuilderError> {
         let sorted_transactions = self.graph.sort()?;
+        let mut previous_txids = HashMap::new();
+        for name in transaction_names {
+            previous_txids.insert(name, self.transaction_by_name(name)?.compute_txid());
+        }
 
         for from in sorted_transactions {
             let transaction = self.transaction_by_name(&from)?;
             let txid = transaction.compute_txid();
 
             for (to, input_index) in self.get_dependencies(&from)? {
+                if !transaction_names.contains(&to) {
+                    continue;
+                }
+
                 let mut dependency = self.transaction_by_name(&to)?.clone();
                 dependency.input[input_index as usize].previous_output.txid = txid;
 
@@ -640,27 +2968,415 @@ impl Protocol {
             }
         }
 
+        for (name, previous_txid) in previous_txids {
+            let txid = self.transaction_by_name(name)?.compute_txid();
+            if txid != previous_txid {
+                self.observers.emit(|| ProtocolEvent::TxidUpdated {
+                    transaction_name: name.clone(),
+                    txid,
+                });
+            }
+        }
+
         Ok(())
     }
 
     pub fn compute_minimum_output_values(&mut self) -> Result<(), ProtocolBuilderError> {
+        self.check_not_frozen()?;
         self.graph.compute_minimum_output_values()?;
+        self.record_mutation("compute_minimum_output_values".to_string());
+        Ok(())
+    }
+
+    /// Sets the dust limits enforced on the outputs of the protocol every time it is built.
+    /// Without a policy, output values are not checked.
+    pub fn set_dust_policy(
+        &mut self,
+        policy: DustPolicy,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        self.dust_policy = Some(policy);
+        Ok(self)
+    }
+
+    pub fn dust_policy(&self) -> Option<&DustPolicy> {
+        self.dust_policy.as_ref()
+    }
+
+    /// Routes the funds left over from the external funding back to the given public key (as a
+    /// P2WPKH output) or script. Every build appends (or updates) a change output in the first
+    /// transaction spending an external transaction, worth its inputs minus its outputs minus the
+    /// fee at the change fee rate. No output is appended when the change would be dust.
+    pub fn set_change_address<D: Into<ChangeDestination>>(
+        &mut self,
+        destination: D,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        let location = self.change.take().and_then(|change| change.location);
+        self.change = Some(ChangeOutput {
+            location,
+            ..ChangeOutput::new(destination.into())
+        });
+        self.record_mutation("set_change_address".to_string());
+        Ok(self)
+    }
+
+    /// Sets the fee rate (in sat/vB) of the transaction receiving the change output.
+    pub fn set_change_fee_rate(
+        &mut self,
+        sat_per_vb: u64,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        match self.change.as_mut() {
+            Some(change) => change.fee_rate = sat_per_vb,
+            None => return Err(ProtocolBuilderError::MissingChangeAddress),
+        }
+        self.record_mutation("set_change_fee_rate".to_string());
+        Ok(self)
+    }
+
+    pub fn change_output(&self) -> Option<&ChangeOutput> {
+        self.change.as_ref()
+    }
+
+    /// When required, every build and rebuild checks the amounts of the transactions with
+    /// `check_amounts`.
+    pub fn require_amount_conservation(
+        &mut self,
+        required: bool,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        self.require_amount_conservation = required;
+        Ok(self)
+    }
+
+    /// Declares the fee (in sats) paid by a transaction, see `check_amounts`.
+    pub fn set_transaction_fee(
+        &mut self,
+        transaction_name: &str,
+        fee: u64,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        if !self.graph.contains_transaction(transaction_name) {
+            return Err(GraphError::MissingTransaction(transaction_name.to_string()).into());
+        }
+        self.fees.insert(transaction_name.to_string(), fee);
+        self.record_mutation(format!("set_transaction_fee {}", transaction_name));
+        Ok(self)
+    }
+
+    /// Records an output as the one used to speed up its transaction, so `speedup_data` can
+    /// find it by transaction name. The speedup and anchor helpers of `ProtocolBuilder` record
+    /// the outputs they add.
+    pub fn set_speedup_output(
+        &mut self,
+        transaction_name: &str,
+        output_index: usize,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.check_not_frozen()?;
+        if output_index >= self.transaction_by_name(transaction_name)?.output.len() {
+            return Err(ProtocolBuilderError::MissingOutput(
+                transaction_name.to_string(),
+                output_index,
+            ));
+        }
+        self.speedup_outputs
+            .insert(transaction_name.to_string(), output_index);
+        self.record_mutation(format!("set_speedup_output {}", transaction_name));
+        Ok(self)
+    }
+
+    pub fn speedup_output(&self, transaction_name: &str) -> Option<usize> {
+        self.speedup_outputs.get(transaction_name).copied()
+    }
+
+    pub fn speedup_outputs(&self) -> &BTreeMap<String, usize> {
+        &self.speedup_outputs
+    }
+
+    /// Data to spend the recorded speedup output of a built transaction in
+    /// `ProtocolBuilder::speedup_transactions`, for P2WPKH speedup outputs and P2A anchors.
+    pub fn speedup_data(
+        &self,
+        transaction_name: &str,
+    ) -> Result<SpeedupData, ProtocolBuilderError> {
+        let output_index = self.speedup_output(transaction_name).ok_or_else(|| {
+            ProtocolBuilderError::MissingSpeedupOutput(transaction_name.to_string())
+        })?;
+        let txid = self.transaction_by_name(transaction_name)?.compute_txid();
+        let output = self
+            .graph
+            .get_output(transaction_name, output_index)?
+            .ok_or_else(|| {
+                ProtocolBuilderError::MissingOutput(transaction_name.to_string(), output_index)
+            })?;
+        let amount = output.get_value().to_sat();
+
+        match output {
+            OutputType::SegwitPublicKey { public_key, .. } => Ok(SpeedupData::new(Utxo::new(
+                txid,
+                output_index as u32,
+                amount,
+                public_key,
+            ))),
+            OutputType::PayToAnchor { .. } => {
+                Ok(SpeedupData::new_anchor(txid, output_index as u32, amount))
+            }
+            _ => Err(ProtocolBuilderError::UnsupportedSpeedupOutput(
+                transaction_name.to_string(),
+                output_index,
+            )),
+        }
+    }
+
+    /// Fee declared for a transaction with `set_transaction_fee`, zero if none was declared.
+    pub fn transaction_fee(&self, transaction_name: &str) -> u64 {
+        self.fees.get(transaction_name).copied().unwrap_or_default()
+    }
+
+    /// Checks that every transaction of the protocol spends at least the value of its outputs
+    /// plus its declared fee, which nodes would otherwise only reject at broadcast
+    /// (`bad-txns-in-belowout`). External transactions and transactions without inputs, still to
+    /// be funded, are not checked.
+    pub fn check_amounts(&self) -> Result<(), ProtocolBuilderError> {
+        for node in self
+            .graph
+            .nodes()
+            .filter(|node| !node.external && !node.transaction.input.is_empty())
+        {
+            let total_in: u64 = self
+                .graph
+                .get_prevouts(&node.name)?
+                .iter()
+                .map(|txout| txout.value.to_sat())
+                .sum();
+            let total_out: u64 = node
+                .transaction
+                .output
+                .iter()
+                .map(|txout| txout.value.to_sat())
+                .sum();
+            let fee = self.transaction_fee(&node.name);
+
+            if total_in < total_out + fee {
+                return Err(ProtocolBuilderError::ValueShortfall(
+                    node.name.clone(),
+                    total_in,
+                    total_out,
+                    fee,
+                    total_out + fee - total_in,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Appends the change output to the funded transaction, or updates its value when it was
+    // already appended by a previous build.
+    fn apply_change_output(&mut self) -> Result<(), ProtocolBuilderError> {
+        let Some(change) = self.change.clone() else {
+            return Ok(());
+        };
+
+        let transaction_name = match &change.location {
+            Some((transaction_name, _)) => transaction_name.clone(),
+            None => self
+                .funded_transaction()?
+                .ok_or(ProtocolBuilderError::MissingFundedTransaction)?,
+        };
+
+        let total_in: u64 = self
+            .graph
+            .get_prevouts(&transaction_name)?
+            .iter()
+            .map(|txout| txout.value.to_sat())
+            .sum();
+
+        let mut transaction = self.transaction_by_name(&transaction_name)?.clone();
+        let output = change.destination.output(0)?;
+        let output_index = match &change.location {
+            Some((_, output_index)) => *output_index,
+            None => {
+                transaction.output.push(bitcoin::TxOut {
+                    value: output.get_value(),
+                    script_pubkey: output.get_script_pubkey().clone(),
+                });
+                transaction.output.len() - 1
+            }
+        };
+
+        let total_out: u64 = transaction
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != output_index)
+            .map(|(_, txout)| txout.value.to_sat())
+            .sum();
+
+        let inputs = self.graph.get_inputs(&transaction_name)?;
+        let fee =
+            estimate_min_relay_fee(&transaction, &transaction_name, &inputs, change.fee_rate, 0)?;
+        let required = total_out + fee;
+        if total_in < required {
+            return Err(ProtocolBuilderError::InsufficientFunding(
+                transaction_name,
+                total_in,
+                required,
+            ));
+        }
+
+        let output = change.destination.output(total_in - required)?;
+        let dust_limit = self
+            .dust_policy
+            .clone()
+            .unwrap_or_default()
+            .dust_limit(&output);
+
+        match change.location {
+            None if output.get_value() < dust_limit => Ok(()),
+            None => {
+                self.add_transaction_output(&transaction_name, &output)?;
+                if let Some(change) = self.change.as_mut() {
+                    change.location = Some((transaction_name, output_index));
+                }
+                Ok(())
+            }
+            Some(_) => {
+                if output.get_value() < dust_limit {
+                    return Err(ProtocolBuilderError::DustOutput(
+                        transaction_name,
+                        output_index,
+                        output.get_value().to_sat(),
+                        dust_limit.to_sat(),
+                    ));
+                }
+
+                if transaction.output[output_index].value == output.get_value()
+                    && transaction.output[output_index].script_pubkey == *output.get_script_pubkey()
+                {
+                    return Ok(());
+                }
+
+                self.check_not_frozen()?;
+                self.graph
+                    .update_output(&transaction_name, output_index, output)?;
+                Ok(())
+            }
+        }
+    }
+
+    // First protocol transaction, in topological order, spending an output of an external
+    // transaction.
+    fn funded_transaction(&self) -> Result<Option<String>, ProtocolBuilderError> {
+        let external: Vec<&str> = self
+            .graph
+            .nodes()
+            .filter(|node| node.external)
+            .map(|node| node.name.as_str())
+            .collect();
+
+        let funded: Vec<String> = self
+            .graph
+            .stored_connections()
+            .into_iter()
+            .filter(|stored| external.contains(&stored.from.as_str()))
+            .map(|stored| stored.to)
+            .collect();
+
+        Ok(self
+            .graph
+            .sort()?
+            .into_iter()
+            .find(|name| funded.contains(name) && !external.contains(&name.as_str())))
+    }
+
+    // Rejects or bumps, depending on the dust policy, the outputs below their dust limit.
+    fn apply_dust_policy(&mut self) -> Result<(), ProtocolBuilderError> {
+        let Some(policy) = self.dust_policy.clone() else {
+            return Ok(());
+        };
+
+        let external: Vec<String> = self
+            .graph
+            .nodes()
+            .filter(|node| node.external)
+            .map(|node| node.name.clone())
+            .collect();
+
+        for transaction_name in self.graph.sort()? {
+            if external.contains(&transaction_name) {
+                continue;
+            }
+
+            for (output_index, output) in self
+                .graph
+                .get_outputs(&transaction_name)?
+                .iter()
+                .enumerate()
+            {
+                if !policy.is_dust(output) {
+                    continue;
+                }
+
+                let dust_limit = policy.dust_limit(output);
+                match policy.action() {
+                    DustAction::Reject => {
+                        return Err(ProtocolBuilderError::DustOutput(
+                            transaction_name,
+                            output_index,
+                            output.get_value().to_sat(),
+                            dust_limit.to_sat(),
+                        ));
+                    }
+                    DustAction::Bump => {
+                        self.check_not_frozen()?;
+                        let mut output = output.clone();
+                        output.set_value(dust_limit);
+                        self.graph
+                            .update_output(&transaction_name, output_index, output)?;
+                    }
+                }
+            }
+        }
+
         Ok(())
     }
 
-    fn compute_sighashes(
+    fn compute_sighashes<S: Signer + ?Sized>(
         &mut self,
-        key_manager: &KeyManager,
+        transaction_names: &[String],
+        signer: &S,
         id: &str,
     ) -> Result<(), ProtocolBuilderError> {
-        let (transactions, transaction_names) = self.graph.sorted_transactions()?;
-        for (transaction, transaction_name) in transactions.iter().zip(transaction_names.iter()) {
+        for transaction_name in transaction_names.iter() {
+            let transaction = self.transaction_by_name(transaction_name)?.clone();
             for (input_index, input) in self.graph.get_inputs(transaction_name)?.iter().enumerate()
             {
                 let output_type = input.output_type().unwrap();
+                check_single_outputs(
+                    transaction_name,
+                    &transaction,
+                    input_index,
+                    output_type,
+                    input.spend_mode(),
+                    input.sighash_type(),
+                )?;
+
+                let hashed_messages = match (output_type, input.sighash_type()) {
+                    (OutputType::Custom { output }, sighash_type) => {
+                        let prevouts = self.graph.get_prevouts(transaction_name)?;
 
-                let hashed_messages = match input.sighash_type() {
-                    SighashType::Taproot(tap_sighash_type) => {
+                        output.compute_sighashes(
+                            &transaction,
+                            transaction_name,
+                            input_index,
+                            &prevouts,
+                            input.spend_mode(),
+                            sighash_type,
+                        )?
+                    }
+                    (_, SighashType::Taproot(tap_sighash_type)) => {
                         //let prevouts = if output_type.has_prevouts() {
                         //    output_type.get_prevouts()
                         //} else {
@@ -668,90 +3384,150 @@ impl Protocol {
                         //};
 
                         output_type.compute_taproot_sighash(
-                            transaction,
+                            &transaction,
                             transaction_name,
                             input_index,
                             &prevouts,
                             input.spend_mode(),
                             tap_sighash_type,
-                            key_manager,
+                            signer,
                             id,
                         )?
                     }
-                    SighashType::Ecdsa(ecdsa_sighash_type) => output_type.compute_ecdsa_sighash(
-                        transaction,
-                        transaction_name,
-                        input_index,
-                        input.spend_mode(),
-                        ecdsa_sighash_type,
-                    )?,
+                    (_, SighashType::Ecdsa(ecdsa_sighash_type)) => output_type
+                        .compute_ecdsa_sighash(
+                            &transaction,
+                            transaction_name,
+                            input_index,
+                            input.spend_mode(),
+                            ecdsa_sighash_type,
+                        )?,
                 };
 
+                let sighashes = hashed_messages.iter().flatten().count();
                 self.graph.update_hashed_messages(
                     transaction_name,
                     input_index as u32,
                     hashed_messages,
                 )?;
+                self.observers.emit(|| ProtocolEvent::SighashComputed {
+                    transaction_name: transaction_name.to_string(),
+                    input_index,
+                    sighashes,
+                });
             }
         }
 
         Ok(())
     }
 
-    fn compute_signatures(
+    // Signs every input of the transactions. Schnorr signatures are verified in parallel once
+    // all the inputs are signed, and nothing is stored if any of them is invalid.
+    fn compute_signatures<S: Signer + ?Sized>(
         &mut self,
-        key_manager: &KeyManager,
+        transaction_names: &[String],
+        signer: &S,
         id: &str,
     ) -> Result<(), ProtocolBuilderError> {
-        let (transactions, transaction_names) = self.graph.sorted_transactions()?;
-        for (_, transaction_name) in transactions.iter().zip(transaction_names.iter()) {
+        let mut checks = SignatureChecks::default();
+        let mut signed = vec![];
+
+        for transaction_name in transaction_names.iter() {
             for (input_index, input) in self.graph.get_inputs(transaction_name)?.iter().enumerate()
             {
-                let output_type = input.output_type().unwrap();
-
-                let signatures = match input.sighash_type() {
-                    SighashType::Taproot(tap_sighash_type) => output_type
-                        .compute_taproot_signature(
+                match InputSigningPlan::new(transaction_name, input_index, input)? {
+                    Some(plan) => {
+                        let (signatures, multisig_signatures) =
+                            plan.sign(signer, id, &mut checks)?;
+                        signed.push((
+                            transaction_name,
+                            input_index,
+                            signatures,
+                            plan.taproot.then_some(multisig_signatures),
+                        ));
+                    }
+                    // Only inputs spending custom outputs have no plan
+                    None => {
+                        let OutputType::Custom { output } = input.output_type()? else {
+                            continue;
+                        };
+                        let signatures = output.compute_signatures(
                             transaction_name,
                             input_index,
                             &input.hashed_messages(),
                             input.spend_mode(),
-                            tap_sighash_type,
-                            key_manager,
+                            input.sighash_type(),
+                            &signer,
                             id,
-                        )?,
-                    SighashType::Ecdsa(ecdsa_sighash_type) => output_type.compute_ecdsa_signature(
-                        transaction_name,
-                        input_index,
-                        &input.hashed_messages(),
-                        input.spend_mode(),
-                        ecdsa_sighash_type,
-                        key_manager,
-                    )?,
-                };
-
-                self.graph.update_input_signatures(
-                    transaction_name,
-                    input_index as u32,
-                    signatures,
-                )?;
+                        )?;
+                        signed.push((transaction_name, input_index, signatures, None));
+                    }
+                }
             }
         }
 
+        checks.verify()?;
+        for (transaction_name, input_index, signatures, multisig_signatures) in signed {
+            self.store_input_signatures(
+                transaction_name,
+                input_index,
+                signatures,
+                multisig_signatures,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores the signatures computed for an input. Multisig signatures are only kept by inputs
+    /// spending taproot outputs.
+    pub(crate) fn store_input_signatures(
+        &mut self,
+        transaction_name: &str,
+        input_index: usize,
+        signatures: Vec<Option<Signature>>,
+        multisig_signatures: Option<BTreeMap<usize, Vec<Option<Signature>>>>,
+    ) -> Result<(), ProtocolBuilderError> {
+        let count = signatures.iter().flatten().count();
+        self.graph
+            .update_input_signatures(transaction_name, input_index as u32, signatures)?;
+        self.emit_signature_added(transaction_name, input_index, count);
+
+        if let Some(multisig_signatures) = multisig_signatures {
+            self.graph.update_input_multisig_signatures(
+                transaction_name,
+                input_index as u32,
+                multisig_signatures,
+            )?;
+        }
+
         Ok(())
     }
 
-    fn get_witness_for_input(
+    fn emit_signature_added(&self, transaction_name: &str, input_index: usize, signatures: usize) {
+        self.observers.emit(|| ProtocolEvent::SignatureAdded {
+            transaction_name: transaction_name.to_string(),
+            input_index,
+            signatures,
+        });
+    }
+
+    pub(super) fn get_witness_for_input(
         &self,
         input_index: usize,
         input: &InputType,
         args: &InputArgs,
     ) -> Result<Witness, ProtocolBuilderError> {
+        if let OutputType::Custom { output } = input.output_type()? {
+            return output.witness(input, args);
+        }
+
         let witness = match input.sighash_type() {
             SighashType::Taproot(..) => match input.output_type()? {
                 OutputType::Taproot { .. } => match args {
-                    InputArgs::TaprootScript { leaf, .. } => {
-                        self.taproot_script_witness(input_index, *leaf, input, args)?
+                    InputArgs::TaprootScript { .. } | InputArgs::TaprootScriptByName { .. } => {
+                        let leaf = args.leaf_index(input.output_type()?)?.unwrap();
+                        self.taproot_script_witness(input_index, leaf, input, args)?
                     }
                     InputArgs::TaprootKey { .. } => self.taproot_key_witness(args)?,
                     _ => {
@@ -770,8 +3546,8 @@ impl Protocol {
                 OutputType::SegwitScript { ref script, .. } => {
                     self.segwit_script_witness(script, args)?
                 }
-                OutputType::SegwitUnspendable { .. } => {
-                    // Create an empty witness for unspendable outputs
+                OutputType::SegwitUnspendable { .. } | OutputType::PayToAnchor { .. } => {
+                    // Create an empty witness for unspendable and anchor outputs
                     Witness::new()
                 }
                 _ => return Err(ProtocolBuilderError::InvalidOutputTypeForSighashType),
@@ -781,9 +3557,41 @@ impl Protocol {
         Ok(witness)
     }
 
-    pub fn create_unspendable_key() -> Result<XOnlyPublicKey, ProtocolBuilderError> {
+    /// Unspendable internal key derived from the protocol name and a label, e.g. the name of the
+    /// output, and the seed of deterministic protocols. Parties building the same protocol derive
+    /// the same keys, so their taproot outputs match without sharing randomness. The unspendable
+    /// proof of the key is added to the protocol.
+    pub fn create_deterministic_unspendable_key(
+        &mut self,
+        label: &str,
+    ) -> Result<XOnlyPublicKey, ProtocolBuilderError> {
+        let mut context = self.seed.map(Vec::from).unwrap_or_default();
+        context.extend_from_slice(format!("{}/{}", self.name, label).as_bytes());
+
+        let (key, derivation_data) = deterministic_unspendable_key(&context)?;
+        let key = XOnlyPublicKey::from(key);
+
+        self.add_unspendable_proof(&key, derivation_data)?;
+        Ok(key)
+    }
+
+    /// New unspendable internal key, with its unspendable proof added to the protocol. Keys of
+    /// protocols created with `new_deterministic` are derived from the seed and the number of
+    /// keys created so far by this method, so the same sequence of calls gives the same keys.
+    /// Other protocols get random keys.
+    pub fn create_unspendable_key(&mut self) -> Result<XOnlyPublicKey, ProtocolBuilderError> {
+        if self.seed.is_some() {
+            let label = format!("#{}", self.derived_unspendable_keys);
+            let key = self.create_deterministic_unspendable_key(&label)?;
+            self.derived_unspendable_keys += 1;
+            return Ok(key);
+        }
+
         let mut rng = secp256k1::rand::thread_rng();
-        let key = XOnlyPublicKey::from(unspendable_key(&mut rng)?);
+        let (key, derivation_data) = unspendable_key_with_derivation(&mut rng)?;
+        let key = XOnlyPublicKey::from(key);
+
+        self.add_unspendable_proof(&key, derivation_data)?;
         Ok(key)
     }
 
@@ -881,3 +3689,125 @@ impl Protocol {
         Ok(witness)
     }
 }
+
+fn leaf_count(output: &OutputType) -> usize {
+    match output {
+        OutputType::Taproot { leaves, .. } => leaves.len(),
+        _ => 0,
+    }
+}
+
+// Output with the keys of the mapping replaced, or None if the output uses none of them.
+fn rotate_output_keys(
+    output: &OutputType,
+    key_mapping: &HashMap<PublicKey, PublicKey>,
+) -> Result<Option<OutputType>, ProtocolBuilderError> {
+    let rotated = match output {
+        OutputType::SegwitPublicKey {
+            value, public_key, ..
+        } => match key_mapping.get(public_key) {
+            Some(new_key) => Some(OutputType::segwit_key(value.to_sat(), new_key)?),
+            None => None,
+        },
+        OutputType::SegwitScript { value, script, .. } => {
+            let mut script = script.clone();
+            match script.replace_keys(key_mapping) {
+                true => Some(OutputType::segwit_script(value.to_sat(), &script)?),
+                false => None,
+            }
+        }
+        OutputType::Taproot {
+            value,
+            internal_key,
+            leaves,
+            layout,
+            ..
+        } => {
+            let mut changed = key_mapping.contains_key(internal_key);
+            let internal_key = key_mapping.get(internal_key).unwrap_or(internal_key);
+
+            let mut leaves = leaves.clone();
+            for leaf in leaves.iter_mut() {
+                changed |= leaf.replace_keys(key_mapping);
+            }
+
+            match changed {
+                true => Some(OutputType::taproot_with_layout(
+                    value.to_sat(),
+                    internal_key,
+                    &leaves,
+                    layout,
+                )?),
+                false => None,
+            }
+        }
+        _ => None,
+    };
+
+    Ok(rotated)
+}
+
+// SIGHASH_SINGLE commits to the output with the same index as the input. Without it, segwit v0
+// commits to no output at all and taproot cannot sign, so the input is rejected up front.
+fn check_single_output(
+    transaction_name: &str,
+    transaction: &Transaction,
+    input_index: usize,
+    sighash_type: &SighashType,
+) -> Result<(), ProtocolBuilderError> {
+    if sighash_type.commits_single_output() && input_index >= transaction.output.len() {
+        return Err(ProtocolBuilderError::MissingSingleOutput(
+            transaction_name.to_string(),
+            input_index,
+        ));
+    }
+
+    Ok(())
+}
+
+// Checks every sighash type the input is signed with. Taproot leaves can override the sighash
+// type of the input, so each selected leaf is checked with its own and the input type only
+// applies to the key path.
+fn check_single_outputs(
+    transaction_name: &str,
+    transaction: &Transaction,
+    input_index: usize,
+    output_type: &OutputType,
+    spend_mode: &SpendMode,
+    sighash_type: &SighashType,
+) -> Result<(), ProtocolBuilderError> {
+    let SighashType::Taproot(tap_sighash_type) = sighash_type else {
+        return check_single_output(transaction_name, transaction, input_index, sighash_type);
+    };
+
+    for effective_sighash_type in
+        output_type.effective_sighash_types(spend_mode, *tap_sighash_type)?
+    {
+        check_single_output(
+            transaction_name,
+            transaction,
+            input_index,
+            &SighashType::Taproot(effective_sighash_type),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Check of the stored revision done by `Protocol::write` before overwriting the protocol.
+enum RevisionCheck {
+    // The stored revision must be the given one, see `save_checked`.
+    Equal(u64),
+    // The stored revision must not be newer than the given one, see `save`.
+    NotNewer(u64),
+}
+
+// Storage key of protocols saved with a binary format.
+fn encoded_key(protocol_name: &str) -> String {
+    format!("{}/encoded", protocol_name)
+}
+
+// Storage key of the revision of the last saved protocol.
+fn revision_key(protocol_name: &str) -> String {
+    format!("{}/revision", protocol_name)
+}
@@ -0,0 +1,158 @@
+use std::{collections::BTreeMap, rc::Rc};
+
+use storage_backend::storage::{KeyValueStore, Storage};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    types::registry::{ProtocolRecord, ProtocolStatus},
+};
+
+use super::protocol::Protocol;
+
+// Storage key of the records of all the protocols in the registry.
+const INDEX_KEY: &str = "protocol_registry/index";
+
+/// Manages many protocol instances saved in the same storage. Protocols are saved as with
+/// `Protocol::save`, and the registry keeps a `ProtocolRecord` for each of them so they can be
+/// listed, tagged and deleted without loading them.
+pub struct ProtocolRegistry {
+    storage: Rc<Storage>,
+}
+
+impl ProtocolRegistry {
+    pub fn new(storage: Rc<Storage>) -> Self {
+        Self { storage }
+    }
+
+    /// Saves the protocol and updates its record. The participants are the parties owning its
+    /// inputs, see `Protocol::set_input_owner`.
+    pub fn save(&self, protocol: &Protocol) -> Result<ProtocolRecord, ProtocolBuilderError> {
+        let mut participants: Vec<String> = protocol
+            .owners()
+            .iter()
+            .map(|owner| owner.party.clone())
+            .collect();
+        participants.sort();
+        participants.dedup();
+
+        self.save_with_participants(protocol, participants)
+    }
+
+    /// Saves the protocol and updates its record with the given participants. The creation
+    /// time, status and tags of protocols already in the registry are kept.
+    pub fn save_with_participants(
+        &self,
+        protocol: &Protocol,
+        participants: Vec<String>,
+    ) -> Result<ProtocolRecord, ProtocolBuilderError> {
+        protocol.save(self.storage.clone())?;
+
+        let mut index = self.index()?;
+        let record = index
+            .entry(protocol.name().to_string())
+            .and_modify(|record| {
+                record.participants = participants.clone();
+                record.touch();
+            })
+            .or_insert_with(|| ProtocolRecord::new(protocol.name(), participants))
+            .clone();
+
+        self.save_index(&index)?;
+        Ok(record)
+    }
+
+    /// Loads a protocol of the registry, or None if it was never saved in it.
+    pub fn load(&self, name: &str) -> Result<Option<Protocol>, ProtocolBuilderError> {
+        match self.index()?.contains_key(name) {
+            true => Protocol::load(name, self.storage.clone()),
+            false => Ok(None),
+        }
+    }
+
+    pub fn record(&self, name: &str) -> Result<Option<ProtocolRecord>, ProtocolBuilderError> {
+        Ok(self.index()?.remove(name))
+    }
+
+    /// Records of all the protocols in the registry, sorted by name.
+    pub fn list(&self) -> Result<Vec<ProtocolRecord>, ProtocolBuilderError> {
+        Ok(self.index()?.into_values().collect())
+    }
+
+    pub fn with_tag(&self, tag: &str) -> Result<Vec<ProtocolRecord>, ProtocolBuilderError> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|record| record.has_tag(tag))
+            .collect())
+    }
+
+    pub fn with_status(
+        &self,
+        status: ProtocolStatus,
+    ) -> Result<Vec<ProtocolRecord>, ProtocolBuilderError> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|record| record.status == status)
+            .collect())
+    }
+
+    pub fn tag(&self, name: &str, tag: &str) -> Result<(), ProtocolBuilderError> {
+        self.update(name, |record| {
+            record.tags.insert(tag.to_string());
+        })
+    }
+
+    pub fn untag(&self, name: &str, tag: &str) -> Result<(), ProtocolBuilderError> {
+        self.update(name, |record| {
+            record.tags.remove(tag);
+        })
+    }
+
+    pub fn set_status(
+        &self,
+        name: &str,
+        status: ProtocolStatus,
+    ) -> Result<(), ProtocolBuilderError> {
+        self.update(name, |record| record.status = status)
+    }
+
+    /// Removes a protocol and its record. Returns false if the protocol is not in the registry.
+    pub fn delete(&self, name: &str) -> Result<bool, ProtocolBuilderError> {
+        let mut index = self.index()?;
+        if index.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        Protocol::delete(name, self.storage.clone())?;
+        self.save_index(&index)?;
+        Ok(true)
+    }
+
+    fn update(
+        &self,
+        name: &str,
+        change: impl FnOnce(&mut ProtocolRecord),
+    ) -> Result<(), ProtocolBuilderError> {
+        let mut index = self.index()?;
+        let record = index
+            .get_mut(name)
+            .ok_or_else(|| ProtocolBuilderError::UnregisteredProtocol(name.to_string()))?;
+
+        change(record);
+        record.touch();
+        self.save_index(&index)
+    }
+
+    fn index(&self) -> Result<BTreeMap<String, ProtocolRecord>, ProtocolBuilderError> {
+        Ok(self.storage.get(INDEX_KEY)?.unwrap_or_default())
+    }
+
+    fn save_index(
+        &self,
+        index: &BTreeMap<String, ProtocolRecord>,
+    ) -> Result<(), ProtocolBuilderError> {
+        self.storage.set(INDEX_KEY, index, None)?;
+        Ok(())
+    }
+}
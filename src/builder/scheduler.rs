@@ -0,0 +1,613 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bitcoin::PublicKey;
+use tracing::{debug, warn};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    scripts::SignMode,
+    types::{
+        input::SpendMode,
+        nonces::{NonceBundle, PartialSignatureBundle},
+        output::MessageId,
+        OutputType, Signer,
+    },
+};
+
+use super::Protocol;
+
+/// Signing progress of a protocol instance handled by the `SigningScheduler`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SigningStatus {
+    /// The instance was added but its sighashes and nonces were not generated yet.
+    Pending,
+    /// Sighashes were computed and nonces generated for all aggregated messages.
+    NoncesGenerated,
+    /// The nonces of the other participants were aggregated, partial signatures can be exported.
+    NoncesAggregated,
+    /// The partial signatures of the other participants were aggregated, the instance is ready
+    /// to be signed.
+    PartialSignaturesAggregated,
+    /// All the signatures of the instance were computed.
+    Signed,
+    /// The instance failed at some stage of the ceremony, the error message is kept.
+    Failed(String),
+}
+
+/// A message that has to be signed with an aggregated (MuSig2) key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AggregatedMessage {
+    pub protocol_name: String,
+    pub id: String,
+    pub message_id: String,
+    pub transaction_name: String,
+    pub input_index: usize,
+    pub script_index: usize,
+    pub message: Vec<u8>,
+    /// Unix time (in seconds) after which the nonces of this message expire.
+    pub deadline: Option<u64>,
+}
+
+struct SigningInstance {
+    protocol: Protocol,
+    id: String,
+    status: SigningStatus,
+    /// Number of times the nonces of the instance expired.
+    epoch: u32,
+    deadline: Option<u64>,
+    input_deadlines: HashMap<(String, usize), u64>,
+    /// Unix time the nonces of the first session were generated.
+    generated_at: Option<u64>,
+    /// Time the deadlines are moved by in the current session, so every session lasts as long
+    /// as the first one.
+    deadline_shift: u64,
+}
+
+impl SigningInstance {
+    // Whether the instance holds the nonces of a MuSig2 session that is not complete yet.
+    fn holds_nonces(&self) -> bool {
+        matches!(
+            self.status,
+            SigningStatus::NoncesGenerated
+                | SigningStatus::NoncesAggregated
+                | SigningStatus::PartialSignaturesAggregated
+        )
+    }
+
+    fn fail(&mut self, stage: &str, error: ProtocolBuilderError) {
+        warn!(
+            "{} failed for instance {} of protocol {}: {}",
+            stage,
+            self.id,
+            self.protocol.name(),
+            error
+        );
+        self.status = SigningStatus::Failed(error.to_string());
+    }
+
+    // Nonces are stored per session id and message id, so every epoch uses a new session id
+    // and expired or failed nonces are never reused.
+    fn session_id(&self) -> String {
+        if self.epoch == 0 {
+            self.id.clone()
+        } else {
+            format!("{}_epoch:{}", self.id, self.epoch)
+        }
+    }
+
+    fn deadline(&self, transaction_name: &str, input_index: usize) -> Option<u64> {
+        self.input_deadlines
+            .get(&(transaction_name.to_string(), input_index))
+            .copied()
+            .or(self.deadline)
+            .map(|deadline| deadline + self.deadline_shift)
+    }
+}
+
+/// Drives the signing ceremonies of many protocol instances (e.g. one per peg-in) side by side.
+/// Instances are identified by the id given when they are added, so several instances of the
+/// same protocol can be scheduled.
+///
+/// The MuSig2 rounds are batched across instances, so every participant exchanges a single
+/// message per round whatever the number of instances:
+/// 1. `generate_nonces` builds the pending instances, generating their nonces.
+/// 2. `export_nonces` returns the nonces of every instance, sent to the other participants, and
+///    `import_peer_nonces` aggregates the nonces received from them.
+/// 3. `export_partial_signatures` and `import_peer_partial_signatures` do the same with the
+///    partial signatures.
+/// 4. `sign` computes the signatures of the instances.
+///
+/// Failing instances are marked and skipped at every stage so the other instances can complete.
+#[derive(Default)]
+pub struct SigningScheduler {
+    instances: Vec<SigningInstance>,
+}
+
+impl SigningScheduler {
+    pub fn new() -> Self {
+        Self { instances: vec![] }
+    }
+
+    /// Schedules a protocol instance. The `id` identifies the instance in the scheduler and is
+    /// the MuSig2 session id used for it, so it must be unique.
+    pub fn add(&mut self, protocol: Protocol, id: &str) -> Result<&mut Self, ProtocolBuilderError> {
+        self.insert(protocol, id)?;
+        Ok(self)
+    }
+
+    /// Schedules a protocol instance whose nonces expire at the given unix time (in seconds),
+    /// see `expire_nonces`. The deadline applies to the first session, later sessions get the
+    /// same lifetime from the time their nonces are generated.
+    pub fn add_with_deadline(
+        &mut self,
+        protocol: Protocol,
+        id: &str,
+        deadline: u64,
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        self.insert(protocol, id)?.deadline = Some(deadline);
+        Ok(self)
+    }
+
+    /// Sets the deadline of the messages signed for an input, overriding the instance deadline.
+    pub fn set_input_deadline(
+        &mut self,
+        id: &str,
+        transaction_name: &str,
+        input_index: usize,
+        deadline: u64,
+    ) -> Result<(), ProtocolBuilderError> {
+        let index = self.existing_position(id)?;
+        self.instances[index]
+            .input_deadlines
+            .insert((transaction_name.to_string(), input_index), deadline);
+        Ok(())
+    }
+
+    /// Expires the nonces of every instance with a message whose deadline is at or before `now`
+    /// (unix time in seconds). The nonces of expired instances are discarded from the signer,
+    /// and the instances are marked as pending to regenerate their nonces in the next call to
+    /// `generate_nonces` under a new session id, so stale MuSig2 sessions are never signed.
+    /// Returns the ids of the expired instances.
+    pub fn expire_nonces<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        now: u64,
+    ) -> Result<Vec<String>, ProtocolBuilderError> {
+        let mut expired = vec![];
+
+        for instance in self
+            .instances
+            .iter_mut()
+            .filter(|instance| instance.holds_nonces())
+        {
+            let messages = aggregated_messages(&instance.protocol, &instance.session_id())?;
+            let is_expired = messages
+                .iter()
+                .filter_map(|(_, message)| {
+                    instance.deadline(&message.transaction_name, message.input_index)
+                })
+                .any(|deadline| deadline <= now);
+
+            if !is_expired {
+                continue;
+            }
+
+            warn!(
+                "Nonces of instance {} of protocol {} expired in session {}",
+                instance.id,
+                instance.protocol.name(),
+                instance.session_id()
+            );
+
+            let keys: BTreeSet<PublicKey> = messages.into_iter().map(|(key, _)| key).collect();
+            for key in keys {
+                signer.discard_nonces(&key, &instance.session_id())?;
+            }
+
+            instance.epoch += 1;
+            instance.status = SigningStatus::Pending;
+            expired.push(instance.id.clone());
+        }
+
+        Ok(expired)
+    }
+
+    /// Session id currently used to generate the nonces of an instance.
+    pub fn session_id(&self, id: &str) -> Option<String> {
+        self.position(id)
+            .map(|index| self.instances[index].session_id())
+    }
+
+    /// Builds every pending instance in turn, computing its sighashes and generating the nonces
+    /// for all the messages signed with aggregated keys. Failing instances are marked and
+    /// skipped. See `generate_nonces_at`.
+    pub fn generate_nonces<S: Signer + ?Sized>(&mut self, signer: &S) -> usize {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        self.generate_nonces_at(signer, now)
+    }
+
+    /// Same as `generate_nonces`, with the nonces generated at `now` (unix time in seconds).
+    /// Instances regenerating their nonces get their deadlines moved by the time passed since
+    /// the first session.
+    pub fn generate_nonces_at<S: Signer + ?Sized>(&mut self, signer: &S, now: u64) -> usize {
+        let mut generated = 0;
+
+        for instance in self
+            .instances
+            .iter_mut()
+            .filter(|instance| instance.status == SigningStatus::Pending)
+        {
+            match instance.protocol.build(signer, &instance.session_id()) {
+                Ok(_) => {
+                    match instance.generated_at {
+                        Some(first) => instance.deadline_shift = now.saturating_sub(first),
+                        None => instance.generated_at = Some(now),
+                    }
+                    instance.status = SigningStatus::NoncesGenerated;
+                    generated += 1;
+                }
+                Err(error) => instance.fail("Nonce generation", error),
+            }
+        }
+
+        debug!("Generated nonces for {} protocol instances", generated);
+        generated
+    }
+
+    /// Exports the nonces of every instance with generated nonces, to be sent to the other
+    /// participants in a single message. Instances whose nonces cannot be exported are marked
+    /// as failed and left out.
+    pub fn export_nonces<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        participant: &PublicKey,
+    ) -> Vec<NonceBundle> {
+        let mut bundles = vec![];
+
+        for instance in self
+            .instances
+            .iter_mut()
+            .filter(|instance| instance.status == SigningStatus::NoncesGenerated)
+        {
+            match instance
+                .protocol
+                .export_nonces(signer, &instance.session_id(), participant)
+            {
+                Ok(bundle) => bundles.push(bundle),
+                Err(error) => instance.fail("Nonce export", error),
+            }
+        }
+
+        bundles
+    }
+
+    /// Aggregates the nonces exported by the other participants for every instance, see
+    /// `export_nonces`. Bundles are matched to the instances by session id, so bundles of expired
+    /// sessions are ignored. Instances with invalid or incomplete bundles are marked as failed.
+    /// Returns the number of instances whose nonces were aggregated.
+    pub fn import_peer_nonces<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        bundles: &[NonceBundle],
+    ) -> usize {
+        let mut aggregated = 0;
+
+        for instance in self
+            .instances
+            .iter_mut()
+            .filter(|instance| instance.status == SigningStatus::NoncesGenerated)
+        {
+            let session_id = instance.session_id();
+            let received: Vec<NonceBundle> = bundles
+                .iter()
+                .filter(|bundle| bundle.id == session_id)
+                .cloned()
+                .collect();
+            if received.is_empty() {
+                continue;
+            }
+
+            match instance
+                .protocol
+                .import_peer_nonces(signer, &session_id, &received)
+            {
+                Ok(_) => {
+                    instance.status = SigningStatus::NoncesAggregated;
+                    aggregated += 1;
+                }
+                Err(error) => instance.fail("Nonce aggregation", error),
+            }
+        }
+
+        warn_unmatched(self, bundles.iter().map(|bundle| &bundle.id));
+        debug!("Aggregated nonces for {} protocol instances", aggregated);
+        aggregated
+    }
+
+    /// Exports the partial signatures of every instance with aggregated nonces, to be sent to
+    /// the other participants in a single message. Instances whose partial signatures cannot be
+    /// exported are marked as failed and left out.
+    pub fn export_partial_signatures<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        participant: &PublicKey,
+    ) -> Vec<PartialSignatureBundle> {
+        let mut bundles = vec![];
+
+        for instance in self
+            .instances
+            .iter_mut()
+            .filter(|instance| instance.status == SigningStatus::NoncesAggregated)
+        {
+            match instance.protocol.export_partial_signatures(
+                signer,
+                &instance.session_id(),
+                participant,
+            ) {
+                Ok(bundle) => bundles.push(bundle),
+                Err(error) => instance.fail("Partial signature export", error),
+            }
+        }
+
+        bundles
+    }
+
+    /// Aggregates the partial signatures exported by the other participants for every instance,
+    /// see `export_partial_signatures`. Bundles are matched to the instances by session id.
+    /// Instances with invalid or incomplete bundles are marked as failed. Returns the number of
+    /// instances ready to be signed.
+    pub fn import_peer_partial_signatures<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        bundles: &[PartialSignatureBundle],
+    ) -> usize {
+        let mut aggregated = 0;
+
+        for instance in self
+            .instances
+            .iter_mut()
+            .filter(|instance| instance.status == SigningStatus::NoncesAggregated)
+        {
+            let session_id = instance.session_id();
+            let received: Vec<PartialSignatureBundle> = bundles
+                .iter()
+                .filter(|bundle| bundle.id == session_id)
+                .cloned()
+                .collect();
+            if received.is_empty() {
+                continue;
+            }
+
+            match instance
+                .protocol
+                .import_peer_partial_signatures(signer, &session_id, &received)
+            {
+                Ok(_) => {
+                    instance.status = SigningStatus::PartialSignaturesAggregated;
+                    aggregated += 1;
+                }
+                Err(error) => instance.fail("Partial signature aggregation", error),
+            }
+        }
+
+        warn_unmatched(self, bundles.iter().map(|bundle| &bundle.id));
+        debug!(
+            "Aggregated partial signatures for {} protocol instances",
+            aggregated
+        );
+        aggregated
+    }
+
+    /// Returns the messages to be signed with an aggregated key for all the instances holding
+    /// nonces, grouped by aggregated key across protocols.
+    pub fn messages_by_aggregated_key(
+        &self,
+    ) -> Result<HashMap<PublicKey, Vec<AggregatedMessage>>, ProtocolBuilderError> {
+        let mut groups: HashMap<PublicKey, Vec<AggregatedMessage>> = HashMap::new();
+
+        for instance in self
+            .instances
+            .iter()
+            .filter(|instance| instance.holds_nonces())
+        {
+            for (key, mut message) in
+                aggregated_messages(&instance.protocol, &instance.session_id())?
+            {
+                message.deadline =
+                    instance.deadline(&message.transaction_name, message.input_index);
+                groups.entry(key).or_default().push(message);
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Computes the signatures of every instance whose partial signatures were aggregated, or
+    /// whose nonces were generated by a signer completing the sessions on its own (e.g. a single
+    /// participant). Failing instances are marked and skipped so the other instances can
+    /// complete.
+    pub fn sign<S: Signer + ?Sized>(&mut self, signer: &S) -> usize {
+        let mut signed = 0;
+
+        for instance in self.instances.iter_mut().filter(|instance| {
+            matches!(
+                instance.status,
+                SigningStatus::NoncesGenerated | SigningStatus::PartialSignaturesAggregated
+            )
+        }) {
+            match instance.protocol.sign(signer, &instance.session_id()) {
+                Ok(_) => {
+                    instance.status = SigningStatus::Signed;
+                    signed += 1;
+                }
+                Err(error) => instance.fail("Signing", error),
+            }
+        }
+
+        debug!("Signed {} protocol instances", signed);
+        signed
+    }
+
+    /// Marks a failed instance as pending again so it is retried in the next call to
+    /// `generate_nonces`, under a new session id so the nonces of the failed attempt are never
+    /// reused.
+    pub fn retry(&mut self, id: &str) -> Result<(), ProtocolBuilderError> {
+        let index = self.existing_position(id)?;
+        self.instances[index].epoch += 1;
+        self.instances[index].status = SigningStatus::Pending;
+        Ok(())
+    }
+
+    pub fn status(&self, id: &str) -> Option<&SigningStatus> {
+        self.position(id).map(|index| &self.instances[index].status)
+    }
+
+    /// Returns the id and status of every instance in the order they were added.
+    pub fn statuses(&self) -> Vec<(String, SigningStatus)> {
+        self.instances
+            .iter()
+            .map(|instance| (instance.id.clone(), instance.status.clone()))
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.instances
+            .iter()
+            .all(|instance| instance.status == SigningStatus::Signed)
+    }
+
+    pub fn protocol(&self, id: &str) -> Option<&Protocol> {
+        self.position(id)
+            .map(|index| &self.instances[index].protocol)
+    }
+
+    /// Consumes the scheduler returning the protocol instances in the order they were added.
+    pub fn into_protocols(self) -> Vec<Protocol> {
+        self.instances
+            .into_iter()
+            .map(|instance| instance.protocol)
+            .collect()
+    }
+
+    fn insert(
+        &mut self,
+        protocol: Protocol,
+        id: &str,
+    ) -> Result<&mut SigningInstance, ProtocolBuilderError> {
+        if self.position(id).is_some() {
+            return Err(ProtocolBuilderError::ProtocolAlreadyScheduled(
+                id.to_string(),
+            ));
+        }
+
+        self.instances.push(SigningInstance {
+            protocol,
+            id: id.to_string(),
+            status: SigningStatus::Pending,
+            epoch: 0,
+            deadline: None,
+            input_deadlines: HashMap::new(),
+            generated_at: None,
+            deadline_shift: 0,
+        });
+
+        Ok(self.instances.last_mut().expect("instance was just added"))
+    }
+
+    fn position(&self, id: &str) -> Option<usize> {
+        self.instances.iter().position(|instance| instance.id == id)
+    }
+
+    fn existing_position(&self, id: &str) -> Result<usize, ProtocolBuilderError> {
+        self.position(id)
+            .ok_or(ProtocolBuilderError::MissingProtocol(id.to_string()))
+    }
+}
+
+// Logs the bundles whose session id matches no instance, e.g. bundles of expired sessions.
+fn warn_unmatched<'a>(scheduler: &SigningScheduler, ids: impl Iterator<Item = &'a String>) {
+    let sessions: BTreeSet<String> = scheduler
+        .instances
+        .iter()
+        .map(|instance| instance.session_id())
+        .collect();
+
+    for id in ids.filter(|id| !sessions.contains(*id)) {
+        warn!("Ignoring bundle of unknown signing session {}", id);
+    }
+}
+
+// Collects the messages of a built protocol that are signed with an aggregated key, along with
+// the key used to sign them.
+pub(super) fn aggregated_messages(
+    protocol: &Protocol,
+    id: &str,
+) -> Result<Vec<(PublicKey, AggregatedMessage)>, ProtocolBuilderError> {
+    let mut messages = vec![];
+
+    for transaction_name in protocol.transaction_names() {
+        for (input_index, input) in protocol.inputs(&transaction_name)?.iter().enumerate() {
+            let (internal_key, leaves) = match input.output_type() {
+                Ok(OutputType::Taproot {
+                    internal_key,
+                    leaves,
+                    ..
+                }) => (internal_key, leaves),
+                _ => continue,
+            };
+
+            let key_path_sign = match input.spend_mode() {
+                SpendMode::All { key_path_sign } | SpendMode::KeyOnly { key_path_sign } => {
+                    Some(*key_path_sign)
+                }
+                _ => None,
+            };
+
+            for (script_index, message) in input.hashed_messages().iter().enumerate() {
+                let message = match message {
+                    Some(message) => message,
+                    None => continue,
+                };
+
+                let key = if script_index < leaves.len() {
+                    let leaf = &leaves[script_index];
+                    match leaf.get_verifying_key() {
+                        Some(key) if leaf.aggregate_signing() => key,
+                        _ => continue,
+                    }
+                } else if key_path_sign == Some(SignMode::Aggregate) {
+                    *internal_key
+                } else {
+                    continue;
+                };
+
+                messages.push((
+                    key,
+                    AggregatedMessage {
+                        protocol_name: protocol.name().to_string(),
+                        id: id.to_string(),
+                        message_id: MessageId::new_string_id(
+                            &transaction_name,
+                            input_index as u32,
+                            script_index as u32,
+                        ),
+                        transaction_name: transaction_name.clone(),
+                        input_index,
+                        script_index,
+                        message: message.as_ref().to_vec(),
+                        deadline: None,
+                    },
+                ));
+            }
+        }
+    }
+
+    Ok(messages)
+}
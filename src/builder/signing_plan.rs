@@ -0,0 +1,507 @@
+use std::collections::BTreeMap;
+
+use bitcoin::{
+    secp256k1::{self, Message},
+    EcdsaSighashType, PublicKey, TapNodeHash, TapSighashType, XOnlyPublicKey,
+};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    helpers::parallel_verify::ParallelSchnorrVerifier,
+    scripts::SignMode,
+    types::{
+        input::{InputType, SighashType, Signature},
+        output::{spend_mode_params, MessageId},
+        signing_request::SignatureId,
+        OutputType, Signer,
+    },
+};
+
+use super::Protocol;
+
+impl Protocol {
+    /// Signing plans of every input of the protocol, in transaction sort order. Inputs spending
+    /// custom outputs sign on their own and have no plan.
+    pub(crate) fn signing_plans(&self) -> Result<Vec<InputSigningPlan>, ProtocolBuilderError> {
+        let mut plans = vec![];
+        for transaction_name in self.graph().sort()? {
+            for (input_index, input) in self
+                .graph()
+                .get_inputs(&transaction_name)?
+                .iter()
+                .enumerate()
+            {
+                plans.extend(InputSigningPlan::new(
+                    &transaction_name,
+                    input_index,
+                    input,
+                )?);
+            }
+        }
+        Ok(plans)
+    }
+}
+
+/// Signature to be made for an input, given by the message and the key that signs it.
+#[derive(Clone, Debug)]
+pub(crate) enum SignatureJob {
+    Schnorr {
+        message: Message,
+        key: PublicKey,
+    },
+    SchnorrWithTweak {
+        message: Message,
+        internal_key: PublicKey,
+        merkle_root: Option<TapNodeHash>,
+    },
+    // Offline signers cannot take part in MuSig2 sessions, so these are not exported
+    Aggregated {
+        aggregated_key: PublicKey,
+        message_id: String,
+    },
+    Ecdsa {
+        message: Message,
+        key: PublicKey,
+    },
+}
+
+/// Where a signature is stored once made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SignatureSlot {
+    /// Position in the signatures of the input, leaf indexes followed by the key path.
+    Path(usize),
+    /// Signature slot of a multisig leaf. Slot 0 is also stored as the leaf signature.
+    Multisig { leaf_index: usize, slot: usize },
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PlannedSignature {
+    pub slot: SignatureSlot,
+    pub job: SignatureJob,
+    pub sighash_type: SighashType,
+}
+
+/// Signatures made for the jobs of a plan.
+#[derive(Clone, Debug)]
+pub(crate) enum JobSignature {
+    Schnorr(secp256k1::schnorr::Signature),
+    SchnorrWithTweak(secp256k1::schnorr::Signature, PublicKey),
+    Ecdsa(secp256k1::ecdsa::Signature),
+}
+
+/// Schnorr signatures made while executing plans, checked against their keys in a single pass
+/// once every plan is executed, so invalid signatures are caught before any of them is stored.
+#[derive(Default)]
+pub(crate) struct SignatureChecks {
+    verifier: ParallelSchnorrVerifier,
+    /// Error reported for each pushed signature if it is invalid.
+    failures: Vec<ProtocolBuilderError>,
+}
+
+impl SignatureChecks {
+    fn push(
+        &mut self,
+        signature: secp256k1::schnorr::Signature,
+        message: Message,
+        key: PublicKey,
+        failure: ProtocolBuilderError,
+    ) {
+        self.verifier
+            .push(signature, message, XOnlyPublicKey::from(key));
+        self.failures.push(failure);
+    }
+
+    /// Verifies every pushed signature, returning the error of the first invalid one.
+    pub fn verify(self) -> Result<(), ProtocolBuilderError> {
+        let secp = secp256k1::Secp256k1::verification_only();
+        match self
+            .verifier
+            .verify(&secp)
+            .into_iter()
+            .zip(self.failures)
+            .find(|(valid, _)| !valid)
+        {
+            Some((_, failure)) => Err(failure),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Signatures an input needs, computed from its stored sighashes. This is the one place the
+/// signing rules live: `sign`, `sign_async` and the offline signing requests all execute plans.
+#[derive(Clone, Debug)]
+pub(crate) struct InputSigningPlan {
+    pub transaction_name: String,
+    pub input_index: usize,
+    pub signature_count: usize,
+    pub signatures: Vec<PlannedSignature>,
+    /// Taproot inputs also store the signatures of their multisig slots.
+    pub taproot: bool,
+}
+
+impl InputSigningPlan {
+    /// Plans the signatures of an input. Inputs spending custom outputs have no plan, as the
+    /// output computes their signatures itself.
+    pub fn new(
+        transaction_name: &str,
+        input_index: usize,
+        input: &InputType,
+    ) -> Result<Option<Self>, ProtocolBuilderError> {
+        let mut plan = InputSigningPlan {
+            transaction_name: transaction_name.to_string(),
+            input_index,
+            signature_count: input.hashed_messages().len(),
+            signatures: vec![],
+            taproot: false,
+        };
+
+        match (input.output_type()?, input.sighash_type()) {
+            (OutputType::Custom { .. }, _) => return Ok(None),
+            (output_type @ OutputType::Taproot { .. }, SighashType::Taproot(tap_sighash_type)) => {
+                plan.add_taproot(input, output_type, tap_sighash_type)?;
+            }
+            (output_type, SighashType::Ecdsa(ecdsa_sighash_type)) => {
+                plan.signature_count = 1;
+                plan.add_ecdsa(input, output_type, ecdsa_sighash_type)?;
+            }
+            (output_type, _) => {
+                return Err(ProtocolBuilderError::InvalidOutputType(
+                    "Taproot".to_string(),
+                    output_type.get_name().to_string(),
+                ));
+            }
+        }
+
+        Ok(Some(plan))
+    }
+
+    fn add_taproot(
+        &mut self,
+        input: &InputType,
+        output_type: &OutputType,
+        tap_sighash_type: &TapSighashType,
+    ) -> Result<(), ProtocolBuilderError> {
+        let OutputType::Taproot {
+            internal_key,
+            leaves,
+            ..
+        } = output_type
+        else {
+            return Ok(());
+        };
+        let leaf_count = leaves.len();
+        self.signature_count = leaf_count + 1;
+        self.taproot = true;
+        let messages = input.hashed_messages();
+        let message = |index: usize| messages.get(index).copied().flatten();
+        let (key_path, scripts_path, key_path_sign_mode, selected_leaves) =
+            spend_mode_params(leaves, input.spend_mode())?;
+
+        if key_path {
+            let job = match key_path_sign_mode {
+                Some(SignMode::Aggregate) => Some(SignatureJob::Aggregated {
+                    aggregated_key: *internal_key,
+                    message_id: self.message_id(leaf_count),
+                }),
+                _ => message(leaf_count).map(|message| SignatureJob::SchnorrWithTweak {
+                    message,
+                    internal_key: *internal_key,
+                    merkle_root: output_type
+                        .get_taproot_spend_info()
+                        .ok()
+                        .flatten()
+                        .and_then(|spend_info| spend_info.merkle_root()),
+                }),
+            };
+            if let Some(job) = job {
+                self.signatures.push(PlannedSignature {
+                    slot: SignatureSlot::Path(leaf_count),
+                    job,
+                    sighash_type: SighashType::Taproot(*tap_sighash_type),
+                });
+            }
+        }
+
+        if !scripts_path {
+            return Ok(());
+        }
+
+        for (leaf_index, leaf) in selected_leaves.unwrap_or_default() {
+            if leaf.skip_signing() {
+                continue;
+            }
+            let sighash_type = SighashType::Taproot(leaf.sighash_type_or(*tap_sighash_type));
+
+            if leaf.aggregate_signing() {
+                if let Some(aggregated_key) = leaf.get_verifying_key() {
+                    self.signatures.push(PlannedSignature {
+                        slot: SignatureSlot::Path(leaf_index),
+                        job: SignatureJob::Aggregated {
+                            aggregated_key,
+                            message_id: self.message_id(leaf_index),
+                        },
+                        sighash_type,
+                    });
+                }
+                continue;
+            }
+
+            let Some(message) = message(leaf_index) else {
+                continue;
+            };
+            if leaf.is_multisig() {
+                for (slot, key) in leaf.get_signers().iter().enumerate() {
+                    self.signatures.push(PlannedSignature {
+                        slot: SignatureSlot::Multisig { leaf_index, slot },
+                        job: SignatureJob::Schnorr { message, key: *key },
+                        sighash_type: sighash_type.clone(),
+                    });
+                }
+            } else if let Some(key) = leaf.get_verifying_key() {
+                self.signatures.push(PlannedSignature {
+                    slot: SignatureSlot::Path(leaf_index),
+                    job: SignatureJob::Schnorr { message, key },
+                    sighash_type,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_ecdsa(
+        &mut self,
+        input: &InputType,
+        output_type: &OutputType,
+        ecdsa_sighash_type: &EcdsaSighashType,
+    ) -> Result<(), ProtocolBuilderError> {
+        if input.spend_mode().is_none() {
+            return Ok(());
+        }
+
+        let key = match output_type {
+            OutputType::SegwitPublicKey { public_key, .. } => Some(*public_key),
+            OutputType::SegwitScript { script, .. } if !script.skip_signing() => {
+                script.get_verifying_key()
+            }
+            OutputType::SegwitScript { .. }
+            | OutputType::SegwitUnspendable { .. }
+            | OutputType::PayToAnchor { .. } => None,
+            _ => {
+                return Err(ProtocolBuilderError::InvalidOutputType(
+                    "Segwit".to_string(),
+                    output_type.get_name().to_string(),
+                ));
+            }
+        };
+
+        if let (Some(key), Some(Some(message))) = (key, input.hashed_messages().first()) {
+            self.signatures.push(PlannedSignature {
+                slot: SignatureSlot::Path(0),
+                job: SignatureJob::Ecdsa {
+                    message: *message,
+                    key,
+                },
+                sighash_type: SighashType::Ecdsa(*ecdsa_sighash_type),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn message_id(&self, script_index: usize) -> String {
+        MessageId::new_string_id(
+            &self.transaction_name,
+            self.input_index as u32,
+            script_index as u32,
+        )
+    }
+
+    /// Identifies the signature planned for `slot`.
+    pub fn signature_id(&self, slot: SignatureSlot) -> SignatureId {
+        let (leaf, slot) = match slot {
+            SignatureSlot::Path(index) if !self.taproot || index + 1 == self.signature_count => {
+                (None, None)
+            }
+            SignatureSlot::Path(index) => (Some(index), None),
+            SignatureSlot::Multisig { leaf_index, slot } => (Some(leaf_index), Some(slot)),
+        };
+        SignatureId {
+            transaction_name: self.transaction_name.clone(),
+            input_index: self.input_index,
+            leaf,
+            slot,
+        }
+    }
+
+    /// Executes the jobs of the plan with `signer`, one after the other, and assembles the
+    /// results. Multisig slots are only signed for the keys the signer holds.
+    #[allow(clippy::type_complexity)]
+    pub fn sign<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+        id: &str,
+        checks: &mut SignatureChecks,
+    ) -> Result<
+        (
+            Vec<Option<Signature>>,
+            BTreeMap<usize, Vec<Option<Signature>>>,
+        ),
+        ProtocolBuilderError,
+    > {
+        let results = self
+            .signatures
+            .iter()
+            .map(|planned| {
+                if let (SignatureSlot::Multisig { .. }, SignatureJob::Schnorr { key, .. }) =
+                    (planned.slot, &planned.job)
+                {
+                    if !signer.holds_key(key)? {
+                        return Ok(None);
+                    }
+                }
+
+                match &planned.job {
+                    SignatureJob::Schnorr { message, key } => {
+                        signer.sign_schnorr(message, key).map(JobSignature::Schnorr)
+                    }
+                    SignatureJob::SchnorrWithTweak {
+                        message,
+                        internal_key,
+                        merkle_root,
+                    } => signer
+                        .sign_schnorr_with_tweak(message, internal_key, *merkle_root)
+                        .map(|(signature, output_key)| {
+                            JobSignature::SchnorrWithTweak(signature, output_key)
+                        }),
+                    SignatureJob::Aggregated {
+                        aggregated_key,
+                        message_id,
+                    } => signer
+                        .get_aggregated_signature(aggregated_key, id, message_id)
+                        .map(JobSignature::Schnorr),
+                    SignatureJob::Ecdsa { message, key } => {
+                        signer.sign_ecdsa(message, key).map(JobSignature::Ecdsa)
+                    }
+                }
+                .map(Some)
+            })
+            .collect();
+
+        self.assemble(results, checks)
+    }
+
+    /// Builds the signatures of the input from the results of its jobs, given in plan order.
+    /// Multisig slots whose key is not held by the signer have no result and are left empty for
+    /// the other parties. Signing errors are returned, and Schnorr signatures are pushed to
+    /// `checks` to be verified against their keys.
+    #[allow(clippy::type_complexity)]
+    pub fn assemble(
+        &self,
+        results: Vec<Result<Option<JobSignature>, ProtocolBuilderError>>,
+        checks: &mut SignatureChecks,
+    ) -> Result<
+        (
+            Vec<Option<Signature>>,
+            BTreeMap<usize, Vec<Option<Signature>>>,
+        ),
+        ProtocolBuilderError,
+    > {
+        let mut signatures = vec![None; self.signature_count];
+        let mut multisig_signatures: BTreeMap<usize, Vec<Option<Signature>>> = BTreeMap::new();
+
+        for (planned, result) in self.signatures.iter().zip(results) {
+            let signature = result?.map(|signature| {
+                match (&planned.job, &signature) {
+                    (SignatureJob::Schnorr { message, key }, JobSignature::Schnorr(schnorr)) => {
+                        checks.push(
+                            *schnorr,
+                            *message,
+                            *key,
+                            self.generation_failed(planned.slot),
+                        )
+                    }
+                    (
+                        SignatureJob::SchnorrWithTweak { message, .. },
+                        JobSignature::SchnorrWithTweak(schnorr, output_key),
+                    ) => checks.push(
+                        *schnorr,
+                        *message,
+                        *output_key,
+                        self.generation_failed(planned.slot),
+                    ),
+                    _ => {}
+                }
+                planned.signature(signature)
+            });
+
+            match planned.slot {
+                SignatureSlot::Path(index) => {
+                    if signature.is_none() {
+                        return Err(self.generation_failed(planned.slot));
+                    }
+                    signatures[index] = signature;
+                }
+                SignatureSlot::Multisig { leaf_index, slot } => {
+                    let slots = multisig_signatures.entry(leaf_index).or_default();
+                    if slots.len() <= slot {
+                        slots.resize(slot + 1, None);
+                    }
+                    if slot == 0 {
+                        signatures[leaf_index] = signature.clone();
+                    }
+                    slots[slot] = signature;
+                }
+            }
+        }
+
+        Ok((signatures, multisig_signatures))
+    }
+
+    fn generation_failed(&self, slot: SignatureSlot) -> ProtocolBuilderError {
+        match slot {
+            SignatureSlot::Multisig { leaf_index, slot } => {
+                ProtocolBuilderError::InvalidMultisigSlotSignature(leaf_index, slot)
+            }
+            SignatureSlot::Path(index)
+                if index + 1 == self.signature_count && self.signature_count > 1 =>
+            {
+                ProtocolBuilderError::KeySpendSignatureGenerationFailed(
+                    self.transaction_name.clone(),
+                    self.input_index,
+                )
+            }
+            SignatureSlot::Path(index) => {
+                ProtocolBuilderError::ScriptSpendSignatureGenerationFailed(
+                    self.transaction_name.clone(),
+                    self.input_index,
+                    index,
+                )
+            }
+        }
+    }
+}
+
+impl PlannedSignature {
+    fn signature(&self, signature: JobSignature) -> Signature {
+        match (signature, &self.sighash_type) {
+            (
+                JobSignature::Schnorr(signature) | JobSignature::SchnorrWithTweak(signature, _),
+                SighashType::Taproot(sighash_type),
+            ) => Signature::Taproot(bitcoin::taproot::Signature {
+                signature,
+                sighash_type: *sighash_type,
+            }),
+            (JobSignature::Ecdsa(signature), SighashType::Ecdsa(sighash_type)) => {
+                Signature::Ecdsa(bitcoin::ecdsa::Signature {
+                    signature,
+                    sighash_type: *sighash_type,
+                })
+            }
+            // Jobs are planned with the sighash type of their kind
+            (signature, sighash_type) => {
+                unreachable!("{:?} signed as {:?}", signature, sighash_type)
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::{collections::HashMap, rc::Rc, str::FromStr};
+
+use bitcoin::{EcdsaSighashType, PublicKey, ScriptBuf, TapSighashType};
+use key_manager::{key_manager::KeyManager, key_type::BitcoinKeyType};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    scripts::{self, ProtocolScript},
+    types::{
+        connection::{InputSpec, OutputSpec},
+        input::SighashType,
+        spec::{ProtocolSpec, SpecKey, SpecKeyType, SpecLeaf, SpecOutput},
+        OutputType,
+    },
+};
+
+use super::Protocol;
+
+impl Protocol {
+    /// Creates a protocol from a declarative spec, adding its connections and then its unspent
+    /// outputs in the order they are declared. The protocol is not built.
+    pub fn from_spec(
+        name: &str,
+        spec: &ProtocolSpec,
+        key_manager: &Rc<KeyManager>,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let keys = spec
+            .keys
+            .iter()
+            .map(|key| Ok((key.name.clone(), spec_key(key, key_manager)?)))
+            .collect::<Result<HashMap<_, _>, ProtocolBuilderError>>()?;
+
+        let mut protocol = Protocol::new(name);
+        for connection in spec.connections.iter() {
+            let (output, spends_taproot) = match &connection.output {
+                // Outputs that already exist are looked up to pick the sighash type
+                SpecOutput::Index { index } => (
+                    OutputSpec::Index(*index),
+                    protocol.spends_taproot(&connection.from, *index),
+                ),
+                SpecOutput::Label { label } => (
+                    OutputSpec::Label(label.clone()),
+                    protocol
+                        .output_index(&connection.from, label)
+                        .is_ok_and(|index| protocol.spends_taproot(&connection.from, index)),
+                ),
+                output => (
+                    output_spec(output, &keys)?,
+                    matches!(output, SpecOutput::Taproot { .. }),
+                ),
+            };
+
+            protocol.add_connection(
+                &connection.name,
+                &connection.from,
+                output,
+                &connection.to,
+                InputSpec::Auto(
+                    sighash_type(&connection.sighash_type, spends_taproot)?,
+                    connection.spend_mode.clone(),
+                ),
+                connection.timelock,
+                connection.txid,
+            )?;
+        }
+
+        for output in spec.outputs.iter() {
+            let (output_type, label) = match output_spec(&output.output, &keys)? {
+                OutputSpec::Auto(output_type) => (output_type, None),
+                OutputSpec::Labeled(label, output_type) => (output_type, Some(label)),
+                _ => {
+                    return Err(ProtocolBuilderError::InvalidSpec(format!(
+                        "unspent output of {} must be a new output",
+                        output.transaction
+                    )))
+                }
+            };
+
+            protocol.add_transaction_output(&output.transaction, &output_type)?;
+            if let Some(label) = label {
+                let index = protocol.get_output_count(&output.transaction)? as usize - 1;
+                protocol.label_output(&output.transaction, index, &label)?;
+            }
+        }
+
+        Ok(protocol)
+    }
+
+    fn spends_taproot(&self, transaction_name: &str, output_index: usize) -> bool {
+        self.get_script_from_output(transaction_name, output_index as u32)
+            .is_ok()
+    }
+}
+
+fn spec_key(key: &SpecKey, key_manager: &KeyManager) -> Result<PublicKey, ProtocolBuilderError> {
+    match (&key.public_key, key.index) {
+        (Some(public_key), _) => PublicKey::from_str(public_key).map_err(|error| {
+            ProtocolBuilderError::InvalidSpec(format!("key {}: {}", key.name, error))
+        }),
+        (None, Some(index)) => {
+            let key_type = match key.key_type {
+                SpecKeyType::P2wpkh => BitcoinKeyType::P2wpkh,
+                SpecKeyType::P2tr => BitcoinKeyType::P2tr,
+            };
+            Ok(key_manager.derive_keypair(key_type, index)?)
+        }
+        (None, None) => Err(ProtocolBuilderError::InvalidSpec(format!(
+            "key {} needs an index or a public key",
+            key.name
+        ))),
+    }
+}
+
+fn output_spec(
+    output: &SpecOutput,
+    keys: &HashMap<String, PublicKey>,
+) -> Result<OutputSpec, ProtocolBuilderError> {
+    let (output_type, label) = match output {
+        SpecOutput::Taproot {
+            value,
+            internal_key,
+            leaves,
+            label,
+        } => {
+            let leaves = leaves
+                .iter()
+                .map(|leaf| spec_leaf(leaf, keys))
+                .collect::<Result<Vec<_>, _>>()?;
+            (
+                OutputType::taproot(*value, key(keys, internal_key)?, &leaves)?,
+                label,
+            )
+        }
+        SpecOutput::SegwitKey {
+            value,
+            key: name,
+            label,
+        } => (OutputType::segwit_key(*value, key(keys, name)?)?, label),
+        SpecOutput::SegwitScript { value, leaf, label } => (
+            OutputType::segwit_script(*value, &spec_leaf(leaf, keys)?)?,
+            label,
+        ),
+        SpecOutput::OpReturn { data } => (
+            OutputType::segwit_unspendable(scripts::op_return(hex_bytes(data)?))?,
+            &None,
+        ),
+        SpecOutput::Index { index } => return Ok(OutputSpec::Index(*index)),
+        SpecOutput::Label { label } => return Ok(OutputSpec::Label(label.clone())),
+    };
+
+    Ok(match label {
+        Some(label) => OutputSpec::Labeled(label.clone(), output_type),
+        None => OutputSpec::Auto(output_type),
+    })
+}
+
+fn spec_leaf(
+    leaf: &SpecLeaf,
+    keys: &HashMap<String, PublicKey>,
+) -> Result<ProtocolScript, ProtocolBuilderError> {
+    Ok(match leaf {
+        SpecLeaf::CheckSignature {
+            key: name,
+            sign_mode,
+        } => scripts::check_signature(key(keys, name)?, *sign_mode),
+        SpecLeaf::Timelock {
+            blocks,
+            key: name,
+            sign_mode,
+        } => scripts::timelock(*blocks, key(keys, name)?, *sign_mode),
+        SpecLeaf::TimelockAbsolute {
+            height,
+            key: name,
+            sign_mode,
+        } => scripts::timelock_absolute(*height, key(keys, name)?, *sign_mode),
+        SpecLeaf::RevealSecret {
+            hashed_secret,
+            key: name,
+            sign_mode,
+        } => scripts::reveal_secret(hex_bytes(hashed_secret)?, key(keys, name)?, *sign_mode),
+        SpecLeaf::Raw {
+            script,
+            key: name,
+            sign_mode,
+        } => ProtocolScript::new(
+            ScriptBuf::from(hex_bytes(script)?),
+            key(keys, name)?,
+            *sign_mode,
+        ),
+    })
+}
+
+fn key<'a>(
+    keys: &'a HashMap<String, PublicKey>,
+    name: &str,
+) -> Result<&'a PublicKey, ProtocolBuilderError> {
+    keys.get(name)
+        .ok_or(ProtocolBuilderError::InvalidSpec(format!(
+            "unknown key {}",
+            name
+        )))
+}
+
+fn hex_bytes(data: &str) -> Result<Vec<u8>, ProtocolBuilderError> {
+    hex::decode(data).map_err(|error| ProtocolBuilderError::InvalidSpec(error.to_string()))
+}
+
+fn sighash_type(name: &str, taproot: bool) -> Result<SighashType, ProtocolBuilderError> {
+    let invalid = |_| ProtocolBuilderError::InvalidSpec(format!("invalid sighash type {}", name));
+
+    Ok(if taproot {
+        SighashType::Taproot(TapSighashType::from_str(name).map_err(invalid)?)
+    } else {
+        SighashType::Ecdsa(EcdsaSighashType::from_str(name).map_err(invalid)?)
+    })
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+
+use bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    scripts::ProtocolScript,
+    types::{
+        connection::{InputSpec, OutputSpec},
+        input::{SighashType, SpendMode},
+        OutputType,
+    },
+};
+
+use super::Protocol;
+
+/// A concrete value or the name of a parameter resolved when the template is instantiated.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Param<T> {
+    Value(T),
+    Placeholder(String),
+}
+
+impl<T: Clone> Param<T> {
+    pub fn placeholder(name: &str) -> Self {
+        Param::Placeholder(name.to_string())
+    }
+
+    fn resolve(&self, values: &HashMap<String, T>) -> Result<T, ProtocolBuilderError> {
+        match self {
+            Param::Value(value) => Ok(value.clone()),
+            Param::Placeholder(name) => values
+                .get(name)
+                .cloned()
+                .ok_or(ProtocolBuilderError::MissingTemplateParameter(name.clone())),
+        }
+    }
+}
+
+impl<T> From<T> for Param<T> {
+    fn from(value: T) -> Self {
+        Param::Value(value)
+    }
+}
+
+/// Values used to instantiate a `ProtocolTemplate`.
+#[derive(Clone, Debug, Default)]
+pub struct TemplateParams {
+    names: HashMap<String, String>,
+    keys: HashMap<String, PublicKey>,
+    amounts: HashMap<String, u64>,
+    scripts: HashMap<String, ProtocolScript>,
+}
+
+impl TemplateParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Value substituted for `{name}` in transaction names.
+    pub fn with_name(mut self, name: &str, value: &str) -> Self {
+        self.names.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn with_key(mut self, name: &str, key: &PublicKey) -> Self {
+        self.keys.insert(name.to_string(), *key);
+        self
+    }
+
+    pub fn with_amount(mut self, name: &str, amount: u64) -> Self {
+        self.amounts.insert(name.to_string(), amount);
+        self
+    }
+
+    pub fn with_script(mut self, name: &str, script: &ProtocolScript) -> Self {
+        self.scripts.insert(name.to_string(), script.clone());
+        self
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TemplateOutput {
+    Taproot {
+        value: Param<u64>,
+        internal_key: Param<PublicKey>,
+        leaves: Vec<Param<ProtocolScript>>,
+    },
+    SegwitPublicKey {
+        value: Param<u64>,
+        public_key: Param<PublicKey>,
+    },
+    SegwitScript {
+        value: Param<u64>,
+        script: Param<ProtocolScript>,
+    },
+}
+
+impl TemplateOutput {
+    fn resolve(&self, params: &TemplateParams) -> Result<OutputType, ProtocolBuilderError> {
+        match self {
+            TemplateOutput::Taproot {
+                value,
+                internal_key,
+                leaves,
+            } => {
+                let leaves = leaves
+                    .iter()
+                    .map(|leaf| leaf.resolve(&params.scripts))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                OutputType::taproot(
+                    value.resolve(&params.amounts)?,
+                    &internal_key.resolve(&params.keys)?,
+                    &leaves,
+                )
+            }
+            TemplateOutput::SegwitPublicKey { value, public_key } => OutputType::segwit_key(
+                value.resolve(&params.amounts)?,
+                &public_key.resolve(&params.keys)?,
+            ),
+            TemplateOutput::SegwitScript { value, script } => OutputType::segwit_script(
+                value.resolve(&params.amounts)?,
+                &script.resolve(&params.scripts)?,
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum TemplateStep {
+    Output {
+        transaction: String,
+        output: TemplateOutput,
+    },
+    Connection {
+        name: String,
+        from: String,
+        output: TemplateOutput,
+        to: String,
+        sighash_type: SighashType,
+        spend_mode: SpendMode,
+        timelock: Option<u16>,
+    },
+}
+
+/// Protocol definition where transaction names, keys, amounts and leaf scripts can be parameters,
+/// so a pattern (e.g. challenge/response) is defined once and instantiated many times.
+/// Transaction names are strings where `{param}` is replaced by the named parameter.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProtocolTemplate {
+    name: String,
+    steps: Vec<TemplateStep>,
+}
+
+impl ProtocolTemplate {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            steps: vec![],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn add_output(&mut self, transaction: &str, output: TemplateOutput) -> &mut Self {
+        self.steps.push(TemplateStep::Output {
+            transaction: transaction.to_string(),
+            output,
+        });
+        self
+    }
+
+    /// Adds a connection creating a new output in `from` spent by a new input in `to`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_connection(
+        &mut self,
+        name: &str,
+        from: &str,
+        output: TemplateOutput,
+        to: &str,
+        sighash_type: &SighashType,
+        spend_mode: &SpendMode,
+        timelock: Option<u16>,
+    ) -> &mut Self {
+        self.steps.push(TemplateStep::Connection {
+            name: name.to_string(),
+            from: from.to_string(),
+            output,
+            to: to.to_string(),
+            sighash_type: sighash_type.clone(),
+            spend_mode: spend_mode.clone(),
+            timelock,
+        });
+        self
+    }
+
+    /// Creates a concrete protocol replacing every parameter with the given values.
+    pub fn instantiate(
+        &self,
+        protocol_name: &str,
+        params: &TemplateParams,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new(protocol_name);
+
+        for step in self.steps.iter() {
+            match step {
+                TemplateStep::Output {
+                    transaction,
+                    output,
+                } => {
+                    protocol.add_transaction_output(
+                        &substitute(transaction, &params.names)?,
+                        &output.resolve(params)?,
+                    )?;
+                }
+                TemplateStep::Connection {
+                    name,
+                    from,
+                    output,
+                    to,
+                    sighash_type,
+                    spend_mode,
+                    timelock,
+                } => {
+                    protocol.add_connection(
+                        &substitute(name, &params.names)?,
+                        &substitute(from, &params.names)?,
+                        OutputSpec::Auto(output.resolve(params)?),
+                        &substitute(to, &params.names)?,
+                        InputSpec::Auto(sighash_type.clone(), spend_mode.clone()),
+                        *timelock,
+                        None,
+                    )?;
+                }
+            }
+        }
+
+        Ok(protocol)
+    }
+}
+
+// Replaces every `{param}` in the template with the value of the parameter.
+fn substitute(
+    template: &str,
+    values: &HashMap<String, String>,
+) -> Result<String, ProtocolBuilderError> {
+    let mut result = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or(ProtocolBuilderError::InvalidTemplateParameter(
+                template.to_string(),
+                "unclosed placeholder".to_string(),
+            ))?
+            + start;
+
+        let name = &rest[start + 1..end];
+        let value = values
+            .get(name)
+            .ok_or(ProtocolBuilderError::MissingTemplateParameter(
+                name.to_string(),
+            ))?;
+
+        result.push_str(&rest[..start]);
+        result.push_str(value);
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
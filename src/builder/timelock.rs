@@ -0,0 +1,52 @@
+use bitcoin::absolute::LockTime;
+
+use crate::{broadcast::ChainState, errors::ProtocolBuilderError};
+
+use super::{trace::relative_blocks, Protocol};
+
+impl Protocol {
+    /// Whether the timelocks of a transaction allow it in the next block: its absolute locktime
+    /// is checked against the tip height or median time past, and the relative timelocks of its
+    /// inputs against the confirmations of the protocol transactions they spend. Time based
+    /// relative locks are approximated with 10 minute blocks. Inputs without a relative lock do
+    /// not require the spent transaction to be mined.
+    pub fn ready_to_send(
+        &self,
+        transaction_name: &str,
+        chain: &dyn ChainState,
+    ) -> Result<bool, ProtocolBuilderError> {
+        let transaction = self.transaction_by_name(transaction_name)?;
+
+        if transaction.is_lock_time_enabled() {
+            let ready = match transaction.lock_time {
+                LockTime::Blocks(height) => height.to_consensus_u32() <= chain.current_height()?,
+                LockTime::Seconds(time) => time.to_consensus_u32() < chain.median_time()?,
+            };
+            if !ready {
+                return Ok(false);
+            }
+        }
+
+        for stored in self.graph().stored_connections() {
+            if stored.to != transaction_name {
+                continue;
+            }
+
+            let sequence = transaction.input[stored.connection.input_index as usize].sequence;
+            let blocks = relative_blocks(sequence);
+            if blocks == 0 {
+                continue;
+            }
+
+            let spent = match self.external_transaction(&stored.from) {
+                Some(external) => external.txid(),
+                None => self.transaction_by_name(&stored.from)?.compute_txid(),
+            };
+            if chain.confirmations(&spent)?.unwrap_or_default() < blocks {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
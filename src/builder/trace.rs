@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use bitcoin::{relative, Sequence, Transaction};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    types::{
+        input::{InputArgs, SpendMode},
+        output::OutputType,
+        trace::{ExecutionTrace, TraceStep, UNASSIGNED_PARTY},
+    },
+};
+
+use super::Protocol;
+
+impl Protocol {
+    /// Builds the trace of a chosen branch, given as the names of its transactions in broadcast
+    /// order. Each transaction is placed at the earliest block allowed by the relative timelocks
+    /// of the inputs spending previous transactions of the branch. Leaves are taken from the
+    /// arguments used to send each transaction, or from the spend mode of its inputs when it
+    /// selects a single leaf. Fails if the branch mixes alternative branches.
+    pub fn execution_trace(
+        &self,
+        branch: &[String],
+        parties: &HashMap<String, String>,
+        args: &HashMap<String, Vec<InputArgs>>,
+    ) -> Result<ExecutionTrace, ProtocolBuilderError> {
+        self.check_branch(branch)?;
+
+        let connections = self.graph().stored_connections();
+        let mut blocks: HashMap<&str, u32> = HashMap::new();
+        let mut trace = ExecutionTrace::default();
+
+        for name in branch {
+            let transaction = self.transaction_by_name(name)?;
+
+            let mut block = 0;
+            for stored in connections.iter().filter(|stored| stored.to == *name) {
+                if let Some(previous_block) = blocks.get(stored.from.as_str()) {
+                    let sequence =
+                        transaction.input[stored.connection.input_index as usize].sequence;
+                    block = block.max(previous_block + relative_blocks(sequence));
+                }
+            }
+            blocks.insert(name, block);
+
+            let inputs = self.inputs(name)?;
+            let leaves = match args.get(name) {
+                Some(args) => args
+                    .iter()
+                    .zip(inputs.iter())
+                    .enumerate()
+                    .filter_map(|(input_index, (args, input))| {
+                        let output_type = input.output_type().ok()?;
+                        let leaf = args.leaf_index(output_type).ok()??;
+                        Some((input_index, leaf))
+                    })
+                    .collect(),
+                None => inputs
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(input_index, input)| match input.spend_mode() {
+                        SpendMode::Script { leaf } => Some((input_index, *leaf)),
+                        SpendMode::ScriptByName(leaf_name) => input
+                            .output_type()
+                            .ok()?
+                            .leaf_index_by_name(leaf_name)
+                            .map(|leaf| (input_index, leaf)),
+                        SpendMode::Scripts { leaves } if leaves.len() == 1 => {
+                            Some((input_index, leaves[0]))
+                        }
+                        _ => None,
+                    })
+                    .collect(),
+            };
+
+            trace.steps.push(TraceStep {
+                transaction_name: name.clone(),
+                party: party(parties, name),
+                block,
+                leaves,
+            });
+        }
+
+        Ok(trace)
+    }
+
+    /// Builds the trace of an execution observed on chain, from the mined protocol transactions
+    /// and their block heights. Leaves are identified from the tapscript revealed in each witness.
+    pub fn recorded_trace(
+        &self,
+        transactions: &[(Transaction, u32)],
+        parties: &HashMap<String, String>,
+    ) -> Result<ExecutionTrace, ProtocolBuilderError> {
+        let first_height = transactions
+            .iter()
+            .map(|(_, height)| *height)
+            .min()
+            .unwrap_or_default();
+
+        let mut mined: Vec<&(Transaction, u32)> = transactions.iter().collect();
+        mined.sort_by_key(|(_, height)| *height);
+
+        let mut trace = ExecutionTrace::default();
+        for (transaction, height) in mined {
+            let name = self.transaction_name_by_id(transaction.compute_txid())?;
+
+            let mut leaves = vec![];
+            for (input_index, (input, txin)) in self
+                .inputs(name)?
+                .iter()
+                .zip(transaction.input.iter())
+                .enumerate()
+            {
+                let (
+                    Ok(OutputType::Taproot {
+                        leaves: scripts, ..
+                    }),
+                    Some(leaf_script),
+                ) = (input.output_type(), txin.witness.taproot_leaf_script())
+                else {
+                    continue;
+                };
+
+                if let Some(leaf) = scripts
+                    .iter()
+                    .position(|script| script.get_script().as_script() == leaf_script.script)
+                {
+                    leaves.push((input_index, leaf));
+                }
+            }
+
+            trace.steps.push(TraceStep {
+                transaction_name: name.clone(),
+                party: party(parties, name),
+                block: height - first_height,
+                leaves,
+            });
+        }
+
+        Ok(trace)
+    }
+}
+
+fn party(parties: &HashMap<String, String>, transaction_name: &str) -> String {
+    parties
+        .get(transaction_name)
+        .cloned()
+        .unwrap_or(UNASSIGNED_PARTY.to_string())
+}
+
+// Blocks the spent output must be buried before the input is valid. Time based locks are
+// approximated with 10 minute blocks.
+pub(super) fn relative_blocks(sequence: Sequence) -> u32 {
+    match sequence.to_relative_lock_time() {
+        Some(relative::LockTime::Blocks(height)) => height.value() as u32,
+        Some(relative::LockTime::Time(time)) => (time.value() as u32 * 512).div_ceil(600),
+        None => 0,
+    }
+}
@@ -0,0 +1,534 @@
+use std::collections::BTreeSet;
+
+use bitcoin::{
+    opcodes::all as opcodes, script::Instruction, taproot::LeafVersion, transaction::Version,
+    Script,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{GraphError, ProtocolBuilderError},
+    graph::{
+        estimate::{estimate_input_witness_bytes, stripped_size_bytes},
+        package::requires_confirmation,
+    },
+    scripts::ProtocolScript,
+    types::output::OutputType,
+};
+
+use super::Protocol;
+
+/// Maximum weight of a standard transaction.
+pub const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+
+/// Maximum sigops cost of a standard transaction.
+pub const MAX_STANDARD_TX_SIGOPS_COST: usize = 80_000;
+
+/// Maximum number of witness stack items (excluding the witness script) of a standard P2WSH
+/// input.
+pub const MAX_STANDARD_P2WSH_STACK_ITEMS: usize = 100;
+
+/// Maximum size of a witness stack item of a standard P2WSH input.
+pub const MAX_STANDARD_P2WSH_STACK_ITEM_SIZE: usize = 80;
+
+/// Maximum size of a standard P2WSH witness script.
+pub const MAX_STANDARD_P2WSH_SCRIPT_SIZE: usize = 3_600;
+
+/// Maximum size of a segwit v0 witness script (consensus).
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// Maximum number of elements in the initial stack of a tapscript (consensus).
+pub const MAX_STACK_SIZE: usize = 1_000;
+
+/// Maximum size of a stack element (consensus).
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+
+/// Maximum depth of a leaf in a taproot script tree (consensus).
+pub const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+
+/// Maximum weight of a TRUC (version 3) transaction (BIP 431).
+pub const TRUC_MAX_WEIGHT: usize = 40_000;
+
+/// Maximum weight of a TRUC transaction spending an unconfirmed TRUC transaction (BIP 431).
+pub const TRUC_CHILD_MAX_WEIGHT: usize = 4_000;
+
+/// Tapscript sigops budget is 50 plus the serialized size of the witness, each signature
+/// operation consumes 50 (consensus).
+const TAPSCRIPT_SIGOPS_WEIGHT: usize = 50;
+
+/// Standardness or consensus rule a transaction of the protocol would break. Input issues refer
+/// to the leaf of the spent output when it is a taproot output.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ValidationIssue {
+    WitnessItemCount {
+        input_index: usize,
+        leaf_index: Option<usize>,
+        count: usize,
+        limit: usize,
+    },
+    WitnessItemSize {
+        input_index: usize,
+        leaf_index: Option<usize>,
+        size: usize,
+        limit: usize,
+    },
+    ScriptSize {
+        input_index: usize,
+        leaf_index: Option<usize>,
+        size: usize,
+        limit: usize,
+    },
+    TaprootTreeDepth {
+        output_index: usize,
+        depth: usize,
+        limit: usize,
+    },
+    SigopsBudget {
+        input_index: usize,
+        leaf_index: usize,
+        sigops: usize,
+        budget: usize,
+    },
+    TransactionSigops {
+        cost: usize,
+        limit: usize,
+    },
+    DustOutput {
+        output_index: usize,
+        value: u64,
+        dust_limit: u64,
+    },
+    TransactionWeight {
+        weight: usize,
+        limit: usize,
+    },
+    /// Version 1 transactions do not enforce BIP 68, so the relative timelock of the input is
+    /// ignored.
+    RelativeTimelockIgnored {
+        input_index: usize,
+    },
+    /// TRUC transaction heavier than allowed, with a lower limit for children of TRUC
+    /// transactions.
+    TrucWeight {
+        weight: usize,
+        limit: usize,
+    },
+    /// TRUC and non-TRUC transactions can only be unconfirmed together when neither spends the
+    /// other.
+    TrucVersionMismatch {
+        parent: String,
+    },
+    /// A TRUC transaction can only have one unconfirmed ancestor.
+    TrucAncestors {
+        ancestors: Vec<String>,
+    },
+    /// A TRUC transaction can only have one unconfirmed child. Lists the children that do not
+    /// conflict with each other, so more than one of them can be unconfirmed at once.
+    TrucDescendants {
+        children: Vec<String>,
+    },
+}
+
+// Connection between protocol transactions whose input has no relative timelock, so both can be
+// unconfirmed at the same time.
+struct UnconfirmedLink {
+    from: String,
+    to: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TransactionDiagnostics {
+    pub transaction_name: String,
+    pub issues: Vec<ValidationIssue>,
+}
+
+/// Result of `Protocol::validate`, with the transactions that have at least one issue.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ValidationReport {
+    pub transactions: Vec<TransactionDiagnostics>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    pub fn issues(&self, transaction_name: &str) -> &[ValidationIssue] {
+        self.transactions
+            .iter()
+            .find(|diagnostics| diagnostics.transaction_name == transaction_name)
+            .map(|diagnostics| diagnostics.issues.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+impl Protocol {
+    /// Checks every transaction of the protocol against the standardness and consensus limits
+    /// that would make it be rejected when broadcasted: witness item count and size, script
+    /// size, taproot tree depth, sigops, dust outputs (using the dust limits of the protocol dust
+    /// policy, or the default ones), transaction weight, the topology of TRUC transactions and
+    /// relative timelocks of version 1 transactions. Witnesses are estimated from the stack
+    /// items declared in each leaf, for every leaf that can be spent.
+    pub fn validate(&self) -> Result<ValidationReport, ProtocolBuilderError> {
+        let mut report = ValidationReport::default();
+        let links = self.unconfirmed_links()?;
+
+        for transaction_name in self.graph().sort()? {
+            if self.is_external_transaction(&transaction_name) {
+                continue;
+            }
+
+            let issues = self.validate_transaction(&transaction_name, &links)?;
+            if !issues.is_empty() {
+                report.transactions.push(TransactionDiagnostics {
+                    transaction_name,
+                    issues,
+                });
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn validate_transaction(
+        &self,
+        transaction_name: &str,
+        links: &[UnconfirmedLink],
+    ) -> Result<Vec<ValidationIssue>, ProtocolBuilderError> {
+        let transaction = self.transaction_by_name(transaction_name)?;
+        let inputs = self.inputs(transaction_name)?;
+        let mut issues = vec![];
+        let mut sigops_cost = 0;
+        let mut witness_bytes = 0;
+        let dust_policy = self.dust_policy().cloned().unwrap_or_default();
+
+        for (input_index, input) in inputs.iter().enumerate() {
+            let Ok(output) = input.output_type() else {
+                continue;
+            };
+
+            witness_bytes += estimate_input_witness_bytes(transaction_name, input, input_index)?;
+
+            match output {
+                OutputType::SegwitPublicKey { .. } => sigops_cost += 1,
+                OutputType::SegwitScript { script, .. } => {
+                    sigops_cost += script.get_script().count_sigops();
+                    issues.extend(p2wsh_issues(input_index, script));
+                }
+                OutputType::Taproot { .. } => {
+                    issues.extend(tapscript_issues(transaction_name, input_index, output)?);
+                }
+                _ => {}
+            }
+        }
+
+        if sigops_cost > MAX_STANDARD_TX_SIGOPS_COST {
+            issues.push(ValidationIssue::TransactionSigops {
+                cost: sigops_cost,
+                limit: MAX_STANDARD_TX_SIGOPS_COST,
+            });
+        }
+
+        for (output_index, output) in self
+            .graph()
+            .get_outputs(transaction_name)?
+            .iter()
+            .enumerate()
+        {
+            let txout = &transaction.output[output_index];
+            let dust_limit = dust_policy.dust_limit(output).to_sat();
+            if txout.value.to_sat() < dust_limit {
+                issues.push(ValidationIssue::DustOutput {
+                    output_index,
+                    value: txout.value.to_sat(),
+                    dust_limit,
+                });
+            }
+
+            if let Some(spend_info) = output.get_taproot_spend_info()? {
+                let depth = spend_info
+                    .script_map()
+                    .values()
+                    .flat_map(|branches| branches.iter().map(|branch| branch.len()))
+                    .max()
+                    .unwrap_or_default();
+
+                if depth > TAPROOT_CONTROL_MAX_NODE_COUNT {
+                    issues.push(ValidationIssue::TaprootTreeDepth {
+                        output_index,
+                        depth,
+                        limit: TAPROOT_CONTROL_MAX_NODE_COUNT,
+                    });
+                }
+            }
+        }
+
+        let marker_and_flag = if witness_bytes > 0 { 2 } else { 0 };
+        let weight = stripped_size_bytes(transaction) * 4 + witness_bytes + marker_and_flag;
+        if weight > MAX_STANDARD_TX_WEIGHT {
+            issues.push(ValidationIssue::TransactionWeight {
+                weight,
+                limit: MAX_STANDARD_TX_WEIGHT,
+            });
+        }
+
+        if transaction.version == Version::ONE {
+            for (input_index, txin) in transaction.input.iter().enumerate() {
+                if txin.sequence.is_relative_lock_time() {
+                    issues.push(ValidationIssue::RelativeTimelockIgnored { input_index });
+                }
+            }
+        }
+        issues.extend(self.truc_issues(transaction_name, weight, links)?);
+
+        Ok(issues)
+    }
+
+    fn truc_issues(
+        &self,
+        transaction_name: &str,
+        weight: usize,
+        links: &[UnconfirmedLink],
+    ) -> Result<Vec<ValidationIssue>, ProtocolBuilderError> {
+        let is_truc = |name: &str| -> Result<bool, ProtocolBuilderError> {
+            Ok(self.transaction_by_name(name)?.version == Version::non_standard(3))
+        };
+        let parents_of = |name: &str| -> BTreeSet<String> {
+            links
+                .iter()
+                .filter(|link| link.to == name)
+                .map(|link| link.from.clone())
+                .collect()
+        };
+
+        let truc = is_truc(transaction_name)?;
+        let parents = parents_of(transaction_name);
+        let mut issues = vec![];
+        for parent in parents.iter() {
+            if is_truc(parent)? != truc {
+                issues.push(ValidationIssue::TrucVersionMismatch {
+                    parent: parent.clone(),
+                });
+            }
+        }
+        if !truc {
+            return Ok(issues);
+        }
+
+        let limit = if parents.is_empty() {
+            TRUC_MAX_WEIGHT
+        } else {
+            TRUC_CHILD_MAX_WEIGHT
+        };
+        if weight > limit {
+            issues.push(ValidationIssue::TrucWeight { weight, limit });
+        }
+
+        let mut ancestors = parents.clone();
+        for parent in parents.iter() {
+            ancestors.extend(parents_of(parent));
+        }
+        if ancestors.len() > 1 {
+            issues.push(ValidationIssue::TrucAncestors {
+                ancestors: ancestors.into_iter().collect(),
+            });
+        }
+
+        // Children spending a common output are alternatives, only one of them can be
+        // broadcast. The limit is only broken by children that can be unconfirmed together.
+        let children: BTreeSet<String> = links
+            .iter()
+            .filter(|link| link.from == transaction_name)
+            .map(|link| link.to.clone())
+            .collect();
+        let spent_by = |child: &str| -> BTreeSet<(String, usize)> {
+            self.graph()
+                .stored_connections()
+                .into_iter()
+                .filter(|stored| stored.to == child)
+                .map(|stored| (stored.from, stored.connection.output_index as usize))
+                .collect()
+        };
+        let spent: Vec<(&String, BTreeSet<(String, usize)>)> = children
+            .iter()
+            .map(|child| (child, spent_by(child)))
+            .collect();
+        let coexisting: BTreeSet<String> = spent
+            .iter()
+            .filter(|(child, outputs)| {
+                spent
+                    .iter()
+                    .any(|(other, others)| other != child && outputs.is_disjoint(others))
+            })
+            .map(|(child, _)| (*child).clone())
+            .collect();
+        if !coexisting.is_empty() {
+            issues.push(ValidationIssue::TrucDescendants {
+                children: coexisting.into_iter().collect(),
+            });
+        }
+
+        Ok(issues)
+    }
+
+    fn unconfirmed_links(&self) -> Result<Vec<UnconfirmedLink>, ProtocolBuilderError> {
+        let mut links = vec![];
+        for stored in self.graph().stored_connections() {
+            if self.is_external_transaction(&stored.from) {
+                continue;
+            }
+
+            let input_index = stored.connection.input_index as usize;
+            let sequence = self
+                .transaction_by_name(&stored.to)?
+                .input
+                .get(input_index)
+                .ok_or(GraphError::MissingInputInfo(stored.to.clone(), input_index))?
+                .sequence;
+            if !requires_confirmation(sequence) {
+                links.push(UnconfirmedLink {
+                    from: stored.from,
+                    to: stored.to,
+                });
+            }
+        }
+
+        Ok(links)
+    }
+
+    fn is_external_transaction(&self, transaction_name: &str) -> bool {
+        self.graph()
+            .nodes()
+            .any(|node| node.name == transaction_name && node.external)
+    }
+}
+
+fn p2wsh_issues(input_index: usize, script: &ProtocolScript) -> Vec<ValidationIssue> {
+    let mut issues = vec![];
+    let items = script.stack_items();
+
+    let count = items.iter().map(|item| item.element_count()).sum();
+    if count > MAX_STANDARD_P2WSH_STACK_ITEMS {
+        issues.push(ValidationIssue::WitnessItemCount {
+            input_index,
+            leaf_index: None,
+            count,
+            limit: MAX_STANDARD_P2WSH_STACK_ITEMS,
+        });
+    }
+
+    let size = items
+        .iter()
+        .map(|item| item.max_element_size())
+        .max()
+        .unwrap_or_default();
+    if size > MAX_STANDARD_P2WSH_STACK_ITEM_SIZE {
+        issues.push(ValidationIssue::WitnessItemSize {
+            input_index,
+            leaf_index: None,
+            size,
+            limit: MAX_STANDARD_P2WSH_STACK_ITEM_SIZE,
+        });
+    }
+
+    let script_size = script.get_script().len();
+    let limit = if script_size > MAX_SCRIPT_SIZE {
+        MAX_SCRIPT_SIZE
+    } else {
+        MAX_STANDARD_P2WSH_SCRIPT_SIZE
+    };
+    if script_size > limit {
+        issues.push(ValidationIssue::ScriptSize {
+            input_index,
+            leaf_index: None,
+            size: script_size,
+            limit,
+        });
+    }
+
+    issues
+}
+
+// Checks every leaf of the spent output, any of them can be used on chain. Tapscripts have no script size limit other than the
+// transaction weight.
+fn tapscript_issues(
+    transaction_name: &str,
+    input_index: usize,
+    output: &OutputType,
+) -> Result<Vec<ValidationIssue>, ProtocolBuilderError> {
+    let (OutputType::Taproot { leaves, .. }, Some(spend_info)) =
+        (output, output.get_taproot_spend_info()?)
+    else {
+        return Ok(vec![]);
+    };
+
+    let mut issues = vec![];
+
+    for (leaf_index, leaf) in leaves.iter().enumerate() {
+        let items = leaf.stack_items();
+        let count = items.iter().map(|item| item.element_count()).sum();
+        if count > MAX_STACK_SIZE {
+            issues.push(ValidationIssue::WitnessItemCount {
+                input_index,
+                leaf_index: Some(leaf_index),
+                count,
+                limit: MAX_STACK_SIZE,
+            });
+        }
+
+        let size = items
+            .iter()
+            .map(|item| item.max_element_size())
+            .max()
+            .unwrap_or_default();
+        if size > MAX_SCRIPT_ELEMENT_SIZE {
+            issues.push(ValidationIssue::WitnessItemSize {
+                input_index,
+                leaf_index: Some(leaf_index),
+                size,
+                limit: MAX_SCRIPT_ELEMENT_SIZE,
+            });
+        }
+
+        let control_block_size = spend_info
+            .control_block(&(leaf.get_script().clone(), LeafVersion::TapScript))
+            .map(|control_block| control_block.size())
+            .ok_or(GraphError::InvalidTaprootInfo(
+                transaction_name.to_string(),
+                input_index,
+            ))?;
+
+        let witness_size = items.iter().map(|item| item.size() + 1).sum::<usize>()
+            + leaf.get_script().len()
+            + control_block_size
+            + 3;
+        let sigops = signature_operations(leaf.get_script());
+        let budget = TAPSCRIPT_SIGOPS_WEIGHT + witness_size;
+
+        if sigops * TAPSCRIPT_SIGOPS_WEIGHT > budget {
+            issues.push(ValidationIssue::SigopsBudget {
+                input_index,
+                leaf_index,
+                sigops,
+                budget,
+            });
+        }
+    }
+
+    Ok(issues)
+}
+
+// Upper bound of the signature operations executed by a tapscript.
+fn signature_operations(script: &Script) -> usize {
+    script
+        .instructions()
+        .filter(|instruction| {
+            matches!(
+                instruction,
+                Ok(Instruction::Op(
+                    opcodes::OP_CHECKSIG | opcodes::OP_CHECKSIGVERIFY | opcodes::OP_CHECKSIGADD
+                ))
+            )
+        })
+        .count()
+}
@@ -0,0 +1,510 @@
+use bitcoin::{
+    secp256k1::{self, ecdsa, schnorr, Message},
+    sighash::SighashCache,
+    taproot::{self, LeafVersion, TAPROOT_ANNEX_PREFIX},
+    PublicKey, TapLeafHash, Transaction, TxOut, XOnlyPublicKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    helpers::{
+        parallel_verify::ParallelSchnorrVerifier, sighash::taproot_prevouts,
+        witness_decoder::witness_leaf_script,
+    },
+    types::{
+        input::{InputType, SighashType, Signature},
+        output::OutputType,
+    },
+};
+
+use super::Protocol;
+
+/// Spending path a stored signature authorizes.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum SignaturePath {
+    /// Taproot key path, verified against the tweaked output key.
+    KeyPath,
+    /// Taproot script path, verified against the verifying key of the leaf.
+    Leaf(usize),
+    /// Segwit v0 input, verified against the output public key or the verifying key of the
+    /// witness script.
+    Segwit,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum SignatureStatus {
+    Valid,
+    /// The signature does not match the re-derived sighash and key.
+    Invalid,
+    /// The signature commits to a sighash type other than the one declared for the input.
+    SighashTypeMismatch {
+        expected: String,
+        found: String,
+    },
+    /// The signature is for an ECDSA input and the stored one is Schnorr, or the other way around.
+    SignatureKindMismatch,
+    /// The spent output has no key the signature could be verified against.
+    MissingKey,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SignatureCheck {
+    pub transaction_name: String,
+    pub input_index: usize,
+    pub path: SignaturePath,
+    pub status: SignatureStatus,
+}
+
+/// Result of `Protocol::verify_all_signatures`, with one check for every stored signature.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SignatureReport {
+    pub checks: Vec<SignatureCheck>,
+}
+
+impl SignatureReport {
+    pub fn is_valid(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &SignatureCheck> {
+        self.checks
+            .iter()
+            .filter(|check| check.status != SignatureStatus::Valid)
+    }
+}
+
+impl Protocol {
+    /// Verifies every signature stored in the protocol, re-deriving each sighash from the
+    /// transactions and spent outputs instead of using the stored messages, so a counterparty can
+    /// check a received pre-signed protocol without trusting the sender. Key path signatures are
+    /// verified against the tweaked output key. Signatures of custom outputs are not checked.
+    pub fn verify_all_signatures(&self) -> Result<SignatureReport, ProtocolBuilderError> {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let mut report = SignatureReport::default();
+        // Schnorr signatures are verified together once every sighash is derived
+        let mut verifier = ParallelSchnorrVerifier::new();
+        let mut deferred = vec![];
+
+        for node in self.graph().nodes().filter(|node| !node.external) {
+            let transaction = &node.transaction;
+            let prevouts = self.graph().get_prevouts(&node.name)?;
+            let mut sighasher = SighashCache::new(transaction);
+
+            for (input_index, input) in node.inputs.iter().enumerate() {
+                for (signature_index, signature) in input.signatures().iter().enumerate() {
+                    let Some(signature) = signature else {
+                        continue;
+                    };
+
+                    let Some((path, check)) = check_signature(
+                        transaction,
+                        &mut sighasher,
+                        &prevouts,
+                        input_index,
+                        input,
+                        signature_index,
+                        signature,
+                    )?
+                    else {
+                        continue;
+                    };
+
+                    let status = match check {
+                        Check::Schnorr(signature, message, key) => {
+                            verifier.push(signature, message, key);
+                            deferred.push(report.checks.len());
+                            SignatureStatus::Invalid
+                        }
+                        check => check.verify(&secp),
+                    };
+
+                    report.checks.push(SignatureCheck {
+                        transaction_name: node.name.clone(),
+                        input_index,
+                        path,
+                        status,
+                    });
+                }
+            }
+        }
+
+        for (check_index, valid) in deferred.into_iter().zip(verifier.verify(&secp)) {
+            if valid {
+                report.checks[check_index].status = SignatureStatus::Valid;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+// Outcome of checking a stored signature, with the signature left to verify once its sighash
+// and key are known. Schnorr signatures are verified together, see `ParallelSchnorrVerifier`.
+enum Check {
+    Done(SignatureStatus),
+    Schnorr(schnorr::Signature, Message, XOnlyPublicKey),
+    Ecdsa(ecdsa::Signature, Message, PublicKey),
+}
+
+impl Check {
+    fn verify(self, secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>) -> SignatureStatus {
+        let verified = match self {
+            Check::Done(status) => return status,
+            Check::Schnorr(signature, message, key) => {
+                secp.verify_schnorr(&signature, &message, &key)
+            }
+            Check::Ecdsa(signature, message, key) => {
+                secp.verify_ecdsa(&message, &signature, &key.inner)
+            }
+        };
+        match verified {
+            Ok(()) => SignatureStatus::Valid,
+            Err(_) => SignatureStatus::Invalid,
+        }
+    }
+}
+
+// Returns None for signatures of outputs that cannot be verified by the protocol.
+pub(super) fn verify_signature(
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    transaction: &Transaction,
+    prevouts: &[TxOut],
+    input_index: usize,
+    input: &InputType,
+    signature_index: usize,
+    signature: &Signature,
+) -> Result<Option<(SignaturePath, SignatureStatus)>, ProtocolBuilderError> {
+    let checked = check_signature(
+        transaction,
+        &mut SighashCache::new(transaction),
+        prevouts,
+        input_index,
+        input,
+        signature_index,
+        signature,
+    )?;
+
+    Ok(checked.map(|(path, check)| (path, check.verify(secp))))
+}
+
+fn check_signature(
+    transaction: &Transaction,
+    sighasher: &mut SighashCache<&Transaction>,
+    prevouts: &[TxOut],
+    input_index: usize,
+    input: &InputType,
+    signature_index: usize,
+    signature: &Signature,
+) -> Result<Option<(SignaturePath, Check)>, ProtocolBuilderError> {
+    let output = input.output_type()?;
+
+    let result = match (output, input.sighash_type(), signature) {
+        (
+            OutputType::Custom { .. }
+            | OutputType::SegwitUnspendable { .. }
+            | OutputType::PayToAnchor { .. },
+            _,
+            _,
+        ) => None,
+        (OutputType::Taproot { leaves, .. }, SighashType::Taproot(expected), signature) => {
+            let path = if signature_index == leaves.len() {
+                SignaturePath::KeyPath
+            } else {
+                SignaturePath::Leaf(signature_index)
+            };
+
+            let Signature::Taproot(signature) = signature else {
+                return Ok(Some((
+                    path,
+                    Check::Done(SignatureStatus::SignatureKindMismatch),
+                )));
+            };
+
+            // Leaves may be signed with their own sighash type
+            let expected = match leaves.get(signature_index) {
+                Some(leaf) => leaf.sighash_type_or(*expected),
+                None => *expected,
+            };
+            if signature.sighash_type != expected {
+                return Ok(Some((
+                    path,
+                    Check::Done(sighash_type_mismatch(&expected, &signature.sighash_type)),
+                )));
+            }
+
+            let prevouts = taproot_prevouts(transaction, prevouts, input_index, expected)?;
+            let (message, key) = match path {
+                SignaturePath::KeyPath => {
+                    let key = output
+                        .get_taproot_spend_info()?
+                        .map(|spend_info| spend_info.output_key().to_x_only_public_key());
+                    let message = Message::from(sighasher.taproot_key_spend_signature_hash(
+                        input_index,
+                        &prevouts,
+                        expected,
+                    )?);
+                    (message, key)
+                }
+                _ => {
+                    let Some(leaf) = leaves.get(signature_index) else {
+                        return Ok(Some((path, Check::Done(SignatureStatus::MissingKey))));
+                    };
+                    let key = leaf.get_verifying_key().map(|key| key.into());
+                    let message = Message::from(sighasher.taproot_script_spend_signature_hash(
+                        input_index,
+                        &prevouts,
+                        TapLeafHash::from_script(leaf.get_script(), LeafVersion::TapScript),
+                        expected,
+                    )?);
+                    (message, key)
+                }
+            };
+
+            let check = match key {
+                Some(key) => Check::Schnorr(signature.signature, message, key),
+                None => Check::Done(SignatureStatus::MissingKey),
+            };
+            Some((path, check))
+        }
+        (_, SighashType::Ecdsa(expected), signature) => {
+            let path = SignaturePath::Segwit;
+
+            let Signature::Ecdsa(signature) = signature else {
+                return Ok(Some((
+                    path,
+                    Check::Done(SignatureStatus::SignatureKindMismatch),
+                )));
+            };
+            if signature.sighash_type != *expected {
+                return Ok(Some((
+                    path,
+                    Check::Done(sighash_type_mismatch(expected, &signature.sighash_type)),
+                )));
+            }
+
+            let value = output.get_value();
+            let (message, key) = match output {
+                OutputType::SegwitPublicKey { public_key, .. } => (
+                    Message::from(sighasher.p2wpkh_signature_hash(
+                        input_index,
+                        output.get_script_pubkey(),
+                        value,
+                        *expected,
+                    )?),
+                    Some(*public_key),
+                ),
+                OutputType::SegwitScript { script, .. } => (
+                    Message::from(sighasher.p2wsh_signature_hash(
+                        input_index,
+                        script.get_script(),
+                        value,
+                        *expected,
+                    )?),
+                    script.get_verifying_key(),
+                ),
+                _ => {
+                    return Ok(Some((
+                        path,
+                        Check::Done(SignatureStatus::SignatureKindMismatch),
+                    )))
+                }
+            };
+
+            let check = match key {
+                Some(key) => Check::Ecdsa(signature.signature, message, key),
+                None => Check::Done(SignatureStatus::MissingKey),
+            };
+            Some((path, check))
+        }
+        (_, SighashType::Taproot(_), _) => None,
+    };
+
+    Ok(result)
+}
+
+/// Sighash type of the path a witness spends and the status of the signature it holds for the
+/// key of that path, None if the path has no key to verify a signature against.
+pub(super) struct WitnessCheck {
+    pub sighash_type: SighashType,
+    pub status: Option<SignatureStatus>,
+}
+
+// Checks the witness of an input spending the output, re-deriving the sighash of the spent path
+// from the transaction and the spent outputs. Leaves may be signed with their own sighash type.
+// Returns None if the witness does not spend a path of the output.
+pub(super) fn check_witness(
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    transaction: &Transaction,
+    prevouts: &[TxOut],
+    input_index: usize,
+    output: &OutputType,
+    sighash_type: &SighashType,
+) -> Result<Option<WitnessCheck>, ProtocolBuilderError> {
+    let witness = &transaction.input[input_index].witness;
+    let mut items: Vec<&[u8]> = witness.iter().collect();
+
+    let check = match (output, sighash_type) {
+        (OutputType::Taproot { leaves, .. }, SighashType::Taproot(default)) => {
+            let mut sighasher = SighashCache::new(transaction);
+
+            let (expected, message, key, stack) = match witness_leaf_script(witness) {
+                Some(script) => {
+                    let Some(leaf) = leaves.iter().find(|leaf| leaf.get_script() == script) else {
+                        return Ok(None);
+                    };
+                    let expected = leaf.sighash_type_or(*default);
+                    let prevouts = taproot_prevouts(transaction, prevouts, input_index, expected)?;
+                    let message = Message::from(sighasher.taproot_script_spend_signature_hash(
+                        input_index,
+                        &prevouts,
+                        TapLeafHash::from_script(script, LeafVersion::TapScript),
+                        expected,
+                    )?);
+                    let key: Option<XOnlyPublicKey> =
+                        leaf.get_verifying_key().map(|key| key.into());
+                    // Signatures are below the leaf script and the control block
+                    let stack = items.len() - 2 - usize::from(is_annex(&items));
+                    (expected, message, key, stack)
+                }
+                None => {
+                    if items.len() == 2 && is_annex(&items) {
+                        items.pop();
+                    }
+                    if items.len() != 1 {
+                        return Ok(None);
+                    }
+                    let prevouts = taproot_prevouts(transaction, prevouts, input_index, *default)?;
+                    let message = Message::from(sighasher.taproot_key_spend_signature_hash(
+                        input_index,
+                        &prevouts,
+                        *default,
+                    )?);
+                    let key = output
+                        .get_taproot_spend_info()?
+                        .map(|spend_info| spend_info.output_key().to_x_only_public_key());
+                    (*default, message, key, 1)
+                }
+            };
+
+            let status = key.map(|key| {
+                witness_status(items[..stack].iter().filter_map(|item| {
+                    let signature = taproot::Signature::from_slice(item).ok()?;
+                    Some(if signature.sighash_type != expected {
+                        sighash_type_mismatch(&expected, &signature.sighash_type)
+                    } else {
+                        Check::Schnorr(signature.signature, message, key).verify(secp)
+                    })
+                }))
+            });
+
+            WitnessCheck {
+                sighash_type: SighashType::Taproot(expected),
+                status,
+            }
+        }
+        (
+            OutputType::SegwitPublicKey { .. } | OutputType::SegwitScript { .. },
+            SighashType::Ecdsa(expected),
+        ) => {
+            let mut sighasher = SighashCache::new(transaction);
+            let value = output.get_value();
+
+            let (message, key) = match output {
+                OutputType::SegwitScript { script, .. } => {
+                    if items.pop() != Some(script.get_script().as_bytes()) {
+                        return Ok(None);
+                    }
+                    let message = Message::from(sighasher.p2wsh_signature_hash(
+                        input_index,
+                        script.get_script(),
+                        value,
+                        *expected,
+                    )?);
+                    (message, script.get_verifying_key())
+                }
+                OutputType::SegwitPublicKey { public_key, .. } => {
+                    // The public key is pushed above the signature
+                    if items.pop() != Some(public_key.to_bytes().as_slice()) {
+                        return Ok(None);
+                    }
+                    let message = Message::from(sighasher.p2wpkh_signature_hash(
+                        input_index,
+                        output.get_script_pubkey(),
+                        value,
+                        *expected,
+                    )?);
+                    (message, Some(*public_key))
+                }
+                _ => return Ok(None),
+            };
+
+            let status = key.map(|key| {
+                witness_status(items.iter().filter_map(|item| {
+                    let signature = bitcoin::ecdsa::Signature::from_slice(item).ok()?;
+                    Some(if signature.sighash_type != *expected {
+                        sighash_type_mismatch(expected, &signature.sighash_type)
+                    } else {
+                        Check::Ecdsa(signature.signature, message, key).verify(secp)
+                    })
+                }))
+            });
+
+            WitnessCheck {
+                sighash_type: SighashType::Ecdsa(*expected),
+                status,
+            }
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(check))
+}
+
+// The annex is the last item of a taproot witness with at least two items, see BIP341.
+fn is_annex(items: &[&[u8]]) -> bool {
+    items.len() >= 2 && items.last().and_then(|item| item.first()) == Some(&TAPROOT_ANNEX_PREFIX)
+}
+
+// A witness is valid if any of its items is a valid signature for the key of the spent path.
+fn witness_status(statuses: impl Iterator<Item = SignatureStatus>) -> SignatureStatus {
+    let mut result = SignatureStatus::Invalid;
+    for status in statuses {
+        match status {
+            SignatureStatus::Valid => return status,
+            SignatureStatus::SighashTypeMismatch { .. } => result = status,
+            _ => {}
+        }
+    }
+    result
+}
+
+/// Key expected to produce the signature at the given index of an input spending the output,
+/// the internal key for taproot key path signatures.
+pub(super) fn signing_key(output: &OutputType, signature_index: usize) -> Option<PublicKey> {
+    match output {
+        OutputType::SegwitPublicKey { public_key, .. } if signature_index == 0 => Some(*public_key),
+        OutputType::SegwitScript { script, .. } if signature_index == 0 => {
+            script.get_verifying_key()
+        }
+        OutputType::Taproot {
+            internal_key,
+            leaves,
+            ..
+        } if signature_index == leaves.len() => Some(*internal_key),
+        OutputType::Taproot { leaves, .. } => leaves
+            .get(signature_index)
+            .and_then(|leaf| leaf.get_verifying_key()),
+        _ => None,
+    }
+}
+
+fn sighash_type_mismatch<E: std::fmt::Debug, F: std::fmt::Debug>(
+    expected: &E,
+    found: &F,
+) -> SignatureStatus {
+    SignatureStatus::SighashTypeMismatch {
+        expected: format!("{:?}", expected),
+        found: format!("{:?}", found),
+    }
+}
@@ -0,0 +1,249 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use bitcoin::{secp256k1::Message, Transaction, Txid};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::value::RawValue;
+
+use crate::{
+    errors::{GraphError, ProtocolBuilderError, SerializationError},
+    scripts::ProtocolScript,
+    types::{
+        input::InputType,
+        output::OutputType,
+        serialization::{split_header, SerializationFormat},
+    },
+};
+
+// Only the fields of a serialized protocol read by the view, everything else is skipped.
+#[derive(Deserialize)]
+struct ViewData<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    #[serde(borrow)]
+    graph: ViewGraph<'a>,
+}
+
+#[derive(Deserialize)]
+struct ViewGraph<'a> {
+    #[serde(borrow)]
+    graph: ViewPetgraph<'a>,
+}
+
+#[derive(Deserialize)]
+struct ViewPetgraph<'a> {
+    #[serde(borrow)]
+    nodes: Vec<ViewNode<'a>>,
+    #[serde(borrow)]
+    edges: Vec<(usize, usize, ViewConnection<'a>)>,
+}
+
+#[derive(Deserialize)]
+struct ViewNode<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    external: bool,
+    #[serde(borrow)]
+    transaction: &'a RawValue,
+    #[serde(borrow)]
+    outputs: Vec<&'a RawValue>,
+    #[serde(borrow)]
+    inputs: Vec<&'a RawValue>,
+}
+
+#[derive(Deserialize)]
+struct ViewConnection<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    input_index: u32,
+    output_index: u32,
+}
+
+/// Read-only view of a JSON serialized protocol, for lookups in memory constrained processes
+/// such as watchtowers. Opening a view only indexes the transaction names and connections; the
+/// transactions, inputs and outputs stay borrowed from the serialized data and are decoded
+/// each time they are queried. No transaction graph is built and no taproot spend info is
+/// computed.
+pub struct ProtocolView<'a> {
+    data: ViewData<'a>,
+    indexes: HashMap<Cow<'a, str>, usize>,
+}
+
+impl<'a> ProtocolView<'a> {
+    /// Opens a view of a protocol serialized as plain JSON, as stored by `Protocol::save`.
+    pub fn from_json(json: &'a str) -> Result<Self, ProtocolBuilderError> {
+        let data: ViewData<'a> = serde_json::from_str(json).map_err(decode_error)?;
+
+        let indexes = data
+            .graph
+            .graph
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.name.clone(), index))
+            .collect();
+
+        Ok(Self { data, indexes })
+    }
+
+    /// Opens a view of a protocol encoded with `Protocol::to_bytes`. Only JSON encoded
+    /// protocols can be viewed.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, ProtocolBuilderError> {
+        match split_header(bytes)? {
+            (SerializationFormat::Json, payload) => {
+                Self::from_json(std::str::from_utf8(payload).map_err(decode_error)?)
+            }
+            (format, _) => Err(SerializationError::UnsupportedView(format.to_string()).into()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.data.name
+    }
+
+    pub fn transaction_names(&self) -> Vec<&str> {
+        self.nodes().iter().map(|node| node.name.as_ref()).collect()
+    }
+
+    pub fn contains_transaction(&self, name: &str) -> bool {
+        self.indexes.contains_key(name)
+    }
+
+    pub fn is_external(&self, name: &str) -> Result<bool, ProtocolBuilderError> {
+        Ok(self.node(name)?.external)
+    }
+
+    pub fn transaction(&self, name: &str) -> Result<Transaction, ProtocolBuilderError> {
+        decode(self.node(name)?.transaction)
+    }
+
+    pub fn txid(&self, name: &str) -> Result<Txid, ProtocolBuilderError> {
+        Ok(self.transaction(name)?.compute_txid())
+    }
+
+    pub fn input_count(&self, name: &str) -> Result<usize, ProtocolBuilderError> {
+        Ok(self.node(name)?.inputs.len())
+    }
+
+    pub fn output_count(&self, name: &str) -> Result<usize, ProtocolBuilderError> {
+        Ok(self.node(name)?.outputs.len())
+    }
+
+    pub fn input(&self, name: &str, input_index: usize) -> Result<InputType, ProtocolBuilderError> {
+        let input = self
+            .node(name)?
+            .inputs
+            .get(input_index)
+            .ok_or_else(|| GraphError::MissingInputInfo(name.to_string(), input_index))?;
+        decode(input)
+    }
+
+    pub fn output(
+        &self,
+        name: &str,
+        output_index: usize,
+    ) -> Result<OutputType, ProtocolBuilderError> {
+        let output =
+            self.node(name)?.outputs.get(output_index).ok_or_else(|| {
+                ProtocolBuilderError::MissingOutput(name.to_string(), output_index)
+            })?;
+        decode(output)
+    }
+
+    /// Sighashes computed for an input when the protocol was built.
+    pub fn sighashes(
+        &self,
+        name: &str,
+        input_index: usize,
+    ) -> Result<Vec<Option<Message>>, ProtocolBuilderError> {
+        Ok(self.input(name, input_index)?.hashed_messages())
+    }
+
+    /// Scripts committed in an output, with their leaf index as in `OutputType::get_scripts`.
+    pub fn scripts(
+        &self,
+        name: &str,
+        output_index: usize,
+    ) -> Result<Vec<(usize, ProtocolScript)>, ProtocolBuilderError> {
+        Ok(self
+            .output(name, output_index)?
+            .get_scripts()
+            .into_iter()
+            .map(|(index, script)| (index, script.clone()))
+            .collect())
+    }
+
+    /// Transactions spending the given output, as `TransactionGraph::spenders`.
+    pub fn spenders(
+        &self,
+        name: &str,
+        output_index: usize,
+    ) -> Result<Vec<&str>, ProtocolBuilderError> {
+        let from = self.index(name)?;
+        let mut spenders: Vec<&str> = vec![];
+
+        for (_, to, _) in self.edges().iter().filter(|(source, _, connection)| {
+            *source == from && connection.output_index as usize == output_index
+        }) {
+            let to = self.nodes()[*to].name.as_ref();
+            if !spenders.contains(&to) {
+                spenders.push(to);
+            }
+        }
+
+        Ok(spenders)
+    }
+
+    /// Transactions spending the outputs of the given one, with the index of the spending input.
+    pub fn dependencies(&self, name: &str) -> Result<Vec<(&str, u32)>, ProtocolBuilderError> {
+        let from = self.index(name)?;
+        Ok(self
+            .edges()
+            .iter()
+            .filter(|(source, _, _)| *source == from)
+            .map(|(_, to, connection)| (self.nodes()[*to].name.as_ref(), connection.input_index))
+            .collect())
+    }
+
+    /// Name of the connection spent by an input, if it spends an output of the protocol.
+    pub fn connection_name(
+        &self,
+        name: &str,
+        input_index: usize,
+    ) -> Result<Option<&str>, ProtocolBuilderError> {
+        let to = self.index(name)?;
+        Ok(self
+            .edges()
+            .iter()
+            .find(|(_, target, connection)| {
+                *target == to && connection.input_index as usize == input_index
+            })
+            .map(|(_, _, connection)| connection.name.as_ref()))
+    }
+
+    fn edges(&self) -> &[(usize, usize, ViewConnection<'a>)] {
+        &self.data.graph.graph.edges
+    }
+
+    fn nodes(&self) -> &[ViewNode<'a>] {
+        &self.data.graph.graph.nodes
+    }
+
+    fn index(&self, name: &str) -> Result<usize, ProtocolBuilderError> {
+        self.indexes
+            .get(name)
+            .copied()
+            .ok_or_else(|| GraphError::MissingTransaction(name.to_string()).into())
+    }
+
+    fn node(&self, name: &str) -> Result<&ViewNode<'a>, ProtocolBuilderError> {
+        Ok(&self.nodes()[self.index(name)?])
+    }
+}
+
+fn decode<T: DeserializeOwned>(raw: &RawValue) -> Result<T, ProtocolBuilderError> {
+    serde_json::from_str(raw.get()).map_err(decode_error)
+}
+
+fn decode_error(error: impl ToString) -> ProtocolBuilderError {
+    SerializationError::DecodeError(SerializationFormat::Json.to_string(), error.to_string()).into()
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use bitcoin::Transaction;
+use key_manager::winternitz::WinternitzPublicKey;
+
+use crate::{
+    errors::ProtocolBuilderError,
+    helpers::witness_decoder::{
+        decode_winternitz_values, verify_winternitz_values, witness_leaf_script, WinternitzValue,
+    },
+    types::OutputType,
+};
+
+use super::Protocol;
+
+impl Protocol {
+    /// Reads the values committed with Winternitz keys in a confirmed transaction of the protocol.
+    /// The spent leaf is found from the script in the witness of the input, its values are
+    /// decoded and checked against the Winternitz public keys, given by key name.
+    pub fn decode_winternitz_witness(
+        &self,
+        transaction_name: &str,
+        input_index: usize,
+        transaction: &Transaction,
+        public_keys: &HashMap<String, WinternitzPublicKey>,
+    ) -> Result<Vec<WinternitzValue>, ProtocolBuilderError> {
+        let input = self
+            .inputs(transaction_name)?
+            .get(input_index)
+            .cloned()
+            .ok_or(ProtocolBuilderError::MissingInput(
+                transaction_name.to_string(),
+                input_index,
+            ))?;
+        let witness = &transaction
+            .input
+            .get(input_index)
+            .ok_or(ProtocolBuilderError::MissingInput(
+                transaction_name.to_string(),
+                input_index,
+            ))?
+            .witness;
+
+        let leaves = match input.output_type()? {
+            OutputType::Taproot { leaves, .. } => leaves,
+            output => {
+                return Err(ProtocolBuilderError::InvalidOutputType(
+                    "Taproot".to_string(),
+                    output.get_name().to_string(),
+                ))
+            }
+        };
+        let leaf = witness_leaf_script(witness)
+            .and_then(|script| leaves.iter().find(|leaf| leaf.get_script() == script))
+            .ok_or(ProtocolBuilderError::InvalidWinternitzWitness(format!(
+                "the witness of input {} of {} does not spend a leaf of the output",
+                input_index, transaction_name
+            )))?;
+
+        let values = decode_winternitz_values(witness, leaf)?;
+        verify_winternitz_values(&values, public_keys)?;
+
+        Ok(values)
+    }
+}
@@ -0,0 +1,67 @@
+use bitcoin::consensus::encode::serialize_hex;
+
+use crate::{
+    errors::ProtocolBuilderError,
+    types::{
+        input::InputArgs,
+        witness_preview::{PreviewedItem, WitnessItemRole, WitnessPreview},
+        OutputType,
+    },
+};
+
+use super::{input_args::arg_roles, Protocol};
+
+impl Protocol {
+    /// Witness `transaction_to_send` would give an input with the args, with the role of each
+    /// item, to debug script verification failures without broadcasting. The args are not
+    /// checked first, so malformed args can be previewed too. Inputs spending custom outputs
+    /// have their items listed as data.
+    pub fn preview_witness(
+        &self,
+        transaction_name: &str,
+        input_index: usize,
+        args: &InputArgs,
+    ) -> Result<WitnessPreview, ProtocolBuilderError> {
+        let input = self
+            .inputs(transaction_name)?
+            .get(input_index)
+            .cloned()
+            .ok_or(ProtocolBuilderError::MissingInput(
+                transaction_name.to_string(),
+                input_index,
+            ))?;
+        let witness = self.get_witness_for_input(input_index, &input, args)?;
+
+        let mut roles = match input.output_type()? {
+            OutputType::Custom { .. } => vec![],
+            _ => arg_roles(&input, args)?,
+        };
+        match (input.output_type()?, args) {
+            (OutputType::SegwitPublicKey { .. }, _) => roles.push(WitnessItemRole::PublicKey),
+            (OutputType::SegwitScript { .. }, _) => roles.push(WitnessItemRole::Script),
+            (
+                OutputType::Taproot { .. },
+                InputArgs::TaprootScript { .. } | InputArgs::TaprootScriptByName { .. },
+            ) => roles.extend([WitnessItemRole::Script, WitnessItemRole::ControlBlock]),
+            _ => {}
+        }
+
+        let items = witness
+            .iter()
+            .enumerate()
+            .map(|(index, item)| PreviewedItem {
+                index,
+                role: roles.get(index).copied().unwrap_or(WitnessItemRole::Data),
+                hex: hex::encode(item),
+                size: item.len(),
+            })
+            .collect();
+
+        Ok(WitnessPreview {
+            transaction_name: transaction_name.to_string(),
+            input_index,
+            items,
+            hex: serialize_hex(&witness),
+        })
+    }
+}
@@ -1,9 +1,18 @@
-use std::{path::PathBuf, rc::Rc};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    rc::Rc,
+    str::FromStr,
+};
 
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, Context, Ok, Result};
 
-use bitcoin::{hashes::Hash, secp256k1, EcdsaSighashType, PublicKey, ScriptBuf, TapSighashType};
-use clap::{Parser, Subcommand};
+use bitcoin::{
+    consensus::encode::deserialize_hex, hashes::Hash, key::Parity, Amount, EcdsaSighashType,
+    PublicKey, ScriptBuf, TapSighashType, Transaction, TxOut,
+};
+use clap::{Parser, Subcommand, ValueEnum};
 use key_manager::{create_key_manager_from_config, key_manager::KeyManager};
 use storage_backend::{storage::Storage, storage_config::StorageConfig};
 use tracing::info;
@@ -11,13 +20,16 @@ use tracing::info;
 use crate::{
     builder::{Protocol, ProtocolBuilder},
     config::Config,
+    graph::graph::GraphOptions,
+    helpers::sighash::raw_sighash,
     scripts::{ProtocolScript, SignMode},
     types::{
         connection::InputSpec,
         input::{SighashType, SpendMode},
         output::OutputType,
+        spec::ProtocolSpec,
+        witness_args::WitnessArgs,
     },
-    unspendable::unspendable_key,
 };
 
 pub struct Cli {
@@ -40,7 +52,13 @@ pub struct Menu {
 
 #[derive(Subcommand)]
 enum Commands {
-    Build,
+    Build {
+        #[arg(
+            long,
+            help = "YAML or JSON spec to create the protocol from, instead of building the stored one"
+        )]
+        spec: Option<PathBuf>,
+    },
 
     BuildAndSign,
 
@@ -131,6 +149,97 @@ enum Commands {
         #[arg(short, long, help = "Key to be used in the script")]
         public_key: String,
     },
+
+    Diff {
+        #[arg(long, help = "Archive of the original protocol, encoded with to_bytes")]
+        old: PathBuf,
+
+        #[arg(long, help = "Archive of the changed protocol, encoded with to_bytes")]
+        new: PathBuf,
+
+        #[arg(long, help = "Path to write a DOT graph of the differences")]
+        dot: Option<PathBuf>,
+
+        #[arg(long, help = "Print the differences without colors")]
+        no_color: bool,
+    },
+
+    Visualize {
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+
+        #[arg(
+            long,
+            help = "File to write the graph to instead of the standard output"
+        )]
+        output: Option<PathBuf>,
+    },
+
+    Inspect {
+        #[arg(long, help = "Name of the transaction to inspect")]
+        tx: String,
+
+        #[arg(long, help = "Print the summary as JSON")]
+        json: bool,
+    },
+
+    Finalize {
+        #[arg(long, help = "Name of the transaction to finalize")]
+        tx: String,
+
+        #[arg(
+            long,
+            help = "Leaf spent by taproot inputs without one in the witness args, which otherwise spend the key path"
+        )]
+        leaf: Option<usize>,
+
+        #[arg(
+            long,
+            help = "JSON file with the leaf and extra witness items (hex or Winternitz signatures) of each input"
+        )]
+        args: Option<PathBuf>,
+    },
+
+    Sighash {
+        #[arg(long, help = "Raw transaction in hex")]
+        tx: String,
+
+        #[arg(
+            long = "prevout",
+            required = true,
+            help = "Output spent by each input, in input order, as <value>:<script pubkey hex>"
+        )]
+        prevouts: Vec<String>,
+
+        #[arg(long, help = "Index of the input to sign")]
+        input_index: usize,
+
+        #[arg(
+            long,
+            help = "Leaf script of a taproot input or witness script of a P2WSH input, in hex"
+        )]
+        script: Option<String>,
+
+        #[arg(
+            long,
+            default_value = "SIGHASH_ALL",
+            help = "Sighash type, e.g. SIGHASH_ALL or SIGHASH_SINGLE|SIGHASH_ANYONECANPAY"
+        )]
+        sighash_type: String,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum GraphFormat {
+    Dot,
+    /// DOT with sighash types, spend modes, signing status and leaf scripts.
+    Detailed,
+    Mermaid,
+    Json,
+    /// Rendered by the graphviz `dot` command, which must be installed.
+    Svg,
+    /// Rendered by the graphviz `dot` command, which must be installed.
+    Png,
 }
 
 impl Cli {
@@ -143,9 +252,12 @@ impl Cli {
         let menu = Menu::parse();
 
         match &menu.command {
-            Commands::Build => {
+            Commands::Build { spec: None } => {
                 self.build(&menu.protocol_name, menu.graph_storage_path)?;
             }
+            Commands::Build { spec: Some(spec) } => {
+                self.build_from_spec(&menu.protocol_name, menu.graph_storage_path, spec)?;
+            }
             Commands::BuildAndSign => {
                 self.build_and_sign(&menu.protocol_name, menu.graph_storage_path)?;
             }
@@ -239,6 +351,43 @@ impl Cli {
                     public_key,
                 )?;
             }
+            Commands::Diff {
+                old,
+                new,
+                dot,
+                no_color,
+            } => {
+                self.diff(old, new, dot.as_deref(), !no_color)?;
+            }
+            Commands::Visualize { format, output } => {
+                self.visualize(
+                    &menu.protocol_name,
+                    menu.graph_storage_path,
+                    *format,
+                    output.as_deref(),
+                )?;
+            }
+            Commands::Inspect { tx, json } => {
+                self.inspect(&menu.protocol_name, menu.graph_storage_path, tx, *json)?;
+            }
+            Commands::Finalize { tx, leaf, args } => {
+                self.finalize(
+                    &menu.protocol_name,
+                    menu.graph_storage_path,
+                    tx,
+                    *leaf,
+                    args.as_deref(),
+                )?;
+            }
+            Commands::Sighash {
+                tx,
+                prevouts,
+                input_index,
+                script,
+                sighash_type,
+            } => {
+                self.sighash(tx, prevouts, *input_index, script.as_deref(), sighash_type)?;
+            }
         }
 
         Ok(())
@@ -261,6 +410,37 @@ impl Cli {
         Ok(())
     }
 
+    fn build_from_spec(
+        &self,
+        protocol_name: &str,
+        graph_storage_path: PathBuf,
+        spec_path: &Path,
+    ) -> Result<()> {
+        let config = StorageConfig::new(graph_storage_path.to_str().unwrap().to_string(), None);
+        let storage = Rc::new(Storage::new(&config).unwrap());
+        let key_manager = Rc::new(self.key_manager()?);
+
+        let spec = ProtocolSpec::load(spec_path)?;
+        let mut protocol = Protocol::from_spec(protocol_name, &spec, &key_manager)?;
+        protocol.build(&key_manager, protocol_name)?;
+        protocol.save(storage)?;
+
+        for name in protocol.transaction_names() {
+            println!(
+                "{} {}",
+                name,
+                protocol.transaction_by_name(&name)?.compute_txid()
+            );
+        }
+
+        info!(
+            "Protocol {} built from {}",
+            protocol_name,
+            spec_path.display()
+        );
+        Ok(())
+    }
+
     fn build_and_sign(&self, protocol_name: &str, graph_storage_path: PathBuf) -> Result<()> {
         let config = StorageConfig::new(graph_storage_path.to_str().unwrap().to_string(), None);
         let storage = Rc::new(Storage::new(&config).unwrap());
@@ -378,15 +558,15 @@ impl Cli {
         let config = StorageConfig::new(graph_storage_path.to_str().unwrap().to_string(), None);
         let storage = Rc::new(Storage::new(&config).unwrap());
 
-        let mut rng = secp256k1::rand::thread_rng();
-        let internal_key = unspendable_key(&mut rng)?;
         let pubkey_bytes = hex::decode(data).expect("Decoding failed");
         let public_key = PublicKey::from_slice(&pubkey_bytes).expect("Invalid public key format");
         let script =
             ProtocolScript::new(ScriptBuf::from(vec![0x00]), &public_key, SignMode::Single);
         let sighash_type = SighashType::Taproot(TapSighashType::All);
 
+        // Derived from the protocol name, so running the command again gives the same outputs
         let mut protocol = Protocol::new(protocol_name);
+        let internal_key = unspendable_internal_key(&mut protocol, from, to)?;
         let builder = ProtocolBuilder {};
 
         builder.add_taproot_connection(
@@ -426,8 +606,6 @@ impl Cli {
         let config = StorageConfig::new(graph_storage_path.to_str().unwrap().to_string(), None);
         let storage = Rc::new(Storage::new(&config).unwrap());
 
-        let mut rng = secp256k1::rand::thread_rng();
-        let internal_key = unspendable_key(&mut rng)?;
         let pubkey_bytes = hex::decode(data).expect("Decoding failed");
         let public_key = PublicKey::from_slice(&pubkey_bytes).expect("Invalid public key format");
         let expired_from =
@@ -436,7 +614,9 @@ impl Cli {
             ProtocolScript::new(ScriptBuf::from(vec![0x01]), &public_key, SignMode::Single);
         let sighash_type = SighashType::Taproot(TapSighashType::All);
 
+        // Derived from the protocol name, so running the command again gives the same outputs
         let mut protocol = Protocol::new(protocol_name);
+        let internal_key = unspendable_internal_key(&mut protocol, from, to)?;
         let builder = ProtocolBuilder {};
 
         builder.add_timelock_connection(
@@ -511,6 +691,149 @@ impl Cli {
         Ok(())
     }
 
+    fn diff(&self, old: &Path, new: &Path, dot: Option<&Path>, colored: bool) -> Result<()> {
+        let old = Protocol::from_bytes(&std::fs::read(old)?)?;
+        let new = Protocol::from_bytes(&std::fs::read(new)?)?;
+
+        let diff = old.diff(&new)?;
+        if diff.is_empty() {
+            info!("Protocols {} and {} are equal", old.name(), new.name());
+        } else {
+            print!("{}", diff.to_text(colored));
+        }
+
+        if let Some(dot) = dot {
+            std::fs::write(dot, old.visualize_diff(&new)?)?;
+            info!("Diff graph written to {}", dot.display());
+        }
+
+        Ok(())
+    }
+
+    fn visualize(
+        &self,
+        protocol_name: &str,
+        graph_storage_path: PathBuf,
+        format: GraphFormat,
+        output: Option<&Path>,
+    ) -> Result<()> {
+        let config = StorageConfig::new(graph_storage_path.to_str().unwrap().to_string(), None);
+        let storage = Rc::new(Storage::new(&config).unwrap());
+
+        let protocol = Protocol::load(protocol_name, storage)?
+            .ok_or(anyhow!("Protocol {} not found", protocol_name))?;
+
+        let options = match format {
+            GraphFormat::Detailed => GraphOptions::Detailed,
+            GraphFormat::Mermaid => GraphOptions::Mermaid,
+            GraphFormat::Json => GraphOptions::Json,
+            GraphFormat::Dot | GraphFormat::Svg | GraphFormat::Png => GraphOptions::Default,
+        };
+        let graph = protocol.visualize(options)?;
+
+        let bytes = match format {
+            GraphFormat::Svg => render_with_graphviz(&graph, "svg")?,
+            GraphFormat::Png => render_with_graphviz(&graph, "png")?,
+            _ => graph.into_bytes(),
+        };
+
+        match output {
+            Some(output) => {
+                std::fs::write(output, bytes)?;
+                info!("Graph of {} written to {}", protocol_name, output.display());
+            }
+            None => std::io::stdout().write_all(&bytes)?,
+        }
+
+        Ok(())
+    }
+
+    fn inspect(
+        &self,
+        protocol_name: &str,
+        graph_storage_path: PathBuf,
+        transaction_name: &str,
+        json: bool,
+    ) -> Result<()> {
+        let config = StorageConfig::new(graph_storage_path.to_str().unwrap().to_string(), None);
+        let storage = Rc::new(Storage::new(&config).unwrap());
+
+        let protocol = Protocol::load(protocol_name, storage)?
+            .ok_or(anyhow!("Protocol {} not found", protocol_name))?;
+
+        let inspection = protocol.inspect(transaction_name)?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&inspection)?);
+        } else {
+            println!("{}", inspection);
+        }
+
+        Ok(())
+    }
+
+    fn finalize(
+        &self,
+        protocol_name: &str,
+        graph_storage_path: PathBuf,
+        transaction_name: &str,
+        leaf: Option<usize>,
+        args: Option<&Path>,
+    ) -> Result<()> {
+        let config = StorageConfig::new(graph_storage_path.to_str().unwrap().to_string(), None);
+        let storage = Rc::new(Storage::new(&config).unwrap());
+        let key_manager = self.key_manager()?;
+
+        let protocol = Protocol::load(protocol_name, storage)?
+            .ok_or(anyhow!("Protocol {} not found", protocol_name))?;
+
+        let witness_args = match args {
+            Some(args) => WitnessArgs::load(args)?,
+            None => WitnessArgs::new(),
+        };
+        println!(
+            "{}",
+            protocol.finalize_hex(transaction_name, leaf, &witness_args, &key_manager)?
+        );
+
+        Ok(())
+    }
+
+    fn sighash(
+        &self,
+        tx: &str,
+        prevouts: &[String],
+        input_index: usize,
+        script: Option<&str>,
+        sighash_type: &str,
+    ) -> Result<()> {
+        let transaction: Transaction = deserialize_hex(tx)?;
+        let prevouts = prevouts
+            .iter()
+            .map(|prevout| parse_prevout(prevout))
+            .collect::<Result<Vec<_>>>()?;
+        let script = script.map(ScriptBuf::from_hex).transpose()?;
+
+        let spends_taproot = prevouts
+            .get(input_index)
+            .is_some_and(|prevout| prevout.script_pubkey.is_p2tr());
+        let sighash_type = if spends_taproot {
+            SighashType::Taproot(TapSighashType::from_str(sighash_type)?)
+        } else {
+            SighashType::Ecdsa(EcdsaSighashType::from_str(sighash_type)?)
+        };
+
+        let message = raw_sighash(
+            &transaction,
+            &prevouts,
+            input_index,
+            script.as_deref(),
+            &sighash_type,
+        )?;
+        println!("{}", message);
+
+        Ok(())
+    }
+
     fn key_manager(&self) -> Result<KeyManager> {
         Ok(create_key_manager_from_config(
             &self.config.key_manager,
@@ -518,3 +841,44 @@ impl Cli {
         )?)
     }
 }
+
+// Parses an output given as <value>:<script pubkey hex>.
+fn parse_prevout(prevout: &str) -> Result<TxOut> {
+    let (value, script_pubkey) = prevout.split_once(':').ok_or(anyhow!(
+        "Invalid prevout {}, expected <value>:<script pubkey hex>",
+        prevout
+    ))?;
+
+    Ok(TxOut {
+        value: Amount::from_sat(value.parse()?),
+        script_pubkey: ScriptBuf::from_hex(script_pubkey)?,
+    })
+}
+
+// Unspendable internal key of the connection between two transactions.
+fn unspendable_internal_key(protocol: &mut Protocol, from: &str, to: &str) -> Result<PublicKey> {
+    let key = protocol.create_deterministic_unspendable_key(&format!("{}_{}", from, to))?;
+    Ok(PublicKey::from(key.public_key(Parity::Even)))
+}
+
+// Renders a DOT graph with the graphviz `dot` command.
+fn render_with_graphviz(graph: &str, format: &str) -> Result<Vec<u8>> {
+    let mut dot = Command::new("dot")
+        .arg(format!("-T{}", format))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run graphviz, is the dot command installed?")?;
+
+    dot.stdin
+        .take()
+        .ok_or(anyhow!("Failed to write to graphviz"))?
+        .write_all(graph.as_bytes())?;
+
+    let rendered = dot.wait_with_output()?;
+    if !rendered.status.success() {
+        return Err(anyhow!("Graphviz failed with {}", rendered.status));
+    }
+
+    Ok(rendered.stdout)
+}
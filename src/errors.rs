@@ -4,7 +4,7 @@ use bitcoin::{
     secp256k1::scalar::OutOfRangeError,
     sighash::{P2wpkhError, SighashTypeParseError, TaprootError},
     taproot::TaprootBuilderError,
-    transaction,
+    transaction, Txid, XOnlyPublicKey,
 };
 use key_manager::{
     errors::{KeyManagerError, WinternitzError},
@@ -14,7 +14,7 @@ use thiserror::Error;
 
 use config as settings;
 
-use crate::types::input::SpendMode;
+use crate::types::{input::SpendMode, limits::ProtocolLimit};
 
 #[derive(Error, Debug)]
 pub enum UnspendableKeyError {
@@ -68,6 +68,38 @@ pub enum GraphError {
 
     #[error("Transaction name cannot be empty")]
     EmptyTransactionName,
+
+    #[error("Output label {1} already used in transaction {0}")]
+    DuplicateOutputLabel(String, String),
+
+    #[error("Input label {1} already used in transaction {0}")]
+    DuplicateInputLabel(String, String),
+
+    #[error("Missing output labeled {1} in transaction {0}")]
+    MissingOutputLabel(String, String),
+
+    #[error("Missing input labeled {1} in transaction {0}")]
+    MissingInputLabel(String, String),
+
+    #[error("Input {1} of transaction {0} is already connected")]
+    InputAlreadyConnected(String, usize),
+
+    #[error(
+        "Node index of transaction {0} does not match the graph nodes, the indexes must be rebuilt"
+    )]
+    IndexDrift(String),
+
+    #[error("Failed to export graph: {0}")]
+    ExportError(String),
+
+    #[error("Connection {0} missing in graph")]
+    UnknownConnection(String),
+
+    #[error("Connection name {0} is used by {1} connections")]
+    AmbiguousConnection(String, usize),
+
+    #[error("Transaction {0} is external and cannot be duplicated")]
+    ExternalSubtree(String),
 }
 
 #[derive(Error, Debug)]
@@ -87,11 +119,38 @@ pub enum ScriptError {
     #[error("Script name cannot be empty")]
     EmptyScriptName,
 
+    #[error("Constant name cannot be empty")]
+    EmptyConstantName,
+
+    #[error("Constant value {0} does not fit in a signed 64-bit number")]
+    ConstantOutOfRange(u64),
+
+    #[error("Constant {0} with value {1} is not pushed by the script at its placeholder")]
+    ConstantNotInScript(String, String),
+
+    #[error("Constant of {0} bytes is too large to be pushed by a script")]
+    ConstantTooLarge(usize),
+
     #[error("Invalid key type. Expected {0}, got {1}")]
     InvalidKeyType(String, String),
 
     #[error("SHA256 is not supported for Winternitz signatures")]
     UnsupportedWinternitzTypeError,
+
+    #[error("Invalid taptree layout: {0}")]
+    InvalidTapTreeLayout(String),
+
+    #[error("OP_RETURN data of {0} bytes does not fit in the {1} bytes available for OP_RETURN scripts in the transaction")]
+    OpReturnDataTooLarge(usize, usize),
+
+    #[error("Invalid multisig threshold {0} for {1} keys")]
+    InvalidMultisigThreshold(usize, usize),
+
+    #[error("Script already has a key named {0}")]
+    DuplicateScriptKey(String),
+
+    #[error("Transaction would have {0} OP_RETURN outputs but the relay policy allows {1}")]
+    TooManyOpReturnOutputs(usize, usize),
 }
 
 #[derive(Error, Debug)]
@@ -226,6 +285,274 @@ pub enum ProtocolBuilderError {
 
     #[error("Invalid spend mode. Expected {0}, got {1}")]
     InvalidSpendMode(String, SpendMode),
+
+    #[error("Invalid re-signed variant for transaction {0}: {1}")]
+    InvalidResignedTransaction(String, String),
+
+    #[error("Protocol instance {0} is already scheduled for signing")]
+    ProtocolAlreadyScheduled(String),
+
+    #[error("Constant {0} is not defined in the protocol constants table")]
+    UndefinedConstant(String),
+
+    #[error("Constant {0} embedded in transaction {1}, output {2}, script {3} has value {4} but the protocol defines {5}")]
+    ConstantMismatch(String, String, usize, usize, String, String),
+
+    #[error("No deserializer registered for custom output kind {0}")]
+    UnregisteredCustomOutput(String),
+
+    #[error("Output {1} is not declared in external transaction {0}")]
+    UndeclaredExternalOutput(String, usize),
+
+    #[error(
+        "Output {1} of external transaction {0} is opaque and cannot be spent by the protocol"
+    )]
+    OpaqueExternalOutput(String, usize),
+
+    #[error("External transaction {0} is declared with txid {1} but the connection uses {2}")]
+    ExternalTxidMismatch(String, Txid, Txid),
+
+    #[error("Constant {0} has different values in the merged protocols")]
+    MergeConstantConflict(String),
+
+    #[error("The {0} of the merged protocol conflicts with this protocol")]
+    MergePolicyConflict(String),
+
+    #[error("Transaction {0} is not in the broadcast queue")]
+    MissingQueuedTransaction(Txid),
+
+    #[error("Missing value for template parameter {0}")]
+    MissingTemplateParameter(String),
+
+    #[error("Invalid template parameter in {0}: {1}")]
+    InvalidTemplateParameter(String, String),
+
+    #[error("Failed to query the chain state")]
+    ChainStateError(#[from] BroadcastError),
+
+    #[error("Missing input arguments to broadcast transaction {0}")]
+    MissingBroadcastArgs(String),
+
+    #[error("Transaction {0} is not committed in the protocol commitment hash")]
+    TransactionNotCommitted(String),
+
+    #[error("Derivation data does not prove that internal key {0} is unspendable")]
+    InvalidUnspendableProof(XOnlyPublicKey),
+
+    #[error("Output {1} of transaction {0} is script-only but its internal key {2} has no unspendable proof")]
+    MissingUnspendableProof(String, usize, XOnlyPublicKey),
+
+    #[error("Missing persisted chunk {0}")]
+    MissingProtocolChunk(String),
+
+    #[error("Failed to serialize or deserialize protocol data")]
+    SerializationError(#[from] SerializationError),
+
+    #[error("Output {1} of transaction {0} has value {2} below the dust limit {3}")]
+    DustOutput(String, usize, u64, u64),
+
+    #[error("Protocol {0} is frozen, unfreeze it before changing its structure")]
+    ProtocolFrozen(String),
+
+    #[error("A reason is required to unfreeze a protocol")]
+    MissingUnfreezeReason,
+
+    #[error("No change address was set")]
+    MissingChangeAddress,
+
+    #[error("No protocol transaction spends an external transaction to receive the change output")]
+    MissingFundedTransaction,
+
+    #[error("Transaction {0} spends {1} sats but requires {2} sats for its outputs and fee")]
+    InsufficientFunding(String, u64, u64),
+
+    #[error("Input {1} of transaction {0} has a relative timelock and must signal replaceability")]
+    CsvConstrainedInput(String, usize),
+
+    #[error("Protocol exceeds the maximum {0}: {1} > {2}")]
+    LimitExceeded(ProtocolLimit, usize, usize),
+
+    #[error("Signature bundle of protocol {1} cannot be imported into protocol {0}")]
+    SignatureBundleMismatch(String, String),
+
+    #[error("Signature {2} of input {1} of transaction {0} is attributed to an unexpected key")]
+    UnexpectedSigningKey(String, usize, usize),
+
+    #[error("Signature {2} of input {1} of transaction {0} is not valid")]
+    InvalidImportedSignature(String, usize, usize),
+
+    #[error("Invalid consensus stream: {0}")]
+    ConsensusStreamError(String),
+
+    #[error(
+        "Party {3} does not control the key signing input {1} (leaf {2:?}) of transaction {0}"
+    )]
+    OwnerKeyMismatch(String, usize, Option<usize>, String),
+
+    #[error("Nonce bundle of {1} cannot be imported into {0}")]
+    NonceBundleMismatch(String, String),
+
+    #[error("Missing nonce for message {0}")]
+    MissingNonce(String),
+
+    #[error("Unexpected nonce for message {0}")]
+    UnexpectedNonce(String),
+
+    #[error("Invalid nonce for message {0}")]
+    InvalidNonce(String),
+
+    #[error("Partial signature bundle of {1} cannot be imported into {0}")]
+    PartialSignatureBundleMismatch(String, String),
+
+    #[error("Missing partial signature for message {0}")]
+    MissingPartialSignature(String),
+
+    #[error("Unexpected partial signature for message {0}")]
+    UnexpectedPartialSignature(String),
+
+    #[error("Invalid partial signature for message {0}")]
+    InvalidPartialSignature(String),
+
+    #[error("History of protocol {0} is not recorded")]
+    HistoryNotRecorded(String),
+
+    #[error("Unknown history step {0}, the last recorded step is {1}")]
+    UnknownHistoryStep(usize, usize),
+
+    #[error("Transactions {0} and {1} belong to alternative branches, at most one can be mined")]
+    ConflictingTransactions(String, String),
+
+    #[error("Template manifest of protocol {1} cannot be checked against protocol {0}")]
+    TemplateManifestMismatch(String, String),
+
+    #[error("Template of leaf {2} of output {1} of transaction {0} does not match the agreed one")]
+    LeafTemplateMismatch(String, usize, usize),
+
+    #[error("Execution trace has no steps")]
+    EmptyTrace,
+
+    #[error("Step {0} of the execution trace has no commitment leaves")]
+    MissingStepCommitments(usize),
+
+    #[error("Invalid protocol spec: {0}")]
+    InvalidSpec(String),
+
+    #[error("Invalid witness args: {0}")]
+    InvalidWitnessArgs(String),
+
+    #[error("Input {1} of transaction {0} signs with SIGHASH_SINGLE but there is no output {1}")]
+    MissingSingleOutput(String, usize),
+
+    #[error("There is no leaf named {0} in the output")]
+    UnknownLeafName(String),
+
+    #[error("Missing value to sign with Winternitz key {0}")]
+    MissingWinternitzValue(String),
+
+    #[error("Invalid Winternitz witness: {0}")]
+    InvalidWinternitzWitness(String),
+
+    #[error("Missing Winternitz public key {0}")]
+    MissingWinternitzPublicKey(String),
+
+    #[error("Invalid Winternitz signature for key {0}")]
+    InvalidWinternitzSignature(String),
+
+    #[error("External signer failed: {0}")]
+    ExternalSignerError(String),
+
+    #[error("Signing responses of {1} cannot be imported into {0}")]
+    SigningResponsesMismatch(String, String),
+
+    #[error("Unexpected signing response for {0}")]
+    UnexpectedSigningResponse(String),
+
+    #[error("Invalid signing response for {0}")]
+    InvalidSigningResponse(String),
+
+    #[error("Transaction {0} spends {1} sats but its outputs are worth {2} sats plus a {3} sats fee, {4} sats short")]
+    ValueShortfall(String, u64, u64, u64, u64),
+
+    #[error("Transaction {0} cannot use version {1}, supported versions are 1, 2 and 3")]
+    UnsupportedTransactionVersion(String, i32),
+
+    #[error("Cannot broadcast an empty package")]
+    EmptyBroadcastPackage,
+
+    #[error("Transaction {0} is not spent by the last transaction of the package")]
+    UnrelatedPackageTransaction(Txid),
+
+    #[error("Transaction {0} has {1} inputs but {2} input args were given")]
+    InputArgsCountMismatch(String, usize, usize),
+
+    #[error("Invalid args for input {1} of transaction {0}: {2}")]
+    InvalidInputArgs(String, usize, String),
+
+    #[error("Protocol {0} is not in the registry")]
+    UnregisteredProtocol(String),
+
+    #[error("Protocol {0} was loaded at revision {1} but revision {2} is stored")]
+    RevisionConflict(String, u64, u64),
+
+    #[error("Transaction {0} was not ready to send after mining {1} blocks")]
+    TransactionNotReady(String, u32),
+
+    #[error("Transaction {0} has {1} confirmations, expected at least {2}")]
+    TransactionUnconfirmed(String, u32, u32),
+
+    #[error("Rounds need at least 2 branches, got {0}")]
+    InvalidArity(usize),
+
+    #[error("Output of {0} needs {1} leaves, one per branch, got {2}")]
+    InvalidBranchLeaves(String, usize, usize),
+
+    #[error("Transaction {0} has no recorded speedup output")]
+    MissingSpeedupOutput(String),
+
+    #[error("Output {1} of transaction {0} is neither a P2WPKH speedup output nor an anchor")]
+    UnsupportedSpeedupOutput(String, usize),
+
+    #[error("Invalid speedup data: {0}")]
+    InvalidSpeedupData(String),
+
+    #[error("Signature of input {1} of transaction {0} is missing")]
+    MissingInputSignature(String, usize),
+
+    #[error("Winternitz key derivation index {0} is used by {1} and by {2}")]
+    WinternitzKeyReuse(u32, String, String),
+
+    #[error("Invalid signature for slot {1} of multisig leaf {0}")]
+    InvalidMultisigSlotSignature(usize, usize),
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum BroadcastError {
+    #[error("Transaction rejected by the node: {0}")]
+    Rejected(String),
+
+    #[error("Node unavailable: {0}")]
+    Unavailable(String),
+}
+
+#[derive(Error, Debug)]
+pub enum SerializationError {
+    #[error("Missing or invalid serialization header")]
+    InvalidHeader,
+
+    #[error("Unknown serialization format {0}")]
+    UnknownFormat(u8),
+
+    #[error("Serialization version {0} is not supported, newest supported version is {1}")]
+    UnsupportedVersion(u8, u8),
+
+    #[error("Failed to encode data as {0}: {1}")]
+    EncodeError(String, String),
+
+    #[error("Failed to decode {0} data: {1}")]
+    DecodeError(String, String),
+
+    #[error("Protocol views cannot read {0} data")]
+    UnsupportedView(String),
 }
 
 #[derive(Error, Debug)]
@@ -0,0 +1,5 @@
+//! Drives a protocol on chain, see `ProtocolRunner`.
+
+pub mod runner;
+
+pub use runner::{Decider, NextTransaction, ProtocolRunner, RunnerEvent, TransactionState};
@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    broadcast::{rules::txid, Broadcaster, ChainState},
+    builder::Protocol,
+    errors::{BroadcastError, ProtocolBuilderError},
+    types::{plan::ExecutionPlan, witness_args::WitnessArgs, Signer},
+};
+
+/// Transaction chosen to be broadcast, with the leaf its taproot inputs spend and the extra
+/// witness items they push, see `Protocol::finalize`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NextTransaction {
+    pub transaction_name: String,
+    pub leaf: Option<usize>,
+    pub witness_args: WitnessArgs,
+}
+
+impl NextTransaction {
+    /// Spends the key path of taproot inputs, or the leaves given in `with_witness_args`.
+    pub fn new(transaction_name: &str) -> Self {
+        Self {
+            transaction_name: transaction_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn spending_leaf(mut self, leaf: usize) -> Self {
+        self.leaf = Some(leaf);
+        self
+    }
+
+    pub fn with_witness_args(mut self, witness_args: WitnessArgs) -> Self {
+        self.witness_args = witness_args;
+        self
+    }
+}
+
+/// Decisions of the party running the protocol, such as the leaf to spend when a transaction
+/// confirms. Closures taking the protocol and the name of the confirmed transaction are deciders
+/// too.
+pub trait Decider {
+    /// Called once when a transaction of the protocol (external ones included) confirms, returns
+    /// the transactions to broadcast in response.
+    fn on_confirmed(
+        &self,
+        protocol: &Protocol,
+        transaction_name: &str,
+    ) -> Result<Vec<NextTransaction>, ProtocolBuilderError>;
+}
+
+impl<F> Decider for F
+where
+    F: Fn(&Protocol, &str) -> Result<Vec<NextTransaction>, ProtocolBuilderError>,
+{
+    fn on_confirmed(
+        &self,
+        protocol: &Protocol,
+        transaction_name: &str,
+    ) -> Result<Vec<NextTransaction>, ProtocolBuilderError> {
+        self(protocol, transaction_name)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub enum TransactionState {
+    /// Neither scheduled nor seen by the chain.
+    #[default]
+    Idle,
+    /// Waiting for the transactions it spends to be seen and for its timelocks.
+    Scheduled,
+    /// In the mempool, or mined with fewer confirmations than the runner requires.
+    Unconfirmed,
+    Confirmed,
+    /// Scheduled, but an alternative branch was seen first so it can no longer be mined. It is
+    /// scheduled again if that branch leaves the chain.
+    Abandoned,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunnerEvent {
+    Confirmed(String),
+    Broadcasted(String, Txid),
+    /// The node rejected the transaction, which stays scheduled to be sent again.
+    Rejected(String, String),
+    Abandoned(String),
+    /// An unconfirmed transaction left the mempool, e.g. evicted or replaced. It is scheduled to
+    /// be sent again if the runner sent it.
+    Dropped(String),
+    /// An abandoned transaction is scheduled again, as the alternative branch left the chain.
+    Revived(String),
+}
+
+/// Advances a built and signed protocol through its graph: it watches the confirmations of its
+/// transactions, asks the `Decider` what to broadcast when one confirms, and sends the chosen
+/// transactions once the ones they spend are seen and their timelocks allow it. Call `step`
+/// periodically, e.g. once per block.
+pub struct ProtocolRunner<'a, S: Signer + ?Sized> {
+    protocol: &'a Protocol,
+    signer: &'a S,
+    decider: Box<dyn Decider + 'a>,
+    plan: ExecutionPlan,
+    // External transactions first, then the rest in topological order
+    watched: Vec<String>,
+    confirmations: u32,
+    states: BTreeMap<String, TransactionState>,
+    // Transactions to send, kept until they confirm so they can be sent again if dropped
+    scheduled: BTreeMap<String, NextTransaction>,
+}
+
+impl<'a, S: Signer + ?Sized> ProtocolRunner<'a, S> {
+    pub fn new(
+        protocol: &'a Protocol,
+        signer: &'a S,
+        decider: impl Decider + 'a,
+    ) -> Result<Self, ProtocolBuilderError> {
+        let plan = protocol.execution_plan()?;
+        let mut watched: Vec<String> = protocol.external_transactions().keys().cloned().collect();
+        for step in plan.steps.iter() {
+            if !watched.contains(&step.transaction_name) {
+                watched.push(step.transaction_name.clone());
+            }
+        }
+        let states = watched
+            .iter()
+            .map(|name| (name.clone(), TransactionState::Idle))
+            .collect();
+
+        Ok(Self {
+            protocol,
+            signer,
+            decider: Box::new(decider),
+            plan,
+            watched,
+            confirmations: 1,
+            states,
+            scheduled: BTreeMap::new(),
+        })
+    }
+
+    /// Confirmations a transaction needs before it counts as confirmed, 1 by default.
+    pub fn with_confirmations(mut self, confirmations: u32) -> Self {
+        self.confirmations = confirmations.max(1);
+        self
+    }
+
+    /// Schedules a transaction to be broadcast, e.g. the first one of the protocol. Abandoned
+    /// transactions are scheduled again, transactions already scheduled or seen by the chain are
+    /// left as they are.
+    pub fn schedule(&mut self, next: NextTransaction) -> Result<(), ProtocolBuilderError> {
+        self.protocol.transaction_by_name(&next.transaction_name)?;
+
+        let state = self
+            .states
+            .entry(next.transaction_name.clone())
+            .or_default();
+        if matches!(*state, TransactionState::Idle | TransactionState::Abandoned) {
+            *state = TransactionState::Scheduled;
+            self.scheduled.insert(next.transaction_name.clone(), next);
+        }
+
+        Ok(())
+    }
+
+    pub fn state(&self, transaction_name: &str) -> TransactionState {
+        self.states
+            .get(transaction_name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn states(&self) -> &BTreeMap<String, TransactionState> {
+        &self.states
+    }
+
+    /// Returns true if no transaction is scheduled or waiting for confirmations.
+    pub fn is_settled(&self) -> bool {
+        !self.states.values().any(|state| {
+            matches!(
+                state,
+                TransactionState::Scheduled | TransactionState::Unconfirmed
+            )
+        })
+    }
+
+    /// Updates the state of every transaction from the node, then broadcasts the scheduled
+    /// transactions that are ready. Returns what happened, in order. Fails if the node cannot be
+    /// reached, leaving the runner ready to step again.
+    pub fn step<N: ChainState + Broadcaster>(
+        &mut self,
+        node: &N,
+    ) -> Result<Vec<RunnerEvent>, ProtocolBuilderError> {
+        let mut events = vec![];
+        self.watch(node, &mut events)?;
+        self.abandon_excluded(&mut events);
+        self.revive_abandoned(&mut events);
+        self.broadcast_ready(node, &mut events)?;
+        Ok(events)
+    }
+
+    // Transactions are checked in topological order, so the decider sees parents confirm first
+    fn watch<N: ChainState>(
+        &mut self,
+        node: &N,
+        events: &mut Vec<RunnerEvent>,
+    ) -> Result<(), ProtocolBuilderError> {
+        for name in self.watched.clone() {
+            let state = self.state(&name);
+            if state == TransactionState::Confirmed {
+                continue;
+            }
+
+            let confirmations = node.confirmations(&txid(self.protocol, &name)?)?;
+            let state = match confirmations {
+                Some(confirmations) if confirmations >= self.confirmations => {
+                    TransactionState::Confirmed
+                }
+                Some(_) => TransactionState::Unconfirmed,
+                None if state == TransactionState::Unconfirmed => {
+                    debug!("Transaction {} dropped from the mempool", name);
+                    events.push(RunnerEvent::Dropped(name.clone()));
+                    match self.scheduled.contains_key(&name) {
+                        true => TransactionState::Scheduled,
+                        false => TransactionState::Idle,
+                    }
+                }
+                None => continue,
+            };
+
+            self.states.insert(name.clone(), state.clone());
+
+            if state == TransactionState::Confirmed {
+                self.scheduled.remove(&name);
+                debug!("Transaction {} confirmed", name);
+                events.push(RunnerEvent::Confirmed(name.clone()));
+                for next in self.decider.on_confirmed(self.protocol, &name)? {
+                    self.schedule(next)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn abandon_excluded(&mut self, events: &mut Vec<RunnerEvent>) {
+        let abandoned: Vec<String> = self
+            .scheduled
+            .keys()
+            .filter(|name| {
+                self.state(name) == TransactionState::Scheduled && self.is_excluded(name)
+            })
+            .cloned()
+            .collect();
+
+        for name in abandoned {
+            debug!(
+                "Transaction {} abandoned, an alternative branch was seen",
+                name
+            );
+            self.states
+                .insert(name.clone(), TransactionState::Abandoned);
+            events.push(RunnerEvent::Abandoned(name));
+        }
+    }
+
+    fn revive_abandoned(&mut self, events: &mut Vec<RunnerEvent>) {
+        let revived: Vec<String> = self
+            .scheduled
+            .keys()
+            .filter(|name| {
+                self.state(name) == TransactionState::Abandoned && !self.is_excluded(name)
+            })
+            .cloned()
+            .collect();
+
+        for name in revived {
+            debug!(
+                "Transaction {} scheduled again, the alternative branch left the chain",
+                name
+            );
+            self.states
+                .insert(name.clone(), TransactionState::Scheduled);
+            events.push(RunnerEvent::Revived(name));
+        }
+    }
+
+    fn is_excluded(&self, transaction_name: &str) -> bool {
+        self.plan
+            .step(transaction_name)
+            .is_some_and(|step| step.excludes.iter().any(|other| self.is_seen(other)))
+    }
+
+    fn broadcast_ready<N: ChainState + Broadcaster>(
+        &mut self,
+        node: &N,
+        events: &mut Vec<RunnerEvent>,
+    ) -> Result<(), ProtocolBuilderError> {
+        let ready: Vec<String> = self
+            .plan
+            .steps
+            .iter()
+            .filter(|step| self.state(&step.transaction_name) == TransactionState::Scheduled)
+            .filter(|step| step.depends_on.iter().all(|parent| self.is_seen(parent)))
+            .map(|step| step.transaction_name.clone())
+            .collect();
+
+        for name in ready {
+            if !self.protocol.ready_to_send(&name, node)? {
+                continue;
+            }
+
+            let next = &self.scheduled[&name];
+            let transaction =
+                self.protocol
+                    .finalize(&name, next.leaf, &next.witness_args, self.signer)?;
+
+            match node.broadcast(&transaction) {
+                Ok(txid) => {
+                    debug!("Transaction {} broadcasted", name);
+                    self.states
+                        .insert(name.clone(), TransactionState::Unconfirmed);
+                    events.push(RunnerEvent::Broadcasted(name, txid));
+                }
+                Err(BroadcastError::Rejected(reason)) => {
+                    warn!("Transaction {} rejected: {}", name, reason);
+                    events.push(RunnerEvent::Rejected(name, reason));
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_seen(&self, transaction_name: &str) -> bool {
+        matches!(
+            self.state(transaction_name),
+            TransactionState::Unconfirmed | TransactionState::Confirmed
+        )
+    }
+}
@@ -24,7 +24,7 @@ fn witness_item_overhead(len: usize) -> usize {
 }
 
 /// Size of tx serialized with empty witnesses (aka "stripped" size).
-fn stripped_size_bytes(tx: &Transaction) -> usize {
+pub(crate) fn stripped_size_bytes(tx: &Transaction) -> usize {
     let mut t = tx.clone();
     for inp in &mut t.input {
         inp.witness = Witness::default();
@@ -36,7 +36,7 @@ fn stripped_size_bytes(tx: &Transaction) -> usize {
 
 /// Estimate witness bytes for a single input, according to its kind.
 /// NOTE: This returns only the per-input witness bytes; the caller should add marker+flag (2 bytes) once per tx if any witness exists.
-fn estimate_input_witness_bytes(
+pub(crate) fn estimate_input_witness_bytes(
     transaction_name: &str,
     input: &InputType,
     index: usize,
@@ -101,7 +101,10 @@ fn estimate_input_witness_bytes(
 
             max_size
         }
-        OutputType::SegwitUnspendable { .. } | OutputType::ExternalUnknown { .. } => 0,
+        OutputType::SegwitUnspendable { .. }
+        | OutputType::PayToAnchor { .. }
+        | OutputType::ExternalUnknown { .. } => 0,
+        OutputType::Custom { output } => output.estimate_witness_bytes(),
     };
 
     Ok(size)
@@ -126,6 +129,19 @@ pub fn estimate_min_relay_fee(
     inputs: &[InputType],
     feerate_sat_per_vb: u64, // e.g., 1 for floor; consider a buffer in practice
     safety_margin_percent: u64, // Optional safety margin in satoshis
+) -> Result<u64, GraphError> {
+    let vbytes = estimate_vsize(tx, transaction_name, inputs)?;
+    let estimation = feerate_sat_per_vb * vbytes * (100 + safety_margin_percent) / 100;
+
+    Ok(estimation)
+}
+
+/// Estimate the vsize of `tx` once the witnesses of its inputs are filled, using the largest
+/// spend path of each input.
+pub fn estimate_vsize(
+    tx: &Transaction,
+    transaction_name: &str,
+    inputs: &[InputType],
 ) -> Result<u64, GraphError> {
     let stripped = stripped_size_bytes(tx);
 
@@ -138,8 +154,5 @@ pub fn estimate_min_relay_fee(
     // If there is at least one witness-bearing input, add marker+flag (2 bytes) once.
     let total_witness = witness_sum + if witness_sum > 0 { 2 } else { 0 };
 
-    let vbytes = vbytes_from_parts(stripped, total_witness);
-    let estimation = feerate_sat_per_vb * vbytes * (100 + safety_margin_percent) / 100;
-
-    Ok(estimation)
+    Ok(vbytes_from_parts(stripped, total_witness))
 }
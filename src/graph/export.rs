@@ -0,0 +1,208 @@
+use std::{collections::HashMap, fmt::Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::GraphError, types::output::OutputType};
+
+use super::graph::TransactionGraph;
+
+/// Graph exported by `GraphOptions::Json`, for dashboards and tools that render protocols
+/// without graphviz.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct GraphExport {
+    pub nodes: Vec<ExportedNode>,
+    pub edges: Vec<ExportedEdge>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExportedNode {
+    pub name: String,
+    pub txid: String,
+    pub external: bool,
+    pub inputs: usize,
+    pub fee: u64,
+    pub outputs: Vec<ExportedOutput>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExportedOutput {
+    pub index: usize,
+    pub value: u64,
+    pub kind: String,
+    pub leaves: Vec<LeafSummary>,
+}
+
+/// Summary of a taproot leaf, or of the script of a P2WSH output with leaf 0.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LeafSummary {
+    pub leaf: usize,
+    pub script_size: usize,
+    pub verifying_key: Option<String>,
+    pub keys: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExportedEdge {
+    pub name: String,
+    pub from: String,
+    pub output_index: usize,
+    pub to: String,
+    pub input_index: usize,
+    /// The output has other spenders, only one of them can be mined.
+    pub alternative: bool,
+    pub color: Option<String>,
+}
+
+impl TransactionGraph {
+    /// Nodes and connections of the graph, in insertion order.
+    pub fn export(
+        &self,
+        input_colors: &HashMap<(String, usize), String>,
+    ) -> Result<GraphExport, GraphError> {
+        let nodes = self
+            .nodes()
+            .map(|node| {
+                let sum_in = node
+                    .inputs
+                    .iter()
+                    .filter_map(|input| input.output_type().ok())
+                    .map(|output| output.get_value().to_sat())
+                    .sum::<u64>();
+                let sum_out = node
+                    .transaction
+                    .output
+                    .iter()
+                    .map(|output| output.value.to_sat())
+                    .sum::<u64>();
+
+                ExportedNode {
+                    name: node.name.clone(),
+                    txid: node.transaction.compute_txid().to_string(),
+                    external: node.external,
+                    inputs: node.transaction.input.len(),
+                    fee: sum_in.saturating_sub(sum_out),
+                    outputs: node
+                        .outputs
+                        .iter()
+                        .enumerate()
+                        .map(|(index, output)| export_output(index, output))
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let mut edges = vec![];
+        for stored in self.stored_connections() {
+            let output_index = stored.connection.output_index as usize;
+            let input_index = stored.connection.input_index as usize;
+            edges.push(ExportedEdge {
+                alternative: self.spenders(&stored.from, output_index)?.len() > 1,
+                color: input_colors.get(&(stored.to.clone(), input_index)).cloned(),
+                name: stored.connection.name,
+                from: stored.from,
+                output_index,
+                to: stored.to,
+                input_index,
+            });
+        }
+
+        Ok(GraphExport { nodes, edges })
+    }
+
+    pub(crate) fn visualize_json(
+        &self,
+        input_colors: &HashMap<(String, usize), String>,
+    ) -> Result<String, GraphError> {
+        serde_json::to_string_pretty(&self.export(input_colors)?)
+            .map_err(|error| GraphError::ExportError(error.to_string()))
+    }
+
+    /// Mermaid flowchart of the graph. Nodes are identified by their position, as transaction
+    /// names may contain characters that are not valid Mermaid identifiers.
+    pub(crate) fn visualize_mermaid(
+        &self,
+        input_colors: &HashMap<(String, usize), String>,
+    ) -> Result<String, GraphError> {
+        let export = self.export(input_colors)?;
+        let ids: HashMap<&str, String> = export
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.name.as_str(), format!("n{}", index)))
+            .collect();
+
+        let mut result = "flowchart LR\n".to_owned();
+        for node in export.nodes.iter() {
+            let values = node
+                .outputs
+                .iter()
+                .map(|output| output.value.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(
+                result,
+                "    {}[\"{}<br/>{}<br/>fee: {}<br/>out: [{}]\"]",
+                ids[node.name.as_str()],
+                escape(&node.name),
+                &node.txid[node.txid.len() - 8..],
+                node.fee,
+                values,
+            )
+            .unwrap();
+        }
+
+        for edge in export.edges.iter() {
+            let arrow = if edge.alternative { "-.->" } else { "-->" };
+            writeln!(
+                result,
+                "    {} {}|\"{} ({}:{})\"| {}",
+                ids[edge.from.as_str()],
+                arrow,
+                escape(&edge.name),
+                edge.output_index,
+                edge.input_index,
+                ids[edge.to.as_str()],
+            )
+            .unwrap();
+        }
+
+        for (position, edge) in export.edges.iter().enumerate() {
+            if let Some(color) = &edge.color {
+                writeln!(
+                    result,
+                    "    linkStyle {} stroke:{},color:{}",
+                    position, color, color
+                )
+                .unwrap();
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+fn export_output(index: usize, output: &OutputType) -> ExportedOutput {
+    ExportedOutput {
+        index,
+        value: output.get_value().to_sat(),
+        kind: output.get_name().to_string(),
+        leaves: output
+            .get_scripts()
+            .into_iter()
+            .map(|(leaf, script)| LeafSummary {
+                leaf,
+                script_size: script.get_script().len(),
+                verifying_key: script.get_verifying_key().map(|key| key.to_string()),
+                keys: script
+                    .get_keys()
+                    .iter()
+                    .map(|key| key.name().to_string())
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('"', "#quot;")
+}
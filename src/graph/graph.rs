@@ -1,8 +1,12 @@
-use std::{collections::HashMap, vec};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::RandomState,
+    vec,
+};
 
 use bitcoin::{secp256k1::Message, Amount, Transaction, TxOut, Txid};
 use petgraph::{
-    algo::toposort,
+    algo::{all_simple_paths, toposort},
     graph::{EdgeIndex, NodeIndex},
     visit::EdgeRef,
     Graph,
@@ -11,7 +15,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     errors::GraphError,
-    graph::estimate::estimate_min_relay_fee,
+    graph::estimate::{estimate_input_witness_bytes, estimate_min_relay_fee},
     types::{
         input::{InputSignatures, InputType, SighashType, Signature, SpendMode},
         output::OutputType,
@@ -32,6 +36,20 @@ pub(crate) struct Node {
     pub(crate) outputs: Vec<OutputType>,
     pub(crate) inputs: Vec<InputType>,
     pub(crate) external: bool,
+    #[serde(default)]
+    pub(crate) resigned: Vec<Transaction>,
+    #[serde(default)]
+    pub(crate) output_labels: HashMap<String, usize>,
+    #[serde(default)]
+    pub(crate) input_labels: HashMap<String, usize>,
+    /// Set when the transaction changed since the last build, so its txid, sighashes and
+    /// signatures (and those of its descendants) must be recomputed.
+    #[serde(default = "default_dirty")]
+    pub(crate) dirty: bool,
+}
+
+fn default_dirty() -> bool {
+    true
 }
 
 impl Node {
@@ -42,6 +60,10 @@ impl Node {
             outputs: vec![],
             inputs: vec![],
             external,
+            resigned: vec![],
+            output_labels: HashMap::new(),
+            input_labels: HashMap::new(),
+            dirty: true,
         }
     }
 
@@ -69,6 +91,15 @@ impl Connection {
     }
 }
 
+/// Connection stored with the names of the transactions it links, used to persist the graph in
+/// separate chunks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StoredConnection {
+    pub(crate) from: String,
+    pub(crate) to: String,
+    pub(crate) connection: Connection,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TransactionGraph {
     graph: Graph<Node, Connection>,
@@ -81,10 +112,21 @@ impl Default for TransactionGraph {
     }
 }
 
+const EXTERNAL_FILL_COLOR: &str = "lightgrey";
+const INTERNAL_FILL_COLOR: &str = "lightblue";
+const DETAILED_SCRIPT_CHARS: usize = 48;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum GraphOptions {
     Default,
     EdgeArrows,
+    /// Mermaid flowchart, for markdown docs and web pages.
+    Mermaid,
+    /// JSON with the nodes and connections, see `GraphExport`.
+    Json,
+    /// DOT with the sighash type, spend mode and signing status of each input, the leaf scripts
+    /// of each output, and external transactions filled in a different color.
+    Detailed,
 }
 
 impl TransactionGraph {
@@ -125,7 +167,10 @@ impl TransactionGraph {
         transaction: Transaction,
     ) -> Result<(), GraphError> {
         let node = self.get_node_mut(name)?;
-        node.transaction = transaction;
+        if node.transaction != transaction {
+            node.transaction = transaction;
+            node.dirty = true;
+        }
         Ok(())
     }
 
@@ -139,6 +184,7 @@ impl TransactionGraph {
         let node = self.get_node_mut(name)?;
         node.transaction = transaction;
         node.inputs.push(InputType::new(spend_mode, sighash_type));
+        node.dirty = true;
         Ok(())
     }
 
@@ -151,6 +197,7 @@ impl TransactionGraph {
         let node = self.get_node_mut(name)?;
         node.transaction = transaction;
         node.outputs.push(output_type);
+        node.dirty = true;
         Ok(())
     }
 
@@ -166,6 +213,13 @@ impl TransactionGraph {
         let to_node_index = self.get_node_index(to)?;
         let output_type = self.get_output_type(from, output_index)?;
 
+        if self.is_input_connected(to, input_index)? {
+            return Err(GraphError::InputAlreadyConnected(
+                to.to_string(),
+                input_index,
+            ));
+        }
+
         let connection = Connection::new(connection_name, input_index, output_index);
 
         self.graph
@@ -173,10 +227,328 @@ impl TransactionGraph {
 
         let to_node = self.get_node_mut(to)?;
         to_node.inputs[input_index].set_output_type(output_type)?;
+        to_node.dirty = true;
+
+        Ok(())
+    }
+
+    /// Replaces an output (e.g. to change its value or leaf scripts), updating the inputs that
+    /// spend it. The transaction is marked as dirty.
+    pub fn update_output(
+        &mut self,
+        name: &str,
+        output_index: usize,
+        output_type: OutputType,
+    ) -> Result<(), GraphError> {
+        let node_index = self.get_node_index(name)?;
+        if output_index >= self.get_node(name)?.outputs.len() {
+            return Err(GraphError::MissingOutput(name.to_string(), output_index));
+        }
+
+        let spenders: Vec<(NodeIndex, usize)> = self
+            .graph
+            .edges(node_index)
+            .filter(|edge| edge.weight().output_index as usize == output_index)
+            .map(|edge| (edge.target(), edge.weight().input_index as usize))
+            .collect();
+
+        // Check every spender before changing anything, so a failure leaves the graph untouched
+        for (to_index, input_index) in spenders.iter() {
+            self.graph[*to_index].inputs[*input_index]
+                .clone()
+                .set_output_type(output_type.clone())?;
+        }
+
+        let node = self.get_node_mut(name)?;
+        node.transaction.output[output_index] = TxOut {
+            value: output_type.get_value(),
+            script_pubkey: output_type.get_script_pubkey().clone(),
+        };
+        node.outputs[output_index] = output_type.clone();
+        node.dirty = true;
+
+        for (to_index, input_index) in spenders {
+            let to_node = self
+                .graph
+                .node_weight_mut(to_index)
+                .ok_or(GraphError::MissingTransaction(name.to_string()))?;
+            to_node.inputs[input_index].set_output_type(output_type.clone())?;
+            to_node.dirty = true;
+        }
+
+        Ok(())
+    }
+
+    /// Removes a connection and the input spending it, shifting down the later inputs of the
+    /// spending transaction. The spent output is kept. Returns the name of the spending
+    /// transaction and the index the removed input had.
+    pub fn remove_connection(
+        &mut self,
+        connection_name: &str,
+    ) -> Result<(String, usize), GraphError> {
+        let edges: Vec<EdgeIndex> = self
+            .graph
+            .edge_indices()
+            .filter(|edge| self.graph[*edge].name == connection_name)
+            .collect();
 
+        match edges.as_slice() {
+            [edge] => self.remove_edge(*edge),
+            [] => Err(GraphError::UnknownConnection(connection_name.to_string())),
+            _ => Err(GraphError::AmbiguousConnection(
+                connection_name.to_string(),
+                edges.len(),
+            )),
+        }
+    }
+
+    /// Removes a transaction and the inputs of other transactions spending its outputs, as
+    /// `remove_connection` does. The outputs it spent are kept in their transactions. Returns
+    /// the removed inputs, in removal order.
+    pub fn remove_transaction(&mut self, name: &str) -> Result<Vec<(String, usize)>, GraphError> {
+        let node_index = self.get_node_index(name)?;
+        let mut removed = vec![];
+
+        while let Some(edge) = self
+            .graph
+            .edges_directed(node_index, petgraph::Direction::Outgoing)
+            .map(|edge| edge.id())
+            .next()
+        {
+            removed.push(self.remove_edge(edge)?);
+        }
+
+        // Removing a node moves the last node to its index
+        self.graph.remove_node(node_index);
+        self.node_indexes.remove(name);
+        if let Some(moved) = self.graph.node_weight(node_index) {
+            self.node_indexes.insert(moved.name.clone(), node_index);
+        }
+
+        Ok(removed)
+    }
+
+    fn remove_edge(&mut self, edge: EdgeIndex) -> Result<(String, usize), GraphError> {
+        let (_, to_index) = self
+            .graph
+            .edge_endpoints(edge)
+            .ok_or(GraphError::MissingConnection)?;
+        let connection = self
+            .graph
+            .remove_edge(edge)
+            .ok_or(GraphError::MissingConnection)?;
+        let input_index = connection.input_index as usize;
+
+        let later: Vec<EdgeIndex> = self
+            .graph
+            .edges_directed(to_index, petgraph::Direction::Incoming)
+            .filter(|edge| edge.weight().input_index as usize > input_index)
+            .map(|edge| edge.id())
+            .collect();
+        for edge in later {
+            self.graph[edge].input_index -= 1;
+        }
+
+        let to_node = &mut self.graph[to_index];
+        to_node.transaction.input.remove(input_index);
+        to_node.inputs.remove(input_index);
+        to_node
+            .input_labels
+            .retain(|_, index| *index != input_index);
+        for index in to_node.input_labels.values_mut() {
+            if *index > input_index {
+                *index -= 1;
+            }
+        }
+        to_node.dirty = true;
+
+        Ok((to_node.name.clone(), input_index))
+    }
+
+    pub fn mark_dirty(&mut self, name: &str) -> Result<(), GraphError> {
+        self.get_node_mut(name)?.dirty = true;
         Ok(())
     }
 
+    pub fn is_dirty(&self, name: &str) -> Result<bool, GraphError> {
+        Ok(self.get_node(name)?.dirty)
+    }
+
+    /// Returns, in topological order, the transactions that must be rebuilt: the dirty ones and
+    /// all their descendants. External transactions are not included.
+    pub fn affected_transactions(&self) -> Result<Vec<String>, GraphError> {
+        let sorted = toposort(&self.graph, None).map_err(|_| GraphError::GraphCycleDetected)?;
+        let mut affected = HashSet::new();
+
+        for node_index in sorted.iter() {
+            let dirty = self.graph[*node_index].dirty
+                || self
+                    .graph
+                    .neighbors_directed(*node_index, petgraph::Direction::Incoming)
+                    .any(|parent| affected.contains(&parent));
+
+            if dirty {
+                affected.insert(*node_index);
+            }
+        }
+
+        Ok(sorted
+            .iter()
+            .filter(|node_index| affected.contains(*node_index))
+            .map(|node_index| &self.graph[*node_index])
+            .filter(|node| !node.external)
+            .map(|node| node.name.clone())
+            .collect())
+    }
+
+    pub fn clear_dirty(&mut self) {
+        for node in self.graph.node_weights_mut() {
+            node.dirty = false;
+        }
+    }
+
+    /// Discards every input signature of the given transactions, keeping the signature slots.
+    /// Returns the names of the transactions that had at least one signature.
+    pub fn clear_transaction_signatures(&mut self, transaction_names: &[String]) -> Vec<String> {
+        let mut cleared = vec![];
+
+        for node in self
+            .graph
+            .node_weights_mut()
+            .filter(|node| transaction_names.contains(&node.name))
+        {
+            let mut signed = false;
+            for input in node.inputs.iter_mut() {
+                signed |= input
+                    .signatures()
+                    .iter()
+                    .any(|signature| signature.is_some());
+                signed |= input
+                    .multisig_signatures()
+                    .values()
+                    .flatten()
+                    .any(|signature| signature.is_some());
+                let empty = vec![None; input.signatures().len()];
+                input.set_signatures(empty);
+                input.set_multisig_signatures(BTreeMap::new());
+            }
+
+            if signed {
+                cleared.push(node.name.clone());
+            }
+        }
+
+        cleared.sort();
+        cleared
+    }
+
+    pub fn is_input_connected(&self, name: &str, input_index: usize) -> Result<bool, GraphError> {
+        let node_index = self.get_node_index(name)?;
+
+        Ok(self
+            .graph
+            .edges_directed(node_index, petgraph::Direction::Incoming)
+            .any(|edge| edge.weight().input_index as usize == input_index))
+    }
+
+    /// Imports all the transactions and connections of another graph, renaming them with
+    /// `prefixed_name`. Fails without modifying the graph if any renamed transaction already exists.
+    pub fn merge(&mut self, other: &TransactionGraph, prefix: &str) -> Result<(), GraphError> {
+        for name in other.node_indexes.keys() {
+            let name = prefixed_name(prefix, name);
+            if self.node_indexes.contains_key(&name) {
+                return Err(GraphError::TransactionAlreadyExists(name));
+            }
+        }
+
+        let mut index_map = HashMap::new();
+        for other_index in other.graph.node_indices() {
+            let mut node = other.graph[other_index].clone();
+            node.name = prefixed_name(prefix, &node.name);
+
+            let name = node.name.clone();
+            let node_index = self.graph.add_node(node);
+            self.node_indexes.insert(name, node_index);
+            index_map.insert(other_index, node_index);
+        }
+
+        for edge in other.graph.edge_references() {
+            let mut connection = edge.weight().clone();
+            connection.name = prefixed_name(prefix, &connection.name);
+
+            self.graph.add_edge(
+                index_map[&edge.source()],
+                index_map[&edge.target()],
+                connection,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Copies a transaction and all its descendants under the names given by `rename`, along
+    /// with the connections between them, also renamed. Connections from other transactions into
+    /// the copied ones spend the same outputs as the originals. Fails without modifying the
+    /// graph if a new name is empty or already used. Returns the original and copied names, in
+    /// topological order.
+    pub fn duplicate_subtree(
+        &mut self,
+        root: &str,
+        rename: impl Fn(&str) -> String,
+    ) -> Result<Vec<(String, String)>, GraphError> {
+        if self.get_node(root)?.external {
+            return Err(GraphError::ExternalSubtree(root.to_string()));
+        }
+
+        let mut names = vec![root.to_string()];
+        names.extend(self.descendants(root)?);
+        let renamed: Vec<(String, String)> = names
+            .into_iter()
+            .map(|name| {
+                let copy = rename(&name);
+                (name, copy)
+            })
+            .collect();
+
+        let mut copies = HashSet::new();
+        for (_, copy) in renamed.iter() {
+            if copy.trim().is_empty() {
+                return Err(GraphError::EmptyTransactionName);
+            }
+            if self.node_indexes.contains_key(copy) || !copies.insert(copy) {
+                return Err(GraphError::TransactionAlreadyExists(copy.clone()));
+            }
+        }
+
+        let mut index_map = HashMap::new();
+        for (name, copy) in renamed.iter() {
+            let original_index = self.get_node_index(name)?;
+            let mut node = self.graph[original_index].clone();
+            node.name = copy.clone();
+            node.resigned.clear();
+            node.dirty = true;
+
+            let node_index = self.graph.add_node(node);
+            self.node_indexes.insert(copy.clone(), node_index);
+            index_map.insert(original_index, node_index);
+        }
+
+        let edges: Vec<(NodeIndex, NodeIndex, Connection)> = self
+            .graph
+            .edge_references()
+            .filter(|edge| index_map.contains_key(&edge.target()))
+            .map(|edge| (edge.source(), edge.target(), edge.weight().clone()))
+            .collect();
+
+        for (source, target, mut connection) in edges {
+            connection.name = rename(&connection.name);
+            let source = index_map.get(&source).copied().unwrap_or(source);
+            self.graph.add_edge(source, index_map[&target], connection);
+        }
+
+        Ok(renamed)
+    }
+
     pub fn update_hashed_messages(
         &mut self,
         transaction_name: &str,
@@ -201,6 +573,18 @@ impl TransactionGraph {
         Ok(())
     }
 
+    pub fn update_input_multisig_signatures(
+        &mut self,
+        transaction_name: &str,
+        input_index: u32,
+        signatures: BTreeMap<usize, Vec<Option<Signature>>>,
+    ) -> Result<(), GraphError> {
+        let node = self.get_node_mut(transaction_name)?;
+        node.inputs[input_index as usize].set_multisig_signatures(signatures);
+
+        Ok(())
+    }
+
     pub fn update_input_signature(
         &mut self,
         transaction_name: &str,
@@ -307,6 +691,106 @@ impl TransactionGraph {
         Ok(self.get_node(name)?.inputs.clone())
     }
 
+    /// Returns the estimated witness size in bytes (i.e. weight units) of each input of a transaction.
+    pub fn estimate_witness_sizes(&self, name: &str) -> Result<Vec<usize>, GraphError> {
+        let node = self.get_node(name)?;
+        node.inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| estimate_input_witness_bytes(name, input, index))
+            .collect()
+    }
+
+    pub fn add_resigned_transaction(
+        &mut self,
+        name: &str,
+        transaction: Transaction,
+    ) -> Result<(), GraphError> {
+        let node = self.get_node_mut(name)?;
+        node.resigned.push(transaction);
+        Ok(())
+    }
+
+    pub fn get_resigned_transactions(&self, name: &str) -> Result<Vec<Transaction>, GraphError> {
+        Ok(self.get_node(name)?.resigned.clone())
+    }
+
+    pub fn label_output(
+        &mut self,
+        name: &str,
+        label: &str,
+        output_index: usize,
+    ) -> Result<(), GraphError> {
+        let node = self.get_node_mut(name)?;
+        if output_index >= node.outputs.len() {
+            return Err(GraphError::MissingOutput(name.to_string(), output_index));
+        }
+        if node.output_labels.contains_key(label) {
+            return Err(GraphError::DuplicateOutputLabel(
+                name.to_string(),
+                label.to_string(),
+            ));
+        }
+
+        node.output_labels.insert(label.to_string(), output_index);
+        Ok(())
+    }
+
+    pub fn label_input(
+        &mut self,
+        name: &str,
+        label: &str,
+        input_index: usize,
+    ) -> Result<(), GraphError> {
+        let node = self.get_node_mut(name)?;
+        if input_index >= node.inputs.len() {
+            return Err(GraphError::MissingInputInfo(name.to_string(), input_index));
+        }
+        if node.input_labels.contains_key(label) {
+            return Err(GraphError::DuplicateInputLabel(
+                name.to_string(),
+                label.to_string(),
+            ));
+        }
+
+        node.input_labels.insert(label.to_string(), input_index);
+        Ok(())
+    }
+
+    pub fn has_output_label(&self, name: &str, label: &str) -> Result<bool, GraphError> {
+        Ok(self.get_node(name)?.output_labels.contains_key(label))
+    }
+
+    pub fn has_input_label(&self, name: &str, label: &str) -> Result<bool, GraphError> {
+        Ok(self.get_node(name)?.input_labels.contains_key(label))
+    }
+
+    pub fn get_output_index_by_label(&self, name: &str, label: &str) -> Result<usize, GraphError> {
+        self.get_node(name)?
+            .output_labels
+            .get(label)
+            .copied()
+            .ok_or(GraphError::MissingOutputLabel(
+                name.to_string(),
+                label.to_string(),
+            ))
+    }
+
+    pub fn get_input_index_by_label(&self, name: &str, label: &str) -> Result<usize, GraphError> {
+        self.get_node(name)?
+            .input_labels
+            .get(label)
+            .copied()
+            .ok_or(GraphError::MissingInputLabel(
+                name.to_string(),
+                label.to_string(),
+            ))
+    }
+
+    pub fn get_outputs(&self, name: &str) -> Result<Vec<OutputType>, GraphError> {
+        Ok(self.get_node(name)?.outputs.clone())
+    }
+
     pub fn get_output_for_input(
         &self,
         name: &str,
@@ -414,6 +898,34 @@ impl TransactionGraph {
         Ok(signature)
     }
 
+    /// Signatures of the slots of a multisig leaf, empty if the leaf has no signed slots.
+    pub fn get_taproot_multisig_signatures(
+        &self,
+        name: &str,
+        input_index: usize,
+        leaf_index: usize,
+    ) -> Result<Vec<Option<bitcoin::taproot::Signature>>, GraphError> {
+        let node = self.get_node(name)?;
+        let input = node.get_input(input_index)?;
+
+        input
+            .multisig_signatures()
+            .get(&leaf_index)
+            .into_iter()
+            .flatten()
+            .map(|signature| match signature {
+                Some(Signature::Taproot(signature)) => Ok(Some(*signature)),
+                None => Ok(None),
+                _ => Err(GraphError::InvalidSignatureType(
+                    name.to_string(),
+                    input_index,
+                    "Taproot".to_string(),
+                    "ECDSA".to_string(),
+                )),
+            })
+            .collect()
+    }
+
     pub fn get_taproot_key_signature(
         &self,
         name: &str,
@@ -463,6 +975,87 @@ impl TransactionGraph {
         Ok(result)
     }
 
+    /// Transactions the given one depends on, directly or indirectly, in topological order.
+    /// External transactions are included, as they must also confirm first.
+    pub fn ancestors(&self, name: &str) -> Result<Vec<String>, GraphError> {
+        self.reachable(name, petgraph::Direction::Incoming)
+    }
+
+    /// Transactions depending on the given one, directly or indirectly, in topological order.
+    pub fn descendants(&self, name: &str) -> Result<Vec<String>, GraphError> {
+        self.reachable(name, petgraph::Direction::Outgoing)
+    }
+
+    /// Every chain of transactions leading from one transaction to another, both included.
+    /// Transactions connected through several outputs are only traversed once per path.
+    pub fn paths_between(&self, from: &str, to: &str) -> Result<Vec<Vec<String>>, GraphError> {
+        let from = self.get_node_index(from)?;
+        let to = self.get_node_index(to)?;
+
+        let mut paths: Vec<Vec<String>> = vec![];
+        for path in
+            all_simple_paths::<Vec<NodeIndex>, _, RandomState>(&self.graph, from, to, 0, None)
+        {
+            let path = path
+                .into_iter()
+                .map(|node_index| self.graph[node_index].name.clone())
+                .collect();
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Transactions without inputs connected to other transactions, in insertion order.
+    pub fn roots(&self) -> Vec<String> {
+        self.boundary(petgraph::Direction::Incoming)
+    }
+
+    /// Transactions whose outputs are not spent by other transactions, in insertion order.
+    pub fn leaves(&self) -> Vec<String> {
+        self.boundary(petgraph::Direction::Outgoing)
+    }
+
+    fn reachable(
+        &self,
+        name: &str,
+        direction: petgraph::Direction,
+    ) -> Result<Vec<String>, GraphError> {
+        let start = self.get_node_index(name)?;
+        let mut reached = HashSet::new();
+        let mut pending = vec![start];
+
+        while let Some(node_index) = pending.pop() {
+            for neighbor in self.graph.neighbors_directed(node_index, direction) {
+                if reached.insert(neighbor) {
+                    pending.push(neighbor);
+                }
+            }
+        }
+
+        let sorted = toposort(&self.graph, None).map_err(|_| GraphError::GraphCycleDetected)?;
+        Ok(sorted
+            .into_iter()
+            .filter(|node_index| reached.contains(node_index))
+            .map(|node_index| self.graph[node_index].name.clone())
+            .collect())
+    }
+
+    fn boundary(&self, direction: petgraph::Direction) -> Vec<String> {
+        self.graph
+            .node_indices()
+            .filter(|node_index| {
+                self.graph
+                    .neighbors_directed(*node_index, direction)
+                    .next()
+                    .is_none()
+            })
+            .map(|node_index| self.graph[node_index].name.clone())
+            .collect()
+    }
+
     pub fn sorted_transactions(&self) -> Result<(Vec<Transaction>, Vec<String>), GraphError> {
         let sorted = toposort(&self.graph, None).map_err(|_| GraphError::GraphCycleDetected)?;
         let result = sorted
@@ -586,6 +1179,7 @@ impl TransactionGraph {
         }
         node.outputs[output_index].set_value(value);
         node.transaction.output[output_index].value = value;
+        node.dirty = true;
 
         Ok(())
     }
@@ -661,7 +1255,67 @@ impl TransactionGraph {
         Ok(())
     }
 
+    /// Transactions spending the given output. Several spenders are alternative branches, of
+    /// which at most one can be mined.
+    pub fn spenders(&self, name: &str, output_index: usize) -> Result<Vec<String>, GraphError> {
+        let node_index = self.get_node_index(name)?;
+        let mut spenders: Vec<String> = vec![];
+
+        // In connection order
+        for edge in self
+            .graph
+            .edge_references()
+            .filter(|edge| edge.source() == node_index)
+        {
+            let to = &self.graph[edge.target()].name;
+            if edge.weight().output_index as usize == output_index && !spenders.contains(to) {
+                spenders.push(to.clone());
+            }
+        }
+
+        Ok(spenders)
+    }
+
+    /// Inputs spending the given output, as (transaction name, input index) pairs in connection
+    /// order.
+    pub fn spending_inputs(
+        &self,
+        name: &str,
+        output_index: usize,
+    ) -> Result<Vec<(String, usize)>, GraphError> {
+        let node_index = self.get_node_index(name)?;
+
+        Ok(self
+            .graph
+            .edges(node_index)
+            .filter(|edge| edge.weight().output_index as usize == output_index)
+            .map(|edge| {
+                (
+                    self.graph[edge.target()].name.clone(),
+                    edge.weight().input_index as usize,
+                )
+            })
+            .collect())
+    }
+
+    /// Exports the graph in DOT format, or in the Mermaid or JSON formats selected by the
+    /// options. Alternative spends of the same output are drawn dashed.
     pub fn visualize(&self, options: GraphOptions) -> Result<String, GraphError> {
+        self.visualize_with_colors(options, &HashMap::new())
+    }
+
+    /// Same as `visualize`, coloring the connections to the given (transaction, input) pairs.
+    pub fn visualize_with_colors(
+        &self,
+        options: GraphOptions,
+        input_colors: &HashMap<(String, usize), String>,
+    ) -> Result<String, GraphError> {
+        match options {
+            GraphOptions::Mermaid => return self.visualize_mermaid(input_colors),
+            GraphOptions::Json => return self.visualize_json(input_colors),
+            _ => {}
+        }
+
         let mut result = "digraph {\ngraph [rankdir=LR]\nnode [shape=record]\n".to_owned();
 
         for node_index in self.graph.node_indices() {
@@ -688,21 +1342,22 @@ impl TransactionGraph {
 
             let total = inputs.max(outputs);
             let mut inout = String::new();
+            let detailed = options == GraphOptions::Detailed;
             for i in 0..total {
-                let input_name = if i < inputs {
-                    format!("<i{}> in{}", i, i)
-                } else {
-                    "---".to_string()
+                let input_name = match from.inputs.get(i) {
+                    Some(input) if detailed && i < inputs => detailed_input(i, input),
+                    _ if i < inputs => format!("<i{}> in{}", i, i),
+                    _ => "---".to_string(),
                 };
-                let output_name = if i < outputs {
-                    format!(
+                let output_name = match from.outputs.get(i) {
+                    Some(output) if detailed && i < outputs => detailed_output(i, output),
+                    _ if i < outputs => format!(
                         "<o{}> out{} [{}]",
                         i,
                         i,
                         from.transaction.output[i].value.to_sat()
-                    )
-                } else {
-                    "---".to_string()
+                    ),
+                    _ => "---".to_string(),
                 };
                 inout.push_str(&format!("{{ {} | {} }} ", input_name, output_name));
                 if i < total - 1 {
@@ -710,18 +1365,35 @@ impl TransactionGraph {
                 }
             }
 
+            let fill = match (detailed, from.external) {
+                (true, true) => format!(" style=filled fillcolor={}", EXTERNAL_FILL_COLOR),
+                (true, false) => format!(" style=filled fillcolor={}", INTERNAL_FILL_COLOR),
+                _ => String::new(),
+            };
             result.push_str(&format!(
-                "{} [label=\"{{ {} [{}] [{}] }} | {}  \"] \n",
+                "{} [label=\"{{ {} [{}] [{}] }} | {}  \"{}] \n",
                 from.name,
                 from.name,
                 fee,
                 last_chars(&from.transaction.compute_txid().to_string(), 8),
                 inout,
+                fill,
             ));
 
             for edge in self.graph.edges(node_index) {
                 let connection = edge.weight();
                 let to = self.graph.node_weight(edge.target()).unwrap();
+                let mut color = input_colors
+                    .get(&(to.name.clone(), connection.input_index as usize))
+                    .map(|color| format!(" color={} fontcolor={}", color, color))
+                    .unwrap_or_default();
+                if self
+                    .spenders(&from.name, connection.output_index as usize)?
+                    .len()
+                    > 1
+                {
+                    color.push_str(" style=dashed");
+                }
                 //Normal view
                 //result.push_str(&format!( "{} -> {} [label={}]\n", from.name, to.name, connection.name,));
                 //Detailed from:vout-to:in (graph view gets messy)
@@ -729,17 +1401,18 @@ impl TransactionGraph {
                 //Detailed from-to:in
                 if options == GraphOptions::EdgeArrows {
                     result.push_str(&format!(
-                        "{}:o{}:e -> {}:i{}:w [label={}]\n",
+                        "{}:o{}:e -> {}:i{}:w [label={}{}]\n",
                         from.name,
                         connection.output_index,
                         to.name,
                         connection.input_index,
                         connection.name,
+                        color,
                     ));
                 } else {
                     result.push_str(&format!(
-                        "{} -> {}:i{} [label={}]\n",
-                        from.name, to.name, connection.input_index, connection.name,
+                        "{} -> {}:i{} [label={}{}]\n",
+                        from.name, to.name, connection.input_index, connection.name, color,
                     ));
                 }
             }
@@ -838,6 +1511,83 @@ impl TransactionGraph {
             .collect()
     }
 
+    /// Nodes in insertion order.
+    pub(crate) fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.graph.node_weights()
+    }
+
+    pub(crate) fn stored_connections(&self) -> Vec<StoredConnection> {
+        self.graph
+            .edge_references()
+            .map(|edge| StoredConnection {
+                from: self.graph[edge.source()].name.clone(),
+                to: self.graph[edge.target()].name.clone(),
+                connection: edge.weight().clone(),
+            })
+            .collect()
+    }
+
+    /// Rebuilds a graph from its nodes, in insertion order, and its connections.
+    pub(crate) fn from_parts(
+        nodes: Vec<Node>,
+        connections: Vec<StoredConnection>,
+    ) -> Result<Self, GraphError> {
+        let mut graph = TransactionGraph::new();
+
+        for node in nodes {
+            if graph.node_indexes.contains_key(&node.name) {
+                return Err(GraphError::TransactionAlreadyExists(node.name));
+            }
+            let name = node.name.clone();
+            let node_index = graph.graph.add_node(node);
+            graph.node_indexes.insert(name, node_index);
+        }
+
+        for stored in connections {
+            let from = graph.get_node_index(&stored.from)?;
+            let to = graph.get_node_index(&stored.to)?;
+            graph.graph.add_edge(from, to, stored.connection);
+        }
+
+        Ok(graph)
+    }
+
+    /// Checks that the index of every transaction name points to the node with that name, and
+    /// that every node is indexed. Indexes written by older versions may drift from the nodes,
+    /// which would silently target the wrong transactions.
+    pub fn check_indexes(&self) -> Result<(), GraphError> {
+        for (name, node_index) in self.node_indexes.iter() {
+            match self.graph.node_weight(*node_index) {
+                Some(node) if node.name == *name => {}
+                _ => return Err(GraphError::IndexDrift(name.clone())),
+            }
+        }
+
+        if let Some(node) = self
+            .graph
+            .node_weights()
+            .find(|node| !self.node_indexes.contains_key(&node.name))
+        {
+            return Err(GraphError::IndexDrift(node.name.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Reconstructs the node indexes from the names stored in the nodes.
+    pub fn rebuild_indexes(&mut self) -> Result<(), GraphError> {
+        let mut node_indexes = HashMap::new();
+        for node_index in self.graph.node_indices() {
+            let name = self.graph[node_index].name.clone();
+            if node_indexes.insert(name.clone(), node_index).is_some() {
+                return Err(GraphError::TransactionAlreadyExists(name));
+            }
+        }
+
+        self.node_indexes = node_indexes;
+        Ok(())
+    }
+
     // Getters for testing purposes
     pub(crate) fn _get_node_count(&self) -> usize {
         self.graph.node_count()
@@ -852,6 +1602,97 @@ impl TransactionGraph {
     }
 }
 
+// Record cell of an input: spent value, sighash type, spend mode and the signatures already
+// produced or still missing for the key path (`key`) and each leaf (`L<leaf>`).
+fn detailed_input(index: usize, input: &InputType) -> String {
+    let value = input
+        .output_type()
+        .map(|output| output.get_value().to_sat())
+        .unwrap_or_default();
+
+    let (mut signed, mut unsigned) = (vec![], vec![]);
+    for (name, is_signed) in signature_slots(input) {
+        if is_signed {
+            signed.push(name);
+        } else {
+            unsigned.push(name);
+        }
+    }
+
+    let mut label = format!(
+        "<i{}> in{} [{}]\\l{} {}\\l",
+        index,
+        index,
+        value,
+        escape_record(&input.sighash_type().to_string()),
+        escape_record(&input.spend_mode().to_string()),
+    );
+    if !signed.is_empty() {
+        label.push_str(&format!("signed: {}\\l", signed.join(" ")));
+    }
+    if !unsigned.is_empty() {
+        label.push_str(&format!("unsigned: {}\\l", unsigned.join(" ")));
+    }
+    label
+}
+
+/// Signatures expected by an input, as (slot, signed): `L<n>` for taproot leaves, `key` for the
+/// taproot key path and `sig` for segwit inputs.
+pub(crate) fn signature_slots(input: &InputType) -> Vec<(String, bool)> {
+    let leaves = match input.output_type() {
+        Ok(OutputType::Taproot { leaves, .. }) => Some(leaves.len()),
+        _ => None,
+    };
+
+    input
+        .hashed_messages()
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| message.is_some())
+        .map(|(slot, _)| {
+            let name = match leaves {
+                Some(leaves) if slot == leaves => "key".to_string(),
+                Some(_) => format!("L{}", slot),
+                None => "sig".to_string(),
+            };
+            (name, matches!(input.signatures().get(slot), Some(Some(_))))
+        })
+        .collect()
+}
+
+// Record cell of an output: value, kind and the (truncated) script of each leaf.
+fn detailed_output(index: usize, output: &OutputType) -> String {
+    let mut label = format!(
+        "<o{}> out{} [{}] {}\\l",
+        index,
+        index,
+        output.get_value().to_sat(),
+        output.get_name()
+    );
+    for (leaf, script) in output.get_scripts() {
+        let mut asm = script.get_script().to_asm_string();
+        if asm.chars().count() > DETAILED_SCRIPT_CHARS {
+            asm = format!(
+                "{}...",
+                asm.chars().take(DETAILED_SCRIPT_CHARS).collect::<String>()
+            );
+        }
+        label.push_str(&format!("L{}: {}\\l", leaf, escape_record(&asm)));
+    }
+    label
+}
+
+fn escape_record(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 fn last_chars(s: &str, n: usize) -> String {
     s.chars()
         .rev()
@@ -861,3 +1702,12 @@ fn last_chars(s: &str, n: usize) -> String {
         .rev()
         .collect()
 }
+
+/// Name given to a transaction or connection imported with `TransactionGraph::merge`.
+pub fn prefixed_name(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}_{}", prefix, name)
+    }
+}
@@ -1,2 +1,4 @@
 pub mod estimate;
+pub mod export;
 pub mod graph;
+pub mod package;
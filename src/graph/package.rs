@@ -0,0 +1,324 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use bitcoin::Sequence;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::GraphError;
+
+use super::graph::TransactionGraph;
+
+/// Default mempool limit of unconfirmed ancestors of a transaction, including itself.
+pub const DEFAULT_ANCESTOR_LIMIT: usize = 25;
+
+/// Default mempool limit of unconfirmed descendants of a transaction, including itself.
+pub const DEFAULT_DESCENDANT_LIMIT: usize = 25;
+
+/// Mempool package limits the protocol structure is checked against.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PackageLimits {
+    pub max_ancestors: usize,
+    pub max_descendants: usize,
+}
+
+impl Default for PackageLimits {
+    fn default() -> Self {
+        Self {
+            max_ancestors: DEFAULT_ANCESTOR_LIMIT,
+            max_descendants: DEFAULT_DESCENDANT_LIMIT,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum PackageLimit {
+    Ancestors,
+    Descendants,
+}
+
+/// Transaction that can be part of an unconfirmed package larger than the mempool accepts, so it
+/// may be impossible to broadcast (or be pinned) until other transactions confirm.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PackageLimitViolation {
+    pub transaction_name: String,
+    pub limit: PackageLimit,
+    /// Transactions in the unconfirmed package, including this one.
+    pub count: usize,
+    /// Longest chain of unconfirmed transactions ending (ancestors) or starting (descendants) at
+    /// this transaction.
+    pub chain: Vec<String>,
+}
+
+/// Input that should have a relative timelock, so the transaction it spends must be confirmed
+/// before it is broadcasted.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConfirmationBarrier {
+    pub transaction_name: String,
+    pub input_index: usize,
+    pub previous_transaction: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct PackageLimitReport {
+    pub violations: Vec<PackageLimitViolation>,
+    /// Suggested barriers that keep every unconfirmed package within the limits.
+    pub barriers: Vec<ConfirmationBarrier>,
+}
+
+impl PackageLimitReport {
+    pub fn is_within_limits(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Returns true if an input with this sequence can only be mined once the output it spends has
+/// at least one confirmation.
+pub fn requires_confirmation(sequence: Sequence) -> bool {
+    sequence.is_relative_lock_time() && sequence.to_consensus_u32() & 0xffff != 0
+}
+
+// Transactions linked by inputs without relative timelock, indexed in topological order.
+struct UnconfirmedGraph {
+    names: Vec<String>,
+    // (parent, input index) for each transaction
+    parents: Vec<Vec<(usize, usize)>>,
+    children: Vec<BTreeSet<usize>>,
+}
+
+impl UnconfirmedGraph {
+    // External transactions are outside the protocol and assumed confirmed.
+    fn new(graph: &TransactionGraph) -> Result<Self, GraphError> {
+        let external: HashSet<&str> = graph
+            .nodes()
+            .filter(|node| node.external)
+            .map(|node| node.name.as_str())
+            .collect();
+
+        let names: Vec<String> = graph
+            .sort()?
+            .into_iter()
+            .filter(|name| !external.contains(name.as_str()))
+            .collect();
+
+        let indexes: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.as_str(), index))
+            .collect();
+
+        let mut parents = vec![vec![]; names.len()];
+        let mut children = vec![BTreeSet::new(); names.len()];
+
+        for stored in graph.stored_connections() {
+            let (Some(from), Some(to)) = (
+                indexes.get(stored.from.as_str()),
+                indexes.get(stored.to.as_str()),
+            ) else {
+                continue;
+            };
+
+            let input_index = stored.connection.input_index as usize;
+            let transaction = graph.get_transaction_by_name(&stored.to)?;
+            let sequence = transaction
+                .input
+                .get(input_index)
+                .ok_or(GraphError::MissingInputInfo(stored.to.clone(), input_index))?
+                .sequence;
+
+            if !requires_confirmation(sequence) {
+                parents[*to].push((*from, input_index));
+                children[*from].insert(*to);
+            }
+        }
+
+        Ok(Self {
+            names,
+            parents,
+            children,
+        })
+    }
+
+    fn parents_of(&self, index: usize) -> BTreeSet<usize> {
+        self.parents[index]
+            .iter()
+            .map(|(parent, _)| *parent)
+            .collect()
+    }
+
+    // Longest chain ending at each transaction (following parents) or starting at it.
+    fn longest_chains(&self, ancestors: bool) -> Vec<Vec<usize>> {
+        let mut chains: Vec<Vec<usize>> = vec![vec![]; self.names.len()];
+        let order: Vec<usize> = if ancestors {
+            (0..self.names.len()).collect()
+        } else {
+            (0..self.names.len()).rev().collect()
+        };
+
+        for index in order {
+            let linked = if ancestors {
+                self.parents_of(index)
+            } else {
+                self.children[index].clone()
+            };
+
+            let mut chain = linked
+                .iter()
+                .map(|other| chains[*other].clone())
+                .max_by_key(|chain| chain.len())
+                .unwrap_or_default();
+
+            if ancestors {
+                chain.push(index);
+            } else {
+                chain.insert(0, index);
+            }
+            chains[index] = chain;
+        }
+
+        chains
+    }
+
+    // Sets of unconfirmed ancestors (or descendants) of each transaction, including itself,
+    // ignoring the cut (parent, child) links.
+    fn packages(&self, ancestors: bool, cut: &HashSet<(usize, usize)>) -> Vec<BTreeSet<usize>> {
+        let mut packages = vec![BTreeSet::new(); self.names.len()];
+        let order: Vec<usize> = if ancestors {
+            (0..self.names.len()).collect()
+        } else {
+            (0..self.names.len()).rev().collect()
+        };
+
+        for index in order {
+            packages[index] = self.package(index, ancestors, &packages, cut);
+        }
+
+        packages
+    }
+
+    fn package(
+        &self,
+        index: usize,
+        ancestors: bool,
+        packages: &[BTreeSet<usize>],
+        cut: &HashSet<(usize, usize)>,
+    ) -> BTreeSet<usize> {
+        let mut package = BTreeSet::from([index]);
+        for other in self.linked(index, ancestors, cut) {
+            package.extend(packages[other].iter());
+        }
+        package
+    }
+
+    fn linked(&self, index: usize, ancestors: bool, cut: &HashSet<(usize, usize)>) -> Vec<usize> {
+        if ancestors {
+            self.parents_of(index)
+                .into_iter()
+                .filter(|parent| !cut.contains(&(*parent, index)))
+                .collect()
+        } else {
+            self.children[index]
+                .iter()
+                .filter(|child| !cut.contains(&(index, **child)))
+                .copied()
+                .collect()
+        }
+    }
+
+    // Greedily cuts the links to the largest packages of each transaction exceeding the limit.
+    // Ancestors are processed first, cutting links for descendants never enlarges an ancestor
+    // package.
+    fn barriers(&self, limits: &PackageLimits) -> HashSet<(usize, usize)> {
+        let mut cut = HashSet::new();
+
+        for (ancestors, max) in [
+            (true, limits.max_ancestors),
+            (false, limits.max_descendants),
+        ] {
+            let mut packages = vec![BTreeSet::new(); self.names.len()];
+            let order: Vec<usize> = if ancestors {
+                (0..self.names.len()).collect()
+            } else {
+                (0..self.names.len()).rev().collect()
+            };
+
+            for index in order {
+                let mut package = self.package(index, ancestors, &packages, &cut);
+
+                let mut linked = self.linked(index, ancestors, &cut);
+                linked.sort_by_key(|other| std::cmp::Reverse(packages[*other].len()));
+
+                for other in linked {
+                    if package.len() <= max {
+                        break;
+                    }
+
+                    cut.insert(if ancestors {
+                        (other, index)
+                    } else {
+                        (index, other)
+                    });
+                    package = self.package(index, ancestors, &packages, &cut);
+                }
+
+                packages[index] = package;
+            }
+        }
+
+        cut
+    }
+}
+
+/// Checks that no transaction of the graph can be part of an unconfirmed package exceeding the
+/// mempool limits. Inputs with a relative timelock act as confirmation barriers: the transaction
+/// they spend must be confirmed first, so it is not part of the package. Descendant counts are
+/// an upper bound, as they include transactions spending the same output.
+pub fn check_package_limits(
+    graph: &TransactionGraph,
+    limits: &PackageLimits,
+) -> Result<PackageLimitReport, GraphError> {
+    let unconfirmed = UnconfirmedGraph::new(graph)?;
+    let mut report = PackageLimitReport::default();
+
+    for (ancestors, limit, max) in [
+        (true, PackageLimit::Ancestors, limits.max_ancestors),
+        (false, PackageLimit::Descendants, limits.max_descendants),
+    ] {
+        let packages = unconfirmed.packages(ancestors, &HashSet::new());
+        let chains = unconfirmed.longest_chains(ancestors);
+
+        for (index, package) in packages.iter().enumerate() {
+            if package.len() > max {
+                report.violations.push(PackageLimitViolation {
+                    transaction_name: unconfirmed.names[index].clone(),
+                    limit: limit.clone(),
+                    count: package.len(),
+                    chain: chains[index]
+                        .iter()
+                        .map(|other| unconfirmed.names[*other].clone())
+                        .collect(),
+                });
+            }
+        }
+    }
+
+    if report.violations.is_empty() {
+        return Ok(report);
+    }
+
+    let cut = unconfirmed.barriers(limits);
+    for (index, parents) in unconfirmed.parents.iter().enumerate() {
+        let mut parents = parents.clone();
+        parents.sort_by_key(|(_, input_index)| *input_index);
+
+        for (parent, input_index) in parents {
+            if cut.contains(&(parent, index)) {
+                report.barriers.push(ConfirmationBarrier {
+                    transaction_name: unconfirmed.names[index].clone(),
+                    input_index,
+                    previous_transaction: unconfirmed.names[parent].clone(),
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
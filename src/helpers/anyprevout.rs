@@ -0,0 +1,163 @@
+//! Experimental BIP118 (SIGHASH_ANYPREVOUT) support, for prototyping rebindable protocols on
+//! signets that enforce it, such as bitcoin-inquisition. Mainnet does not enforce these rules.
+
+use bitcoin::{
+    consensus::Encodable,
+    hashes::{sha256, Hash, HashEngine},
+    opcodes::all::OP_CHECKSIG,
+    script::Builder,
+    secp256k1::{schnorr, Message},
+    sighash::TapSighash,
+    taproot::LeafVersion,
+    PublicKey, Script, TapLeafHash, Transaction, TxOut, XOnlyPublicKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    scripts::{ProtocolScript, SignMode},
+};
+
+/// Key version of BIP118 public keys, also committed to by their signatures.
+pub const ANYPREVOUT_KEY_VERSION: u8 = 0x01;
+
+const SIGHASH_ALL: u8 = 0x01;
+const SIGHASH_NONE: u8 = 0x02;
+const SIGHASH_SINGLE: u8 = 0x03;
+const SIGHASH_ANYPREVOUT: u8 = 0x40;
+const SIGHASH_ANYPREVOUTANYSCRIPT: u8 = 0xc0;
+
+/// BIP118 sighash types. `AnyPrevout` variants still commit to the amount and script pubkey of
+/// the spent output, `AnyScript` variants only commit to the sequence of the input.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AnyPrevoutSighashType {
+    AllAnyPrevout,
+    NoneAnyPrevout,
+    SingleAnyPrevout,
+    AllAnyScript,
+    NoneAnyScript,
+    SingleAnyScript,
+}
+
+impl AnyPrevoutSighashType {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            AnyPrevoutSighashType::AllAnyPrevout => SIGHASH_ANYPREVOUT | SIGHASH_ALL,
+            AnyPrevoutSighashType::NoneAnyPrevout => SIGHASH_ANYPREVOUT | SIGHASH_NONE,
+            AnyPrevoutSighashType::SingleAnyPrevout => SIGHASH_ANYPREVOUT | SIGHASH_SINGLE,
+            AnyPrevoutSighashType::AllAnyScript => SIGHASH_ANYPREVOUTANYSCRIPT | SIGHASH_ALL,
+            AnyPrevoutSighashType::NoneAnyScript => SIGHASH_ANYPREVOUTANYSCRIPT | SIGHASH_NONE,
+            AnyPrevoutSighashType::SingleAnyScript => SIGHASH_ANYPREVOUTANYSCRIPT | SIGHASH_SINGLE,
+        }
+    }
+
+    pub fn is_any_script(self) -> bool {
+        self.to_u8() & SIGHASH_ANYPREVOUTANYSCRIPT == SIGHASH_ANYPREVOUTANYSCRIPT
+    }
+
+    fn output_flag(self) -> u8 {
+        self.to_u8() & 0x03
+    }
+}
+
+/// Schnorr signature with its BIP118 sighash type, serialized as 65 bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnyPrevoutSignature {
+    pub signature: schnorr::Signature,
+    pub sighash_type: AnyPrevoutSighashType,
+}
+
+impl AnyPrevoutSignature {
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut serialized = self.signature.as_ref().to_vec();
+        serialized.push(self.sighash_type.to_u8());
+        serialized
+    }
+}
+
+/// Tapscript leaf checking a signature of a BIP118 public key, which only accepts signatures
+/// with an ANYPREVOUT sighash type.
+pub fn check_anyprevout_signature(public_key: &PublicKey, sign_mode: SignMode) -> ProtocolScript {
+    let mut key = vec![ANYPREVOUT_KEY_VERSION];
+    key.extend_from_slice(&XOnlyPublicKey::from(*public_key).serialize());
+
+    let script = Builder::new()
+        .push_slice(<[u8; 33]>::try_from(key).expect("version byte and x-only key"))
+        .push_opcode(OP_CHECKSIG)
+        .into_script();
+
+    ProtocolScript::new(script, public_key, sign_mode)
+}
+
+/// BIP118 digest signed by a leaf of a taproot input. The digest commits to neither the outpoint
+/// nor the index of the input, so the signature can be rebound to any transaction spending an
+/// output with the same amount and script pubkey (or any taproot output with the same leaf, for
+/// `AnyScript` types). Only the script path can be signed with ANYPREVOUT.
+pub fn anyprevout_script_sighash(
+    transaction: &Transaction,
+    input_index: usize,
+    prevout: &TxOut,
+    leaf_script: &Script,
+    sighash_type: AnyPrevoutSighashType,
+) -> Result<Message, ProtocolBuilderError> {
+    let input = transaction
+        .input
+        .get(input_index)
+        .ok_or(ProtocolBuilderError::MissingInput(
+            transaction.compute_txid().to_string(),
+            input_index,
+        ))?;
+
+    let mut engine = TapSighash::engine();
+
+    // Epoch and control
+    engine.input(&[0x00, sighash_type.to_u8()]);
+
+    // Transaction data, without prevouts, amounts, script pubkeys and sequences of all inputs
+    encode(&transaction.version, &mut engine);
+    encode(&transaction.lock_time, &mut engine);
+    if sighash_type.output_flag() == SIGHASH_ALL {
+        let mut outputs = sha256::Hash::engine();
+        for output in transaction.output.iter() {
+            encode(output, &mut outputs);
+        }
+        engine.input(sha256::Hash::from_engine(outputs).as_ref());
+    }
+
+    // Data about this input, script path spend without annex
+    engine.input(&[0x02]);
+    if !sighash_type.is_any_script() {
+        encode(&prevout.value, &mut engine);
+        encode(&prevout.script_pubkey, &mut engine);
+    }
+    encode(&input.sequence, &mut engine);
+
+    // Data about this output
+    if sighash_type.output_flag() == SIGHASH_SINGLE {
+        let output = transaction.output.get(input_index).ok_or(
+            ProtocolBuilderError::MissingSingleOutput(
+                transaction.compute_txid().to_string(),
+                input_index,
+            ),
+        )?;
+        let mut single = sha256::Hash::engine();
+        encode(output, &mut single);
+        engine.input(sha256::Hash::from_engine(single).as_ref());
+    }
+
+    // Script path extension
+    if !sighash_type.is_any_script() {
+        let leaf_hash = TapLeafHash::from_script(leaf_script, LeafVersion::TapScript);
+        engine.input(leaf_hash.as_ref());
+    }
+    engine.input(&[ANYPREVOUT_KEY_VERSION]);
+    engine.input(&u32::MAX.to_le_bytes());
+
+    Ok(Message::from_digest(
+        TapSighash::from_engine(engine).to_byte_array(),
+    ))
+}
+
+fn encode<T: Encodable>(value: &T, engine: &mut sha256::HashEngine) {
+    value.consensus_encode(engine).expect("engines don't error");
+}
@@ -0,0 +1,65 @@
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn poly_mod(mut c: u64, val: u64) -> u64 {
+    let c0 = c >> 35;
+    c = ((c & 0x7ffffffff) << 5) ^ val;
+    if c0 & 1 != 0 {
+        c ^= 0xf5dee51989;
+    }
+    if c0 & 2 != 0 {
+        c ^= 0xa9fdca3312;
+    }
+    if c0 & 4 != 0 {
+        c ^= 0x1bab10e32d;
+    }
+    if c0 & 8 != 0 {
+        c ^= 0x3706b1677a;
+    }
+    if c0 & 16 != 0 {
+        c ^= 0x644d626ffd;
+    }
+    c
+}
+
+/// Computes the BIP-380 checksum of a descriptor. Returns None if the descriptor contains
+/// characters outside the descriptor character set.
+pub fn descriptor_checksum(descriptor: &str) -> Option<String> {
+    let mut c = 1u64;
+    let mut cls = 0u64;
+    let mut cls_count = 0;
+
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET.find(ch)? as u64;
+        c = poly_mod(c, position & 31);
+        cls = cls * 3 + (position >> 5);
+        cls_count += 1;
+        if cls_count == 3 {
+            c = poly_mod(c, cls);
+            cls = 0;
+            cls_count = 0;
+        }
+    }
+
+    if cls_count > 0 {
+        c = poly_mod(c, cls);
+    }
+
+    for _ in 0..8 {
+        c = poly_mod(c, 0);
+    }
+    c ^= 1;
+
+    let checksum = (0..8)
+        .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+        .collect();
+
+    Some(checksum)
+}
+
+/// Appends the BIP-380 checksum to a descriptor, i.e. `descriptor#checksum`.
+pub fn with_checksum(descriptor: &str) -> Option<String> {
+    let checksum = descriptor_checksum(descriptor)?;
+    Some(format!("{}#{}", descriptor, checksum))
+}
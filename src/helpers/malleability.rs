@@ -0,0 +1,421 @@
+use bitcoin::{opcodes::all as opcodes, script::Instruction};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ProtocolBuilderError, ScriptError},
+    scripts::{KeyType, ProtocolScript},
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum WinternitzMalleability {
+    /// The Winternitz digits are numbers that a third party can re-encode non-minimally. The
+    /// re-encoded witness is still valid, changes the wtxid and can be padded to lower the
+    /// transaction feerate (pinning).
+    NonCanonicalDigits,
+    /// The leaf checks no signature committing to the spending transaction, so anyone who sees
+    /// the Winternitz signatures can reuse them in a different transaction.
+    UncommittedTransaction,
+}
+
+/// Malleability issues found in a leaf with Winternitz keys.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WinternitzLeafReport {
+    pub transaction_name: String,
+    pub output_index: usize,
+    pub leaf_index: usize,
+    pub issues: Vec<WinternitzMalleability>,
+    /// Variant of the leaf with canonical-form checks for its digits, when requested.
+    pub hardened: Option<ProtocolScript>,
+}
+
+/// Returns the malleability issues of a leaf. Leaves without Winternitz keys have none.
+pub fn winternitz_leaf_issues(
+    script: &ProtocolScript,
+) -> Result<Vec<WinternitzMalleability>, ScriptError> {
+    let has_winternitz_keys = script
+        .get_keys()
+        .iter()
+        .any(|key| matches!(key.key_type(), KeyType::WinternitzKey { .. }));
+
+    if !has_winternitz_keys {
+        return Ok(vec![]);
+    }
+
+    let mut issues = vec![];
+
+    if !script.has_canonical_winternitz_digits()? {
+        issues.push(WinternitzMalleability::NonCanonicalDigits);
+    }
+
+    let checks_signature = script.get_script().instructions().any(|instruction| {
+        matches!(
+            instruction,
+            Ok(Instruction::Op(
+                opcodes::OP_CHECKSIG | opcodes::OP_CHECKSIGVERIFY | opcodes::OP_CHECKSIGADD
+            ))
+        )
+    });
+
+    if !checks_signature {
+        issues.push(WinternitzMalleability::UncommittedTransaction);
+    }
+
+    Ok(issues)
+}
+
+/// Builds the report entry of a leaf, or None if the leaf has no issues. When a hardening offset
+/// is given (see `ProtocolScript::set_canonical_winternitz_digits`), leaves with non-canonical
+/// digits include a hardened variant.
+pub(crate) fn winternitz_leaf_report(
+    transaction_name: &str,
+    output_index: usize,
+    leaf_index: usize,
+    script: &ProtocolScript,
+    hardening_offset: Option<usize>,
+) -> Result<Option<WinternitzLeafReport>, ProtocolBuilderError> {
+    let issues = winternitz_leaf_issues(script)?;
+    if issues.is_empty() {
+        return Ok(None);
+    }
+
+    let hardened = match hardening_offset {
+        Some(offset) if issues.contains(&WinternitzMalleability::NonCanonicalDigits) => {
+            let mut hardened = script.clone();
+            hardened.set_canonical_winternitz_digits(offset)?;
+            Some(hardened)
+        }
+        _ => None,
+    };
+
+    Ok(Some(WinternitzLeafReport {
+        transaction_name: transaction_name.to_string(),
+        output_index,
+        leaf_index,
+        issues,
+        hardened,
+    }))
+}
+
+/// Way a third party can alter the witness spending a leaf without invalidating it. Altered
+/// witnesses change the wtxid, or the txid of transactions spending a P2WSH output, and can be
+/// padded to lower the transaction feerate.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum LeafMalleability {
+    /// The leaf checks no signature committing to the spending transaction, so anyone who sees
+    /// the witness can replay it in a different transaction.
+    UncommittedTransaction,
+    /// Witness items, by position from the top of the witness stack, that the script drops or
+    /// only checks for truthiness. They can be replaced with any value.
+    UnconstrainedStackItems(Vec<usize>),
+    /// The script ends with this many items instead of exactly one, so it can never be spent.
+    UncleanStack(usize),
+    /// OP_IF or OP_NOTIF on a witness item of a P2WSH script. Unlike in tapscript, MINIMALIF is
+    /// only a relay policy for segwit v0, so the condition can be re-encoded.
+    NonMinimalIf,
+    /// See `WinternitzMalleability::NonCanonicalDigits`.
+    NonCanonicalDigits,
+}
+
+/// Malleability issues found in a leaf, or in the script of a P2WSH output.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LeafMalleabilityReport {
+    pub transaction_name: String,
+    pub output_index: usize,
+    /// Leaf index, 0 for P2WSH outputs.
+    pub leaf_index: usize,
+    pub issues: Vec<LeafMalleability>,
+}
+
+/// Returns the ways the witness of a leaf can be malleated. The script is executed symbolically,
+/// tracking which witness items each stack value is computed from. Execution stops at the first
+/// branch or opcode whose effect on the stack is not known statically, after which every item
+/// still on the stack is assumed to be constrained, so no issue is reported that the script does
+/// not have.
+pub fn leaf_malleability(
+    script: &ProtocolScript,
+    segwit_v0: bool,
+) -> Result<Vec<LeafMalleability>, ScriptError> {
+    let mut issues = vec![];
+
+    if !checks_signature(script) {
+        issues.push(LeafMalleability::UncommittedTransaction);
+    }
+
+    let analysis = StackAnalysis::run(script, segwit_v0);
+    let unconstrained: Vec<usize> = (0..analysis.witness_items)
+        .filter(|item| !analysis.constrained.contains(item))
+        .collect();
+    if !unconstrained.is_empty() {
+        issues.push(LeafMalleability::UnconstrainedStackItems(unconstrained));
+    }
+    if analysis.complete && analysis.stack.len() != 1 {
+        issues.push(LeafMalleability::UncleanStack(analysis.stack.len()));
+    }
+    if analysis.witness_condition {
+        issues.push(LeafMalleability::NonMinimalIf);
+    }
+
+    if winternitz_leaf_issues(script)?.contains(&WinternitzMalleability::NonCanonicalDigits) {
+        issues.push(LeafMalleability::NonCanonicalDigits);
+    }
+
+    Ok(issues)
+}
+
+fn checks_signature(script: &ProtocolScript) -> bool {
+    script.get_script().instructions().any(|instruction| {
+        matches!(
+            instruction,
+            Ok(Instruction::Op(
+                opcodes::OP_CHECKSIG
+                    | opcodes::OP_CHECKSIGVERIFY
+                    | opcodes::OP_CHECKSIGADD
+                    | opcodes::OP_CHECKMULTISIG
+                    | opcodes::OP_CHECKMULTISIGVERIFY
+            ))
+        )
+    })
+}
+
+// Symbolic stack value: the witness items it is computed from, and its value if it is a
+// small constant (needed to follow OP_PICK and OP_ROLL).
+#[derive(Clone, Debug)]
+struct Value {
+    sources: Vec<usize>,
+    // Witness item as provided, not computed by the script
+    raw: bool,
+    number: Option<usize>,
+}
+
+impl Value {
+    fn constant(number: Option<usize>) -> Self {
+        Self {
+            sources: vec![],
+            raw: false,
+            number,
+        }
+    }
+
+    fn computed(sources: Vec<usize>) -> Self {
+        Self {
+            sources,
+            raw: false,
+            number: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct StackAnalysis {
+    stack: Vec<Value>,
+    alt_stack: Vec<Value>,
+    // Witness items read so far, the deepest has the highest index
+    witness_items: usize,
+    constrained: Vec<usize>,
+    witness_condition: bool,
+    complete: bool,
+}
+
+impl StackAnalysis {
+    fn run(script: &ProtocolScript, segwit_v0: bool) -> Self {
+        let mut analysis = Self::default();
+
+        for instruction in script.get_script().instructions() {
+            let known = match instruction {
+                Ok(Instruction::PushBytes(bytes)) => {
+                    let number = match bytes.as_bytes() {
+                        [] => Some(0),
+                        [byte] if *byte <= 0x7f => Some(*byte as usize),
+                        _ => None,
+                    };
+                    analysis.stack.push(Value::constant(number));
+                    true
+                }
+                Ok(Instruction::Op(op)) => analysis.apply(op, segwit_v0),
+                Err(_) => false,
+            };
+
+            if !known {
+                analysis.stop();
+                return analysis;
+            }
+        }
+
+        analysis.complete = true;
+        analysis
+    }
+
+    // Applies an opcode to the stack, returns false if its effect is not known statically.
+    fn apply(&mut self, op: bitcoin::Opcode, segwit_v0: bool) -> bool {
+        use opcodes::*;
+
+        let code = op.to_u8();
+        if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&code) {
+            let number = (code - OP_PUSHNUM_1.to_u8() + 1) as usize;
+            self.stack.push(Value::constant(Some(number)));
+            return true;
+        }
+
+        match op {
+            OP_PUSHNUM_NEG1 => self.stack.push(Value::constant(None)),
+            OP_NOP | OP_CODESEPARATOR => {}
+            OP_DROP => {
+                self.pop();
+            }
+            OP_2DROP => {
+                self.pop();
+                self.pop();
+            }
+            OP_NIP => {
+                self.ensure(2);
+                self.stack.remove(self.stack.len() - 2);
+            }
+            OP_DUP => self.copy(&[1]),
+            OP_2DUP => self.copy(&[2, 1]),
+            OP_3DUP => self.copy(&[3, 2, 1]),
+            OP_OVER => self.copy(&[2]),
+            OP_2OVER => self.copy(&[4, 3]),
+            OP_SWAP => self.roll(1),
+            OP_ROT => self.roll(2),
+            OP_2SWAP => {
+                self.roll(3);
+                self.roll(3);
+            }
+            OP_TUCK => {
+                self.ensure(2);
+                let top = self.stack[self.stack.len() - 1].clone();
+                self.stack.insert(self.stack.len() - 2, top);
+            }
+            OP_PICK | OP_ROLL => {
+                let Some(depth) = self.pop().number else {
+                    return false;
+                };
+                match op {
+                    OP_PICK => {
+                        self.ensure(depth + 1);
+                        let value = self.stack[self.stack.len() - 1 - depth].clone();
+                        self.stack.push(value);
+                    }
+                    _ => self.roll(depth),
+                }
+            }
+            OP_TOALTSTACK => {
+                let value = self.pop();
+                self.alt_stack.push(value);
+            }
+            OP_FROMALTSTACK => match self.alt_stack.pop() {
+                Some(value) => self.stack.push(value),
+                None => return false,
+            },
+            OP_CSV | OP_CLTV => {
+                self.ensure(1);
+                let top = self.stack[self.stack.len() - 1].sources.clone();
+                self.constrain(&top);
+            }
+            OP_SIZE => {
+                self.ensure(1);
+                let top = self.stack[self.stack.len() - 1].sources.clone();
+                self.constrain(&top);
+                self.stack.push(Value::computed(top));
+            }
+            OP_IF | OP_NOTIF => {
+                let condition = self.pop();
+                if segwit_v0 && condition.raw {
+                    self.witness_condition = true;
+                }
+                self.constrain(&condition.sources);
+                return false;
+            }
+            OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL | OP_RIPEMD160
+            | OP_SHA1 | OP_SHA256 | OP_HASH160 | OP_HASH256 => self.consume(1, 1),
+            OP_ADD
+            | OP_SUB
+            | OP_BOOLAND
+            | OP_BOOLOR
+            | OP_NUMEQUAL
+            | OP_NUMNOTEQUAL
+            | OP_LESSTHAN
+            | OP_GREATERTHAN
+            | OP_LESSTHANOREQUAL
+            | OP_GREATERTHANOREQUAL
+            | OP_MIN
+            | OP_MAX
+            | OP_EQUAL
+            | OP_CHECKSIG => self.consume(2, 1),
+            OP_EQUALVERIFY | OP_NUMEQUALVERIFY | OP_CHECKSIGVERIFY => self.consume(2, 0),
+            OP_WITHIN | OP_CHECKSIGADD => self.consume(3, 1),
+            // A value only checked for truthiness can still be replaced by any other true value
+            OP_VERIFY => {
+                self.pop();
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    // Makes sure the stack has `depth` items, reading witness items below it as needed.
+    fn ensure(&mut self, depth: usize) {
+        while self.stack.len() < depth {
+            let item = self.witness_items;
+            self.witness_items += 1;
+            self.stack.insert(
+                0,
+                Value {
+                    sources: vec![item],
+                    raw: true,
+                    number: None,
+                },
+            );
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.ensure(1);
+        self.stack.pop().unwrap()
+    }
+
+    // Pushes copies of the items at the given depths, 1 being the top.
+    fn copy(&mut self, depths: &[usize]) {
+        self.ensure(depths[0]);
+        let len = self.stack.len();
+        for depth in depths {
+            let value = self.stack[len - depth].clone();
+            self.stack.push(value);
+        }
+    }
+
+    // Moves the item below `depth` items to the top.
+    fn roll(&mut self, depth: usize) {
+        self.ensure(depth + 1);
+        let value = self.stack.remove(self.stack.len() - 1 - depth);
+        self.stack.push(value);
+    }
+
+    fn consume(&mut self, inputs: usize, outputs: usize) {
+        let mut sources = vec![];
+        for _ in 0..inputs {
+            sources.extend(self.pop().sources);
+        }
+        self.constrain(&sources);
+
+        for _ in 0..outputs {
+            self.stack.push(Value::computed(sources.clone()));
+        }
+    }
+
+    fn constrain(&mut self, sources: &[usize]) {
+        self.constrained.extend_from_slice(sources);
+    }
+
+    // Every value left on the stacks may be constrained by the rest of the script.
+    fn stop(&mut self) {
+        let sources: Vec<usize> = self
+            .stack
+            .iter()
+            .chain(self.alt_stack.iter())
+            .flat_map(|value| value.sources.clone())
+            .collect();
+        self.constrain(&sources);
+    }
+}
@@ -1 +1,9 @@
+#[cfg(feature = "anyprevout")]
+pub mod anyprevout;
+pub mod descriptors;
+pub mod malleability;
+pub mod parallel_verify;
+pub mod sighash;
+pub mod spend_info_cache;
 pub mod weight_computing;
+pub mod witness_decoder;
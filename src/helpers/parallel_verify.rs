@@ -0,0 +1,70 @@
+use std::thread;
+
+use bitcoin::{
+    secp256k1::{schnorr, Message, Secp256k1, Verification},
+    XOnlyPublicKey,
+};
+
+// Fewer signatures are not worth spawning threads for
+const MIN_PARALLEL_BATCH: usize = 64;
+
+/// Schnorr signatures collected to be verified in a single pass. This is not batch
+/// verification in the cryptographic sense: libsecp256k1 has no batch verification API, so each
+/// signature is verified on its own and the signatures are split across the available threads
+/// instead, which is where most of the time goes when verifying protocols with thousands of
+/// leaves.
+#[derive(Clone, Debug, Default)]
+pub struct ParallelSchnorrVerifier {
+    items: Vec<(schnorr::Signature, Message, XOnlyPublicKey)>,
+}
+
+impl ParallelSchnorrVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a signature to verify. Results of `verify` are given in the order signatures are
+    /// pushed.
+    pub fn push(&mut self, signature: schnorr::Signature, message: Message, key: XOnlyPublicKey) {
+        self.items.push((signature, message, key));
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Verifies every signature, returning whether each one is valid in the order they were
+    /// pushed.
+    pub fn verify<C: Verification + Sync>(&self, secp: &Secp256k1<C>) -> Vec<bool> {
+        let verify = |(signature, message, key): &(schnorr::Signature, Message, XOnlyPublicKey)| {
+            secp.verify_schnorr(signature, message, key).is_ok()
+        };
+
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        if threads == 1 || self.items.len() < MIN_PARALLEL_BATCH {
+            return self.items.iter().map(verify).collect();
+        }
+
+        let chunk_size = self.items.len().div_ceil(threads);
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .items
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || chunk.iter().map(verify).collect::<Vec<_>>()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("verification thread panicked"))
+                .collect()
+        })
+    }
+
+    /// Returns true if every signature is valid.
+    pub fn verify_all<C: Verification + Sync>(&self, secp: &Secp256k1<C>) -> bool {
+        self.verify(secp).into_iter().all(|valid| valid)
+    }
+}
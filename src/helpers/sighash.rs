@@ -0,0 +1,166 @@
+use bitcoin::{
+    secp256k1::Message,
+    sighash::{self, SighashCache},
+    taproot::LeafVersion,
+    Amount, EcdsaSighashType, PublicKey, Script, ScriptBuf, TapLeafHash, TapSighashType,
+    Transaction, TxOut, WScriptHash,
+};
+
+use crate::{errors::ProtocolBuilderError, types::input::SighashType};
+
+/// Digest signed by a P2WPKH input spending an output of the given key.
+pub fn p2wpkh_sighash(
+    transaction: &Transaction,
+    input_index: usize,
+    public_key: &PublicKey,
+    value: Amount,
+    sighash_type: EcdsaSighashType,
+) -> Result<Message, ProtocolBuilderError> {
+    let wpkh = public_key.wpubkey_hash().expect("key is compressed");
+    p2wpkh_script_sighash(
+        transaction,
+        input_index,
+        &ScriptBuf::new_p2wpkh(&wpkh),
+        value,
+        sighash_type,
+    )
+}
+
+/// Digest signed by a P2WSH input spending an output of the given script.
+pub fn p2wsh_sighash(
+    transaction: &Transaction,
+    input_index: usize,
+    script: &Script,
+    value: Amount,
+    sighash_type: EcdsaSighashType,
+) -> Result<Message, ProtocolBuilderError> {
+    let script_pubkey = ScriptBuf::new_p2wsh(&WScriptHash::from(script.to_owned()));
+
+    let mut sighasher = SighashCache::new(transaction);
+    Ok(Message::from(sighasher.p2wsh_signature_hash(
+        input_index,
+        &script_pubkey,
+        value,
+        sighash_type,
+    )?))
+}
+
+/// Digest signed by the key path of a taproot input.
+pub fn taproot_key_sighash(
+    transaction: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    sighash_type: TapSighashType,
+) -> Result<Message, ProtocolBuilderError> {
+    let mut sighasher = SighashCache::new(transaction);
+    Ok(Message::from(sighasher.taproot_key_spend_signature_hash(
+        input_index,
+        &taproot_prevouts(transaction, prevouts, input_index, sighash_type)?,
+        sighash_type,
+    )?))
+}
+
+/// Digest signed by the given leaf of a taproot input.
+pub fn taproot_script_sighash(
+    transaction: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    leaf_script: &Script,
+    sighash_type: TapSighashType,
+) -> Result<Message, ProtocolBuilderError> {
+    let mut sighasher = SighashCache::new(transaction);
+    Ok(Message::from(
+        sighasher.taproot_script_spend_signature_hash(
+            input_index,
+            &taproot_prevouts(transaction, prevouts, input_index, sighash_type)?,
+            TapLeafHash::from_script(leaf_script, LeafVersion::TapScript),
+            sighash_type,
+        )?,
+    ))
+}
+
+/// Outputs committed to by a taproot signature. `ANYONECANPAY` signatures only commit to the
+/// output spent by their own input, so inputs added later (e.g. to bump the fee) do not change the
+/// digest.
+pub fn taproot_prevouts<'a>(
+    transaction: &Transaction,
+    prevouts: &'a [TxOut],
+    input_index: usize,
+    sighash_type: TapSighashType,
+) -> Result<sighash::Prevouts<'a, TxOut>, ProtocolBuilderError> {
+    if SighashType::Taproot(sighash_type).is_anyone_can_pay() {
+        let prevout = prevouts
+            .get(input_index)
+            .ok_or(ProtocolBuilderError::MissingInput(
+                transaction.compute_txid().to_string(),
+                input_index,
+            ))?;
+        Ok(sighash::Prevouts::One(input_index, prevout.clone()))
+    } else {
+        Ok(sighash::Prevouts::All(prevouts))
+    }
+}
+
+/// Digest this crate signs for an input of a raw transaction, given the outputs spent by all its
+/// inputs. The spend path follows the script pubkey of the spent output: P2WPKH outputs, P2WSH
+/// outputs with their witness script, and taproot outputs through the key path or, given its
+/// script, a leaf.
+pub fn raw_sighash(
+    transaction: &Transaction,
+    prevouts: &[TxOut],
+    input_index: usize,
+    script: Option<&Script>,
+    sighash_type: &SighashType,
+) -> Result<Message, ProtocolBuilderError> {
+    let prevout = prevouts
+        .get(input_index)
+        .ok_or(ProtocolBuilderError::MissingInput(
+            transaction.compute_txid().to_string(),
+            input_index,
+        ))?;
+    let script_pubkey = &prevout.script_pubkey;
+
+    match (sighash_type, script) {
+        (SighashType::Ecdsa(sighash_type), _) if script_pubkey.is_p2wpkh() => {
+            p2wpkh_script_sighash(
+                transaction,
+                input_index,
+                script_pubkey,
+                prevout.value,
+                *sighash_type,
+            )
+        }
+        (SighashType::Ecdsa(sighash_type), Some(script)) if script_pubkey.is_p2wsh() => {
+            p2wsh_sighash(
+                transaction,
+                input_index,
+                script,
+                prevout.value,
+                *sighash_type,
+            )
+        }
+        (SighashType::Taproot(sighash_type), None) if script_pubkey.is_p2tr() => {
+            taproot_key_sighash(transaction, input_index, prevouts, *sighash_type)
+        }
+        (SighashType::Taproot(sighash_type), Some(script)) if script_pubkey.is_p2tr() => {
+            taproot_script_sighash(transaction, input_index, prevouts, script, *sighash_type)
+        }
+        _ => Err(ProtocolBuilderError::InvalidOutputTypeForSighashType),
+    }
+}
+
+fn p2wpkh_script_sighash(
+    transaction: &Transaction,
+    input_index: usize,
+    script_pubkey: &Script,
+    value: Amount,
+    sighash_type: EcdsaSighashType,
+) -> Result<Message, ProtocolBuilderError> {
+    let mut sighasher = SighashCache::new(transaction);
+    Ok(Message::from(sighasher.p2wpkh_signature_hash(
+        input_index,
+        script_pubkey,
+        value,
+        sighash_type,
+    )?))
+}
@@ -0,0 +1,93 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use bitcoin::{
+    taproot::{LeafVersion, TaprootSpendInfo},
+    TapLeafHash, XOnlyPublicKey,
+};
+
+use crate::{
+    perf::time_tree_building,
+    scripts::{ProtocolScript, TapTreeLayout},
+};
+
+// The cache is emptied when full, protocols rarely have this many distinct taptrees
+const MAX_ENTRIES: usize = 4096;
+
+type SpendInfoKey = (XOnlyPublicKey, Vec<TapLeafHash>, TapTreeLayout);
+
+#[derive(Default)]
+struct SpendInfoCache {
+    entries: HashMap<SpendInfoKey, TaprootSpendInfo>,
+    hits: u64,
+    misses: u64,
+}
+
+/// Counters of the taproot spend info cache, see `cached_spend_info`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpendInfoCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+fn cache() -> &'static Mutex<SpendInfoCache> {
+    static CACHE: OnceLock<Mutex<SpendInfoCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(SpendInfoCache::default()))
+}
+
+/// Returns the spend info of the taptree with the given internal key, leaves and layout,
+/// building it with `build` only the first time it is requested. Outputs with the same leaves
+/// are common in protocols with many rounds, and their spend info is otherwise rebuilt for every
+/// sighash, signature and witness. Failed builds are not cached.
+pub fn cached_spend_info<E>(
+    internal_key: &XOnlyPublicKey,
+    leaves: &[ProtocolScript],
+    layout: &TapTreeLayout,
+    build: impl FnOnce() -> Result<TaprootSpendInfo, E>,
+) -> Result<TaprootSpendInfo, E> {
+    let leaf_hashes = leaves
+        .iter()
+        .map(|leaf| TapLeafHash::from_script(leaf.get_script(), LeafVersion::TapScript))
+        .collect();
+    let key = (*internal_key, leaf_hashes, layout.clone());
+
+    {
+        let mut cache = cache().lock().expect("spend info cache poisoned");
+        if let Some(spend_info) = cache.entries.get(&key).cloned() {
+            cache.hits += 1;
+            return Ok(spend_info);
+        }
+        cache.misses += 1;
+    }
+
+    // Built without holding the lock, so other threads are not blocked meanwhile
+    let spend_info = time_tree_building(build)?;
+
+    let mut cache = cache().lock().expect("spend info cache poisoned");
+    if cache.entries.len() >= MAX_ENTRIES {
+        cache.entries.clear();
+    }
+    cache.entries.insert(key, spend_info.clone());
+    Ok(spend_info)
+}
+
+pub fn spend_info_cache_stats() -> SpendInfoCacheStats {
+    let cache = cache().lock().expect("spend info cache poisoned");
+    SpendInfoCacheStats {
+        hits: cache.hits,
+        misses: cache.misses,
+        entries: cache.entries.len(),
+    }
+}
+
+/// Removes every cached spend info, keeping the counters.
+pub fn clear_spend_info_cache() {
+    cache()
+        .lock()
+        .expect("spend info cache poisoned")
+        .entries
+        .clear();
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use bitcoin::{taproot::TAPROOT_ANNEX_PREFIX, Script, Witness};
+use key_manager::{
+    verifier::SignatureVerifier,
+    winternitz::{
+        message_digits_length, to_checksummed_message, WinternitzPublicKey, WinternitzSignature,
+    },
+};
+
+use crate::{
+    errors::ProtocolBuilderError,
+    scripts::{KeyType, ProtocolScript, ScriptKey},
+};
+
+/// Value committed with a Winternitz key of a leaf, decoded from the witness spending the leaf.
+#[derive(Clone, Debug)]
+pub struct WinternitzValue {
+    pub key: ScriptKey,
+    pub message: Vec<u8>,
+    pub signature: WinternitzSignature,
+}
+
+impl WinternitzValue {
+    /// Returns true if the signature of the value was made with the given public key.
+    pub fn verify(&self, public_key: &WinternitzPublicKey) -> bool {
+        SignatureVerifier::new().verify_winternitz_signature(
+            &self.signature,
+            &self.message,
+            public_key,
+        )
+    }
+}
+
+/// Decodes the values signed with the Winternitz keys of a leaf from a taproot script-path
+/// witness of that leaf, returned in key position order. Winternitz signatures are expected at
+/// the bottom of the stack, the key with the highest position first, as pushed by
+/// `Protocol::sign_winternitz`. Items above them (e.g. the taproot signature) are ignored.
+///
+/// Signatures are not verified, see `verify_winternitz_values`.
+pub fn decode_winternitz_values(
+    witness: &Witness,
+    leaf: &ProtocolScript,
+) -> Result<Vec<WinternitzValue>, ProtocolBuilderError> {
+    let items = stack_items(witness)?;
+    let mut values = vec![];
+    let mut offset = 0;
+
+    for key in leaf.get_keys().into_iter().rev() {
+        let KeyType::WinternitzKey {
+            key_type,
+            message_size,
+        } = key.key_type()
+        else {
+            continue;
+        };
+
+        let digits_count = key.key_type().winternitz_digits()?;
+        let pairs = items
+            .get(offset..offset + 2 * digits_count)
+            .ok_or_else(|| {
+                ProtocolBuilderError::InvalidWinternitzWitness(format!(
+                    "missing signature items for key {}",
+                    key.name()
+                ))
+            })?;
+        offset += 2 * digits_count;
+
+        let mut hashes = vec![];
+        let mut digits = vec![];
+        for pair in pairs.chunks(2) {
+            hashes.push(pair[0].to_vec());
+            digits.push(decode_digit(pair[1], key.name())?);
+        }
+
+        let message = message_from_digits(&digits, message_size, key.name())?;
+        values.push(WinternitzValue {
+            key,
+            message,
+            signature: WinternitzSignature::from_hashes_and_digits(hashes, digits, key_type),
+        });
+    }
+
+    values.reverse();
+    Ok(values)
+}
+
+/// Checks each decoded value against the public key with the same name as its key.
+pub fn verify_winternitz_values(
+    values: &[WinternitzValue],
+    public_keys: &HashMap<String, WinternitzPublicKey>,
+) -> Result<(), ProtocolBuilderError> {
+    for value in values {
+        let name = value.key.name();
+        let public_key =
+            public_keys
+                .get(name)
+                .ok_or(ProtocolBuilderError::MissingWinternitzPublicKey(
+                    name.to_string(),
+                ))?;
+
+        if !value.verify(public_key) {
+            return Err(ProtocolBuilderError::InvalidWinternitzSignature(
+                name.to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Leaf script of a taproot script-path witness, followed by the control block and optionally
+/// the annex (BIP341).
+pub fn witness_leaf_script(witness: &Witness) -> Option<&Script> {
+    let items = script_path_items(witness)?;
+    witness.nth(items - 2).map(Script::from_bytes)
+}
+
+// Number of items up to the control block, without the annex.
+fn script_path_items(witness: &Witness) -> Option<usize> {
+    let annex = witness.len() >= 2 && witness.last()?.first() == Some(&TAPROOT_ANNEX_PREFIX);
+    let items = witness.len() - usize::from(annex);
+
+    (items >= 2).then_some(items)
+}
+
+// Witness items below the leaf script.
+fn stack_items(witness: &Witness) -> Result<Vec<&[u8]>, ProtocolBuilderError> {
+    let items =
+        script_path_items(witness).ok_or(ProtocolBuilderError::InvalidWinternitzWitness(
+            "not a taproot script-path witness".to_string(),
+        ))?;
+
+    Ok(witness.iter().take(items - 2).collect())
+}
+
+// Digits are script numbers, pushed as an empty item when zero.
+fn decode_digit(item: &[u8], key_name: &str) -> Result<u8, ProtocolBuilderError> {
+    match item {
+        [] => Ok(0),
+        [digit] if *digit < 0x80 => Ok(*digit),
+        _ => Err(ProtocolBuilderError::InvalidWinternitzWitness(format!(
+            "invalid digit {} for key {}",
+            hex::encode(item),
+            key_name
+        ))),
+    }
+}
+
+// Inverts the digit encoding of the key manager, byte by byte, and checks the checksum digits by
+// encoding the message again.
+fn message_from_digits(
+    digits: &[u8],
+    message_size: usize,
+    key_name: &str,
+) -> Result<Vec<u8>, ProtocolBuilderError> {
+    let digits_per_byte = message_digits_length(1);
+    let bytes: HashMap<Vec<u8>, u8> = (0..=u8::MAX)
+        .map(|byte| {
+            let digits = to_checksummed_message(&[byte]);
+            (digits[..digits_per_byte].to_vec(), byte)
+        })
+        .collect();
+
+    let invalid = || {
+        ProtocolBuilderError::InvalidWinternitzWitness(format!(
+            "digits do not encode a message for key {}",
+            key_name
+        ))
+    };
+
+    let message = digits[..message_size]
+        .chunks(digits_per_byte)
+        .map(|chunk| bytes.get(chunk).copied())
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+
+    if to_checksummed_message(&message) != digits {
+        return Err(invalid());
+    }
+
+    Ok(message)
+}
@@ -1,10 +1,15 @@
+pub mod broadcast;
 pub mod builder;
 pub mod cli;
 pub mod config;
 pub mod errors;
+pub mod executor;
 pub mod graph;
 pub mod helpers;
+pub mod perf;
 pub mod scripts;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod tests;
 pub mod types;
 pub mod unspendable;
@@ -0,0 +1,85 @@
+//! Timing breakdowns of protocol builds, to tune large protocols and catch regressions. See
+//! `Protocol::last_build_timings` and the benches in `benches/`.
+
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+/// Time spent in each phase of the last `build`, `build_and_sign`, `rebuild` or `sign` of a
+/// protocol. Phases that did not run are zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BuildTimings {
+    /// Transactions processed.
+    pub transactions: usize,
+    pub txids: Duration,
+    pub sighashes: Duration,
+    pub signatures: Duration,
+    /// Time spent building taptrees, already included in the other phases. Taptrees found in
+    /// the spend info cache take no time.
+    pub tree_building: Duration,
+    pub total: Duration,
+}
+
+thread_local! {
+    static TREE_BUILDING: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// Total time the current thread spent building taptrees.
+pub fn tree_building_time() -> Duration {
+    TREE_BUILDING.with(|total| total.get())
+}
+
+pub(crate) fn time_tree_building<T>(build: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = build();
+    TREE_BUILDING.with(|total| total.set(total.get() + start.elapsed()));
+    result
+}
+
+/// Collects the `BuildTimings` of a build while it runs.
+pub(crate) struct BuildTimer {
+    start: Instant,
+    tree_building: Duration,
+    timings: BuildTimings,
+}
+
+impl BuildTimer {
+    pub(crate) fn new(transactions: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            tree_building: tree_building_time(),
+            timings: BuildTimings {
+                transactions,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub(crate) fn txids<T>(&mut self, phase: impl FnOnce() -> T) -> T {
+        timed(&mut self.timings.txids, phase)
+    }
+
+    pub(crate) fn sighashes<T>(&mut self, phase: impl FnOnce() -> T) -> T {
+        timed(&mut self.timings.sighashes, phase)
+    }
+
+    pub(crate) fn signatures<T>(&mut self, phase: impl FnOnce() -> T) -> T {
+        timed(&mut self.timings.signatures, phase)
+    }
+
+    pub(crate) fn finish(self) -> BuildTimings {
+        BuildTimings {
+            tree_building: tree_building_time() - self.tree_building,
+            total: self.start.elapsed(),
+            ..self.timings
+        }
+    }
+}
+
+fn timed<T>(elapsed: &mut Duration, phase: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = phase();
+    *elapsed += start.elapsed();
+    result
+}
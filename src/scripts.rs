@@ -1,20 +1,24 @@
 use std::{
-    collections::HashMap,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
     fmt::{Display, Formatter},
 };
 
 use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
     key::{Secp256k1, UntweakedPublicKey},
+    opcodes::all as opcodes,
+    script::{Builder, Instruction, PushBytes, PushBytesBuf, Script},
     secp256k1::All,
-    taproot::{TaprootBuilder, TaprootSpendInfo},
-    PublicKey, ScriptBuf, XOnlyPublicKey,
+    taproot::{TaprootBuilder, TaprootSpendInfo, TAPROOT_CONTROL_MAX_NODE_COUNT},
+    PublicKey, ScriptBuf, TapSighashType, Transaction, XOnlyPublicKey,
 };
 
 use bitcoin_script_functions::signatures::winternitz::winternitz_checksig;
 use bitcoin_script_stack::stack::StackTracker;
 use bitcoin_scriptexec::treepp::*;
 use itertools::Itertools;
-use key_manager::winternitz::{WinternitzPublicKey, WinternitzType};
+use key_manager::winternitz::{checksum_length, WinternitzPublicKey, WinternitzType};
 use serde::{Deserialize, Serialize};
 
 use crate::errors::ScriptError;
@@ -22,6 +26,43 @@ use crate::errors::ScriptError;
 const SCHNORR_SIG_SIZE: usize = 64;
 const ECDSA_SIG_SIZE: usize = 73;
 const WINTERNITZ_SIG_OVERHEAD_FACTOR: usize = 25;
+const WINTERNITZ_MAX_HASH_SIZE: usize = 32;
+
+/// Largest data carried by a standard OP_RETURN output, in a script of 83 bytes.
+pub const OP_RETURN_MAX_DATA_SIZE: usize = 80;
+/// Largest standard OP_RETURN script before Bitcoin Core v30.
+pub const OP_RETURN_MAX_SCRIPT_SIZE: usize = 83;
+/// Default limit of Bitcoin Core on the size of all the OP_RETURN scripts of a transaction.
+pub const OP_RETURN_MAX_TOTAL_SIZE: usize = 100_000;
+
+/// Relay policy the OP_RETURN outputs of a transaction must follow to be standard.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum OpReturnPolicy {
+    /// Bitcoin Core before v30: a single OP_RETURN output of up to `OP_RETURN_MAX_SCRIPT_SIZE`
+    /// bytes per transaction.
+    #[default]
+    SingleOutput,
+    /// Bitcoin Core v30 and later: any number of OP_RETURN outputs, as long as their scripts
+    /// add up to at most `OP_RETURN_MAX_TOTAL_SIZE` bytes.
+    MultipleOutputs,
+}
+
+impl OpReturnPolicy {
+    pub fn max_outputs(&self) -> usize {
+        match self {
+            OpReturnPolicy::SingleOutput => 1,
+            OpReturnPolicy::MultipleOutputs => usize::MAX,
+        }
+    }
+
+    /// Size allowed for all the OP_RETURN scripts of a transaction.
+    pub fn max_total_size(&self) -> usize {
+        match self {
+            OpReturnPolicy::SingleOutput => OP_RETURN_MAX_SCRIPT_SIZE,
+            OpReturnPolicy::MultipleOutputs => OP_RETURN_MAX_TOTAL_SIZE,
+        }
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum KeyType {
@@ -49,6 +90,12 @@ impl KeyType {
         })
     }
 
+    /// Number of digits (message plus checksum) of a Winternitz signature for this key.
+    pub fn winternitz_digits(&self) -> Result<usize, ScriptError> {
+        let message_size = self.winternitz_message_size()?;
+        Ok(message_size + checksum_length(message_size))
+    }
+
     pub fn winternitz_message_size(&self) -> Result<usize, ScriptError> {
         match self {
             KeyType::WinternitzKey { message_size, .. } => Ok(*message_size),
@@ -70,6 +117,10 @@ pub struct ScriptKey {
     key_type: KeyType,
     key_position: u32,
     derivation_index: u32,
+    /// Hash of the Winternitz public key, telling apart keys of different parties derived with
+    /// the same index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
 }
 
 impl ScriptKey {
@@ -79,9 +130,31 @@ impl ScriptKey {
             key_type,
             key_position,
             derivation_index,
+            fingerprint: None,
         }
     }
 
+    /// Key for a Winternitz public key, see `ProtocolScript::add_winternitz_key`.
+    pub fn winternitz(
+        name: &str,
+        public_key: &WinternitzPublicKey,
+        key_position: u32,
+    ) -> Result<Self, ScriptError> {
+        let mut engine = sha256::Hash::engine();
+        for hash in public_key.to_hashes() {
+            engine.input(&hash);
+        }
+
+        let mut key = Self::new(
+            name,
+            public_key.derivation_index()?,
+            KeyType::winternitz(public_key)?,
+            key_position,
+        );
+        key.fingerprint = Some(sha256::Hash::from_engine(engine).to_string());
+        Ok(key)
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -97,6 +170,150 @@ impl ScriptKey {
     pub fn key_position(&self) -> u32 {
         self.key_position
     }
+
+    /// Hash of the public key of Winternitz keys added with `add_winternitz_key`.
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.fingerprint.as_deref()
+    }
+}
+
+/// Value of a named protocol constant (e.g. CHALLENGE_BLOCKS) embedded in one or more scripts.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ConstantValue {
+    Number(i64),
+    Bytes(Vec<u8>),
+}
+
+impl ConstantValue {
+    /// Script pushing the value: numbers as minimally encoded script numbers (including OP_0 and
+    /// OP_1 to OP_16), bytes as a data push.
+    pub fn push_script(&self) -> Result<ScriptBuf, ScriptError> {
+        match self {
+            ConstantValue::Number(number) => Ok(Builder::new().push_int(*number).into_script()),
+            ConstantValue::Bytes(bytes) => {
+                let bytes = PushBytesBuf::try_from(bytes.clone())
+                    .map_err(|_| ScriptError::ConstantTooLarge(bytes.len()))?;
+                Ok(Builder::new().push_slice(bytes).into_script())
+            }
+        }
+    }
+
+    pub fn as_number(&self) -> Option<i64> {
+        match self {
+            ConstantValue::Number(number) => Some(*number),
+            ConstantValue::Bytes(_) => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            ConstantValue::Number(_) => None,
+            ConstantValue::Bytes(bytes) => Some(bytes),
+        }
+    }
+}
+
+impl Display for ConstantValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstantValue::Number(number) => write!(f, "{}", number),
+            ConstantValue::Bytes(bytes) => write!(f, "0x{}", hex::encode(bytes)),
+        }
+    }
+}
+
+impl From<i64> for ConstantValue {
+    fn from(value: i64) -> Self {
+        ConstantValue::Number(value)
+    }
+}
+
+impl TryFrom<u64> for ConstantValue {
+    type Error = ScriptError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        i64::try_from(value)
+            .map(ConstantValue::Number)
+            .map_err(|_| ScriptError::ConstantOutOfRange(value))
+    }
+}
+
+impl From<u32> for ConstantValue {
+    fn from(value: u32) -> Self {
+        ConstantValue::Number(value as i64)
+    }
+}
+
+impl From<u16> for ConstantValue {
+    fn from(value: u16) -> Self {
+        ConstantValue::Number(value as i64)
+    }
+}
+
+impl From<Vec<u8>> for ConstantValue {
+    fn from(value: Vec<u8>) -> Self {
+        ConstantValue::Bytes(value)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum TemplatePart {
+    Script(ScriptBuf),
+    Constant(String),
+}
+
+/// Script with named placeholders for protocol constants, built into a leaf with
+/// `Protocol::build_script`. Each placeholder is replaced by a push of the constant value and
+/// recorded in the leaf, so audits find the constants a leaf embeds by name.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl ScriptTemplate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a script fragment.
+    pub fn push_script(mut self, script: ScriptBuf) -> Self {
+        self.parts.push(TemplatePart::Script(script));
+        self
+    }
+
+    /// Appends a placeholder for the named protocol constant.
+    pub fn push_constant(mut self, name: &str) -> Self {
+        self.parts.push(TemplatePart::Constant(name.to_string()));
+        self
+    }
+
+    /// Builds the script, pushing the value `resolve` returns for each placeholder. Returns the
+    /// script and, for each placeholder, the constant name, its value and the offset of the push
+    /// in the script.
+    pub fn resolve<E: From<ScriptError>>(
+        &self,
+        mut resolve: impl FnMut(&str) -> Result<ConstantValue, E>,
+    ) -> Result<(ScriptBuf, Vec<(String, ConstantValue, usize)>), E> {
+        let mut bytes = vec![];
+        let mut placeholders = vec![];
+
+        for part in self.parts.iter() {
+            match part {
+                TemplatePart::Script(script) => bytes.extend_from_slice(script.as_bytes()),
+                TemplatePart::Constant(name) => {
+                    if name.trim().is_empty() {
+                        return Err(ScriptError::EmptyConstantName.into());
+                    }
+                    let value = resolve(name)?;
+                    let offset = bytes.len();
+                    bytes.extend_from_slice(value.push_script()?.as_bytes());
+                    placeholders.push((name.clone(), value, offset));
+                }
+            }
+        }
+
+        Ok((ScriptBuf::from_bytes(bytes), placeholders))
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -169,6 +386,23 @@ impl StackItem {
             StackItem::Raw { size } => *size,
         }
     }
+
+    /// Number of witness stack elements of the item. Winternitz signatures push a hash and a
+    /// digit for every message and checksum digit.
+    pub fn element_count(&self) -> usize {
+        match self {
+            StackItem::WinternitzSig { size } => 2 * size.div_ceil(WINTERNITZ_SIG_OVERHEAD_FACTOR),
+            _ => 1,
+        }
+    }
+
+    /// Size of the largest witness stack element of the item.
+    pub fn max_element_size(&self) -> usize {
+        match self {
+            StackItem::WinternitzSig { size } => (*size).min(WINTERNITZ_MAX_HASH_SIZE),
+            _ => self.size(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -178,6 +412,19 @@ pub struct ProtocolScript {
     verifying_key: Option<PublicKey>,
     sign_mode: SignMode,
     items: Vec<StackItem>,
+    #[serde(default)]
+    constants: HashMap<String, ConstantValue>,
+    /// Offsets of the pushes of each constant placeholder in the script.
+    #[serde(default)]
+    constant_offsets: HashMap<String, Vec<usize>>,
+    #[serde(default)]
+    assert_leaf_id: Option<u32>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    signers: Vec<PublicKey>,
+    #[serde(default)]
+    sighash_type: Option<TapSighashType>,
 }
 
 impl ProtocolScript {
@@ -188,6 +435,12 @@ impl ProtocolScript {
             verifying_key: Some(*verifying_key),
             sign_mode,
             items: Vec::new(),
+            constants: HashMap::new(),
+            constant_offsets: HashMap::new(),
+            assert_leaf_id: None,
+            name: None,
+            signers: Vec::new(),
+            sighash_type: None,
         }
     }
 
@@ -198,9 +451,17 @@ impl ProtocolScript {
             verifying_key: None,
             sign_mode: SignMode::Skip,
             items: Vec::new(),
+            constants: HashMap::new(),
+            constant_offsets: HashMap::new(),
+            assert_leaf_id: None,
+            name: None,
+            signers: Vec::new(),
+            sighash_type: None,
         }
     }
 
+    /// Registers a key of the script. Fails if the script already has a key with the same name,
+    /// use `add_or_replace_key` to replace it.
     pub fn add_key(
         &mut self,
         name: &str,
@@ -208,15 +469,54 @@ impl ProtocolScript {
         key_type: KeyType,
         key_position: u32,
     ) -> Result<(), ScriptError> {
-        if name.trim().is_empty() {
+        self.insert_key(ScriptKey::new(
+            name,
+            derivation_index,
+            key_type,
+            key_position,
+        ))
+    }
+
+    /// Registers a Winternitz key of the script as `add_key`, keeping the hash of its public key
+    /// so keys of different parties with the same derivation index are told apart.
+    pub fn add_winternitz_key(
+        &mut self,
+        name: &str,
+        public_key: &WinternitzPublicKey,
+        key_position: u32,
+    ) -> Result<(), ScriptError> {
+        self.insert_key(ScriptKey::winternitz(name, public_key, key_position)?)
+    }
+
+    fn insert_key(&mut self, key: ScriptKey) -> Result<(), ScriptError> {
+        if key.name().trim().is_empty() {
             return Err(ScriptError::EmptyScriptName);
         }
-        let key = ScriptKey::new(name, derivation_index, key_type, key_position);
+        if self.keys.contains_key(key.name()) {
+            return Err(ScriptError::DuplicateScriptKey(key.name().to_string()));
+        }
         self.keys.insert(key.name().to_string(), key);
 
         Ok(())
     }
 
+    /// Registers a key of the script, replacing any key with the same name. Returns the replaced
+    /// key.
+    pub fn add_or_replace_key(
+        &mut self,
+        name: &str,
+        derivation_index: u32,
+        key_type: KeyType,
+        key_position: u32,
+    ) -> Result<Option<ScriptKey>, ScriptError> {
+        if name.trim().is_empty() {
+            return Err(ScriptError::EmptyScriptName);
+        }
+        let key = ScriptKey::new(name, derivation_index, key_type, key_position);
+
+        Ok(self.keys.insert(key.name().to_string(), key))
+    }
+
     pub fn get_key(&self, name: &str) -> Option<ScriptKey> {
         self.keys.get(name).cloned()
     }
@@ -230,14 +530,148 @@ impl ProtocolScript {
             .collect()
     }
 
+    /// Records a placeholder of the named protocol constant, resolved to `value` and pushed at
+    /// `offset` in the script, see `ScriptTemplate::resolve`. Fails with `ConstantNotInScript`
+    /// if the script does not push the value at that offset.
+    pub(crate) fn add_constant_placeholder(
+        &mut self,
+        name: &str,
+        value: ConstantValue,
+        offset: usize,
+    ) -> Result<(), ScriptError> {
+        let conflicting = matches!(self.constants.get(name), Some(current) if *current != value);
+        if conflicting || !self.pushes_at(offset, &value.push_script()?) {
+            return Err(ScriptError::ConstantNotInScript(
+                name.to_string(),
+                value.to_string(),
+            ));
+        }
+
+        self.constants.insert(name.to_string(), value);
+        self.constant_offsets
+            .entry(name.to_string())
+            .or_default()
+            .push(offset);
+        Ok(())
+    }
+
+    /// Checks that the script pushes the recorded value of each constant at every one of its
+    /// placeholders.
+    pub fn check_constant_placeholders(&self) -> Result<(), ScriptError> {
+        for (name, value) in self.constants.iter() {
+            let push = value.push_script()?;
+            if !self
+                .constant_offsets(name)
+                .iter()
+                .all(|offset| self.pushes_at(*offset, &push))
+            {
+                return Err(ScriptError::ConstantNotInScript(
+                    name.to_string(),
+                    value.to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pushes_at(&self, offset: usize, push: &Script) -> bool {
+        self.script
+            .as_bytes()
+            .get(offset..offset + push.len())
+            .is_some_and(|bytes| bytes == push.as_bytes())
+    }
+
+    /// Offsets of the pushes of the placeholders of the named constant in the script.
+    pub fn constant_offsets(&self, name: &str) -> &[usize] {
+        self.constant_offsets
+            .get(name)
+            .map(|offsets| offsets.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn get_constant(&self, name: &str) -> Option<&ConstantValue> {
+        self.constants.get(name)
+    }
+
+    pub fn get_constants(&self) -> &HashMap<String, ConstantValue> {
+        &self.constants
+    }
+
     pub fn get_verifying_key(&self) -> Option<PublicKey> {
         self.verifying_key
     }
 
+    /// Keys of the signature slots of a multisig leaf (see `checksigadd_multisig`), in the order
+    /// the script checks them. Empty for leaves checking a single signature.
+    pub fn get_signers(&self) -> &[PublicKey] {
+        &self.signers
+    }
+
+    pub fn is_multisig(&self) -> bool {
+        !self.signers.is_empty()
+    }
+
     pub fn get_script(&self) -> &ScriptBuf {
         &self.script
     }
 
+    /// Replaces the verifying key and every push of a public key of the mapping (compressed or
+    /// x-only) in the script with the mapped key. Returns true if the script changed.
+    pub fn replace_keys(&mut self, mapping: &HashMap<PublicKey, PublicKey>) -> bool {
+        let mut changed = false;
+
+        if let Some(new_key) = self.verifying_key.and_then(|key| mapping.get(&key)) {
+            self.verifying_key = Some(*new_key);
+            changed = true;
+        }
+
+        for signer in self.signers.iter_mut() {
+            if let Some(new_key) = mapping.get(signer) {
+                *signer = *new_key;
+                changed = true;
+            }
+        }
+
+        let replacements: HashMap<Vec<u8>, Vec<u8>> = mapping
+            .iter()
+            .flat_map(|(old, new)| {
+                [
+                    (old.to_bytes(), new.to_bytes()),
+                    (
+                        XOnlyPublicKey::from(*old).serialize().to_vec(),
+                        XOnlyPublicKey::from(*new).serialize().to_vec(),
+                    ),
+                ]
+            })
+            .collect();
+
+        // Keys have the same size, so the payloads are replaced in place keeping any other
+        // push encoded as it was
+        let mut bytes = self.script.to_bytes();
+        for instruction in self.script.instruction_indices() {
+            let Ok((index, Instruction::PushBytes(push))) = instruction else {
+                continue;
+            };
+
+            let Some(new_key) = replacements.get(push.as_bytes()) else {
+                continue;
+            };
+
+            let header = match bytes[index] {
+                0x4c => 2,
+                0x4d => 3,
+                0x4e => 5,
+                _ => 1,
+            };
+            bytes[index + header..index + header + new_key.len()].copy_from_slice(new_key);
+            changed = true;
+        }
+        self.script = ScriptBuf::from(bytes);
+
+        changed
+    }
+
     pub fn add_stack_item(&mut self, item: StackItem) {
         self.items.push(item);
     }
@@ -246,6 +680,66 @@ impl ProtocolScript {
         self.items.clone()
     }
 
+    /// Hash of what the script commits to: its bytes, its verifying key and sign mode, its keys
+    /// (by position, name and derivation index) and the expected witness stack items. Two scripts
+    /// with the same hash are spent with the same witness, so parties can exchange these hashes
+    /// to agree on the scripts behind each leaf.
+    pub fn template_hash(&self) -> sha256::Hash {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&(self.script.len() as u32).to_le_bytes());
+        engine.input(self.script.as_bytes());
+
+        match self.verifying_key {
+            Some(key) => engine.input(&key.to_bytes()),
+            None => engine.input(&[0]),
+        }
+        engine.input(&[self.sign_mode as u8]);
+
+        let keys = self
+            .keys
+            .values()
+            .sorted_by(|a, b| (a.key_position, &a.name).cmp(&(b.key_position, &b.name)))
+            .collect::<Vec<_>>();
+        engine.input(&(keys.len() as u32).to_le_bytes());
+        for key in keys {
+            engine.input(&key.key_position.to_le_bytes());
+            engine.input(&(key.name.len() as u32).to_le_bytes());
+            engine.input(key.name.as_bytes());
+            engine.input(&key.derivation_index.to_le_bytes());
+        }
+
+        // Signers are only committed for multisig leaves, keeping the hash of other leaves
+        if self.is_multisig() {
+            engine.input(&(self.signers.len() as u32).to_le_bytes());
+            for signer in self.signers.iter() {
+                engine.input(&signer.to_bytes());
+            }
+        }
+
+        // Same for the sighash type, only committed when the leaf overrides the input one
+        if let Some(sighash_type) = self.sighash_type {
+            engine.input(&(sighash_type as u32).to_le_bytes());
+        }
+
+        engine.input(&(self.items.len() as u32).to_le_bytes());
+        for item in self.items.iter() {
+            let (tag, value) = match item {
+                StackItem::SchnorrSig {
+                    non_default_sighash,
+                } => (0u8, u64::from(*non_default_sighash)),
+                StackItem::EcdsaSig {
+                    non_default_sighash,
+                } => (1, u64::from(*non_default_sighash)),
+                StackItem::WinternitzSig { size } => (2, *size as u64),
+                StackItem::Raw { size } => (3, *size as u64),
+            };
+            engine.input(&[tag]);
+            engine.input(&value.to_le_bytes());
+        }
+
+        sha256::Hash::from_engine(engine)
+    }
+
     pub fn skip_signing(&self) -> bool {
         self.sign_mode == SignMode::Skip
     }
@@ -258,6 +752,157 @@ impl ProtocolScript {
         self.sign_mode == SignMode::Aggregate
     }
 
+    /// Number of Winternitz digits in the witness of this script, for all its Winternitz keys.
+    pub fn winternitz_digits(&self) -> Result<usize, ScriptError> {
+        self.keys
+            .values()
+            .filter(|key| matches!(key.key_type, KeyType::WinternitzKey { .. }))
+            .map(|key| key.key_type.winternitz_digits())
+            .sum()
+    }
+
+    /// Prepends checks that reject witnesses whose Winternitz digits are not minimally encoded.
+    /// Non-minimal numbers are consensus valid in tapscript, so without these checks anyone can
+    /// re-encode the digits of a valid witness. `offset` is the number of witness items on top of
+    /// the Winternitz signatures, e.g. 1 for the schnorr signature of the leaves built with
+    /// `verify_winternitz_signatures`.
+    pub fn set_canonical_winternitz_digits(&mut self, offset: usize) -> Result<(), ScriptError> {
+        let mut builder = Builder::new();
+
+        // Each Winternitz signature item is a (hash, digit) pair with the digit on top
+        for digit in 0..self.winternitz_digits()? {
+            builder = builder
+                .push_int((offset + 2 * digit) as i64)
+                .push_opcode(opcodes::OP_PICK)
+                .push_opcode(opcodes::OP_SIZE)
+                .push_opcode(opcodes::OP_SWAP)
+                .push_opcode(opcodes::OP_0NOTEQUAL)
+                .push_opcode(opcodes::OP_EQUALVERIFY);
+        }
+
+        let mut script = builder.into_bytes();
+        script.extend_from_slice(self.script.as_bytes());
+        self.script = ScriptBuf::from(script);
+
+        Ok(())
+    }
+
+    /// Returns true if the script checks the encoding of all its Winternitz digits (see
+    /// `set_canonical_winternitz_digits`).
+    pub fn has_canonical_winternitz_digits(&self) -> Result<bool, ScriptError> {
+        let opcodes: Vec<_> = self
+            .script
+            .instructions()
+            .filter_map(|instruction| match instruction {
+                Ok(Instruction::Op(opcode)) => Some(opcode),
+                _ => None,
+            })
+            .collect();
+
+        let checks = opcodes
+            .windows(4)
+            .filter(|window| {
+                window
+                    == &[
+                        opcodes::OP_SIZE,
+                        opcodes::OP_SWAP,
+                        opcodes::OP_0NOTEQUAL,
+                        opcodes::OP_EQUALVERIFY,
+                    ]
+            })
+            .count();
+
+        Ok(checks >= self.winternitz_digits()?)
+    }
+
+    /// Script as asm, an instruction per line prefixed with its byte offset, for auditing
+    /// generated scripts. Pushes of the verifying key and of the multisig signers are annotated,
+    /// and so are the public key hashes of the Winternitz keys, which their checks push in key
+    /// position order, with the position, type and message size of their key. Keys that could
+    /// not be located in the script are listed at the end.
+    pub fn disassemble(&self) -> String {
+        let bytes = self.script.as_bytes();
+
+        // Winternitz keys in position order, with the number of hashes each one pushes
+        let mut winternitz_keys = self
+            .get_keys()
+            .into_iter()
+            .filter_map(|key| {
+                let digits = key.key_type().winternitz_digits().ok()?;
+                Some((key, digits))
+            })
+            .peekable();
+        let mut hashes_found = 0;
+        let mut located = vec![];
+
+        let mut lines = vec![];
+        let mut instructions = self.script.instruction_indices();
+        let mut offset = 0;
+        while let Some(instruction) = instructions.next() {
+            let Ok((index, instruction)) = instruction else {
+                // Nothing after a malformed instruction can be decoded
+                lines.push(format!(
+                    "{:04x}  <invalid> {}",
+                    offset,
+                    hex::encode(&bytes[offset..])
+                ));
+                break;
+            };
+            let end = bytes.len() - instructions.as_script().len();
+            let asm = Script::from_bytes(&bytes[index..end]).to_asm_string();
+
+            let mut annotation = None;
+            if let Instruction::PushBytes(push) = instruction {
+                let push = push.as_bytes();
+                // The first signer of a multisig leaf is also its verifying key
+                if let Some(signer) = self.signers.iter().position(|key| is_key_push(key, push)) {
+                    annotation = Some(format!("signer {}", signer));
+                } else if self
+                    .verifying_key
+                    .is_some_and(|key| is_key_push(&key, push))
+                {
+                    annotation = Some(format!("verifying key, {}", self.sign_mode));
+                } else if let Some((key, digits)) = winternitz_keys.peek() {
+                    if Some(push.len()) == winternitz_hash_size(&key.key_type()) {
+                        hashes_found += 1;
+                        annotation = Some(format!(
+                            "key {} at position {} ({}), hash {} of {}",
+                            key.name(),
+                            key.key_position(),
+                            key_type_name(&key.key_type()),
+                            hashes_found,
+                            digits
+                        ));
+                        if hashes_found == *digits {
+                            located.push(key.name().to_string());
+                            winternitz_keys.next();
+                            hashes_found = 0;
+                        }
+                    }
+                }
+            }
+
+            match annotation {
+                Some(annotation) => lines.push(format!("{:04x}  {}  # {}", index, asm, annotation)),
+                None => lines.push(format!("{:04x}  {}", index, asm)),
+            }
+            offset = end;
+        }
+
+        for key in self.get_keys() {
+            if !located.iter().any(|name| name == key.name()) {
+                lines.push(format!(
+                    "# key {} at position {} ({}) is not located in the script",
+                    key.name(),
+                    key.key_position(),
+                    key_type_name(&key.key_type())
+                ));
+            }
+        }
+
+        lines.join("\n")
+    }
+
     pub fn set_assert_leaf_id(&mut self, leaf_id: u32) {
         let original_script = self.script.clone();
         self.script = script!(
@@ -265,6 +910,75 @@ impl ProtocolScript {
             OP_EQUALVERIFY
             { original_script }
         );
+        self.assert_leaf_id = Some(leaf_id);
+    }
+
+    /// Names the script so it can be selected by name instead of by leaf index, see
+    /// `SpendMode::ScriptByName` and `InputArgs::new_taproot_script_args_by_name`.
+    pub fn set_name(&mut self, name: &str) -> Result<&mut Self, ScriptError> {
+        if name.trim().is_empty() {
+            return Err(ScriptError::EmptyScriptName);
+        }
+
+        self.name = Some(name.to_string());
+        Ok(self)
+    }
+
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Signs the leaf with `sighash_type` instead of the sighash type of the spending input, so
+    /// leaves of the same output can commit to different parts of the transaction (e.g. `All`
+    /// for a dispute leaf and `SinglePlusAnyoneCanPay` for a fee bump leaf).
+    pub fn set_sighash_type(&mut self, sighash_type: TapSighashType) -> &mut Self {
+        self.sighash_type = Some(sighash_type);
+        self
+    }
+
+    pub fn get_sighash_type(&self) -> Option<TapSighashType> {
+        self.sighash_type
+    }
+
+    /// Sighash type the leaf is signed with when the input uses `default`.
+    pub fn sighash_type_or(&self, default: TapSighashType) -> TapSighashType {
+        self.sighash_type.unwrap_or(default)
+    }
+
+    /// Leaf id the script asserts, if it was set with `set_assert_leaf_id`.
+    pub fn assert_leaf_id(&self) -> Option<u32> {
+        self.assert_leaf_id
+    }
+}
+
+// True if the push is the compressed or x-only encoding of the key.
+fn is_key_push(key: &PublicKey, push: &[u8]) -> bool {
+    push == key.to_bytes().as_slice() || push == XOnlyPublicKey::from(*key).serialize().as_slice()
+}
+
+// Size of the public key hashes pushed by the checks of a Winternitz key.
+fn winternitz_hash_size(key_type: &KeyType) -> Option<usize> {
+    match key_type {
+        KeyType::WinternitzKey {
+            key_type: WinternitzType::SHA256,
+            ..
+        } => Some(32),
+        KeyType::WinternitzKey {
+            key_type: WinternitzType::HASH160,
+            ..
+        } => Some(20),
+        _ => None,
+    }
+}
+
+fn key_type_name(key_type: &KeyType) -> String {
+    match key_type {
+        KeyType::EcdsaKey => "ecdsa".to_string(),
+        KeyType::XOnlyKey => "x-only".to_string(),
+        KeyType::WinternitzKey {
+            key_type,
+            message_size,
+        } => format!("winternitz {:?}, {} digit message", key_type, message_size),
     }
 }
 
@@ -311,12 +1025,7 @@ pub fn verify_winternitz_signatures_aux<T: AsRef<str>>(
 
     let mut protocol_script = ProtocolScript::new(script, verifying_key, sign_mode);
     for (i, (name, key)) in public_keys.iter().enumerate() {
-        protocol_script.add_key(
-            name.as_ref(),
-            key.derivation_index()?,
-            KeyType::winternitz(key)?,
-            i as u32,
-        )?;
+        protocol_script.add_winternitz_key(name.as_ref(), key, i as u32)?;
     }
 
     Ok(protocol_script)
@@ -335,12 +1044,7 @@ pub fn verify_winternitz_signature(
     );
 
     let mut protocol_script = ProtocolScript::new(script, verifying_key, sign_mode);
-    protocol_script.add_key(
-        "value",
-        public_key.derivation_index()?,
-        KeyType::winternitz(public_key)?,
-        0,
-    )?;
+    protocol_script.add_winternitz_key("value", public_key, 0)?;
 
     Ok(protocol_script)
 }
@@ -364,12 +1068,7 @@ pub fn verify_winternitz_signature_timelock(
     );
 
     let mut protocol_script = ProtocolScript::new(script, verifying_key, sign_mode);
-    protocol_script.add_key(
-        public_key_name,
-        public_key.derivation_index()?,
-        KeyType::winternitz(public_key)?,
-        0,
-    )?;
+    protocol_script.add_winternitz_key(public_key_name, public_key, 0)?;
 
     protocol_script.add_stack_item(StackItem::new_schnorr_sig(true));
     protocol_script.add_stack_item(StackItem::new_winternitz_sig(&public_key));
@@ -390,10 +1089,75 @@ pub fn timelock(blocks: u16, timelock_key: &PublicKey, sign_mode: SignMode) -> P
     ProtocolScript::new(script, timelock_key, sign_mode)
 }
 
+pub fn timelock_absolute(
+    height: u32,
+    timelock_key: &PublicKey,
+    sign_mode: SignMode,
+) -> ProtocolScript {
+    let script = script!(
+        // Once the chain reaches the given height, the timelocked public key can spend the funds
+        { height }
+        OP_CLTV
+        OP_DROP
+        { XOnlyPublicKey::from(*timelock_key).serialize().to_vec() }
+        OP_CHECKSIG
+    );
+
+    ProtocolScript::new(script, timelock_key, sign_mode)
+}
+
 pub fn op_return(data: Vec<u8>) -> ScriptBuf {
     script!(OP_RETURN { data })
 }
 
+/// OP_RETURN scripts carrying the data in chunks of up to `OP_RETURN_MAX_DATA_SIZE` bytes, so
+/// each output is standard. Fails if the scripts exceed `available` bytes, the space left for
+/// OP_RETURN scripts in the transaction (see `OpReturnPolicy::max_total_size`).
+pub fn op_return_chunks(data: &[u8], available: usize) -> Result<Vec<ScriptBuf>, ScriptError> {
+    let chunks = match data.is_empty() {
+        true => vec![data],
+        false => data.chunks(OP_RETURN_MAX_DATA_SIZE).collect(),
+    };
+
+    let too_large = || ScriptError::OpReturnDataTooLarge(data.len(), available);
+    let scripts = chunks
+        .into_iter()
+        .map(|chunk| {
+            <&PushBytes>::try_from(chunk)
+                .map(ScriptBuf::new_op_return)
+                .map_err(|_| too_large())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if scripts.iter().map(|script| script.len()).sum::<usize>() > available {
+        return Err(too_large());
+    }
+
+    Ok(scripts)
+}
+
+/// Data carried by the OP_RETURN output at `output_index`, or `None` if the output does not
+/// exist or is not an OP_RETURN. Data added with `ProtocolBuilder::add_op_return_output` is
+/// reassembled by concatenating the data of the outputs it added, in output order.
+pub fn op_return_data(transaction: &Transaction, output_index: usize) -> Option<Vec<u8>> {
+    let output = transaction.output.get(output_index)?;
+    if !output.script_pubkey.is_op_return() {
+        return None;
+    }
+
+    Some(
+        output
+            .script_pubkey
+            .instructions()
+            .filter_map(|instruction| match instruction {
+                Ok(Instruction::PushBytes(bytes)) => Some(bytes.as_bytes().to_vec()),
+                _ => None,
+            })
+            .flatten()
+            .collect(),
+    )
+}
+
 // TODO aggregated_key must be an aggregated key and not a single public key
 pub fn timelock_renew(aggregated_key: &PublicKey, sign_mode: SignMode) -> ProtocolScript {
     let script = script!(
@@ -413,6 +1177,41 @@ pub fn check_signature(public_key: &PublicKey, sign_mode: SignMode) -> ProtocolS
     ProtocolScript::new(script, public_key, sign_mode)
 }
 
+/// n-of-m tapscript multisig: `<key_1> OP_CHECKSIG <key_2> OP_CHECKSIGADD ... <key_m>
+/// OP_CHECKSIGADD <threshold> OP_NUMEQUAL`. The keys are the signature slots of the leaf, so the
+/// protocol signs every slot whose key is held by the key manager, see
+/// `Protocol::input_multisig_signatures`. The first key is the verifying key of the leaf.
+pub fn checksigadd_multisig(
+    keys: &[PublicKey],
+    threshold: usize,
+    sign_mode: SignMode,
+) -> Result<ProtocolScript, ScriptError> {
+    if keys.is_empty() || threshold == 0 || threshold > keys.len() {
+        return Err(ScriptError::InvalidMultisigThreshold(threshold, keys.len()));
+    }
+
+    let script = script!(
+        for (index, key) in keys.iter().enumerate() {
+            { XOnlyPublicKey::from(*key).serialize().to_vec() }
+            if index == 0 {
+                OP_CHECKSIG
+            } else {
+                OP_CHECKSIGADD
+            }
+        }
+        { threshold as u32 }
+        OP_NUMEQUAL
+    );
+
+    let mut protocol_script = ProtocolScript::new(script, &keys[0], sign_mode);
+    protocol_script.signers = keys.to_vec();
+    for _ in keys {
+        protocol_script.add_stack_item(StackItem::new_schnorr_sig(false));
+    }
+
+    Ok(protocol_script)
+}
+
 pub fn check_aggregated_signature(
     aggregated_key: &PublicKey,
     sign_mode: SignMode,
@@ -436,24 +1235,9 @@ pub fn kickoff(
     );
 
     let mut protocol_script = ProtocolScript::new(script, aggregated_key, sign_mode);
-    protocol_script.add_key(
-        "input",
-        input_key.derivation_index()?,
-        KeyType::winternitz(input_key)?,
-        0,
-    )?;
-    protocol_script.add_key(
-        "ending_state",
-        ending_state_key.derivation_index()?,
-        KeyType::winternitz(ending_state_key)?,
-        1,
-    )?;
-    protocol_script.add_key(
-        "ending_step_number",
-        ending_step_number_key.derivation_index()?,
-        KeyType::winternitz(ending_step_number_key)?,
-        2,
-    )?;
+    protocol_script.add_winternitz_key("input", input_key, 0)?;
+    protocol_script.add_winternitz_key("ending_state", ending_state_key, 1)?;
+    protocol_script.add_winternitz_key("ending_step_number", ending_step_number_key, 2)?;
     Ok(protocol_script)
 }
 
@@ -476,18 +1260,16 @@ pub fn initial_stages(
 
     let mut protocol_script = ProtocolScript::new(script, aggregated_key, sign_mode);
     for (index, key) in interval_keys.iter().enumerate() {
-        protocol_script.add_key(
+        protocol_script.add_winternitz_key(
             format!("stage_{}_{}", stage, index).as_str(),
-            key.derivation_index()?,
-            KeyType::winternitz(key)?,
+            key,
             index as u32,
         )?;
     }
 
-    protocol_script.add_key(
+    protocol_script.add_winternitz_key(
         format!("selection_{}", stage).as_str(),
-        selection_key.derivation_index()?,
-        KeyType::winternitz(selection_key)?,
+        selection_key,
         interval_keys.len() as u32,
     )?;
     Ok(protocol_script)
@@ -514,25 +1296,22 @@ pub fn stage_from_3_and_upward(
 
     let mut protocol_script = ProtocolScript::new(script, aggregated_key, sign_mode);
     for (index, key) in interval_keys.iter().enumerate() {
-        protocol_script.add_key(
+        protocol_script.add_winternitz_key(
             format!("stage_{}_{}", stage, index).as_str(),
-            key.derivation_index()?,
-            KeyType::winternitz(key)?,
+            key,
             index as u32,
         )?;
     }
 
-    protocol_script.add_key(
-        format!("selection_{}", stage).as_str(),
-        key_previous_selection_bob.derivation_index()?,
-        KeyType::winternitz(key_previous_selection_bob)?,
+    protocol_script.add_winternitz_key(
+        format!("selection_{}_bob", stage).as_str(),
+        key_previous_selection_bob,
         interval_keys.len() as u32,
     )?;
-    protocol_script.add_key(
-        format!("selection_{}", stage).as_str(),
-        key_previous_selection_alice.derivation_index()?,
-        KeyType::winternitz(key_previous_selection_alice)?,
-        interval_keys.len() as u32,
+    protocol_script.add_winternitz_key(
+        format!("selection_{}_alice", stage).as_str(),
+        key_previous_selection_alice,
+        interval_keys.len() as u32 + 1,
     )?;
 
     Ok(protocol_script)
@@ -551,12 +1330,7 @@ pub fn linked_message_challenge(
     );
 
     let mut protocol_script = ProtocolScript::new(script, aggregated_key, sign_mode);
-    protocol_script.add_key(
-        "xc",
-        xc_key.derivation_index()?,
-        KeyType::winternitz(xc_key)?,
-        0,
-    )?;
+    protocol_script.add_winternitz_key("xc", xc_key, 0)?;
 
     Ok(protocol_script)
 }
@@ -578,24 +1352,9 @@ pub fn linked_message_response(
     );
 
     let mut protocol_script = ProtocolScript::new(script, aggregated_key, sign_mode);
-    protocol_script.add_key(
-        "xc",
-        xc_key.derivation_index()?,
-        KeyType::winternitz(xc_key)?,
-        0,
-    )?;
-    protocol_script.add_key(
-        "xp",
-        xp_key.derivation_index()?,
-        KeyType::winternitz(xp_key)?,
-        1,
-    )?;
-    protocol_script.add_key(
-        "yp",
-        yp_key.derivation_index()?,
-        KeyType::winternitz(yp_key)?,
-        2,
-    )?;
+    protocol_script.add_winternitz_key("xc", xc_key, 0)?;
+    protocol_script.add_winternitz_key("xp", xp_key, 1)?;
+    protocol_script.add_winternitz_key("yp", yp_key, 2)?;
 
     Ok(protocol_script)
 }
@@ -659,28 +1418,194 @@ pub fn reveal_secret(
     ProtocolScript::new(script, pub_key, sign_mode)
 }
 
+/// Name of the hashlock leaf of the scripts returned by `htlc`.
+pub const HTLC_CLAIM_LEAF: &str = "claim";
+/// Name of the timelock leaf of the scripts returned by `htlc`.
+pub const HTLC_REFUND_LEAF: &str = "refund";
+
+/// Hashed timelock contract leaves. The claim leaf lets the recipient spend revealing the 32 byte
+/// preimage of `hash` (the size is checked so the preimage can be used on other chains too), and
+/// the refund leaf lets the refund key spend once `timeout_blocks` have passed. The leaves are
+/// named `HTLC_CLAIM_LEAF` and `HTLC_REFUND_LEAF`, in that order.
+pub fn htlc(
+    hash: sha256::Hash,
+    recipient_key: &PublicKey,
+    refund_key: &PublicKey,
+    timeout_blocks: u16,
+    sign_mode: SignMode,
+) -> Result<(ProtocolScript, ProtocolScript), ScriptError> {
+    let script = script!(
+        OP_SIZE
+        32
+        OP_EQUALVERIFY
+        OP_SHA256
+        { hash.to_byte_array().to_vec() }
+        OP_EQUALVERIFY
+        { XOnlyPublicKey::from(*recipient_key).serialize().to_vec() }
+        OP_CHECKSIG
+    );
+
+    // The preimage goes on top of the recipient signature
+    let mut claim = ProtocolScript::new(script, recipient_key, sign_mode);
+    claim.set_name(HTLC_CLAIM_LEAF)?;
+    claim.add_stack_item(StackItem::new_raw(32));
+    claim.add_stack_item(StackItem::new_schnorr_sig(false));
+
+    let mut refund = timelock(timeout_blocks, refund_key, sign_mode);
+    refund.set_name(HTLC_REFUND_LEAF)?;
+    refund.add_stack_item(StackItem::new_schnorr_sig(false));
+
+    Ok((claim, refund))
+}
+
 pub fn build_taproot_spend_info(
     secp: &Secp256k1<All>,
     internal_key: &UntweakedPublicKey,
     leaves: &[ProtocolScript],
 ) -> Result<TaprootSpendInfo, ScriptError> {
-    let scripts_count = leaves.len();
+    build_taproot_spend_info_with_layout(secp, internal_key, leaves, &TapTreeLayout::Balanced)
+}
 
+/// Builds the taptree of the leaves with the shape given by the layout. Leaf indexes keep
+/// referring to the position of each leaf in `leaves`, whatever its position in the tree.
+pub fn build_taproot_spend_info_with_layout(
+    secp: &Secp256k1<All>,
+    internal_key: &UntweakedPublicKey,
+    leaves: &[ProtocolScript],
+    layout: &TapTreeLayout,
+) -> Result<TaprootSpendInfo, ScriptError> {
     let mut tr_builder = TaprootBuilder::new();
 
     // For empty scripts finalize the tree
-    if scripts_count == 0 {
+    if leaves.is_empty() {
         return tr_builder
             .finalize(secp, *internal_key)
             .map_err(|_| ScriptError::TapTreeFinalizeError);
     }
 
+    for (leaf_index, depth) in layout.tree_order(leaves.len())? {
+        tr_builder = tr_builder.add_leaf(depth, leaves[leaf_index].get_script().clone())?;
+    }
+
+    tr_builder
+        .finalize(secp, *internal_key)
+        .map_err(|_| ScriptError::TapTreeFinalizeError)
+}
+
+/// Shape of the taptree of a taproot output. Deeper leaves pay a longer control block when spent,
+/// so leaves expected to be spent often should be closer to the root.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum TapTreeLayout {
+    /// Balanced tree, see `taproot_leaf_depths`.
+    #[default]
+    Balanced,
+    /// Depth of each leaf, in leaf order. The depths must describe a full binary tree.
+    Depths(Vec<u8>),
+    /// Expected spend weight (e.g. probability) of each leaf, in leaf order. The leaves are
+    /// placed in a Huffman tree, minimizing the expected control block size.
+    Weights(Vec<u32>),
+}
+
+impl TapTreeLayout {
+    /// Returns the depth of each leaf, in leaf order.
+    pub fn leaf_depths(&self, leaves_count: usize) -> Result<Vec<u8>, ScriptError> {
+        let depths = match self {
+            TapTreeLayout::Balanced => return Ok(taproot_leaf_depths(leaves_count)),
+            TapTreeLayout::Depths(depths) => depths.clone(),
+            TapTreeLayout::Weights(weights) => huffman_depths(weights),
+        };
+
+        if depths.len() != leaves_count {
+            return Err(ScriptError::InvalidTapTreeLayout(format!(
+                "expected {} leaves, got {}",
+                leaves_count,
+                depths.len()
+            )));
+        }
+        check_full_tree(&depths)?;
+
+        Ok(depths)
+    }
+
+    /// Returns the leaf index and depth of each leaf in the order they are added to the tree,
+    /// from left to right.
+    pub fn tree_order(&self, leaves_count: usize) -> Result<Vec<(usize, u8)>, ScriptError> {
+        let depths = self.leaf_depths(leaves_count)?;
+        let mut order = depths.into_iter().enumerate().collect::<Vec<_>>();
+
+        // Adding the deepest leaves first always yields a valid tree. Balanced trees keep the
+        // order of the leaves.
+        if *self != TapTreeLayout::Balanced {
+            order.sort_by_key(|(leaf_index, depth)| (Reverse(*depth), *leaf_index));
+        }
+
+        Ok(order)
+    }
+}
+
+// Depths of the leaves in a Huffman tree of their weights. Ties are broken by creation order so
+// the same weights always produce the same tree.
+fn huffman_depths(weights: &[u32]) -> Vec<u8> {
+    let mut depths = vec![0u8; weights.len()];
+    let mut nodes = weights
+        .iter()
+        .enumerate()
+        .map(|(leaf_index, weight)| Reverse((*weight as u64, leaf_index, vec![leaf_index])))
+        .collect::<BinaryHeap<_>>();
+
+    let mut next_id = weights.len();
+    while nodes.len() > 1 {
+        let Reverse((left_weight, _, mut left)) = nodes.pop().unwrap();
+        let Reverse((right_weight, _, right)) = nodes.pop().unwrap();
+
+        left.extend(right);
+        for leaf_index in left.iter() {
+            depths[*leaf_index] += 1;
+        }
+
+        nodes.push(Reverse((left_weight + right_weight, next_id, left)));
+        next_id += 1;
+    }
+
+    depths
+}
+
+// Checks that the depths describe a full binary tree, merging the leaves in pairs from the deepest
+// level up to the root.
+fn check_full_tree(depths: &[u8]) -> Result<(), ScriptError> {
+    let invalid = |message: &str| Err(ScriptError::InvalidTapTreeLayout(message.to_string()));
+
+    let max_depth = depths.iter().copied().max().unwrap_or_default();
+    if max_depth as usize > TAPROOT_CONTROL_MAX_NODE_COUNT {
+        return invalid("leaves are too deep");
+    }
+
+    let mut nodes = 0usize;
+    for depth in (1..=max_depth).rev() {
+        nodes += depths
+            .iter()
+            .filter(|leaf_depth| **leaf_depth == depth)
+            .count();
+        if !nodes.is_multiple_of(2) {
+            return invalid("depths do not describe a full binary tree");
+        }
+        nodes /= 2;
+    }
+    nodes += depths.iter().filter(|leaf_depth| **leaf_depth == 0).count();
+
+    if nodes != 1 {
+        return invalid("depths do not describe a full binary tree");
+    }
+
+    Ok(())
+}
+
+/// Returns the depth of each leaf in the taptree built by `build_taproot_spend_info`, in the same
+/// order as the leaves are added to the tree.
+pub fn taproot_leaf_depths(scripts_count: usize) -> Vec<u8> {
     // For a single script, add it at depth 0
-    if scripts_count == 1 {
-        tr_builder = tr_builder.add_leaf(0, leaves[0].get_script().clone())?;
-        return tr_builder
-            .finalize(secp, *internal_key)
-            .map_err(|_| ScriptError::TapTreeFinalizeError);
+    if scripts_count <= 1 {
+        return vec![0; scripts_count];
     }
 
     // For multiple scripts, build a balanced tree
@@ -704,19 +1629,16 @@ pub fn build_taproot_spend_info(
     // Calculate how many nodes go at the minimum depth vs minimum depth + 1
     let total_slots = 1 << (min_depth + 1); // 2^(min_depth + 1)
     let nodes_at_min_depth = total_slots - scripts_count;
-    // Add leaves at minimum depth
-    for i in 0..nodes_at_min_depth {
-        tr_builder = tr_builder.add_leaf(min_depth, leaves[i].get_script().clone())?;
-    }
 
-    // Add remaining leaves at minimum depth + 1
-    for i in nodes_at_min_depth..scripts_count {
-        tr_builder = tr_builder.add_leaf(min_depth + 1, leaves[i].get_script().clone())?;
-    }
-
-    tr_builder
-        .finalize(secp, *internal_key)
-        .map_err(|_| ScriptError::TapTreeFinalizeError)
+    (0..scripts_count)
+        .map(|i| {
+            if i < nodes_at_min_depth {
+                min_depth
+            } else {
+                min_depth + 1
+            }
+        })
+        .collect()
 }
 
 pub fn operator_hashed_slot_preimage(
@@ -751,7 +1673,7 @@ pub fn verify_signature(
 mod tests {
     use bitcoin::{
         hex::FromHex,
-        opcodes::all::{OP_CHECKSIG, OP_CSV, OP_DROP, OP_RETURN},
+        opcodes::all::{OP_CHECKSIG, OP_CLTV, OP_CSV, OP_DROP, OP_RETURN},
         PublicKey, XOnlyPublicKey,
     };
     use std::str::FromStr;
@@ -830,16 +1752,17 @@ mod tests {
 
         let mut script = ProtocolScript::new(get_script_buff(), &verifying_key, SignMode::Single);
         script
-            .add_key(AGGREGATED_SIGNATURE, 0, KeyType::EcdsaKey, 0)
+            .add_key("first", 0, KeyType::EcdsaKey, 0)
             .expect("Failed to add key");
         script
-            .add_key(AGGREGATED_SIGNATURE, 2, KeyType::EcdsaKey, 2)
+            .add_key("third", 2, KeyType::EcdsaKey, 2)
             .expect("Failed to add key");
         script
-            .add_key(AGGREGATED_SIGNATURE, 1, KeyType::EcdsaKey, 1)
+            .add_key("second", 1, KeyType::EcdsaKey, 1)
             .expect("Failed to add key");
         let keys = script.get_keys();
 
+        assert_eq!(keys.len(), 3);
         assert!(keys
             .windows(2)
             .all(|w| w[0].key_position() <= w[1].key_position()));
@@ -912,6 +1835,32 @@ mod tests {
         assert!(script.add_key("", 1, KeyType::EcdsaKey, 0).is_err());
     }
 
+    #[test]
+    fn test_timelock_absolute_output_script() {
+        let height = 850_000;
+        let pubkey_bytes =
+            hex::decode("02c6047f9441ed7d6d3045406e95c07cd85a6a6d4c90d35b8c6a568f07cfd511fd")
+                .expect("Decoding failed");
+        let public_key = PublicKey::from_slice(&pubkey_bytes).expect("Invalid public key format");
+
+        let script_timelock = timelock_absolute(height, &public_key, SignMode::Single);
+
+        let instructions = script_timelock
+            .get_script()
+            .instructions()
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(instructions.len(), 5, "Script should have 5 instructions");
+        assert_eq!(instructions[0].script_num(), Some(height as i64));
+        assert_eq!(instructions[1].opcode(), Some(OP_CLTV));
+        assert_eq!(instructions[2].opcode(), Some(OP_DROP));
+        assert_eq!(
+            instructions[3].push_bytes().unwrap().as_bytes(),
+            &public_key.inner.serialize()[1..],
+        );
+        assert_eq!(instructions[4].opcode(), Some(OP_CHECKSIG));
+    }
+
     #[test]
     fn test_timelock_output_script() {
         // Arrange
@@ -0,0 +1,242 @@
+use key_manager::key_type::BitcoinKeyType;
+use proptest::{collection::vec, option, prelude::*, sample::Index};
+
+use crate::{
+    builder::Protocol,
+    errors::ProtocolBuilderError,
+    scripts::{self, ProtocolScript, SignMode},
+    types::{
+        connection::{InputSpec, OutputSpec},
+        input::SpendMode,
+        output::OutputType,
+        witness_args::{InputWitnessArgs, WitnessArgs},
+    },
+};
+
+use super::TestContext;
+
+/// Most leaves of the generated taproot outputs.
+pub const MAX_LEAVES: usize = 4;
+
+/// Value of every generated output, amounts are not conserved.
+pub const OUTPUT_VALUE: u64 = 1_000;
+
+/// Name of the external transaction funding the first generated transaction.
+pub const FUNDING_NAME: &str = "funding";
+
+/// How a generated output is created and spent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArbitrarySpend {
+    /// P2WPKH output.
+    Segwit,
+    /// Taproot output with signature checking leaves, spent through the key path.
+    TaprootKey { leaves: usize },
+    /// Taproot output with signature checking leaves, spent through one of them.
+    TaprootLeaf { leaves: usize, leaf: usize },
+}
+
+/// Spend of an output of `from` by a new input of `to`.
+#[derive(Clone, Debug)]
+pub struct ArbitraryConnection {
+    pub from: usize,
+    pub to: usize,
+    pub spend: ArbitrarySpend,
+    pub timelock: Option<u16>,
+}
+
+/// Random protocol DAG, generated with `arbitrary_protocol` or `any::<ArbitraryProtocol>()` and
+/// turned into a `Protocol` with `build`. Transactions are named after their index and only
+/// spend transactions with lower indexes, so the indexes are a topological order. The first
+/// transaction spends an external funding transaction.
+#[derive(Clone, Debug)]
+pub struct ArbitraryProtocol {
+    pub transactions: usize,
+    pub funding: ArbitrarySpend,
+    pub connections: Vec<ArbitraryConnection>,
+    /// Extra outputs nobody spends, as the index of their transaction and their kind.
+    pub unspent_outputs: Vec<(usize, ArbitrarySpend)>,
+}
+
+impl ArbitraryProtocol {
+    pub fn transaction_name(index: usize) -> String {
+        format!("tx{}", index)
+    }
+
+    pub fn transaction_names(&self) -> Vec<String> {
+        (0..self.transactions).map(Self::transaction_name).collect()
+    }
+
+    /// Spends of the inputs of a transaction, by input index.
+    pub fn inputs(&self, transaction: usize) -> Vec<&ArbitrarySpend> {
+        let funding = (transaction == 0).then_some(&self.funding);
+        funding
+            .into_iter()
+            .chain(
+                self.connections
+                    .iter()
+                    .filter(|connection| connection.to == transaction)
+                    .map(|connection| &connection.spend),
+            )
+            .collect()
+    }
+
+    /// Leaves spent by the inputs of a transaction, for `Protocol::finalize`.
+    pub fn witness_args(&self, transaction: usize) -> WitnessArgs {
+        let inputs = self
+            .inputs(transaction)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(input, spend)| match spend {
+                ArbitrarySpend::TaprootLeaf { leaf, .. } => Some(InputWitnessArgs {
+                    input,
+                    leaf: Some(*leaf),
+                    items: vec![],
+                }),
+                _ => None,
+            })
+            .collect();
+
+        WitnessArgs { inputs }
+    }
+
+    /// Protocol with the generated transactions, with keys of the context key manager. It is
+    /// neither built nor signed.
+    pub fn build(&self, tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("arbitrary");
+
+        protocol.add_external_tx(tc.funding_tx(FUNDING_NAME, output(tc, &self.funding)?))?;
+        protocol.add_connection(
+            FUNDING_NAME,
+            FUNDING_NAME,
+            OutputSpec::Last,
+            Self::transaction_name(0),
+            input(tc, &self.funding),
+            None,
+            None,
+        )?;
+
+        for (index, connection) in self.connections.iter().enumerate() {
+            protocol.add_connection(
+                &format!("connection_{}", index),
+                Self::transaction_name(connection.from),
+                OutputSpec::Auto(output(tc, &connection.spend)?),
+                Self::transaction_name(connection.to),
+                input(tc, &connection.spend),
+                connection.timelock,
+                None,
+            )?;
+        }
+
+        for (transaction, spend) in self.unspent_outputs.iter() {
+            protocol.add_transaction_output(
+                &Self::transaction_name(*transaction),
+                &output(tc, spend)?,
+            )?;
+        }
+
+        Ok(protocol)
+    }
+}
+
+impl Arbitrary for ArbitraryProtocol {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        arbitrary_protocol(8).boxed()
+    }
+}
+
+/// Protocols of 1 to `max_transactions` transactions. Every transaction but the first spends one
+/// or two outputs of lower indexed transactions.
+pub fn arbitrary_protocol(max_transactions: usize) -> impl Strategy<Value = ArbitraryProtocol> {
+    (1..=max_transactions.max(1)).prop_flat_map(|transactions| {
+        let parents = || {
+            vec(
+                (any::<Index>(), arbitrary_spend(), option::of(1..=16u16)),
+                1..=2,
+            )
+        };
+
+        (
+            arbitrary_spend(),
+            vec(parents(), transactions - 1),
+            vec((any::<Index>(), arbitrary_spend()), 0..=3),
+        )
+            .prop_map(move |(funding, parents, unspent_outputs)| {
+                let connections = parents
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(child, parents)| {
+                        let to = child + 1;
+                        parents.into_iter().map(move |(from, spend, timelock)| {
+                            ArbitraryConnection {
+                                from: from.index(to),
+                                to,
+                                spend,
+                                timelock,
+                            }
+                        })
+                    })
+                    .collect();
+
+                ArbitraryProtocol {
+                    transactions,
+                    funding,
+                    connections,
+                    unspent_outputs: unspent_outputs
+                        .into_iter()
+                        .map(|(transaction, spend)| (transaction.index(transactions), spend))
+                        .collect(),
+                }
+            })
+    })
+}
+
+pub fn arbitrary_spend() -> impl Strategy<Value = ArbitrarySpend> {
+    prop_oneof![
+        Just(ArbitrarySpend::Segwit),
+        (0..=MAX_LEAVES).prop_map(|leaves| ArbitrarySpend::TaprootKey { leaves }),
+        (1..=MAX_LEAVES)
+            .prop_flat_map(|leaves| (Just(leaves), 0..leaves))
+            .prop_map(|(leaves, leaf)| ArbitrarySpend::TaprootLeaf { leaves, leaf }),
+    ]
+}
+
+fn output(tc: &TestContext, spend: &ArbitrarySpend) -> Result<OutputType, ProtocolBuilderError> {
+    let leaves = match spend {
+        ArbitrarySpend::Segwit => return tc.segwit_funding(OUTPUT_VALUE, 0),
+        ArbitrarySpend::TaprootKey { leaves } | ArbitrarySpend::TaprootLeaf { leaves, .. } => {
+            *leaves
+        }
+    };
+
+    // A key per leaf so no two leaves of an output are the same script
+    let leaves = (1..=leaves as u32)
+        .map(|index| {
+            let key = tc.derive_key(BitcoinKeyType::P2tr, index)?;
+            Ok(scripts::check_signature(&key, SignMode::Single))
+        })
+        .collect::<Result<Vec<ProtocolScript>, ProtocolBuilderError>>()?;
+
+    OutputType::taproot(
+        OUTPUT_VALUE,
+        &tc.derive_key(BitcoinKeyType::P2tr, 0)?,
+        &leaves,
+    )
+}
+
+fn input(tc: &TestContext, spend: &ArbitrarySpend) -> InputSpec {
+    match spend {
+        ArbitrarySpend::Segwit => InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+        ArbitrarySpend::TaprootKey { .. } => InputSpec::Auto(
+            tc.tr_sighash_type(),
+            SpendMode::KeyOnly {
+                key_path_sign: SignMode::Single,
+            },
+        ),
+        ArbitrarySpend::TaprootLeaf { leaf, .. } => {
+            InputSpec::Auto(tc.tr_sighash_type(), SpendMode::Script { leaf: *leaf })
+        }
+    }
+}
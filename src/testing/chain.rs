@@ -0,0 +1,84 @@
+use std::collections::{HashMap, HashSet};
+
+use bitcoin::Txid;
+
+use crate::{
+    broadcast::{ChainState, ChainTime},
+    errors::BroadcastError,
+};
+
+/// Seconds the mock median time past advances for each mined block.
+pub const MOCK_BLOCK_INTERVAL: u32 = 600;
+
+/// In-memory chain to test timelocks and broadcast rules without a node. Transactions are mined
+/// at the current height, so their confirmations grow as blocks are added.
+#[derive(Clone, Debug, Default)]
+pub struct MockChain {
+    height: u32,
+    median_time: u32,
+    mined: HashMap<Txid, u32>,
+    mempool: HashSet<Txid>,
+}
+
+impl MockChain {
+    pub fn new(height: u32, median_time: u32) -> Self {
+        Self {
+            height,
+            median_time,
+            ..Default::default()
+        }
+    }
+
+    /// Adds blocks to the chain, advancing the median time `MOCK_BLOCK_INTERVAL` per block.
+    pub fn advance(&mut self, blocks: u32) -> &mut Self {
+        self.height += blocks;
+        self.median_time += blocks * MOCK_BLOCK_INTERVAL;
+        self
+    }
+
+    pub fn set_median_time(&mut self, median_time: u32) -> &mut Self {
+        self.median_time = median_time;
+        self
+    }
+
+    /// Mines the transaction in a new block at the next height.
+    pub fn mine(&mut self, txid: Txid) -> &mut Self {
+        self.advance(1);
+        self.mempool.remove(&txid);
+        self.mined.insert(txid, self.height);
+        self
+    }
+
+    pub fn add_to_mempool(&mut self, txid: Txid) -> &mut Self {
+        if !self.mined.contains_key(&txid) {
+            self.mempool.insert(txid);
+        }
+        self
+    }
+
+    /// Removes a transaction from the mempool, as an eviction or a replacement would.
+    pub fn evict(&mut self, txid: Txid) -> &mut Self {
+        self.mempool.remove(&txid);
+        self
+    }
+}
+
+impl ChainTime for MockChain {
+    fn current_height(&self) -> Result<u32, BroadcastError> {
+        Ok(self.height)
+    }
+
+    fn median_time(&self) -> Result<u32, BroadcastError> {
+        Ok(self.median_time)
+    }
+}
+
+impl ChainState for MockChain {
+    fn confirmations(&self, txid: &Txid) -> Result<Option<u32>, BroadcastError> {
+        if let Some(height) = self.mined.get(txid) {
+            return Ok(Some(self.height - height + 1));
+        }
+
+        Ok(self.mempool.contains(txid).then_some(0))
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{
+    builder::{Protocol, ProtocolBuilder},
+    errors::ProtocolBuilderError,
+    scripts::{ProtocolScript, SignMode, StackItem},
+    types::{
+        connection::{InputSpec, OutputSpec},
+        external::ExternalTx,
+        input::{SighashType, SpendMode},
+        output::OutputType,
+    },
+};
+use anyhow::Error;
+use bitcoin::{
+    hashes::Hash,
+    key::rand::RngCore,
+    opcodes::all::OP_CHECKSIG,
+    script::Builder,
+    secp256k1::{self},
+    Network, PublicKey, Txid, XOnlyPublicKey,
+};
+use key_manager::{
+    config::KeyManagerConfig, create_key_manager_from_config, key_manager::KeyManager,
+    key_type::BitcoinKeyType,
+};
+use redact::Secret;
+use std::{env, fs, path::PathBuf, rc::Rc};
+use storage_backend::{storage::Storage, storage_config::StorageConfig};
+
+/// Mnemonic of the key managers created by `TestContext`, so tests derive the same keys on
+/// every run.
+pub const TEST_MNEMONIC: &str =
+    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+pub struct TemporaryDir {
+    pub path: PathBuf,
+}
+
+impl TemporaryDir {
+    /// Create a new test directory structure
+    pub fn new(test_prefix: &str) -> Self {
+        let temp_base = env::temp_dir();
+        let mut rng = secp256k1::rand::thread_rng();
+        let random_id = rng.next_u32();
+        let temp_path = temp_base.join(format!("{}_{}", test_prefix, random_id));
+        fs::create_dir_all(&temp_path).expect("Failed to create temp directory");
+
+        Self { path: temp_path }
+    }
+
+    /// Get a path inside the test subdir
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.path.join(relative)
+    }
+}
+
+// Optional: clean up the temporary directory when done (after all tests)
+impl Drop for TemporaryDir {
+    fn drop(&mut self) {
+        // Clean up the entire root dir, including all test subdirs
+        if self.path.exists() {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// Options of a `TestContext`, created with `TestContext::builder`.
+pub struct TestContextBuilder {
+    path_prefix: String,
+    network: Network,
+    mnemonic: String,
+}
+
+impl TestContextBuilder {
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Derives the keys from another mnemonic, e.g. to give each party of a test its own keys.
+    pub fn with_mnemonic(mut self, mnemonic: &str) -> Self {
+        self.mnemonic = mnemonic.to_string();
+        self
+    }
+
+    pub fn build(self) -> Result<TestContext, Error> {
+        let test_dir = TemporaryDir::new(&self.path_prefix);
+        let key_manager =
+            key_manager_from_mnemonic(self.network, &self.path_prefix, &self.mnemonic)?;
+
+        Ok(TestContext {
+            test_dir,
+            key_manager,
+            network: self.network,
+        })
+    }
+}
+
+/// Scaffolding to build protocols in tests: a key manager with deterministic keys, storages in a
+/// temporary directory removed on drop, and the funding outputs most protocols start from.
+pub struct TestContext {
+    test_dir: TemporaryDir,
+    key_manager: Rc<KeyManager>,
+    network: Network,
+}
+
+impl TestContext {
+    /// Regtest context with keys derived from `TEST_MNEMONIC`.
+    pub fn new(test_context_path_prefix: &str) -> Result<Self, Error> {
+        Self::builder(test_context_path_prefix).build()
+    }
+
+    pub fn builder(test_context_path_prefix: &str) -> TestContextBuilder {
+        TestContextBuilder {
+            path_prefix: test_context_path_prefix.to_string(),
+            network: Network::Regtest,
+            mnemonic: TEST_MNEMONIC.to_string(),
+        }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn ecdsa_sighash_type(&self) -> SighashType {
+        SighashType::ecdsa_all()
+    }
+
+    pub fn tr_sighash_type(&self) -> SighashType {
+        SighashType::taproot_all()
+    }
+
+    pub fn key_manager(&self) -> &Rc<KeyManager> {
+        &self.key_manager
+    }
+
+    pub fn new_storage(&self, name: &str) -> Storage {
+        let path = self.test_dir.path(name).to_str().unwrap().to_string();
+        let config = StorageConfig::new(path, None);
+        Storage::new(&config).unwrap()
+    }
+
+    pub fn derive_key(
+        &self,
+        key_type: BitcoinKeyType,
+        index: u32,
+    ) -> Result<PublicKey, ProtocolBuilderError> {
+        Ok(self.key_manager.derive_keypair(key_type, index)?)
+    }
+
+    /// P2WPKH output of the key derived at `index`.
+    pub fn segwit_funding(
+        &self,
+        value: u64,
+        index: u32,
+    ) -> Result<OutputType, ProtocolBuilderError> {
+        OutputType::segwit_key(value, &self.derive_key(BitcoinKeyType::P2wpkh, index)?)
+    }
+
+    /// Key path only taproot output of the key derived at `index`.
+    pub fn taproot_funding(
+        &self,
+        value: u64,
+        index: u32,
+    ) -> Result<OutputType, ProtocolBuilderError> {
+        OutputType::taproot(value, &self.derive_key(BitcoinKeyType::P2tr, index)?, &[])
+    }
+
+    /// External funding transaction with the given output. Its txid is derived from the name, so
+    /// the protocols built on it are the same on every run.
+    pub fn funding_tx(&self, name: &str, output: OutputType) -> ExternalTx {
+        let txid = Txid::hash(name.as_bytes());
+        ExternalTx::new(name, txid).with_known_output(output)
+    }
+
+    /// Connects an external transaction named `EXT`, with an all zeros txid, to `to` through
+    /// `output`. Segwit inputs are signed with `ecdsa_sighash_type` and any other input with
+    /// `tr_sighash_type`.
+    pub fn add_external_input(
+        &self,
+        protocol: &mut Protocol,
+        output: OutputType,
+        to: &str,
+        spend_mode: SpendMode,
+    ) -> Result<(), ProtocolBuilderError> {
+        let sighash_type = match spend_mode {
+            SpendMode::Segwit => self.ecdsa_sighash_type(),
+            _ => self.tr_sighash_type(),
+        };
+
+        ProtocolBuilder {}.add_external_connection(
+            protocol,
+            "EXT",
+            Hash::all_zeros(),
+            OutputSpec::Auto(output),
+            to,
+            InputSpec::Auto(sighash_type, spend_mode),
+        )?;
+        Ok(())
+    }
+}
+
+/// `<key> OP_CHECKSIG` leaf signed with the key.
+pub fn checksig_leaf(public_key: &PublicKey) -> ProtocolScript {
+    let script = Builder::new()
+        .push_x_only_key(&XOnlyPublicKey::from(*public_key))
+        .push_opcode(OP_CHECKSIG)
+        .into_script();
+    ProtocolScript::new(script, public_key, SignMode::Single)
+}
+
+/// Same as `checksig_leaf`, declaring the Schnorr signature the leaf takes as its stack item.
+pub fn checksig_leaf_with_signature(public_key: &PublicKey) -> ProtocolScript {
+    let mut leaf = checksig_leaf(public_key);
+    leaf.add_stack_item(StackItem::new_schnorr_sig(false));
+    leaf
+}
+
+pub fn new_key_manager(network: Network, path_prefix: &str) -> Result<Rc<KeyManager>, Error> {
+    key_manager_from_mnemonic(network, path_prefix, TEST_MNEMONIC)
+}
+
+fn key_manager_from_mnemonic(
+    network: Network,
+    path_prefix: &str,
+    mnemonic: &str,
+) -> Result<Rc<KeyManager>, Error> {
+    let test_dir = TemporaryDir::new(path_prefix);
+    let keystore_path = test_dir.path("keystore");
+
+    let keystore_password = Secret::from("secret_password__123ABC");
+
+    let storage_config = StorageConfig::new(
+        keystore_path.to_str().unwrap().to_string(),
+        Some(keystore_password),
+    );
+
+    let key_manager_config =
+        KeyManagerConfig::new(network.to_string(), Some(mnemonic.to_string().into()), None);
+
+    let key_manager = create_key_manager_from_config(&key_manager_config, &storage_config)?;
+
+    Ok(Rc::new(key_manager))
+}
+
+pub fn clear_test_directories() {
+    let temp_base = env::temp_dir();
+    let root_dir = temp_base.join("key_manager");
+
+    if root_dir.exists() {
+        let _ = fs::remove_dir_all(&root_dir);
+    }
+}
@@ -0,0 +1,10 @@
+//! Helpers to test protocols, available to other crates with the `testing` feature.
+
+pub mod arbitrary;
+pub mod chain;
+pub mod context;
+#[cfg(feature = "regtest")]
+pub mod regtest;
+pub mod snapshot;
+
+pub use context::{TestContext, TestContextBuilder};
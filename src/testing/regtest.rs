@@ -0,0 +1,175 @@
+use bitcoin::{address::NetworkUnchecked, Address, Network, Transaction, Txid};
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+use bitvmx_bitcoin_rpc::{
+    bitcoin_client::{BitcoinClient, BitcoinClientApi},
+    rpc_config::RpcConfig,
+};
+use tracing::debug;
+
+use crate::{
+    broadcast::{Broadcaster, ChainState, ChainTime, FeeEstimator},
+    builder::Protocol,
+    errors::{BroadcastError, ProtocolBuilderError},
+    executor::NextTransaction,
+    types::{output::OutputType, Signer},
+};
+
+/// Blocks mined at most while waiting for the timelocks of a transaction.
+pub const MAX_WAIT_BLOCKS: u32 = 1_000;
+
+/// Runs protocol transactions through a live regtest node, to check they are valid by consensus
+/// and not only well formed. Funds, broadcasts and mines with `bitvmx_bitcoin_rpc`, and reads
+/// confirmations with `getrawtransaction`, so the node must run with `-txindex`.
+///
+/// Blocks are mined to a new address of the configured wallet, which funds the protocols. Mine
+/// 101 blocks on a fresh node so the first coinbase can be spent.
+pub struct RegtestHarness {
+    client: BitcoinClient,
+    node: Client,
+    mining_address: Address,
+}
+
+impl RegtestHarness {
+    pub fn new(config: &RpcConfig) -> Result<Self, ProtocolBuilderError> {
+        let client = BitcoinClient::new_from_config(config).map_err(unavailable)?;
+
+        let url = format!("{}/wallet/{}", config.url, config.wallet);
+        let auth = Auth::UserPass(config.username.clone(), config.password.clone());
+        let node = Client::new(&url, auth).map_err(unavailable)?;
+
+        let mining_address = node
+            .call::<String>("getnewaddress", &[])
+            .map_err(unavailable)?
+            .parse::<Address<NetworkUnchecked>>()
+            .map_err(unavailable)?
+            .require_network(Network::Regtest)
+            .map_err(unavailable)?;
+
+        Ok(Self {
+            client,
+            node,
+            mining_address,
+        })
+    }
+
+    pub fn client(&self) -> &BitcoinClient {
+        &self.client
+    }
+
+    pub fn node(&self) -> &Client {
+        &self.node
+    }
+
+    /// Mines blocks to the wallet, returns the new tip height.
+    pub fn mine(&self, blocks: u32) -> Result<u32, ProtocolBuilderError> {
+        self.client
+            .mine_blocks_to_address(blocks as u64, &self.mining_address)
+            .map_err(unavailable)?;
+        Ok(self.current_height()?)
+    }
+
+    /// Sends the value of an output to its script pubkey from the wallet and mines the funding
+    /// transaction. Returns the transaction and the index of the funded output, to connect
+    /// external inputs of a protocol to.
+    pub fn fund(&self, output: &OutputType) -> Result<(Transaction, u32), ProtocolBuilderError> {
+        let address = Address::from_script(output.get_script_pubkey(), Network::Regtest)
+            .map_err(|e| BroadcastError::Rejected(e.to_string()))?;
+
+        let (transaction, vout) = self
+            .client
+            .fund_address(&address, output.get_value())
+            .map_err(|e| BroadcastError::Rejected(e.to_string()))?;
+        self.mine(1)?;
+
+        Ok((transaction, vout))
+    }
+
+    /// Finalizes a transaction of a built and signed protocol, mining blocks until its timelocks
+    /// allow it, then broadcasts it and mines it.
+    pub fn send<S: Signer + ?Sized>(
+        &self,
+        protocol: &Protocol,
+        next: &NextTransaction,
+        signer: &S,
+    ) -> Result<Txid, ProtocolBuilderError> {
+        let name = &next.transaction_name;
+
+        let mut waited = 0;
+        while !protocol.ready_to_send(name, self)? {
+            if waited == MAX_WAIT_BLOCKS {
+                return Err(ProtocolBuilderError::TransactionNotReady(
+                    name.clone(),
+                    waited,
+                ));
+            }
+            self.mine(1)?;
+            waited += 1;
+        }
+
+        let transaction = protocol.finalize(name, next.leaf, &next.witness_args, signer)?;
+        let txid = self.client.broadcast(&transaction)?;
+        self.mine(1)?;
+        self.assert_confirmations(name, &txid, 1)?;
+
+        debug!("Transaction {} confirmed after {} blocks", name, waited);
+        Ok(txid)
+    }
+
+    /// Sends the transactions in order, parents before their children.
+    pub fn run<S: Signer + ?Sized>(
+        &self,
+        protocol: &Protocol,
+        transactions: &[NextTransaction],
+        signer: &S,
+    ) -> Result<Vec<Txid>, ProtocolBuilderError> {
+        transactions
+            .iter()
+            .map(|next| self.send(protocol, next, signer))
+            .collect()
+    }
+
+    /// Fails unless the transaction has at least the given confirmations, returns them.
+    pub fn assert_confirmations(
+        &self,
+        transaction_name: &str,
+        txid: &Txid,
+        confirmations: u32,
+    ) -> Result<u32, ProtocolBuilderError> {
+        let found = self.confirmations(txid)?.unwrap_or(0);
+        if found < confirmations {
+            return Err(ProtocolBuilderError::TransactionUnconfirmed(
+                transaction_name.to_string(),
+                found,
+                confirmations,
+            ));
+        }
+
+        Ok(found)
+    }
+}
+
+impl ChainTime for RegtestHarness {
+    fn current_height(&self) -> Result<u32, BroadcastError> {
+        self.node.current_height()
+    }
+
+    fn median_time(&self) -> Result<u32, BroadcastError> {
+        self.node.median_time()
+    }
+}
+
+impl ChainState for RegtestHarness {
+    fn confirmations(&self, txid: &Txid) -> Result<Option<u32>, BroadcastError> {
+        self.node.confirmations(txid)
+    }
+}
+
+impl FeeEstimator for RegtestHarness {
+    fn estimate_fee_rate(&self, target_blocks: u16) -> Result<u64, BroadcastError> {
+        self.node.estimate_fee_rate(target_blocks)
+    }
+}
+
+fn unavailable(error: impl ToString) -> BroadcastError {
+    BroadcastError::Unavailable(error.to_string())
+}
@@ -0,0 +1,132 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Environment variable that makes `assert_snapshot` accept the current output, creating or
+/// overwriting the stored snapshots.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "UPDATE_SNAPSHOTS";
+
+/// Extension of the stored snapshots. Rejected outputs are written next to them with a `.new`
+/// suffix, so they can be reviewed and renamed to accept them.
+pub const SNAPSHOT_EXTENSION: &str = "snap";
+
+#[derive(Debug, PartialEq)]
+pub enum SnapshotError {
+    Missing(PathBuf),
+    Mismatch {
+        path: PathBuf,
+        line: usize,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Missing(path) => write!(
+                f,
+                "snapshot {} does not exist, run with {}=1 to create it",
+                path.display(),
+                UPDATE_SNAPSHOTS_ENV
+            ),
+            SnapshotError::Mismatch {
+                path,
+                line,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "snapshot {} changed at line {}\n  expected: {}\n  actual:   {}\nrun with {}=1 to accept the change",
+                path.display(),
+                line,
+                expected,
+                actual,
+                UPDATE_SNAPSHOTS_ENV
+            ),
+        }
+    }
+}
+
+/// Serializes a value as pretty printed JSON with the keys of every object sorted, so exports
+/// of maps don't depend on their iteration order.
+pub fn canonical_json<T: Serialize>(value: &T) -> Result<String, serde_json::Error> {
+    let value = canonicalize(serde_json::to_value(value)?);
+    let mut json = serde_json::to_string_pretty(&value)?;
+    json.push('\n');
+    Ok(json)
+}
+
+fn canonicalize(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            let mut sorted = Map::new();
+            for (key, value) in entries {
+                sorted.insert(key, canonicalize(value));
+            }
+            Value::Object(sorted)
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonicalize).collect()),
+        value => value,
+    }
+}
+
+pub fn snapshot_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.{}", name, SNAPSHOT_EXTENSION))
+}
+
+/// Compares an output with the snapshot `{dir}/{name}.snap`, reporting the first line that
+/// differs.
+pub fn check_snapshot(dir: &Path, name: &str, actual: &str) -> Result<(), SnapshotError> {
+    let path = snapshot_path(dir, name);
+    let Ok(expected) = fs::read_to_string(&path) else {
+        return Err(SnapshotError::Missing(path));
+    };
+
+    if expected == actual {
+        return Ok(());
+    }
+
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => line += 1,
+            (expected, actual) => {
+                return Err(SnapshotError::Mismatch {
+                    path,
+                    line,
+                    expected: expected.unwrap_or("<end of snapshot>").to_string(),
+                    actual: actual.unwrap_or("<end of output>").to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// Fails when an output differs from its stored snapshot. The rejected output is written to
+/// `{dir}/{name}.snap.new`. When `UPDATE_SNAPSHOTS` is set the output is stored instead.
+pub fn assert_snapshot(dir: &Path, name: &str, actual: &str) {
+    let path = snapshot_path(dir, name);
+
+    if env::var_os(UPDATE_SNAPSHOTS_ENV).is_some() {
+        fs::create_dir_all(dir).expect("Failed to create snapshot directory");
+        fs::write(&path, actual).expect("Failed to write snapshot");
+        return;
+    }
+
+    if let Err(error) = check_snapshot(dir, name, actual) {
+        let mut rejected = path.into_os_string();
+        rejected.push(".new");
+        let _ = fs::write(rejected, actual);
+        panic!("{}", error);
+    }
+}
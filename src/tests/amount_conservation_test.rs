@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        tests::utils::TestContext,
+        types::{input::SpendMode, output::OutputType},
+    };
+
+    fn protocol(tc: &TestContext, value: u64) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("amount_conservation");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        protocol.add_transaction_output("A", &OutputType::segwit_key(value, &public_key)?)?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_amount_conservation() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_amount_conservation").unwrap();
+        let mut protocol = protocol(&tc, 9_000)?;
+        protocol.require_amount_conservation(true)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        protocol.set_transaction_fee("A", 1_000)?;
+        assert_eq!(protocol.transaction_fee("A"), 1_000);
+        protocol.build(tc.key_manager(), "")?;
+
+        protocol.set_transaction_fee("A", 1_500)?;
+        assert!(matches!(
+            protocol.build(tc.key_manager(), ""),
+            Err(ProtocolBuilderError::ValueShortfall(name, 10_000, 9_000, 1_500, 500)) if name == "A"
+        ));
+
+        assert!(matches!(
+            protocol.set_transaction_fee("B", 1_000),
+            Err(ProtocolBuilderError::GraphBuildingError(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_amount_conservation_not_required() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_amount_conservation_not_required").unwrap();
+
+        // Outputs worth more than the inputs are only rejected when required
+        let mut protocol = protocol(&tc, 12_000)?;
+        protocol.build(tc.key_manager(), "")?;
+        assert!(matches!(
+            protocol.check_amounts(),
+            Err(ProtocolBuilderError::ValueShortfall(
+                _,
+                10_000,
+                12_000,
+                0,
+                2_000
+            ))
+        ));
+
+        protocol.require_amount_conservation(true)?;
+        assert!(protocol.build(tc.key_manager(), "").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_amount_conservation_on_rebuild() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_amount_conservation_on_rebuild").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = protocol(&tc, 9_000)?;
+        protocol.require_amount_conservation(true)?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        // The updated output spends more than the transaction receives
+        protocol.update_output("A", 0, &OutputType::segwit_key(12_000, &public_key)?)?;
+        assert!(matches!(
+            protocol.rebuild(tc.key_manager(), ""),
+            Err(ProtocolBuilderError::ValueShortfall(name, 10_000, 12_000, 0, 2_000)) if name == "A"
+        ));
+        assert!(matches!(
+            protocol.rebuild_and_sign(tc.key_manager(), ""),
+            Err(ProtocolBuilderError::ValueShortfall(..))
+        ));
+
+        Ok(())
+    }
+}
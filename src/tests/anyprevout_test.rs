@@ -0,0 +1,151 @@
+#[cfg(all(test, feature = "anyprevout"))]
+mod tests {
+    use bitcoin::{
+        hashes::Hash, secp256k1, Amount, OutPoint, ScriptBuf, TxOut, Txid, XOnlyPublicKey,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        helpers::anyprevout::{
+            anyprevout_script_sighash, check_anyprevout_signature, AnyPrevoutSighashType,
+            ANYPREVOUT_KEY_VERSION,
+        },
+        scripts::SignMode,
+        tests::utils::TestContext,
+        types::{input::SpendMode, output::OutputType},
+    };
+
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaves = [check_anyprevout_signature(&public_key, SignMode::Single)];
+
+        let mut protocol = Protocol::new("anyprevout");
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::taproot(20_000, &public_key, &leaves)?,
+            "A",
+            SpendMode::None,
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            10_000,
+            &public_key,
+            &leaves,
+            &SpendMode::None,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.add_transaction_output("B", &OutputType::segwit_key(9_000, &public_key)?)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_anyprevout_signature_rebinds() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_anyprevout_signature_rebinds").unwrap();
+        let protocol = protocol(&tc)?;
+
+        let transaction = protocol.transaction_by_name("B")?;
+        let prevout = protocol.graph().get_prevouts("B")?[0].clone();
+        let leaf = protocol.get_script_from_output("A", 0)?.1[0].clone();
+
+        // Spending a different outpoint with the same amount and script pubkey
+        let mut rebound = transaction.clone();
+        rebound.input[0].previous_output = OutPoint::new(Txid::all_zeros(), 3);
+
+        // Spending an output with the same leaf but a different amount
+        let other_prevout = TxOut {
+            value: Amount::from_sat(15_000),
+            script_pubkey: prevout.script_pubkey.clone(),
+        };
+
+        let sighash = |transaction, prevout, sighash_type| {
+            anyprevout_script_sighash(transaction, 0, prevout, leaf.get_script(), sighash_type)
+                .unwrap()
+        };
+
+        for sighash_type in [
+            AnyPrevoutSighashType::AllAnyPrevout,
+            AnyPrevoutSighashType::AllAnyScript,
+        ] {
+            let expected = protocol.anyprevout_sighash("B", 0, 0, sighash_type)?;
+            assert_eq!(sighash(transaction, &prevout, sighash_type), expected);
+            assert_eq!(sighash(&rebound, &prevout, sighash_type), expected);
+
+            let any_script = sighash_type.is_any_script();
+            assert_eq!(
+                sighash(&rebound, &other_prevout, sighash_type) == expected,
+                any_script
+            );
+        }
+
+        // Only SIGHASH_NONE allows changing the outputs
+        let mut new_outputs = rebound.clone();
+        new_outputs.output[0].value = Amount::from_sat(8_000);
+        for (sighash_type, unchanged) in [
+            (AnyPrevoutSighashType::AllAnyPrevout, false),
+            (AnyPrevoutSighashType::NoneAnyPrevout, true),
+        ] {
+            assert_eq!(
+                sighash(&new_outputs, &prevout, sighash_type)
+                    == sighash(transaction, &prevout, sighash_type),
+                unchanged
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_anyprevout() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_sign_anyprevout").unwrap();
+        let protocol = protocol(&tc)?;
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        // BIP118 keys are the x-only key prefixed with the key version
+        let leaf = protocol.get_script_from_output("A", 0)?.1[0].clone();
+        let script = leaf.get_script().as_bytes();
+        assert_eq!(script.len(), 35);
+        assert_eq!(script[1], ANYPREVOUT_KEY_VERSION);
+
+        let sighash_type = AnyPrevoutSighashType::SingleAnyPrevout;
+        let signature = protocol.sign_anyprevout("B", 0, 0, sighash_type, tc.key_manager())?;
+        let message = protocol.anyprevout_sighash("B", 0, 0, sighash_type)?;
+
+        let secp = secp256k1::Secp256k1::verification_only();
+        assert!(secp
+            .verify_schnorr(
+                &signature.signature,
+                &message,
+                &XOnlyPublicKey::from(public_key)
+            )
+            .is_ok());
+        assert_eq!(signature.to_vec().len(), 65);
+        assert_eq!(signature.to_vec()[64], 0x43);
+
+        // SIGHASH_SINGLE needs an output with the index of the input
+        let mut transaction = protocol.transaction_by_name("B")?.clone();
+        transaction.output.clear();
+        assert!(matches!(
+            anyprevout_script_sighash(
+                &transaction,
+                0,
+                &TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: ScriptBuf::new(),
+                },
+                leaf.get_script(),
+                sighash_type,
+            ),
+            Err(ProtocolBuilderError::MissingSingleOutput(_, 0))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        testing::{arbitrary::ArbitraryProtocol, snapshot::canonical_json, TestContext},
+        types::serialization::SerializationFormat,
+    };
+
+    fn check_invariants(spec: &ArbitraryProtocol) -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_arbitrary_protocol").unwrap();
+        let mut protocol = spec.build(&tc)?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        // Parents are sorted, and so signed, before the transactions spending them
+        let order = protocol.graph().sort()?;
+        assert_eq!(order.len(), spec.transactions);
+        for connection in spec.connections.iter() {
+            let position = |index| {
+                let name = ArbitraryProtocol::transaction_name(index);
+                order.iter().position(|sorted| *sorted == name).unwrap()
+            };
+            assert!(position(connection.from) < position(connection.to));
+        }
+        assert!(protocol.verify_all_signatures()?.is_valid());
+
+        let bytes = protocol.to_bytes(SerializationFormat::Json)?;
+        let restored = Protocol::from_bytes(&bytes)?;
+        assert_eq!(
+            canonical_json(&restored).unwrap(),
+            canonical_json(&protocol).unwrap()
+        );
+        assert!(restored.verify_all_signatures()?.is_valid());
+
+        for (index, name) in spec.transaction_names().iter().enumerate() {
+            let transaction =
+                restored.finalize(name, None, &spec.witness_args(index), tc.key_manager())?;
+            assert_eq!(
+                transaction.compute_txid(),
+                protocol.transaction_by_name(name)?.compute_txid()
+            );
+            assert_eq!(transaction.input.len(), spec.inputs(index).len());
+            assert!(transaction
+                .input
+                .iter()
+                .all(|txin| !txin.witness.is_empty()));
+        }
+
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn test_arbitrary_protocol_invariants(spec in any::<ArbitraryProtocol>()) {
+            check_invariants(&spec).unwrap();
+        }
+    }
+}
@@ -0,0 +1,272 @@
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use std::{
+        cell::Cell,
+        future::Future,
+        pin::Pin,
+        rc::Rc,
+        str::FromStr,
+        task::{Context, Poll},
+    };
+
+    use bitcoin::{
+        hashes::Hash,
+        secp256k1::{ecdsa, schnorr, Message},
+        PublicKey, TapNodeHash, TapSighashType,
+    };
+    use futures::executor::block_on;
+    use key_manager::{
+        key_manager::KeyManager,
+        key_type::BitcoinKeyType,
+        winternitz::{WinternitzSignature, WinternitzType},
+    };
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{checksigadd_multisig, SignMode},
+        tests::utils::{checksig_leaf, TestContext},
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::{Signature, SpendMode},
+            output::OutputType,
+            signer::{AsyncSigner, Signer},
+        },
+    };
+
+    // Returns pending once, as a request waiting on the network would
+    struct Yield(bool);
+
+    impl Future for Yield {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    // Remote signer tracking how many requests are in flight at once
+    struct NetworkSigner {
+        keys: Rc<KeyManager>,
+        in_flight: Cell<usize>,
+        max_in_flight: Cell<usize>,
+    }
+
+    impl NetworkSigner {
+        async fn request<T>(&self, sign: impl FnOnce() -> T) -> T {
+            self.in_flight.set(self.in_flight.get() + 1);
+            self.max_in_flight
+                .set(self.max_in_flight.get().max(self.in_flight.get()));
+            Yield(false).await;
+            self.in_flight.set(self.in_flight.get() - 1);
+            sign()
+        }
+    }
+
+    impl AsyncSigner for NetworkSigner {
+        async fn sign_schnorr(
+            &self,
+            message: &Message,
+            public_key: &PublicKey,
+        ) -> Result<schnorr::Signature, ProtocolBuilderError> {
+            self.request(|| Signer::sign_schnorr(&self.keys, message, public_key))
+                .await
+        }
+
+        async fn sign_schnorr_with_tweak(
+            &self,
+            message: &Message,
+            internal_key: &PublicKey,
+            merkle_root: Option<TapNodeHash>,
+        ) -> Result<(schnorr::Signature, PublicKey), ProtocolBuilderError> {
+            self.request(|| {
+                Signer::sign_schnorr_with_tweak(&self.keys, message, internal_key, merkle_root)
+            })
+            .await
+        }
+
+        async fn sign_ecdsa(
+            &self,
+            message: &Message,
+            public_key: &PublicKey,
+        ) -> Result<ecdsa::Signature, ProtocolBuilderError> {
+            self.request(|| Signer::sign_ecdsa(&self.keys, message, public_key))
+                .await
+        }
+
+        async fn sign_winternitz(
+            &self,
+            message: &[u8],
+            key_type: WinternitzType,
+            derivation_index: u32,
+        ) -> Result<WinternitzSignature, ProtocolBuilderError> {
+            self.request(|| {
+                Signer::sign_winternitz(&self.keys, message, key_type, derivation_index)
+            })
+            .await
+        }
+
+        async fn get_aggregated_signature(
+            &self,
+            aggregated_key: &PublicKey,
+            id: &str,
+            message_id: &str,
+        ) -> Result<schnorr::Signature, ProtocolBuilderError> {
+            self.request(|| {
+                Signer::get_aggregated_signature(&self.keys, aggregated_key, id, message_id)
+            })
+            .await
+        }
+    }
+
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let mut bump = checksig_leaf(&tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 3)?);
+        bump.set_sighash_type(TapSighashType::SinglePlusAnyoneCanPay);
+        let leaves = [
+            checksig_leaf(&tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?),
+            bump,
+        ];
+
+        let mut protocol = Protocol::new("async_signing");
+        let builder = ProtocolBuilder {};
+        builder
+            .add_external_connection(
+                &mut protocol,
+                "EXT",
+                Hash::all_zeros(),
+                OutputSpec::Auto(OutputType::segwit_key(10_000, &funding_key)?),
+                "A",
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            )?
+            .add_taproot_connection(
+                &mut protocol,
+                "A_B",
+                "A",
+                9_000,
+                &internal_key,
+                &leaves,
+                &SpendMode::All {
+                    key_path_sign: SignMode::Single,
+                },
+                "B",
+                &tc.tr_sighash_type(),
+            )?;
+        protocol.add_transaction_output("B", &OutputType::segwit_key(8_000, &funding_key)?)?;
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    fn sighash_types(signatures: &[Option<Signature>]) -> Vec<Option<String>> {
+        signatures
+            .iter()
+            .map(|signature| {
+                signature.as_ref().map(|signature| match signature {
+                    Signature::Taproot(signature) => signature.sighash_type.to_string(),
+                    Signature::Ecdsa(signature) => signature.sighash_type.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sign_async() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_sign_async").unwrap();
+        let mut expected = protocol(&tc)?;
+        expected.sign(tc.key_manager(), "")?;
+
+        let mut protocol = protocol(&tc)?;
+        let signer = NetworkSigner {
+            keys: tc.key_manager().clone(),
+            in_flight: Cell::new(0),
+            max_in_flight: Cell::new(0),
+        };
+        block_on(protocol.sign_async(&signer, ""))?;
+
+        // Same signatures as signing synchronously, all requested at once
+        for transaction_name in ["A", "B"] {
+            assert_eq!(
+                sighash_types(protocol.inputs(transaction_name)?[0].signatures()),
+                sighash_types(expected.inputs(transaction_name)?[0].signatures())
+            );
+        }
+        assert_eq!(signer.max_in_flight.get(), 4);
+        assert!(protocol.verify_all_signatures()?.is_valid());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_input_async() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_sign_input_async").unwrap();
+        let mut protocol = protocol(&tc)?;
+
+        // Any synchronous signer can be awaited
+        let signatures = block_on(protocol.sign_input_async("B", 0, tc.key_manager(), ""))?;
+        assert!(signatures.iter().all(|signature| signature.is_some()));
+        assert_eq!(
+            sighash_types(protocol.inputs("B")?[0].signatures()),
+            sighash_types(&signatures)
+        );
+        assert!(protocol.inputs("A")?[0]
+            .signatures()
+            .iter()
+            .all(|signature| signature.is_none()));
+
+        assert!(matches!(
+            block_on(protocol.sign_input_async("B", 1, tc.key_manager(), "")),
+            Err(ProtocolBuilderError::MissingInput(name, 1)) if name == "B"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_async_multisig() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_sign_async_multisig").unwrap();
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        // Key of another party, not held by the test key manager
+        let other_party_key = PublicKey::from_str(
+            "02c6047f9441ed7d6d3045406e95c07cd85a6a6d4c90d35b8c6a568f07cfd511fd",
+        )
+        .unwrap();
+        let keys = [
+            tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?,
+            other_party_key,
+        ];
+        let leaf = checksigadd_multisig(&keys, 2, SignMode::Single)?;
+
+        let mut protocol = Protocol::new("async_multisig");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::taproot(10_000, &internal_key, &[leaf])?,
+            "A",
+            SpendMode::ScriptsOnly,
+        )?;
+        protocol.build(tc.key_manager(), "")?;
+
+        // The key manager tells which slots it holds, the others are left empty
+        block_on(protocol.sign_async(tc.key_manager(), ""))?;
+        let signatures = protocol.input_multisig_signatures("A", 0, 0)?;
+        assert!(signatures[0].is_some() && signatures[1].is_none());
+
+        // A signer claiming every key fails to sign the slot of the other party
+        let signer = NetworkSigner {
+            keys: tc.key_manager().clone(),
+            in_flight: Cell::new(0),
+            max_in_flight: Cell::new(0),
+        };
+        assert!(matches!(
+            block_on(protocol.sign_async(&signer, "")),
+            Err(ProtocolBuilderError::SignatureError(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use bitcoin::{absolute::LockTime, transaction::Version, OutPoint, Transaction, TxIn, Txid};
+
+    use crate::{
+        broadcast::{BroadcastPolicy, BroadcastQueue, BroadcastStatus, Broadcaster},
+        errors::{BroadcastError, ProtocolBuilderError},
+        tests::utils::TestContext,
+    };
+
+    struct MockBroadcaster {
+        responses: RefCell<Vec<Result<(), BroadcastError>>>,
+        sent: RefCell<Vec<Txid>>,
+    }
+
+    impl MockBroadcaster {
+        fn new(responses: Vec<Result<(), BroadcastError>>) -> Self {
+            Self {
+                responses: RefCell::new(responses),
+                sent: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    impl Broadcaster for MockBroadcaster {
+        fn broadcast(&self, transaction: &Transaction) -> Result<Txid, BroadcastError> {
+            let txid = transaction.compute_txid();
+            self.sent.borrow_mut().push(txid);
+            self.responses.borrow_mut().remove(0).map(|_| txid)
+        }
+    }
+
+    fn transaction(lock_time: u32) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(lock_time),
+            input: vec![],
+            output: vec![],
+        }
+    }
+
+    // Node with package relay, recording each submitted package
+    struct PackageBroadcaster {
+        packages: RefCell<Vec<Vec<Txid>>>,
+    }
+
+    impl Broadcaster for PackageBroadcaster {
+        fn broadcast(&self, _transaction: &Transaction) -> Result<Txid, BroadcastError> {
+            Err(BroadcastError::Rejected(
+                "min relay fee not met".to_string(),
+            ))
+        }
+
+        fn broadcast_package(
+            &self,
+            transactions: &[Transaction],
+        ) -> Result<Vec<Txid>, BroadcastError> {
+            let txids: Vec<Txid> = transactions.iter().map(|tx| tx.compute_txid()).collect();
+            self.packages.borrow_mut().push(txids.clone());
+            Ok(txids)
+        }
+    }
+
+    fn child(parent: &Transaction) -> Transaction {
+        let mut child = transaction(0);
+        child.input.push(TxIn {
+            previous_output: OutPoint::new(parent.compute_txid(), 0),
+            ..TxIn::default()
+        });
+        child
+    }
+
+    fn policy() -> BroadcastPolicy {
+        BroadcastPolicy {
+            max_attempts: 2,
+            initial_backoff: 10,
+            max_backoff: 100,
+            max_per_round: 10,
+        }
+    }
+
+    #[test]
+    fn test_broadcast_deduplicates_by_txid() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_broadcast_deduplicates_by_txid").unwrap();
+        let queue = BroadcastQueue::new("queue", Rc::new(tc.new_storage("queue")), policy());
+
+        let tx = transaction(0);
+        assert!(queue.enqueue(&tx)?);
+        assert!(!queue.enqueue(&tx)?);
+        assert_eq!(queue.entries()?.len(), 1);
+
+        let broadcaster = MockBroadcaster::new(vec![Ok(())]);
+        assert_eq!(queue.process_at(&broadcaster, 0)?, 1);
+
+        let status = queue.status(&tx.compute_txid())?.unwrap();
+        assert_eq!(status.status, BroadcastStatus::Broadcasted);
+        assert!(queue.pending()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_retries_with_backoff() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_broadcast_retries_with_backoff").unwrap();
+        let queue = BroadcastQueue::new("queue", Rc::new(tc.new_storage("queue")), policy());
+
+        let tx = transaction(0);
+        let txid = tx.compute_txid();
+        queue.enqueue(&tx)?;
+
+        let broadcaster = MockBroadcaster::new(vec![
+            Err(BroadcastError::Rejected("non-BIP68-final".to_string())),
+            Err(BroadcastError::Rejected(
+                "bad-txns-inputs-missingorspent".to_string(),
+            )),
+            Ok(()),
+        ]);
+
+        queue.process_at(&broadcaster, 0)?;
+        let status = queue.status(&txid)?.unwrap();
+        assert_eq!(status.attempts, 1);
+        assert_eq!(status.next_attempt, 10);
+
+        // Still backing off
+        queue.process_at(&broadcaster, 5)?;
+        assert_eq!(broadcaster.sent.borrow().len(), 1);
+
+        queue.process_at(&broadcaster, 10)?;
+        let status = queue.status(&txid)?.unwrap();
+        assert_eq!(status.status, BroadcastStatus::Failed);
+        assert_eq!(
+            status.rejections,
+            vec!["non-BIP68-final", "bad-txns-inputs-missingorspent"]
+        );
+
+        queue.retry(&txid)?;
+        assert_eq!(queue.process_at(&broadcaster, 10)?, 1);
+        assert_eq!(
+            queue.status(&txid)?.unwrap().status,
+            BroadcastStatus::Broadcasted
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_node_unavailable() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_broadcast_node_unavailable").unwrap();
+        let storage = Rc::new(tc.new_storage("queue"));
+        let queue = BroadcastQueue::new("queue", storage.clone(), policy());
+
+        queue.enqueue(&transaction(0))?;
+        queue.enqueue(&transaction(1))?;
+
+        let broadcaster = MockBroadcaster::new(vec![Err(BroadcastError::Unavailable(
+            "connection refused".to_string(),
+        ))]);
+
+        // The round stops at the first unavailable error without counting the attempt
+        assert_eq!(queue.process_at(&broadcaster, 0)?, 0);
+        assert_eq!(broadcaster.sent.borrow().len(), 1);
+
+        let queue = BroadcastQueue::new("queue", storage, policy());
+        let pending = queue.pending()?;
+        assert_eq!(pending.len(), 2);
+        assert!(pending.iter().all(|entry| entry.attempts == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_rate_limit() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_broadcast_rate_limit").unwrap();
+        let policy = BroadcastPolicy {
+            max_per_round: 1,
+            ..policy()
+        };
+        let queue = BroadcastQueue::new("queue", Rc::new(tc.new_storage("queue")), policy);
+
+        queue.enqueue(&transaction(0))?;
+        queue.enqueue(&transaction(1))?;
+
+        let broadcaster = MockBroadcaster::new(vec![Ok(()), Ok(())]);
+        assert_eq!(queue.process_at(&broadcaster, 0)?, 1);
+        assert_eq!(queue.pending()?.len(), 1);
+        assert_eq!(queue.process_at(&broadcaster, 0)?, 1);
+        assert!(queue.pending()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_package() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_broadcast_package").unwrap();
+        let queue = BroadcastQueue::new("queue", Rc::new(tc.new_storage("queue")), policy());
+
+        let parent = transaction(1);
+        let child = child(&parent);
+        let package = vec![parent.compute_txid(), child.compute_txid()];
+
+        assert!(queue.enqueue_package(&[parent.clone(), child.clone()])?);
+        assert!(!queue.enqueue_package(&[parent.clone(), child.clone()])?);
+        assert!(matches!(
+            queue.enqueue_package(&[transaction(2), child.clone()]),
+            Err(ProtocolBuilderError::UnrelatedPackageTransaction(_))
+        ));
+        assert!(matches!(
+            queue.enqueue_package(&[]),
+            Err(ProtocolBuilderError::EmptyBroadcastPackage)
+        ));
+        queue.enqueue(&transaction(3))?;
+
+        let broadcaster = PackageBroadcaster {
+            packages: RefCell::new(vec![]),
+        };
+        assert_eq!(queue.process_at(&broadcaster, 0)?, 2);
+        assert_eq!(*broadcaster.packages.borrow(), vec![package.clone()]);
+        for txid in &package {
+            let status = queue.status(txid)?.unwrap();
+            assert_eq!(status.status, BroadcastStatus::Broadcasted);
+            assert_eq!(status.package, package);
+        }
+
+        // The standalone transaction is still broadcast on its own
+        assert_eq!(queue.pending()?.len(), 1);
+        assert_eq!(queue.pending()?[0].attempts, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_package_sequential_fallback() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_broadcast_package_sequential_fallback").unwrap();
+        let queue = BroadcastQueue::new("queue", Rc::new(tc.new_storage("queue")), policy());
+
+        let parent = transaction(1);
+        let child = child(&parent);
+        queue.enqueue_package(&[parent.clone(), child.clone()])?;
+
+        let broadcaster = MockBroadcaster::new(vec![
+            Err(BroadcastError::Rejected(
+                "min relay fee not met".to_string(),
+            )),
+            Ok(()),
+            Ok(()),
+        ]);
+
+        // A rejected parent fails the whole package
+        assert_eq!(queue.process_at(&broadcaster, 0)?, 0);
+        assert_eq!(*broadcaster.sent.borrow(), vec![parent.compute_txid()]);
+        assert_eq!(queue.status(&child.compute_txid())?.unwrap().attempts, 1);
+
+        assert_eq!(queue.process_at(&broadcaster, 10)?, 2);
+        assert_eq!(
+            *broadcaster.sent.borrow(),
+            vec![
+                parent.compute_txid(),
+                parent.compute_txid(),
+                child.compute_txid()
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unreachable_node_is_unavailable() {
+        // Nothing listens on port 1, the connection is refused
+        let client = bitcoincore_rpc::Client::new(
+            "http://127.0.0.1:1",
+            bitcoincore_rpc::Auth::UserPass("user".to_string(), "password".to_string()),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            client.broadcast(&transaction(0)),
+            Err(BroadcastError::Unavailable(_))
+        ));
+        assert!(matches!(
+            client.broadcast_package(&[transaction(0), transaction(1)]),
+            Err(BroadcastError::Unavailable(_))
+        ));
+    }
+}
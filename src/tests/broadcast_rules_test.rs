@@ -0,0 +1,205 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::{HashMap, HashSet},
+        rc::Rc,
+    };
+
+    use bitcoin::{hashes::Hash, ScriptBuf, Txid};
+
+    use crate::{
+        broadcast::{
+            enqueue_triggered, triggered_transactions, BroadcastPolicy, BroadcastQueue, ChainState,
+            ChainTime,
+        },
+        builder::Protocol,
+        errors::{BroadcastError, GraphError, ProtocolBuilderError},
+        tests::utils::TestContext,
+        types::{
+            broadcast_rule::BroadcastRule,
+            connection::{InputSpec, OutputSpec},
+            external::ExternalTx,
+            input::SpendMode,
+            InputArgs, OutputType,
+        },
+    };
+
+    #[derive(Default)]
+    struct MockChain {
+        height: RefCell<u32>,
+        confirmations: RefCell<HashMap<Txid, u32>>,
+    }
+
+    impl ChainTime for MockChain {
+        fn current_height(&self) -> Result<u32, BroadcastError> {
+            Ok(*self.height.borrow())
+        }
+
+        fn median_time(&self) -> Result<u32, BroadcastError> {
+            Ok(0)
+        }
+    }
+
+    impl ChainState for MockChain {
+        fn confirmations(&self, txid: &Txid) -> Result<Option<u32>, BroadcastError> {
+            Ok(self.confirmations.borrow().get(txid).copied())
+        }
+    }
+
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("rules");
+        protocol.add_external_tx(
+            ExternalTx::new("start", Txid::from_byte_array([1; 32]))
+                .with_known_output(OutputType::segwit_unspendable(ScriptBuf::from(vec![0x51]))?)
+                .with_known_output(OutputType::segwit_unspendable(ScriptBuf::from(vec![0x52]))?),
+        )?;
+
+        for (index, name) in ["challenge", "timeout"].iter().enumerate() {
+            protocol.add_connection(
+                name,
+                "start",
+                OutputSpec::Index(index),
+                name,
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+                None,
+                None,
+            )?;
+        }
+
+        protocol.add_broadcast_rule(
+            BroadcastRule::new("timeout")
+                .after_confirmations("start", 144)
+                .unless_observed("challenge"),
+        )?;
+
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    fn txid(protocol: &Protocol, name: &str) -> Txid {
+        match protocol.external_transaction(name) {
+            Some(external) => external.txid(),
+            None => protocol.transaction_by_name(name).unwrap().compute_txid(),
+        }
+    }
+
+    #[test]
+    fn test_rule_triggers_after_confirmations() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rule_triggers_after_confirmations").unwrap();
+        let protocol = protocol(&tc)?;
+        let chain = MockChain::default();
+
+        assert!(triggered_transactions(&protocol, &chain)?.is_empty());
+
+        chain
+            .confirmations
+            .borrow_mut()
+            .insert(txid(&protocol, "start"), 143);
+        assert!(triggered_transactions(&protocol, &chain)?.is_empty());
+
+        chain
+            .confirmations
+            .borrow_mut()
+            .insert(txid(&protocol, "start"), 144);
+        assert_eq!(triggered_transactions(&protocol, &chain)?, vec!["timeout"]);
+
+        // Once the timeout is seen by the chain the rule stops triggering.
+        chain
+            .confirmations
+            .borrow_mut()
+            .insert(txid(&protocol, "timeout"), 0);
+        assert!(triggered_transactions(&protocol, &chain)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_suppressed_when_challenge_observed() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rule_suppressed_when_challenge_observed").unwrap();
+        let protocol = protocol(&tc)?;
+        let chain = MockChain::default();
+
+        chain
+            .confirmations
+            .borrow_mut()
+            .insert(txid(&protocol, "start"), 200);
+        chain
+            .confirmations
+            .borrow_mut()
+            .insert(txid(&protocol, "challenge"), 0);
+
+        assert!(triggered_transactions(&protocol, &chain)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_at_height_and_enqueue() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rule_at_height_and_enqueue").unwrap();
+        let mut protocol = protocol(&tc)?;
+        protocol.add_broadcast_rule(BroadcastRule::new("challenge").at_height(1000))?;
+
+        let chain = MockChain::default();
+        *chain.height.borrow_mut() = 1000;
+
+        let queue = BroadcastQueue::new(
+            "rules",
+            Rc::new(tc.new_storage("queue")),
+            BroadcastPolicy::default(),
+        );
+
+        let args = HashMap::from([("challenge".to_string(), vec![InputArgs::new_segwit_args()])]);
+
+        assert_eq!(
+            enqueue_triggered(&queue, &protocol, &chain, &args)?,
+            vec!["challenge"]
+        );
+        assert!(enqueue_triggered(&queue, &protocol, &chain, &args)?.is_empty());
+
+        let queued: HashSet<Txid> = queue.pending()?.iter().map(|entry| entry.txid).collect();
+        assert_eq!(queued, HashSet::from([txid(&protocol, "challenge")]));
+
+        let result = enqueue_triggered(&queue, &protocol, &chain, &HashMap::new());
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::MissingBroadcastArgs(name)) if name == "challenge"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_with_unknown_transaction() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rule_with_unknown_transaction").unwrap();
+        let mut protocol = protocol(&tc)?;
+
+        let result =
+            protocol.add_broadcast_rule(BroadcastRule::new("timeout").unless_observed("missing"));
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::MissingTransaction(name)
+            )) if name == "missing"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rules_are_prefixed_on_merge() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rules_are_prefixed_on_merge").unwrap();
+        let mut host = Protocol::new("host");
+        host.merge(protocol(&tc)?, "dispute")?;
+
+        let rule = host.broadcast_rules().get("dispute_timeout").unwrap();
+        assert_eq!(
+            rule,
+            &BroadcastRule::new("dispute_timeout")
+                .after_confirmations("dispute_start", 144)
+                .unless_observed("dispute_challenge")
+        );
+
+        Ok(())
+    }
+}
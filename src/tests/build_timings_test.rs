@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        scripts::{self, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    #[test]
+    fn test_build_timings() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_build_timings").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let taproot_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaves = [scripts::check_signature(&taproot_key, SignMode::Single)];
+
+        let mut protocol = Protocol::new("build_timings");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(20_000, &public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        protocol.add_connection(
+            "A_B",
+            "A",
+            OutputSpec::Auto(OutputType::taproot(10_000, &taproot_key, &leaves)?),
+            "B",
+            InputSpec::Auto(tc.tr_sighash_type(), SpendMode::Script { leaf: 0 }),
+            None,
+            None,
+        )?;
+        assert!(protocol.last_build_timings().is_none());
+
+        protocol.build(tc.key_manager(), "")?;
+        let timings = *protocol.last_build_timings().unwrap();
+        // External transactions are not built
+        assert_eq!(timings.transactions, 2);
+        assert_eq!(timings.signatures, Duration::ZERO);
+        assert!(timings.total >= timings.txids + timings.sighashes);
+
+        protocol.sign(tc.key_manager(), "")?;
+        let timings = *protocol.last_build_timings().unwrap();
+        assert_eq!(timings.txids, Duration::ZERO);
+        assert!(timings.signatures > Duration::ZERO);
+
+        // Nothing changed since the last build
+        protocol.rebuild(tc.key_manager(), "")?;
+        assert_eq!(protocol.last_build_timings().unwrap().transactions, 0);
+
+        Ok(())
+    }
+}
@@ -1,27 +1,31 @@
 #[cfg(test)]
 mod tests {
     use bitcoin::{
-        hashes::Hash,
+        absolute::LockTime,
+        hashes::{sha256, Hash},
         key::rand,
+        opcodes::all::{OP_CHECKSIG, OP_DROP},
+        script::Builder,
         secp256k1::{Message, Secp256k1},
-        ScriptBuf,
+        transaction::Version,
+        PublicKey, ScriptBuf, Sequence, XOnlyPublicKey,
     };
+    use key_manager::key_type::BitcoinKeyType;
 
     use crate::{
-        builder::{Protocol, ProtocolBuilder},
+        builder::{Protocol, ProtocolBuilder, ValidationIssue},
         errors::{GraphError, ProtocolBuilderError},
-        graph::graph::GraphOptions,
-        scripts::{ProtocolScript, SignMode},
+        graph::{export::GraphExport, graph::GraphOptions},
+        scripts::{self, ProtocolScript, SignMode, HTLC_CLAIM_LEAF, HTLC_REFUND_LEAF},
         tests::utils::TestContext,
         types::{
             connection::{InputSpec, OutputSpec},
             input::{InputArgs, Signature, SpendMode},
             output::OutputType,
+            sequence::SequencePolicy,
         },
     };
 
-    use key_manager::key_type::BitcoinKeyType;
-
     #[test]
     fn test_single_connection() -> Result<(), ProtocolBuilderError> {
         let tc = TestContext::new("test_single_connection").unwrap();
@@ -1077,6 +1081,162 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_timelock_connection_expire_transaction() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_timelock_connection_expire_transaction").unwrap();
+        let internal_key = tc
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2tr, 0)
+            .unwrap();
+
+        let value = 1000;
+        let blocks = 144;
+        let expired_script =
+            ProtocolScript::new(ScriptBuf::from(vec![0x01]), &internal_key, SignMode::Single);
+        let renew_script =
+            ProtocolScript::new(ScriptBuf::from(vec![0x02]), &internal_key, SignMode::Single);
+
+        let mut protocol = Protocol::new("timelock_expire_test");
+        let builder = ProtocolBuilder {};
+
+        builder.add_timelock_connection_with_expire(
+            &mut protocol,
+            "challenge",
+            "A",
+            value,
+            &internal_key,
+            &expired_script,
+            &renew_script,
+            &SpendMode::Script { leaf: 1 },
+            "B",
+            blocks,
+            &tc.tr_sighash_type(),
+            "A_expired",
+        )?;
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let tx_b = protocol.transaction_by_name("B")?;
+        let expire = protocol.transaction_by_name("A_expired")?;
+
+        // Both transactions spend the same timelock output
+        assert_eq!(expire.input.len(), 1);
+        assert_eq!(
+            expire.input[0].previous_output,
+            tx_b.input[0].previous_output
+        );
+        assert_eq!(
+            expire.input[0].sequence,
+            bitcoin::Sequence::from_height(blocks)
+        );
+        assert_eq!(protocol.next_transactions("A")?.len(), 2);
+        let mut connection_names: Vec<String> = protocol
+            .graph()
+            .stored_connections()
+            .into_iter()
+            .map(|stored| stored.connection.name)
+            .collect();
+        connection_names.sort();
+        assert_eq!(connection_names, vec!["challenge", "challenge_expire"]);
+
+        // Only the expired leaf is signed for the expire transaction
+        assert!(protocol
+            .input_taproot_script_spend_signature("A_expired", 0, 0)?
+            .is_some());
+        assert!(protocol
+            .input_taproot_script_spend_signature("A_expired", 0, 1)?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cltv_connection_locktime() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_cltv_connection_locktime").unwrap();
+        let internal_key = tc
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2tr, 0)
+            .unwrap();
+
+        let height = 850_000;
+        let cltv_script = scripts::timelock_absolute(height, &internal_key, SignMode::Single);
+
+        let mut protocol = Protocol::new("cltv_locktime_test");
+        let builder = ProtocolBuilder {};
+
+        builder.add_cltv_connection(
+            &mut protocol,
+            "cltv",
+            "A",
+            1000,
+            &internal_key,
+            &[cltv_script],
+            &SpendMode::ScriptsOnly,
+            "B",
+            LockTime::from_height(height).unwrap(),
+            &tc.tr_sighash_type(),
+        )?;
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let tx_a = protocol.transaction_by_name("A")?;
+        let tx_b = protocol.transaction_by_name("B")?;
+
+        assert_eq!(tx_a.lock_time, LockTime::ZERO);
+        assert_eq!(tx_b.lock_time, LockTime::from_height(height).unwrap());
+        // The locktime is only enforced when the input sequence is not final
+        assert!(tx_b.input[0].sequence.enables_absolute_lock_time());
+        assert!(protocol
+            .input_taproot_script_spend_signature("B", 0, 0)?
+            .is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cltv_connection_with_final_sequence_policy() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_cltv_connection_with_final_sequence_policy").unwrap();
+        let internal_key = tc
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2tr, 0)
+            .unwrap();
+
+        let height = 850_000;
+        let cltv_script = scripts::timelock_absolute(height, &internal_key, SignMode::Single);
+
+        let mut protocol = Protocol::new("cltv_final_policy_test");
+        protocol.add_transaction("B")?;
+        protocol.set_sequence_policy("B", SequencePolicy::Final)?;
+
+        // The input is added with a final sequence, set_locktime must make it non-final
+        ProtocolBuilder {}.add_cltv_connection(
+            &mut protocol,
+            "cltv",
+            "A",
+            1000,
+            &internal_key,
+            &[cltv_script],
+            &SpendMode::ScriptsOnly,
+            "B",
+            LockTime::from_height(height).unwrap(),
+            &tc.tr_sighash_type(),
+        )?;
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let tx_b = protocol.transaction_by_name("B")?;
+        assert_eq!(
+            tx_b.input[0].sequence,
+            bitcoin::Sequence::ENABLE_LOCKTIME_NO_RBF
+        );
+        assert!(tx_b.input[0].sequence.enables_absolute_lock_time());
+        assert!(protocol
+            .input_taproot_script_spend_signature("B", 0, 0)?
+            .is_some());
+
+        Ok(())
+    }
+
     #[test]
     fn test_add_transaction_with_empty_name() {
         let mut protocol = Protocol::new("empty_name_test");
@@ -1343,6 +1503,140 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_visualize_mermaid_and_json() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_visualize_mermaid_and_json").unwrap();
+        let internal_key = tc
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2wpkh, 0)
+            .unwrap();
+
+        let value = 1000;
+        let script =
+            ProtocolScript::new(ScriptBuf::from(vec![0x04]), &internal_key, SignMode::Single);
+
+        let mut protocol = Protocol::new("export_test");
+        let builder = ProtocolBuilder {};
+
+        // ext -> A -> B, and A -> C spending the same output
+        builder.add_external_connection(
+            &mut protocol,
+            "ext",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::segwit_script(value, &script)?),
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+        )?;
+        builder.add_p2wsh_output(&mut protocol, "A", value, &script)?;
+        for (name, to) in [("ab", "B"), ("ac", "C")] {
+            protocol.add_connection(
+                name,
+                "A",
+                OutputSpec::Index(0),
+                to,
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+                None,
+                None,
+            )?;
+        }
+        protocol.build(tc.key_manager(), "")?;
+
+        let mermaid = protocol.visualize(GraphOptions::Mermaid)?;
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains("    n1[\"A<br/>"));
+        assert!(mermaid.contains("    n1 -.->|\"ab (0:0)\"| n2"));
+        assert!(mermaid.contains("    n1 -.->|\"ac (0:0)\"| n3"));
+        assert!(mermaid.contains("    n0 -->|\"external (0:0)\"| n1"));
+
+        let json = protocol.visualize(GraphOptions::Json)?;
+        let export: GraphExport = serde_json::from_str(&json).unwrap();
+        assert_eq!(export.nodes.len(), 4);
+        assert_eq!(
+            export.nodes[1].txid,
+            protocol
+                .transaction_by_name("A")?
+                .compute_txid()
+                .to_string()
+        );
+        assert_eq!(export.nodes[1].outputs[0].value, value);
+        assert_eq!(export.nodes[1].outputs[0].leaves.len(), 1);
+        assert_eq!(export.nodes[1].outputs[0].leaves[0].script_size, 1);
+        assert!(export.nodes[0].external);
+
+        assert_eq!(export.edges.len(), 3);
+        assert_eq!(export.edges[1].name, "ab");
+        assert!(export.edges[1].alternative);
+        assert!(!export.edges[0].alternative);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_visualize_detailed() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_visualize_detailed").unwrap();
+        let internal_key = tc
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2tr, 0)
+            .unwrap();
+        let funding_key = tc
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2wpkh, 1)
+            .unwrap();
+
+        let leaves = [0x51, 0x52].map(|push| {
+            let script = Builder::new()
+                .push_opcode(push.into())
+                .push_opcode(OP_DROP)
+                .push_x_only_key(&XOnlyPublicKey::from(internal_key))
+                .push_opcode(OP_CHECKSIG)
+                .into_script();
+            ProtocolScript::new(script, &internal_key, SignMode::Single)
+        });
+
+        let mut protocol = Protocol::new("detailed_test");
+        let builder = ProtocolBuilder {};
+        builder.add_external_connection(
+            &mut protocol,
+            "ext",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::segwit_key(10_000, &funding_key)?),
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "ab",
+            "A",
+            8_000,
+            &internal_key,
+            &leaves,
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+
+        protocol.build(tc.key_manager(), "")?;
+        let dot = protocol.visualize(GraphOptions::Detailed)?;
+        assert!(dot.contains("<i0> in0 [8000]\\lTaproot(All) ScriptsOnly\\lunsigned: L0 L1\\l"));
+        assert!(dot
+            .contains("<o0> out0 [8000] TaprootScript\\lL0: OP_PUSHNUM_1 OP_DROP OP_PUSHBYTES_32"));
+        assert!(dot.contains("style=filled fillcolor=lightgrey"));
+        assert!(dot.contains("style=filled fillcolor=lightblue"));
+
+        protocol.sign(tc.key_manager(), "")?;
+        let dot = protocol.visualize(GraphOptions::Detailed)?;
+        assert!(dot.contains("<i0> in0 [8000]\\lTaproot(All) ScriptsOnly\\lsigned: L0 L1\\l"));
+        assert!(dot.contains("<i0> in0 [10000]\\lEcdsa(All) Segwit\\lsigned: sig\\l"));
+
+        // The default view is unchanged
+        let dot = protocol.visualize(GraphOptions::Default)?;
+        assert!(dot.contains("<i0> in0"));
+        assert!(!dot.contains("signed"));
+        assert!(!dot.contains("fillcolor"));
+
+        Ok(())
+    }
+
     #[test]
     fn test_visualize_empty_protocol() -> Result<(), ProtocolBuilderError> {
         let protocol = Protocol::new("empty_test");
@@ -1509,4 +1803,531 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_labeled_connections() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_labeled_connections").unwrap();
+        let internal_key = tc
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2tr, 0)
+            .unwrap();
+
+        let challenge =
+            ProtocolScript::new(ScriptBuf::from(vec![0x01]), &internal_key, SignMode::Single);
+        let timeout =
+            ProtocolScript::new(ScriptBuf::from(vec![0x02]), &internal_key, SignMode::Single);
+
+        let mut protocol = Protocol::new("labeled_connections");
+        protocol
+            .add_connection(
+                "challenge",
+                "A",
+                OutputSpec::Labeled(
+                    "challenge_out".to_string(),
+                    OutputType::taproot(1000, &internal_key, std::slice::from_ref(&challenge))?,
+                ),
+                "B",
+                InputSpec::Labeled(
+                    "challenge_in".to_string(),
+                    tc.tr_sighash_type(),
+                    SpendMode::ScriptsOnly,
+                ),
+                None,
+                None,
+            )?
+            .add_connection(
+                "timeout",
+                "A",
+                OutputSpec::Labeled(
+                    "timeout_out".to_string(),
+                    OutputType::taproot(1000, &internal_key, std::slice::from_ref(&timeout))?,
+                ),
+                "B",
+                InputSpec::Auto(tc.tr_sighash_type(), SpendMode::ScriptsOnly),
+                None,
+                None,
+            )?
+            .add_connection(
+                "timeout",
+                "A",
+                "timeout_out".into(),
+                "C",
+                InputSpec::Labeled(
+                    "timeout_in".to_string(),
+                    tc.tr_sighash_type(),
+                    SpendMode::ScriptsOnly,
+                ),
+                None,
+                None,
+            )?;
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        assert_eq!(protocol.output_index("A", "challenge_out")?, 0);
+        assert_eq!(protocol.output_index("A", "timeout_out")?, 1);
+        assert_eq!(protocol.input_index("B", "challenge_in")?, 0);
+        assert_eq!(protocol.input_index("C", "timeout_in")?, 0);
+
+        let c = protocol.transaction_by_name("C")?;
+        assert_eq!(c.input[0].previous_output.vout, 1);
+
+        let (_, leaves) = protocol.get_script_from_labeled_output("A", "challenge_out")?;
+        assert_eq!(leaves[0].get_script(), challenge.get_script());
+        assert_eq!(
+            protocol
+                .get_script_to_spend_labeled("C", "timeout_in", 0)?
+                .get_script(),
+            timeout.get_script()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_and_missing_labels() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_duplicate_and_missing_labels").unwrap();
+        let internal_key = tc
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2tr, 0)
+            .unwrap();
+
+        let script =
+            ProtocolScript::new(ScriptBuf::from(vec![0x01]), &internal_key, SignMode::Single);
+        let output_type = OutputType::taproot(1000, &internal_key, &[script])?;
+
+        let mut protocol = Protocol::new("labels");
+        protocol.add_connection(
+            "first",
+            "A",
+            OutputSpec::Labeled("out".to_string(), output_type.clone()),
+            "B",
+            InputSpec::Auto(tc.tr_sighash_type(), SpendMode::ScriptsOnly),
+            None,
+            None,
+        )?;
+
+        let result = protocol.add_connection(
+            "second",
+            "A",
+            OutputSpec::Labeled("out".to_string(), output_type),
+            "B",
+            InputSpec::Auto(tc.tr_sighash_type(), SpendMode::ScriptsOnly),
+            None,
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::DuplicateOutputLabel(_, _)
+            ))
+        ));
+        assert_eq!(protocol.transaction_by_name("A")?.output.len(), 1);
+
+        assert!(matches!(
+            protocol.output_index("A", "missing"),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::MissingOutputLabel(_, _)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rounds_with_per_round_leaves() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rounds_with_per_round_leaves").unwrap();
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let round_keys = (1..=3)
+            .map(|index| tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, index))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let leaves = |round: u32| {
+            let key = &round_keys[round as usize];
+            (
+                vec![ProtocolScript::new(
+                    ScriptBuf::from(vec![0x04]),
+                    key,
+                    SignMode::Single,
+                )],
+                vec![ProtocolScript::new(
+                    ScriptBuf::from(vec![0x05]),
+                    key,
+                    SignMode::Single,
+                )],
+            )
+        };
+
+        let mut protocol = Protocol::new("rounds_with_leaves");
+        let builder = ProtocolBuilder {};
+        let (first, last) = builder.connect_taproot_rounds_with(
+            &mut protocol,
+            "rounds",
+            3,
+            "B",
+            "C",
+            1000,
+            &internal_key,
+            leaves,
+            &SpendMode::ScriptsOnly,
+            &tc.tr_sighash_type(),
+        )?;
+        assert_eq!((first.as_str(), last.as_str()), ("B_0", "C_2"));
+
+        for round in 0..3 {
+            let (leaves_from, leaves_to) = leaves(round);
+            let from_output = OutputType::taproot(1000, &internal_key, &leaves_from)?;
+            assert_eq!(
+                &protocol
+                    .transaction_by_name(&format!("B_{}", round))?
+                    .output[0]
+                    .script_pubkey,
+                from_output.get_script_pubkey()
+            );
+
+            let to_outputs = &protocol
+                .transaction_by_name(&format!("C_{}", round))?
+                .output;
+            if round < 2 {
+                let to_output = OutputType::taproot(1000, &internal_key, &leaves_to)?;
+                assert_eq!(&to_outputs[0].script_pubkey, to_output.get_script_pubkey());
+            } else {
+                assert!(to_outputs.is_empty());
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_htlc_leaves() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_htlc_leaves").unwrap();
+        let recipient_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let refund_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let hash = sha256::Hash::hash(&[0x42; 32]);
+
+        let (claim, refund) =
+            scripts::htlc(hash, &recipient_key, &refund_key, 144, SignMode::Single)?;
+
+        assert_eq!(claim.get_name(), Some(HTLC_CLAIM_LEAF));
+        assert_eq!(claim.get_verifying_key(), Some(recipient_key));
+        assert_eq!(refund.get_name(), Some(HTLC_REFUND_LEAF));
+        assert_eq!(refund.get_verifying_key(), Some(refund_key));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_htlc_connection() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_htlc_connection").unwrap();
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let recipient_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+        let refund_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 3)?;
+        let preimage = [0x42; 32];
+
+        let mut protocol = Protocol::new("htlc");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &funding_key)?,
+            "LOCK",
+            SpendMode::Segwit,
+        )?;
+        ProtocolBuilder {}.add_htlc_connection(
+            &mut protocol,
+            "pegout",
+            "LOCK",
+            8_000,
+            &internal_key,
+            sha256::Hash::hash(&preimage),
+            &recipient_key,
+            &refund_key,
+            144,
+            SignMode::Single,
+            "CLAIM",
+            &tc.tr_sighash_type(),
+            Some("REFUND"),
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        assert_eq!(
+            protocol.leaf_map("LOCK", 0)?.keys().collect::<Vec<_>>(),
+            vec![HTLC_CLAIM_LEAF, HTLC_REFUND_LEAF]
+        );
+
+        // Each transaction signs its own leaf, the refund waits for the timeout
+        assert!(protocol
+            .input_taproot_script_spend_signature("CLAIM", 0, 1)?
+            .is_none());
+        assert!(protocol
+            .input_taproot_script_spend_signature("REFUND", 0, 0)?
+            .is_none());
+        let refund = protocol.transaction_by_name("REFUND")?;
+        assert_eq!(refund.input[0].sequence, Sequence::from_height(144));
+
+        // The preimage goes on top of the recipient signature
+        let mut args = InputArgs::new_taproot_script_args_by_name(HTLC_CLAIM_LEAF);
+        args.push_taproot_signature(
+            protocol
+                .input_taproot_script_spend_signature("CLAIM", 0, 0)?
+                .unwrap(),
+        )?;
+        args.push_slice(&preimage);
+        let claim = protocol.transaction_to_send("CLAIM", &[args])?;
+        assert_eq!(claim.input[0].witness.len(), 4);
+        assert_eq!(claim.input[0].witness.nth(1).unwrap(), preimage);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_connect_transactions_with_handles() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_connect_transactions_with_handles").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let output = OutputType::segwit_key(10_000, &public_key)?;
+
+        let mut protocol = Protocol::new("handles");
+        let funding = protocol.new_external_transaction("funding")?;
+        let start = protocol.new_transaction("start")?;
+        let end = protocol.new_transaction("end")?;
+        assert_eq!(start.name(), "start");
+
+        protocol.add_connection(
+            "funding_start",
+            &funding,
+            OutputSpec::Auto(output.clone()),
+            &start,
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            Some(Hash::all_zeros()),
+        )?;
+        protocol.add_transaction_output(start.name(), &output)?;
+        protocol.add_transaction_output(start.name(), &output)?;
+
+        protocol.connect_output(
+            "start_end",
+            &start.output(1),
+            &end,
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+        )?;
+        let end_tx = protocol.transaction_by_name("end")?;
+        assert_eq!(end_tx.input[0].previous_output.vout, 1);
+
+        // Handles and names can be mixed
+        protocol.add_connection(
+            "start_end_2",
+            "start",
+            OutputSpec::Index(0),
+            &end,
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+        assert_eq!(protocol.transaction_by_name("end")?.input.len(), 2);
+
+        assert!(matches!(
+            protocol.connect_output(
+                "start_end_3",
+                &start.output(5),
+                &end,
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            ),
+            Err(ProtocolBuilderError::MissingOutput(_, 5))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_of_existing_transaction() -> Result<(), ProtocolBuilderError> {
+        let mut protocol = Protocol::new("handles");
+        protocol.add_transaction("start")?;
+
+        let start = protocol.handle("start")?;
+        assert_eq!(start, protocol.handle("start")?);
+        assert_eq!(start.output(2).to_string(), "start:2");
+
+        assert!(matches!(
+            protocol.handle("strat"),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::MissingTransaction(_)
+            ))
+        ));
+
+        let output = OutputType::ExternalUnknown {
+            script_pubkey: ScriptBuf::default(),
+        };
+        protocol.add_transaction_output(start.name(), &output)?;
+        assert_eq!(protocol.get_output_count("start")?, 1);
+
+        Ok(())
+    }
+
+    fn connect(
+        tc: &TestContext,
+        protocol: &mut Protocol,
+        from: &str,
+        to: &str,
+        timelock: Option<u16>,
+        public_key: &PublicKey,
+    ) -> Result<(), ProtocolBuilderError> {
+        protocol.add_connection(
+            &format!("{}_{}", from, to),
+            from,
+            OutputSpec::Auto(OutputType::segwit_key(1_000, public_key)?),
+            to,
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            timelock,
+            None,
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_version() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_transaction_version").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = Protocol::new("transaction_version");
+        connect(&tc, &mut protocol, "A", "B", Some(10), &public_key)?;
+
+        assert_eq!(protocol.transaction_version("B")?, Version::TWO);
+        assert!(matches!(
+            protocol.set_transaction_version("B", Version::non_standard(4)),
+            Err(ProtocolBuilderError::UnsupportedTransactionVersion(name, 4)) if name == "B"
+        ));
+
+        // Version 1 transactions ignore relative timelocks
+        protocol.set_transaction_version("B", Version::ONE)?;
+        assert_eq!(
+            protocol.validate()?.issues("B"),
+            &[ValidationIssue::RelativeTimelockIgnored { input_index: 0 }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truc_topology() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_truc_topology").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = Protocol::new("truc_topology");
+        connect(&tc, &mut protocol, "A", "B", None, &public_key)?;
+        connect(&tc, &mut protocol, "B", "C", None, &public_key)?;
+        connect(&tc, &mut protocol, "B", "D", Some(10), &public_key)?;
+        let truc = Version::non_standard(3);
+        protocol.set_transaction_version("B", truc)?;
+
+        // B spends and is spent by unconfirmed version 2 transactions
+        let report = protocol.validate()?;
+        assert_eq!(
+            report.issues("B"),
+            &[ValidationIssue::TrucVersionMismatch {
+                parent: "A".to_string()
+            }]
+        );
+        assert_eq!(
+            report.issues("C"),
+            &[ValidationIssue::TrucVersionMismatch {
+                parent: "B".to_string()
+            }]
+        );
+        // D can only be broadcast once B confirms
+        assert!(report.issues("D").is_empty());
+
+        protocol.set_transaction_version("A", truc)?;
+        protocol.set_transaction_version("C", truc)?;
+        let report = protocol.validate()?;
+        assert!(report.issues("B").is_empty());
+        assert_eq!(
+            report.issues("C"),
+            &[ValidationIssue::TrucAncestors {
+                ancestors: vec!["A".to_string(), "B".to_string()]
+            }]
+        );
+
+        connect(&tc, &mut protocol, "A", "E", None, &public_key)?;
+        protocol.set_transaction_version("E", truc)?;
+        assert_eq!(
+            protocol.validate()?.issues("A"),
+            &[ValidationIssue::TrucDescendants {
+                children: vec!["B".to_string(), "E".to_string()]
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truc_conflicting_children() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_truc_conflicting_children").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let truc = Version::non_standard(3);
+        let mut protocol = Protocol::new("truc_conflicting_children");
+
+        // A single child spending two outputs
+        connect(&tc, &mut protocol, "A", "B", None, &public_key)?;
+        protocol.add_connection(
+            "A_B_2",
+            "A",
+            OutputSpec::Auto(OutputType::segwit_key(1_000, &public_key)?),
+            "B",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+        for name in ["A", "B"] {
+            protocol.set_transaction_version(name, truc)?;
+        }
+        assert!(protocol.validate()?.issues("A").is_empty());
+
+        // An alternative to B spending one of its outputs
+        protocol.add_connection(
+            "A_C",
+            "A",
+            OutputSpec::Index(1),
+            "C",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+        protocol.set_transaction_version("C", truc)?;
+        assert!(protocol.validate()?.issues("A").is_empty());
+
+        // D does not conflict with B nor C, so it can be unconfirmed along with either
+        connect(&tc, &mut protocol, "A", "D", None, &public_key)?;
+        protocol.set_transaction_version("D", truc)?;
+        assert_eq!(
+            protocol.validate()?.issues("A"),
+            &[ValidationIssue::TrucDescendants {
+                children: vec!["B".to_string(), "C".to_string(), "D".to_string()]
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequence_policy() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_sequence_policy").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = Protocol::new("sequence_policy");
+        connect(&tc, &mut protocol, "A", "C", None, &public_key)?;
+        connect(&tc, &mut protocol, "B", "C", Some(10), &public_key)?;
+        assert_eq!(protocol.sequence_policy("C"), SequencePolicy::Replaceable);
+
+        protocol.set_sequence_policy("C", SequencePolicy::Final)?;
+        connect(&tc, &mut protocol, "D", "C", None, &public_key)?;
+
+        let sequences: Vec<Sequence> = protocol
+            .transaction_by_name("C")?
+            .input
+            .iter()
+            .map(|txin| txin.sequence)
+            .collect();
+        assert_eq!(
+            sequences,
+            vec![Sequence::MAX, Sequence::from_height(10), Sequence::MAX]
+        );
+
+        Ok(())
+    }
 }
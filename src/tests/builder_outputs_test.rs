@@ -7,21 +7,21 @@ mod tests {
         opcodes::all::{OP_PUSHNUM_1, OP_RETURN},
         Amount, PublicKey, ScriptBuf, Sequence,
     };
+    use key_manager::key_type::BitcoinKeyType;
 
     use crate::{
         builder::{Protocol, ProtocolBuilder},
-        errors::ProtocolBuilderError,
-        scripts::{self, ProtocolScript, SignMode},
+        errors::{ProtocolBuilderError, ScriptError},
+        scripts::{self, OpReturnPolicy, ProtocolScript, SignMode},
         tests::utils::TestContext,
         types::{
             connection::{InputSpec, OutputSpec},
+            dust::DustPolicy,
             input::{InputArgs, SpendMode},
-            output::OutputType,
+            output::{OutputType, SpeedupData, Utxo},
         },
     };
 
-    use key_manager::key_type::BitcoinKeyType;
-
     #[test]
     fn test_op_return_output_script() -> Result<(), ProtocolBuilderError> {
         let tc = TestContext::new("test_op_return_output_script").unwrap();
@@ -95,6 +95,111 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_op_return_chunked_data() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_op_return_chunked_data").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let data = (0..200).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut protocol = Protocol::new("op_return_chunks");
+        let builder = ProtocolBuilder {};
+        builder
+            .add_external_connection(
+                &mut protocol,
+                "ext",
+                Hash::all_zeros(),
+                OutputSpec::Auto(OutputType::segwit_key(1_000, &public_key)?),
+                "op_return",
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            )?
+            .add_op_return_output_with_policy(
+                &mut protocol,
+                "op_return",
+                data.clone(),
+                OpReturnPolicy::MultipleOutputs,
+            )?;
+
+        // 200 bytes are carried by three standard outputs
+        let tx = protocol.transaction_by_name("op_return")?;
+        assert_eq!(tx.output.len(), 3);
+        assert_eq!(
+            tx.output
+                .iter()
+                .map(|output| output.script_pubkey.len())
+                .collect::<Vec<_>>(),
+            vec![83, 83, 42]
+        );
+        let reassembled = (0..3)
+            .flat_map(|index| scripts::op_return_data(tx, index).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(reassembled, data);
+        assert_eq!(scripts::op_return_data(tx, 1).unwrap(), data[80..160]);
+        assert_eq!(scripts::op_return_data(tx, 3), None);
+
+        // Before v30 a transaction is standard with a single OP_RETURN output
+        assert!(matches!(
+            builder.add_op_return_output(&mut protocol, "op_return", vec![0xab; 10]),
+            Err(ProtocolBuilderError::ScriptError(
+                ScriptError::TooManyOpReturnOutputs(4, 1)
+            ))
+        ));
+
+        // The data of all the OP_RETURN outputs cannot exceed the policy limit
+        let used = 2 * 83 + 42;
+        let fitting = vec![0xab; (scripts::OP_RETURN_MAX_TOTAL_SIZE - used) / 83 * 80];
+        builder.add_op_return_output_with_policy(
+            &mut protocol,
+            "op_return",
+            fitting,
+            OpReturnPolicy::MultipleOutputs,
+        )?;
+        assert!(matches!(
+            builder.add_op_return_output_with_policy(
+                &mut protocol,
+                "op_return",
+                vec![0xab; 80],
+                OpReturnPolicy::MultipleOutputs,
+            ),
+            Err(ProtocolBuilderError::ScriptError(
+                ScriptError::OpReturnDataTooLarge(80, _)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_op_return_single_output_policy() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_op_return_single_output_policy").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("op_return_single");
+        let builder = ProtocolBuilder {};
+        builder.add_external_connection(
+            &mut protocol,
+            "ext",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::segwit_key(1_000, &public_key)?),
+            "op_return",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+        )?;
+
+        // Data that does not fit in a single standard output is rejected
+        assert!(matches!(
+            builder.add_op_return_output(&mut protocol, "op_return", vec![0xab; 81]),
+            Err(ProtocolBuilderError::ScriptError(
+                ScriptError::TooManyOpReturnOutputs(2, 1)
+            ))
+        ));
+
+        builder.add_op_return_output(&mut protocol, "op_return", vec![0xab; 80])?;
+        let tx = protocol.transaction_by_name("op_return")?;
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(scripts::op_return_data(tx, 0), Some(vec![0xab; 80]));
+
+        Ok(())
+    }
+
     #[test]
     fn test_taproot_keypath_and_signature() -> Result<(), anyhow::Error> {
         // Arrange
@@ -249,4 +354,323 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_pay_to_anchor_speedup() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_pay_to_anchor_speedup").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let builder = ProtocolBuilder {};
+
+        let anchor = OutputType::pay_to_anchor();
+        assert_eq!(anchor.get_script_pubkey(), &ScriptBuf::new_p2a());
+        assert_eq!(DustPolicy::default().dust_limit(&anchor), Amount::ZERO);
+
+        // Zero fee parent with an ephemeral anchor
+        let mut protocol = Protocol::new("pay_to_anchor");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        protocol.add_transaction_output("A", &OutputType::segwit_key(10_000, &public_key)?)?;
+        builder.add_anchor_output(&mut protocol, "A")?;
+        protocol.build(tc.key_manager(), "")?;
+        assert!(protocol.validate()?.issues("A").is_empty());
+
+        let parent = protocol.transaction_by_name("A")?;
+        let funding = Utxo::new(Hash::all_zeros(), 0, 5_000, &public_key);
+        let cpfp = builder.speedup_transactions(
+            &[SpeedupData::new_anchor(parent.compute_txid(), 1, 0)],
+            funding,
+            &public_key,
+            1_000,
+            tc.key_manager().as_ref(),
+        )?;
+
+        assert_eq!(cpfp.input.len(), 2);
+        assert_eq!(cpfp.input[0].previous_output.txid, parent.compute_txid());
+        assert_eq!(cpfp.input[0].previous_output.vout, 1);
+        assert!(cpfp.input[0].witness.is_empty());
+        assert_eq!(cpfp.input[1].witness.len(), 2);
+        assert_eq!(cpfp.output[0].value, Amount::from_sat(4_000));
+
+        Ok(())
+    }
+
+    // EXT -> A -> B -> C
+    //          -> D
+    fn protocol(
+        tc: &TestContext,
+        public_key: &PublicKey,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("speedup_outputs");
+        protocol.add_connection(
+            "ext_a",
+            "EXT",
+            OutputSpec::Auto(OutputType::segwit_key(10_000, public_key)?),
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            Some(Hash::all_zeros()),
+        )?;
+
+        for (from, to) in [("A", "B"), ("B", "C"), ("A", "D")] {
+            protocol.add_connection(
+                &format!("{}_{}", from, to),
+                from,
+                OutputSpec::Auto(OutputType::segwit_key(1_000, public_key)?),
+                to,
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+                None,
+                None,
+            )?;
+        }
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_speedup_outputs_everywhere() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_speedup_outputs_everywhere").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let speedup_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 1)?;
+        let builder = ProtocolBuilder {};
+        let mut protocol = protocol(&tc, &public_key)?;
+
+        let sped_up = builder.add_speedup_outputs_everywhere(&mut protocol, &speedup_key, 500)?;
+        assert_eq!(sped_up, vec!["A", "B"]);
+        assert_eq!(protocol.speedup_output("A"), Some(2));
+        assert_eq!(protocol.speedup_output("B"), Some(1));
+        assert_eq!(protocol.speedup_output("C"), None);
+        assert_eq!(protocol.speedup_output("EXT"), None);
+
+        protocol.build(tc.key_manager(), "")?;
+
+        let speedup = protocol.speedup_data("A")?;
+        let a = protocol.transaction_by_name("A")?;
+        assert_eq!(
+            speedup.utxo.as_ref().map(|utxo| utxo.txid),
+            Some(a.compute_txid())
+        );
+        assert_eq!(speedup.utxo.as_ref().map(|utxo| utxo.vout), Some(2));
+
+        let funding = Utxo::new(Hash::all_zeros(), 0, 5_000, &public_key);
+        let cpfp = builder.speedup_transactions(
+            &[protocol.speedup_data("A")?, protocol.speedup_data("B")?],
+            funding,
+            &public_key,
+            1_000,
+            tc.key_manager().as_ref(),
+        )?;
+        assert_eq!(cpfp.input.len(), 3);
+        assert_eq!(cpfp.input[0].previous_output.txid, a.compute_txid());
+        assert_eq!(
+            cpfp.input[1].previous_output.txid,
+            protocol.transaction_by_name("B")?.compute_txid()
+        );
+
+        assert!(matches!(
+            protocol.speedup_data("C"),
+            Err(ProtocolBuilderError::MissingSpeedupOutput(name)) if name == "C"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_anchor_speedup_data() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_anchor_speedup_data").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = protocol(&tc, &public_key)?;
+
+        ProtocolBuilder {}.add_anchor_output(&mut protocol, "B")?;
+        protocol.build(tc.key_manager(), "")?;
+
+        let speedup = protocol.speedup_data("B")?;
+        assert!(speedup.is_anchor());
+        assert_eq!(
+            speedup.partial_utxo,
+            Some((protocol.transaction_by_name("B")?.compute_txid(), 1, 0))
+        );
+
+        Ok(())
+    }
+
+    fn dusty_protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaf = ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Skip);
+
+        let mut protocol = Protocol::new("dust");
+        protocol.add_connection(
+            "A_B",
+            "A",
+            OutputSpec::Auto(OutputType::taproot(
+                10_000,
+                &public_key,
+                std::slice::from_ref(&leaf),
+            )?),
+            "B",
+            InputSpec::Auto(tc.tr_sighash_type(), SpendMode::None),
+            None,
+            None,
+        )?;
+        protocol.add_transaction_output("B", &OutputType::segwit_key(100, &public_key)?)?;
+        protocol.add_transaction_output("B", &OutputType::taproot(300, &public_key, &[leaf])?)?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_dust_outputs_are_rejected() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_dust_outputs_are_rejected").unwrap();
+        let mut protocol = dusty_protocol(&tc)?;
+        protocol.set_dust_policy(DustPolicy::reject())?;
+
+        let result = protocol.build(tc.key_manager(), "");
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::DustOutput(name, 0, 100, 294)) if name == "B"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dust_outputs_are_bumped() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_dust_outputs_are_bumped").unwrap();
+        let mut protocol = dusty_protocol(&tc)?;
+        protocol.set_dust_policy(DustPolicy::bump())?;
+        protocol.build(tc.key_manager(), "")?;
+
+        // P2WPKH and P2TR outputs have different dust limits
+        let transaction = protocol.transaction_by_name("B")?;
+        assert_eq!(transaction.output[0].value, Amount::from_sat(294));
+        assert_eq!(transaction.output[1].value, Amount::from_sat(330));
+        assert!(protocol.validate()?.is_valid());
+
+        // A higher dust relay fee raises the limits
+        protocol.set_dust_policy(DustPolicy::bump().with_dust_relay_fee(6))?;
+        protocol.build(tc.key_manager(), "")?;
+
+        let transaction = protocol.transaction_by_name("B")?;
+        assert_eq!(transaction.output[0].value, Amount::from_sat(588));
+        assert_eq!(transaction.output[1].value, Amount::from_sat(660));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_dust_policy() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_no_dust_policy").unwrap();
+        let mut protocol = dusty_protocol(&tc)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        assert!(protocol.dust_policy().is_none());
+        let transaction = protocol.transaction_by_name("B")?;
+        assert_eq!(transaction.output[0].value, Amount::from_sat(100));
+        assert_eq!(transaction.output[1].value, Amount::from_sat(300));
+
+        Ok(())
+    }
+
+    // EXT -> A -> B
+    fn funded_protocol(
+        tc: &TestContext,
+        public_key: &PublicKey,
+        funding: u64,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let leaf = ProtocolScript::new(ScriptBuf::from(vec![0x51]), public_key, SignMode::Skip);
+
+        let mut protocol = Protocol::new("change");
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(funding, public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            5_000,
+            public_key,
+            &[leaf],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_change_output_is_appended() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_change_output_is_appended").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = funded_protocol(&tc, &public_key, 10_000)?;
+        protocol.set_change_address(public_key)?;
+        protocol.set_change_fee_rate(2)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        let transaction = protocol.transaction_by_name("A")?;
+        assert_eq!(transaction.output.len(), 2);
+        assert!(transaction.output[1].script_pubkey.is_p2wpkh());
+
+        let change = transaction.output[1].value;
+        let fee = Amount::from_sat(10_000 - 5_000) - change;
+        assert!(fee > Amount::from_sat(2 * 100) && fee < Amount::from_sat(2 * 200));
+        assert_eq!(
+            protocol
+                .change_output()
+                .and_then(|change| change.location()),
+            Some(("A", 1))
+        );
+
+        // Building again updates the existing change output
+        protocol.update_output("EXT", 0, &OutputType::segwit_key(11_000, &public_key)?)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        let transaction = protocol.transaction_by_name("A")?;
+        assert_eq!(transaction.output.len(), 2);
+        assert_eq!(
+            transaction.output[1].value,
+            change + Amount::from_sat(1_000)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dust_change_is_not_appended() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_dust_change_is_not_appended").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = funded_protocol(&tc, &public_key, 5_300)?;
+        protocol.set_change_address(ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash().unwrap()))?;
+        protocol.build(tc.key_manager(), "")?;
+
+        assert_eq!(protocol.transaction_by_name("A")?.output.len(), 1);
+        assert!(protocol.change_output().unwrap().location().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insufficient_funding() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_insufficient_funding").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = funded_protocol(&tc, &public_key, 4_000)?;
+        protocol.set_change_address(public_key)?;
+
+        assert!(matches!(
+            protocol.build(tc.key_manager(), ""),
+            Err(ProtocolBuilderError::InsufficientFunding(name, 4_000, _)) if name == "A"
+        ));
+
+        Ok(())
+    }
 }
@@ -1,22 +1,30 @@
 #[cfg(test)]
 mod tests {
-    use bitcoin::{hashes::Hash, PublicKey, ScriptBuf};
-    use std::rc::Rc;
+    use std::{io::Cursor, rc::Rc};
+
+    use bitcoin::{
+        consensus::{encode::VarInt, Encodable},
+        hashes::Hash,
+        PublicKey, ScriptBuf,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+    use storage_backend::storage::KeyValueStore;
 
     use crate::{
-        builder::{Protocol, ProtocolBuilder},
-        errors::ProtocolBuilderError,
+        builder::{LazyProtocol, Protocol, ProtocolBuilder},
+        errors::{GraphError, ProtocolBuilderError, SerializationError},
         scripts::{ProtocolScript, SignMode},
         tests::utils::TestContext,
         types::{
+            bundle::SignatureFilter,
             connection::{InputSpec, OutputSpec},
-            input::SpendMode,
+            input::{InputArgs, SpendMode},
             output::OutputType,
+            sequence::SequencePolicy,
+            serialization::{serialize, SerializationFormat},
         },
     };
 
-    use key_manager::key_type::BitcoinKeyType;
-
     #[test]
     fn test_persistence() -> Result<(), ProtocolBuilderError> {
         let tc = TestContext::new("test_persistence").unwrap();
@@ -257,4 +265,425 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_chunked_persistence() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_chunked_persistence").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let storage = Rc::new(tc.new_storage("protocol"));
+
+        let script =
+            ProtocolScript::new(ScriptBuf::from(vec![0x04]), &public_key, SignMode::Single);
+
+        let mut protocol = Protocol::new("chunked");
+        let builder = ProtocolBuilder {};
+
+        for (from, to) in [("A", "B"), ("B", "C"), ("A", "D")] {
+            builder.add_taproot_connection(
+                &mut protocol,
+                &format!("{}_{}", from, to),
+                from,
+                1000,
+                &public_key,
+                std::slice::from_ref(&script),
+                &SpendMode::ScriptsOnly,
+                to,
+                &tc.tr_sighash_type(),
+            )?;
+        }
+        protocol.set_constant("STAKE", 1000i64)?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        protocol.save_chunked(storage.clone())?;
+
+        let lazy = LazyProtocol::open("chunked", storage.clone())?.unwrap();
+        assert_eq!(lazy.transaction_names(), &["A", "B", "C", "D"]);
+        assert_eq!(lazy.next_transactions("A")?.len(), 2);
+        assert_eq!(lazy.loaded_transactions(), 0);
+
+        let b = lazy.transaction_by_name("B")?;
+        assert_eq!(b, *protocol.transaction_by_name("B")?);
+        assert_eq!(lazy.outputs("B")?.len(), 1);
+        assert_eq!(lazy.loaded_transactions(), 1);
+
+        assert!(matches!(
+            lazy.transaction_by_name("E"),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::MissingTransaction(_)
+            ))
+        ));
+
+        let loaded = lazy.load()?;
+        assert_eq!(loaded.transaction_names(), protocol.transaction_names());
+        assert_eq!(loaded.get_transaction_ids(), protocol.get_transaction_ids());
+        assert_eq!(
+            loaded.next_transactions("A")?,
+            protocol.next_transactions("A")?
+        );
+        assert_eq!(loaded.constants(), protocol.constants());
+        assert_eq!(
+            loaded.input_taproot_script_spend_signature("C", 0, 0)?,
+            protocol.input_taproot_script_spend_signature("C", 0, 0)?
+        );
+
+        assert!(Protocol::load_chunked("missing", storage.clone())?.is_none());
+        assert!(Protocol::load_chunked("chunked", storage)?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_persistence_keeps_every_field() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_chunked_persistence_keeps_every_field").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let storage = Rc::new(tc.new_storage("protocol"));
+
+        let script =
+            ProtocolScript::new(ScriptBuf::from(vec![0x04]), &public_key, SignMode::Single);
+
+        let mut protocol = Protocol::new_deterministic("chunked_fields", [7; 32]);
+        protocol.record_history();
+        ProtocolBuilder {}.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            1000,
+            &public_key,
+            &[script],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        // Leave as few fields as possible with their default value
+        protocol.set_constant("STAKE", 1000i64)?;
+        protocol.set_transaction_fee("B", 100)?;
+        protocol.set_sequence_policy("B", SequencePolicy::Final)?;
+        protocol.set_input_owner("B", 0, "operator")?;
+        protocol.create_unspendable_key()?;
+        protocol.require_unspendable_proofs(true)?;
+        protocol.require_amount_conservation(true)?;
+        protocol.freeze();
+
+        protocol.save_chunked(storage.clone())?;
+        let loaded = Protocol::load_chunked("chunked_fields", storage)?.unwrap();
+
+        // Fails if a field of the protocol is not persisted with the metadata
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&protocol).unwrap()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialization_formats() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_serialization_formats").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let storage = Rc::new(tc.new_storage("protocol"));
+
+        let script =
+            ProtocolScript::new(ScriptBuf::from(vec![0x04]), &public_key, SignMode::Single);
+
+        let mut protocol = Protocol::new("formats");
+        ProtocolBuilder {}.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            1000,
+            &public_key,
+            std::slice::from_ref(&script),
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let json = protocol.to_bytes(SerializationFormat::Json)?;
+        for format in [
+            SerializationFormat::Json,
+            SerializationFormat::Bincode,
+            SerializationFormat::Cbor,
+        ] {
+            let bytes = protocol.to_bytes(format)?;
+            assert_eq!(SerializationFormat::from_header(&bytes)?, format);
+            if format != SerializationFormat::Json {
+                assert!(bytes.len() < json.len());
+            }
+
+            let decoded = Protocol::from_bytes(&bytes)?;
+            assert_eq!(
+                decoded.get_transaction_ids(),
+                protocol.get_transaction_ids()
+            );
+            assert_eq!(
+                decoded.input_taproot_script_spend_signature("B", 0, 0)?,
+                protocol.input_taproot_script_spend_signature("B", 0, 0)?
+            );
+
+            protocol.save_with_format(storage.clone(), format)?;
+            let loaded = Protocol::load("formats", storage.clone())?.unwrap();
+            assert_eq!(loaded.get_transaction_ids(), protocol.get_transaction_ids());
+        }
+
+        // Data written by a newer version is rejected instead of misread
+        let mut bytes = protocol.to_bytes(SerializationFormat::Bincode)?;
+        bytes[4] += 1;
+        assert!(matches!(
+            Protocol::from_bytes(&bytes),
+            Err(ProtocolBuilderError::SerializationError(
+                SerializationError::UnsupportedVersion(2, 1)
+            ))
+        ));
+
+        assert!(matches!(
+            Protocol::from_bytes(b"{}"),
+            Err(ProtocolBuilderError::SerializationError(
+                SerializationError::InvalidHeader
+            ))
+        ));
+
+        Ok(())
+    }
+
+    // Swaps the stored indexes of two transactions, as written by older versions.
+    fn drifted_protocol() -> Result<serde_json::Value, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("drift");
+        protocol.add_transaction("A")?;
+        protocol.add_transaction("B")?;
+        protocol.add_transaction_output(
+            "A",
+            &OutputType::ExternalUnknown {
+                script_pubkey: ScriptBuf::default(),
+            },
+        )?;
+
+        let mut value = serde_json::to_value(&protocol).unwrap();
+        let indexes = &mut value["graph"]["node_indexes"];
+        let a = indexes["A"].clone();
+        indexes["A"] = indexes["B"].clone();
+        indexes["B"] = a;
+        Ok(value)
+    }
+
+    #[test]
+    fn test_index_drift_is_detected_on_decode() -> Result<(), ProtocolBuilderError> {
+        let bytes = serialize(&drifted_protocol()?, SerializationFormat::Json)?;
+        assert!(matches!(
+            Protocol::from_bytes(&bytes),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::IndexDrift(_)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_and_rebuild_indexes() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_load_and_rebuild_indexes").unwrap();
+        let storage = Rc::new(tc.new_storage("protocol"));
+        storage.set("drift", drifted_protocol()?, None)?;
+
+        assert!(matches!(
+            Protocol::load("drift", storage.clone()),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::IndexDrift(_)
+            ))
+        ));
+
+        let protocol = Protocol::load_and_rebuild_indexes("drift", storage.clone())?.unwrap();
+        assert_eq!(protocol.get_output_count("A")?, 1);
+        assert_eq!(protocol.get_output_count("B")?, 0);
+        protocol.save(storage.clone())?;
+        assert!(Protocol::load("drift", storage)?.is_some());
+
+        Ok(())
+    }
+
+    // EXT_A -> A and EXT_B -> B, each input signed by a different process
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("revision");
+        let builder = ProtocolBuilder {};
+        for (from, to) in [("EXT_A", "A"), ("EXT_B", "B")] {
+            builder.add_external_connection(
+                &mut protocol,
+                from,
+                Hash::all_zeros(),
+                OutputSpec::Auto(OutputType::segwit_key(10_000, &public_key)?),
+                to,
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            )?;
+        }
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_revision_conflict() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_revision_conflict").unwrap();
+        let storage = Rc::new(tc.new_storage("protocol"));
+
+        let mut protocol = protocol(&tc)?;
+        assert_eq!(protocol.revision(), 0);
+        protocol.save_checked(storage.clone())?;
+        assert_eq!(protocol.revision(), 1);
+        assert_eq!(Protocol::stored_revision("revision", storage.clone())?, 1);
+
+        // Signatures each process receives from its own signing party
+        let mut signed = protocol.clone();
+        signed.sign(tc.key_manager(), "")?;
+        let bundle_a =
+            signed.export_signatures(&SignatureFilter::all().with_transactions(&["A"]))?;
+        let bundle_b =
+            signed.export_signatures(&SignatureFilter::all().with_transactions(&["B"]))?;
+
+        let mut signer = Protocol::load("revision", storage.clone())?.unwrap();
+        let mut monitor = Protocol::load("revision", storage.clone())?.unwrap();
+        assert_eq!(monitor.revision(), 1);
+
+        signer.import_signatures(&bundle_a)?;
+        signer.save_checked(storage.clone())?;
+
+        // The monitor would clobber the signature stored by the signer
+        monitor.import_signatures(&bundle_b)?;
+        assert!(matches!(
+            monitor.save_checked(storage.clone()),
+            Err(ProtocolBuilderError::RevisionConflict(name, 1, 2)) if name == "revision"
+        ));
+
+        monitor.reload_and_merge_signatures(storage.clone())?;
+        assert_eq!(monitor.revision(), 2);
+        monitor.save_checked(storage.clone())?;
+
+        let stored = Protocol::load("revision", storage.clone())?.unwrap();
+        assert_eq!(stored.revision(), 3);
+        assert!(stored.input_ecdsa_signature("A", 0)?.is_some());
+        assert!(stored.input_ecdsa_signature("B", 0)?.is_some());
+
+        // A new protocol cannot overwrite a saved one
+        assert!(matches!(
+            protocol.save_checked(storage.clone()),
+            Err(ProtocolBuilderError::RevisionConflict(_, 1, 3))
+        ));
+
+        // Nor can a plain save of a stale protocol
+        assert!(matches!(
+            protocol.save(storage.clone()),
+            Err(ProtocolBuilderError::RevisionConflict(_, 1, 3))
+        ));
+        let stored = Protocol::load("revision", storage.clone())?.unwrap();
+        assert!(stored.input_ecdsa_signature("A", 0)?.is_some());
+
+        // A plain save of the stored revision overwrites it and keeps the revision
+        stored.save(storage.clone())?;
+        assert_eq!(Protocol::stored_revision("revision", storage.clone())?, 3);
+
+        Protocol::delete("revision", storage.clone())?;
+        assert_eq!(Protocol::stored_revision("revision", storage.clone())?, 0);
+
+        Ok(())
+    }
+
+    // EXT -> A -> B -> C
+    fn chain(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("stream");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(100_000, &public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+
+        for (from, to, value) in [("A", "B", 90_000), ("B", "C", 80_000)] {
+            protocol.add_connection(
+                &format!("{}_{}", from, to),
+                from,
+                OutputSpec::Auto(OutputType::segwit_key(value, &public_key)?),
+                to,
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+                None,
+                None,
+            )?;
+        }
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_consensus_stream_roundtrip() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_consensus_stream_roundtrip").unwrap();
+        let protocol = chain(&tc)?;
+
+        let mut stream = vec![];
+        let written = protocol.write_consensus_stream(&mut stream)?;
+        assert_eq!(written, stream.len());
+
+        let expected_size = 1 + ["A", "B", "C"]
+            .iter()
+            .map(|name| 1 + protocol.transaction_by_name(name).unwrap().total_size())
+            .sum::<usize>();
+        assert_eq!(stream.len(), expected_size);
+
+        let transactions = protocol.read_consensus_stream(&mut Cursor::new(&stream))?;
+        let names: Vec<_> = transactions.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["A", "B", "C"]);
+        for (name, transaction) in transactions.iter() {
+            assert_eq!(transaction, protocol.transaction_by_name(name)?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_signed_and_unknown_transactions() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_read_signed_and_unknown_transactions").unwrap();
+        let protocol = chain(&tc)?;
+
+        // Signed transactions keep their txid and are matched to their node
+        let mut args = InputArgs::new_segwit_args();
+        args.push_ecdsa_signature(protocol.input_ecdsa_signature("B", 0)?.unwrap())?;
+        let signed = protocol.transaction_to_send("B", &[args])?;
+        let mut stream = vec![];
+        VarInt(1).consensus_encode(&mut stream).unwrap();
+        VarInt(signed.total_size() as u64)
+            .consensus_encode(&mut stream)
+            .unwrap();
+        signed.consensus_encode(&mut stream).unwrap();
+
+        let transactions = protocol.read_consensus_stream(&mut Cursor::new(&stream))?;
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].0, "B");
+        assert!(!transactions[0].1.input[0].witness.is_empty());
+
+        let mut other = signed.clone();
+        other.lock_time = bitcoin::absolute::LockTime::from_consensus(1);
+        let mut stream = vec![];
+        VarInt(1).consensus_encode(&mut stream).unwrap();
+        VarInt(other.total_size() as u64)
+            .consensus_encode(&mut stream)
+            .unwrap();
+        other.consensus_encode(&mut stream).unwrap();
+
+        assert!(matches!(
+            protocol.read_consensus_stream(&mut Cursor::new(&stream)),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::TransactionNotFound(_)
+            ))
+        ));
+
+        // Truncated streams are rejected
+        assert!(matches!(
+            protocol.read_consensus_stream(&mut Cursor::new(&stream[..stream.len() - 1])),
+            Err(ProtocolBuilderError::ConsensusStreamError(_))
+        ));
+
+        Ok(())
+    }
 }
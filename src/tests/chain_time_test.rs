@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, hashes::Hash, ScriptBuf, Txid};
+
+    use crate::{
+        broadcast::{triggered_transactions, ChainState},
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        testing::chain::{MockChain, MOCK_BLOCK_INTERVAL},
+        tests::utils::TestContext,
+        types::{
+            broadcast_rule::BroadcastRule,
+            connection::{InputSpec, OutputSpec},
+            external::ExternalTx,
+            input::SpendMode,
+            OutputType,
+        },
+    };
+
+    const START_TIME: u32 = 1_700_000_000;
+
+    // start:0 -> timeout after 10 blocks, start:1 -> late at an absolute height
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("chain_time");
+        protocol.add_external_tx(
+            ExternalTx::new("start", Txid::from_byte_array([1; 32]))
+                .with_known_output(OutputType::segwit_unspendable(ScriptBuf::from(vec![0x51]))?)
+                .with_known_output(OutputType::segwit_unspendable(ScriptBuf::from(vec![0x52]))?),
+        )?;
+
+        for (index, (name, timelock)) in [("timeout", Some(10)), ("late", None)]
+            .into_iter()
+            .enumerate()
+        {
+            protocol.add_connection(
+                name,
+                "start",
+                OutputSpec::Index(index),
+                name,
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+                timelock,
+                None,
+            )?;
+        }
+        protocol.set_locktime("late", LockTime::from_height(120).unwrap())?;
+
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    fn start() -> Txid {
+        Txid::from_byte_array([1; 32])
+    }
+
+    #[test]
+    fn test_relative_timelock_readiness() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_relative_timelock_readiness").unwrap();
+        let protocol = protocol(&tc)?;
+        let mut chain = MockChain::new(100, START_TIME);
+
+        assert!(!protocol.ready_to_send("timeout", &chain)?);
+
+        chain.add_to_mempool(start());
+        assert_eq!(chain.confirmations(&start())?, Some(0));
+        assert!(!protocol.ready_to_send("timeout", &chain)?);
+
+        chain.mine(start()).advance(8);
+        assert_eq!(chain.confirmations(&start())?, Some(9));
+        assert!(!protocol.ready_to_send("timeout", &chain)?);
+
+        chain.advance(1);
+        assert!(protocol.ready_to_send("timeout", &chain)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_absolute_timelock_readiness() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_absolute_timelock_readiness").unwrap();
+        let mut protocol = protocol(&tc)?;
+        let mut chain = MockChain::new(119, START_TIME);
+
+        // Inputs without relative timelocks don't need the spent transaction to be mined
+        assert!(!protocol.ready_to_send("late", &chain)?);
+        chain.advance(1);
+        assert!(protocol.ready_to_send("late", &chain)?);
+
+        // Time locks are compared to the median time past
+        let unlock_time = START_TIME + 20 * MOCK_BLOCK_INTERVAL;
+        protocol.set_locktime("late", LockTime::from_time(unlock_time).unwrap())?;
+        assert!(!protocol.ready_to_send("late", &chain)?);
+
+        chain.set_median_time(unlock_time);
+        assert!(!protocol.ready_to_send("late", &chain)?);
+        chain.advance(1);
+        assert!(protocol.ready_to_send("late", &chain)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rules_wait_for_timelocks() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rules_wait_for_timelocks").unwrap();
+        let mut protocol = protocol(&tc)?;
+        protocol
+            .add_broadcast_rule(BroadcastRule::new("timeout").after_confirmations("start", 1))?;
+
+        let mut chain = MockChain::new(100, START_TIME);
+        chain.mine(start());
+        assert!(triggered_transactions(&protocol, &chain)?.is_empty());
+
+        chain.advance(9);
+        assert_eq!(triggered_transactions(&protocol, &chain)?, vec!["timeout"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, hashes::Hash, PublicKey, ScriptBuf};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    fn protocol(
+        tc: &TestContext,
+        public_key: &PublicKey,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("commitment");
+        let builder = ProtocolBuilder {};
+
+        builder.add_external_connection(
+            &mut protocol,
+            "funding",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::segwit_key(10_000, public_key)?),
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+        )?;
+
+        let script = ProtocolScript::new(ScriptBuf::from(vec![0x01]), public_key, SignMode::Single);
+        for (from, to) in [("A", "B"), ("B", "C"), ("C", "D"), ("A", "E")] {
+            builder.add_p2wsh_connection(
+                &mut protocol,
+                &format!("{}_{}", from, to),
+                from,
+                1000,
+                &script,
+                to,
+                &tc.ecdsa_sighash_type(),
+            )?;
+        }
+
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_inclusion_proofs() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_inclusion_proofs").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let protocol = protocol(&tc, &public_key)?;
+        let commitment = protocol.commitment_hash()?;
+
+        for name in ["A", "B", "C", "D", "E"] {
+            let proof = protocol.inclusion_proof(name)?;
+            assert_eq!(proof.leaf_count, 5);
+
+            let transaction = protocol.transaction_by_name(name)?;
+            assert!(proof.verify(transaction, &commitment));
+
+            let mut tampered = transaction.clone();
+            tampered.lock_time = LockTime::from_consensus(1);
+            assert!(!proof.verify(&tampered, &commitment));
+        }
+
+        // A proof only verifies the transaction under its own name
+        let mut proof = protocol.inclusion_proof("B")?;
+        proof.transaction_name = "C".to_string();
+        assert!(!proof.verify(protocol.transaction_by_name("B")?, &commitment));
+
+        assert!(matches!(
+            protocol.inclusion_proof("funding"),
+            Err(ProtocolBuilderError::TransactionNotCommitted(name)) if name == "funding"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_commitment_changes_with_protocol() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_commitment_changes_with_protocol").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let protocol_a = protocol(&tc, &public_key)?;
+        let mut protocol_b = protocol(&tc, &public_key)?;
+        assert_eq!(protocol_a.commitment_hash()?, protocol_b.commitment_hash()?);
+
+        let script =
+            ProtocolScript::new(ScriptBuf::from(vec![0x02]), &public_key, SignMode::Single);
+        protocol_b.update_output("C", 0, &OutputType::segwit_script(500, &script)?)?;
+        protocol_b.rebuild(tc.key_manager(), "")?;
+        assert_ne!(protocol_a.commitment_hash()?, protocol_b.commitment_hash()?);
+
+        let proof = protocol_a.inclusion_proof("D")?;
+        assert!(!proof.verify(
+            protocol_b.transaction_by_name("D")?,
+            &protocol_a.commitment_hash()?
+        ));
+
+        Ok(())
+    }
+}
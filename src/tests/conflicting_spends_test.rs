@@ -0,0 +1,148 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::{hashes::Hash, Txid};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        broadcast::{triggered_transactions, ChainState, ChainTime},
+        builder::Protocol,
+        errors::{BroadcastError, ProtocolBuilderError},
+        graph::graph::GraphOptions,
+        tests::utils::TestContext,
+        types::{
+            broadcast_rule::BroadcastRule,
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    struct MockChain {
+        confirmations: HashMap<Txid, u32>,
+    }
+
+    impl ChainTime for MockChain {
+        fn current_height(&self) -> Result<u32, BroadcastError> {
+            Ok(0)
+        }
+
+        fn median_time(&self) -> Result<u32, BroadcastError> {
+            Ok(0)
+        }
+    }
+
+    impl ChainState for MockChain {
+        fn confirmations(&self, txid: &Txid) -> Result<Option<u32>, BroadcastError> {
+            Ok(self.confirmations.get(txid).copied())
+        }
+    }
+
+    // start:0 is spent by both challenge and timeout, challenge -> response
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let output = OutputType::segwit_key(10_000, &public_key)?;
+        let input = InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit);
+
+        let mut protocol = Protocol::new("alternatives");
+        protocol.add_connection(
+            "funding",
+            "EXT",
+            OutputSpec::Auto(output.clone()),
+            "start",
+            input.clone(),
+            None,
+            Some(Hash::all_zeros()),
+        )?;
+        protocol.add_connection(
+            "challenge",
+            "start",
+            OutputSpec::Auto(output.clone()),
+            "challenge",
+            input.clone(),
+            None,
+            None,
+        )?;
+        protocol.add_connection(
+            "timeout",
+            "start",
+            OutputSpec::Index(0),
+            "timeout",
+            input.clone(),
+            Some(10),
+            None,
+        )?;
+        protocol.add_connection(
+            "response",
+            "challenge",
+            OutputSpec::Auto(output),
+            "response",
+            input,
+            None,
+            None,
+        )?;
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_alternative_spends_of_an_output() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_alternative_spends_of_an_output").unwrap();
+        let protocol = protocol(&tc)?;
+
+        assert_eq!(protocol.spenders("start", 0)?, vec!["challenge", "timeout"]);
+        assert_eq!(protocol.spenders("challenge", 0)?, vec!["response"]);
+
+        protocol.check_branch(&names(&["start", "challenge", "response"]))?;
+        protocol.check_branch(&names(&["start", "timeout"]))?;
+        assert!(matches!(
+            protocol.check_branch(&names(&["start", "response", "timeout"])),
+            Err(ProtocolBuilderError::ConflictingTransactions(a, b)) if a == "response" && b == "timeout"
+        ));
+        assert!(protocol
+            .execution_trace(
+                &names(&["timeout", "challenge"]),
+                &HashMap::new(),
+                &HashMap::new()
+            )
+            .is_err());
+
+        let dot = protocol.visualize(GraphOptions::Default)?;
+        assert!(dot.contains("start -> challenge:i0 [label=challenge style=dashed]"));
+        assert!(dot.contains("start -> timeout:i0 [label=timeout style=dashed]"));
+        assert!(dot.contains("challenge -> response:i0 [label=response]"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rules_of_alternative_branches_are_skipped() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rules_of_alternative_branches_are_skipped").unwrap();
+        let mut protocol = protocol(&tc)?;
+        protocol.add_broadcast_rule(BroadcastRule::new("timeout"))?;
+        protocol.add_broadcast_rule(BroadcastRule::new("response"))?;
+
+        let mut chain = MockChain {
+            confirmations: HashMap::new(),
+        };
+        // Both rules hold, but only the first one in name order is triggered
+        assert_eq!(triggered_transactions(&protocol, &chain)?, vec!["response"]);
+
+        // Once the challenge is mined the timeout can no longer be broadcast
+        let challenge = protocol.transaction_by_name("challenge")?.compute_txid();
+        chain.confirmations.insert(challenge, 1);
+        assert_eq!(triggered_transactions(&protocol, &chain)?, vec!["response"]);
+
+        // Once the timeout is mined the response can no longer be broadcast
+        let timeout = protocol.transaction_by_name("timeout")?.compute_txid();
+        chain.confirmations = HashMap::from([(timeout, 1)]);
+        assert!(triggered_transactions(&protocol, &chain)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        consensus::serialize,
+        hashes::Hash,
+        key::Parity,
+        secp256k1::{Secp256k1, SecretKey},
+        PublicKey, ScriptBuf, Transaction, Txid,
+    };
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    const SEED: [u8; 32] = [7; 32];
+
+    // Keys that do not depend on the key manager, so txids are the same everywhere
+    fn fixed_key(byte: u8) -> PublicKey {
+        let secret_key = SecretKey::from_slice(&[byte; 32]).unwrap();
+        PublicKey::new(secret_key.public_key(&Secp256k1::new()))
+    }
+
+    fn build(tc: &TestContext, seed: [u8; 32]) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = fixed_key(1);
+        let leaf = ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Single);
+
+        let mut protocol = Protocol::new_deterministic("deterministic", seed);
+        let builder = ProtocolBuilder {};
+        builder.add_external_connection(
+            &mut protocol,
+            "funding",
+            Txid::from_byte_array([1; 32]),
+            OutputSpec::Auto(OutputType::segwit_key(10_000, &public_key)?),
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+        )?;
+
+        for (from, to) in [("A", "B"), ("B", "C")] {
+            let internal_key =
+                PublicKey::from(protocol.create_unspendable_key()?.public_key(Parity::Even));
+            builder.add_taproot_connection(
+                &mut protocol,
+                &format!("{}_{}", from, to),
+                from,
+                5_000,
+                &internal_key,
+                std::slice::from_ref(&leaf),
+                &SpendMode::ScriptsOnly,
+                to,
+                &tc.tr_sighash_type(),
+            )?;
+        }
+
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    fn transactions(protocol: &Protocol) -> Result<Vec<Transaction>, ProtocolBuilderError> {
+        ["A", "B", "C"]
+            .iter()
+            .map(|name| protocol.transaction_by_name(name).cloned())
+            .collect()
+    }
+
+    #[test]
+    fn test_deterministic_build() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_deterministic_build").unwrap();
+        let other_tc = TestContext::new("test_deterministic_build_other").unwrap();
+
+        let protocol = build(&tc, SEED)?;
+        let other = build(&other_tc, SEED)?;
+        for (transaction, other) in transactions(&protocol)?
+            .iter()
+            .zip(transactions(&other)?.iter())
+        {
+            assert_eq!(serialize(transaction), serialize(other));
+        }
+
+        // Same txid on every run and every machine
+        assert_eq!(
+            protocol
+                .transaction_by_name("C")?
+                .compute_txid()
+                .to_string(),
+            "795172be87921106d67d45be43153ab11eb536227ee244851779e2ad3fa5aa47"
+        );
+
+        let reseeded = build(&tc, [8; 32])?;
+        assert_ne!(
+            reseeded.transaction_by_name("C")?.compute_txid(),
+            protocol.transaction_by_name("C")?.compute_txid()
+        );
+
+        let mut restored = Protocol::from_bytes(&protocol.to_bytes(Default::default())?)?;
+        assert_eq!(restored.seed(), Some(SEED));
+
+        // Keys created with a label do not shift the keys derived from the seed
+        let mut other = Protocol::new_deterministic("deterministic", SEED);
+        other.create_deterministic_unspendable_key("A_B")?;
+        other.create_unspendable_key()?;
+        other.create_unspendable_key()?;
+        assert_eq!(
+            restored.create_unspendable_key()?,
+            other.create_unspendable_key()?
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{hashes::Hash, PublicKey};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::{GraphError, ProtocolBuilderError},
+        tests::utils::TestContext,
+        types::{
+            broadcast_rule::BroadcastRule,
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    fn connect(
+        tc: &TestContext,
+        protocol: &mut Protocol,
+        name: &str,
+        from: &str,
+        to: &str,
+        public_key: &PublicKey,
+    ) -> Result<(), ProtocolBuilderError> {
+        protocol.add_connection(
+            name,
+            from,
+            OutputSpec::Auto(OutputType::segwit_key(1_000, public_key)?),
+            to,
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+        Ok(())
+    }
+
+    // EXT -> A -> B -> C
+    fn protocol(
+        tc: &TestContext,
+        public_key: &PublicKey,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("duplicate_subtree");
+        protocol.add_connection(
+            "ext_a",
+            "EXT",
+            OutputSpec::Auto(OutputType::segwit_key(10_000, public_key)?),
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            Some(Hash::all_zeros()),
+        )?;
+        connect(tc, &mut protocol, "a_b", "A", "B", public_key)?;
+        connect(tc, &mut protocol, "b_c", "B", "C", public_key)?;
+
+        protocol
+            .set_transaction_fee("B", 500)?
+            .set_input_owner("C", 0, "operator")?
+            .add_broadcast_rule(BroadcastRule::new("C").after_confirmations("B", 6))?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_duplicate_subtree() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_duplicate_subtree").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = protocol(&tc, &public_key)?;
+
+        let copies = protocol.duplicate_subtree("B", |name| format!("{}_1", name))?;
+        assert_eq!(copies, vec!["B_1", "C_1"]);
+        assert_eq!(protocol.graph().spenders("A", 0)?, vec!["B", "B_1"]);
+        assert_eq!(protocol.graph().spenders("B_1", 0)?, vec!["C_1"]);
+        assert_eq!(protocol.graph().spenders("B", 0)?, vec!["C"]);
+
+        assert_eq!(protocol.transaction_fee("B_1"), 500);
+        assert_eq!(protocol.owner("C_1", 0, None), Some("operator"));
+        assert_eq!(
+            protocol.broadcast_rules()["C_1"],
+            BroadcastRule::new("C_1").after_confirmations("B_1", 6)
+        );
+
+        // Rewire the copy to its own output of A
+        protocol.remove_connection("a_b_1")?;
+        connect(&tc, &mut protocol, "a_b_1", "A", "B_1", &public_key)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        let b_1 = protocol.transaction_by_name("B_1")?;
+        assert_ne!(
+            b_1.compute_txid(),
+            protocol.transaction_by_name("B")?.compute_txid()
+        );
+        assert_eq!(
+            protocol.transaction_by_name("C_1")?.input[0]
+                .previous_output
+                .txid,
+            b_1.compute_txid()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_subtree_conflicts() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_duplicate_subtree_conflicts").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = protocol(&tc, &public_key)?;
+
+        assert!(matches!(
+            protocol.duplicate_subtree("A", |name| format!("{}_1", name.replace('C', "B"))),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::TransactionAlreadyExists(name)
+            )) if name == "B_1"
+        ));
+        assert!(matches!(
+            protocol.duplicate_subtree("B", |name| name.replace('B', "C")),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::TransactionAlreadyExists(name)
+            )) if name == "C"
+        ));
+        assert!(matches!(
+            protocol.duplicate_subtree("EXT", |name| format!("{}_1", name)),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::ExternalSubtree(_)
+            ))
+        ));
+        assert_eq!(protocol.transaction_names().len(), 4);
+
+        Ok(())
+    }
+}
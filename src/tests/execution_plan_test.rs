@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+            plan::ConflictSet,
+        },
+    };
+
+    // EXT -> start; start:0 is spent by both challenge and timeout; challenge -> response
+    #[test]
+    fn test_execution_plan_with_alternative_branches() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_execution_plan_with_alternative_branches").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let output = OutputType::segwit_key(10_000, &public_key)?;
+        let input = InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit);
+
+        let mut protocol = Protocol::new("plan");
+        protocol.add_connection(
+            "funding",
+            "EXT",
+            OutputSpec::Auto(output.clone()),
+            "start",
+            input.clone(),
+            None,
+            Some(Hash::all_zeros()),
+        )?;
+        protocol.add_connection(
+            "challenge",
+            "start",
+            OutputSpec::Auto(output.clone()),
+            "challenge",
+            input.clone(),
+            None,
+            None,
+        )?;
+        protocol.add_connection(
+            "timeout",
+            "start",
+            OutputSpec::Index(0),
+            "timeout",
+            input.clone(),
+            None,
+            None,
+        )?;
+        protocol.add_connection(
+            "response",
+            "challenge",
+            OutputSpec::Auto(output),
+            "response",
+            input,
+            None,
+            None,
+        )?;
+
+        let plan = protocol.execution_plan()?;
+        let names: Vec<&str> = plan
+            .steps
+            .iter()
+            .map(|step| step.transaction_name.as_str())
+            .collect();
+        assert_eq!(names.len(), 4);
+        assert_eq!(names[0], "start");
+
+        assert_eq!(
+            plan.conflicts,
+            vec![ConflictSet {
+                transaction_name: "start".to_string(),
+                output_index: 0,
+                spenders: vec!["challenge".to_string(), "timeout".to_string()],
+            }]
+        );
+
+        let start = plan.step("start").unwrap();
+        assert_eq!(start.depends_on, vec!["EXT"]);
+        assert!(start.conflicts_with.is_empty() && start.excludes.is_empty());
+
+        assert_eq!(
+            plan.step("challenge").unwrap().conflicts_with,
+            vec!["timeout"]
+        );
+        assert_eq!(plan.step("timeout").unwrap().excludes.len(), 2);
+        assert_eq!(plan.step("response").unwrap().excludes, vec!["timeout"]);
+
+        assert!(plan.are_exclusive("response", "timeout"));
+        assert!(plan.are_exclusive("timeout", "response"));
+        assert!(!plan.are_exclusive("challenge", "response"));
+
+        assert_eq!(
+            plan.remaining(&["start".to_string(), "timeout".to_string()]),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            plan.remaining(&["start".to_string(), "challenge".to_string()]),
+            vec!["response"]
+        );
+
+        Ok(())
+    }
+}
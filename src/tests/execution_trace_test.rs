@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::ScriptBuf;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::{InputArgs, SpendMode},
+            output::OutputType,
+            trace::{TraceStep, UNASSIGNED_PARTY},
+        },
+    };
+
+    // A -> B spending any of two leaves, B -> C spending the first leaf after 10 blocks.
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaves = [
+            ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Single),
+            ProtocolScript::new(ScriptBuf::from(vec![0x52]), &public_key, SignMode::Single),
+        ];
+
+        let mut protocol = Protocol::new("trace");
+        protocol
+            .add_connection(
+                "A_B",
+                "A",
+                OutputSpec::Auto(OutputType::taproot(10_000, &public_key, &leaves)?),
+                "B",
+                InputSpec::Auto(tc.tr_sighash_type(), SpendMode::ScriptsOnly),
+                None,
+                None,
+            )?
+            .add_connection(
+                "B_C",
+                "B",
+                OutputSpec::Auto(OutputType::taproot(5_000, &public_key, &leaves)?),
+                "C",
+                InputSpec::Auto(tc.tr_sighash_type(), SpendMode::Script { leaf: 0 }),
+                Some(10),
+                None,
+            )?;
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    fn parties() -> HashMap<String, String> {
+        HashMap::from([
+            ("A".to_string(), "Alice".to_string()),
+            ("B".to_string(), "Bob".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_execution_trace() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_execution_trace").unwrap();
+        let protocol = protocol(&tc)?;
+
+        let branch = ["A", "B", "C"].map(String::from);
+        let args = HashMap::from([("B".to_string(), vec![InputArgs::new_taproot_script_args(1)])]);
+        let trace = protocol.execution_trace(&branch, &parties(), &args)?;
+
+        assert_eq!(
+            trace.steps,
+            vec![
+                TraceStep {
+                    transaction_name: "A".to_string(),
+                    party: "Alice".to_string(),
+                    block: 0,
+                    leaves: vec![],
+                },
+                TraceStep {
+                    transaction_name: "B".to_string(),
+                    party: "Bob".to_string(),
+                    block: 0,
+                    leaves: vec![(0, 1)],
+                },
+                TraceStep {
+                    transaction_name: "C".to_string(),
+                    party: UNASSIGNED_PARTY.to_string(),
+                    block: 10,
+                    leaves: vec![(0, 0)],
+                },
+            ]
+        );
+
+        let expected = "sequenceDiagram
+    participant Alice
+    participant Bob
+    participant Unassigned
+    participant Bitcoin
+    Note over Bitcoin: block +0
+    Alice->>Bitcoin: A
+    Bob->>Bitcoin: B (leaf 1)
+    Note over Bitcoin: block +10
+    Unassigned->>Bitcoin: C (leaf 0)
+";
+        assert_eq!(trace.to_mermaid(), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recorded_trace() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_recorded_trace").unwrap();
+        let protocol = protocol(&tc)?;
+
+        let a = protocol.transaction_to_send("A", &[])?;
+        let b = protocol.transaction_to_send("B", &[InputArgs::new_taproot_script_args(1)])?;
+        let c = protocol.transaction_to_send("C", &[InputArgs::new_taproot_script_args(0)])?;
+
+        let trace = protocol.recorded_trace(&[(c, 115), (a, 100), (b, 102)], &parties())?;
+
+        let summary: Vec<_> = trace
+            .steps
+            .iter()
+            .map(|step| {
+                (
+                    step.transaction_name.as_str(),
+                    step.block,
+                    step.leaves.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("A", 0, vec![]),
+                ("B", 2, vec![(0, 1)]),
+                ("C", 15, vec![(0, 0)]),
+            ]
+        );
+        assert_eq!(trace.parties(), vec!["Alice", "Bob", UNASSIGNED_PARTY]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{hashes::Hash, Address, Network, ScriptBuf, Txid};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            explorer::Explorer,
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    #[test]
+    fn test_explorer_links() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_explorer_links").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaf = ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Skip);
+        let funding_txid = Txid::from_byte_array([7; 32]);
+
+        let mut protocol = Protocol::new("explorer");
+        let builder = ProtocolBuilder {};
+        builder.add_external_connection(
+            &mut protocol,
+            "EXT",
+            funding_txid,
+            OutputSpec::Auto(OutputType::segwit_key(10_000, &public_key)?),
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            5_000,
+            &public_key,
+            &[leaf],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.add_transaction_output(
+            "A",
+            &OutputType::segwit_unspendable(ScriptBuf::new_op_return([1, 2]))?,
+        )?;
+        protocol.label_output("A", 0, "stake")?;
+        protocol.build(tc.key_manager(), "")?;
+
+        let explorer = Explorer::new("http://localhost:8080/", Network::Regtest);
+        let links = protocol.explorer_links(&explorer)?;
+        assert_eq!(links.transactions.len(), 3);
+
+        let external = links.transaction("EXT").unwrap();
+        assert!(external.external);
+        assert_eq!(external.txid, funding_txid);
+        assert_eq!(
+            external.url,
+            format!("http://localhost:8080/tx/{}", funding_txid)
+        );
+
+        let a = links.transaction("A").unwrap();
+        let txid = protocol.transaction_by_name("A")?.compute_txid();
+        assert_eq!(a.url, format!("http://localhost:8080/tx/{}", txid));
+        assert_eq!(a.outputs[0].labels, vec!["stake".to_string()]);
+
+        let script_pubkey = &protocol.transaction_by_name("A")?.output[0].script_pubkey;
+        let address = Address::from_script(script_pubkey, Network::Regtest).unwrap();
+        assert!(address.to_string().starts_with("bcrt1p"));
+        assert_eq!(a.outputs[0].address, Some(address.to_string()));
+        assert_eq!(
+            a.outputs[0].address_url,
+            Some(format!("http://localhost:8080/address/{}", address))
+        );
+
+        // OP_RETURN outputs have no address
+        assert_eq!(a.outputs[1].address, None);
+        assert_eq!(a.outputs[1].address_url, None);
+
+        assert_eq!(
+            Explorer::mempool_space(Network::Signet).transaction_url(&txid),
+            format!("https://mempool.space/signet/tx/{}", txid)
+        );
+
+        Ok(())
+    }
+}
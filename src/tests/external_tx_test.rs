@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use bitcoin::{hashes::Hash, OutPoint, Txid};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            external::{ExternalOutput, ExternalTx},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    fn funding_protocol(tc: &TestContext) -> Result<(Protocol, Txid), ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let txid = Txid::from_byte_array([7; 32]);
+
+        let funding = ExternalTx::new("funding", txid)
+            .with_opaque_outputs(2)
+            .with_known_output(OutputType::segwit_key(10_000, &public_key)?);
+
+        let mut protocol = Protocol::new("external");
+        protocol.add_external_tx(funding)?;
+
+        Ok((protocol, txid))
+    }
+
+    fn connect_funding(
+        protocol: &mut Protocol,
+        tc: &TestContext,
+        output: OutputSpec,
+        txid: Option<Txid>,
+    ) -> Result<(), ProtocolBuilderError> {
+        protocol.add_connection(
+            "funding",
+            "funding",
+            output,
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            txid,
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_spend_known_external_output() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_spend_known_external_output").unwrap();
+        let (mut protocol, txid) = funding_protocol(&tc)?;
+
+        let funding = protocol.external_transaction("funding").unwrap();
+        assert_eq!(funding.output_count(), 3);
+        assert!(funding.known_output(1).is_none());
+        assert!(funding.known_output(2).is_some());
+
+        connect_funding(&mut protocol, &tc, OutputSpec::Last, None)?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let a = protocol.transaction_by_name("A")?;
+        assert_eq!(a.input[0].previous_output, OutPoint::new(txid, 2));
+        assert!(protocol.input_ecdsa_signature("A", 0)?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_undeclared_external_outputs() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_reject_undeclared_external_outputs").unwrap();
+        let (mut protocol, _) = funding_protocol(&tc)?;
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 1)?;
+
+        let result = connect_funding(&mut protocol, &tc, OutputSpec::Index(0), None);
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::OpaqueExternalOutput(_, 0))
+        ));
+
+        let result = connect_funding(&mut protocol, &tc, OutputSpec::Index(3), None);
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::UndeclaredExternalOutput(_, 3))
+        ));
+
+        let output = OutputSpec::Auto(OutputType::segwit_key(1000, &public_key)?);
+        let result = connect_funding(&mut protocol, &tc, output, None);
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::UndeclaredExternalOutput(_, 3))
+        ));
+
+        let result = connect_funding(
+            &mut protocol,
+            &tc,
+            OutputSpec::Index(2),
+            Some(Hash::all_zeros()),
+        );
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::ExternalTxidMismatch(_, _, _))
+        ));
+
+        assert_eq!(protocol.transaction_by_name("funding")?.output.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_tx_persistence() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_external_tx_persistence").unwrap();
+        let storage = Rc::new(tc.new_storage("protocol"));
+        let (protocol, txid) = funding_protocol(&tc)?;
+
+        protocol.save(storage.clone())?;
+        drop(protocol);
+
+        let protocol = Protocol::load("external", storage)?.unwrap();
+        let funding = protocol.external_transaction("funding").unwrap();
+
+        assert_eq!(funding.txid(), txid);
+        assert!(matches!(funding.outputs()[0], ExternalOutput::Opaque(2)));
+        assert!(matches!(funding.outputs()[1], ExternalOutput::Known(_)));
+
+        Ok(())
+    }
+}
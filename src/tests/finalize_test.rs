@@ -0,0 +1,162 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        consensus::encode::deserialize_hex,
+        opcodes::all::{OP_CHECKSIGVERIFY, OP_DROP, OP_PUSHNUM_1},
+        script::Builder,
+        PublicKey, Transaction, XOnlyPublicKey,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode, StackItem},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::{InputArgs, SpendMode},
+            output::OutputType,
+            witness_args::WitnessArgs,
+        },
+    };
+
+    // Checks the signature on top of the stack, then drops `extra_items` witness items. Only the
+    // signature is declared, so leaves with extra items accept any items under it.
+    fn checksig(public_key: &PublicKey, extra_items: usize) -> ProtocolScript {
+        let mut builder = Builder::new()
+            .push_x_only_key(&XOnlyPublicKey::from(*public_key))
+            .push_opcode(OP_CHECKSIGVERIFY);
+        for _ in 0..extra_items {
+            builder = builder.push_opcode(OP_DROP);
+        }
+        let script = builder.push_opcode(OP_PUSHNUM_1).into_script();
+
+        let mut leaf = ProtocolScript::new(script, public_key, SignMode::Single);
+        if extra_items == 0 {
+            leaf.add_stack_item(StackItem::new_schnorr_sig(false));
+        }
+        leaf
+    }
+
+    // EXT -> A -> B, B can spend the key path or any of the two leaves
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+
+        let mut protocol = Protocol::new("finalize");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &funding_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        protocol.add_connection(
+            "A_B",
+            "A",
+            OutputSpec::Auto(OutputType::taproot(
+                8_000,
+                &key,
+                &[checksig(&key, 0), checksig(&key, 3)],
+            )?),
+            "B",
+            InputSpec::Auto(
+                tc.tr_sighash_type(),
+                SpendMode::All {
+                    key_path_sign: SignMode::Single,
+                },
+            ),
+            None,
+            None,
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_finalize_transactions() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_finalize_transactions").unwrap();
+        let protocol = protocol(&tc)?;
+        let none = WitnessArgs::new();
+
+        // Segwit: signature and key
+        let a = protocol.finalize("A", None, &none, tc.key_manager())?;
+        assert_eq!(a.input[0].witness.len(), 2);
+        let hex = protocol.finalize_hex("A", None, &none, tc.key_manager())?;
+        assert_eq!(deserialize_hex::<Transaction>(&hex).unwrap(), a);
+
+        // Taproot key path: only the signature
+        let b = protocol.finalize("B", None, &none, tc.key_manager())?;
+        assert_eq!(b.input[0].witness.len(), 1);
+
+        // Leaf for the whole transaction: signature, script and control block
+        let b = protocol.finalize("B", Some(0), &none, tc.key_manager())?;
+        assert_eq!(b.input[0].witness.len(), 3);
+
+        assert!(matches!(
+            protocol.finalize("B", Some(5), &none, tc.key_manager()),
+            Err(ProtocolBuilderError::MissingTaprootLeaf(5, 0))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_finalize_with_witness_args() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_finalize_with_witness_args").unwrap();
+        let protocol = protocol(&tc)?;
+
+        let witness_args: WitnessArgs = serde_json::from_str(
+            r#"{
+                "inputs": [
+                    {
+                        "input": 0,
+                        "leaf": 1,
+                        "items": [
+                            { "type": "hex", "data": "cafe" },
+                            { "type": "winternitz_message", "message": "0a", "index": 7, "key_type": "HASH160" }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let args = protocol.input_args_for("B", Some(0), &witness_args, tc.key_manager())?;
+        assert_eq!(args.len(), 1);
+        let items: Vec<_> = args[0].iter().cloned().collect();
+        assert_eq!(items[0], vec![0xca, 0xfe]);
+        assert_eq!(
+            *items.last().unwrap(),
+            protocol
+                .input_taproot_script_spend_signature("B", 0, 1)?
+                .unwrap()
+                .serialize()
+                .to_vec()
+        );
+
+        // The hex item, the Winternitz signature and the taproot signature
+        let signature = tc.key_manager().sign_winternitz_message(
+            &[0x0a],
+            key_manager::winternitz::WinternitzType::HASH160,
+            7,
+        )?;
+        let mut winternitz = InputArgs::new_taproot_script_args(1);
+        winternitz.push_winternitz_signature(signature);
+        assert_eq!(items.len(), 1 + winternitz.len() + 1);
+
+        // The leaf of the input takes precedence, and the script and control block follow
+        let b = protocol.finalize("B", Some(0), &witness_args, tc.key_manager())?;
+        let witness: Vec<_> = b.input[0].witness.iter().collect();
+        assert_eq!(witness.len(), items.len() + 2);
+        assert_eq!(
+            witness[witness.len() - 2],
+            protocol.get_script_from_output("A", 0)?.1[1]
+                .get_script()
+                .as_bytes()
+        );
+
+        Ok(())
+    }
+}
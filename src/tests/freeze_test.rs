@@ -0,0 +1,174 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{PublicKey, ScriptBuf};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            audit::AuditEvent, broadcast_rule::BroadcastRule, dust::DustPolicy, input::SpendMode,
+            limits::ProtocolLimits, output::OutputType,
+        },
+    };
+
+    // EXT -> A -> B, built and signed
+    fn signed_protocol(
+        tc: &TestContext,
+        public_key: &PublicKey,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let leaf = ProtocolScript::new(ScriptBuf::from(vec![0x51]), public_key, SignMode::Single);
+
+        let mut protocol = Protocol::new("frozen");
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            5_000,
+            public_key,
+            &[leaf],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_frozen_protocol_rejects_structural_changes() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_frozen_protocol_rejects_structural_changes").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = signed_protocol(&tc, &public_key)?;
+        protocol.freeze();
+        assert!(protocol.is_frozen());
+
+        let output = OutputType::segwit_key(1_000, &public_key)?;
+        assert!(matches!(
+            protocol.add_transaction_output("B", &output),
+            Err(ProtocolBuilderError::ProtocolFrozen(_))
+        ));
+        assert!(matches!(
+            protocol.add_transaction("C"),
+            Err(ProtocolBuilderError::ProtocolFrozen(_))
+        ));
+        assert!(matches!(
+            protocol.set_constant("STAKE", 1_000i64),
+            Err(ProtocolBuilderError::ProtocolFrozen(_))
+        ));
+        assert!(matches!(
+            protocol.add_broadcast_rule(BroadcastRule::new("B").at_height(1_000)),
+            Err(ProtocolBuilderError::ProtocolFrozen(_))
+        ));
+        assert!(matches!(
+            protocol.unfreeze(" "),
+            Err(ProtocolBuilderError::MissingUnfreezeReason)
+        ));
+
+        // Signatures are kept when unfreezing
+        protocol.unfreeze("add a refund output")?;
+        assert!(!protocol.is_frozen());
+        assert!(protocol.input_ecdsa_signature("A", 0)?.is_some());
+
+        // The next build only discards the signatures of the changed transactions and their
+        // descendants
+        protocol.add_transaction_output("B", &output)?;
+        protocol.build(tc.key_manager(), "")?;
+        assert!(protocol.input_ecdsa_signature("A", 0)?.is_some());
+        assert!(protocol
+            .input_taproot_script_spend_signature("B", 0, 0)?
+            .is_none());
+
+        protocol.sign(tc.key_manager(), "")?;
+        assert!(protocol
+            .input_taproot_script_spend_signature("B", 0, 0)?
+            .is_some());
+
+        let trail = protocol.audit_trail();
+        assert_eq!(trail.len(), 3);
+        assert_eq!(trail[0].event, AuditEvent::Frozen);
+        assert_eq!(
+            trail[1].event,
+            AuditEvent::Unfrozen {
+                reason: "add a refund output".to_string(),
+            }
+        );
+        assert_eq!(
+            trail[2].event,
+            AuditEvent::SignaturesCleared {
+                transactions: vec!["B".to_string()],
+            }
+        );
+
+        // Later builds are not recorded
+        protocol.build(tc.key_manager(), "")?;
+        assert_eq!(protocol.audit_trail().len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_frozen_protocol_rejects_annotations_and_policies() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_frozen_protocol_rejects_annotations_and_policies").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = signed_protocol(&tc, &public_key)?;
+        let variant = protocol.transaction_by_name("B")?.clone();
+        protocol.freeze();
+
+        let frozen = |result: Result<(), ProtocolBuilderError>| {
+            matches!(result, Err(ProtocolBuilderError::ProtocolFrozen(_)))
+        };
+        assert!(frozen(protocol.label_output("A", 0, "stake").map(|_| ())));
+        assert!(frozen(protocol.label_input("B", 0, "from_a").map(|_| ())));
+        assert!(frozen(
+            protocol.set_input_owner("B", 0, "alice").map(|_| ())
+        ));
+        assert!(frozen(
+            protocol.set_leaf_owner("B", 0, 0, "alice").map(|_| ())
+        ));
+        assert!(frozen(
+            protocol
+                .import_resigned_transaction("B", variant)
+                .map(|_| ())
+        ));
+        assert!(frozen(
+            protocol.set_dust_policy(DustPolicy::reject()).map(|_| ())
+        ));
+        assert!(frozen(
+            protocol.set_limits(ProtocolLimits::default()).map(|_| ())
+        ));
+        assert!(frozen(
+            protocol.require_amount_conservation(true).map(|_| ())
+        ));
+        assert!(frozen(
+            protocol.require_unspendable_proofs(true).map(|_| ())
+        ));
+        assert!(frozen(protocol.update_input_signature("B", 0, None, 0)));
+        assert!(frozen(protocol.update_input_signatures("B", 0, vec![None])));
+
+        // Nothing was changed
+        assert_eq!(protocol.owner("B", 0, None), None);
+        assert!(protocol.dust_policy().is_none());
+        assert!(protocol
+            .input_taproot_script_spend_signature("B", 0, 0)?
+            .is_some());
+
+        protocol.unfreeze("assign owners")?;
+        protocol.set_input_owner("B", 0, "alice")?;
+        assert_eq!(protocol.owner("B", 0, None), Some("alice"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{hashes::Hash, PublicKey, Txid};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        tests::utils::TestContext,
+        types::{
+            funding::{CoinSelection, FundingSource, FundingUtxo},
+            output::OutputType,
+        },
+    };
+
+    fn source(
+        public_key: &PublicKey,
+        strategy: CoinSelection,
+    ) -> Result<FundingSource, ProtocolBuilderError> {
+        let mut source = FundingSource::new(strategy);
+        for (txid, vout, value) in [(1, 0, 6_000), (1, 2, 3_000), (2, 1, 5_000), (3, 0, 1_000)] {
+            source = source.with_utxo(FundingUtxo::new(
+                Txid::from_byte_array([txid; 32]),
+                vout,
+                OutputType::segwit_key(value, public_key)?,
+            )?);
+        }
+        Ok(source)
+    }
+
+    fn values(utxos: &[FundingUtxo]) -> Vec<u64> {
+        utxos.iter().map(|utxo| utxo.value()).collect()
+    }
+
+    #[test]
+    fn test_coin_selection() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_coin_selection").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let largest_first = source(&public_key, CoinSelection::LargestFirst)?;
+        assert_eq!(values(&largest_first.select(8_000)?), vec![6_000, 5_000]);
+
+        // 5_000 + 3_000 matches the target exactly
+        let bnb = source(
+            &public_key,
+            CoinSelection::BranchAndBound { tolerance: 100 },
+        )?;
+        assert_eq!(values(&bnb.select(8_000)?), vec![5_000, 3_000]);
+
+        // Without a match within the tolerance it falls back to largest first
+        assert_eq!(values(&bnb.select(10_500)?), vec![6_000, 5_000]);
+
+        assert!(matches!(
+            bnb.select(20_000),
+            Err(ProtocolBuilderError::InsufficientFunds(15_000, 20_000))
+        ));
+
+        let unspendable = OutputType::segwit_unspendable(Default::default())?;
+        assert!(FundingUtxo::new(Txid::all_zeros(), 0, unspendable).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_funding() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_add_funding").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("funding");
+        let source = source(&public_key, CoinSelection::BranchAndBound { tolerance: 0 })?;
+        let selected = protocol.add_funding(&source, "start", 10_000)?;
+        assert_eq!(values(&selected), vec![6_000, 3_000, 1_000]);
+
+        // Both UTXOs of the first transaction are spent from a single external transaction
+        let funding = protocol.external_transaction("start_funding_0").unwrap();
+        assert_eq!(funding.txid(), Txid::from_byte_array([1; 32]));
+        assert_eq!(funding.output_count(), 3);
+        assert!(funding.known_output(1).is_none());
+
+        protocol.add_transaction_output("start", &OutputType::segwit_key(9_000, &public_key)?)?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let start = protocol.transaction_by_name("start")?;
+        let outpoints: Vec<(Txid, u32)> = start
+            .input
+            .iter()
+            .map(|txin| (txin.previous_output.txid, txin.previous_output.vout))
+            .collect();
+        assert_eq!(
+            outpoints,
+            vec![
+                (Txid::from_byte_array([1; 32]), 0),
+                (Txid::from_byte_array([1; 32]), 2),
+                (Txid::from_byte_array([3; 32]), 0),
+            ]
+        );
+        for input_index in 0..3 {
+            assert!(protocol
+                .input_ecdsa_signature("start", input_index)?
+                .is_some());
+        }
+
+        // Funding again uses new external transaction names
+        protocol.add_funding(&source, "start", 5_000)?;
+        assert!(protocol.external_transaction("start_funding_2").is_some());
+
+        Ok(())
+    }
+}
@@ -1,10 +1,21 @@
 #[cfg(test)]
 mod test {
-    use crate::errors::GraphError;
-    use crate::graph::graph::{Connection, Node, TransactionGraph};
+    use bitcoin::{
+        consensus::Decodable, hashes::Hash, hex::test_hex_unwrap as hex, PublicKey, Transaction,
+    };
+    use key_manager::key_type::BitcoinKeyType;
 
-    use bitcoin::hex::test_hex_unwrap as hex;
-    use bitcoin::{consensus::Decodable, Transaction};
+    use crate::{
+        builder::Protocol,
+        errors::{GraphError, ProtocolBuilderError},
+        graph::graph::{Connection, Node, TransactionGraph},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
 
     const SOME_TX: &str = "0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000";
 
@@ -143,4 +154,263 @@ mod test {
 
         assert_eq!(graph._get_node_count(), 3);
     }
+
+    // EXT -> A, A -> B (twice), A -> C, B -> D, C -> D
+    fn diamond(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let output = OutputType::segwit_key(10_000, &public_key)?;
+        let input = InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit);
+
+        let mut protocol = Protocol::new("diamond");
+        protocol.add_connection(
+            "EXT_A",
+            "EXT",
+            OutputSpec::Auto(output.clone()),
+            "A",
+            input.clone(),
+            None,
+            Some(Hash::all_zeros()),
+        )?;
+        for (name, from, to) in [
+            ("A_B", "A", "B"),
+            ("A_B_2", "A", "B"),
+            ("A_C", "A", "C"),
+            ("B_D", "B", "D"),
+            ("C_D", "C", "D"),
+        ] {
+            protocol.add_connection(
+                name,
+                from,
+                OutputSpec::Auto(output.clone()),
+                to,
+                input.clone(),
+                None,
+                None,
+            )?;
+        }
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_ancestors_and_descendants() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_ancestors_and_descendants").unwrap();
+        let protocol = diamond(&tc)?;
+
+        let ancestors = protocol.ancestors("D")?;
+        assert_eq!(ancestors.len(), 4);
+        assert_eq!(&ancestors[..2], &["EXT".to_string(), "A".to_string()]);
+        assert!(ancestors.contains(&"B".to_string()) && ancestors.contains(&"C".to_string()));
+
+        assert_eq!(protocol.ancestors("B")?, vec!["EXT", "A"]);
+        assert!(protocol.ancestors("EXT")?.is_empty());
+
+        let descendants = protocol.descendants("A")?;
+        assert_eq!(descendants.len(), 3);
+        assert_eq!(descendants.last().unwrap(), "D");
+        assert!(protocol.descendants("D")?.is_empty());
+
+        assert!(matches!(
+            protocol.ancestors("E"),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::MissingTransaction(_)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_paths_roots_and_leaves() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_paths_roots_and_leaves").unwrap();
+        let protocol = diamond(&tc)?;
+
+        let mut paths = protocol.paths_between("EXT", "D")?;
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![vec!["EXT", "A", "B", "D"], vec!["EXT", "A", "C", "D"],]
+        );
+        assert!(protocol.paths_between("B", "C")?.is_empty());
+
+        assert_eq!(protocol.roots(), vec!["EXT"]);
+        assert_eq!(protocol.leaves(), vec!["D"]);
+
+        Ok(())
+    }
+
+    // EXT -> A -> B -> C
+    //          ------->
+    fn protocol(
+        tc: &TestContext,
+        public_key: &PublicKey,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("graph_editing");
+        protocol.add_connection(
+            "ext_a",
+            "EXT",
+            OutputSpec::Auto(OutputType::segwit_key(10_000, public_key)?),
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            Some(Hash::all_zeros()),
+        )?;
+
+        for (name, from, to, value) in [
+            ("a_c", "A", "C", 4000),
+            ("a_b", "A", "B", 5000),
+            ("b_c", "B", "C", 3000),
+        ] {
+            protocol.add_connection(
+                name,
+                from,
+                OutputSpec::Auto(OutputType::segwit_key(value, public_key)?),
+                to,
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+                None,
+                None,
+            )?;
+        }
+
+        protocol
+            .label_input("C", 1, "from_b")?
+            .set_input_owner("C", 0, "alice")?
+            .set_input_owner("C", 1, "bob")?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_remove_connection() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_remove_connection").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = protocol(&tc, &public_key)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        protocol.remove_connection("a_c")?;
+
+        // The later input of C shifts down with its label and owner
+        assert_eq!(protocol.inputs("C")?.len(), 1);
+        assert_eq!(protocol.input_index("C", "from_b")?, 0);
+        assert_eq!(protocol.owner("C", 0, None), Some("bob"));
+        assert_eq!(protocol.owners().len(), 1);
+
+        // The spent output is kept
+        assert_eq!(protocol.transaction_by_name("A")?.output.len(), 2);
+
+        assert!(protocol.is_dirty("C")?);
+        assert_eq!(protocol.rebuild(tc.key_manager(), "")?, vec!["C"]);
+        assert_eq!(
+            protocol.transaction_by_name("C")?.input[0]
+                .previous_output
+                .txid,
+            protocol.transaction_by_name("B")?.compute_txid()
+        );
+
+        assert!(matches!(
+            protocol.remove_connection("a_c"),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::UnknownConnection(name)
+            )) if name == "a_c"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_ambiguous_connection() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_remove_ambiguous_connection").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = protocol(&tc, &public_key)?;
+        protocol.add_connection(
+            "a_b",
+            "A",
+            OutputSpec::Auto(OutputType::segwit_key(1000, &public_key)?),
+            "D",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+
+        assert!(matches!(
+            protocol.remove_connection("a_b"),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::AmbiguousConnection(_, 2)
+            ))
+        ));
+        assert_eq!(protocol.inputs("B")?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_transaction() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_remove_transaction").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = protocol(&tc, &public_key)?;
+        protocol.set_transaction_fee("B", 1000)?;
+        protocol.set_input_owner("B", 0, "alice")?;
+        protocol.build(tc.key_manager(), "")?;
+
+        protocol.remove_transaction("B")?;
+
+        let mut names = protocol.transaction_names();
+        names.sort();
+        assert_eq!(names, vec!["A", "C", "EXT"]);
+        protocol.graph().check_indexes()?;
+
+        assert_eq!(protocol.transaction_fee("B"), 0);
+        assert_eq!(protocol.owner("C", 0, None), Some("alice"));
+        assert_eq!(protocol.owners().len(), 1);
+        assert!(matches!(
+            protocol.input_index("C", "from_b"),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::MissingInputLabel(..)
+            ))
+        ));
+
+        assert_eq!(protocol.inputs("C")?.len(), 1);
+        assert_eq!(protocol.rebuild(tc.key_manager(), "")?, vec!["C"]);
+
+        // The first transaction is replaced by the last one in the graph, which is reindexed
+        protocol.remove_transaction("EXT")?;
+        protocol.graph().check_indexes()?;
+        assert!(protocol.inputs("A")?.is_empty());
+        assert_eq!(protocol.inputs("C")?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_output_type() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_replace_output_type").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let tr_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let mut protocol = protocol(&tc, &public_key)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        // B signs its input with ECDSA, so the output it spends cannot become taproot
+        let taproot = OutputType::taproot(5000, &tr_key, &[])?;
+        assert!(matches!(
+            protocol.replace_output_type("A", 1, &taproot),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::InvalidOutputTypeForSighashType
+            ))
+        ));
+        assert_eq!(
+            protocol.transaction_by_name("A")?.output[1].value.to_sat(),
+            5000
+        );
+        assert!(!protocol.is_dirty("A")?);
+
+        let replaced =
+            protocol.replace_output_type("A", 1, &OutputType::segwit_key(4500, &public_key)?)?;
+        assert_eq!(replaced.get_value().to_sat(), 5000);
+        assert_eq!(
+            protocol.inputs("B")?[0].output_type()?.get_value().to_sat(),
+            4500
+        );
+        assert_eq!(protocol.rebuild(tc.key_manager(), "")?, vec!["A", "B", "C"]);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,182 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{PublicKey, ScriptBuf};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{input::SpendMode, output::OutputType},
+    };
+
+    fn leaf(public_key: &PublicKey, opcode: u8) -> ProtocolScript {
+        ProtocolScript::new(ScriptBuf::from(vec![opcode]), public_key, SignMode::Single)
+    }
+
+    // EXT -> A -> B -> D
+    //          -> C
+    fn protocol(
+        tc: &TestContext,
+        public_key: &PublicKey,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("incremental");
+        let builder = ProtocolBuilder {};
+
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+
+        for (name, from, to, value) in [
+            ("a_b", "A", "B", 5000),
+            ("a_c", "A", "C", 4000),
+            ("b_d", "B", "D", 3000),
+        ] {
+            builder.add_taproot_connection(
+                &mut protocol,
+                name,
+                from,
+                value,
+                public_key,
+                &[leaf(public_key, 0x01)],
+                &SpendMode::ScriptsOnly,
+                to,
+                &tc.tr_sighash_type(),
+            )?;
+        }
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_rebuild_only_affected_transactions() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rebuild_only_affected_transactions").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = protocol(&tc, &public_key)?;
+        assert!(protocol.is_dirty("A")?);
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        assert!(!protocol.is_dirty("A")?);
+        assert!(protocol.rebuild(tc.key_manager(), "")?.is_empty());
+
+        let a_txid = protocol.transaction_by_name("A")?.compute_txid();
+        let c_message = protocol.get_hashed_message("C", 0, 0)?;
+        let d_message = protocol.get_hashed_message("D", 0, 0)?;
+
+        protocol.update_output(
+            "B",
+            0,
+            &OutputType::taproot(2500, &public_key, &[leaf(&public_key, 0x02)])?,
+        )?;
+        assert!(protocol.is_dirty("B")?);
+        assert!(protocol.is_dirty("D")?);
+        assert!(!protocol.is_dirty("C")?);
+
+        let rebuilt = protocol.rebuild_and_sign(tc.key_manager(), "")?;
+        assert_eq!(rebuilt, vec!["B", "D"]);
+
+        assert_eq!(protocol.transaction_by_name("A")?.compute_txid(), a_txid);
+        assert_eq!(protocol.get_hashed_message("C", 0, 0)?, c_message);
+        assert_ne!(protocol.get_hashed_message("D", 0, 0)?, d_message);
+
+        let b = protocol.transaction_by_name("B")?;
+        assert_eq!(b.output[0].value.to_sat(), 2500);
+        assert_eq!(
+            protocol.transaction_by_name("D")?.input[0]
+                .previous_output
+                .txid,
+            b.compute_txid()
+        );
+        assert_eq!(
+            protocol.get_script_to_spend("D", 0, 0)?.get_script(),
+            &ScriptBuf::from(vec![0x02])
+        );
+
+        // The incremental rebuild matches a full rebuild of the protocol
+        let mut full = protocol.clone();
+        full.build_and_sign(tc.key_manager(), "")?;
+        for name in ["A", "B", "C", "D"] {
+            assert_eq!(
+                full.transaction_by_name(name)?.compute_txid(),
+                protocol.transaction_by_name(name)?.compute_txid()
+            );
+            assert_eq!(
+                full.get_hashed_message(name, 0, 0)?,
+                protocol.get_hashed_message(name, 0, 0)?
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebuild_propagates_txid_changes() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rebuild_propagates_txid_changes").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = protocol(&tc, &public_key)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        protocol.update_output(
+            "A",
+            1,
+            &OutputType::taproot(3500, &public_key, &[leaf(&public_key, 0x01)])?,
+        )?;
+
+        let rebuilt = protocol.rebuild(tc.key_manager(), "")?;
+        assert_eq!(rebuilt.len(), 4);
+
+        let a_txid = protocol.transaction_by_name("A")?.compute_txid();
+        for name in ["B", "C"] {
+            assert_eq!(
+                protocol.transaction_by_name(name)?.input[0]
+                    .previous_output
+                    .txid,
+                a_txid
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebuild_discards_stale_signatures() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_rebuild_discards_stale_signatures").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = protocol(&tc, &public_key)?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        let c_signature = protocol.input_taproot_script_spend_signature("C", 0, 0)?;
+        assert!(c_signature.is_some());
+
+        protocol.update_output(
+            "B",
+            0,
+            &OutputType::taproot(2500, &public_key, &[leaf(&public_key, 0x02)])?,
+        )?;
+        assert_eq!(protocol.rebuild(tc.key_manager(), "")?, vec!["B", "D"]);
+
+        // Signatures of the rebuilt transactions would not verify against the new sighashes
+        for name in ["B", "D"] {
+            assert!(protocol
+                .input_taproot_script_spend_signature(name, 0, 0)?
+                .is_none());
+        }
+        assert_eq!(
+            protocol.input_taproot_script_spend_signature("C", 0, 0)?,
+            c_signature
+        );
+
+        protocol.sign(tc.key_manager(), "")?;
+        assert!(protocol
+            .input_taproot_script_spend_signature("D", 0, 0)?
+            .is_some());
+
+        Ok(())
+    }
+}
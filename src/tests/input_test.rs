@@ -1,10 +1,26 @@
 #[cfg(test)]
 mod test {
-    use crate::types::input::{InputSignatures, SighashType, Signature};
+    use bitcoin::{
+        hashes::{sha256, Hash},
+        key::rand,
+        secp256k1::{Message, Secp256k1},
+        taproot, Amount, EcdsaSighashType, OutPoint, ScriptBuf, Sequence, TapSighashType,
+        Transaction, TxIn, TxOut, Witness,
+    };
+    use key_manager::key_type::BitcoinKeyType;
 
-    use bitcoin::key::rand;
-    use bitcoin::secp256k1::{Message, Secp256k1};
-    use bitcoin::{taproot, EcdsaSighashType, TapSighashType};
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        helpers::sighash::raw_sighash,
+        scripts::{ProtocolScript, SignMode, HTLC_CLAIM_LEAF},
+        tests::utils::{checksig_leaf, TestContext},
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::{InputArgs, InputSignatures, SighashType, Signature, SpendMode},
+            output::OutputType,
+        },
+    };
 
     #[test]
     fn test_empty_signatures() {
@@ -70,4 +86,393 @@ mod test {
             _ => panic!("Expected ECDSA sighash type"),
         }
     }
+
+    // Adds a fee bumping input and a change output, as a wallet would after the protocol signed.
+    fn bump_fee(transaction: &Transaction) -> Transaction {
+        let mut bumped = transaction.clone();
+        bumped.input.push(TxIn {
+            previous_output: OutPoint::new(Hash::all_zeros(), 7),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        });
+        bumped.output.push(TxOut {
+            value: Amount::from_sat(5_000),
+            script_pubkey: ScriptBuf::new_op_return([0x01]),
+        });
+        bumped
+    }
+
+    // EXT -> A (P2WPKH, NONE|ANYONECANPAY) -> B (taproot, SINGLE|ANYONECANPAY)
+    #[test]
+    fn test_anyone_can_pay_survives_fee_bumping() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_anyone_can_pay_survives_fee_bumping").unwrap();
+        let segwit_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let taproot_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let leaves = [checksig_leaf(&taproot_key)];
+
+        let none_acp = SighashType::Ecdsa(EcdsaSighashType::NonePlusAnyoneCanPay);
+        let single_acp = SighashType::Taproot(TapSighashType::SinglePlusAnyoneCanPay);
+
+        let mut protocol = Protocol::new("sighash_flags");
+        let builder = ProtocolBuilder {};
+        builder.add_external_connection(
+            &mut protocol,
+            "EXT",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::segwit_key(10_000, &segwit_key)?),
+            "A",
+            InputSpec::Auto(none_acp.clone(), SpendMode::Segwit),
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            9_000,
+            &taproot_key,
+            &leaves,
+            &SpendMode::All {
+                key_path_sign: SignMode::Single,
+            },
+            "B",
+            &single_acp,
+        )?;
+        protocol.add_transaction_output("B", &OutputType::segwit_key(8_000, &segwit_key)?)?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let fee_input = TxOut {
+            value: Amount::from_sat(20_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&segwit_key.wpubkey_hash().unwrap()),
+        };
+
+        for (name, script, sighash_type) in [
+            ("A", None, &none_acp),
+            ("B", Some(leaves[0].get_script().as_script()), &single_acp),
+            ("B", None, &single_acp),
+        ] {
+            let bumped = bump_fee(protocol.transaction_by_name(name)?);
+            let mut prevouts = protocol.graph().get_prevouts(name)?;
+            prevouts.push(fee_input.clone());
+
+            let hashed_messages = protocol.inputs(name)?[0].hashed_messages();
+            let expected = match script {
+                Some(_) => hashed_messages[0],
+                None => *hashed_messages.last().unwrap(),
+            };
+            assert_eq!(
+                Some(raw_sighash(&bumped, &prevouts, 0, script, sighash_type)?),
+                expected
+            );
+        }
+
+        // Signing the new transaction without ANYONECANPAY commits to the fee input
+        let bumped = bump_fee(protocol.transaction_by_name("B")?);
+        let mut prevouts = protocol.graph().get_prevouts("B")?;
+        prevouts.push(fee_input);
+        let single = SighashType::Taproot(TapSighashType::Single);
+        assert_ne!(
+            Some(raw_sighash(&bumped, &prevouts, 0, None, &single)?),
+            *protocol.inputs("B")?[0].hashed_messages().last().unwrap()
+        );
+
+        // The stored signatures still verify
+        assert!(protocol.verify_all_signatures()?.is_valid());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_single_requires_matching_output() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_single_requires_matching_output").unwrap();
+        let taproot_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaves = [checksig_leaf(&taproot_key)];
+
+        let mut protocol = Protocol::new("sighash_single");
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::taproot(20_000, &taproot_key, &leaves)?,
+            "A",
+            SpendMode::ScriptsOnly,
+        )?;
+        for (name, sighash_type) in [
+            ("A_B_0", tc.tr_sighash_type()),
+            ("A_B_1", SighashType::Taproot(TapSighashType::Single)),
+        ] {
+            builder.add_taproot_connection(
+                &mut protocol,
+                name,
+                "A",
+                9_000,
+                &taproot_key,
+                &leaves,
+                &SpendMode::ScriptsOnly,
+                "B",
+                &sighash_type,
+            )?;
+        }
+        protocol
+            .add_transaction_output("B", &OutputType::taproot(8_000, &taproot_key, &leaves)?)?;
+
+        // Input 1 of B has no output 1 to commit to
+        assert!(matches!(
+            protocol.build(tc.key_manager(), ""),
+            Err(ProtocolBuilderError::MissingSingleOutput(name, 1)) if name == "B"
+        ));
+
+        protocol.add_transaction_output("B", &OutputType::taproot(500, &taproot_key, &leaves)?)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        Ok(())
+    }
+
+    fn leaves(tc: &TestContext) -> Result<Vec<ProtocolScript>, ProtocolBuilderError> {
+        let dispute = checksig_leaf(&tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?);
+        let mut bump = checksig_leaf(&tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?);
+        bump.set_sighash_type(TapSighashType::SinglePlusAnyoneCanPay);
+        Ok(vec![dispute, bump])
+    }
+
+    #[test]
+    fn test_leaf_sighash_type() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_leaf_sighash_type").unwrap();
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaves = leaves(&tc)?;
+
+        let mut protocol = Protocol::new("leaf_sighash");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::taproot(10_000, &internal_key, &leaves)?,
+            "A",
+            SpendMode::ScriptsOnly,
+        )?;
+        protocol.add_transaction_output(
+            "A",
+            &OutputType::taproot(9_000, &internal_key, &[checksig_leaf(&internal_key)])?,
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        // Each leaf is signed with its own sighash type
+        let sighash_type = |leaf| -> Result<TapSighashType, ProtocolBuilderError> {
+            Ok(protocol
+                .input_taproot_script_spend_signature("A", 0, leaf)?
+                .unwrap()
+                .sighash_type)
+        };
+        assert_eq!(sighash_type(0)?, TapSighashType::All);
+        assert_eq!(sighash_type(1)?, TapSighashType::SinglePlusAnyoneCanPay);
+        assert!(protocol.verify_all_signatures()?.is_valid());
+
+        // The override is part of what the leaf commits to
+        assert_ne!(
+            leaves[1].template_hash(),
+            checksig_leaf(&tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?)
+                .template_hash()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_leaf_sighash_single_requires_output() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_leaf_sighash_single_requires_output").unwrap();
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaves = leaves(&tc)?;
+
+        let mut protocol = Protocol::new("leaf_sighash_single");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::taproot(10_000, &internal_key, &leaves)?,
+            "A",
+            SpendMode::ScriptsOnly,
+        )?;
+
+        // The input sighash type signs no single output, but the bump leaf does
+        assert!(matches!(
+            protocol.build(tc.key_manager(), ""),
+            Err(ProtocolBuilderError::MissingSingleOutput(name, 0)) if name == "A"
+        ));
+
+        protocol.add_transaction_output(
+            "A",
+            &OutputType::taproot(9_000, &internal_key, &[checksig_leaf(&internal_key)])?,
+        )?;
+        protocol.build(tc.key_manager(), "")?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_leaf_sighash_overrides_single_input() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_leaf_sighash_overrides_single_input").unwrap();
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let mut all = checksig_leaf(&tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?);
+        all.set_sighash_type(TapSighashType::All);
+        let output = OutputType::taproot(10_000, &internal_key, &[all])?;
+        let single = SighashType::Taproot(TapSighashType::Single);
+
+        // Only the leaves are signed and none of them commits to a single output
+        let mut protocol = Protocol::new("leaf_sighash_overrides");
+        ProtocolBuilder {}.add_external_connection(
+            &mut protocol,
+            "EXT",
+            Hash::all_zeros(),
+            OutputSpec::Auto(output.clone()),
+            "A",
+            InputSpec::Auto(single.clone(), SpendMode::ScriptsOnly),
+        )?;
+        protocol.build(tc.key_manager(), "")?;
+
+        // The key path is signed with the sighash type of the input
+        let mut protocol = Protocol::new("leaf_sighash_overrides_key_path");
+        ProtocolBuilder {}.add_external_connection(
+            &mut protocol,
+            "EXT",
+            Hash::all_zeros(),
+            OutputSpec::Auto(output),
+            "A",
+            InputSpec::Auto(
+                single,
+                SpendMode::All {
+                    key_path_sign: SignMode::Single,
+                },
+            ),
+        )?;
+        assert!(matches!(
+            protocol.build(tc.key_manager(), ""),
+            Err(ProtocolBuilderError::MissingSingleOutput(name, 0)) if name == "A"
+        ));
+
+        Ok(())
+    }
+
+    // EXT -> LOCK -> CLAIM, CLAIM spends the hashlock leaf
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let recipient_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+        let refund_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 3)?;
+
+        let mut protocol = Protocol::new("input_args");
+        ProtocolBuilder {}
+            .add_external_connection(
+                &mut protocol,
+                "EXT",
+                Hash::all_zeros(),
+                OutputSpec::Auto(OutputType::segwit_key(10_000, &funding_key)?),
+                "LOCK",
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            )?
+            .add_htlc_connection(
+                &mut protocol,
+                "pegout",
+                "LOCK",
+                8_000,
+                &internal_key,
+                sha256::Hash::hash(&[0x42; 32]),
+                &recipient_key,
+                &refund_key,
+                144,
+                SignMode::Single,
+                "CLAIM",
+                &tc.tr_sighash_type(),
+                None,
+            )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    fn claim_args(protocol: &Protocol, preimage: &[u8]) -> Result<InputArgs, ProtocolBuilderError> {
+        let mut args = InputArgs::new_taproot_script_args_by_name(HTLC_CLAIM_LEAF);
+        args.push_taproot_signature(
+            protocol
+                .input_taproot_script_spend_signature("CLAIM", 0, 0)?
+                .unwrap(),
+        )?;
+        args.push_slice(preimage);
+        Ok(args)
+    }
+
+    #[test]
+    fn test_key_spend_args() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_key_spend_args").unwrap();
+        let protocol = protocol(&tc)?;
+
+        assert!(matches!(
+            protocol.transaction_to_send("LOCK", &[InputArgs::new_segwit_args()]),
+            Err(ProtocolBuilderError::InvalidInputArgs(name, 0, reason))
+                if name == "LOCK" && reason == "expected 1 witness items, got 0"
+        ));
+        assert!(matches!(
+            protocol.transaction_to_send("LOCK", &[]),
+            Err(ProtocolBuilderError::InputArgsCountMismatch(_, 1, 0))
+        ));
+
+        let mut args = InputArgs::new_segwit_args();
+        args.push_ecdsa_signature(protocol.input_ecdsa_signature("LOCK", 0)?.unwrap())?;
+        protocol.transaction_to_send("LOCK", &[args])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_leaf_stack_item_args() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_leaf_stack_item_args").unwrap();
+        let protocol = protocol(&tc)?;
+
+        // The claim leaf declares a 32 byte preimage on top of the recipient signature
+        protocol.transaction_to_send("CLAIM", &[claim_args(&protocol, &[0x42; 32])?])?;
+
+        assert!(matches!(
+            protocol.transaction_to_send("CLAIM", &[claim_args(&protocol, &[0x42; 33])?]),
+            Err(ProtocolBuilderError::InvalidInputArgs(_, 0, reason))
+                if reason == "item 1 (33 bytes) is not a valid item of up to 32 bytes"
+        ));
+
+        let mut args = InputArgs::new_taproot_script_args_by_name(HTLC_CLAIM_LEAF);
+        args.push_slice(&[0x42; 32]).push_slice(&[0x42; 32]);
+        assert!(matches!(
+            protocol.transaction_to_send("CLAIM", &[args]),
+            Err(ProtocolBuilderError::InvalidInputArgs(_, 0, reason))
+                if reason == "item 0 (32 bytes) is not a valid schnorr signature"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_args_from_protocol() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_input_args_from_protocol").unwrap();
+        let protocol = protocol(&tc)?;
+
+        let args = InputArgs::from_protocol(&protocol, "LOCK", 0, None)?;
+        protocol.transaction_to_send("LOCK", &[args])?;
+
+        // The preimage goes on top of the recipient signature
+        let mut args = InputArgs::from_protocol(&protocol, "CLAIM", 0, Some(0))?;
+        args.push_slice(&[0x42; 32]);
+        protocol.transaction_to_send("CLAIM", &[args])?;
+
+        // Only the claim leaf is signed
+        for leaf in [None, Some(1)] {
+            assert!(matches!(
+                InputArgs::from_protocol(&protocol, "CLAIM", 0, leaf),
+                Err(ProtocolBuilderError::MissingInputSignature(name, 0)) if name == "CLAIM"
+            ));
+        }
+        assert!(matches!(
+            InputArgs::from_protocol(&protocol, "CLAIM", 0, Some(2)),
+            Err(ProtocolBuilderError::MissingTaprootLeaf(2, 0))
+        ));
+        assert!(matches!(
+            InputArgs::from_protocol(&protocol, "LOCK", 0, Some(0)),
+            Err(ProtocolBuilderError::InvalidOutputType(..))
+        ));
+        assert!(matches!(
+            InputArgs::from_protocol(&protocol, "LOCK", 1, None),
+            Err(ProtocolBuilderError::MissingInput(name, 1)) if name == "LOCK"
+        ));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests {
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        helpers::weight_computing::get_transaction_hex,
+        tests::utils::{checksig_leaf_with_signature, TestContext},
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    // EXT -> A -> B, the output of A has two leaves and is labeled
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+
+        let mut protocol = Protocol::new("inspect");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &funding_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        protocol.add_connection(
+            "A_B",
+            "A",
+            OutputSpec::Labeled(
+                "dispute".to_string(),
+                OutputType::taproot(
+                    8_000,
+                    &key,
+                    &[
+                        checksig_leaf_with_signature(&key),
+                        checksig_leaf_with_signature(&key),
+                    ],
+                )?,
+            ),
+            "B",
+            InputSpec::Auto(tc.tr_sighash_type(), SpendMode::ScriptsOnly),
+            None,
+            None,
+        )?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_inspect_transaction() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_inspect_transaction").unwrap();
+        let mut protocol = protocol(&tc)?;
+
+        protocol.build(tc.key_manager(), "")?;
+        let unsigned = protocol.inspect("B")?;
+        assert!(!unsigned.is_fully_signed());
+        assert_eq!(
+            unsigned.inputs[0].signatures,
+            vec![("L0".to_string(), false), ("L1".to_string(), false)]
+        );
+
+        protocol.sign(tc.key_manager(), "")?;
+        let b = protocol.inspect("B")?;
+        assert!(b.is_fully_signed());
+        assert!(!b.external);
+        assert_eq!(b.txid, protocol.transaction_by_name("B")?.compute_txid());
+        assert_eq!(
+            b.hex,
+            get_transaction_hex(protocol.transaction_by_name("B")?)
+        );
+        assert!(b.estimated_vsize.unwrap() * 4 > b.weight);
+        assert_eq!(b.inputs[0].spends, Some(("A".to_string(), 0)));
+        assert_eq!(b.inputs[0].value, Some(8_000));
+        assert!(b.outputs.is_empty());
+
+        let a = protocol.inspect("A")?;
+        assert_eq!(a.inputs[0].spends, Some(("EXT".to_string(), 0)));
+        assert_eq!(a.inputs[0].signatures, vec![("sig".to_string(), true)]);
+        assert_eq!(a.outputs[0].value, 8_000);
+        assert_eq!(a.outputs[0].leaves, 2);
+        assert_eq!(a.outputs[0].label.as_deref(), Some("dispute"));
+
+        let ext = protocol.inspect("EXT")?;
+        assert!(ext.external);
+        assert_eq!(ext.estimated_vsize, None);
+
+        let text = a.to_string();
+        assert!(text.contains("spends EXT:0 [10000]"));
+        assert!(text.contains("sig=signed"));
+        assert!(text.contains(&format!("hex: {}", a.hex)));
+
+        assert!(matches!(
+            protocol.inspect("C"),
+            Err(ProtocolBuilderError::MissingTransaction(name, _)) if name == "C"
+        ));
+
+        Ok(())
+    }
+}
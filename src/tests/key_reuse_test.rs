@@ -0,0 +1,214 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{PublicKey, ScriptBuf};
+    use key_manager::{key_type::BitcoinKeyType, winternitz::WinternitzType};
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::{ProtocolBuilderError, ScriptError},
+        scripts::{self, KeyType, ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{input::SpendMode, output::OutputType},
+    };
+
+    fn winternitz() -> KeyType {
+        KeyType::WinternitzKey {
+            key_type: WinternitzType::HASH160,
+            message_size: 4,
+        }
+    }
+
+    // Leaf committing to a value with a Winternitz key
+    fn leaf(
+        public_key: &PublicKey,
+        script: u8,
+        keys: &[(&str, u32)],
+    ) -> Result<ProtocolScript, ProtocolBuilderError> {
+        let mut leaf = ProtocolScript::new(
+            ScriptBuf::from(vec![0x51 + script]),
+            public_key,
+            SignMode::Single,
+        );
+        for (position, (name, derivation_index)) in keys.iter().enumerate() {
+            leaf.add_key(name, *derivation_index, winternitz(), position as u32)?;
+        }
+        Ok(leaf)
+    }
+
+    #[test]
+    fn test_duplicate_script_key() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_duplicate_script_key").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let mut script = leaf(&public_key, 0, &[("value", 3)])?;
+
+        assert!(matches!(
+            script.add_key("value", 4, winternitz(), 1),
+            Err(ScriptError::DuplicateScriptKey(name)) if name == "value"
+        ));
+        assert_eq!(script.get_key("value").unwrap().derivation_index(), 3);
+
+        let replaced = script.add_or_replace_key("value", 4, winternitz(), 0)?;
+        assert_eq!(replaced.unwrap().derivation_index(), 3);
+        assert_eq!(script.get_key("value").unwrap().derivation_index(), 4);
+        assert!(script
+            .add_or_replace_key("other", 5, winternitz(), 1)?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stage_selection_keys() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_stage_selection_keys").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let interval_keys = (0..2)
+            .map(|index| {
+                tc.key_manager()
+                    .derive_winternitz(4, WinternitzType::HASH160, index)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let bob = tc
+            .key_manager()
+            .derive_winternitz(1, WinternitzType::HASH160, 2)?;
+        let alice = tc
+            .key_manager()
+            .derive_winternitz(1, WinternitzType::HASH160, 3)?;
+
+        let script = scripts::stage_from_3_and_upward(
+            3,
+            &public_key,
+            &interval_keys,
+            &bob,
+            &alice,
+            SignMode::Single,
+        )?;
+
+        // Both selection keys are kept, bob's is checked first
+        let keys = script.get_keys();
+        assert_eq!(keys.len(), 4);
+        assert_eq!(keys[2].name(), "selection_3_bob");
+        assert_eq!(keys[2].derivation_index(), 2);
+        assert_eq!(keys[3].name(), "selection_3_alice");
+        assert_eq!(keys[3].key_position(), 3);
+        assert!(keys[2].fingerprint().is_some());
+
+        // Keys of each party derived with the same index are not the same key
+        let alice = tc
+            .key_manager()
+            .derive_winternitz(2, WinternitzType::HASH160, 2)?;
+        let script = scripts::stage_from_3_and_upward(
+            3,
+            &public_key,
+            &interval_keys,
+            &bob,
+            &alice,
+            SignMode::Single,
+        )?;
+        let keys = script.get_keys();
+        assert_eq!(keys[2].derivation_index(), keys[3].derivation_index());
+        assert_ne!(keys[2].fingerprint(), keys[3].fingerprint());
+
+        let mut protocol = Protocol::new("selection");
+        protocol.add_transaction("A")?;
+        protocol
+            .add_transaction_output("A", &OutputType::taproot(1000, &public_key, &[script])?)?;
+        protocol.check_winternitz_key_reuse()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_winternitz_key_reuse() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_winternitz_key_reuse").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let mut protocol = Protocol::new("key_reuse");
+        protocol.add_transaction("A")?.add_transaction("B")?;
+
+        // The same key commits to the same value in two leaves of the same output
+        let leaves = [
+            leaf(&public_key, 0, &[("value", 1), ("other", 2)])?,
+            leaf(&public_key, 1, &[("value", 1)])?,
+        ];
+        protocol.add_transaction_output("A", &OutputType::taproot(1000, &public_key, &leaves)?)?;
+        protocol.check_winternitz_key_reuse()?;
+
+        // A different value signed with the same one-time key
+        let reused = [leaf(&public_key, 2, &[("challenge", 2)])?];
+        protocol.add_transaction_output("B", &OutputType::taproot(1000, &public_key, &reused)?)?;
+        assert!(matches!(
+            protocol.check_winternitz_key_reuse(),
+            Err(ProtocolBuilderError::WinternitzKeyReuse(2, first, second))
+                if first == "key other of leaf 0 of output 0 of A"
+                    && second == "key challenge of leaf 0 of output 0 of B"
+        ));
+
+        // The same value name in another output is spent by another transaction
+        let mut protocol = Protocol::new("output_reuse");
+        protocol.add_transaction("A")?.add_transaction("B")?;
+        protocol.add_transaction_output("A", &OutputType::taproot(1000, &public_key, &leaves)?)?;
+        protocol
+            .add_transaction_output("B", &OutputType::taproot(1000, &public_key, &leaves[1..])?)?;
+        assert!(matches!(
+            protocol.check_winternitz_key_reuse(),
+            Err(ProtocolBuilderError::WinternitzKeyReuse(1, first, second))
+                if first == "key value of leaf 0 of output 0 of A"
+                    && second == "key value of leaf 0 of output 0 of B"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_winternitz_key_reuse_across_rounds() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_winternitz_key_reuse_across_rounds").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let builder = ProtocolBuilder {};
+        let spend_mode = SpendMode::ScriptsOnly;
+
+        // Every round commits to the value with the same keys
+        let leaves = [leaf(&public_key, 0, &[("value", 1)])?];
+        let mut protocol = Protocol::new("rounds");
+        builder.connect_taproot_rounds(
+            &mut protocol,
+            "rounds",
+            2,
+            "B",
+            "C",
+            1000,
+            &public_key,
+            &leaves,
+            &leaves,
+            &spend_mode,
+            &tc.tr_sighash_type(),
+        )?;
+        assert!(matches!(
+            protocol.check_winternitz_key_reuse(),
+            Err(ProtocolBuilderError::WinternitzKeyReuse(1, first, second))
+                if first == "key value of leaf 0 of output 0 of B_0"
+                    && second == "key value of leaf 0 of output 0 of C_0"
+        ));
+
+        // Each round with its own keys
+        let mut protocol = Protocol::new("rounds");
+        builder.connect_taproot_rounds_with(
+            &mut protocol,
+            "rounds",
+            2,
+            "B",
+            "C",
+            1000,
+            &public_key,
+            |round| {
+                (
+                    vec![leaf(&public_key, 0, &[("value", round * 2)]).unwrap()],
+                    vec![leaf(&public_key, 0, &[("value", round * 2 + 1)]).unwrap()],
+                )
+            },
+            &spend_mode,
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.check_winternitz_key_reuse()?;
+
+        Ok(())
+    }
+}
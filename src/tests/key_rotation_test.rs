@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        tests::utils::{checksig_leaf_with_signature, TestContext},
+        types::{input::SpendMode, output::OutputType},
+    };
+
+    #[test]
+    fn test_resign_with_rotated_keys() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_resign_with_rotated_keys").unwrap();
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let old_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let new_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+
+        // EXT -> A -> B -> C, only the output of A uses the rotated key
+        let mut protocol = Protocol::new("rotation");
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &funding_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            8_000,
+            &old_key,
+            &[checksig_leaf_with_signature(&old_key)],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "B_C",
+            "B",
+            6_000,
+            &funding_key,
+            &[checksig_leaf_with_signature(&funding_key)],
+            &SpendMode::ScriptsOnly,
+            "C",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        let old_b = protocol.transaction_by_name("B")?.clone();
+        let old_c = protocol.transaction_by_name("C")?.clone();
+
+        let mapping = HashMap::from([(old_key, new_key)]);
+        let rebuilt = protocol.resign_with(tc.key_manager(), &mapping, "")?;
+        assert_eq!(rebuilt, vec!["A", "B", "C"]);
+
+        let expected =
+            OutputType::taproot(8_000, &new_key, &[checksig_leaf_with_signature(&new_key)])?;
+        let a = protocol.transaction_by_name("A")?.clone();
+        assert_eq!(&a.output[0].script_pubkey, expected.get_script_pubkey());
+
+        // Txids cascade downstream
+        let b = protocol.transaction_by_name("B")?;
+        assert_eq!(b.input[0].previous_output.txid, a.compute_txid());
+        assert_ne!(b.compute_txid(), old_b.compute_txid());
+        let c = protocol.transaction_by_name("C")?;
+        assert_eq!(c.input[0].previous_output.txid, b.compute_txid());
+        assert_ne!(c.compute_txid(), old_c.compute_txid());
+
+        assert!(protocol
+            .input_taproot_script_spend_signature("B", 0, 0)?
+            .is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_script_keys() {
+        let tc = TestContext::new("test_replace_script_keys").unwrap();
+        let old_key = tc
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2tr, 0)
+            .unwrap();
+        let new_key = tc
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2tr, 1)
+            .unwrap();
+
+        let mut leaf = checksig_leaf_with_signature(&old_key);
+        assert!(!leaf.replace_keys(&HashMap::new()));
+        assert!(leaf.replace_keys(&HashMap::from([(old_key, new_key)])));
+        assert_eq!(
+            leaf.get_script(),
+            checksig_leaf_with_signature(&new_key).get_script()
+        );
+        assert_eq!(leaf.get_verifying_key(), Some(new_key));
+    }
+}
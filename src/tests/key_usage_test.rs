@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use bitcoin::{PublicKey, ScriptBuf};
+    use key_manager::{key_type::BitcoinKeyType, winternitz::WinternitzType};
+
+    use crate::{
+        builder::{Protocol, ProtocolRegistry},
+        errors::ProtocolBuilderError,
+        scripts::{KeyType, ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{key_usage::KeyUsageReport, output::OutputType},
+    };
+
+    // Leaf committing to a value with a Winternitz key
+    fn leaf(
+        public_key: &PublicKey,
+        script: u8,
+        name: &str,
+        derivation_index: u32,
+    ) -> Result<ProtocolScript, ProtocolBuilderError> {
+        let mut leaf = ProtocolScript::new(
+            ScriptBuf::from(vec![0x51 + script]),
+            public_key,
+            SignMode::Single,
+        );
+        leaf.add_key(
+            name,
+            derivation_index,
+            KeyType::WinternitzKey {
+                key_type: WinternitzType::HASH160,
+                message_size: 4,
+            },
+            0,
+        )?;
+        Ok(leaf)
+    }
+
+    // A commits to a value in two leaves and to a challenge with the given index
+    fn protocol(
+        public_key: &PublicKey,
+        name: &str,
+        challenge_index: u32,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new(name);
+        protocol.add_transaction("A")?;
+
+        let leaves = [
+            leaf(public_key, 0, "value", 1)?,
+            leaf(public_key, 1, "value", 1)?,
+            leaf(public_key, 2, "challenge", challenge_index)?,
+        ];
+        protocol.add_transaction_output("A", &OutputType::taproot(1000, public_key, &leaves)?)?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_key_usage() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_key_usage").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let first = protocol(&public_key, "first", 2)?;
+
+        let report = first.key_usage();
+        assert_eq!(report.derivation_indexes(), vec![1, 2]);
+        let usages = report.usages(1);
+        assert_eq!(usages.len(), 2);
+        assert_eq!(usages[1].leaf_index, Some(1));
+        assert_eq!(
+            usages[1].to_string(),
+            "key value of leaf 1 of output 0 of A"
+        );
+        assert!(report.usages(3).is_empty());
+        assert!(report.is_safe());
+
+        // The challenge is signed with the key of the value
+        let reused = protocol(&public_key, "reused", 1)?;
+        let report = reused.key_usage();
+        assert_eq!(report.reuses().len(), 1);
+        assert_eq!(report.reuses()[0].derivation_index, 1);
+        assert_eq!(report.reuses()[0].usages.len(), 3);
+        assert!(report.to_string().contains("1: REUSED\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_usage_across_protocols() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_key_usage_across_protocols").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let first = protocol(&public_key, "first", 2)?;
+        let second = protocol(&public_key, "second", 3)?;
+
+        // Each instance is safe on its own, but both sign their value with index 1
+        assert!(first.key_usage().is_safe() && second.key_usage().is_safe());
+        let report = KeyUsageReport::from_protocols([&first, &second]);
+        let reuses = report.reuses();
+        assert_eq!(reuses.len(), 1);
+        assert_eq!(reuses[0].derivation_index, 1);
+        assert_eq!(report.usages(2)[0].protocol_name, "first");
+        assert_eq!(report.usages(3)[0].protocol_name, "second");
+
+        let storage = Rc::new(tc.new_storage("key_usage"));
+        let registry = ProtocolRegistry::new(storage);
+        registry.save(&first)?;
+        registry.save(&second)?;
+        assert_eq!(registry.key_usage()?, report);
+
+        Ok(())
+    }
+}
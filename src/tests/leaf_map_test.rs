@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        opcodes::all::OP_CHECKSIG,
+        script::Builder,
+        secp256k1,
+        taproot::{ControlBlock, LeafVersion},
+        PublicKey, ScriptBuf, TapLeafHash, XOnlyPublicKey,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode, TapTreeLayout},
+        tests::utils::TestContext,
+        types::{input::SpendMode, output::OutputType},
+    };
+
+    fn leaves(public_key: &PublicKey) -> Vec<ProtocolScript> {
+        (0..3)
+            .map(|tag| {
+                let script = Builder::new()
+                    .push_int(tag)
+                    .push_x_only_key(&XOnlyPublicKey::from(*public_key))
+                    .push_opcode(OP_CHECKSIG)
+                    .into_script();
+                ProtocolScript::new(script, public_key, SignMode::Single)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_leaf_index_of() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_leaf_index_of").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaves = leaves(&public_key);
+
+        // Leaf indexes follow the order of the leaves, whatever the shape of the tree
+        let output = OutputType::taproot_with_layout(
+            1_000,
+            &public_key,
+            &leaves,
+            &TapTreeLayout::Weights(vec![1, 1, 10]),
+        )?;
+        for (index, leaf) in leaves.iter().enumerate() {
+            assert_eq!(output.leaf_index_of(leaf.get_script()), Some(index));
+        }
+        assert_eq!(output.leaf_index_of(&ScriptBuf::new()), None);
+
+        let segwit = OutputType::segwit_script(1_000, &leaves[1])?;
+        assert_eq!(segwit.leaf_index_of(leaves[1].get_script()), Some(0));
+        assert_eq!(segwit.leaf_index_of(leaves[0].get_script()), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_leaf_map() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_leaf_map").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let mut leaves = leaves(&public_key);
+        leaves[2].set_assert_leaf_id(7);
+
+        let mut protocol = Protocol::new("leaf_map");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::taproot(10_000, &public_key, &leaves)?,
+            "A",
+            SpendMode::ScriptsOnly,
+        )?;
+        protocol.build(tc.key_manager(), "")?;
+
+        let output = protocol.get_script_from_output("EXT", 0)?.0.clone();
+        let output_key = output
+            .get_taproot_spend_info()?
+            .unwrap()
+            .output_key()
+            .to_x_only_public_key();
+
+        let map = protocol.leaf_map("EXT", 0)?;
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec!["L0", "L1", "L2"]);
+
+        let secp = secp256k1::Secp256k1::new();
+        for (index, leaf) in leaves.iter().enumerate() {
+            let info = &map[&format!("L{}", index)];
+            assert_eq!(info.index, index);
+            assert_eq!(
+                info.leaf_hash,
+                TapLeafHash::from_script(leaf.get_script(), LeafVersion::TapScript)
+            );
+
+            let control_block = ControlBlock::decode(&info.control_block).unwrap();
+            assert!(control_block.verify_taproot_commitment(&secp, output_key, leaf.get_script()));
+        }
+
+        // The asserted leaf id is tracked by the script
+        assert_eq!(map["L0"].assert_leaf_id, None);
+        assert_eq!(map["L2"].assert_leaf_id, Some(7));
+
+        assert!(matches!(
+            protocol.leaf_map("EXT", 3),
+            Err(ProtocolBuilderError::MissingOutput(_, 3))
+        ));
+
+        Ok(())
+    }
+}
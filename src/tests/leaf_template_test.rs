@@ -0,0 +1,167 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        opcodes::all::{OP_CHECKSIG, OP_DROP},
+        script::Builder,
+        PublicKey, XOnlyPublicKey,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode, StackItem},
+        tests::utils::{checksig_leaf_with_signature, TestContext},
+        types::{
+            input::{InputArgs, SpendMode},
+            leaf_template::TemplateManifest,
+            output::OutputType,
+            serialization::SerializationFormat,
+        },
+    };
+
+    // Same key and label, but the script also expects (and drops) an extra witness item
+    fn checksig_with_extra_item(public_key: &PublicKey) -> ProtocolScript {
+        let script = Builder::new()
+            .push_opcode(OP_DROP)
+            .push_x_only_key(&XOnlyPublicKey::from(*public_key))
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+
+        let mut leaf = ProtocolScript::new(script, public_key, SignMode::Single);
+        leaf.add_stack_item(StackItem::new_schnorr_sig(false));
+        leaf.add_stack_item(StackItem::new_raw(32));
+        leaf
+    }
+
+    // EXT -> A -> B, the output of A has a leaf for each party
+    fn protocol(
+        tc: &TestContext,
+        alice: &PublicKey,
+        bob_leaf: ProtocolScript,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("templates");
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &funding_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            8_000,
+            alice,
+            &[checksig_leaf_with_signature(alice), bob_leaf],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.build(tc.key_manager(), "")?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_exchange_leaf_templates() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_exchange_leaf_templates").unwrap();
+        let alice = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let bob = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+
+        let ours = protocol(&tc, &alice, checksig_leaf_with_signature(&bob))?;
+        let manifest = ours.leaf_templates();
+        assert_eq!(manifest.len(), 2);
+        assert!(manifest.get("A", 0, 1).is_some());
+        assert!(manifest.get("EXT", 0, 0).is_none());
+
+        let bytes = manifest.to_bytes(SerializationFormat::Cbor)?;
+        let received = TemplateManifest::from_bytes(&bytes)?;
+        assert_eq!(received, manifest);
+
+        let mut theirs = protocol(&tc, &alice, checksig_leaf_with_signature(&bob))?;
+        theirs.verify_leaf_templates(&received)?;
+        theirs.agree_leaf_templates(received)?;
+        assert_eq!(theirs.agreed_templates(), Some(&manifest));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_substituted_leaf() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_reject_substituted_leaf").unwrap();
+        let alice = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let bob = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+
+        let manifest = protocol(&tc, &alice, checksig_leaf_with_signature(&bob))?.leaf_templates();
+        let mut tampered = protocol(&tc, &alice, checksig_with_extra_item(&bob))?;
+
+        assert!(matches!(
+            tampered.agree_leaf_templates(manifest.clone()),
+            Err(ProtocolBuilderError::LeafTemplateMismatch(name, 0, 1)) if name == "A"
+        ));
+        assert!(tampered.agreed_templates().is_none());
+
+        // Only the witness template differs
+        let mut extra_item = checksig_leaf_with_signature(&bob);
+        extra_item.add_stack_item(StackItem::new_raw(32));
+        assert_ne!(
+            extra_item.template_hash(),
+            checksig_leaf_with_signature(&bob).template_hash()
+        );
+        assert_eq!(
+            checksig_leaf_with_signature(&bob).template_hash(),
+            checksig_leaf_with_signature(&bob).template_hash()
+        );
+
+        let mut missing = manifest.clone();
+        missing.leaves.pop();
+        let honest = protocol(&tc, &alice, checksig_leaf_with_signature(&bob))?;
+        assert!(matches!(
+            honest.verify_leaf_templates(&missing),
+            Err(ProtocolBuilderError::LeafTemplateMismatch(_, 0, 1))
+        ));
+
+        let mut renamed = manifest;
+        renamed.protocol_name = "other".to_string();
+        assert!(matches!(
+            honest.verify_leaf_templates(&renamed),
+            Err(ProtocolBuilderError::TemplateManifestMismatch(_, _))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_templates_before_broadcast() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_check_templates_before_broadcast").unwrap();
+        let alice = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let bob = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+
+        let mut protocol = protocol(&tc, &alice, checksig_leaf_with_signature(&bob))?;
+        let manifest = protocol.leaf_templates();
+        protocol.agree_leaf_templates(manifest)?;
+
+        let tampered = OutputType::taproot(
+            8_000,
+            &alice,
+            &[
+                checksig_leaf_with_signature(&alice),
+                checksig_with_extra_item(&bob),
+            ],
+        )?;
+        protocol.update_output("A", 0, &tampered)?;
+        protocol.build(tc.key_manager(), "")?;
+
+        let args = [InputArgs::new_taproot_script_args(1)];
+        assert!(matches!(
+            protocol.transaction_to_send("B", &args),
+            Err(ProtocolBuilderError::LeafTemplateMismatch(name, 0, 1)) if name == "A"
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{opcodes::all as opcodes, script::Builder, PublicKey, ScriptBuf};
+    use key_manager::{key_type::BitcoinKeyType, winternitz::WinternitzType};
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        helpers::malleability::{
+            leaf_malleability, winternitz_leaf_issues, LeafMalleability, WinternitzMalleability,
+        },
+        scripts::{KeyType, ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::output::OutputType,
+    };
+
+    fn key_type() -> KeyType {
+        KeyType::WinternitzKey {
+            key_type: WinternitzType::HASH160,
+            message_size: 4,
+        }
+    }
+
+    fn winternitz_leaf(public_key: &PublicKey, check_signature: bool) -> ProtocolScript {
+        let mut builder = Builder::new();
+        if check_signature {
+            builder = builder
+                .push_x_only_key(&public_key.inner.x_only_public_key().0)
+                .push_opcode(opcodes::OP_CHECKSIGVERIFY);
+        }
+        let script = builder.push_opcode(opcodes::OP_PUSHNUM_1).into_script();
+
+        let mut leaf = ProtocolScript::new(script, public_key, SignMode::Single);
+        leaf.add_key("value", 0, key_type(), 0).unwrap();
+        leaf
+    }
+
+    #[test]
+    fn test_winternitz_leaf_issues() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_winternitz_leaf_issues").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let leaf = winternitz_leaf(&public_key, true);
+        assert_eq!(
+            winternitz_leaf_issues(&leaf)?,
+            vec![WinternitzMalleability::NonCanonicalDigits]
+        );
+
+        let leaf = winternitz_leaf(&public_key, false);
+        assert_eq!(
+            winternitz_leaf_issues(&leaf)?,
+            vec![
+                WinternitzMalleability::NonCanonicalDigits,
+                WinternitzMalleability::UncommittedTransaction
+            ]
+        );
+
+        let leaf = ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Single);
+        assert!(winternitz_leaf_issues(&leaf)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_winternitz_digits() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_canonical_winternitz_digits").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let original = winternitz_leaf(&public_key, true);
+        let mut leaf = original.clone();
+        leaf.set_canonical_winternitz_digits(1)?;
+
+        assert!(leaf.has_canonical_winternitz_digits()?);
+        assert!(winternitz_leaf_issues(&leaf)?.is_empty());
+        assert!(leaf
+            .get_script()
+            .as_bytes()
+            .ends_with(original.get_script().as_bytes()));
+
+        // The first check picks the digit right below the schnorr signature
+        let prefix = Builder::new()
+            .push_int(1)
+            .push_opcode(opcodes::OP_PICK)
+            .push_opcode(opcodes::OP_SIZE)
+            .push_opcode(opcodes::OP_SWAP)
+            .push_opcode(opcodes::OP_0NOTEQUAL)
+            .push_opcode(opcodes::OP_EQUALVERIFY)
+            .into_bytes();
+        assert!(leaf.get_script().as_bytes().starts_with(&prefix));
+
+        let digits = key_type().winternitz_digits()?;
+        assert_eq!(
+            leaf.get_script().len(),
+            original.get_script().len() + prefix.len() * digits
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_malleability_report() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_protocol_malleability_report").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = Protocol::new("malleability");
+        protocol.add_transaction_output(
+            "commit",
+            &OutputType::taproot(
+                1000,
+                &public_key,
+                &[
+                    ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Single),
+                    winternitz_leaf(&public_key, true),
+                ],
+            )?,
+        )?;
+
+        let report = protocol.winternitz_malleability_report(None)?;
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].transaction_name, "commit");
+        assert_eq!(report[0].output_index, 0);
+        assert_eq!(report[0].leaf_index, 1);
+        assert!(report[0].hardened.is_none());
+
+        let report = protocol.winternitz_malleability_report(Some(1))?;
+        let hardened = report[0].hardened.as_ref().unwrap();
+        assert!(winternitz_leaf_issues(hardened)?.is_empty());
+
+        Ok(())
+    }
+
+    fn leaf(public_key: &PublicKey, build: impl Fn(Builder) -> Builder) -> ProtocolScript {
+        let script = build(Builder::new()).into_script();
+        ProtocolScript::new(script, public_key, SignMode::Single)
+    }
+
+    fn checksig(builder: Builder, public_key: &PublicKey) -> Builder {
+        builder
+            .push_x_only_key(&public_key.inner.x_only_public_key().0)
+            .push_opcode(opcodes::OP_CHECKSIG)
+    }
+
+    #[test]
+    fn test_leaf_malleability() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_leaf_malleability").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let signed = leaf(&public_key, |builder| checksig(builder, &public_key));
+        assert!(leaf_malleability(&signed, false)?.is_empty());
+
+        // The top witness item is dropped, the signature below it is checked
+        let dropped = leaf(&public_key, |builder| {
+            checksig(builder.push_opcode(opcodes::OP_DROP), &public_key)
+        });
+        assert_eq!(
+            leaf_malleability(&dropped, false)?,
+            vec![LeafMalleability::UnconstrainedStackItems(vec![0])]
+        );
+
+        let preimage = leaf(&public_key, |builder| {
+            builder
+                .push_opcode(opcodes::OP_SHA256)
+                .push_slice([0; 32])
+                .push_opcode(opcodes::OP_EQUAL)
+        });
+        assert_eq!(
+            leaf_malleability(&preimage, false)?,
+            vec![LeafMalleability::UncommittedTransaction]
+        );
+
+        let unclean = leaf(&public_key, |builder| {
+            let builder = checksig(builder, &public_key).push_opcode(opcodes::OP_TOALTSTACK);
+            checksig(builder, &public_key).push_opcode(opcodes::OP_FROMALTSTACK)
+        });
+        assert_eq!(
+            leaf_malleability(&unclean, false)?,
+            vec![LeafMalleability::UncleanStack(2)]
+        );
+
+        // MINIMALIF is only a consensus rule for tapscript
+        let branch = leaf(&public_key, |builder| {
+            let builder = builder.push_opcode(opcodes::OP_IF);
+            checksig(builder, &public_key)
+                .push_opcode(opcodes::OP_ELSE)
+                .push_opcode(opcodes::OP_PUSHNUM_1)
+                .push_opcode(opcodes::OP_ENDIF)
+        });
+        assert!(leaf_malleability(&branch, false)?.is_empty());
+        assert_eq!(
+            leaf_malleability(&branch, true)?,
+            vec![LeafMalleability::NonMinimalIf]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_malleability_audit() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_protocol_malleability_audit").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let signed = leaf(&public_key, |builder| checksig(builder, &public_key));
+        let dropped = leaf(&public_key, |builder| {
+            checksig(builder.push_opcode(opcodes::OP_2DROP), &public_key)
+        });
+
+        let mut protocol = Protocol::new("malleability_audit");
+        protocol.add_transaction_output(
+            "commit",
+            &OutputType::taproot(1000, &public_key, &[signed.clone(), dropped])?,
+        )?;
+        protocol.add_transaction_output("commit", &OutputType::segwit_script(1000, &signed)?)?;
+        protocol.add_transaction_output(
+            "commit",
+            &OutputType::taproot(1000, &public_key, &[winternitz_leaf(&public_key, false)])?,
+        )?;
+
+        let report = protocol.audit_malleability()?;
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].transaction_name, "commit");
+        assert_eq!((report[0].output_index, report[0].leaf_index), (0, 1));
+        assert_eq!(
+            report[0].issues,
+            vec![LeafMalleability::UnconstrainedStackItems(vec![0, 1])]
+        );
+        assert_eq!((report[1].output_index, report[1].leaf_index), (2, 0));
+        assert_eq!(
+            report[1].issues,
+            vec![
+                LeafMalleability::UncommittedTransaction,
+                LeafMalleability::NonCanonicalDigits
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -1,10 +1,74 @@
+pub mod amount_conservation_test;
+pub mod anyprevout_test;
+pub mod arbitrary_protocol_test;
+pub mod async_signing_test;
+pub mod broadcast_queue_test;
+pub mod broadcast_rules_test;
+pub mod build_timings_test;
 pub mod builder_connection_test;
 pub mod builder_outputs_test;
 pub mod builder_persistance_test;
+pub mod chain_time_test;
+pub mod commitment_test;
+pub mod conflicting_spends_test;
+pub mod deterministic_build_test;
+pub mod duplicate_subtree_test;
+pub mod execution_plan_test;
+pub mod execution_trace_test;
+pub mod explorer_test;
+pub mod external_tx_test;
+pub mod finalize_test;
+pub mod freeze_test;
+pub mod funding_test;
 pub mod graph_test;
+pub mod incremental_rebuild_test;
 pub mod input_test;
+pub mod inspect_test;
+pub mod key_reuse_test;
+pub mod key_rotation_test;
+pub mod key_usage_test;
+pub mod leaf_map_test;
+pub mod leaf_template_test;
+pub mod malleability_test;
+pub mod multisig_test;
+pub mod nary_rounds_test;
+pub mod nonce_bundle_test;
+pub mod offline_signing_test;
 pub mod ots_checksig;
 pub mod output_test;
+pub mod ownership_test;
+pub mod package_limits_test;
+pub mod parallel_verify_test;
+pub mod protocol_constants_test;
+pub mod protocol_diff_test;
+pub mod protocol_events_test;
+pub mod protocol_history_test;
+pub mod protocol_limits_test;
+pub mod protocol_merge_test;
+pub mod protocol_registry_test;
+pub mod protocol_runner_test;
+pub mod protocol_spec_test;
+pub mod protocol_template_test;
+pub mod protocol_view_test;
+pub mod regtest_test;
+pub mod replaceability_test;
+pub mod sighash_test;
+pub mod signature_bundle_test;
+pub mod signature_verification_test;
+pub mod signer_test;
+pub mod signing_scheduler_test;
 pub mod single_scripts_test;
+pub mod skeleton_test;
+pub mod snapshot_test;
+pub mod speedup_data_test;
+pub mod speedup_fee_test;
+pub mod spend_info_cache_test;
+pub mod test_context_test;
+pub mod trace_step_test;
+pub mod unspendable_test;
 pub mod utils;
+pub mod validation_test;
 pub mod weight_computing_test;
+pub mod winternitz_args_test;
+pub mod witness_decoder_test;
+pub mod witness_preview_test;
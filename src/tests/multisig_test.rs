@@ -0,0 +1,171 @@
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bitcoin::{
+        key::Keypair,
+        secp256k1::{Secp256k1, SecretKey},
+        PublicKey,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::{ProtocolBuilderError, ScriptError},
+        scripts::{checksigadd_multisig, SignMode},
+        tests::utils::TestContext,
+        types::{
+            input::SpendMode, output::OutputType, sequence::SequencePolicy,
+            witness_args::WitnessArgs,
+        },
+    };
+
+    // Key of another party, not held by the test key manager
+    fn other_party_key() -> PublicKey {
+        PublicKey::from_str("02c6047f9441ed7d6d3045406e95c07cd85a6a6d4c90d35b8c6a568f07cfd511fd")
+            .unwrap()
+    }
+
+    // Keys of another party that signs its own slots
+    fn other_party_keypair() -> Keypair {
+        let mut secret = [0; 32];
+        secret[31] = 2;
+        Keypair::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&secret).unwrap())
+    }
+
+    #[test]
+    fn test_multisig_leaf() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_multisig_leaf").unwrap();
+        let keys = [
+            tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?,
+            tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?,
+            other_party_key(),
+        ];
+
+        let leaf = checksigadd_multisig(&keys, 2, SignMode::Single)?;
+        assert!(leaf.is_multisig());
+        assert_eq!(leaf.get_signers(), keys);
+        assert_eq!(leaf.get_verifying_key(), Some(keys[0]));
+        assert_eq!(leaf.stack_items().len(), 3);
+
+        for threshold in [0, 4] {
+            assert!(matches!(
+                checksigadd_multisig(&keys, threshold, SignMode::Single),
+                Err(ScriptError::InvalidMultisigThreshold(t, 3)) if t == threshold
+            ));
+        }
+        assert!(matches!(
+            checksigadd_multisig(&[], 1, SignMode::Single),
+            Err(ScriptError::InvalidMultisigThreshold(1, 0))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multisig_signatures() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_multisig_signatures").unwrap();
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let keys = [
+            tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?,
+            other_party_key(),
+            tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?,
+        ];
+        let leaf = checksigadd_multisig(&keys, 2, SignMode::Single)?;
+
+        let mut protocol = Protocol::new("multisig");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::taproot(10_000, &internal_key, &[leaf])?,
+            "A",
+            SpendMode::ScriptsOnly,
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        // Every slot held by the key manager is signed
+        let signatures = protocol.input_multisig_signatures("A", 0, 0)?;
+        assert_eq!(signatures.len(), 3);
+        assert!(signatures[0].is_some() && signatures[2].is_some());
+        assert!(signatures[1].is_none());
+        assert!(protocol
+            .input_taproot_script_spend_signature("A", 0, 0)?
+            .is_some());
+
+        // The first key is checked first, so its signature goes on top
+        let args = protocol.input_args_for("A", Some(0), &WitnessArgs::new(), tc.key_manager())?;
+        assert_eq!(
+            args[0].iter().cloned().collect::<Vec<_>>(),
+            vec![
+                signatures[2].unwrap().to_vec(),
+                vec![],
+                signatures[0].unwrap().to_vec(),
+            ]
+        );
+
+        // Multisig slots are discarded when the input changes after unfreezing
+        protocol.freeze();
+        protocol.unfreeze("rotate keys")?;
+        protocol.set_sequence_policy("A", SequencePolicy::Final)?;
+        protocol.build(tc.key_manager(), "")?;
+        assert!(protocol
+            .input_multisig_signatures("A", 0, 0)?
+            .iter()
+            .all(|signature| signature.is_none()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_multisig_signature() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_import_multisig_signature").unwrap();
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let keys = [
+            PublicKey::new(other_party_keypair().public_key()),
+            tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?,
+        ];
+        let leaf = checksigadd_multisig(&keys, 2, SignMode::Single)?;
+
+        let mut protocol = Protocol::new("multisig_import");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::taproot(10_000, &internal_key, &[leaf])?,
+            "A",
+            SpendMode::ScriptsOnly,
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        // The first slot belongs to the other party, so the leaf has no signature yet
+        let held = protocol.input_multisig_signatures("A", 0, 0)?[1].unwrap();
+        assert!(protocol.input_multisig_signatures("A", 0, 0)?[0].is_none());
+        assert!(protocol
+            .input_taproot_script_spend_signature("A", 0, 0)?
+            .is_none());
+
+        let message = protocol.get_hashed_message("A", 0, 0)?.unwrap();
+        let signature = bitcoin::taproot::Signature {
+            signature: Secp256k1::new().sign_schnorr(&message, &other_party_keypair()),
+            sighash_type: held.sighash_type,
+        };
+
+        // A signature is only accepted for the slot of the key that made it
+        assert!(matches!(
+            protocol.import_multisig_signature("A", 0, 0, 1, signature),
+            Err(ProtocolBuilderError::InvalidMultisigSlotSignature(0, 1))
+        ));
+        assert!(protocol
+            .import_multisig_signature("A", 0, 0, 2, signature)
+            .is_err());
+
+        protocol.import_multisig_signature("A", 0, 0, 0, signature)?;
+        assert_eq!(
+            protocol.input_multisig_signatures("A", 0, 0)?,
+            vec![Some(signature), Some(held)]
+        );
+        assert_eq!(
+            protocol.input_taproot_script_spend_signature("A", 0, 0)?,
+            Some(signature)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{PublicKey, ScriptBuf};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{input::SpendMode, nary_rounds::NaryRounds, output::OutputType},
+    };
+
+    // A leaf per branch, committing to the round of the output.
+    fn branch_leaves(public_key: &PublicKey, path: &[usize], arity: usize) -> Vec<ProtocolScript> {
+        (0..arity)
+            .map(|branch| {
+                ProtocolScript::new(
+                    ScriptBuf::from(vec![0x51 + path.len() as u8, 0x51 + branch as u8]),
+                    public_key,
+                    SignMode::Single,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_nary_rounds() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_nary_rounds").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let mut protocol = Protocol::new("nary_rounds");
+
+        let rounds = ProtocolBuilder {}.connect_nary_rounds(
+            &mut protocol,
+            "search",
+            "start",
+            3,
+            2,
+            1000,
+            &public_key,
+            |path| Ok(branch_leaves(&public_key, path, 3)),
+            &tc.tr_sighash_type(),
+        )?;
+
+        assert_eq!(rounds.len(), 3 + 9);
+        assert_eq!(rounds.root(), "start");
+        assert_eq!(
+            rounds.children(&[]),
+            vec!["search_0", "search_1", "search_2"]
+        );
+        assert_eq!(rounds.transaction(&[2, 1]), Some("search_2_1"));
+        assert_eq!(rounds.path("search_2_1"), Some([2, 1].as_slice()));
+        assert_eq!(rounds.round(2).len(), 9);
+        assert!(rounds.children(&[2, 1]).is_empty());
+        assert_eq!(
+            NaryRounds::transaction_name("search", &[1, 0]),
+            "search_1_0"
+        );
+
+        // Every branch spends its own leaf of the single output of its parent
+        assert_eq!(protocol.transaction_by_name("search_1")?.output.len(), 1);
+        assert_eq!(
+            protocol.graph().spenders("search_1", 0)?,
+            rounds.children(&[1])
+        );
+        for branch in 0..3 {
+            let inputs = protocol.inputs(&format!("search_1_{}", branch))?;
+            assert!(matches!(
+                inputs[0].spend_mode(),
+                SpendMode::Script { leaf } if *leaf == branch
+            ));
+        }
+
+        let output = OutputType::taproot(1000, &public_key, &branch_leaves(&public_key, &[1], 3))?;
+        assert_eq!(
+            &protocol.transaction_by_name("search_1")?.output[0].script_pubkey,
+            output.get_script_pubkey()
+        );
+        assert!(protocol
+            .transaction_by_name("search_1_1")?
+            .output
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nary_rounds_errors() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_nary_rounds_errors").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let mut protocol = Protocol::new("nary_rounds_errors");
+        let builder = ProtocolBuilder {};
+
+        assert!(matches!(
+            builder.connect_nary_rounds(
+                &mut protocol,
+                "search",
+                "start",
+                1,
+                2,
+                1000,
+                &public_key,
+                |path| Ok(branch_leaves(&public_key, path, 1)),
+                &tc.tr_sighash_type(),
+            ),
+            Err(ProtocolBuilderError::InvalidArity(1))
+        ));
+
+        assert!(matches!(
+            builder.connect_nary_rounds(
+                &mut protocol,
+                "search",
+                "start",
+                2,
+                2,
+                1000,
+                &public_key,
+                |path| Ok(branch_leaves(&public_key, path, 3)),
+                &tc.tr_sighash_type(),
+            ),
+            Err(ProtocolBuilderError::InvalidBranchLeaves(name, 2, 3)) if name == "start"
+        ));
+
+        Ok(())
+    }
+}
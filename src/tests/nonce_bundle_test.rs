@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::PublicKey;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{self, SignMode},
+        tests::utils::TestContext,
+        types::{input::SpendMode, nonces::NonceBundle, serialization::SerializationFormat},
+    };
+
+    // A -> B spending two leaves signed with the aggregated key
+    fn aggregated_protocol(
+        tc: &TestContext,
+        aggregated_key: &PublicKey,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("musig");
+        let leaves = [
+            scripts::check_signature(aggregated_key, SignMode::Aggregate),
+            scripts::timelock(10, aggregated_key, SignMode::Aggregate),
+        ];
+
+        ProtocolBuilder {}.add_taproot_connection(
+            &mut protocol,
+            "claim",
+            "A",
+            1000,
+            aggregated_key,
+            &leaves,
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.build(tc.key_manager(), "session")?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_exchange_nonce_bundles() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_exchange_nonce_bundles").unwrap();
+        let aggregated_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let participant = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let peer = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+        let protocol = aggregated_protocol(&tc, &aggregated_key)?;
+
+        let bundle = protocol.export_nonces(tc.key_manager(), "session", &participant)?;
+        assert_eq!(bundle.len(), 2);
+        assert!(bundle
+            .nonces
+            .iter()
+            .all(|nonce| nonce.aggregated_key == aggregated_key && nonce.nonce.len() == 66));
+
+        let bytes = bundle.to_bytes(SerializationFormat::Bincode)?;
+        let mut received = NonceBundle::from_bytes(&bytes)?;
+        assert_eq!(received, bundle);
+
+        received.participant = peer;
+        protocol.import_peer_nonces(tc.key_manager(), "session", &[received])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_invalid_nonce_bundles() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_reject_invalid_nonce_bundles").unwrap();
+        let aggregated_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let participant = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let protocol = aggregated_protocol(&tc, &aggregated_key)?;
+        let bundle = protocol.export_nonces(tc.key_manager(), "session", &participant)?;
+
+        let import = |bundle: NonceBundle| {
+            protocol.import_peer_nonces(tc.key_manager(), "session", &[bundle])
+        };
+
+        let mut other_session = bundle.clone();
+        other_session.id = "other".to_string();
+        assert!(matches!(
+            import(other_session),
+            Err(ProtocolBuilderError::NonceBundleMismatch(_, _))
+        ));
+
+        let mut incomplete = bundle.clone();
+        incomplete.nonces.pop();
+        assert!(matches!(
+            import(incomplete),
+            Err(ProtocolBuilderError::MissingNonce(_))
+        ));
+
+        let mut duplicated = bundle.clone();
+        duplicated.nonces.push(bundle.nonces[0].clone());
+        assert!(matches!(
+            import(duplicated),
+            Err(ProtocolBuilderError::UnexpectedNonce(_))
+        ));
+
+        let mut invalid = bundle.clone();
+        invalid.nonces[0].nonce.truncate(33);
+        assert!(matches!(
+            import(invalid),
+            Err(ProtocolBuilderError::InvalidNonce(_))
+        ));
+
+        Ok(())
+    }
+}
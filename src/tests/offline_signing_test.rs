@@ -0,0 +1,205 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{hashes::Hash, TapSighashType};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::SignMode,
+        tests::utils::{checksig_leaf, TestContext},
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::{SighashType, Signature, SpendMode},
+            output::OutputType,
+            serialization::SerializationFormat,
+            signing_request::{SigningKey, SigningPackage, SigningResponses},
+            Signer,
+        },
+    };
+
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let mut bump = checksig_leaf(&tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 3)?);
+        bump.set_sighash_type(TapSighashType::SinglePlusAnyoneCanPay);
+        let leaves = [
+            checksig_leaf(&tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?),
+            bump,
+        ];
+
+        let mut protocol = Protocol::new("offline_signing");
+        let builder = ProtocolBuilder {};
+        builder
+            .add_external_connection(
+                &mut protocol,
+                "EXT",
+                Hash::all_zeros(),
+                OutputSpec::Auto(OutputType::segwit_key(10_000, &funding_key)?),
+                "A",
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            )?
+            .add_taproot_connection(
+                &mut protocol,
+                "A_B",
+                "A",
+                9_000,
+                &internal_key,
+                &leaves,
+                &SpendMode::All {
+                    key_path_sign: SignMode::Single,
+                },
+                "B",
+                &tc.tr_sighash_type(),
+            )?;
+        protocol.add_transaction_output("B", &OutputType::segwit_key(8_000, &funding_key)?)?;
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    // Air-gapped signer, only given the serialized package and its keys
+    fn sign_offline(tc: &TestContext, package: &[u8]) -> Result<Vec<u8>, ProtocolBuilderError> {
+        let package = SigningPackage::from_bytes(package)?;
+        let mut responses = package.responses();
+
+        for request in package.requests.iter() {
+            let message = request.message();
+            let signature = match (&request.key, &request.sighash_type) {
+                (SigningKey::Schnorr(key), SighashType::Taproot(sighash_type)) => {
+                    Signature::Taproot(bitcoin::taproot::Signature {
+                        signature: tc.key_manager().sign_schnorr(&message, key)?,
+                        sighash_type: *sighash_type,
+                    })
+                }
+                (
+                    SigningKey::TweakedSchnorr {
+                        internal_key,
+                        merkle_root,
+                    },
+                    SighashType::Taproot(sighash_type),
+                ) => Signature::Taproot(bitcoin::taproot::Signature {
+                    signature: tc
+                        .key_manager()
+                        .sign_schnorr_with_tweak(&message, internal_key, *merkle_root)?
+                        .0,
+                    sighash_type: *sighash_type,
+                }),
+                (SigningKey::Ecdsa(key), SighashType::Ecdsa(sighash_type)) => {
+                    Signature::Ecdsa(bitcoin::ecdsa::Signature {
+                        signature: tc.key_manager().sign_ecdsa(&message, key)?,
+                        sighash_type: *sighash_type,
+                    })
+                }
+                _ => unreachable!(),
+            };
+            responses.push(request.respond(signature));
+        }
+
+        Ok(responses.to_bytes(SerializationFormat::Json)?)
+    }
+
+    #[test]
+    fn test_export_signing_requests() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_export_signing_requests").unwrap();
+        let protocol = protocol(&tc)?;
+
+        let package = protocol.export_signing_requests()?;
+        let ids: Vec<String> = package
+            .requests
+            .iter()
+            .map(|request| request.id.to_string())
+            .collect();
+        assert_eq!(
+            ids,
+            vec!["A:0 key path", "B:0 key path", "B:0 leaf 0", "B:0 leaf 1"]
+        );
+        assert!(matches!(
+            package.requests[3].sighash_type,
+            SighashType::Taproot(TapSighashType::SinglePlusAnyoneCanPay)
+        ));
+        assert!(matches!(
+            package.requests[1].key,
+            SigningKey::TweakedSchnorr {
+                merkle_root: Some(_),
+                ..
+            }
+        ));
+
+        let bytes = package.to_bytes(SerializationFormat::Bincode)?;
+        assert_eq!(SigningPackage::from_bytes(&bytes)?.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_signing_responses() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_import_signing_responses").unwrap();
+        let mut protocol = protocol(&tc)?;
+
+        let package = protocol
+            .export_signing_requests()?
+            .to_bytes(SerializationFormat::Json)?;
+        let responses = SigningResponses::from_bytes(&sign_offline(&tc, &package)?)?;
+        protocol.import_signing_responses(&responses)?;
+
+        assert!(protocol.verify_all_signatures()?.is_valid());
+        assert!(protocol.inputs("B")?[0]
+            .signatures()
+            .iter()
+            .all(|signature| signature.is_some()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_invalid_signing_responses() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_import_invalid_signing_responses").unwrap();
+        let mut protocol = protocol(&tc)?;
+        let package = protocol.export_signing_requests()?;
+        let responses = SigningResponses::from_bytes(&sign_offline(
+            &tc,
+            &package.to_bytes(SerializationFormat::Json)?,
+        )?)?;
+
+        let mut other = responses.clone();
+        other.protocol_name = "other".to_string();
+        assert!(matches!(
+            protocol.import_signing_responses(&other),
+            Err(ProtocolBuilderError::SigningResponsesMismatch(..))
+        ));
+
+        let mut duplicated = responses.clone();
+        duplicated.push(responses.responses[0].clone());
+        assert!(matches!(
+            protocol.import_signing_responses(&duplicated),
+            Err(ProtocolBuilderError::UnexpectedSigningResponse(id)) if id == "A:0 key path"
+        ));
+
+        // Signature of leaf 0 answering leaf 1
+        let mut swapped = responses.clone();
+        swapped.responses[3].signature = responses.responses[2].signature.clone();
+        assert!(matches!(
+            protocol.import_signing_responses(&swapped),
+            Err(ProtocolBuilderError::InvalidSigningResponse(id)) if id == "B:0 leaf 1"
+        ));
+
+        // Nothing is stored from rejected responses
+        assert!(protocol.inputs("B")?[0]
+            .signatures()
+            .iter()
+            .all(|signature| signature.is_none()));
+
+        // Partial responses only fill their own signatures
+        let mut partial = package.responses();
+        partial.push(responses.responses[2].clone());
+        protocol.import_signing_responses(&partial)?;
+        let signed: Vec<bool> = protocol.inputs("B")?[0]
+            .signatures()
+            .iter()
+            .map(|signature| signature.is_some())
+            .collect();
+        assert_eq!(signed, vec![true, false, false]);
+
+        Ok(())
+    }
+}
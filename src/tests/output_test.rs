@@ -1,11 +1,32 @@
 #[cfg(test)]
 mod tests {
-    use crate::{
-        scripts::{ProtocolScript, SignMode},
-        types::output::{OutputType, AUTO_AMOUNT, RECOVER_AMOUNT},
+    use std::rc::Rc;
+
+    use bitcoin::{
+        hashes::Hash,
+        key::rand,
+        opcodes::all::{OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_PUSHNUM_1},
+        script::Builder,
+        secp256k1::{Message, Secp256k1},
+        Amount, PublicKey, ScriptBuf, Transaction, TxOut, WScriptHash, Witness, XOnlyPublicKey,
     };
+    use key_manager::key_type::BitcoinKeyType;
 
-    use bitcoin::{key::rand, secp256k1::Secp256k1, Amount, ScriptBuf, WScriptHash};
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::{GraphError, ProtocolBuilderError, ScriptError},
+        helpers::descriptors::descriptor_checksum,
+        scripts::{ProtocolScript, SignMode, StackItem, TapTreeLayout},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            custom::{register_custom_output, CustomOutput},
+            input::{InputArgs, InputType, SighashType, Signature, SpendMode},
+            output::{OutputType, AUTO_AMOUNT, RECOVER_AMOUNT},
+            signer::Signer,
+            witness_args::WitnessArgs,
+        },
+    };
 
     #[test]
     fn test_new_segwit_key_spend() {
@@ -102,4 +123,548 @@ mod tests {
         assert_eq!(recover_script_output.recover_value(), true);
         assert!(recover_script_output.dust_limit().to_sat() >= 540);
     }
+
+    #[test]
+    fn test_descriptor_checksum() {
+        // Test vectors from BIP-380
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(
+            descriptor_checksum("addr(mkmZxiEcEd8ZqjQWVZuC6so5dFMKEFpN2j)").unwrap(),
+            "02wpgw69"
+        );
+        assert!(descriptor_checksum("raw(\u{e9})").is_none());
+    }
+
+    #[test]
+    fn test_segwit_descriptors() {
+        let secp = Secp256k1::new();
+        let (_, public_key) = secp.generate_keypair(&mut rand::thread_rng());
+        let public_key = bitcoin::PublicKey::from(public_key);
+
+        let key_output = OutputType::segwit_key(1000, &public_key).unwrap();
+        let descriptor = key_output.to_descriptor().unwrap();
+        assert!(descriptor.starts_with(&format!("wpkh({})#", public_key)));
+
+        // Scripts that are not miniscript can only be exported by script pubkey
+        let script =
+            ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Single);
+        let script_output = OutputType::segwit_script(1000, &script).unwrap();
+        let descriptor = script_output.to_descriptor().unwrap();
+        let script_pubkey = ScriptBuf::new_p2wsh(&WScriptHash::hash(&[0x51]));
+        assert!(descriptor.starts_with(&format!("raw({})#", hex::encode(script_pubkey))));
+
+        let script = ProtocolScript::new(
+            Builder::new()
+                .push_key(&public_key)
+                .push_opcode(OP_CHECKSIG)
+                .into_script(),
+            &public_key,
+            SignMode::Single,
+        );
+        let script_output = OutputType::segwit_script(1000, &script).unwrap();
+        let descriptor = script_output.to_descriptor().unwrap();
+        assert!(descriptor.starts_with(&format!("wsh(pk({}))#", public_key)));
+
+        let unknown_output = OutputType::ExternalUnknown {
+            script_pubkey: ScriptBuf::new(),
+        };
+        assert!(unknown_output.to_descriptor().is_none());
+    }
+
+    #[test]
+    fn test_taproot_descriptor_tree() {
+        let secp = Secp256k1::new();
+        let (_, public_key) = secp.generate_keypair(&mut rand::thread_rng());
+        let public_key = bitcoin::PublicKey::from(public_key);
+        let internal_key = bitcoin::XOnlyPublicKey::from(public_key);
+
+        let keys = (0..3)
+            .map(|_| XOnlyPublicKey::from(secp.generate_keypair(&mut rand::thread_rng()).1))
+            .collect::<Vec<_>>();
+        let leaves = keys
+            .iter()
+            .map(|key| {
+                let script = Builder::new()
+                    .push_x_only_key(key)
+                    .push_opcode(OP_CHECKSIG)
+                    .into_script();
+                ProtocolScript::new(script, &public_key, SignMode::Single)
+            })
+            .collect::<Vec<_>>();
+
+        let output = OutputType::taproot(1000, &public_key, &leaves).unwrap();
+        let descriptor = output.to_descriptor().unwrap();
+        let (body, checksum) = descriptor.split_once('#').unwrap();
+
+        assert_eq!(
+            body,
+            format!(
+                "tr({},{{pk({}),{{pk({}),pk({})}}}})",
+                internal_key, keys[0], keys[1], keys[2]
+            )
+        );
+        assert_eq!(descriptor_checksum(body).unwrap(), checksum);
+
+        // Trees with leaves that are not miniscript are exported by output key
+        let leaves = [0x51, 0x52, 0x53]
+            .iter()
+            .map(|op| {
+                ProtocolScript::new(ScriptBuf::from(vec![*op]), &public_key, SignMode::Single)
+            })
+            .collect::<Vec<_>>();
+        let output = OutputType::taproot(1000, &public_key, &leaves).unwrap();
+        let output_key = &output.get_script_pubkey().as_bytes()[2..];
+        assert!(output
+            .to_descriptor()
+            .unwrap()
+            .starts_with(&format!("rawtr({})#", hex::encode(output_key))));
+
+        let key_only = OutputType::taproot(1000, &public_key, &[]).unwrap();
+        assert!(key_only
+            .to_descriptor()
+            .unwrap()
+            .starts_with(&format!("tr({})#", internal_key)));
+    }
+
+    #[test]
+    fn test_export_descriptors() {
+        let secp = Secp256k1::new();
+        let (_, public_key) = secp.generate_keypair(&mut rand::thread_rng());
+        let public_key = bitcoin::PublicKey::from(public_key);
+
+        let mut protocol = Protocol::new("descriptors");
+        protocol
+            .add_external_transaction("funding")
+            .unwrap()
+            .add_unknown_outputs("funding", 2)
+            .unwrap()
+            .add_transaction_output("A", &OutputType::segwit_key(1000, &public_key).unwrap())
+            .unwrap()
+            .add_transaction_output("A", &OutputType::taproot(1000, &public_key, &[]).unwrap())
+            .unwrap();
+
+        let descriptors = protocol.export_descriptors().unwrap();
+
+        assert_eq!(descriptors.len(), 2);
+        assert!(descriptors.iter().all(|d| d.transaction_name == "A"));
+        assert_eq!(descriptors[0].output_index, 0);
+        assert!(descriptors[0].descriptor.starts_with("wpkh("));
+        assert_eq!(descriptors[1].output_index, 1);
+        assert!(descriptors[1].descriptor.starts_with("tr("));
+    }
+
+    fn leaves(public_key: &PublicKey, count: usize) -> Vec<ProtocolScript> {
+        (0..count)
+            .map(|tag| {
+                let script = Builder::new()
+                    .push_int(tag as i64)
+                    .push_x_only_key(&XOnlyPublicKey::from(*public_key))
+                    .push_opcode(OP_CHECKSIGVERIFY)
+                    .push_opcode(OP_PUSHNUM_1)
+                    .into_script();
+                let mut leaf = ProtocolScript::new(script, public_key, SignMode::Single);
+                leaf.add_stack_item(StackItem::new_schnorr_sig(false));
+                leaf
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_layout_depths() {
+        assert_eq!(
+            TapTreeLayout::Balanced.leaf_depths(5).unwrap(),
+            vec![2, 2, 2, 3, 3]
+        );
+        assert_eq!(
+            TapTreeLayout::Weights(vec![1, 1, 2, 4])
+                .leaf_depths(4)
+                .unwrap(),
+            vec![3, 3, 2, 1]
+        );
+        assert_eq!(
+            TapTreeLayout::Weights(vec![7]).leaf_depths(1).unwrap(),
+            vec![0]
+        );
+
+        // Deepest leaves are added first
+        assert_eq!(
+            TapTreeLayout::Depths(vec![1, 2, 2]).tree_order(3).unwrap(),
+            vec![(1, 2), (2, 2), (0, 1)]
+        );
+
+        for (layout, count) in [
+            (TapTreeLayout::Depths(vec![1, 2]), 2),
+            (TapTreeLayout::Depths(vec![1, 1, 1]), 3),
+            (TapTreeLayout::Depths(vec![1, 1]), 3),
+            (TapTreeLayout::Weights(vec![1, 2]), 3),
+        ] {
+            assert!(matches!(
+                layout.leaf_depths(count),
+                Err(ScriptError::InvalidTapTreeLayout(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_taproot_output_with_layout() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_taproot_output_with_layout").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaves = leaves(&public_key, 3);
+
+        let balanced = OutputType::taproot(1_000, &public_key, &leaves)?;
+        let explicit_balanced = OutputType::taproot_with_layout(
+            1_000,
+            &public_key,
+            &leaves,
+            &TapTreeLayout::Depths(vec![1, 2, 2]),
+        )?;
+        assert_eq!(
+            balanced.get_script_pubkey(),
+            explicit_balanced.get_script_pubkey()
+        );
+
+        // Moving the last leaf to the top changes the tree
+        let layout = TapTreeLayout::Depths(vec![2, 2, 1]);
+        let output = OutputType::taproot_with_layout(1_000, &public_key, &leaves, &layout)?;
+        assert_ne!(balanced.get_script_pubkey(), output.get_script_pubkey());
+        assert_eq!(output.taproot_layout(), layout);
+
+        // Leaves that are not miniscript can only be exported by output key
+        assert!(output.to_descriptor().unwrap().starts_with("rawtr("));
+
+        let keys = (1..=3)
+            .map(|index| tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, index))
+            .collect::<Result<Vec<_>, _>>()?;
+        let pk_leaves = keys
+            .iter()
+            .map(|key| {
+                let script = Builder::new()
+                    .push_x_only_key(&XOnlyPublicKey::from(*key))
+                    .push_opcode(OP_CHECKSIG)
+                    .into_script();
+                ProtocolScript::new(script, key, SignMode::Single)
+            })
+            .collect::<Vec<_>>();
+        let output = OutputType::taproot_with_layout(1_000, &public_key, &pk_leaves, &layout)?;
+        let pk = |index: usize| format!("pk({})", XOnlyPublicKey::from(keys[index]));
+        let descriptor = output.to_descriptor().unwrap();
+        assert!(descriptor.contains(&format!("{{{{{},{}}},{}}}", pk(0), pk(1), pk(2))));
+
+        assert!(matches!(
+            OutputType::taproot_with_layout(
+                1_000,
+                &public_key,
+                &leaves,
+                &TapTreeLayout::Depths(vec![1, 1])
+            ),
+            Err(ProtocolBuilderError::ScriptError(
+                ScriptError::InvalidTapTreeLayout(_)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_weighted_leaves_have_shorter_control_blocks() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_weighted_leaves_have_shorter_control_blocks").unwrap();
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+
+        let mut protocol = Protocol::new("taptree_layout");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &funding_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        protocol.add_connection(
+            "A_B",
+            "A",
+            OutputSpec::Auto(OutputType::taproot_with_layout(
+                8_000,
+                &public_key,
+                &leaves(&public_key, 5),
+                &TapTreeLayout::Weights(vec![100, 1, 1, 1, 1]),
+            )?),
+            "B",
+            InputSpec::Auto(tc.tr_sighash_type(), SpendMode::ScriptsOnly),
+            None,
+            None,
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        // Control blocks are 33 bytes plus 32 bytes per level of depth
+        let control_block_size = |leaf| -> Result<usize, ProtocolBuilderError> {
+            let b = protocol.finalize("B", Some(leaf), &WitnessArgs::new(), tc.key_manager())?;
+            Ok(b.input[0].witness.last().unwrap().len())
+        };
+        assert_eq!(control_block_size(0)?, 33 + 32);
+        assert_eq!(control_block_size(1)?, 33 + 3 * 32);
+
+        Ok(())
+    }
+
+    fn leaf(public_key: &PublicKey, name: &str, tag: i64) -> ProtocolScript {
+        let script = Builder::new()
+            .push_int(tag)
+            .push_x_only_key(&XOnlyPublicKey::from(*public_key))
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let mut leaf = ProtocolScript::new(script, public_key, SignMode::Single);
+        leaf.set_name(name).unwrap();
+        leaf
+    }
+
+    fn protocol(tc: &TestContext, spend_mode: SpendMode) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaves = [
+            leaf(&public_key, "timeout", 1),
+            leaf(&public_key, "reveal", 2),
+        ];
+
+        let mut protocol = Protocol::new("named_leaves");
+        ProtocolBuilder {}.add_external_connection(
+            &mut protocol,
+            "EXT",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::taproot(10_000, &public_key, &leaves)?),
+            "A",
+            InputSpec::Auto(tc.tr_sighash_type(), spend_mode),
+        )?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_spend_mode_by_name() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_spend_mode_by_name").unwrap();
+        let mut protocol = protocol(&tc, SpendMode::ScriptByName("reveal".to_string()))?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        // Only the named leaf is signed
+        assert!(protocol
+            .input_taproot_script_spend_signature("A", 0, 0)?
+            .is_none());
+        let signature = protocol
+            .input_taproot_script_spend_signature("A", 0, 1)?
+            .unwrap();
+
+        let (output, leaves) = protocol.get_script_from_output("EXT", 0)?;
+        assert_eq!(output.leaf_index_by_name("reveal"), Some(1));
+        assert_eq!(output.leaf_index_by_name("unknown"), None);
+        assert_eq!(
+            protocol.leaf_map("EXT", 0)?.keys().collect::<Vec<_>>(),
+            vec!["reveal", "timeout"]
+        );
+
+        // The witness spends the named leaf
+        let mut args = InputArgs::new_taproot_script_args_by_name("reveal");
+        args.push_taproot_signature(signature)?;
+        let transaction = protocol.transaction_to_send("A", &[args])?;
+        let witness = &transaction.input[0].witness;
+        assert_eq!(witness.len(), 3);
+        assert_eq!(witness.nth(1).unwrap(), leaves[1].get_script().as_bytes());
+
+        let args = InputArgs::new_taproot_script_args_by_name("refund");
+        assert!(matches!(
+            protocol.transaction_to_send("A", &[args]),
+            Err(ProtocolBuilderError::UnknownLeafName(name)) if name == "refund"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_leaf_name() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_unknown_leaf_name").unwrap();
+        let mut protocol = protocol(&tc, SpendMode::ScriptByName("refund".to_string()))?;
+
+        assert!(matches!(
+            protocol.build(tc.key_manager(), ""),
+            Err(ProtocolBuilderError::UnknownLeafName(name)) if name == "refund"
+        ));
+
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let mut leaf = leaf(&public_key, "reveal", 1);
+        assert!(matches!(
+            leaf.set_name(" "),
+            Err(ScriptError::EmptyScriptName)
+        ));
+        assert_eq!(leaf.get_name(), Some("reveal"));
+
+        Ok(())
+    }
+
+    const OP_TRUE_KIND: &str = "op_true";
+
+    /// Anyone-can-spend P2WSH output, spent by revealing an OP_TRUE witness script.
+    #[derive(Clone, Debug)]
+    struct OpTrueOutput {
+        value: Amount,
+        script_pubkey: ScriptBuf,
+    }
+
+    impl OpTrueOutput {
+        fn new(value: u64) -> Self {
+            Self {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::new_p2wsh(&Self::script().wscript_hash()),
+            }
+        }
+
+        fn script() -> ScriptBuf {
+            ScriptBuf::from(vec![0x51])
+        }
+
+        fn from_bytes(data: &[u8]) -> Result<Box<dyn CustomOutput>, ProtocolBuilderError> {
+            let value = u64::from_le_bytes(data.try_into().expect("8 bytes value"));
+            Ok(Box::new(Self::new(value)))
+        }
+    }
+
+    impl CustomOutput for OpTrueOutput {
+        fn kind(&self) -> &str {
+            OP_TRUE_KIND
+        }
+
+        fn value(&self) -> Amount {
+            self.value
+        }
+
+        fn set_value(&mut self, value: Amount) {
+            self.value = value;
+        }
+
+        fn script_pubkey(&self) -> &ScriptBuf {
+            &self.script_pubkey
+        }
+
+        fn supports_sighash_type(&self, sighash_type: &SighashType) -> bool {
+            matches!(sighash_type, SighashType::Ecdsa(_))
+        }
+
+        fn compute_sighashes(
+            &self,
+            _transaction: &Transaction,
+            _transaction_name: &str,
+            _input_index: usize,
+            _prevouts: &[TxOut],
+            _spend_mode: &SpendMode,
+            _sighash_type: &SighashType,
+        ) -> Result<Vec<Option<Message>>, ProtocolBuilderError> {
+            Ok(vec![None])
+        }
+
+        fn compute_signatures(
+            &self,
+            _transaction_name: &str,
+            _input_index: usize,
+            _hashed_messages: &[Option<Message>],
+            _spend_mode: &SpendMode,
+            _sighash_type: &SighashType,
+            _signer: &dyn Signer,
+            _id: &str,
+        ) -> Result<Vec<Option<Signature>>, ProtocolBuilderError> {
+            Ok(vec![None])
+        }
+
+        fn witness(
+            &self,
+            _input: &InputType,
+            _args: &InputArgs,
+        ) -> Result<Witness, ProtocolBuilderError> {
+            Ok(Witness::from_slice(&[Self::script().as_bytes()]))
+        }
+
+        fn estimate_witness_bytes(&self) -> usize {
+            3
+        }
+
+        fn to_bytes(&self) -> Vec<u8> {
+            self.value.to_sat().to_le_bytes().to_vec()
+        }
+
+        fn clone_box(&self) -> Box<dyn CustomOutput> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn custom_protocol(sighash_type: SighashType) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("custom_output");
+        protocol.add_connection(
+            "op_true",
+            "A",
+            OutputSpec::Auto(OutputType::custom(OpTrueOutput::new(1000))),
+            "B",
+            InputSpec::Auto(sighash_type, SpendMode::Segwit),
+            None,
+            None,
+        )?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_custom_output_witness() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_custom_output_witness").unwrap();
+        let mut protocol = custom_protocol(tc.ecdsa_sighash_type())?;
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        // The output signs its own inputs, so there is nothing to request from other signers
+        assert!(protocol.export_signing_requests()?.requests.is_empty());
+
+        let a = protocol.transaction_by_name("A")?;
+        assert_eq!(a.output[0].value, Amount::from_sat(1000));
+        assert_eq!(
+            a.output[0].script_pubkey,
+            OpTrueOutput::new(1000).script_pubkey
+        );
+
+        let b = protocol.transaction_to_send("B", &[InputArgs::new_segwit_args()])?;
+        assert_eq!(b.input[0].witness.len(), 1);
+        assert_eq!(
+            b.input[0].witness.nth(0),
+            Some(OpTrueOutput::script().as_bytes())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_output_persistence() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_custom_output_persistence").unwrap();
+        let storage = Rc::new(tc.new_storage("protocol"));
+        register_custom_output(OP_TRUE_KIND, OpTrueOutput::from_bytes);
+
+        let protocol = custom_protocol(tc.ecdsa_sighash_type())?;
+        protocol.save(storage.clone())?;
+        drop(protocol);
+
+        let protocol = Protocol::load("custom_output", storage)?.unwrap();
+        let inputs = protocol.inputs("B")?;
+
+        match inputs[0].output_type()? {
+            OutputType::Custom { output } => {
+                assert_eq!(output.kind(), OP_TRUE_KIND);
+                assert_eq!(output.value(), Amount::from_sat(1000));
+            }
+            other => panic!("Expected a custom output, got {}", other.get_name()),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_output_unsupported_sighash() {
+        let tc = TestContext::new("test_custom_output_unsupported_sighash").unwrap();
+
+        let result = custom_protocol(tc.tr_sighash_type());
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::InvalidOutputTypeForSighashType
+            ))
+        ));
+    }
 }
@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::PublicKey;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        graph::graph::GraphOptions,
+        tests::utils::{checksig_leaf_with_signature, TestContext},
+        types::{input::SpendMode, output::OutputType},
+    };
+
+    // EXT -> A -> B, Alice funds A and each party owns a leaf of the output spent by B
+    fn owned_protocol(
+        tc: &TestContext,
+        alice: &PublicKey,
+        bob: &PublicKey,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("ownership");
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, alice)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            8_000,
+            alice,
+            &[
+                checksig_leaf_with_signature(alice),
+                checksig_leaf_with_signature(bob),
+            ],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+
+        protocol
+            .set_input_owner("A", 0, "alice")?
+            .set_leaf_owner("B", 0, 0, "alice")?
+            .set_leaf_owner("B", 0, 1, "bob")?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_signing_manifests() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_signing_manifests").unwrap();
+        let alice = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let bob = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+
+        let mut protocol = owned_protocol(&tc, &alice, &bob)?;
+        assert_eq!(protocol.owner("B", 0, Some(1)), Some("bob"));
+        assert_eq!(protocol.owner("A", 0, None), Some("alice"));
+        assert_eq!(protocol.owner("B", 0, None), None);
+
+        let manifest = protocol.signing_manifest("alice")?;
+        let tasks: Vec<_> = manifest
+            .tasks
+            .iter()
+            .map(|task| (task.transaction_name.as_str(), task.leaf))
+            .collect();
+        assert_eq!(tasks, vec![("A", None), ("B", Some(0))]);
+        assert!(manifest.tasks.iter().all(|task| task.message.is_none()));
+
+        protocol.build(tc.key_manager(), "")?;
+        let manifest = protocol.signing_manifest("bob")?;
+        assert_eq!(manifest.tasks.len(), 1);
+        assert_eq!(manifest.tasks[0].public_key, Some(bob));
+        assert_eq!(
+            manifest.tasks[0].message,
+            protocol
+                .get_hashed_message("B", 0, 1)?
+                .map(|message| message.as_ref().to_vec())
+        );
+
+        assert!(matches!(
+            protocol.set_leaf_owner("B", 0, 2, "bob"),
+            Err(ProtocolBuilderError::MissingTaprootLeaf(2, 0))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_ownership() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_check_ownership").unwrap();
+        let alice = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let bob = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+
+        let mut protocol = owned_protocol(&tc, &alice, &bob)?;
+        let party_keys = HashMap::from([
+            ("alice".to_string(), vec![alice]),
+            ("bob".to_string(), vec![bob]),
+        ]);
+        protocol.check_ownership(&party_keys)?;
+
+        protocol.set_leaf_owner("B", 0, 1, "alice")?;
+        assert!(matches!(
+            protocol.check_ownership(&party_keys),
+            Err(ProtocolBuilderError::OwnerKeyMismatch(name, 0, Some(1), party))
+                if name == "B" && party == "alice"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_visualize_by_owner() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_visualize_by_owner").unwrap();
+        let alice = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let bob = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+
+        let mut protocol = owned_protocol(&tc, &alice, &bob)?;
+        let dot = protocol.visualize_by_owner(GraphOptions::Default)?;
+        let edge = |dot: &str, prefix: &str| {
+            dot.lines()
+                .find(|line| line.starts_with(prefix))
+                .unwrap()
+                .to_string()
+        };
+        assert!(edge(&dot, "EXT -> A:i0").ends_with(" color=blue fontcolor=blue]"));
+        assert_eq!(edge(&dot, "A -> B:i0"), "A -> B:i0 [label=A_B]");
+
+        protocol.set_leaf_owner("B", 0, 0, "bob")?;
+        let dot = protocol.visualize_by_owner(GraphOptions::Default)?;
+        assert_eq!(
+            edge(&dot, "A -> B:i0"),
+            "A -> B:i0 [label=A_B color=red fontcolor=red]"
+        );
+
+        assert_eq!(
+            protocol.visualize(GraphOptions::Default)?,
+            protocol.graph().visualize(GraphOptions::Default)?
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod tests {
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        graph::package::{ConfirmationBarrier, PackageLimit, PackageLimits},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    // Chain of transactions T0 -> T1 -> ... where the given links wait for a confirmation.
+    fn chain(
+        tc: &TestContext,
+        length: usize,
+        timelocked: &[usize],
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let mut protocol = Protocol::new("chain");
+
+        for index in 1..length {
+            protocol.add_connection(
+                &format!("link_{}", index),
+                format!("T{}", index - 1),
+                OutputSpec::Auto(OutputType::segwit_key(100_000, &public_key)?),
+                format!("T{}", index),
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+                timelocked.contains(&index).then_some(1),
+                None,
+            )?;
+        }
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_chain_exceeding_package_limits() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_chain_exceeding_package_limits").unwrap();
+        let protocol = chain(&tc, 30, &[])?;
+
+        let report = protocol.check_package_limits(&PackageLimits::default())?;
+        assert!(!report.is_within_limits());
+
+        let ancestors: Vec<_> = report
+            .violations
+            .iter()
+            .filter(|violation| violation.limit == PackageLimit::Ancestors)
+            .collect();
+        assert_eq!(ancestors.len(), 5);
+        assert_eq!(ancestors[0].transaction_name, "T25");
+        assert_eq!(ancestors[0].count, 26);
+        assert_eq!(ancestors[0].chain.first().unwrap(), "T0");
+        assert_eq!(ancestors[0].chain.len(), 26);
+
+        let descendants: Vec<_> = report
+            .violations
+            .iter()
+            .filter(|violation| violation.limit == PackageLimit::Descendants)
+            .map(|violation| violation.transaction_name.as_str())
+            .collect();
+        assert_eq!(descendants, vec!["T0", "T1", "T2", "T3", "T4"]);
+
+        assert_eq!(
+            report.barriers,
+            vec![ConfirmationBarrier {
+                transaction_name: "T25".to_string(),
+                input_index: 0,
+                previous_transaction: "T24".to_string(),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_timelocks_are_barriers() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_relative_timelocks_are_barriers").unwrap();
+
+        let protocol = chain(&tc, 30, &[15])?;
+        let report = protocol.check_package_limits(&PackageLimits::default())?;
+        assert!(report.is_within_limits());
+        assert!(report.barriers.is_empty());
+
+        let limits = PackageLimits {
+            max_ancestors: 10,
+            max_descendants: 25,
+        };
+        let report = protocol.check_package_limits(&limits)?;
+        assert!(!report.is_within_limits());
+        assert!(report
+            .violations
+            .iter()
+            .all(|violation| violation.limit == PackageLimit::Ancestors));
+
+        let barriers: Vec<_> = report
+            .barriers
+            .iter()
+            .map(|barrier| barrier.transaction_name.as_str())
+            .collect();
+        assert_eq!(barriers, vec!["T10", "T25"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        hashes::{sha256, Hash},
+        key::{Keypair, Secp256k1},
+        secp256k1::{rand::thread_rng, Message},
+    };
+
+    use crate::helpers::parallel_verify::ParallelSchnorrVerifier;
+
+    // Signatures of distinct messages, enough to be split across threads
+    fn verifier(size: usize, invalid: &[usize]) -> ParallelSchnorrVerifier {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::new(&secp, &mut thread_rng());
+        let other = Keypair::new(&secp, &mut thread_rng());
+
+        let mut verifier = ParallelSchnorrVerifier::new();
+        for index in 0..size {
+            let message =
+                Message::from_digest(sha256::Hash::hash(&index.to_le_bytes()).to_byte_array());
+            let signature = secp.sign_schnorr(&message, &keypair);
+            let key = match invalid.contains(&index) {
+                true => other.x_only_public_key().0,
+                false => keypair.x_only_public_key().0,
+            };
+            verifier.push(signature, message, key);
+        }
+        verifier
+    }
+
+    #[test]
+    fn test_parallel_verify() {
+        let secp = Secp256k1::verification_only();
+
+        let valid = verifier(200, &[]);
+        assert_eq!(valid.len(), 200);
+        assert!(valid.verify_all(&secp));
+
+        let results = verifier(200, &[0, 97, 199]).verify(&secp);
+        let failed: Vec<usize> = (0..results.len()).filter(|i| !results[*i]).collect();
+        assert_eq!(failed, vec![0, 97, 199]);
+
+        // A few signatures are verified in place
+        let results = verifier(3, &[1]).verify(&secp);
+        assert_eq!(results, vec![true, false, true]);
+
+        assert!(ParallelSchnorrVerifier::new().verify(&secp).is_empty());
+    }
+}
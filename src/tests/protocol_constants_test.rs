@@ -0,0 +1,187 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        opcodes::all::{OP_CHECKSIG, OP_CSV, OP_DROP},
+        script::Builder,
+        PublicKey, XOnlyPublicKey,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::{ProtocolBuilderError, ScriptError},
+        scripts::{self, ConstantValue, ScriptTemplate, SignMode},
+        tests::utils::TestContext,
+    };
+
+    const CHALLENGE_BLOCKS: &str = "CHALLENGE_BLOCKS";
+
+    fn timelock_template(public_key: &PublicKey) -> ScriptTemplate {
+        ScriptTemplate::new()
+            .push_constant(CHALLENGE_BLOCKS)
+            .push_script(
+                Builder::new()
+                    .push_opcode(OP_CSV)
+                    .push_opcode(OP_DROP)
+                    .push_x_only_key(&XOnlyPublicKey::from(*public_key))
+                    .push_opcode(OP_CHECKSIG)
+                    .into_script(),
+            )
+    }
+
+    fn timelock_output(
+        protocol: &mut Protocol,
+        transaction_name: &str,
+        public_key: &PublicKey,
+    ) -> Result<(), ProtocolBuilderError> {
+        let expired =
+            protocol.build_script(&timelock_template(public_key), public_key, SignMode::Single)?;
+        let renew = scripts::timelock_renew(public_key, SignMode::Single);
+
+        ProtocolBuilder {}.add_timelock_output(
+            protocol,
+            transaction_name,
+            1000,
+            public_key,
+            &expired,
+            &renew,
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_constant_usages() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_constant_usages").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = Protocol::new("constants");
+        protocol.set_constant(CHALLENGE_BLOCKS, 144u16)?;
+
+        timelock_output(&mut protocol, "A", &public_key)?;
+        timelock_output(&mut protocol, "B", &public_key)?;
+
+        protocol.check_constants()?;
+        protocol.build(tc.key_manager(), "")?;
+
+        let usages = protocol.constant_usages(CHALLENGE_BLOCKS)?;
+        assert_eq!(usages.len(), 2);
+        assert!(usages.iter().all(|usage| usage.output_index == 0));
+        assert!(usages.iter().all(|usage| usage.script_index == 0));
+        assert_eq!(
+            protocol.constants().get(CHALLENGE_BLOCKS),
+            Some(&ConstantValue::Number(144))
+        );
+
+        // The placeholder is pushed like a hardcoded value
+        let leaf = protocol.build_script(
+            &timelock_template(&public_key),
+            &public_key,
+            SignMode::Single,
+        )?;
+        assert_eq!(
+            leaf.get_script(),
+            scripts::timelock(144, &public_key, SignMode::Single).get_script()
+        );
+        assert_eq!(leaf.constant_offsets(CHALLENGE_BLOCKS), &[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_constant_mismatch() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_constant_mismatch").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = Protocol::new("constants");
+        protocol.set_constant(CHALLENGE_BLOCKS, 100u16)?;
+        timelock_output(&mut protocol, "B", &public_key)?;
+
+        // Leaves built before the constant is redefined keep the old value
+        protocol.set_constant(CHALLENGE_BLOCKS, 144u16)?;
+        timelock_output(&mut protocol, "A", &public_key)?;
+
+        let result = protocol.build(tc.key_manager(), "");
+        match result {
+            Err(ProtocolBuilderError::ConstantMismatch(
+                name,
+                transaction_name,
+                _,
+                _,
+                value,
+                expected,
+            )) => {
+                assert_eq!(name, CHALLENGE_BLOCKS);
+                assert_eq!(transaction_name, "B");
+                assert_eq!(value, "100");
+                assert_eq!(expected, "144");
+            }
+            _ => panic!("Expected ConstantMismatch error"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undefined_constant() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_undefined_constant").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = Protocol::new("constants");
+        assert!(matches!(
+            timelock_output(&mut protocol, "A", &public_key),
+            Err(ProtocolBuilderError::UndefinedConstant(name)) if name == CHALLENGE_BLOCKS
+        ));
+        assert!(matches!(
+            protocol.constant("STAKE_AMOUNT"),
+            Err(ProtocolBuilderError::UndefinedConstant(_))
+        ));
+        assert!(matches!(
+            protocol.build_script(
+                &ScriptTemplate::new().push_constant(" "),
+                &public_key,
+                SignMode::Single
+            ),
+            Err(ProtocolBuilderError::ScriptError(
+                ScriptError::EmptyConstantName
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_constant_placeholders() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_constant_placeholders").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        // Hardcoded values are not usages of a constant, even if they are equal
+        let hardcoded = scripts::timelock(6, &public_key, SignMode::Single);
+        assert!(hardcoded.get_constants().is_empty());
+
+        // Usages are tied to the offset of the placeholder, not to any push of the same value
+        let mut protocol = Protocol::new("constants");
+        protocol.set_constant(CHALLENGE_BLOCKS, 6u16)?;
+        let mut leaf = protocol.build_script(
+            &timelock_template(&public_key),
+            &public_key,
+            SignMode::Single,
+        )?;
+        assert_eq!(leaf.constant_offsets(CHALLENGE_BLOCKS), &[0]);
+        assert!(matches!(
+            leaf.add_constant_placeholder(CHALLENGE_BLOCKS, ConstantValue::Number(6), 1),
+            Err(ScriptError::ConstantNotInScript(name, value)) if name == CHALLENGE_BLOCKS && value == "6"
+        ));
+
+        assert!(matches!(
+            ConstantValue::Bytes(vec![0; 521]).push_script(),
+            Err(ScriptError::ConstantTooLarge(521))
+        ));
+        assert!(matches!(
+            ConstantValue::try_from(u64::MAX),
+            Err(ScriptError::ConstantOutOfRange(u64::MAX))
+        ));
+        assert_eq!(ConstantValue::try_from(144u64)?, ConstantValue::Number(144));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, ScriptBuf};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            diff::TransactionChange,
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    fn protocol(tc: &TestContext, leaf_script: u8) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaf = ProtocolScript::new(
+            ScriptBuf::from(vec![leaf_script]),
+            &public_key,
+            SignMode::Single,
+        );
+
+        let mut protocol = Protocol::new("diff");
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            5_000,
+            &public_key,
+            &[leaf],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_diff_of_equal_protocols_is_empty() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_diff_of_equal_protocols_is_empty").unwrap();
+        let old = protocol(&tc, 0x51)?;
+        let new = protocol(&tc, 0x51)?;
+
+        assert!(old.diff(&new)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_reports_structural_changes() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_diff_reports_structural_changes").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 1)?;
+        let old = protocol(&tc, 0x51)?;
+
+        let mut new = protocol(&tc, 0x52)?;
+        new.set_locktime("B", LockTime::from_height(100).unwrap())?;
+        new.add_connection(
+            "B_C",
+            "B",
+            OutputSpec::Auto(OutputType::segwit_key(1_000, &public_key)?),
+            "C",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+
+        let diff = old.diff(&new)?;
+        assert_eq!(diff.added_transactions, vec!["C"]);
+        assert!(diff.removed_transactions.is_empty());
+
+        let changes: Vec<(&str, &Vec<TransactionChange>)> = diff
+            .changed_transactions
+            .iter()
+            .map(|changed| (changed.transaction_name.as_str(), &changed.changes))
+            .collect();
+        assert_eq!(changes.len(), 2);
+
+        assert_eq!(changes[0].0, "A");
+        assert!(matches!(
+            changes[0].1.as_slice(),
+            [TransactionChange::LeafScript {
+                output_index: 0,
+                leaf: 0,
+                ..
+            }]
+        ));
+
+        assert_eq!(changes[1].0, "B");
+        assert!(changes[1]
+            .1
+            .contains(&TransactionChange::LockTime { old: 0, new: 100 }));
+        assert!(changes[1].1.contains(&TransactionChange::OutputAdded {
+            output_index: 0,
+            value: 1_000,
+        }));
+
+        // The reverse diff removes what was added
+        let reverse = new.diff(&old)?;
+        assert_eq!(reverse.removed_transactions, vec!["C"]);
+
+        let text = diff.to_text(false);
+        assert!(text.contains("+ C\n"));
+        assert!(text.contains("~ B\n    locktime: 0 -> 100\n"));
+        assert!(diff.to_text(true).contains("\x1b[32m+ C\x1b[0m"));
+
+        let dot = old.visualize_diff(&new)?;
+        assert!(dot.contains("C [label=\"C\" color=darkgreen fontcolor=darkgreen]"));
+        assert!(dot.contains("A [label=\"A\" color=orange fontcolor=orange]"));
+        assert!(dot.contains("B -> C [label=B_C color=darkgreen fontcolor=darkgreen]"));
+        assert!(dot.contains("A -> B [label=A_B]"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            event::ProtocolEvent,
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    #[test]
+    fn test_protocol_events() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_protocol_events").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut protocol = Protocol::new("events");
+        let recorded = events.clone();
+        protocol.on_event(move |event| recorded.lock().unwrap().push(event.clone()));
+
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        protocol.add_connection(
+            "A_B",
+            "A",
+            OutputSpec::Auto(OutputType::segwit_key(9_000, &public_key)?),
+            "B",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec![
+                ProtocolEvent::TransactionAdded {
+                    transaction_name: "EXT".to_string(),
+                    external: true,
+                },
+                ProtocolEvent::TransactionAdded {
+                    transaction_name: "A".to_string(),
+                    external: false,
+                },
+                ProtocolEvent::ConnectionAdded {
+                    connection_name: "external".to_string(),
+                    from: "EXT".to_string(),
+                    output_index: 0,
+                    to: "A".to_string(),
+                    input_index: 0,
+                },
+                ProtocolEvent::TransactionAdded {
+                    transaction_name: "B".to_string(),
+                    external: false,
+                },
+                ProtocolEvent::ConnectionAdded {
+                    connection_name: "A_B".to_string(),
+                    from: "A".to_string(),
+                    output_index: 0,
+                    to: "B".to_string(),
+                    input_index: 0,
+                },
+            ]
+        );
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        let events = events.lock().unwrap().drain(..).collect::<Vec<_>>();
+        let txid = protocol.transaction_by_name("B")?.compute_txid();
+        assert!(events.contains(&ProtocolEvent::TxidUpdated {
+            transaction_name: "B".to_string(),
+            txid,
+        }));
+        for name in ["A", "B"] {
+            assert!(events.contains(&ProtocolEvent::SighashComputed {
+                transaction_name: name.to_string(),
+                input_index: 0,
+                sighashes: 1,
+            }));
+            assert!(events.contains(&ProtocolEvent::SignatureAdded {
+                transaction_name: name.to_string(),
+                input_index: 0,
+                signatures: 1,
+            }));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_events_not_cloned() -> Result<(), ProtocolBuilderError> {
+        let count = Arc::new(Mutex::new(0));
+        let mut protocol = Protocol::new("events");
+        let counter = count.clone();
+        protocol.on_event(move |_| *counter.lock().unwrap() += 1);
+
+        let mut copy = protocol.clone();
+        copy.add_transaction("A")?;
+        assert_eq!(*count.lock().unwrap(), 0);
+
+        protocol.add_transaction("A")?;
+        protocol.clear_observers().add_transaction("B")?;
+        assert_eq!(*count.lock().unwrap(), 1);
+
+        Ok(())
+    }
+}
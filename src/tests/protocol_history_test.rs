@@ -0,0 +1,159 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::ScriptBuf;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            audit::AuditEvent, broadcast_rule::BroadcastRule, input::SpendMode, output::OutputType,
+            serialization::SerializationFormat,
+        },
+    };
+
+    #[test]
+    fn test_replay_to_rebuilds_protocol_after_each_mutation() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_replay_to_rebuilds_protocol_after_each_mutation").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaf = ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Single);
+
+        let mut protocol = Protocol::new("history");
+        protocol.add_transaction("A")?;
+        protocol.record_history();
+
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            5_000,
+            &public_key,
+            &[leaf],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.set_constant("STAKE", 1_000i64)?;
+
+        let history = protocol.history();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[0].operation(), "record_history");
+        assert_eq!(history[1].operation(), "add_connection external");
+        assert_eq!(history[2].operation(), "add_connection A_B");
+        assert_eq!(history[3].operation(), "set_constant STAKE");
+
+        let mutations: Vec<usize> = protocol
+            .audit_trail()
+            .iter()
+            .filter_map(|entry| match &entry.event {
+                AuditEvent::Mutation { step, .. } => Some(*step),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(mutations, vec![1, 2, 3]);
+
+        let initial = protocol.replay_to(0)?;
+        assert_eq!(initial.transaction_names(), vec!["A".to_string()]);
+        assert!(initial.audit_trail().is_empty());
+
+        let connected = protocol.replay_to(1)?;
+        assert_eq!(connected.transaction_by_name("A")?.input.len(), 1);
+        assert!(connected.transaction_by_name("B").is_err());
+
+        let before_constant = protocol.replay_to(2)?;
+        assert!(before_constant.transaction_by_name("B").is_ok());
+        assert!(before_constant.constant("STAKE").is_err());
+        assert_eq!(before_constant.history().len(), 3);
+
+        assert!(protocol.replay_to(3)?.constant("STAKE").is_ok());
+
+        protocol.add_broadcast_rule(BroadcastRule::new("B").after_confirmations("A", 6))?;
+        assert_eq!(protocol.history()[4].operation(), "add_broadcast_rule B");
+        assert!(protocol.replay_to(3)?.broadcast_rules().is_empty());
+        assert!(protocol.replay_to(4)?.broadcast_rules().contains_key("B"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_to_rejects_unknown_steps() -> Result<(), ProtocolBuilderError> {
+        let mut protocol = Protocol::new("history");
+        protocol.add_transaction("A")?;
+        assert!(matches!(
+            protocol.replay_to(0),
+            Err(ProtocolBuilderError::HistoryNotRecorded(_))
+        ));
+
+        protocol.record_history().add_transaction("B")?;
+        assert!(matches!(
+            protocol.replay_to(2),
+            Err(ProtocolBuilderError::UnknownHistoryStep(2, 1))
+        ));
+
+        // Replayed protocols keep recording from their step
+        let mut replayed = protocol.replay_to(0)?;
+        replayed.add_transaction("C")?;
+        assert_eq!(replayed.history().len(), 2);
+        assert_eq!(replayed.history()[1].operation(), "add_transaction C");
+        assert!(replayed.transaction_by_name("B").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_survives_serialization() -> Result<(), ProtocolBuilderError> {
+        let mut protocol = Protocol::new("history");
+        protocol.record_history().add_transaction("A")?;
+        protocol.add_transaction("B")?;
+
+        let bytes = protocol.to_bytes(SerializationFormat::Bincode)?;
+        let restored = Protocol::from_bytes(&bytes)?;
+        assert_eq!(restored.history().len(), 3);
+        assert_eq!(
+            restored.replay_to(1)?.transaction_names(),
+            vec!["A".to_string()]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_records_deltas() -> Result<(), ProtocolBuilderError> {
+        let mut protocol = Protocol::new("history");
+        protocol.record_history();
+        for index in 0..50 {
+            protocol.add_transaction(&format!("T{}", index))?;
+        }
+
+        // Each step only stores the transaction it added, not a copy of the protocol
+        let protocol_size = serde_json::to_string(&protocol.replay_to(50)?)
+            .unwrap()
+            .len();
+        let step_size = serde_json::to_string(&protocol.history()[50])
+            .unwrap()
+            .len();
+        assert!(step_size * 10 < protocol_size);
+        assert_eq!(protocol.replay_to(25)?.transaction_names().len(), 25);
+
+        // Restored histories keep recording deltas against the replayed state
+        let bytes = protocol.to_bytes(SerializationFormat::Bincode)?;
+        let mut restored = Protocol::from_bytes(&bytes)?;
+        restored.add_transaction("last")?;
+        assert_eq!(restored.replay_to(51)?.transaction_names().len(), 51);
+        assert_eq!(
+            restored.replay_to(51)?.transaction_names(),
+            restored.transaction_names()
+        );
+
+        Ok(())
+    }
+}
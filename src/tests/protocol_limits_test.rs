@@ -0,0 +1,239 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::ScriptBuf;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            limits::{ProtocolLimit, ProtocolLimits},
+            output::OutputType,
+            serialization::SerializationFormat,
+        },
+    };
+
+    fn leaves(tc: &TestContext, count: usize) -> Result<Vec<ProtocolScript>, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        Ok((0..count)
+            .map(|index| {
+                ProtocolScript::new(
+                    ScriptBuf::from(vec![0x51 + index as u8]),
+                    &public_key,
+                    SignMode::Single,
+                )
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_transactions_limit() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_transactions_limit").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("limited");
+        protocol.set_limits(ProtocolLimits::default().with_max_transactions(2))?;
+        protocol.add_connection(
+            "A_B",
+            "A",
+            OutputSpec::Auto(OutputType::segwit_key(10_000, &public_key)?),
+            "B",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+
+        assert!(matches!(
+            protocol.add_transaction("C"),
+            Err(ProtocolBuilderError::LimitExceeded(
+                ProtocolLimit::Transactions,
+                3,
+                2
+            ))
+        ));
+        assert_eq!(protocol.graph().nodes().count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_io_limits() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_transaction_io_limits").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let output = OutputType::segwit_key(10_000, &public_key)?;
+
+        let mut protocol = Protocol::new("limited");
+        protocol.set_limits(
+            ProtocolLimits::default()
+                .with_max_inputs_per_transaction(2)
+                .with_max_outputs_per_transaction(1),
+        )?;
+        for from in ["A", "B"] {
+            protocol.add_connection(
+                &format!("{}_C", from),
+                from,
+                OutputSpec::Auto(output.clone()),
+                "C",
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+                None,
+                None,
+            )?;
+        }
+
+        // Rejected before the output of D is added
+        assert!(matches!(
+            protocol.add_connection(
+                "D_C",
+                "D",
+                OutputSpec::Auto(output.clone()),
+                "C",
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+                None,
+                None,
+            ),
+            Err(ProtocolBuilderError::LimitExceeded(
+                ProtocolLimit::InputsPerTransaction,
+                3,
+                2
+            ))
+        ));
+        assert_eq!(protocol.get_output_count("D")?, 0);
+
+        assert!(matches!(
+            protocol.add_transaction_output("A", &output),
+            Err(ProtocolBuilderError::LimitExceeded(
+                ProtocolLimit::OutputsPerTransaction,
+                2,
+                1
+            ))
+        ));
+        assert_eq!(protocol.get_output_count("A")?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_leaf_limits() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_leaf_limits").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = Protocol::new("limited");
+        protocol.set_limits(
+            ProtocolLimits::default()
+                .with_max_leaves_per_output(3)
+                .with_max_total_leaves(5),
+        )?;
+        protocol.add_transaction("A")?;
+
+        let output = OutputType::taproot(1_000, &public_key, &leaves(&tc, 4)?)?;
+        assert!(matches!(
+            protocol.add_transaction_output("A", &output),
+            Err(ProtocolBuilderError::LimitExceeded(
+                ProtocolLimit::LeavesPerOutput,
+                4,
+                3
+            ))
+        ));
+
+        let builder = ProtocolBuilder {};
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            1_000,
+            &public_key,
+            &leaves(&tc, 3)?,
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+
+        let output = OutputType::taproot(1_000, &public_key, &leaves(&tc, 3)?)?;
+        assert!(matches!(
+            protocol.add_transaction_output("A", &output),
+            Err(ProtocolBuilderError::LimitExceeded(
+                ProtocolLimit::TotalLeaves,
+                6,
+                5
+            ))
+        ));
+
+        // Replacing an output only counts the leaves of the new one
+        let output = OutputType::taproot(1_000, &public_key, &leaves(&tc, 2)?)?;
+        protocol.update_output("A", 0, &output)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_limits_on_existing_protocol() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_set_limits_on_existing_protocol").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = Protocol::new("limited");
+        protocol.add_transaction("A")?;
+        protocol.add_transaction_output(
+            "A",
+            &OutputType::taproot(1_000, &public_key, &leaves(&tc, 4)?)?,
+        )?;
+
+        let result = protocol.set_limits(ProtocolLimits::default().with_max_total_leaves(2));
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::LimitExceeded(
+                ProtocolLimit::TotalLeaves,
+                4,
+                2
+            ))
+        ));
+        assert_eq!(protocol.limits(), &ProtocolLimits::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialized_size_limit() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_serialized_size_limit").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = Protocol::new("untrusted");
+        protocol.add_transaction("A")?;
+        protocol.add_transaction_output(
+            "A",
+            &OutputType::taproot(1_000, &public_key, &leaves(&tc, 4)?)?,
+        )?;
+        let bytes = protocol.to_bytes(SerializationFormat::Json)?;
+
+        let limits = ProtocolLimits::default().with_max_serialized_size(bytes.len() - 1);
+        assert!(matches!(
+            Protocol::from_bytes_with_limits(&bytes, &limits),
+            Err(ProtocolBuilderError::LimitExceeded(
+                ProtocolLimit::SerializedSize,
+                _,
+                _
+            ))
+        ));
+
+        let limits = ProtocolLimits::default()
+            .with_max_serialized_size(bytes.len() * 2)
+            .with_max_transactions(1);
+        let decoded = Protocol::from_bytes_with_limits(&bytes, &limits)?;
+        assert_eq!(decoded.limits(), &limits);
+
+        let limits = ProtocolLimits::default().with_max_leaves_per_output(2);
+        assert!(matches!(
+            Protocol::from_bytes_with_limits(&bytes, &limits),
+            Err(ProtocolBuilderError::LimitExceeded(
+                ProtocolLimit::LeavesPerOutput,
+                4,
+                2
+            ))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{hashes::Hash, key::Parity, OutPoint, PublicKey, ScriptBuf, Sequence};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::{GraphError, ProtocolBuilderError},
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            dust::DustPolicy,
+            input::SpendMode,
+            limits::ProtocolLimits,
+            output::OutputType,
+        },
+    };
+
+    fn leaf(public_key: &PublicKey) -> ProtocolScript {
+        ProtocolScript::new(ScriptBuf::from(vec![0x01]), public_key, SignMode::Single)
+    }
+
+    // Dispute sub-DAG whose first transaction has an open input to be connected by the host.
+    fn dispute(tc: &TestContext, public_key: &PublicKey) -> Result<Protocol, ProtocolBuilderError> {
+        let mut dispute = Protocol::new("dispute");
+        dispute.add_transaction_input(
+            Hash::all_zeros(),
+            0,
+            "challenge",
+            Sequence::ENABLE_RBF_NO_LOCKTIME,
+            &SpendMode::ScriptsOnly,
+            &tc.tr_sighash_type(),
+        )?;
+
+        ProtocolBuilder {}.add_taproot_connection(
+            &mut dispute,
+            "response",
+            "challenge",
+            1000,
+            public_key,
+            &[leaf(public_key)],
+            &SpendMode::ScriptsOnly,
+            "response",
+            &tc.tr_sighash_type(),
+        )?;
+
+        Ok(dispute)
+    }
+
+    fn host(public_key: &PublicKey) -> Result<Protocol, ProtocolBuilderError> {
+        let mut host = Protocol::new("host");
+        host.add_transaction_output(
+            "start",
+            &OutputType::taproot(2000, public_key, &[leaf(public_key)])?,
+        )?;
+        Ok(host)
+    }
+
+    #[test]
+    fn test_merge_and_connect_subprotocol() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_merge_and_connect_subprotocol").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = host(&public_key)?;
+        protocol.merge(dispute(&tc, &public_key)?, "dispute")?;
+
+        let mut names = protocol.transaction_names();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["dispute_challenge", "dispute_response", "start"]
+        );
+
+        protocol.add_connection(
+            "dispute_entry",
+            "start",
+            OutputSpec::Index(0),
+            "dispute_challenge",
+            InputSpec::Index(0),
+            None,
+            None,
+        )?;
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let start = protocol.transaction_by_name("start")?;
+        let challenge = protocol.transaction_by_name("dispute_challenge")?;
+        let response = protocol.transaction_by_name("dispute_response")?;
+
+        assert_eq!(
+            challenge.input[0].previous_output,
+            OutPoint::new(start.compute_txid(), 0)
+        );
+        assert_eq!(
+            response.input[0].previous_output,
+            OutPoint::new(challenge.compute_txid(), 0)
+        );
+        assert!(protocol
+            .input_taproot_script_spend_signature("dispute_challenge", 0, 0)?
+            .is_some());
+
+        let result = protocol.add_connection(
+            "dispute_entry",
+            "start",
+            OutputSpec::Index(0),
+            "dispute_challenge",
+            InputSpec::Index(0),
+            None,
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::InputAlreadyConnected(_, 0)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_name_collision() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_merge_name_collision").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = host(&public_key)?;
+        protocol.merge(dispute(&tc, &public_key)?, "first")?;
+        protocol.merge(dispute(&tc, &public_key)?, "second")?;
+
+        let result = protocol.merge(dispute(&tc, &public_key)?, "first");
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::TransactionAlreadyExists(_)
+            ))
+        ));
+        assert_eq!(protocol.transaction_names().len(), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_constant_conflict() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_merge_constant_conflict").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = host(&public_key)?;
+        protocol.set_constant("CHALLENGE_BLOCKS", 144u16)?;
+
+        let mut sub = dispute(&tc, &public_key)?;
+        sub.set_constant("CHALLENGE_BLOCKS", 100u16)?;
+
+        let result = protocol.merge(sub, "dispute");
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::MergeConstantConflict(_))
+        ));
+        assert_eq!(protocol.transaction_names().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_owners_and_unspendable_proofs() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_merge_owners_and_unspendable_proofs").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut sub = dispute(&tc, &public_key)?;
+        sub.set_input_owner("response", 0, "operator")?;
+        let internal_key = sub.create_unspendable_key()?;
+        ProtocolBuilder {}.add_taproot_connection(
+            &mut sub,
+            "finish",
+            "response",
+            500,
+            &PublicKey::from(internal_key.public_key(Parity::Even)),
+            &[leaf(&public_key)],
+            &SpendMode::ScriptsOnly,
+            "finish",
+            &tc.tr_sighash_type(),
+        )?;
+
+        let mut protocol = host(&public_key)?;
+        protocol.merge(sub, "dispute")?;
+
+        assert_eq!(
+            protocol.owner("dispute_response", 0, None),
+            Some("operator")
+        );
+        assert_eq!(protocol.owner("response", 0, None), None);
+        assert!(protocol.check_unspendable_proofs().is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_policy_conflicts() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_merge_policy_conflicts").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let conflict = |sub: Protocol, host: &mut Protocol| match host.merge(sub, "dispute") {
+            Err(ProtocolBuilderError::MergePolicyConflict(policy)) => Some(policy),
+            _ => None,
+        };
+
+        // Policies of the merged protocol must be unset or match the host
+        let mut sub = dispute(&tc, &public_key)?;
+        sub.set_dust_policy(DustPolicy::reject())?;
+        assert_eq!(
+            conflict(sub, &mut host(&public_key)?),
+            Some("dust policy".to_string())
+        );
+
+        let mut sub = dispute(&tc, &public_key)?;
+        sub.set_limits(ProtocolLimits::default().with_max_transactions(10))?;
+        assert_eq!(
+            conflict(sub, &mut host(&public_key)?),
+            Some("limits".to_string())
+        );
+
+        let mut sub = dispute(&tc, &public_key)?;
+        sub.require_amount_conservation(true)?;
+        assert_eq!(
+            conflict(sub, &mut host(&public_key)?),
+            Some("amount conservation requirement".to_string())
+        );
+
+        let mut sub = dispute(&tc, &public_key)?;
+        sub.require_unspendable_proofs(true)?;
+        assert_eq!(
+            conflict(sub, &mut host(&public_key)?),
+            Some("unspendable proofs requirement".to_string())
+        );
+
+        // State tied to the transactions of the merged protocol is not carried over
+        let mut sub = dispute(&tc, &public_key)?;
+        sub.record_history();
+        assert_eq!(
+            conflict(sub, &mut host(&public_key)?),
+            Some("history".to_string())
+        );
+
+        let mut sub = dispute(&tc, &public_key)?;
+        sub.freeze();
+        sub.unfreeze("rework")?;
+        assert_eq!(
+            conflict(sub, &mut host(&public_key)?),
+            Some("audit trail".to_string())
+        );
+
+        // Matching policies are kept, unset ones take the policies of the host
+        let mut protocol = host(&public_key)?;
+        protocol
+            .set_dust_policy(DustPolicy::reject())?
+            .require_amount_conservation(true)?;
+        let mut sub = dispute(&tc, &public_key)?;
+        sub.set_dust_policy(DustPolicy::reject())?;
+        protocol.merge(sub, "dispute")?;
+        assert_eq!(protocol.dust_policy(), Some(&DustPolicy::reject()));
+        assert_eq!(protocol.transaction_names().len(), 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolRegistry},
+        errors::ProtocolBuilderError,
+        tests::utils::TestContext,
+        types::{input::SpendMode, output::OutputType, registry::ProtocolStatus},
+    };
+
+    fn protocol(tc: &TestContext, name: &str) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new(name);
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_protocol_registry() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_protocol_registry").unwrap();
+        let storage = Rc::new(tc.new_storage("registry"));
+        let registry = ProtocolRegistry::new(storage.clone());
+
+        let mut first = protocol(&tc, "first")?;
+        first.set_input_owner("A", 0, "alice")?;
+        let record = registry.save(&first)?;
+        assert_eq!(record.participants, vec!["alice".to_string()]);
+        assert_eq!(record.status, ProtocolStatus::Created);
+
+        let second = protocol(&tc, "second")?;
+        registry.save_with_participants(&second, vec!["carol".to_string()])?;
+
+        let names: Vec<String> = registry.list()?.into_iter().map(|r| r.name).collect();
+        assert_eq!(names, vec!["first".to_string(), "second".to_string()]);
+        assert!(registry.load("second")?.is_some());
+
+        // Protocols saved outside the registry are not managed by it
+        protocol(&tc, "other")?.save(storage.clone())?;
+        assert!(registry.load("other")?.is_none());
+        assert!(registry.record("other")?.is_none());
+
+        registry.tag("first", "mainnet")?;
+        registry.set_status("second", ProtocolStatus::Running)?;
+        assert_eq!(registry.with_tag("mainnet")?.len(), 1);
+        assert_eq!(
+            registry.with_status(ProtocolStatus::Running)?[0].name,
+            "second"
+        );
+
+        // Saving again keeps the status and tags
+        registry.save(&first)?;
+        let record = registry.record("first")?.unwrap();
+        assert!(record.has_tag("mainnet"));
+        registry.untag("first", "mainnet")?;
+        assert!(registry.with_tag("mainnet")?.is_empty());
+
+        assert!(matches!(
+            registry.tag("other", "mainnet"),
+            Err(ProtocolBuilderError::UnregisteredProtocol(name)) if name == "other"
+        ));
+
+        assert!(registry.delete("first")?);
+        assert!(!registry.delete("first")?);
+        assert!(Protocol::load("first", storage.clone())?.is_none());
+        assert_eq!(registry.list()?.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use bitcoin::{hashes::Hash, Transaction, Txid};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        broadcast::{Broadcaster, ChainState, ChainTime},
+        builder::Protocol,
+        errors::{BroadcastError, ProtocolBuilderError},
+        executor::{NextTransaction, ProtocolRunner, RunnerEvent, TransactionState},
+        testing::chain::MockChain,
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            external::ExternalTx,
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    // Mock node adding the broadcasted transactions to its mempool
+    #[derive(Default)]
+    struct MockNode {
+        chain: RefCell<MockChain>,
+        reject: RefCell<Option<String>>,
+    }
+
+    impl ChainTime for MockNode {
+        fn current_height(&self) -> Result<u32, BroadcastError> {
+            self.chain.borrow().current_height()
+        }
+
+        fn median_time(&self) -> Result<u32, BroadcastError> {
+            self.chain.borrow().median_time()
+        }
+    }
+
+    impl ChainState for MockNode {
+        fn confirmations(&self, txid: &Txid) -> Result<Option<u32>, BroadcastError> {
+            self.chain.borrow().confirmations(txid)
+        }
+    }
+
+    impl Broadcaster for MockNode {
+        fn broadcast(&self, transaction: &Transaction) -> Result<Txid, BroadcastError> {
+            if let Some(reason) = self.reject.borrow_mut().take() {
+                return Err(BroadcastError::Rejected(reason));
+            }
+            let txid = transaction.compute_txid();
+            self.chain.borrow_mut().add_to_mempool(txid);
+            Ok(txid)
+        }
+    }
+
+    // EXT -> A -> B, or C once A has 10 confirmations
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("runner");
+        protocol.add_external_tx(
+            ExternalTx::new("EXT", Txid::all_zeros())
+                .with_known_output(OutputType::segwit_key(10_000, &public_key)?),
+        )?;
+        protocol.add_connection(
+            "EXT_A",
+            "EXT",
+            OutputSpec::Index(0),
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+        protocol.add_transaction_output("A", &OutputType::segwit_key(9_000, &public_key)?)?;
+        for (to, timelock) in [("B", None), ("C", Some(10))] {
+            protocol.add_transaction_output(to, &OutputType::segwit_key(8_000, &public_key)?)?;
+            protocol.add_connection(
+                &format!("A_{}", to),
+                "A",
+                OutputSpec::Index(0),
+                to,
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+                timelock,
+                None,
+            )?;
+        }
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    fn txid(protocol: &Protocol, name: &str) -> Txid {
+        protocol.transaction_by_name(name).unwrap().compute_txid()
+    }
+
+    #[test]
+    fn test_protocol_runner() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_protocol_runner").unwrap();
+        let protocol = protocol(&tc)?;
+        let node = MockNode::default();
+
+        let decider = |_: &Protocol, name: &str| match name {
+            "A" => Ok(vec![NextTransaction::new("B")]),
+            _ => Ok(vec![]),
+        };
+        let mut runner = ProtocolRunner::new(&protocol, tc.key_manager(), decider)?;
+        runner.schedule(NextTransaction::new("A"))?;
+        runner.schedule(NextTransaction::new("C"))?;
+        assert_eq!(runner.state("A"), TransactionState::Scheduled);
+
+        // A waits for the funding transaction
+        assert!(runner.step(&node)?.is_empty());
+
+        node.chain.borrow_mut().mine(Txid::all_zeros());
+        *node.reject.borrow_mut() = Some("mempool full".to_string());
+        assert_eq!(
+            runner.step(&node)?,
+            vec![
+                RunnerEvent::Confirmed("EXT".to_string()),
+                RunnerEvent::Rejected("A".to_string(), "mempool full".to_string()),
+            ]
+        );
+        assert_eq!(
+            runner.step(&node)?,
+            vec![RunnerEvent::Broadcasted(
+                "A".to_string(),
+                txid(&protocol, "A")
+            )]
+        );
+        assert_eq!(runner.state("A"), TransactionState::Unconfirmed);
+
+        // Once A confirms the decider picks B, and C (still timelocked) can no longer be mined
+        node.chain.borrow_mut().mine(txid(&protocol, "A"));
+        assert_eq!(
+            runner.step(&node)?,
+            vec![
+                RunnerEvent::Confirmed("A".to_string()),
+                RunnerEvent::Broadcasted("B".to_string(), txid(&protocol, "B")),
+            ]
+        );
+        assert_eq!(
+            runner.step(&node)?,
+            vec![RunnerEvent::Abandoned("C".to_string())]
+        );
+        assert!(!runner.is_settled());
+
+        // B is evicted, so it is sent again and C can be mined once more
+        node.chain.borrow_mut().evict(txid(&protocol, "B"));
+        assert_eq!(
+            runner.step(&node)?,
+            vec![
+                RunnerEvent::Dropped("B".to_string()),
+                RunnerEvent::Revived("C".to_string()),
+                RunnerEvent::Broadcasted("B".to_string(), txid(&protocol, "B")),
+            ]
+        );
+        assert_eq!(
+            runner.step(&node)?,
+            vec![RunnerEvent::Abandoned("C".to_string())]
+        );
+
+        node.chain.borrow_mut().mine(txid(&protocol, "B"));
+        runner.step(&node)?;
+        assert_eq!(runner.state("B"), TransactionState::Confirmed);
+        assert_eq!(runner.state("C"), TransactionState::Abandoned);
+        assert!(runner.is_settled());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+#[cfg(test)]
+mod tests {
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        tests::utils::TestContext,
+        types::{
+            input::SighashType,
+            spec::{ProtocolSpec, SpecFormat},
+            OutputType,
+        },
+    };
+
+    const SPEC: &str = r#"
+keys:
+  - name: funding
+    index: 0
+    key_type: p2wpkh
+  - name: alice
+    index: 1
+  - name: bob
+    index: 2
+connections:
+  - name: funding
+    from: EXT
+    to: A
+    txid: "0000000000000000000000000000000000000000000000000000000000000000"
+    spend_mode: Segwit
+    output:
+      type: segwit_key
+      value: 100000
+      key: funding
+  - name: A_B
+    from: A
+    to: B
+    output:
+      type: taproot
+      value: 90000
+      internal_key: alice
+      label: dispute
+      leaves:
+        - template: raw
+          script: "51"
+          key: alice
+        - template: raw
+          script: "52"
+          key: bob
+          sign_mode: Skip
+  - name: A_C
+    from: A
+    to: C
+    sighash_type: SIGHASH_SINGLE
+    output:
+      type: label
+      label: dispute
+outputs:
+  - transaction: B
+    output:
+      type: segwit_key
+      value: 80000
+      key: bob
+  - transaction: C
+    output:
+      type: op_return
+      data: "cafe"
+"#;
+
+    #[test]
+    fn test_protocol_from_yaml_spec() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_protocol_from_yaml_spec").unwrap();
+        let key_manager = tc.key_manager();
+        let spec = ProtocolSpec::parse(SPEC, SpecFormat::Yaml)?;
+
+        let mut protocol = Protocol::from_spec("spec", &spec, key_manager)?;
+        protocol.build(key_manager, "")?;
+
+        assert_eq!(protocol.transaction_names().len(), 4);
+        assert_eq!(protocol.spenders("A", 0)?, vec!["B", "C"]);
+        assert_eq!(protocol.output_index("A", "dispute")?, 0);
+
+        let alice = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let (output, leaves) = protocol.get_script_from_output("A", 0)?;
+        assert!(
+            matches!(output, OutputType::Taproot { internal_key, .. } if *internal_key == alice)
+        );
+        assert_eq!(leaves.len(), 2);
+        assert_eq!(leaves[1].get_script().as_bytes(), &[0x52]);
+
+        // The sighash type follows the spent output
+        let inputs = protocol.inputs("C")?;
+        assert!(matches!(
+            inputs[0].sighash_type(),
+            SighashType::Taproot(bitcoin::TapSighashType::Single)
+        ));
+        assert!(matches!(
+            protocol.inputs("A")?[0].sighash_type(),
+            SighashType::Ecdsa(bitcoin::EcdsaSighashType::All)
+        ));
+
+        let b = protocol.transaction_by_name("B")?;
+        assert_eq!(b.output[0].value.to_sat(), 80_000);
+        let c = protocol.transaction_by_name("C")?;
+        assert_eq!(c.output.len(), 1);
+        assert_eq!(c.output[0].value.to_sat(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_from_json_spec() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_protocol_from_json_spec").unwrap();
+        let key_manager = tc.key_manager();
+
+        // Same protocol as the YAML spec, in JSON
+        let yaml = ProtocolSpec::parse(SPEC, SpecFormat::Yaml)?;
+        let json = serde_json::to_string(&yaml).unwrap();
+        let spec = ProtocolSpec::parse(&json, SpecFormat::Json)?;
+
+        let mut from_yaml = Protocol::from_spec("spec", &yaml, key_manager)?;
+        let mut from_json = Protocol::from_spec("spec", &spec, key_manager)?;
+        from_yaml.build(key_manager, "")?;
+        from_json.build(key_manager, "")?;
+
+        for name in from_yaml.transaction_names() {
+            assert_eq!(
+                from_yaml.transaction_by_name(&name)?.compute_txid(),
+                from_json.transaction_by_name(&name)?.compute_txid()
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_invalid_spec() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_reject_invalid_spec").unwrap();
+        let key_manager = tc.key_manager();
+
+        let unknown_key = SPEC.replace(
+            "key: bob\n          sign_mode",
+            "key: carol\n          sign_mode",
+        );
+        let spec = ProtocolSpec::parse(&unknown_key, SpecFormat::Yaml)?;
+        assert!(matches!(
+            Protocol::from_spec("spec", &spec, key_manager),
+            Err(ProtocolBuilderError::InvalidSpec(message)) if message == "unknown key carol"
+        ));
+
+        let unknown_template = SPEC.replace("template: raw", "template: unknown");
+        assert!(matches!(
+            ProtocolSpec::parse(&unknown_template, SpecFormat::Yaml),
+            Err(ProtocolBuilderError::InvalidSpec(_))
+        ));
+
+        let bad_sighash = SPEC.replace("SIGHASH_SINGLE", "SIGHASH_SOME");
+        let spec = ProtocolSpec::parse(&bad_sighash, SpecFormat::Yaml)?;
+        assert!(matches!(
+            Protocol::from_spec("spec", &spec, key_manager),
+            Err(ProtocolBuilderError::InvalidSpec(_))
+        ));
+
+        Ok(())
+    }
+}
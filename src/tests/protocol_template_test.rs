@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{PublicKey, ScriptBuf};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Param, ProtocolTemplate, TemplateOutput, TemplateParams},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::input::SpendMode,
+    };
+
+    fn leaf(public_key: &PublicKey) -> ProtocolScript {
+        ProtocolScript::new(ScriptBuf::from(vec![0x01]), public_key, SignMode::Single)
+    }
+
+    fn challenge_response(tc: &TestContext) -> ProtocolTemplate {
+        let mut template = ProtocolTemplate::new("challenge_response");
+        template
+            .add_output(
+                "challenge_{operator}",
+                TemplateOutput::SegwitPublicKey {
+                    value: Param::placeholder("stake"),
+                    public_key: Param::placeholder("operator_key"),
+                },
+            )
+            .add_connection(
+                "response_{operator}",
+                "challenge_{operator}",
+                TemplateOutput::Taproot {
+                    value: Param::placeholder("stake"),
+                    internal_key: Param::placeholder("operator_key"),
+                    leaves: vec![Param::placeholder("response_script")],
+                },
+                "response_{operator}",
+                &tc.tr_sighash_type(),
+                &SpendMode::ScriptsOnly,
+                None,
+            );
+        template
+    }
+
+    #[test]
+    fn test_instantiate_template_for_each_operator() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_instantiate_template_for_each_operator").unwrap();
+        let template = challenge_response(&tc);
+
+        for (index, operator) in ["alice", "bob"].iter().enumerate() {
+            let public_key = tc
+                .key_manager()
+                .derive_keypair(BitcoinKeyType::P2tr, index as u32)?;
+            let stake = 1000 * (index as u64 + 1);
+
+            let params = TemplateParams::new()
+                .with_name("operator", operator)
+                .with_key("operator_key", &public_key)
+                .with_amount("stake", stake)
+                .with_script("response_script", &leaf(&public_key));
+
+            let protocol = template.instantiate(operator, &params)?;
+
+            let mut names = protocol.transaction_names();
+            names.sort();
+            assert_eq!(
+                names,
+                vec![
+                    format!("challenge_{}", operator),
+                    format!("response_{}", operator)
+                ]
+            );
+
+            let challenge = protocol.transaction_by_name(&format!("challenge_{}", operator))?;
+            assert_eq!(challenge.output.len(), 2);
+            assert_eq!(challenge.output[0].value.to_sat(), stake);
+            assert_eq!(challenge.output[1].value.to_sat(), stake);
+
+            let script = protocol.get_script_to_spend(&format!("response_{}", operator), 0, 0)?;
+            assert_eq!(script.get_verifying_key(), Some(public_key));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_instantiate_template_missing_parameter() {
+        let tc = TestContext::new("test_instantiate_template_missing_parameter").unwrap();
+        let template = challenge_response(&tc);
+
+        let params = TemplateParams::new()
+            .with_name("operator", "alice")
+            .with_amount("stake", 1000);
+
+        let result = template.instantiate("alice", &params);
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::MissingTemplateParameter(name)) if name == "operator_key"
+        ));
+
+        let result = template.instantiate("alice", &TemplateParams::new());
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::MissingTemplateParameter(name)) if name == "operator"
+        ));
+    }
+}
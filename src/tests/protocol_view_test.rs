@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::ScriptBuf;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolView},
+        errors::{GraphError, ProtocolBuilderError, SerializationError},
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+            serialization::SerializationFormat,
+        },
+    };
+
+    // EXT -> A, A spends to B or C through the leaves of a taproot output
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let taproot_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaves = [
+            ProtocolScript::new(ScriptBuf::from(vec![0x51]), &taproot_key, SignMode::Skip),
+            ProtocolScript::new(ScriptBuf::from(vec![0x52]), &taproot_key, SignMode::Skip),
+        ];
+
+        let mut protocol = Protocol::new("view");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(20_000, &public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        protocol
+            .add_transaction_output("A", &OutputType::taproot(10_000, &taproot_key, &leaves)?)?;
+        for (to, leaf) in [("B", 0), ("C", 1)] {
+            protocol.add_connection(
+                &format!("A_{}", to),
+                "A",
+                OutputSpec::Index(0),
+                to,
+                InputSpec::Auto(tc.tr_sighash_type(), SpendMode::Script { leaf }),
+                None,
+                None,
+            )?;
+            protocol.add_transaction_output(to, &OutputType::segwit_key(9_000, &public_key)?)?;
+        }
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_protocol_view() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_protocol_view").unwrap();
+        let mut protocol = protocol(&tc)?;
+        let bytes = protocol.to_bytes(SerializationFormat::Json)?;
+        let view = ProtocolView::from_bytes(&bytes)?;
+
+        assert_eq!(view.name(), "view");
+        assert_eq!(view.transaction_names(), vec!["EXT", "A", "B", "C"]);
+        assert!(view.is_external("EXT")?);
+        assert!(!view.contains_transaction("D"));
+
+        for name in ["A", "B", "C"] {
+            assert_eq!(
+                view.txid(name)?,
+                protocol.transaction_by_name(name)?.compute_txid()
+            );
+            assert_eq!(
+                view.sighashes(name, 0)?[0],
+                protocol.get_hashed_message(name, 0, 0)?
+            );
+        }
+
+        let scripts = view.scripts("A", 0)?;
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(scripts[1].1.get_script(), &ScriptBuf::from(vec![0x52]));
+        assert_eq!(view.output_count("A")?, 1);
+        assert_eq!(view.input_count("B")?, 1);
+
+        assert_eq!(view.spenders("A", 0)?, vec!["B", "C"]);
+        assert_eq!(view.dependencies("EXT")?, vec![("A", 0)]);
+        assert_eq!(view.connection_name("C", 0)?, Some("A_C"));
+        assert!(view.spenders("B", 0)?.is_empty());
+
+        assert!(matches!(
+            view.transaction("D"),
+            Err(ProtocolBuilderError::GraphBuildingError(
+                GraphError::MissingTransaction(_)
+            ))
+        ));
+        assert!(matches!(
+            view.output("A", 1),
+            Err(ProtocolBuilderError::MissingOutput(_, 1))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_view_formats() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_protocol_view_formats").unwrap();
+        let protocol = protocol(&tc)?;
+
+        // Plain JSON, as stored by `Protocol::save`
+        let json = serde_json::to_string(&protocol).unwrap();
+        assert_eq!(ProtocolView::from_json(&json)?.transaction_names().len(), 4);
+
+        let bytes = protocol.to_bytes(SerializationFormat::Bincode)?;
+        assert!(matches!(
+            ProtocolView::from_bytes(&bytes),
+            Err(ProtocolBuilderError::SerializationError(
+                SerializationError::UnsupportedView(_)
+            ))
+        ));
+
+        Ok(())
+    }
+}
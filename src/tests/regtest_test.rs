@@ -0,0 +1,132 @@
+#[cfg(all(test, feature = "regtest"))]
+mod tests {
+    use bitcoin::{opcodes::all::OP_CHECKSIG, script::Builder, ScriptBuf, XOnlyPublicKey};
+    use bitcoincore_rpc::RpcApi;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        config::Config,
+        errors::{BroadcastError, ProtocolBuilderError},
+        executor::NextTransaction,
+        scripts::{ProtocolScript, SignMode},
+        testing::regtest::RegtestHarness,
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            external::ExternalTx,
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    // Needs the regtest node of config/development.json, running with -txindex
+    #[test]
+    #[ignore]
+    fn test_regtest_timelocked_chain() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_regtest_timelocked_chain").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let config = Config::new().unwrap();
+
+        let harness = RegtestHarness::new(&config.rpc)?;
+        harness.mine(101)?;
+
+        let funding_output = OutputType::segwit_key(100_000, &public_key)?;
+        let (funding, vout) = harness.fund(&funding_output)?;
+
+        let mut protocol = Protocol::new("regtest");
+        protocol.add_external_tx(
+            ExternalTx::new("funding", funding.compute_txid())
+                .with_opaque_outputs(vout)
+                .with_known_output(funding_output),
+        )?;
+        protocol.add_connection(
+            "funding",
+            "funding",
+            OutputSpec::Last,
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+        protocol.add_connection(
+            "A_B",
+            "A",
+            OutputSpec::Auto(OutputType::segwit_key(99_000, &public_key)?),
+            "B",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            Some(5),
+            None,
+        )?;
+        protocol.add_transaction_output("B", &OutputType::segwit_key(98_000, &public_key)?)?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let txids = harness.run(
+            &protocol,
+            &[NextTransaction::new("A"), NextTransaction::new("B")],
+            tc.key_manager(),
+        )?;
+
+        // B is broadcast once A is 5 blocks deep, and mined in the next block
+        assert_eq!(harness.assert_confirmations("A", &txids[0], 6)?, 6);
+        harness.assert_confirmations("B", &txids[1], 1)?;
+
+        Ok(())
+    }
+
+    // Needs the regtest node of config/development.json
+    #[test]
+    #[ignore]
+    fn test_regtest_descriptor_round_trip() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_regtest_descriptor_round_trip").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let config = Config::new().unwrap();
+        let harness = RegtestHarness::new(&config.rpc)?;
+
+        let pk = ProtocolScript::new(
+            Builder::new()
+                .push_x_only_key(&XOnlyPublicKey::from(public_key))
+                .push_opcode(OP_CHECKSIG)
+                .into_script(),
+            &public_key,
+            SignMode::Single,
+        );
+        let wsh_pk = ProtocolScript::new(
+            Builder::new()
+                .push_key(&public_key)
+                .push_opcode(OP_CHECKSIG)
+                .into_script(),
+            &public_key,
+            SignMode::Single,
+        );
+        let op_true =
+            ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Single);
+
+        let outputs = [
+            OutputType::segwit_key(1_000, &public_key)?,
+            OutputType::segwit_script(1_000, &wsh_pk)?,
+            OutputType::segwit_script(1_000, &op_true)?,
+            OutputType::taproot(1_000, &public_key, &[])?,
+            OutputType::taproot(1_000, &public_key, &[pk.clone(), pk.clone(), pk])?,
+            OutputType::taproot(1_000, &public_key, &[op_true])?,
+        ];
+
+        // The node parses every descriptor back into the script pubkey of the output
+        for output in outputs.iter() {
+            let descriptor = output.to_descriptor().unwrap();
+            let addresses = harness
+                .node()
+                .derive_addresses(&descriptor, None)
+                .map_err(|error| BroadcastError::Unavailable(error.to_string()))?;
+            assert_eq!(addresses.len(), 1, "{}", descriptor);
+            assert_eq!(
+                addresses[0].assume_checked_ref().script_pubkey().as_bytes(),
+                output.get_script_pubkey().as_bytes(),
+                "{}",
+                descriptor
+            );
+        }
+
+        Ok(())
+    }
+}
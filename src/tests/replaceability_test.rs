@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{ScriptBuf, Sequence};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    #[test]
+    fn test_toggle_replaceability() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_toggle_replaceability").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaf = ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Skip);
+
+        // EXT -> A -> B, where B spends A after 10 blocks
+        let mut protocol = Protocol::new("rbf");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &public_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        protocol.add_connection(
+            "A_B",
+            "A",
+            OutputSpec::Auto(OutputType::taproot(5_000, &public_key, &[leaf])?),
+            "B",
+            InputSpec::Auto(tc.tr_sighash_type(), SpendMode::ScriptsOnly),
+            Some(10),
+            None,
+        )?;
+        protocol.build(tc.key_manager(), "")?;
+        assert!(protocol.is_replaceable("A")?);
+
+        let rebuilt = protocol.set_replaceable("A", false, tc.key_manager(), "")?;
+        assert_eq!(rebuilt, vec!["A".to_string(), "B".to_string()]);
+        assert!(!protocol.is_replaceable("A")?);
+
+        let a = protocol.transaction_by_name("A")?;
+        assert_eq!(a.input[0].sequence, Sequence::MAX);
+        let txid = a.compute_txid();
+        assert_eq!(
+            protocol.transaction_by_name("B")?.input[0]
+                .previous_output
+                .txid,
+            txid
+        );
+
+        // Inputs with a relative timelock always signal replaceability
+        assert!(matches!(
+            protocol.set_replaceable("B", false, tc.key_manager(), ""),
+            Err(ProtocolBuilderError::CsvConstrainedInput(name, 0)) if name == "B"
+        ));
+        protocol.set_replaceable("B", true, tc.key_manager(), "")?;
+        assert_eq!(
+            protocol.transaction_by_name("B")?.input[0].sequence,
+            Sequence::from_height(10)
+        );
+
+        protocol.set_replaceable("A", true, tc.key_manager(), "")?;
+        assert_eq!(
+            protocol.transaction_by_name("A")?.input[0].sequence,
+            Sequence::ENABLE_RBF_NO_LOCKTIME
+        );
+
+        Ok(())
+    }
+}
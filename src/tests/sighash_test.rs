@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        hashes::Hash,
+        opcodes::all::{OP_CHECKSIG, OP_DROP},
+        script::Builder,
+        EcdsaSighashType, PublicKey, ScriptBuf, TapSighashType, XOnlyPublicKey,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        helpers::sighash::raw_sighash,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::{SighashType, SpendMode},
+            output::OutputType,
+        },
+    };
+
+    fn leaf(public_key: &PublicKey, tag: u8) -> ProtocolScript {
+        let script = Builder::new()
+            .push_int(tag as i64)
+            .push_opcode(OP_DROP)
+            .push_x_only_key(&XOnlyPublicKey::from(*public_key))
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        ProtocolScript::new(script, public_key, SignMode::Single)
+    }
+
+    // EXT -> A (P2WPKH) -> B (taproot, key path and two leaves) -> C (P2WSH)
+    #[test]
+    fn test_raw_sighash_matches_protocol() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_raw_sighash_matches_protocol").unwrap();
+        let segwit_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let taproot_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let leaves = [leaf(&taproot_key, 1), leaf(&taproot_key, 2)];
+        let witness_script = leaf(&segwit_key, 3);
+
+        let mut protocol = Protocol::new("sighash");
+        let builder = ProtocolBuilder {};
+        builder.add_external_connection(
+            &mut protocol,
+            "EXT",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::segwit_key(10_000, &segwit_key)?),
+            "A",
+            InputSpec::Auto(
+                SighashType::Ecdsa(EcdsaSighashType::SinglePlusAnyoneCanPay),
+                SpendMode::Segwit,
+            ),
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            9_000,
+            &taproot_key,
+            &leaves,
+            &SpendMode::All {
+                key_path_sign: SignMode::Single,
+            },
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.add_connection(
+            "B_C",
+            "B",
+            OutputSpec::Auto(OutputType::segwit_script(8_000, &witness_script)?),
+            "C",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+        protocol.build(tc.key_manager(), "")?;
+
+        let expected = |name: &str| -> Result<_, ProtocolBuilderError> {
+            Ok(protocol.inputs(name)?[0].hashed_messages())
+        };
+        let sighash = |name: &str, script: Option<&ScriptBuf>, sighash_type: &SighashType| {
+            raw_sighash(
+                protocol.transaction_by_name(name).unwrap(),
+                &protocol.graph().get_prevouts(name).unwrap(),
+                0,
+                script.map(|script| script.as_script()),
+                sighash_type,
+            )
+        };
+
+        let single_acp = SighashType::Ecdsa(EcdsaSighashType::SinglePlusAnyoneCanPay);
+        assert_eq!(Some(sighash("A", None, &single_acp)?), expected("A")?[0]);
+
+        let all = SighashType::Taproot(TapSighashType::All);
+        for (index, leaf) in leaves.iter().enumerate() {
+            assert_eq!(
+                Some(sighash("B", Some(leaf.get_script()), &all)?),
+                expected("B")?[index]
+            );
+        }
+        assert_eq!(Some(sighash("B", None, &all)?), expected("B")?[2]);
+
+        let script = witness_script.get_script();
+        assert_eq!(
+            Some(sighash("C", Some(script), &tc.ecdsa_sighash_type())?),
+            expected("C")?[0]
+        );
+
+        // The sighash type must match the spent output
+        assert!(matches!(
+            sighash("B", None, &tc.ecdsa_sighash_type()),
+            Err(ProtocolBuilderError::InvalidOutputTypeForSighashType)
+        ));
+        assert!(matches!(
+            sighash("C", None, &tc.ecdsa_sighash_type()),
+            Err(ProtocolBuilderError::InvalidOutputTypeForSighashType)
+        ));
+
+        Ok(())
+    }
+}
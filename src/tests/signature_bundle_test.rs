@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::PublicKey;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        tests::utils::{checksig_leaf_with_signature, TestContext},
+        types::{
+            bundle::{SignatureBundle, SignatureFilter},
+            input::SpendMode,
+            output::OutputType,
+            serialization::SerializationFormat,
+        },
+    };
+
+    // EXT -> A -> B, the output of A has a leaf for each party
+    fn protocol(
+        tc: &TestContext,
+        alice: &PublicKey,
+        bob: &PublicKey,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("bundle");
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &funding_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            8_000,
+            alice,
+            &[
+                checksig_leaf_with_signature(alice),
+                checksig_leaf_with_signature(bob),
+            ],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_exchange_signature_bundle() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_exchange_signature_bundle").unwrap();
+        let alice = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let bob = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+
+        let mut signed = protocol(&tc, &alice, &bob)?;
+        signed.build_and_sign(tc.key_manager(), "")?;
+
+        let bundle = signed.export_signatures(&SignatureFilter::all())?;
+        assert_eq!(bundle.len(), 3);
+
+        let bundle = signed.export_signatures(&SignatureFilter::all().with_public_key(&bob))?;
+        assert_eq!(bundle.len(), 1);
+        assert_eq!(bundle.signatures[0].transaction_name, "B");
+        assert_eq!(bundle.signatures[0].signature_index, 1);
+
+        let bundle = signed.export_signatures(&SignatureFilter::all().with_transactions(&["B"]))?;
+        assert_eq!(bundle.len(), 2);
+
+        // The counterparty only builds the protocol and receives the signatures
+        let bytes = bundle.to_bytes(SerializationFormat::Cbor)?;
+        let received = SignatureBundle::from_bytes(&bytes)?;
+
+        let mut unsigned = protocol(&tc, &alice, &bob)?;
+        unsigned.build(tc.key_manager(), "")?;
+        unsigned.import_signatures(&received)?;
+
+        let report = unsigned.verify_all_signatures()?;
+        assert!(report.is_valid());
+        assert_eq!(report.checks.len(), 2);
+        assert_eq!(
+            unsigned.input_taproot_script_spend_signature("B", 0, 1)?,
+            signed.input_taproot_script_spend_signature("B", 0, 1)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_invalid_bundles() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_reject_invalid_bundles").unwrap();
+        let alice = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let bob = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+
+        let mut signed = protocol(&tc, &alice, &bob)?;
+        signed.build_and_sign(tc.key_manager(), "")?;
+        let bundle = signed.export_signatures(&SignatureFilter::all().with_transactions(&["B"]))?;
+
+        let mut unsigned = protocol(&tc, &alice, &bob)?;
+        unsigned.build(tc.key_manager(), "")?;
+
+        let mut renamed = bundle.clone();
+        renamed.protocol_name = "other".to_string();
+        assert!(matches!(
+            unsigned.import_signatures(&renamed),
+            Err(ProtocolBuilderError::SignatureBundleMismatch(_, _))
+        ));
+
+        let mut misattributed = bundle.clone();
+        misattributed.signatures[1].public_key = alice;
+        assert!(matches!(
+            unsigned.import_signatures(&misattributed),
+            Err(ProtocolBuilderError::UnexpectedSigningKey(_, 0, 1))
+        ));
+
+        // Alice's signature moved to the leaf of Bob
+        let mut swapped = bundle.clone();
+        swapped.signatures[1].signature = bundle.signatures[0].signature.clone();
+        assert!(matches!(
+            unsigned.import_signatures(&swapped),
+            Err(ProtocolBuilderError::InvalidImportedSignature(_, 0, 1))
+        ));
+
+        // Nothing is stored from rejected bundles
+        assert!(unsigned.verify_all_signatures()?.checks.is_empty());
+
+        Ok(())
+    }
+}
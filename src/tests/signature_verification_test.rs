@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::TapSighashType;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder, SignaturePath, SignatureStatus},
+        errors::ProtocolBuilderError,
+        scripts::SignMode,
+        tests::utils::{checksig_leaf_with_signature, TestContext},
+        types::{
+            input::{Signature, SpendMode},
+            output::OutputType,
+        },
+    };
+
+    // EXT -> A -> B, with B spending an output of A with two leaves and a key path
+    fn signed_protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let leaf_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+
+        let mut protocol = Protocol::new("verification");
+        let builder = ProtocolBuilder {};
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(10_000, &funding_key)?,
+            "A",
+            SpendMode::Segwit,
+        )?;
+        builder.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            8_000,
+            &internal_key,
+            &[
+                checksig_leaf_with_signature(&leaf_key),
+                checksig_leaf_with_signature(&internal_key),
+            ],
+            &SpendMode::All {
+                key_path_sign: SignMode::Single,
+            },
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_verify_all_signatures() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_verify_all_signatures").unwrap();
+        let protocol = signed_protocol(&tc)?;
+
+        let report = protocol.verify_all_signatures()?;
+        assert!(report.is_valid());
+
+        let paths: Vec<_> = report
+            .checks
+            .iter()
+            .map(|check| (check.transaction_name.as_str(), check.path.clone()))
+            .collect();
+        assert_eq!(paths.len(), 4);
+        assert!(paths.contains(&("A", SignaturePath::Segwit)));
+        assert!(paths.contains(&("B", SignaturePath::Leaf(0))));
+        assert!(paths.contains(&("B", SignaturePath::Leaf(1))));
+        assert!(paths.contains(&("B", SignaturePath::KeyPath)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_tampered_signatures() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_verify_tampered_signatures").unwrap();
+        let mut protocol = signed_protocol(&tc)?;
+
+        let key_path_signature = protocol.inputs("B")?[0].signatures()[2].clone();
+        let Some(Signature::Taproot(mut retyped)) = key_path_signature.clone() else {
+            panic!("Expected a key path signature");
+        };
+        retyped.sighash_type = TapSighashType::SinglePlusAnyoneCanPay;
+
+        // A valid signature of the key path does not verify for a leaf
+        protocol.update_input_signature("B", 0, key_path_signature.clone(), 0)?;
+        protocol.update_input_signature("B", 0, Some(Signature::Taproot(retyped)), 2)?;
+        protocol.update_input_signature("A", 0, key_path_signature, 0)?;
+
+        let report = protocol.verify_all_signatures()?;
+        assert!(!report.is_valid());
+
+        let failures: Vec<_> = report
+            .failures()
+            .map(|check| {
+                (
+                    check.transaction_name.as_str(),
+                    check.path.clone(),
+                    check.status.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(failures.len(), 3);
+        assert!(failures.contains(&("B", SignaturePath::Leaf(0), SignatureStatus::Invalid)));
+        assert!(failures.contains(&(
+            "A",
+            SignaturePath::Segwit,
+            SignatureStatus::SignatureKindMismatch
+        )));
+        assert!(failures.iter().any(|(name, path, status)| *name == "B"
+            && *path == SignaturePath::KeyPath
+            && matches!(status, SignatureStatus::SighashTypeMismatch { .. })));
+
+        Ok(())
+    }
+}
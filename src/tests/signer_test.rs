@@ -0,0 +1,280 @@
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    use bitcoin::{
+        hashes::Hash,
+        opcodes::all::OP_CHECKSIG,
+        script::Builder,
+        secp256k1::{ecdsa, schnorr, Message},
+        PublicKey, TapNodeHash, XOnlyPublicKey,
+    };
+    use key_manager::{
+        key_manager::KeyManager,
+        key_type::BitcoinKeyType,
+        winternitz::{WinternitzSignature, WinternitzType},
+    };
+    use musig2::{secp256k1::Scalar, PubNonce};
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{checksigadd_multisig, ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+            Signer,
+        },
+    };
+
+    // Stands in for a remote signing service, recording the requests it receives
+    struct RemoteSigner {
+        keys: Rc<KeyManager>,
+        online: bool,
+        // Signs a different message, as a faulty service would
+        forge_schnorr: bool,
+        requests: RefCell<Vec<&'static str>>,
+    }
+
+    impl RemoteSigner {
+        fn request(&self, kind: &'static str) -> Result<(), ProtocolBuilderError> {
+            self.requests.borrow_mut().push(kind);
+            match self.online {
+                true => Ok(()),
+                false => Err(ProtocolBuilderError::ExternalSignerError(
+                    "service unavailable".to_string(),
+                )),
+            }
+        }
+    }
+
+    impl Signer for RemoteSigner {
+        fn sign_schnorr(
+            &self,
+            message: &Message,
+            public_key: &PublicKey,
+        ) -> Result<schnorr::Signature, ProtocolBuilderError> {
+            self.request("schnorr")?;
+            match self.forge_schnorr {
+                true => self
+                    .keys
+                    .sign_schnorr(&Message::from_digest([1; 32]), public_key),
+                false => self.keys.sign_schnorr(message, public_key),
+            }
+        }
+
+        fn sign_schnorr_with_tweak(
+            &self,
+            message: &Message,
+            internal_key: &PublicKey,
+            merkle_root: Option<TapNodeHash>,
+        ) -> Result<(schnorr::Signature, PublicKey), ProtocolBuilderError> {
+            self.request("schnorr_with_tweak")?;
+            self.keys
+                .sign_schnorr_with_tweak(message, internal_key, merkle_root)
+        }
+
+        fn sign_ecdsa(
+            &self,
+            message: &Message,
+            public_key: &PublicKey,
+        ) -> Result<ecdsa::Signature, ProtocolBuilderError> {
+            self.request("ecdsa")?;
+            self.keys.sign_ecdsa(message, public_key)
+        }
+
+        fn sign_winternitz(
+            &self,
+            message: &[u8],
+            key_type: WinternitzType,
+            derivation_index: u32,
+        ) -> Result<WinternitzSignature, ProtocolBuilderError> {
+            self.request("winternitz")?;
+            self.keys
+                .sign_winternitz(message, key_type, derivation_index)
+        }
+
+        fn generate_nonce(
+            &self,
+            message_id: &str,
+            message: Vec<u8>,
+            aggregated_key: &PublicKey,
+            id: &str,
+            tweak: Option<Scalar>,
+        ) -> Result<(), ProtocolBuilderError> {
+            Signer::generate_nonce(&self.keys, message_id, message, aggregated_key, id, tweak)
+        }
+
+        fn get_pub_nonces(
+            &self,
+            aggregated_key: &PublicKey,
+            id: &str,
+        ) -> Result<Vec<(String, PubNonce)>, ProtocolBuilderError> {
+            self.keys.get_pub_nonces(aggregated_key, id)
+        }
+
+        fn aggregate_nonces(
+            &self,
+            aggregated_key: &PublicKey,
+            id: &str,
+            nonces: HashMap<PublicKey, Vec<(String, PubNonce)>>,
+        ) -> Result<(), ProtocolBuilderError> {
+            Signer::aggregate_nonces(&self.keys, aggregated_key, id, nonces)
+        }
+
+        fn get_aggregated_signature(
+            &self,
+            aggregated_key: &PublicKey,
+            id: &str,
+            message_id: &str,
+        ) -> Result<schnorr::Signature, ProtocolBuilderError> {
+            Signer::get_aggregated_signature(&self.keys, aggregated_key, id, message_id)
+        }
+    }
+
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let taproot_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let script = Builder::new()
+            .push_x_only_key(&XOnlyPublicKey::from(taproot_key))
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let leaves = [ProtocolScript::new(script, &taproot_key, SignMode::Single)];
+
+        let mut protocol = Protocol::new("remote_signer");
+        let builder = ProtocolBuilder {};
+        builder
+            .add_external_connection(
+                &mut protocol,
+                "EXT",
+                Hash::all_zeros(),
+                OutputSpec::Auto(OutputType::segwit_key(10_000, &funding_key)?),
+                "A",
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            )?
+            .add_taproot_connection(
+                &mut protocol,
+                "A_B",
+                "A",
+                9_000,
+                &taproot_key,
+                &leaves,
+                &SpendMode::All {
+                    key_path_sign: SignMode::Single,
+                },
+                "B",
+                &tc.tr_sighash_type(),
+            )?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_external_signer() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_external_signer").unwrap();
+        let signer = RemoteSigner {
+            keys: tc.key_manager().clone(),
+            online: true,
+            forge_schnorr: false,
+            requests: RefCell::new(vec![]),
+        };
+
+        let mut protocol = protocol(&tc)?;
+        protocol.build_and_sign(&signer, "")?;
+
+        assert_eq!(
+            *signer.requests.borrow(),
+            vec!["ecdsa", "schnorr_with_tweak", "schnorr"]
+        );
+        assert!(protocol.verify_all_signatures()?.is_valid());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_signer_error() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_external_signer_error").unwrap();
+        let signer = RemoteSigner {
+            keys: tc.key_manager().clone(),
+            online: false,
+            forge_schnorr: false,
+            requests: RefCell::new(vec![]),
+        };
+
+        // Sighashes need no signer requests, signing fails on the first one
+        let mut protocol = protocol(&tc)?;
+        protocol.build(&signer, "")?;
+        assert!(matches!(
+            protocol.sign(&signer, ""),
+            Err(ProtocolBuilderError::ExternalSignerError(_))
+        ));
+        assert_eq!(*signer.requests.borrow(), vec!["ecdsa"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_signer_invalid_signature() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_external_signer_invalid_signature").unwrap();
+        let signer = RemoteSigner {
+            keys: tc.key_manager().clone(),
+            online: true,
+            forge_schnorr: true,
+            requests: RefCell::new(vec![]),
+        };
+
+        // Signatures are verified once every input is signed, so none of them is stored
+        let mut protocol = protocol(&tc)?;
+        protocol.build(&signer, "")?;
+        assert!(matches!(
+            protocol.sign(&signer, ""),
+            Err(ProtocolBuilderError::ScriptSpendSignatureGenerationFailed(name, 0, 0)) if name == "B"
+        ));
+        assert_eq!(
+            *signer.requests.borrow(),
+            vec!["ecdsa", "schnorr_with_tweak", "schnorr"]
+        );
+        assert!(protocol.input_ecdsa_signature("A", 0)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_signer_multisig_error() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_external_signer_multisig_error").unwrap();
+        let signer = RemoteSigner {
+            keys: tc.key_manager().clone(),
+            online: true,
+            forge_schnorr: false,
+            requests: RefCell::new(vec![]),
+        };
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let keys = [
+            tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?,
+            tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?,
+        ];
+        let leaf = checksigadd_multisig(&keys, 2, SignMode::Single)?;
+
+        let mut protocol = Protocol::new("remote_multisig");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::taproot(10_000, &internal_key, &[leaf])?,
+            "A",
+            SpendMode::ScriptsOnly,
+        )?;
+        protocol.build(&signer, "")?;
+
+        // The signer holds every slot, so its failures are errors and not empty slots
+        let offline = RemoteSigner {
+            online: false,
+            ..signer
+        };
+        assert!(matches!(
+            protocol.sign(&offline, ""),
+            Err(ProtocolBuilderError::ExternalSignerError(_))
+        ));
+
+        Ok(())
+    }
+}
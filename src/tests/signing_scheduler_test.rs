@@ -0,0 +1,398 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::PublicKey;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder, SigningScheduler, SigningStatus},
+        errors::ProtocolBuilderError,
+        scripts::{self, SignMode},
+        tests::utils::TestContext,
+        types::{input::SpendMode, Signer},
+    };
+
+    fn new_instance(
+        tc: &TestContext,
+        name: &str,
+        key: &PublicKey,
+        sign_mode: SignMode,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new(name);
+        let leaf = scripts::check_signature(key, sign_mode);
+
+        ProtocolBuilder {}.add_taproot_connection(
+            &mut protocol,
+            "claim",
+            "A",
+            1000,
+            key,
+            &[leaf],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_scheduled_signing() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_scheduled_signing").unwrap();
+        let key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut scheduler = SigningScheduler::new();
+        for index in 0..3 {
+            let name = format!("pegin_{}", index);
+            scheduler.add(new_instance(&tc, &name, &key, SignMode::Single)?, &name)?;
+        }
+
+        assert_eq!(scheduler.status("pegin_0"), Some(&SigningStatus::Pending));
+        assert!(!scheduler.is_complete());
+
+        assert_eq!(scheduler.generate_nonces(tc.key_manager()), 3);
+        // Single signed leaves do not require aggregated signatures
+        assert!(scheduler.messages_by_aggregated_key()?.is_empty());
+
+        assert_eq!(scheduler.sign(tc.key_manager()), 3);
+        assert!(scheduler.is_complete());
+
+        for protocol in scheduler.into_protocols() {
+            assert!(protocol
+                .input_taproot_script_spend_signature("B", 0, 0)?
+                .is_some());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_messages_grouped_by_aggregated_key() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_messages_grouped_by_aggregated_key").unwrap();
+        let participant = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let aggregated_key = tc
+            .key_manager()
+            .new_musig2_session(vec![participant], participant)?;
+
+        let mut scheduler = SigningScheduler::new();
+        scheduler.add(
+            new_instance(&tc, "pegin_0", &aggregated_key, SignMode::Aggregate)?,
+            "pegin_0",
+        )?;
+        scheduler.add(
+            new_instance(&tc, "pegin_1", &aggregated_key, SignMode::Aggregate)?,
+            "pegin_1",
+        )?;
+
+        scheduler.generate_nonces(tc.key_manager());
+
+        let groups = scheduler.messages_by_aggregated_key()?;
+        assert_eq!(groups.len(), 1);
+
+        let messages = &groups[&aggregated_key];
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().any(|m| m.protocol_name == "pegin_0"));
+        assert!(messages.iter().any(|m| m.protocol_name == "pegin_1"));
+        assert!(messages
+            .iter()
+            .all(|m| m.transaction_name == "B" && m.input_index == 0 && m.script_index == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicated_instance() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_duplicated_instance").unwrap();
+        let key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut scheduler = SigningScheduler::new();
+        scheduler.add(new_instance(&tc, "pegin", &key, SignMode::Single)?, "pegin")?;
+
+        let result = scheduler.add(new_instance(&tc, "other", &key, SignMode::Single)?, "pegin");
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::ProtocolAlreadyScheduled(id)) if id == "pegin"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_instances_of_the_same_protocol() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_instances_of_the_same_protocol").unwrap();
+        let participant = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let aggregated_key = tc
+            .key_manager()
+            .new_musig2_session(vec![participant], participant)?;
+
+        let mut scheduler = SigningScheduler::new();
+        scheduler
+            .add(
+                new_instance(&tc, "pegin", &aggregated_key, SignMode::Aggregate)?,
+                "pegin_0",
+            )?
+            .add(
+                new_instance(&tc, "pegin", &aggregated_key, SignMode::Aggregate)?,
+                "pegin_1",
+            )?;
+
+        assert_eq!(scheduler.generate_nonces(tc.key_manager()), 2);
+        assert_eq!(
+            scheduler.statuses(),
+            vec![
+                ("pegin_0".to_string(), SigningStatus::NoncesGenerated),
+                ("pegin_1".to_string(), SigningStatus::NoncesGenerated),
+            ]
+        );
+
+        let messages = &scheduler.messages_by_aggregated_key()?[&aggregated_key];
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| m.protocol_name == "pegin"));
+        assert!(messages.iter().any(|m| m.id == "pegin_0"));
+        assert!(messages.iter().any(|m| m.id == "pegin_1"));
+
+        scheduler.retry("pegin_1")?;
+        assert_eq!(
+            scheduler.status("pegin_0"),
+            Some(&SigningStatus::NoncesGenerated)
+        );
+        assert_eq!(scheduler.status("pegin_1"), Some(&SigningStatus::Pending));
+        assert_eq!(
+            scheduler.session_id("pegin_1"),
+            Some("pegin_1_epoch:1".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expired_nonces_are_regenerated() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_expired_nonces_are_regenerated").unwrap();
+        let participant = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let aggregated_key = tc
+            .key_manager()
+            .new_musig2_session(vec![participant], participant)?;
+
+        let mut scheduler = SigningScheduler::new();
+        scheduler
+            .add_with_deadline(
+                new_instance(&tc, "pegin_0", &aggregated_key, SignMode::Aggregate)?,
+                "pegin_0",
+                1_000,
+            )?
+            .add(
+                new_instance(&tc, "pegin_1", &aggregated_key, SignMode::Aggregate)?,
+                "pegin_1",
+            )?;
+        scheduler.set_input_deadline("pegin_1", "B", 0, 2_000)?;
+
+        assert_eq!(scheduler.generate_nonces_at(tc.key_manager(), 500), 2);
+
+        let messages = &scheduler.messages_by_aggregated_key()?[&aggregated_key];
+        let deadline = |name: &str| {
+            messages
+                .iter()
+                .find(|m| m.protocol_name == name)
+                .map(|m| (m.id.clone(), m.deadline))
+        };
+        assert_eq!(
+            deadline("pegin_0"),
+            Some(("pegin_0".to_string(), Some(1_000)))
+        );
+        assert_eq!(
+            deadline("pegin_1"),
+            Some(("pegin_1".to_string(), Some(2_000)))
+        );
+
+        assert!(scheduler.expire_nonces(tc.key_manager(), 999)?.is_empty());
+        assert_eq!(
+            scheduler.expire_nonces(tc.key_manager(), 1_000)?,
+            vec!["pegin_0"]
+        );
+        assert_eq!(scheduler.status("pegin_0"), Some(&SigningStatus::Pending));
+        assert_eq!(
+            scheduler.status("pegin_1"),
+            Some(&SigningStatus::NoncesGenerated)
+        );
+
+        // The nonces of the expired session are discarded from the key manager
+        assert!(tc
+            .key_manager()
+            .get_pub_nonces(&aggregated_key, "pegin_0")?
+            .is_empty());
+        assert!(!tc
+            .key_manager()
+            .get_pub_nonces(&aggregated_key, "pegin_1")?
+            .is_empty());
+
+        // Only the expired instance is regenerated, under a new session id
+        assert_eq!(scheduler.generate_nonces_at(tc.key_manager(), 1_200), 1);
+        assert_eq!(
+            scheduler.session_id("pegin_0"),
+            Some("pegin_0_epoch:1".to_string())
+        );
+
+        let messages = &scheduler.messages_by_aggregated_key()?[&aggregated_key];
+        let regenerated = messages
+            .iter()
+            .find(|m| m.protocol_name == "pegin_0")
+            .unwrap();
+        assert_eq!(regenerated.id, "pegin_0_epoch:1");
+        // The new session lasts as long as the first one
+        assert_eq!(regenerated.deadline, Some(1_700));
+        assert!(scheduler.expire_nonces(tc.key_manager(), 1_699)?.is_empty());
+
+        // Retries use a new session id too
+        scheduler.retry("pegin_0")?;
+        assert_eq!(
+            scheduler.session_id("pegin_0"),
+            Some("pegin_0_epoch:2".to_string())
+        );
+
+        assert!(matches!(
+            scheduler.set_input_deadline("missing", "B", 0, 0),
+            Err(ProtocolBuilderError::MissingProtocol(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batched_ceremony() -> Result<(), ProtocolBuilderError> {
+        let alice = TestContext::new("test_batched_ceremony_alice").unwrap();
+        let bob = TestContext::new("test_batched_ceremony_bob").unwrap();
+        let alice_key = alice
+            .key_manager()
+            .derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let bob_key = bob.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let participants = vec![alice_key, bob_key];
+        let aggregated_key = alice
+            .key_manager()
+            .new_musig2_session(participants.clone(), alice_key)?;
+        assert_eq!(
+            bob.key_manager()
+                .new_musig2_session(participants, bob_key)?,
+            aggregated_key
+        );
+
+        let schedule = |tc: &TestContext| -> Result<SigningScheduler, ProtocolBuilderError> {
+            let mut scheduler = SigningScheduler::new();
+            for index in 0..3 {
+                let id = format!("pegin_{}", index);
+                scheduler.add(
+                    new_instance(tc, "pegin", &aggregated_key, SignMode::Aggregate)?,
+                    &id,
+                )?;
+            }
+            assert_eq!(scheduler.generate_nonces(tc.key_manager()), 3);
+            Ok(scheduler)
+        };
+        let mut alice_scheduler = schedule(&alice)?;
+        let mut bob_scheduler = schedule(&bob)?;
+
+        // A single message per participant carries the nonces of every instance
+        let alice_nonces = alice_scheduler.export_nonces(alice.key_manager(), &alice_key);
+        let bob_nonces = bob_scheduler.export_nonces(bob.key_manager(), &bob_key);
+        assert_eq!(alice_nonces.len(), 3);
+        assert_eq!(bob_nonces.len(), 3);
+
+        assert_eq!(
+            alice_scheduler.import_peer_nonces(alice.key_manager(), &bob_nonces),
+            3
+        );
+        assert_eq!(
+            bob_scheduler.import_peer_nonces(bob.key_manager(), &alice_nonces),
+            3
+        );
+        assert_eq!(
+            alice_scheduler.status("pegin_0"),
+            Some(&SigningStatus::NoncesAggregated)
+        );
+
+        // Instances waiting for partial signatures are not signed
+        assert_eq!(alice_scheduler.sign(alice.key_manager()), 0);
+
+        let alice_signatures =
+            alice_scheduler.export_partial_signatures(alice.key_manager(), &alice_key);
+        let bob_signatures = bob_scheduler.export_partial_signatures(bob.key_manager(), &bob_key);
+        assert_eq!(alice_signatures.len(), 3);
+        assert!(bob_signatures
+            .iter()
+            .all(|bundle| bundle.participant == bob_key && bundle.len() == 1));
+
+        assert_eq!(
+            alice_scheduler.import_peer_partial_signatures(alice.key_manager(), &bob_signatures),
+            3
+        );
+        assert_eq!(
+            bob_scheduler.import_peer_partial_signatures(bob.key_manager(), &alice_signatures),
+            3
+        );
+
+        assert_eq!(alice_scheduler.sign(alice.key_manager()), 3);
+        assert_eq!(bob_scheduler.sign(bob.key_manager()), 3);
+        assert!(alice_scheduler.is_complete());
+        assert!(bob_scheduler.is_complete());
+
+        for index in 0..3 {
+            let id = format!("pegin_{}", index);
+            let signature = |scheduler: &SigningScheduler| {
+                scheduler
+                    .protocol(&id)
+                    .unwrap()
+                    .input_taproot_script_spend_signature("B", 0, 0)
+            };
+            let signature_of_alice = signature(&alice_scheduler)?;
+            assert!(signature_of_alice.is_some());
+            assert_eq!(signature_of_alice, signature(&bob_scheduler)?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batched_bundles_of_expired_sessions() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_batched_bundles_of_expired_sessions").unwrap();
+        let participant = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let peer = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let aggregated_key = tc
+            .key_manager()
+            .new_musig2_session(vec![participant], participant)?;
+
+        let mut scheduler = SigningScheduler::new();
+        scheduler.add_with_deadline(
+            new_instance(&tc, "pegin", &aggregated_key, SignMode::Aggregate)?,
+            "pegin_0",
+            1_000,
+        )?;
+        scheduler.generate_nonces_at(tc.key_manager(), 500);
+
+        let mut stale = scheduler.export_nonces(tc.key_manager(), &participant);
+        assert_eq!(stale.len(), 1);
+        stale[0].participant = peer;
+
+        scheduler.expire_nonces(tc.key_manager(), 1_000)?;
+        scheduler.generate_nonces_at(tc.key_manager(), 1_100);
+
+        // Bundles of the expired session do not match the new session id
+        assert_eq!(scheduler.import_peer_nonces(tc.key_manager(), &stale), 0);
+        assert_eq!(
+            scheduler.status("pegin_0"),
+            Some(&SigningStatus::NoncesGenerated)
+        );
+
+        // Incomplete bundles fail the instance they belong to
+        let mut incomplete = scheduler.export_nonces(tc.key_manager(), &participant);
+        incomplete[0].participant = peer;
+        incomplete[0].nonces.clear();
+        assert_eq!(
+            scheduler.import_peer_nonces(tc.key_manager(), &incomplete),
+            0
+        );
+        assert!(matches!(
+            scheduler.status("pegin_0"),
+            Some(SigningStatus::Failed(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -1,11 +1,14 @@
 #[cfg(test)]
 mod tests {
-    use bitcoin::{hashes::Hash, ScriptBuf};
+    use bitcoin::{
+        hashes::Hash, opcodes::all as opcodes, script::Builder, ScriptBuf, XOnlyPublicKey,
+    };
+    use key_manager::{key_type::BitcoinKeyType, winternitz::WinternitzType};
 
     use crate::{
         builder::Protocol,
         errors::ProtocolBuilderError,
-        scripts::{self, ProtocolScript, SignMode},
+        scripts::{self, KeyType, ProtocolScript, SignMode},
         tests::utils::TestContext,
         types::{
             connection::{InputSpec, OutputSpec},
@@ -14,8 +17,6 @@ mod tests {
         },
     };
 
-    use key_manager::key_type::BitcoinKeyType;
-
     #[test]
     fn test_single_scripts_generation() -> Result<(), ProtocolBuilderError> {
         let tc = TestContext::new("test_single_scripts_generation").unwrap();
@@ -194,4 +195,73 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_disassemble_winternitz_keys() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_disassemble_winternitz_keys").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        // The first key is checked with its 3 hashes, the second one is not checked
+        let mut builder = Builder::new()
+            .push_slice(XOnlyPublicKey::from(public_key).serialize())
+            .push_opcode(opcodes::OP_CHECKSIGVERIFY);
+        for hash in 0..3u8 {
+            builder = builder
+                .push_slice([hash; 20])
+                .push_opcode(opcodes::OP_EQUALVERIFY);
+        }
+        let mut script = ProtocolScript::new(
+            builder.push_opcode(opcodes::OP_PUSHNUM_1).into_script(),
+            &public_key,
+            SignMode::Single,
+        );
+        let winternitz = |message_size| KeyType::WinternitzKey {
+            key_type: WinternitzType::HASH160,
+            message_size,
+        };
+        script.add_key("value", 0, winternitz(2), 0)?;
+        script.add_key("other", 1, winternitz(4), 1)?;
+        script.add_key("operator", 2, KeyType::ecdsa(), 2)?;
+
+        let asm = script.disassemble();
+        let lines: Vec<&str> = asm.lines().collect();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(
+            lines[0],
+            format!(
+                "0000  OP_PUSHBYTES_32 {}  # verifying key, SignMode::Single",
+                XOnlyPublicKey::from(public_key)
+            )
+        );
+        assert_eq!(lines[1], "0021  OP_CHECKSIGVERIFY");
+        assert_eq!(
+            lines[2],
+            format!(
+                "0022  OP_PUSHBYTES_20 {}  # key value at position 0 (winternitz HASH160, 2 digit message), hash 1 of 3",
+                "00".repeat(20)
+            )
+        );
+        assert!(lines[6].ends_with("hash 3 of 3"));
+        assert_eq!(lines[8], "0064  OP_PUSHNUM_1");
+        assert_eq!(
+            lines[9],
+            "# key other at position 1 (winternitz HASH160, 4 digit message) is not located in the script"
+        );
+        assert_eq!(
+            lines[10],
+            "# key operator at position 2 (ecdsa) is not located in the script"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_disassemble_malformed_script() {
+        // Malformed scripts are disassembled up to the bad instruction
+        let script = ProtocolScript::new_unspendable(ScriptBuf::from(vec![0x51, 0x4c, 0x05, 0x01]));
+        assert_eq!(
+            script.disassemble(),
+            "0000  OP_PUSHNUM_1\n0001  <invalid> 4c0501"
+        );
+    }
 }
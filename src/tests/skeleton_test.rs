@@ -0,0 +1,240 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        hashes::Hash, opcodes::all::OP_CHECKSIG, script::Builder, Amount, EcdsaSighashType,
+        OutPoint, PublicKey, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut, Txid,
+        Witness, XOnlyPublicKey,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::{InputArgs, SighashType, SpendMode},
+            OutputType,
+        },
+    };
+
+    fn fee_bumpable_protocol(
+        tc: &TestContext,
+        sighash_type: EcdsaSighashType,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("skeleton");
+        let builder = ProtocolBuilder {};
+
+        builder
+            .add_external_connection(
+                &mut protocol,
+                "ext",
+                Hash::all_zeros(),
+                OutputSpec::Auto(OutputType::segwit_key(10_000, &public_key)?),
+                "start",
+                InputSpec::Auto(SighashType::Ecdsa(sighash_type), SpendMode::Segwit),
+            )?
+            .add_p2wpkh_output(&mut protocol, "start", 9_000, &public_key)?;
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    // Protocol whose input can be spent by a leaf signed with ALL or by a fee bump leaf signed
+    // with SINGLE|ANYONECANPAY.
+    fn leaf_sighash_protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let leaf = |public_key: &PublicKey| {
+            let script = Builder::new()
+                .push_x_only_key(&XOnlyPublicKey::from(*public_key))
+                .push_opcode(OP_CHECKSIG)
+                .into_script();
+            ProtocolScript::new(script, public_key, SignMode::Single)
+        };
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let dispute = leaf(&tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?);
+        let mut bump = leaf(&tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?);
+        bump.set_sighash_type(TapSighashType::SinglePlusAnyoneCanPay);
+
+        let mut protocol = Protocol::new("leaf_skeleton");
+        ProtocolBuilder {}.add_external_connection(
+            &mut protocol,
+            "ext",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::taproot(
+                10_000,
+                &internal_key,
+                &[dispute, bump],
+            )?),
+            "start",
+            InputSpec::Auto(SighashType::taproot_all(), SpendMode::ScriptsOnly),
+        )?;
+        protocol.add_transaction_output("start", &OutputType::segwit_key(9_000, &internal_key)?)?;
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    fn signed_variant(
+        protocol: &Protocol,
+        args: InputArgs,
+    ) -> Result<Transaction, ProtocolBuilderError> {
+        let mut variant = protocol.transaction_to_send("start", &[args])?;
+        variant.input.push(fee_input());
+        Ok(variant)
+    }
+
+    fn fee_input() -> TxIn {
+        TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::default(),
+        }
+    }
+
+    #[test]
+    fn test_export_skeletons() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_export_skeletons").unwrap();
+        let protocol = fee_bumpable_protocol(&tc, EcdsaSighashType::AllPlusAnyoneCanPay)?;
+
+        let skeletons = protocol.export_skeletons()?;
+        assert_eq!(skeletons.len(), 1);
+
+        let skeleton = &skeletons[0];
+        let start = protocol.transaction_by_name("start")?;
+        assert_eq!(skeleton.name, "start");
+        assert_eq!(skeleton.txid, start.compute_txid());
+        assert_eq!(skeleton.inputs.len(), 1);
+        assert_eq!(skeleton.outputs.len(), 1);
+
+        let input = &skeleton.inputs[0];
+        assert_eq!(input.amount, 10_000);
+        assert!(input.anyone_can_pay);
+        assert!(input.witness_weight > 0);
+
+        assert_eq!(skeleton.outputs[0].amount, 9_000);
+        assert!(skeleton.estimated_weight > start.weight().to_wu());
+        assert_eq!(
+            skeleton.estimated_vsize,
+            skeleton.estimated_weight.div_ceil(4)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_anyone_can_pay_variant() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_import_anyone_can_pay_variant").unwrap();
+        let mut protocol = fee_bumpable_protocol(&tc, EcdsaSighashType::AllPlusAnyoneCanPay)?;
+
+        let variant = signed_variant(&protocol, InputArgs::new_segwit_args())?;
+
+        let imported = protocol.import_resigned_transaction("start", variant.clone())?;
+        assert_eq!(imported.txid, variant.compute_txid());
+
+        // The fee input added by the service spends an output the protocol does not know
+        assert_eq!(imported.unverified_inputs, vec![variant.input.len() - 1]);
+        assert_eq!(protocol.resigned_transactions("start")?, vec![variant]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_rejects_modified_outputs() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_import_rejects_modified_outputs").unwrap();
+        let mut protocol = fee_bumpable_protocol(&tc, EcdsaSighashType::AllPlusAnyoneCanPay)?;
+
+        let mut variant = signed_variant(&protocol, InputArgs::new_segwit_args())?;
+        variant.output.push(TxOut {
+            value: Amount::from_sat(500),
+            script_pubkey: ScriptBuf::new(),
+        });
+
+        let result = protocol.import_resigned_transaction("start", variant);
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::InvalidResignedTransaction(_, _))
+        ));
+        assert!(protocol.resigned_transactions("start")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_rejects_added_inputs_without_anyone_can_pay() -> Result<(), ProtocolBuilderError>
+    {
+        let tc =
+            TestContext::new("test_import_rejects_added_inputs_without_anyone_can_pay").unwrap();
+        let mut protocol = fee_bumpable_protocol(&tc, EcdsaSighashType::All)?;
+
+        let variant = signed_variant(&protocol, InputArgs::new_segwit_args())?;
+
+        let result = protocol.import_resigned_transaction("start", variant);
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::InvalidResignedTransaction(_, _))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_rejects_invalid_signatures() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_import_rejects_invalid_signatures").unwrap();
+        let mut protocol = fee_bumpable_protocol(&tc, EcdsaSighashType::AllPlusAnyoneCanPay)?;
+
+        // Unsigned variants are rejected too
+        let mut unsigned = protocol.transaction_by_name("start")?.clone();
+        unsigned.input.push(fee_input());
+        assert!(matches!(
+            protocol.import_resigned_transaction("start", unsigned),
+            Err(ProtocolBuilderError::InvalidResignedTransaction(_, _))
+        ));
+
+        // A tampered signature does not verify against the spent output
+        let mut variant = signed_variant(&protocol, InputArgs::new_segwit_args())?;
+        let mut witness = variant.input[0].witness.to_vec();
+        let mut signature = witness[0].clone();
+        let last = signature.len() - 2;
+        signature[last] ^= 1;
+        witness[0] = signature;
+        variant.input[0].witness = Witness::from_slice(&witness);
+
+        let result = protocol.import_resigned_transaction("start", variant);
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::InvalidResignedTransaction(_, _))
+        ));
+        assert!(protocol.resigned_transactions("start")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_uses_leaf_sighash_type() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_import_uses_leaf_sighash_type").unwrap();
+        let mut protocol = leaf_sighash_protocol(&tc)?;
+
+        // The fee bump leaf only commits to its own input and output
+        let mut variant = signed_variant(&protocol, InputArgs::new_taproot_script_args(1))?;
+        variant.output.push(TxOut {
+            value: Amount::from_sat(500),
+            script_pubkey: ScriptBuf::new(),
+        });
+        protocol.import_resigned_transaction("start", variant)?;
+
+        // The dispute leaf is signed with the ALL sighash type of the input
+        let variant = signed_variant(&protocol, InputArgs::new_taproot_script_args(0))?;
+        let result = protocol.import_resigned_transaction("start", variant);
+        assert!(matches!(
+            result,
+            Err(ProtocolBuilderError::InvalidResignedTransaction(_, _))
+        ));
+        assert_eq!(protocol.resigned_transactions("start")?.len(), 1);
+
+        Ok(())
+    }
+}
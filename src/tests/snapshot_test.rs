@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, str::FromStr};
+
+    use bitcoin::{hashes::Hash, opcodes::all::OP_CHECKSIG, script::Builder, PublicKey, Txid};
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        graph::graph::GraphOptions,
+        scripts::{ProtocolScript, SignMode, StackItem},
+        testing::snapshot::{assert_snapshot, canonical_json, check_snapshot, SnapshotError},
+        tests::utils::{TemporaryDir, TestContext},
+        types::{
+            connection::{InputSpec, OutputSpec},
+            external::ExternalTx,
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    const SNAPSHOTS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/snapshots");
+
+    // Fixed keys so the exports don't depend on the key manager
+    fn keys() -> (PublicKey, PublicKey) {
+        (
+            PublicKey::from_str(
+                "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            )
+            .unwrap(),
+            PublicKey::from_str(
+                "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+            )
+            .unwrap(),
+        )
+    }
+
+    fn checksig(public_key: &PublicKey) -> ProtocolScript {
+        let script = Builder::new()
+            .push_key(public_key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let mut leaf = ProtocolScript::new(script, public_key, SignMode::Skip);
+        leaf.add_stack_item(StackItem::new_schnorr_sig(false));
+        leaf
+    }
+
+    // EXT -> start -> challenge -> response
+    //              -> timeout (after 10 blocks)
+    fn reference_protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let (alice, bob) = keys();
+        let mut protocol = Protocol::new("reference");
+        let builder = ProtocolBuilder {};
+
+        protocol.add_external_tx(
+            ExternalTx::new("EXT", Txid::from_byte_array([1; 32]))
+                .with_opaque_outputs(1)
+                .with_known_output(OutputType::segwit_key(100_000, &alice)?),
+        )?;
+        protocol.add_connection(
+            "funding",
+            "EXT",
+            OutputSpec::Index(1),
+            "start",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::None),
+            None,
+            None,
+        )?;
+
+        builder.add_taproot_connection(
+            &mut protocol,
+            "challenge",
+            "start",
+            50_000,
+            &alice,
+            &[checksig(&alice), checksig(&bob)],
+            &SpendMode::None,
+            "challenge",
+            &tc.tr_sighash_type(),
+        )?;
+        protocol.add_connection(
+            "timeout",
+            "start",
+            OutputSpec::Auto(OutputType::segwit_key(40_000, &bob)?),
+            "timeout",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::None),
+            Some(10),
+            None,
+        )?;
+        protocol.add_connection(
+            "response",
+            "challenge",
+            OutputSpec::Auto(OutputType::segwit_script(45_000, &checksig(&bob))?),
+            "response",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::None),
+            None,
+            None,
+        )?;
+        protocol.label_output("start", 0, "stake")?;
+
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_reference_protocol_snapshots() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_reference_protocol_snapshots").unwrap();
+        let protocol = reference_protocol(&tc)?;
+        let dir = Path::new(SNAPSHOTS_DIR);
+
+        assert_snapshot(
+            dir,
+            "reference_dot",
+            &protocol.visualize(GraphOptions::Default)?,
+        );
+        assert_snapshot(
+            dir,
+            "reference_dot_edge_arrows",
+            &protocol.visualize(GraphOptions::EdgeArrows)?,
+        );
+        assert_snapshot(
+            dir,
+            "reference_dot_detailed",
+            &protocol.visualize(GraphOptions::Detailed)?,
+        );
+        assert_snapshot(
+            dir,
+            "reference_mermaid",
+            &protocol.visualize(GraphOptions::Mermaid)?,
+        );
+        assert_snapshot(
+            dir,
+            "reference_graph_json",
+            &protocol.visualize(GraphOptions::Json)?,
+        );
+        assert_snapshot(dir, "reference_json", &canonical_json(&protocol).unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_mismatch() {
+        let dir = TemporaryDir::new("test_snapshot_mismatch");
+        let snapshot = "digraph {\nA -> B\n}";
+
+        assert!(matches!(
+            check_snapshot(&dir.path, "graph", snapshot),
+            Err(SnapshotError::Missing(_))
+        ));
+
+        fs::write(dir.path("graph.snap"), snapshot).unwrap();
+        assert_eq!(check_snapshot(&dir.path, "graph", snapshot), Ok(()));
+
+        let error = check_snapshot(&dir.path, "graph", "digraph {\nA -> C\n}").unwrap_err();
+        assert_eq!(
+            error,
+            SnapshotError::Mismatch {
+                path: dir.path("graph.snap"),
+                line: 2,
+                expected: "A -> B".to_string(),
+                actual: "A -> C".to_string(),
+            }
+        );
+
+        let error = check_snapshot(&dir.path, "graph", "digraph {\nA -> B").unwrap_err();
+        assert!(matches!(error, SnapshotError::Mismatch { line: 3, .. }));
+    }
+}
@@ -0,0 +1,135 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{hashes::Hash, Txid};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::ProtocolBuilder,
+        errors::ProtocolBuilderError,
+        scripts::{self, SignMode},
+        tests::utils::TestContext,
+        types::output::{OutputType, SpeedupData, SpeedupDataBuilder, SpeedupKind, Utxo},
+    };
+
+    fn txid(byte: u8) -> Txid {
+        Txid::from_byte_array([byte; 32])
+    }
+
+    #[test]
+    fn test_speedup_data_kinds() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_speedup_data_kinds").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let taproot_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaf = scripts::check_signature(&taproot_key, SignMode::Single);
+        let taproot = OutputType::taproot(1_000, &taproot_key, &[leaf])?;
+
+        let keyed =
+            SpeedupDataBuilder::keyed_anchor(Utxo::new(txid(1), 0, 500, &public_key)).build()?;
+        assert_eq!(keyed.kind()?, SpeedupKind::KeyedAnchor);
+
+        let leaf_anchor = SpeedupDataBuilder::taproot_leaf_anchor(txid(2), 1, &taproot, 0)
+            .with_winternitz_signatures(vec![])
+            .with_leaf_identification()
+            .build()?;
+        assert_eq!(leaf_anchor.kind()?, SpeedupKind::TaprootLeafAnchor);
+        assert_eq!(leaf_anchor.partial_utxo, Some((txid(2), 1, 1_000)));
+
+        let anchor = SpeedupDataBuilder::pay_to_anchor(txid(3), 2, 0).build()?;
+        assert_eq!(anchor.kind()?, SpeedupKind::PayToAnchor);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_speedup_data() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_invalid_speedup_data").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let taproot_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaf = scripts::check_signature(&taproot_key, SignMode::Single);
+        let taproot = OutputType::taproot(1_000, &taproot_key, &[leaf])?;
+        let invalid = |result: Result<SpeedupData, ProtocolBuilderError>| {
+            matches!(result, Err(ProtocolBuilderError::InvalidSpeedupData(_)))
+        };
+
+        // Leaf spending data only applies to taproot leaf anchors
+        assert!(invalid(
+            SpeedupDataBuilder::keyed_anchor(Utxo::new(txid(1), 0, 500, &public_key))
+                .with_leaf_identification()
+                .build()
+        ));
+        assert!(invalid(
+            SpeedupDataBuilder::pay_to_anchor(txid(1), 0, 0)
+                .with_winternitz_signatures(vec![])
+                .build()
+        ));
+
+        assert!(invalid(
+            SpeedupDataBuilder::taproot_leaf_anchor(txid(1), 0, &taproot, 1).build()
+        ));
+        assert!(invalid(
+            SpeedupDataBuilder::taproot_leaf_anchor(
+                txid(1),
+                0,
+                &OutputType::segwit_key(1_000, &public_key)?,
+                0
+            )
+            .build()
+        ));
+
+        // Structs filled by hand are validated too
+        let mut both = SpeedupData::new(Utxo::new(txid(1), 0, 500, &public_key));
+        both.partial_utxo = Some((txid(1), 0, 500));
+        assert!(matches!(
+            both.kind(),
+            Err(ProtocolBuilderError::InvalidSpeedupData(_))
+        ));
+
+        let mut mismatched =
+            SpeedupDataBuilder::taproot_leaf_anchor(txid(1), 0, &taproot, 0).build()?;
+        mismatched.partial_utxo = Some((txid(1), 0, 900));
+        assert!(matches!(
+            mismatched.kind(),
+            Err(ProtocolBuilderError::InvalidSpeedupData(_))
+        ));
+
+        let funding = Utxo::new(txid(9), 0, 5_000, &public_key);
+        assert!(matches!(
+            ProtocolBuilder {}.speedup_transactions(
+                &[mismatched],
+                funding,
+                &public_key,
+                1_000,
+                tc.key_manager().as_ref(),
+            ),
+            Err(ProtocolBuilderError::InvalidSpeedupData(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_taproot_leaf_speedup() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_taproot_leaf_speedup").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let taproot_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let leaf = scripts::check_signature(&taproot_key, SignMode::Single);
+        let taproot = OutputType::taproot(1_000, &taproot_key, &[leaf])?;
+
+        let speedup = SpeedupDataBuilder::taproot_leaf_anchor(txid(1), 3, &taproot, 0).build()?;
+        let cpfp = ProtocolBuilder {}.speedup_transactions(
+            &[speedup],
+            Utxo::new(txid(9), 0, 5_000, &public_key),
+            &public_key,
+            1_000,
+            tc.key_manager().as_ref(),
+        )?;
+
+        assert_eq!(cpfp.input.len(), 2);
+        assert_eq!(cpfp.input[0].previous_output.vout, 3);
+        // Signature, leaf script and control block
+        assert_eq!(cpfp.input[0].witness.len(), 3);
+        assert_eq!(cpfp.input[1].witness.len(), 2);
+
+        Ok(())
+    }
+}
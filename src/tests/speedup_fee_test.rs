@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{hashes::Hash, Txid};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        broadcast::FeeEstimator,
+        builder::ProtocolBuilder,
+        errors::{BroadcastError, ProtocolBuilderError},
+        tests::utils::TestContext,
+        types::output::{SpeedupDataBuilder, UnconfirmedParent, Utxo},
+    };
+
+    struct FixedFeeRate(u64);
+
+    impl FeeEstimator for FixedFeeRate {
+        fn estimate_fee_rate(&self, target_blocks: u16) -> Result<u64, BroadcastError> {
+            match target_blocks {
+                0 => Err(BroadcastError::Unavailable("no estimate".to_string())),
+                _ => Ok(self.0),
+            }
+        }
+    }
+
+    fn txid(byte: u8) -> Txid {
+        Txid::from_byte_array([byte; 32])
+    }
+
+    #[test]
+    fn test_speedup_fee_for_target() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_speedup_fee_for_target").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let builder = ProtocolBuilder {};
+        let speedups = [SpeedupDataBuilder::pay_to_anchor(txid(1), 1, 0).build()?];
+        let funding = Utxo::new(txid(9), 0, 50_000, &public_key);
+
+        // Zero fee parent, the child pays for the whole package
+        let parents = [UnconfirmedParent { vsize: 300, fee: 0 }];
+        let cpfp = builder.speedup_transactions_for_target(
+            &speedups,
+            &parents,
+            funding.clone(),
+            &public_key,
+            6,
+            &FixedFeeRate(10),
+            tc.key_manager().as_ref(),
+        )?;
+        let child_vsize = cpfp.vsize() as u64;
+        assert_eq!(
+            cpfp.output[0].value.to_sat(),
+            50_000 - 10 * (300 + child_vsize)
+        );
+
+        // Parents paying more than the target leave the child at the minimum relay fee
+        let parents = [UnconfirmedParent {
+            vsize: 300,
+            fee: 20_000,
+        }];
+        let cpfp = builder.speedup_transactions_for_target(
+            &speedups,
+            &parents,
+            funding.clone(),
+            &public_key,
+            6,
+            &FixedFeeRate(10),
+            tc.key_manager().as_ref(),
+        )?;
+        assert_eq!(cpfp.output[0].value.to_sat(), 50_000 - child_vsize);
+
+        assert!(matches!(
+            builder.speedup_transactions_for_target(
+                &speedups,
+                &parents,
+                funding.clone(),
+                &public_key,
+                6,
+                &FixedFeeRate(1_000),
+                tc.key_manager().as_ref(),
+            ),
+            Err(ProtocolBuilderError::InsufficientFunds(50_000, _))
+        ));
+        assert!(matches!(
+            builder.speedup_transactions_for_target(
+                &speedups,
+                &parents,
+                funding,
+                &public_key,
+                0,
+                &FixedFeeRate(10),
+                tc.key_manager().as_ref(),
+            ),
+            Err(ProtocolBuilderError::ChainStateError(
+                BroadcastError::Unavailable(_)
+            ))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        key::Secp256k1,
+        secp256k1::{rand::thread_rng, Keypair},
+        PublicKey, ScriptBuf, XOnlyPublicKey,
+    };
+
+    use crate::{
+        errors::ProtocolBuilderError,
+        helpers::spend_info_cache::{cached_spend_info, spend_info_cache_stats},
+        scripts::{build_taproot_spend_info_with_layout, ProtocolScript, SignMode, TapTreeLayout},
+        types::output::OutputType,
+    };
+
+    #[test]
+    fn test_spend_info_cache() -> Result<(), ProtocolBuilderError> {
+        let secp = Secp256k1::new();
+        // A fresh key, so no other test has cached these taptrees
+        let keypair = Keypair::new(&secp, &mut thread_rng());
+        let public_key = PublicKey::new(keypair.public_key());
+        let internal_key = XOnlyPublicKey::from(public_key);
+        let leaves: Vec<ProtocolScript> = (0..3u8)
+            .map(|i| {
+                ProtocolScript::new(ScriptBuf::from(vec![0x51 + i]), &public_key, SignMode::Skip)
+            })
+            .collect();
+        let layout = TapTreeLayout::Balanced;
+
+        let misses = spend_info_cache_stats().misses;
+        let output = OutputType::taproot(10_000, &public_key, &leaves)?;
+        assert!(spend_info_cache_stats().misses > misses);
+        assert!(spend_info_cache_stats().entries > 0);
+
+        // Identical outputs reuse the spend info
+        let cached = cached_spend_info(&internal_key, &leaves, &layout, || {
+            Err(ProtocolBuilderError::EmptyScripts)
+        })?;
+        assert_eq!(output.get_taproot_spend_info()?, Some(cached));
+        assert_eq!(
+            OutputType::taproot(20_000, &public_key, &leaves)?.get_script_pubkey(),
+            output.get_script_pubkey()
+        );
+
+        // Other shapes of the same leaves are built again
+        let depths = TapTreeLayout::Depths(vec![1, 2, 2]);
+        let mut built = false;
+        cached_spend_info(&internal_key, &leaves, &depths, || {
+            built = true;
+            build_taproot_spend_info_with_layout(&secp, &internal_key, &leaves, &depths)
+        })?;
+        assert!(built);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, OutPoint};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        testing::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+        },
+    };
+
+    #[test]
+    fn test_context_builder() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_context_builder").unwrap();
+        assert_eq!(tc.network(), Network::Regtest);
+
+        let other = TestContext::builder("test_context_builder_testnet")
+            .with_network(Network::Testnet)
+            .build()
+            .unwrap();
+        assert_eq!(other.network(), Network::Testnet);
+
+        // Keys are derived from the same mnemonic in every context
+        assert_eq!(
+            tc.derive_key(BitcoinKeyType::P2tr, 3)?,
+            other.derive_key(BitcoinKeyType::P2tr, 3)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_context_funding() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_context_funding").unwrap();
+
+        let funding = tc.funding_tx("funding", tc.segwit_funding(10_000, 0)?);
+        assert_eq!(
+            funding.txid(),
+            tc.funding_tx("funding", tc.taproot_funding(10_000, 0)?)
+                .txid()
+        );
+
+        let txid = funding.txid();
+        let mut protocol = Protocol::new("context_funding");
+        protocol.add_external_tx(funding)?;
+        protocol.add_connection(
+            "funding",
+            "funding",
+            OutputSpec::Last,
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            None,
+            None,
+        )?;
+        protocol.add_transaction_output("A", &tc.taproot_funding(9_000, 1)?)?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let a = protocol.transaction_by_name("A")?;
+        assert_eq!(a.input[0].previous_output, OutPoint::new(txid, 0));
+        assert!(protocol.input_ecdsa_signature("A", 0)?.is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        opcodes::all::{OP_CHECKSIG, OP_DROP},
+        script::Builder,
+        PublicKey, XOnlyPublicKey,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode, StackItem},
+        tests::utils::TestContext,
+        types::{
+            input::{InputArgs, SpendMode},
+            output::OutputType,
+            trace_step::StepCommitments,
+        },
+    };
+
+    // Prover committing every 10th step from step 100, with two commitment leaves per step and a
+    // challenge leaf for every step but the second one.
+    struct MockProver {
+        key: PublicKey,
+        steps: usize,
+    }
+
+    impl MockProver {
+        // The tag makes each leaf of the trace different
+        fn leaf(&self, tag: i64) -> ProtocolScript {
+            let script = Builder::new()
+                .push_int(tag)
+                .push_opcode(OP_DROP)
+                .push_x_only_key(&XOnlyPublicKey::from(self.key))
+                .push_opcode(OP_CHECKSIG)
+                .into_script();
+
+            let mut leaf = ProtocolScript::new(script, &self.key, SignMode::Single);
+            leaf.add_stack_item(StackItem::new_schnorr_sig(false));
+            leaf
+        }
+    }
+
+    impl StepCommitments for MockProver {
+        fn step_count(&self) -> usize {
+            self.steps
+        }
+
+        fn step_number(&self, index: usize) -> u64 {
+            100 + 10 * index as u64
+        }
+
+        fn commitment_leaves(
+            &self,
+            index: usize,
+        ) -> Result<Vec<ProtocolScript>, ProtocolBuilderError> {
+            Ok(vec![
+                self.leaf(1000 + index as i64),
+                self.leaf(2000 + index as i64),
+            ])
+        }
+
+        fn challenge_leaves(
+            &self,
+            index: usize,
+        ) -> Result<Vec<ProtocolScript>, ProtocolBuilderError> {
+            if index == 1 {
+                return Ok(vec![]);
+            }
+            Ok(vec![self.leaf(3000 + index as i64)])
+        }
+    }
+
+    struct SilentProver;
+
+    impl StepCommitments for SilentProver {
+        fn step_count(&self) -> usize {
+            1
+        }
+
+        fn commitment_leaves(
+            &self,
+            _index: usize,
+        ) -> Result<Vec<ProtocolScript>, ProtocolBuilderError> {
+            Ok(vec![])
+        }
+
+        fn challenge_leaves(
+            &self,
+            _index: usize,
+        ) -> Result<Vec<ProtocolScript>, ProtocolBuilderError> {
+            Ok(vec![])
+        }
+    }
+
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        let mut protocol = Protocol::new("dispute");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::segwit_key(100_000, &funding_key)?,
+            "kickoff",
+            SpendMode::Segwit,
+        )?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_connect_trace_steps() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_connect_trace_steps").unwrap();
+        let key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let prover = MockProver { key, steps: 3 };
+
+        let mut protocol = protocol(&tc)?;
+        let steps = ProtocolBuilder {}.connect_trace_steps(
+            &mut protocol,
+            "trace",
+            "kickoff",
+            1_000,
+            &key,
+            &prover,
+            &SpendMode::ScriptsOnly,
+            &tc.tr_sighash_type(),
+        )?;
+
+        assert_eq!(steps.len(), 3);
+        assert_eq!(
+            steps
+                .iter()
+                .map(|step| step.step_number)
+                .collect::<Vec<_>>(),
+            vec![100, 110, 120]
+        );
+        assert_eq!(steps[0].transaction_name, "trace_step_0");
+        assert_eq!(
+            steps[0].challenge_name.as_deref(),
+            Some("trace_challenge_0")
+        );
+        assert_eq!(steps[1].challenge_name, None);
+        assert_eq!(
+            steps[2].challenge_name.as_deref(),
+            Some("trace_challenge_2")
+        );
+
+        // Commitments of the next step come first, then the challenges of the step
+        assert_eq!(steps[0].commitment_leaves, vec![0, 1]);
+        assert_eq!(steps[0].challenge_leaves, vec![2]);
+        assert!(steps[1].challenge_leaves.is_empty());
+        assert_eq!(steps[2].challenge_leaves, vec![0]);
+
+        assert_eq!(protocol.spenders("kickoff", 0)?, vec!["trace_step_0"]);
+        assert_eq!(
+            protocol.spenders("trace_step_0", 0)?,
+            vec!["trace_step_1", "trace_challenge_0"]
+        );
+        assert_eq!(protocol.spenders("trace_step_1", 0)?, vec!["trace_step_2"]);
+        assert_eq!(
+            protocol.spenders("trace_step_2", 0)?,
+            vec!["trace_challenge_2"]
+        );
+
+        let (_, leaves) = protocol.get_script_from_output("trace_step_0", 0)?;
+        assert_eq!(leaves.len(), 3);
+        assert_eq!(
+            leaves[0].get_script(),
+            prover.commitment_leaves(1)?[0].get_script()
+        );
+        assert_eq!(
+            leaves[2].get_script(),
+            prover.challenge_leaves(0)?[0].get_script()
+        );
+
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        let leaf = steps[1].commitment_leaves[1];
+        let mut commit = InputArgs::new_taproot_script_args(leaf);
+        commit.push_taproot_signature(
+            protocol
+                .input_taproot_script_spend_signature("trace_step_1", 0, leaf)?
+                .unwrap(),
+        )?;
+        let step = protocol.transaction_to_send("trace_step_1", &[commit])?;
+        assert_eq!(step.input.len(), 1);
+
+        let leaf = steps[0].challenge_leaves[0];
+        let mut challenge = InputArgs::new_taproot_script_args(leaf);
+        challenge.push_taproot_signature(
+            protocol
+                .input_taproot_script_spend_signature("trace_challenge_0", 0, leaf)?
+                .unwrap(),
+        )?;
+        let challenge = protocol.transaction_to_send("trace_challenge_0", &[challenge])?;
+        assert_eq!(
+            challenge.input[0].previous_output.txid,
+            protocol.transaction_by_name("trace_step_0")?.compute_txid()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_trace_without_commitments() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_reject_trace_without_commitments").unwrap();
+        let key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let builder = ProtocolBuilder {};
+
+        let mut protocol = protocol(&tc)?;
+        assert!(matches!(
+            builder.connect_trace_steps(
+                &mut protocol,
+                "trace",
+                "kickoff",
+                1_000,
+                &key,
+                &MockProver { key, steps: 0 },
+                &SpendMode::ScriptsOnly,
+                &tc.tr_sighash_type(),
+            ),
+            Err(ProtocolBuilderError::EmptyTrace)
+        ));
+        assert!(matches!(
+            builder.connect_trace_steps(
+                &mut protocol,
+                "trace",
+                "kickoff",
+                1_000,
+                &key,
+                &SilentProver,
+                &SpendMode::ScriptsOnly,
+                &tc.tr_sighash_type(),
+            ),
+            Err(ProtocolBuilderError::MissingStepCommitments(0))
+        ));
+
+        Ok(())
+    }
+}
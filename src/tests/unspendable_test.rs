@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use bitcoin::{
+        hashes::Hash, key::Parity, secp256k1::rand::thread_rng, PublicKey, ScriptBuf,
+        XOnlyPublicKey,
+    };
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+        unspendable::{
+            deterministic_unspendable_key, unspendable_key_with_derivation, verify_unspendable,
+        },
+    };
+
+    #[test]
+    fn test_verify_unspendable() -> Result<(), ProtocolBuilderError> {
+        let mut rng = thread_rng();
+        let (key, derivation_data) = unspendable_key_with_derivation(&mut rng)?;
+        let (other_key, other_derivation_data) = unspendable_key_with_derivation(&mut rng)?;
+
+        let key = XOnlyPublicKey::from(key);
+        let other_key = XOnlyPublicKey::from(other_key);
+
+        assert!(verify_unspendable(&key, &derivation_data)?);
+        assert!(verify_unspendable(&other_key, &other_derivation_data)?);
+        assert!(!verify_unspendable(&key, &other_derivation_data)?);
+
+        Ok(())
+    }
+
+    fn protocol(
+        tc: &TestContext,
+        public_key: &PublicKey,
+        internal_key: &PublicKey,
+    ) -> Result<Protocol, ProtocolBuilderError> {
+        let mut protocol = Protocol::new("unspendable");
+        let builder = ProtocolBuilder {};
+
+        builder.add_external_connection(
+            &mut protocol,
+            "funding",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::segwit_key(10_000, public_key)?),
+            "A",
+            InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+        )?;
+
+        builder.add_taproot_connection(
+            &mut protocol,
+            "a_b",
+            "A",
+            5000,
+            internal_key,
+            &[ProtocolScript::new(
+                ScriptBuf::from(vec![0x01]),
+                public_key,
+                SignMode::Single,
+            )],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+
+        protocol.build(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_require_unspendable_proofs() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_require_unspendable_proofs").unwrap();
+        let storage = Rc::new(tc.new_storage("protocol"));
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut rng = thread_rng();
+        let (internal_key, derivation_data) = unspendable_key_with_derivation(&mut rng)?;
+        let (_, other_derivation_data) = unspendable_key_with_derivation(&mut rng)?;
+        let x_only_key = XOnlyPublicKey::from(internal_key);
+
+        let mut protocol = protocol(&tc, &public_key, &internal_key)?;
+
+        // Proofs are not required by default
+        protocol.commitment_hash()?;
+
+        protocol.require_unspendable_proofs(true)?;
+        assert!(matches!(
+            protocol.commitment_hash(),
+            Err(ProtocolBuilderError::MissingUnspendableProof(name, 0, key))
+                if name == "A" && key == x_only_key
+        ));
+
+        assert!(matches!(
+            protocol.add_unspendable_proof(&x_only_key, other_derivation_data),
+            Err(ProtocolBuilderError::InvalidUnspendableProof(key)) if key == x_only_key
+        ));
+
+        protocol.add_unspendable_proof(&x_only_key, derivation_data)?;
+        protocol.save(storage.clone())?;
+
+        let protocol = Protocol::load("unspendable", storage)?.unwrap();
+        protocol.commitment_hash()?;
+        protocol.inclusion_proof("B")?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unspendable_proofs_of_unspent_outputs() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_unspendable_proofs_of_unspent_outputs").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut rng = thread_rng();
+        let (internal_key, derivation_data) = unspendable_key_with_derivation(&mut rng)?;
+        let mut protocol = protocol(&tc, &public_key, &internal_key)?;
+        protocol.add_unspendable_proof(&XOnlyPublicKey::from(internal_key), derivation_data)?;
+        protocol.require_unspendable_proofs(true)?;
+
+        // Outputs no protocol input spends are script-only unless they have no leaves
+        let leaf = ProtocolScript::new(ScriptBuf::from(vec![0x01]), &public_key, SignMode::Single);
+        protocol.add_transaction_output("B", &OutputType::taproot(1000, &public_key, &[])?)?;
+        protocol.add_transaction_output("B", &OutputType::taproot(1000, &public_key, &[leaf])?)?;
+        assert!(matches!(
+            protocol.commitment_hash(),
+            Err(ProtocolBuilderError::MissingUnspendableProof(name, 1, key))
+                if name == "B" && key == XOnlyPublicKey::from(public_key)
+        ));
+
+        // Spending the output through the key path shows the key is not meant to be unspendable
+        protocol.add_connection(
+            "b_c",
+            "B",
+            OutputSpec::Index(1),
+            "C",
+            InputSpec::Auto(
+                tc.tr_sighash_type(),
+                SpendMode::All {
+                    key_path_sign: SignMode::Single,
+                },
+            ),
+            None,
+            None,
+        )?;
+        protocol.check_unspendable_proofs()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deterministic_unspendable_key() -> Result<(), ProtocolBuilderError> {
+        let (key, derivation_data) = deterministic_unspendable_key(b"context")?;
+        let key = XOnlyPublicKey::from(key);
+        assert!(verify_unspendable(&key, &derivation_data)?);
+        assert_eq!(
+            deterministic_unspendable_key(b"context")?.1,
+            derivation_data
+        );
+        assert_ne!(deterministic_unspendable_key(b"other")?.1, derivation_data);
+
+        // Both parties derive the same key, with its proof, for the same protocol and label
+        let mut protocol = Protocol::new("deterministic");
+        let mut counterparty = Protocol::new("deterministic");
+        let key = protocol.create_deterministic_unspendable_key("a_b")?;
+        assert_eq!(
+            counterparty.create_deterministic_unspendable_key("a_b")?,
+            key
+        );
+        assert_ne!(protocol.create_deterministic_unspendable_key("b_c")?, key);
+        assert_ne!(
+            Protocol::new("other").create_deterministic_unspendable_key("a_b")?,
+            key
+        );
+
+        let tc = TestContext::new("test_deterministic_unspendable_key").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let key = Protocol::new("unspendable").create_deterministic_unspendable_key("a_b")?;
+        let internal_key = PublicKey::from(key.public_key(Parity::Even));
+
+        // The protocol derives the key it was built with, which adds the proof
+        let mut protocol = self::protocol(&tc, &public_key, &internal_key)?;
+        assert_eq!(protocol.create_deterministic_unspendable_key("a_b")?, key);
+        protocol.require_unspendable_proofs(true)?;
+        protocol.commitment_hash()?;
+
+        Ok(())
+    }
+}
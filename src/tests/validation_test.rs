@@ -0,0 +1,143 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::ScriptBuf;
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder, ValidationIssue},
+        errors::ProtocolBuilderError,
+        scripts::{ProtocolScript, SignMode, StackItem},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    #[test]
+    fn test_valid_protocol() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_valid_protocol").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let mut leaf =
+            ProtocolScript::new(ScriptBuf::from(vec![0xac]), &public_key, SignMode::Single);
+        leaf.add_stack_item(StackItem::new_schnorr_sig(false));
+
+        let mut protocol = Protocol::new("valid");
+        ProtocolBuilder {}.add_taproot_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            10_000,
+            &public_key,
+            &[leaf],
+            &SpendMode::ScriptsOnly,
+            "B",
+            &tc.tr_sighash_type(),
+        )?;
+
+        assert!(protocol.validate()?.is_valid());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_oversized_p2wsh_witness() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_oversized_p2wsh_witness").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+
+        // 60 Winternitz digits push 120 stack elements
+        let mut script = ProtocolScript::new(
+            ScriptBuf::from(vec![0x51; 4_000]),
+            &public_key,
+            SignMode::Skip,
+        );
+        script.add_stack_item(StackItem::WinternitzSig { size: 60 * 25 });
+        script.add_stack_item(StackItem::new_raw(100));
+
+        let mut protocol = Protocol::new("p2wsh");
+        ProtocolBuilder {}.add_p2wsh_connection(
+            &mut protocol,
+            "A_B",
+            "A",
+            10_000,
+            &script,
+            "B",
+            &tc.ecdsa_sighash_type(),
+        )?;
+
+        let report = protocol.validate()?;
+        assert!(report.issues("A").is_empty());
+        assert_eq!(
+            report.issues("B"),
+            &[
+                ValidationIssue::WitnessItemCount {
+                    input_index: 0,
+                    leaf_index: None,
+                    count: 121,
+                    limit: 100,
+                },
+                ValidationIssue::WitnessItemSize {
+                    input_index: 0,
+                    leaf_index: None,
+                    size: 100,
+                    limit: 80,
+                },
+                ValidationIssue::ScriptSize {
+                    input_index: 0,
+                    leaf_index: None,
+                    size: 4_000,
+                    limit: 3_600,
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sigops_budget_and_dust() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_sigops_budget_and_dust").unwrap();
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        // Ten signature checks with a single signature in the witness
+        let mut leaf =
+            ProtocolScript::new(ScriptBuf::from(vec![0xac; 10]), &public_key, SignMode::Skip);
+        leaf.add_stack_item(StackItem::new_schnorr_sig(false));
+        let valid = ProtocolScript::new(ScriptBuf::from(vec![0x51]), &public_key, SignMode::Skip);
+
+        let mut protocol = Protocol::new("sigops");
+        protocol.add_connection(
+            "A_B",
+            "A",
+            OutputSpec::Auto(OutputType::taproot(10_000, &public_key, &[valid, leaf])?),
+            "B",
+            InputSpec::Auto(tc.tr_sighash_type(), SpendMode::None),
+            None,
+            None,
+        )?;
+        protocol.add_transaction_output("B", &OutputType::segwit_key(100, &public_key)?)?;
+
+        let issues = protocol.validate()?.issues("B").to_vec();
+        assert_eq!(issues.len(), 2);
+        assert!(matches!(
+            issues[0],
+            ValidationIssue::SigopsBudget {
+                input_index: 0,
+                leaf_index: 1,
+                sigops: 10,
+                ..
+            }
+        ));
+        assert_eq!(
+            issues[1],
+            ValidationIssue::DustOutput {
+                output_index: 0,
+                value: 100,
+                dust_limit: 294,
+            }
+        );
+
+        Ok(())
+    }
+}
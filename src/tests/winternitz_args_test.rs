@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::PublicKey;
+    use key_manager::{key_type::BitcoinKeyType, winternitz::WinternitzType};
+
+    use crate::{
+        builder::Protocol,
+        errors::ProtocolBuilderError,
+        scripts::{KeyType, ProtocolScript},
+        tests::utils::{checksig_leaf, TestContext},
+        types::{
+            input::{InputArgs, SpendMode},
+            output::OutputType,
+        },
+    };
+
+    // Leaf declaring two Winternitz keys, added out of position order, and an ECDSA key
+    fn leaf(public_key: &PublicKey) -> ProtocolScript {
+        let mut leaf = checksig_leaf(public_key);
+        let winternitz = |message_size| KeyType::WinternitzKey {
+            key_type: WinternitzType::HASH160,
+            message_size,
+        };
+        leaf.add_key("second", 5, winternitz(4), 1).unwrap();
+        leaf.add_key("first", 3, winternitz(2), 0).unwrap();
+        leaf.add_key("operator", 0, KeyType::ecdsa(), 2).unwrap();
+        leaf
+    }
+
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let public_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 0)?;
+
+        let mut protocol = Protocol::new("winternitz_args");
+        tc.add_external_input(
+            &mut protocol,
+            OutputType::taproot(10_000, &public_key, &[leaf(&public_key)])?,
+            "A",
+            SpendMode::ScriptsOnly,
+        )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_sign_winternitz() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_sign_winternitz").unwrap();
+        let protocol = protocol(&tc)?;
+        let values = HashMap::from([
+            ("first".to_string(), vec![0x01]),
+            ("second".to_string(), vec![0x02, 0x03]),
+        ]);
+
+        let args = protocol.sign_winternitz("A", 0, 0, &values, tc.key_manager())?;
+        assert!(matches!(args, InputArgs::TaprootScript { leaf: 0, .. }));
+
+        // The last key goes deepest, the first key right below the taproot signature
+        let key_manager = tc.key_manager();
+        let mut expected = InputArgs::new_taproot_script_args(0);
+        expected.push_winternitz_signature(key_manager.sign_winternitz_message(
+            &[0x02, 0x03],
+            WinternitzType::HASH160,
+            5,
+        )?);
+        expected.push_winternitz_signature(key_manager.sign_winternitz_message(
+            &[0x01],
+            WinternitzType::HASH160,
+            3,
+        )?);
+        expected.push_taproot_signature(
+            protocol
+                .input_taproot_script_spend_signature("A", 0, 0)?
+                .unwrap(),
+        )?;
+        assert_eq!(
+            args.iter().collect::<Vec<_>>(),
+            expected.iter().collect::<Vec<_>>()
+        );
+
+        // The arguments spend the leaf as they are
+        let transaction = protocol.transaction_to_send("A", &[args])?;
+        assert_eq!(transaction.input[0].witness.len(), expected.len() + 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_winternitz_missing_value() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_sign_winternitz_missing_value").unwrap();
+        let protocol = protocol(&tc)?;
+        let values = HashMap::from([("first".to_string(), vec![0x01])]);
+
+        assert!(matches!(
+            protocol.sign_winternitz("A", 0, 0, &values, tc.key_manager()),
+            Err(ProtocolBuilderError::MissingWinternitzValue(name)) if name == "second"
+        ));
+        assert!(matches!(
+            protocol.sign_winternitz("A", 0, 1, &values, tc.key_manager()),
+            Err(ProtocolBuilderError::MissingTaprootLeaf(1, 0))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::{
+        hashes::Hash, opcodes::all::OP_CHECKSIG, script::Builder, PublicKey, Witness,
+        XOnlyPublicKey,
+    };
+    use key_manager::{
+        key_type::BitcoinKeyType,
+        winternitz::{WinternitzPublicKey, WinternitzType},
+    };
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        helpers::witness_decoder::decode_winternitz_values,
+        scripts::{KeyType, ProtocolScript, SignMode},
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::SpendMode,
+            output::OutputType,
+        },
+    };
+
+    fn leaf(public_key: &PublicKey, keys: &[(&str, &WinternitzPublicKey)]) -> ProtocolScript {
+        let script = Builder::new()
+            .push_x_only_key(&XOnlyPublicKey::from(*public_key))
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let mut leaf = ProtocolScript::new(script, public_key, SignMode::Single);
+        for (position, (name, key)) in keys.iter().enumerate() {
+            leaf.add_key(
+                name,
+                key.derivation_index().unwrap(),
+                KeyType::winternitz(key).unwrap(),
+                position as u32,
+            )
+            .unwrap();
+        }
+        leaf
+    }
+
+    #[test]
+    fn test_decode_winternitz_witness() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_decode_winternitz_witness").unwrap();
+        let key_manager = tc.key_manager();
+        let public_key = key_manager.derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let public_keys = HashMap::from([
+            (
+                "step".to_string(),
+                key_manager.derive_winternitz(1, WinternitzType::HASH160, 3)?,
+            ),
+            (
+                "hash".to_string(),
+                key_manager.derive_winternitz(2, WinternitzType::HASH160, 5)?,
+            ),
+        ]);
+
+        let mut protocol = Protocol::new("witness_decoder");
+        ProtocolBuilder {}.add_external_connection(
+            &mut protocol,
+            "EXT",
+            Hash::all_zeros(),
+            OutputSpec::Auto(OutputType::taproot(
+                10_000,
+                &public_key,
+                &[leaf(
+                    &public_key,
+                    &[
+                        ("step", &public_keys["step"]),
+                        ("hash", &public_keys["hash"]),
+                    ],
+                )],
+            )?),
+            "A",
+            InputSpec::Auto(tc.tr_sighash_type(), SpendMode::ScriptsOnly),
+        )?;
+        protocol.build_and_sign(key_manager, "")?;
+
+        let values = HashMap::from([
+            ("step".to_string(), vec![0x2a]),
+            ("hash".to_string(), vec![0xbe, 0x07]),
+        ]);
+        let args = protocol.sign_winternitz("A", 0, 0, &values, key_manager)?;
+        let transaction = protocol.transaction_to_send("A", &[args])?;
+
+        // Values come back in key position order, with verified signatures
+        let decoded = protocol.decode_winternitz_witness("A", 0, &transaction, &public_keys)?;
+        assert_eq!(
+            decoded
+                .iter()
+                .map(|value| (value.key.name(), value.message.clone()))
+                .collect::<Vec<_>>(),
+            vec![("step", vec![0x2a]), ("hash", vec![0xbe, 0x07])]
+        );
+
+        // Signatures made with other keys are rejected
+        let mut wrong_keys = public_keys.clone();
+        wrong_keys.insert(
+            "hash".to_string(),
+            key_manager.derive_winternitz(2, WinternitzType::HASH160, 6)?,
+        );
+        assert!(matches!(
+            protocol.decode_winternitz_witness("A", 0, &transaction, &wrong_keys),
+            Err(ProtocolBuilderError::InvalidWinternitzSignature(name)) if name == "hash"
+        ));
+
+        let mut missing_keys = public_keys.clone();
+        missing_keys.remove("step");
+        assert!(matches!(
+            protocol.decode_winternitz_witness("A", 0, &transaction, &missing_keys),
+            Err(ProtocolBuilderError::MissingWinternitzPublicKey(name)) if name == "step"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_invalid_witness() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_decode_invalid_witness").unwrap();
+        let key_manager = tc.key_manager();
+        let public_key = key_manager.derive_keypair(BitcoinKeyType::P2tr, 0)?;
+        let winternitz_key = key_manager.derive_winternitz(1, WinternitzType::HASH160, 3)?;
+        let leaf = leaf(&public_key, &[("value", &winternitz_key)]);
+        let signature = key_manager.sign_winternitz_message(&[0x10], WinternitzType::HASH160, 3)?;
+
+        let witness = |digits: &[Vec<u8>]| {
+            let mut witness = Witness::new();
+            for (hash, digit) in signature.to_hashes().iter().zip(digits) {
+                witness.push(hash);
+                witness.push(digit);
+            }
+            witness.push(leaf.get_script().as_bytes());
+            witness.push([0xc0; 33]);
+            witness
+        };
+        let digits = signature
+            .checksummed_message_digits()
+            .iter()
+            .map(|digit| if *digit == 0 { vec![] } else { vec![*digit] })
+            .collect::<Vec<_>>();
+
+        let decoded = decode_winternitz_values(&witness(&digits), &leaf)?;
+        assert_eq!(decoded[0].message, vec![0x10]);
+
+        // A digit changed without its checksum
+        let mut tampered = digits.clone();
+        tampered[0] = vec![0x02];
+        assert!(matches!(
+            decode_winternitz_values(&witness(&tampered), &leaf),
+            Err(ProtocolBuilderError::InvalidWinternitzWitness(_))
+        ));
+
+        assert!(matches!(
+            decode_winternitz_values(&witness(&digits[1..]), &leaf),
+            Err(ProtocolBuilderError::InvalidWinternitzWitness(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::{sha256, Hash};
+    use key_manager::key_type::BitcoinKeyType;
+
+    use crate::{
+        builder::{Protocol, ProtocolBuilder},
+        errors::ProtocolBuilderError,
+        scripts::SignMode,
+        tests::utils::TestContext,
+        types::{
+            connection::{InputSpec, OutputSpec},
+            input::{InputArgs, SpendMode},
+            output::OutputType,
+            witness_preview::WitnessItemRole,
+        },
+    };
+
+    // EXT -> LOCK -> CLAIM, CLAIM spends the hashlock leaf
+    fn protocol(tc: &TestContext) -> Result<Protocol, ProtocolBuilderError> {
+        let funding_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2wpkh, 0)?;
+        let internal_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 1)?;
+        let recipient_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 2)?;
+        let refund_key = tc.key_manager().derive_keypair(BitcoinKeyType::P2tr, 3)?;
+
+        let mut protocol = Protocol::new("witness_preview");
+        ProtocolBuilder {}
+            .add_external_connection(
+                &mut protocol,
+                "EXT",
+                Hash::all_zeros(),
+                OutputSpec::Auto(OutputType::segwit_key(10_000, &funding_key)?),
+                "LOCK",
+                InputSpec::Auto(tc.ecdsa_sighash_type(), SpendMode::Segwit),
+            )?
+            .add_htlc_connection(
+                &mut protocol,
+                "pegout",
+                "LOCK",
+                8_000,
+                &internal_key,
+                sha256::Hash::hash(&[0x42; 32]),
+                &recipient_key,
+                &refund_key,
+                144,
+                SignMode::Single,
+                "CLAIM",
+                &tc.tr_sighash_type(),
+                None,
+            )?;
+        protocol.build_and_sign(tc.key_manager(), "")?;
+        Ok(protocol)
+    }
+
+    #[test]
+    fn test_preview_witness() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_preview_witness").unwrap();
+        let protocol = protocol(&tc)?;
+
+        let args = InputArgs::from_protocol(&protocol, "LOCK", 0, None)?;
+        let preview = protocol.preview_witness("LOCK", 0, &args)?;
+        assert_eq!(
+            preview.roles(),
+            vec![WitnessItemRole::EcdsaSignature, WitnessItemRole::PublicKey]
+        );
+        assert_eq!(preview.items[1].size, 33);
+
+        let mut args = InputArgs::from_protocol(&protocol, "CLAIM", 0, Some(0))?;
+        args.push_slice(&[0x42; 32]);
+        let preview = protocol.preview_witness("CLAIM", 0, &args)?;
+        assert_eq!(
+            preview.roles(),
+            vec![
+                WitnessItemRole::SchnorrSignature,
+                WitnessItemRole::Data,
+                WitnessItemRole::Script,
+                WitnessItemRole::ControlBlock,
+            ]
+        );
+        assert_eq!(preview.items[1].hex, "42".repeat(32));
+
+        // The preview matches the witness of the transaction to send
+        let transaction = protocol.transaction_to_send("CLAIM", &[args])?;
+        let witness = &transaction.input[0].witness;
+        assert_eq!(preview.items.len(), witness.len());
+        for (item, expected) in preview.items.iter().zip(witness.iter()) {
+            assert_eq!(item.hex, hex::encode(expected));
+        }
+        assert_eq!(
+            preview.hex,
+            bitcoin::consensus::encode::serialize_hex(witness)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preview_malformed_witness() -> Result<(), ProtocolBuilderError> {
+        let tc = TestContext::new("test_preview_malformed_witness").unwrap();
+        let protocol = protocol(&tc)?;
+
+        // Items in the wrong order are previewed with the roles the leaf expects
+        let mut args = InputArgs::new_taproot_script_args(0);
+        args.push_slice(&[0x42; 32]).push_slice(&[0x42; 32]);
+        assert!(protocol
+            .transaction_to_send("CLAIM", &[args.clone()])
+            .is_err());
+
+        let preview = protocol.preview_witness("CLAIM", 0, &args)?;
+        assert_eq!(preview.roles()[0], WitnessItemRole::SchnorrSignature);
+        assert_eq!(preview.items[0].size, 32);
+
+        let dump = preview.hex_dump();
+        assert!(dump.starts_with("#0 schnorr signature (32 bytes)\n  0000  4242"));
+        assert!(dump.contains("#3 control block (65 bytes)\n  0000  "));
+        assert!(dump.contains("\n  0040  "));
+
+        assert!(matches!(
+            protocol.preview_witness("CLAIM", 1, &args),
+            Err(ProtocolBuilderError::MissingInput(name, 1)) if name == "CLAIM"
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum AuditEvent {
+    /// Structural changes were locked, typically before exchanging sighashes.
+    Frozen,
+    /// Structural changes were unlocked.
+    Unfrozen { reason: String },
+    /// The signatures of the listed transactions were discarded by the first build after an
+    /// unfreeze, since the transactions or their ancestors changed.
+    SignaturesCleared { transactions: Vec<String> },
+    /// Structural change recorded while the history is recorded, see `Protocol::replay_to`.
+    Mutation { step: usize, operation: String },
+}
+
+/// Entry of the audit trail of a protocol, see `Protocol::freeze`, `Protocol::unfreeze` and
+/// `Protocol::record_history`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AuditEntry {
+    /// Unix time of the event.
+    pub timestamp: u64,
+    pub event: AuditEvent,
+}
+
+impl AuditEntry {
+    pub fn new(event: AuditEvent) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        Self { timestamp, event }
+    }
+}
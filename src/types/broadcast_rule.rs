@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+use crate::graph::graph::prefixed_name;
+
+/// Chain condition that must hold before a transaction is broadcasted automatically.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum BroadcastCondition {
+    /// The named transaction has at least the given number of confirmations.
+    Confirmations(String, u32),
+    /// The named transaction has not been seen, neither in the mempool nor in a block.
+    NotObserved(String),
+    /// The chain tip is at or above the given height.
+    Height(u32),
+}
+
+impl BroadcastCondition {
+    /// Name of the protocol transaction the condition refers to, if any.
+    pub fn transaction_name(&self) -> Option<&str> {
+        match self {
+            BroadcastCondition::Confirmations(name, _) | BroadcastCondition::NotObserved(name) => {
+                Some(name)
+            }
+            BroadcastCondition::Height(_) => None,
+        }
+    }
+
+    fn renamed(self, rename: &impl Fn(&str) -> String) -> Self {
+        match self {
+            BroadcastCondition::Confirmations(name, confirmations) => {
+                BroadcastCondition::Confirmations(rename(&name), confirmations)
+            }
+            BroadcastCondition::NotObserved(name) => BroadcastCondition::NotObserved(rename(&name)),
+            BroadcastCondition::Height(height) => BroadcastCondition::Height(height),
+        }
+    }
+}
+
+/// Rule attached to a protocol transaction so it is broadcasted automatically once all its
+/// conditions hold, e.g. "broadcast TIMEOUT_TAKE_2 once CHALLENGE_START has 144 confirmations
+/// unless CHALLENGE was observed".
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BroadcastRule {
+    transaction: String,
+    conditions: Vec<BroadcastCondition>,
+}
+
+impl BroadcastRule {
+    pub fn new(transaction: &str) -> Self {
+        Self {
+            transaction: transaction.to_string(),
+            conditions: vec![],
+        }
+    }
+
+    pub fn after_confirmations(mut self, transaction: &str, confirmations: u32) -> Self {
+        self.conditions.push(BroadcastCondition::Confirmations(
+            transaction.to_string(),
+            confirmations,
+        ));
+        self
+    }
+
+    pub fn unless_observed(mut self, transaction: &str) -> Self {
+        self.conditions
+            .push(BroadcastCondition::NotObserved(transaction.to_string()));
+        self
+    }
+
+    pub fn at_height(mut self, height: u32) -> Self {
+        self.conditions.push(BroadcastCondition::Height(height));
+        self
+    }
+
+    pub fn transaction(&self) -> &str {
+        &self.transaction
+    }
+
+    pub fn conditions(&self) -> &[BroadcastCondition] {
+        &self.conditions
+    }
+
+    pub(crate) fn with_prefix(self, prefix: &str) -> Self {
+        self.renamed(|name| prefixed_name(prefix, name))
+    }
+
+    /// Same rule with the transaction and the transactions of its conditions renamed.
+    pub(crate) fn renamed(self, rename: impl Fn(&str) -> String) -> Self {
+        Self {
+            transaction: rename(&self.transaction),
+            conditions: self
+                .conditions
+                .into_iter()
+                .map(|condition| condition.renamed(&rename))
+                .collect(),
+        }
+    }
+}
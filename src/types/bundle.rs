@@ -0,0 +1,100 @@
+use bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::SerializationError;
+
+use super::{
+    input::Signature,
+    serialization::{deserialize, serialize, SerializationFormat},
+};
+
+/// Signature of an input of a protocol transaction. For taproot inputs the signature index is
+/// the leaf index, or the number of leaves for the key path.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BundledSignature {
+    pub transaction_name: String,
+    pub input_index: usize,
+    pub signature_index: usize,
+    /// Key that produced the signature: the output key of segwit outputs, the verifying key of
+    /// scripts and leaves, or the internal (untweaked) key for taproot key path spends.
+    pub public_key: PublicKey,
+    pub signature: Signature,
+}
+
+/// Signatures exchanged between the parties of a protocol, so each party only needs to send the
+/// signatures it produced instead of the whole serialized protocol. See
+/// `Protocol::export_signatures` and `Protocol::import_signatures`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignatureBundle {
+    pub protocol_name: String,
+    pub signatures: Vec<BundledSignature>,
+}
+
+impl SignatureBundle {
+    pub fn new(protocol_name: &str) -> Self {
+        Self {
+            protocol_name: protocol_name.to_string(),
+            signatures: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    pub fn to_bytes(&self, format: SerializationFormat) -> Result<Vec<u8>, SerializationError> {
+        serialize(self, format)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        deserialize(bytes)
+    }
+}
+
+/// Selects the signatures exported in a bundle. An empty filter selects every stored signature.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SignatureFilter {
+    pub transactions: Option<Vec<String>>,
+    pub public_keys: Option<Vec<PublicKey>>,
+}
+
+impl SignatureFilter {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn with_transactions(mut self, transaction_names: &[&str]) -> Self {
+        self.transactions = Some(
+            transaction_names
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        );
+        self
+    }
+
+    /// Only signatures produced by the given key, e.g. the keys of the exporting party.
+    pub fn with_public_key(mut self, public_key: &PublicKey) -> Self {
+        self.public_keys
+            .get_or_insert_with(Vec::new)
+            .push(*public_key);
+        self
+    }
+
+    pub fn matches(&self, transaction_name: &str, public_key: &PublicKey) -> bool {
+        let transaction_matches = self
+            .transactions
+            .as_ref()
+            .is_none_or(|names| names.iter().any(|name| name == transaction_name));
+        let key_matches = self
+            .public_keys
+            .as_ref()
+            .is_none_or(|keys| keys.contains(public_key));
+
+        transaction_matches && key_matches
+    }
+}
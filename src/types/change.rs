@@ -0,0 +1,69 @@
+use bitcoin::{Amount, PublicKey, ScriptBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ProtocolBuilderError;
+
+use super::OutputType;
+
+/// Default fee rate (in sat/vB) paid by the transaction receiving the change output.
+pub const DEFAULT_CHANGE_FEE_RATE: u64 = 1;
+
+/// Address receiving the funds left over from the external funding of a protocol.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ChangeDestination {
+    /// Paid to a P2WPKH output.
+    PublicKey(PublicKey),
+    /// Paid to an arbitrary script, that the protocol never spends.
+    Script(ScriptBuf),
+}
+
+impl From<PublicKey> for ChangeDestination {
+    fn from(public_key: PublicKey) -> Self {
+        ChangeDestination::PublicKey(public_key)
+    }
+}
+
+impl From<ScriptBuf> for ChangeDestination {
+    fn from(script_pubkey: ScriptBuf) -> Self {
+        ChangeDestination::Script(script_pubkey)
+    }
+}
+
+impl ChangeDestination {
+    pub fn output(&self, value: u64) -> Result<OutputType, ProtocolBuilderError> {
+        match self {
+            ChangeDestination::PublicKey(public_key) => OutputType::segwit_key(value, public_key),
+            ChangeDestination::Script(script_pubkey) => {
+                let mut output = OutputType::segwit_unspendable(script_pubkey.clone())?;
+                output.set_value(Amount::from_sat(value));
+                Ok(output)
+            }
+        }
+    }
+}
+
+/// Change output appended by `Protocol::build` to the first transaction spending external funds.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ChangeOutput {
+    pub destination: ChangeDestination,
+    /// Fee rate (in sat/vB) left to the funded transaction.
+    pub fee_rate: u64,
+    /// Transaction and index of the change output, once appended.
+    pub(crate) location: Option<(String, usize)>,
+}
+
+impl ChangeOutput {
+    pub fn new(destination: ChangeDestination) -> Self {
+        Self {
+            destination,
+            fee_rate: DEFAULT_CHANGE_FEE_RATE,
+            location: None,
+        }
+    }
+
+    pub fn location(&self) -> Option<(&str, usize)> {
+        self.location
+            .as_ref()
+            .map(|(transaction_name, output_index)| (transaction_name.as_str(), *output_index))
+    }
+}
@@ -0,0 +1,116 @@
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    Transaction, Txid,
+};
+use serde::{Deserialize, Serialize};
+
+const LEAF_TAG: u8 = 0x00;
+const NODE_TAG: u8 = 0x01;
+
+/// Proof that a transaction is part of a protocol instance, verified against the protocol
+/// commitment hash (see `Protocol::commitment_hash`) without revealing the other transactions.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct InclusionProof {
+    pub transaction_name: String,
+    pub leaf_index: u32,
+    pub leaf_count: u32,
+    /// Sibling hashes from the leaf up to the root. Levels where the node has no sibling are
+    /// skipped.
+    pub siblings: Vec<sha256::Hash>,
+}
+
+impl InclusionProof {
+    /// Returns true if the transaction (signed or not, witnesses are not committed) is the one
+    /// committed under this proof's name in the given commitment hash.
+    pub fn verify(&self, transaction: &Transaction, commitment: &sha256::Hash) -> bool {
+        self.verify_txid(&transaction.compute_txid(), commitment)
+    }
+
+    pub fn verify_txid(&self, txid: &Txid, commitment: &sha256::Hash) -> bool {
+        if self.leaf_index >= self.leaf_count {
+            return false;
+        }
+
+        let mut hash = leaf_hash(&self.transaction_name, txid);
+        let mut index = self.leaf_index as usize;
+        let mut width = self.leaf_count as usize;
+        let mut siblings = self.siblings.iter();
+
+        while width > 1 {
+            let sibling_index = index ^ 1;
+            if sibling_index < width {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                hash = if index & 1 == 0 {
+                    node_hash(&hash, sibling)
+                } else {
+                    node_hash(sibling, &hash)
+                };
+            }
+            index /= 2;
+            width = width.div_ceil(2);
+        }
+
+        siblings.next().is_none() && hash == *commitment
+    }
+}
+
+/// Hash of the canonical encoding of a protocol transaction: its name and its txid.
+pub fn leaf_hash(transaction_name: &str, txid: &Txid) -> sha256::Hash {
+    let mut engine = sha256::Hash::engine();
+    engine.input(&[LEAF_TAG]);
+    engine.input(&(transaction_name.len() as u32).to_le_bytes());
+    engine.input(transaction_name.as_bytes());
+    engine.input(txid.as_byte_array());
+    sha256::Hash::from_engine(engine)
+}
+
+fn node_hash(left: &sha256::Hash, right: &sha256::Hash) -> sha256::Hash {
+    let mut engine = sha256::Hash::engine();
+    engine.input(&[NODE_TAG]);
+    engine.input(left.as_byte_array());
+    engine.input(right.as_byte_array());
+    sha256::Hash::from_engine(engine)
+}
+
+/// Computes the Merkle root of the leaves. A node without sibling is promoted unchanged to the
+/// next level.
+pub(crate) fn merkle_root(leaves: &[sha256::Hash]) -> sha256::Hash {
+    if leaves.is_empty() {
+        return sha256::Hash::all_zeros();
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+pub(crate) fn merkle_path(leaves: &[sha256::Hash], leaf_index: usize) -> Vec<sha256::Hash> {
+    let mut siblings = vec![];
+    let mut level = leaves.to_vec();
+    let mut index = leaf_index;
+
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(index ^ 1) {
+            siblings.push(*sibling);
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+
+    siblings
+}
+
+fn next_level(level: &[sha256::Hash]) -> Vec<sha256::Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
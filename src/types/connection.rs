@@ -10,13 +10,24 @@ use super::{
 pub enum InputSpec {
     Index(usize),
     Auto(SighashType, SpendMode),
+    /// Adds a new input, like `Auto`, and assigns it a label unique within the transaction.
+    Labeled(String, SighashType, SpendMode),
+    /// Refers to an input previously added with a label.
+    Label(String),
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OutputSpec {
+    /// Refers to an existing output. An output already spent by another transaction can be
+    /// spent again, making both spenders alternative branches (see `Protocol::spenders`).
     Index(usize),
     Auto(OutputType),
     Last,
+    /// Adds a new output, like `Auto`, and assigns it a label unique within the transaction.
+    Labeled(String, OutputType),
+    /// Refers to an output previously added with a label.
+    Label(String),
 }
 
 impl Into<OutputSpec> for OutputType {
@@ -31,12 +42,24 @@ impl Into<OutputSpec> for usize {
     }
 }
 
+impl From<&str> for OutputSpec {
+    fn from(label: &str) -> Self {
+        OutputSpec::Label(label.to_string())
+    }
+}
+
 impl Into<InputSpec> for usize {
     fn into(self) -> InputSpec {
         InputSpec::Index(self)
     }
 }
 
+impl From<&str> for InputSpec {
+    fn from(label: &str) -> Self {
+        InputSpec::Label(label.to_string())
+    }
+}
+
 pub enum ConnectionType {
     Internal {
         from: String,
@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{OnceLock, RwLock},
+};
+
+use bitcoin::{secp256k1::Message, Amount, ScriptBuf, Transaction, TxOut, Witness};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::ProtocolBuilderError;
+
+use super::{
+    input::{InputArgs, InputType, SighashType, Signature, SpendMode},
+    signer::Signer,
+};
+
+/// Extension point for output kinds defined outside this crate (e.g. covenant outputs). Custom
+/// outputs are wrapped in `OutputType::Custom` and handled generically by the graph, the sighash
+/// and signing loops and the witness assembly.
+///
+/// Custom outputs are persisted as their `kind` plus the bytes returned by `to_bytes`. A
+/// deserializer must be registered for every kind with `register_custom_output` before loading a
+/// protocol that contains it.
+pub trait CustomOutput: fmt::Debug {
+    /// Unique identifier of the output kind.
+    fn kind(&self) -> &str;
+
+    fn value(&self) -> Amount;
+
+    fn set_value(&mut self, value: Amount);
+
+    fn script_pubkey(&self) -> &ScriptBuf;
+
+    /// Returns true if inputs with the given sighash type can spend this output.
+    fn supports_sighash_type(&self, sighash_type: &SighashType) -> bool;
+
+    /// Computes the messages to be signed by the input spending this output.
+    fn compute_sighashes(
+        &self,
+        transaction: &Transaction,
+        transaction_name: &str,
+        input_index: usize,
+        prevouts: &[TxOut],
+        spend_mode: &SpendMode,
+        sighash_type: &SighashType,
+    ) -> Result<Vec<Option<Message>>, ProtocolBuilderError>;
+
+    /// Signs the messages previously computed with `compute_sighashes`.
+    #[allow(clippy::too_many_arguments)]
+    fn compute_signatures(
+        &self,
+        transaction_name: &str,
+        input_index: usize,
+        hashed_messages: &[Option<Message>],
+        spend_mode: &SpendMode,
+        sighash_type: &SighashType,
+        signer: &dyn Signer,
+        id: &str,
+    ) -> Result<Vec<Option<Signature>>, ProtocolBuilderError>;
+
+    /// Builds the witness of the input spending this output.
+    fn witness(&self, input: &InputType, args: &InputArgs)
+        -> Result<Witness, ProtocolBuilderError>;
+
+    /// Estimated size in bytes of the witness spending this output.
+    fn estimate_witness_bytes(&self) -> usize;
+
+    fn dust_limit(&self) -> Amount {
+        Amount::from_sat(540)
+    }
+
+    fn to_descriptor(&self) -> Option<String> {
+        None
+    }
+
+    /// Serializes the output data, decoded back by the deserializer registered for its kind.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    fn clone_box(&self) -> Box<dyn CustomOutput>;
+}
+
+pub type CustomOutputDeserializer =
+    fn(&[u8]) -> Result<Box<dyn CustomOutput>, ProtocolBuilderError>;
+
+fn registry() -> &'static RwLock<HashMap<String, CustomOutputDeserializer>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, CustomOutputDeserializer>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Registers the deserializer of a custom output kind. Registering a kind again replaces its
+/// deserializer.
+pub fn register_custom_output(kind: &str, deserializer: CustomOutputDeserializer) {
+    registry()
+        .write()
+        .expect("custom output registry poisoned")
+        .insert(kind.to_string(), deserializer);
+}
+
+pub fn is_custom_output_registered(kind: &str) -> bool {
+    registry()
+        .read()
+        .expect("custom output registry poisoned")
+        .contains_key(kind)
+}
+
+/// Owned custom output, stored inside `OutputType::Custom`.
+#[derive(Debug)]
+pub struct BoxedCustomOutput(Box<dyn CustomOutput>);
+
+impl BoxedCustomOutput {
+    pub fn new<T: CustomOutput + 'static>(output: T) -> Self {
+        Self(Box::new(output))
+    }
+
+    pub fn from_bytes(kind: &str, data: &[u8]) -> Result<Self, ProtocolBuilderError> {
+        let deserializer = *registry()
+            .read()
+            .expect("custom output registry poisoned")
+            .get(kind)
+            .ok_or(ProtocolBuilderError::UnregisteredCustomOutput(
+                kind.to_string(),
+            ))?;
+
+        Ok(Self(deserializer(data)?))
+    }
+}
+
+impl std::ops::Deref for BoxedCustomOutput {
+    type Target = dyn CustomOutput;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl std::ops::DerefMut for BoxedCustomOutput {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut()
+    }
+}
+
+impl Clone for BoxedCustomOutput {
+    fn clone(&self) -> Self {
+        Self(self.0.clone_box())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedCustomOutput {
+    kind: String,
+    data: Vec<u8>,
+}
+
+impl Serialize for BoxedCustomOutput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedCustomOutput {
+            kind: self.kind().to_string(),
+            data: self.to_bytes(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BoxedCustomOutput {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serialized = SerializedCustomOutput::deserialize(deserializer)?;
+        BoxedCustomOutput::from_bytes(&serialized.kind, &serialized.data)
+            .map_err(|e| de::Error::custom(e.to_string()))
+    }
+}
@@ -0,0 +1,252 @@
+use std::fmt::{Display, Formatter, Write};
+
+use bitcoin::{PublicKey, ScriptBuf};
+use serde::{Deserialize, Serialize};
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Output of a transaction spent by an input, as (transaction name, output index).
+pub type SpentOutput = (String, usize);
+
+/// Structural difference between two versions of a protocol, see `Protocol::diff`. Transactions
+/// are matched by name, so a renamed transaction shows up as removed and added.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ProtocolDiff {
+    pub added_transactions: Vec<String>,
+    pub removed_transactions: Vec<String>,
+    pub changed_transactions: Vec<TransactionDiff>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TransactionDiff {
+    pub transaction_name: String,
+    pub changes: Vec<TransactionChange>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum TransactionChange {
+    LockTime {
+        old: u32,
+        new: u32,
+    },
+    InputAdded(usize),
+    InputRemoved(usize),
+    /// The input spends a different output, or is no longer (or newly) connected.
+    InputSpends {
+        input_index: usize,
+        old: Option<SpentOutput>,
+        new: Option<SpentOutput>,
+    },
+    Sequence {
+        input_index: usize,
+        old: u32,
+        new: u32,
+    },
+    OutputAdded {
+        output_index: usize,
+        value: u64,
+    },
+    OutputRemoved {
+        output_index: usize,
+        value: u64,
+    },
+    OutputValue {
+        output_index: usize,
+        old: u64,
+        new: u64,
+    },
+    /// The output has a different kind, e.g. a taproot output became a segwit one.
+    OutputKind {
+        output_index: usize,
+        old: String,
+        new: String,
+    },
+    /// The segwit key, the verifying key of the segwit script or the taproot internal key.
+    OutputKey {
+        output_index: usize,
+        old: Option<PublicKey>,
+        new: Option<PublicKey>,
+    },
+    OutputScript {
+        output_index: usize,
+        old: ScriptBuf,
+        new: ScriptBuf,
+    },
+    LeafAdded {
+        output_index: usize,
+        leaf: usize,
+    },
+    LeafRemoved {
+        output_index: usize,
+        leaf: usize,
+    },
+    LeafScript {
+        output_index: usize,
+        leaf: usize,
+        old: ScriptBuf,
+        new: ScriptBuf,
+    },
+    LeafKey {
+        output_index: usize,
+        leaf: usize,
+        old: Option<PublicKey>,
+        new: Option<PublicKey>,
+    },
+}
+
+impl ProtocolDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_transactions.is_empty()
+            && self.removed_transactions.is_empty()
+            && self.changed_transactions.is_empty()
+    }
+
+    /// Lists the differences one per line, prefixing added transactions with `+`, removed ones
+    /// with `-` and changed ones with `~`. With `colored`, lines are wrapped in ANSI colors for
+    /// terminals.
+    pub fn to_text(&self, colored: bool) -> String {
+        let mut text = String::new();
+        let mut line = |color: &str, content: String| {
+            if colored {
+                writeln!(text, "{}{}{}", color, content, RESET).unwrap();
+            } else {
+                writeln!(text, "{}", content).unwrap();
+            }
+        };
+
+        for name in self.added_transactions.iter() {
+            line(GREEN, format!("+ {}", name));
+        }
+        for name in self.removed_transactions.iter() {
+            line(RED, format!("- {}", name));
+        }
+        for transaction in self.changed_transactions.iter() {
+            line(YELLOW, format!("~ {}", transaction.transaction_name));
+            for change in transaction.changes.iter() {
+                line(change.color(), format!("    {}", change));
+            }
+        }
+
+        text
+    }
+}
+
+impl TransactionChange {
+    fn color(&self) -> &'static str {
+        match self {
+            Self::InputAdded(_) | Self::OutputAdded { .. } | Self::LeafAdded { .. } => GREEN,
+            Self::InputRemoved(_) | Self::OutputRemoved { .. } | Self::LeafRemoved { .. } => RED,
+            _ => YELLOW,
+        }
+    }
+}
+
+impl Display for TransactionChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LockTime { old, new } => write!(f, "locktime: {} -> {}", old, new),
+            Self::InputAdded(input_index) => write!(f, "+ input {}", input_index),
+            Self::InputRemoved(input_index) => write!(f, "- input {}", input_index),
+            Self::InputSpends {
+                input_index,
+                old,
+                new,
+            } => write!(
+                f,
+                "input {} spends: {} -> {}",
+                input_index,
+                spent_label(old),
+                spent_label(new)
+            ),
+            Self::Sequence {
+                input_index,
+                old,
+                new,
+            } => write!(
+                f,
+                "input {} sequence: {:#x} -> {:#x}",
+                input_index, old, new
+            ),
+            Self::OutputAdded {
+                output_index,
+                value,
+            } => write!(f, "+ output {} [{}]", output_index, value),
+            Self::OutputRemoved {
+                output_index,
+                value,
+            } => write!(f, "- output {} [{}]", output_index, value),
+            Self::OutputValue {
+                output_index,
+                old,
+                new,
+            } => write!(f, "output {} value: {} -> {}", output_index, old, new),
+            Self::OutputKind {
+                output_index,
+                old,
+                new,
+            } => write!(f, "output {} kind: {} -> {}", output_index, old, new),
+            Self::OutputKey {
+                output_index,
+                old,
+                new,
+            } => write!(
+                f,
+                "output {} key: {} -> {}",
+                output_index,
+                key_label(old),
+                key_label(new)
+            ),
+            Self::OutputScript {
+                output_index,
+                old,
+                new,
+            } => write!(f, "output {} script: {} -> {}", output_index, old, new),
+            Self::LeafAdded { output_index, leaf } => {
+                write!(f, "+ output {} leaf {}", output_index, leaf)
+            }
+            Self::LeafRemoved { output_index, leaf } => {
+                write!(f, "- output {} leaf {}", output_index, leaf)
+            }
+            Self::LeafScript {
+                output_index,
+                leaf,
+                old,
+                new,
+            } => write!(
+                f,
+                "output {} leaf {} script: {} -> {}",
+                output_index, leaf, old, new
+            ),
+            Self::LeafKey {
+                output_index,
+                leaf,
+                old,
+                new,
+            } => write!(
+                f,
+                "output {} leaf {} key: {} -> {}",
+                output_index,
+                leaf,
+                key_label(old),
+                key_label(new)
+            ),
+        }
+    }
+}
+
+fn spent_label(spent: &Option<SpentOutput>) -> String {
+    match spent {
+        Some((transaction_name, output_index)) => format!("{}:{}", transaction_name, output_index),
+        None => "unconnected".to_string(),
+    }
+}
+
+fn key_label(key: &Option<PublicKey>) -> String {
+    match key {
+        Some(key) => key.to_string(),
+        None => "none".to_string(),
+    }
+}
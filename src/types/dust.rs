@@ -0,0 +1,90 @@
+use bitcoin::{Amount, FeeRate};
+use serde::{Deserialize, Serialize};
+
+use super::OutputType;
+
+/// Default dust relay fee of Bitcoin Core, in sat/vB.
+pub const DEFAULT_DUST_RELAY_FEE: u64 = 3;
+
+/// What to do with outputs whose value is below the dust limit of their script kind.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DustAction {
+    /// Building the protocol fails with a `DustOutput` error.
+    Reject,
+    /// The output value is raised to the dust limit.
+    Bump,
+}
+
+/// Dust limits enforced on the outputs of a protocol when it is built. Limits depend on the
+/// script kind, as Bitcoin Core computes them from the size of the output and of the input
+/// spending it (e.g. 330 sats for P2TR and P2WSH, 294 sats for P2WPKH and 0 for OP_RETURN at the
+/// default dust relay fee).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DustPolicy {
+    action: DustAction,
+    dust_relay_fee: u64,
+}
+
+impl Default for DustPolicy {
+    fn default() -> Self {
+        Self::reject()
+    }
+}
+
+impl DustPolicy {
+    pub fn reject() -> Self {
+        Self {
+            action: DustAction::Reject,
+            dust_relay_fee: DEFAULT_DUST_RELAY_FEE,
+        }
+    }
+
+    pub fn bump() -> Self {
+        Self {
+            action: DustAction::Bump,
+            dust_relay_fee: DEFAULT_DUST_RELAY_FEE,
+        }
+    }
+
+    /// Dust relay fee (in sat/vB) of the nodes the transactions are broadcasted to.
+    pub fn with_dust_relay_fee(mut self, sat_per_vb: u64) -> Self {
+        self.dust_relay_fee = sat_per_vb;
+        self
+    }
+
+    pub fn action(&self) -> DustAction {
+        self.action
+    }
+
+    pub fn dust_relay_fee(&self) -> u64 {
+        self.dust_relay_fee
+    }
+
+    /// Minimum value of the output. Custom outputs declare their own limit, and P2A anchors
+    /// are ephemeral dust, relayed at any value in a zero-fee transaction whose anchor is spent
+    /// in the same package.
+    pub fn dust_limit(&self, output: &OutputType) -> Amount {
+        match output {
+            OutputType::Custom { output } => output.dust_limit(),
+            OutputType::PayToAnchor { .. } => Amount::ZERO,
+            _ => output
+                .get_script_pubkey()
+                .minimal_non_dust_custom(FeeRate::from_sat_per_kwu(
+                    self.dust_relay_fee.saturating_mul(250),
+                )),
+        }
+    }
+
+    /// Returns true if the value of the output is below its dust limit. Outputs whose value is
+    /// still to be computed (see `compute_minimum_output_values`) are not checked.
+    pub fn is_dust(&self, output: &OutputType) -> bool {
+        if output.auto_value() || output.recover_value() {
+            return false;
+        }
+
+        match output {
+            OutputType::ExternalUnknown { .. } => false,
+            _ => output.get_value() < self.dust_limit(output),
+        }
+    }
+}
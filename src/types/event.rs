@@ -0,0 +1,83 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+
+/// Change made to a protocol, passed to the callbacks registered with `Protocol::on_event`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ProtocolEvent {
+    TransactionAdded {
+        transaction_name: String,
+        external: bool,
+    },
+    ConnectionAdded {
+        connection_name: String,
+        from: String,
+        output_index: usize,
+        to: String,
+        input_index: usize,
+    },
+    /// The txid of the transaction changed after the txids of the transactions it spends were
+    /// updated, when the protocol is built.
+    TxidUpdated {
+        transaction_name: String,
+        txid: Txid,
+    },
+    /// Sighashes of an input were computed, one for each path it can be signed for.
+    SighashComputed {
+        transaction_name: String,
+        input_index: usize,
+        sighashes: usize,
+    },
+    /// Signatures of an input were stored.
+    SignatureAdded {
+        transaction_name: String,
+        input_index: usize,
+        signatures: usize,
+    },
+}
+
+pub type EventCallback = Arc<dyn Fn(&ProtocolEvent) + Send + Sync>;
+
+/// Callbacks registered on a protocol. They are neither serialized nor cloned with it, so copies
+/// of a protocol (e.g. history checkpoints) do not notify the observers of the original.
+#[derive(Default)]
+pub(crate) struct Observers {
+    callbacks: Vec<EventCallback>,
+}
+
+impl Observers {
+    pub(crate) fn push(&mut self, callback: EventCallback) {
+        self.callbacks.push(callback);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.callbacks.clear();
+    }
+
+    pub(crate) fn emit(&self, event: impl FnOnce() -> ProtocolEvent) {
+        if self.callbacks.is_empty() {
+            return;
+        }
+
+        let event = event();
+        for callback in self.callbacks.iter() {
+            callback(&event);
+        }
+    }
+}
+
+impl Clone for Observers {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl Debug for Observers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Observers({})", self.callbacks.len())
+    }
+}
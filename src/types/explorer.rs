@@ -0,0 +1,88 @@
+use bitcoin::{Network, Txid};
+use serde::{Deserialize, Serialize};
+
+/// Block explorer the exported links point to. Links follow the mempool.space (and esplora)
+/// layout: `{base_url}/tx/{txid}` and `{base_url}/address/{address}`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Explorer {
+    base_url: String,
+    network: Network,
+}
+
+impl Explorer {
+    /// Explorer hosted at a custom url, e.g. a regtest explorer.
+    pub fn new(base_url: &str, network: Network) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            network,
+        }
+    }
+
+    /// Public mempool.space instance of the network. Regtest has no public instance, so the
+    /// default url of a local mempool deployment is used.
+    pub fn mempool_space(network: Network) -> Self {
+        let base_url = match network {
+            Network::Testnet => "https://mempool.space/testnet",
+            Network::Testnet4 => "https://mempool.space/testnet4",
+            Network::Signet => "https://mempool.space/signet",
+            Network::Regtest => "http://localhost:8080",
+            _ => "https://mempool.space",
+        };
+
+        Self::new(base_url, network)
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn transaction_url(&self, txid: &Txid) -> String {
+        format!("{}/tx/{}", self.base_url, txid)
+    }
+
+    pub fn address_url(&self, address: &str) -> String {
+        format!("{}/address/{}", self.base_url, address)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExplorerOutput {
+    pub output_index: usize,
+    pub amount: u64,
+    pub script_pubkey: String,
+    /// Labels assigned to the output with `Protocol::label_output`.
+    pub labels: Vec<String>,
+    /// Expected address of the output, None for scripts without an address (e.g. OP_RETURN).
+    pub address: Option<String>,
+    pub address_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExplorerTransaction {
+    pub name: String,
+    pub txid: Txid,
+    pub url: String,
+    /// True for transactions created outside the protocol.
+    pub external: bool,
+    pub outputs: Vec<ExplorerOutput>,
+}
+
+/// Explorer links of every transaction of a protocol, see `Protocol::explorer_links`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExplorerLinks {
+    pub protocol_name: String,
+    pub network: Network,
+    pub transactions: Vec<ExplorerTransaction>,
+}
+
+impl ExplorerLinks {
+    pub fn transaction(&self, name: &str) -> Option<&ExplorerTransaction> {
+        self.transactions
+            .iter()
+            .find(|transaction| transaction.name == name)
+    }
+}
@@ -0,0 +1,96 @@
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+
+use super::OutputType;
+
+/// Output declared for a transaction created outside the protocol.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExternalOutput {
+    /// Output with a known type, that protocol transactions can spend.
+    Known(OutputType),
+    /// Outputs owned by third parties. Only their count is declared so the indexes of the known
+    /// outputs that follow them are preserved.
+    Opaque(u32),
+}
+
+/// Transaction created and owned outside the protocol (e.g. a funding or peg-in transaction).
+/// Protocol transactions can only spend its known outputs. It is persisted separately from the
+/// protocol transactions so verifiers can tell which data was not produced by the protocol.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalTx {
+    name: String,
+    txid: Txid,
+    outputs: Vec<ExternalOutput>,
+}
+
+impl ExternalTx {
+    pub fn new(name: &str, txid: Txid) -> Self {
+        Self {
+            name: name.to_string(),
+            txid,
+            outputs: vec![],
+        }
+    }
+
+    pub fn with_known_output(mut self, output_type: OutputType) -> Self {
+        self.outputs.push(ExternalOutput::Known(output_type));
+        self
+    }
+
+    pub fn with_opaque_outputs(mut self, count: u32) -> Self {
+        if count > 0 {
+            self.outputs.push(ExternalOutput::Opaque(count));
+        }
+        self
+    }
+
+    pub(crate) fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn txid(&self) -> Txid {
+        self.txid
+    }
+
+    pub fn outputs(&self) -> &[ExternalOutput] {
+        &self.outputs
+    }
+
+    /// Total number of declared outputs, known and opaque.
+    pub fn output_count(&self) -> usize {
+        self.outputs
+            .iter()
+            .map(|output| match output {
+                ExternalOutput::Known(_) => 1,
+                ExternalOutput::Opaque(count) => *count as usize,
+            })
+            .sum()
+    }
+
+    /// Returns the type of a declared output, or None if the output is opaque or not declared.
+    pub fn known_output(&self, output_index: usize) -> Option<&OutputType> {
+        let mut index = 0;
+        for output in self.outputs.iter() {
+            match output {
+                ExternalOutput::Known(output_type) => {
+                    if index == output_index {
+                        return Some(output_type);
+                    }
+                    index += 1;
+                }
+                ExternalOutput::Opaque(count) => {
+                    index += *count as usize;
+                    if output_index < index {
+                        return None;
+                    }
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,211 @@
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::ProtocolBuilderError, scripts::SignMode};
+
+use super::{
+    input::{SighashType, SpendMode},
+    OutputType,
+};
+
+/// Combinations explored by branch-and-bound before falling back to largest-first.
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// External UTXO that can fund a protocol. Only outputs spent with a single key are supported:
+/// P2WPKH outputs and taproot outputs spent through their key path.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FundingUtxo {
+    pub txid: Txid,
+    pub vout: u32,
+    pub output: OutputType,
+}
+
+impl FundingUtxo {
+    pub fn new(txid: Txid, vout: u32, output: OutputType) -> Result<Self, ProtocolBuilderError> {
+        match output {
+            OutputType::SegwitPublicKey { .. } | OutputType::Taproot { .. } => {
+                Ok(Self { txid, vout, output })
+            }
+            _ => Err(ProtocolBuilderError::InvalidOutputType(
+                "SegwitPublicKey or Taproot".to_string(),
+                output.get_name().to_string(),
+            )),
+        }
+    }
+
+    pub fn value(&self) -> u64 {
+        self.output.get_value().to_sat()
+    }
+
+    pub(crate) fn spend_mode(&self) -> SpendMode {
+        match self.output {
+            OutputType::Taproot { .. } => SpendMode::KeyOnly {
+                key_path_sign: SignMode::Single,
+            },
+            _ => SpendMode::Segwit,
+        }
+    }
+
+    pub(crate) fn sighash_type(&self) -> SighashType {
+        match self.output {
+            OutputType::Taproot { .. } => SighashType::taproot_all(),
+            _ => SighashType::ecdsa_all(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum CoinSelection {
+    /// Spends the largest UTXOs until the target is reached.
+    LargestFirst,
+    /// Searches the combination whose total exceeds the target by at most `tolerance` with the
+    /// least excess, so no change output is needed. Falls back to largest-first when there is
+    /// none.
+    BranchAndBound { tolerance: u64 },
+}
+
+/// Set of external UTXOs a protocol can be funded from, see `Protocol::add_funding`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FundingSource {
+    utxos: Vec<FundingUtxo>,
+    strategy: CoinSelection,
+}
+
+impl FundingSource {
+    pub fn new(strategy: CoinSelection) -> Self {
+        Self {
+            utxos: vec![],
+            strategy,
+        }
+    }
+
+    pub fn with_utxo(mut self, utxo: FundingUtxo) -> Self {
+        self.utxos.push(utxo);
+        self
+    }
+
+    pub fn utxos(&self) -> &[FundingUtxo] {
+        &self.utxos
+    }
+
+    pub fn strategy(&self) -> &CoinSelection {
+        &self.strategy
+    }
+
+    /// Selects the UTXOs spent to gather at least `target` sats, largest first.
+    pub fn select(&self, target: u64) -> Result<Vec<FundingUtxo>, ProtocolBuilderError> {
+        let mut utxos = self.utxos.clone();
+        utxos.sort_by_key(|utxo| std::cmp::Reverse(utxo.value()));
+
+        let available = utxos.iter().map(|utxo| utxo.value()).sum::<u64>();
+        if available < target {
+            return Err(ProtocolBuilderError::InsufficientFunds(available, target));
+        }
+
+        if let CoinSelection::BranchAndBound { tolerance } = self.strategy {
+            if let Some(selected) = branch_and_bound(&utxos, target, tolerance) {
+                return Ok(selected
+                    .into_iter()
+                    .map(|index| utxos[index].clone())
+                    .collect());
+            }
+        }
+
+        let mut total = 0;
+        Ok(utxos
+            .into_iter()
+            .take_while(|utxo| {
+                let needed = total < target;
+                total += utxo.value();
+                needed
+            })
+            .collect())
+    }
+}
+
+// Depth-first search over the UTXOs (sorted by decreasing value), including or excluding each
+// one. Returns the indexes of the combination with the least excess within the tolerance.
+fn branch_and_bound(utxos: &[FundingUtxo], target: u64, tolerance: u64) -> Option<Vec<usize>> {
+    // Value of the UTXOs after each index, to prune branches that can't reach the target
+    let mut remaining = vec![0; utxos.len() + 1];
+    for index in (0..utxos.len()).rev() {
+        remaining[index] = remaining[index + 1] + utxos[index].value();
+    }
+
+    let mut best: Option<(u64, Vec<usize>)> = None;
+    let mut selected = vec![];
+    let mut tries = 0;
+
+    search(
+        utxos,
+        &remaining,
+        target,
+        target.saturating_add(tolerance),
+        0,
+        0,
+        &mut selected,
+        &mut best,
+        &mut tries,
+    );
+
+    best.map(|(_, selected)| selected)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn search(
+    utxos: &[FundingUtxo],
+    remaining: &[u64],
+    target: u64,
+    upper_bound: u64,
+    index: usize,
+    total: u64,
+    selected: &mut Vec<usize>,
+    best: &mut Option<(u64, Vec<usize>)>,
+    tries: &mut usize,
+) {
+    *tries += 1;
+    if *tries > BNB_MAX_TRIES || total > upper_bound || total + remaining[index] < target {
+        return;
+    }
+
+    if total >= target {
+        let excess = total - target;
+        if best
+            .as_ref()
+            .is_none_or(|(best_excess, _)| excess < *best_excess)
+        {
+            *best = Some((excess, selected.clone()));
+        }
+        return;
+    }
+
+    if index == utxos.len() {
+        return;
+    }
+
+    selected.push(index);
+    search(
+        utxos,
+        remaining,
+        target,
+        upper_bound,
+        index + 1,
+        total + utxos[index].value(),
+        selected,
+        best,
+        tries,
+    );
+    selected.pop();
+
+    search(
+        utxos,
+        remaining,
+        target,
+        upper_bound,
+        index + 1,
+        total,
+        selected,
+        best,
+        tries,
+    );
+}
@@ -0,0 +1,65 @@
+use std::fmt::{Display, Formatter};
+
+/// Transaction of a protocol, returned by `Protocol::new_transaction` and `Protocol::handle`.
+/// Handles can only be obtained for transactions that exist, so passing them instead of names
+/// (`add_connection` accepts either) rules out typos.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TxHandle {
+    name: String,
+}
+
+impl TxHandle {
+    pub(crate) fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Output of the transaction at the given index. The index is checked when the output is
+    /// used, see `Protocol::connect_output`.
+    pub fn output(&self, index: usize) -> OutputHandle {
+        OutputHandle {
+            transaction: self.clone(),
+            index,
+        }
+    }
+}
+
+impl AsRef<str> for TxHandle {
+    fn as_ref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Display for TxHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Output of a protocol transaction, see `TxHandle::output`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OutputHandle {
+    transaction: TxHandle,
+    index: usize,
+}
+
+impl OutputHandle {
+    pub fn transaction(&self) -> &TxHandle {
+        &self.transaction
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl Display for OutputHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.transaction, self.index)
+    }
+}
@@ -1,4 +1,7 @@
-use std::fmt::{Display, Formatter};
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+};
 
 use bitcoin::{secp256k1::Message, Amount, EcdsaSighashType, TapSighashType};
 use key_manager::winternitz::WinternitzSignature;
@@ -28,6 +31,10 @@ pub enum SpendMode {
     /// Compute sighashes and signatures for a specific script path.
     Script { leaf: usize },
 
+    /// Compute sighashes and signatures for the script path with the given name, see
+    /// `ProtocolScript::set_name`. The leaf is looked up when the protocol is built.
+    ScriptByName(String),
+
     /// Spend mode for P2WSH and P2WPKH.
     Segwit,
 
@@ -46,6 +53,7 @@ impl Display for SpendMode {
             } => write!(f, "KeyOnly({})", key_path_sign_mode),
             SpendMode::ScriptsOnly => write!(f, "ScriptsOnly"),
             SpendMode::Script { leaf } => write!(f, "Script({})", leaf),
+            SpendMode::ScriptByName(name) => write!(f, "ScriptByName({})", name),
             SpendMode::Scripts { leaves } => write!(f, "Scripts({:?})", leaves),
             SpendMode::None => write!(f, "None"),
             SpendMode::Segwit => write!(f, "Segwit"),
@@ -71,7 +79,7 @@ impl SpendMode {
     }
 
     pub fn is_script(&self) -> bool {
-        matches!(self, SpendMode::Script { .. })
+        matches!(self, SpendMode::Script { .. } | SpendMode::ScriptByName(_))
     }
 
     pub fn is_segwit(&self) -> bool {
@@ -142,6 +150,53 @@ impl SighashType {
     pub fn ecdsa_all() -> SighashType {
         SighashType::Ecdsa(EcdsaSighashType::All)
     }
+
+    /// Returns true if the signature only commits to its own input, allowing other inputs to be
+    /// added to the transaction without invalidating it.
+    pub fn is_anyone_can_pay(&self) -> bool {
+        match self {
+            SighashType::Taproot(tap_sighash) => matches!(
+                tap_sighash,
+                TapSighashType::AllPlusAnyoneCanPay
+                    | TapSighashType::NonePlusAnyoneCanPay
+                    | TapSighashType::SinglePlusAnyoneCanPay
+            ),
+            SighashType::Ecdsa(ecdsa_sighash) => matches!(
+                ecdsa_sighash,
+                EcdsaSighashType::AllPlusAnyoneCanPay
+                    | EcdsaSighashType::NonePlusAnyoneCanPay
+                    | EcdsaSighashType::SinglePlusAnyoneCanPay
+            ),
+        }
+    }
+
+    /// Returns true if the signature commits to all the outputs of the transaction.
+    pub fn commits_all_outputs(&self) -> bool {
+        match self {
+            SighashType::Taproot(tap_sighash) => matches!(
+                tap_sighash,
+                TapSighashType::Default | TapSighashType::All | TapSighashType::AllPlusAnyoneCanPay
+            ),
+            SighashType::Ecdsa(ecdsa_sighash) => matches!(
+                ecdsa_sighash,
+                EcdsaSighashType::All | EcdsaSighashType::AllPlusAnyoneCanPay
+            ),
+        }
+    }
+
+    /// Returns true if the signature only commits to the output with the same index as its input.
+    pub fn commits_single_output(&self) -> bool {
+        match self {
+            SighashType::Taproot(tap_sighash) => matches!(
+                tap_sighash,
+                TapSighashType::Single | TapSighashType::SinglePlusAnyoneCanPay
+            ),
+            SighashType::Ecdsa(ecdsa_sighash) => matches!(
+                ecdsa_sighash,
+                EcdsaSighashType::Single | EcdsaSighashType::SinglePlusAnyoneCanPay
+            ),
+        }
+    }
 }
 
 impl Display for SighashType {
@@ -157,6 +212,7 @@ impl Display for SighashType {
 pub enum InputArgs {
     TaprootKey { args: Vec<Vec<u8>> },
     TaprootScript { args: Vec<Vec<u8>>, leaf: usize },
+    TaprootScriptByName { args: Vec<Vec<u8>>, name: String },
     Segwit { args: Vec<Vec<u8>> },
 }
 
@@ -165,6 +221,14 @@ impl InputArgs {
         Self::TaprootScript { args: vec![], leaf }
     }
 
+    /// Args to spend the leaf with the given name, looked up when the witness is built.
+    pub fn new_taproot_script_args_by_name(name: &str) -> Self {
+        Self::TaprootScriptByName {
+            args: vec![],
+            name: name.to_string(),
+        }
+    }
+
     pub fn new_taproot_key_args() -> Self {
         Self::TaprootKey { args: vec![] }
     }
@@ -178,6 +242,9 @@ impl InputArgs {
             Self::TaprootKey { args: taproot_args } => taproot_args.push(args.to_vec()),
             Self::TaprootScript {
                 args: taproot_args, ..
+            }
+            | Self::TaprootScriptByName {
+                args: taproot_args, ..
             } => taproot_args.push(args.to_vec()),
             Self::Segwit { args: segwit_args } => segwit_args.push(args.to_vec()),
         }
@@ -191,13 +258,39 @@ impl InputArgs {
     ) -> Result<&mut Self, ProtocolBuilderError> {
         match self {
             Self::TaprootKey { .. } => self.push_slice(&taproot_signature.serialize()),
-            Self::TaprootScript { .. } => self.push_slice(&taproot_signature.serialize()),
+            Self::TaprootScript { .. } | Self::TaprootScriptByName { .. } => {
+                self.push_slice(&taproot_signature.serialize())
+            }
             _ => return Err(ProtocolBuilderError::InvalidSignatureType),
         };
 
         Ok(self)
     }
 
+    /// Pushes the signatures of the slots of a multisig leaf, given in the order of its keys. The
+    /// first key is checked first, so its signature goes on top, and missing signatures are
+    /// pushed as empty items.
+    pub fn push_multisig_signatures(
+        &mut self,
+        signatures: &[Option<bitcoin::taproot::Signature>],
+    ) -> Result<&mut Self, ProtocolBuilderError> {
+        if !matches!(
+            self,
+            Self::TaprootScript { .. } | Self::TaprootScriptByName { .. }
+        ) {
+            return Err(ProtocolBuilderError::InvalidSignatureType);
+        }
+
+        for signature in signatures.iter().rev() {
+            match signature {
+                Some(signature) => self.push_slice(&signature.to_vec()),
+                None => self.push_slice(&[]),
+            };
+        }
+
+        Ok(self)
+    }
+
     pub fn push_ecdsa_signature(
         &mut self,
         ecdsa_signature: bitcoin::ecdsa::Signature,
@@ -234,7 +327,9 @@ impl InputArgs {
     pub fn iter(&self) -> std::slice::Iter<'_, Vec<u8>> {
         match self {
             Self::TaprootKey { args } => args.iter(),
-            Self::TaprootScript { args, .. } => args.iter(),
+            Self::TaprootScript { args, .. } | Self::TaprootScriptByName { args, .. } => {
+                args.iter()
+            }
             Self::Segwit { args } => args.iter(),
         }
     }
@@ -242,10 +337,25 @@ impl InputArgs {
     pub fn len(&self) -> usize {
         match self {
             Self::TaprootKey { args } => args.len(),
-            Self::TaprootScript { args, .. } => args.len(),
+            Self::TaprootScript { args, .. } | Self::TaprootScriptByName { args, .. } => args.len(),
             Self::Segwit { args } => args.len(),
         }
     }
+
+    /// Leaf spent by script path args, resolving named leaves in the spent output.
+    pub fn leaf_index(
+        &self,
+        output_type: &OutputType,
+    ) -> Result<Option<usize>, ProtocolBuilderError> {
+        match self {
+            Self::TaprootScript { leaf, .. } => Ok(Some(*leaf)),
+            Self::TaprootScriptByName { name, .. } => output_type
+                .leaf_index_by_name(name)
+                .map(Some)
+                .ok_or(ProtocolBuilderError::UnknownLeafName(name.clone())),
+            _ => Ok(None),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -255,6 +365,8 @@ pub struct InputType {
     hashed_messages: Vec<Option<Vec<u8>>>,
     signatures: Vec<Option<Signature>>,
     spend_mode: SpendMode,
+    #[serde(default)]
+    multisig_signatures: BTreeMap<usize, Vec<Option<Signature>>>,
 }
 
 impl InputType {
@@ -265,6 +377,7 @@ impl InputType {
             hashed_messages: vec![],
             signatures: vec![],
             spend_mode: spend_mode.clone(),
+            multisig_signatures: BTreeMap::new(),
         }
     }
 
@@ -279,12 +392,17 @@ impl InputType {
         match self.sighash_type {
             SighashType::Taproot(_) => match output_type {
                 OutputType::Taproot { .. } => {}
+                OutputType::Custom { ref output }
+                    if output.supports_sighash_type(&self.sighash_type) => {}
                 _ => Err(GraphError::InvalidOutputTypeForSighashType)?,
             },
             SighashType::Ecdsa(_) => match output_type {
+                OutputType::Custom { ref output }
+                    if output.supports_sighash_type(&self.sighash_type) => {}
                 OutputType::SegwitPublicKey { .. } => {}
                 OutputType::SegwitScript { .. } => {}
                 OutputType::SegwitUnspendable { .. } => {}
+                OutputType::PayToAnchor { .. } => {}
                 _ => Err(GraphError::InvalidOutputTypeForSighashType)?,
             },
         }
@@ -353,6 +471,15 @@ impl InputType {
         &self.signatures
     }
 
+    /// Signatures of the slots of the multisig leaves, by leaf index.
+    pub fn multisig_signatures(&self) -> &BTreeMap<usize, Vec<Option<Signature>>> {
+        &self.multisig_signatures
+    }
+
+    pub fn set_multisig_signatures(&mut self, signatures: BTreeMap<usize, Vec<Option<Signature>>>) {
+        self.multisig_signatures = signatures;
+    }
+
     pub fn get_signature(&self, index: usize) -> Result<&Option<Signature>, GraphError> {
         self.signatures
             .get(index)
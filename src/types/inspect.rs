@@ -0,0 +1,117 @@
+use std::fmt::{Display, Formatter};
+
+use bitcoin::{OutPoint, Txid};
+use serde::{Deserialize, Serialize};
+
+use super::diff::SpentOutput;
+
+/// Summary of a transaction of a protocol for debugging, see `Protocol::inspect`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TransactionInspection {
+    pub name: String,
+    pub txid: Txid,
+    pub external: bool,
+    /// Consensus encoding of the transaction as stored, without witnesses.
+    pub hex: String,
+    /// Weight of the transaction as stored, without witnesses.
+    pub weight: u64,
+    /// Estimated vsize once the witnesses are filled, using the largest spend path of each input.
+    pub estimated_vsize: Option<u64>,
+    pub inputs: Vec<InspectedInput>,
+    pub outputs: Vec<InspectedOutput>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct InspectedInput {
+    pub index: usize,
+    pub previous_output: OutPoint,
+    /// Transaction of the protocol and output spent, if the input is connected.
+    pub spends: Option<SpentOutput>,
+    pub value: Option<u64>,
+    pub spend_mode: Option<String>,
+    pub sighash_type: Option<String>,
+    /// Signatures expected by the input, as (slot, signed): `L<n>` for taproot leaves, `key` for
+    /// the taproot key path and `sig` for segwit inputs.
+    pub signatures: Vec<(String, bool)>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct InspectedOutput {
+    pub index: usize,
+    pub value: u64,
+    pub kind: String,
+    pub script_pubkey: String,
+    /// Number of taproot leaves.
+    pub leaves: usize,
+    pub label: Option<String>,
+}
+
+impl TransactionInspection {
+    pub fn is_fully_signed(&self) -> bool {
+        self.inputs
+            .iter()
+            .all(|input| input.signatures.iter().all(|(_, signed)| *signed))
+    }
+}
+
+impl Display for TransactionInspection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let external = if self.external { " (external)" } else { "" };
+        writeln!(f, "transaction: {}{}", self.name, external)?;
+        writeln!(f, "txid: {}", self.txid)?;
+        match self.estimated_vsize {
+            Some(vsize) => writeln!(
+                f,
+                "weight: {} WU unsigned, ~{} vB signed",
+                self.weight, vsize
+            )?,
+            None => writeln!(f, "weight: {} WU unsigned", self.weight)?,
+        }
+
+        writeln!(f, "inputs:")?;
+        for input in self.inputs.iter() {
+            let spends = match &input.spends {
+                Some((transaction_name, output_index)) => {
+                    format!("{}:{}", transaction_name, output_index)
+                }
+                None => input.previous_output.to_string(),
+            };
+            write!(f, "  {}: spends {}", input.index, spends)?;
+            if let Some(value) = input.value {
+                write!(f, " [{}]", value)?;
+            }
+            if let (Some(sighash_type), Some(spend_mode)) = (&input.sighash_type, &input.spend_mode)
+            {
+                write!(f, " {} {}", sighash_type, spend_mode)?;
+            }
+            writeln!(f)?;
+
+            if !input.signatures.is_empty() {
+                let slots = input
+                    .signatures
+                    .iter()
+                    .map(|(slot, signed)| {
+                        format!("{}={}", slot, if *signed { "signed" } else { "missing" })
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                writeln!(f, "     signatures: {}", slots)?;
+            }
+        }
+
+        writeln!(f, "outputs:")?;
+        for output in self.outputs.iter() {
+            write!(f, "  {}: [{}] {}", output.index, output.value, output.kind)?;
+            if output.leaves > 0 {
+                write!(f, ", {} leaves", output.leaves)?;
+            }
+            if let Some(label) = &output.label {
+                write!(f, ", label {}", label)?;
+            }
+            writeln!(f)?;
+            writeln!(f, "     script_pubkey: {}", output.script_pubkey)?;
+        }
+
+        write!(f, "hex: {}", self.hex)
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::scripts::KeyType;
+
+/// Winternitz key registered in a script of an output of a protocol, see `KeyUsageReport`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct KeyUsage {
+    pub protocol_name: String,
+    pub transaction_name: String,
+    pub output_index: usize,
+    /// Leaf of taproot outputs, None for P2WSH scripts.
+    pub leaf_index: Option<usize>,
+    pub key_name: String,
+    pub key_type: KeyType,
+    /// Hash of the Winternitz public key, see `ScriptKey::fingerprint`.
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+}
+
+impl KeyUsage {
+    /// Keys derived with the same index are the same key if they have the same type and public
+    /// key. Keys added without their public key are assumed to be derived by the same party.
+    pub fn same_key(&self, other: &KeyUsage) -> bool {
+        self.key_type == other.key_type && self.fingerprint == other.fingerprint
+    }
+
+    /// A Winternitz key signs the value revealed by the input spending its output, so keys of
+    /// a protocol with the same name in leaves of the same output sign the same message, as
+    /// only one of them is spent. The same key in another output, e.g. in every round of
+    /// `connect_taproot_rounds`, or in another protocol signs another message.
+    pub fn same_message(&self, other: &KeyUsage) -> bool {
+        self.protocol_name == other.protocol_name
+            && self.transaction_name == other.transaction_name
+            && self.output_index == other.output_index
+            && self.key_name == other.key_name
+    }
+}
+
+impl Display for KeyUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "key {} of ", self.key_name)?;
+        if let Some(leaf_index) = self.leaf_index {
+            write!(f, "leaf {} of ", leaf_index)?;
+        }
+        write!(
+            f,
+            "output {} of {}",
+            self.output_index, self.transaction_name
+        )
+    }
+}
+
+/// Key used to sign distinct messages, with all its usages.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct KeyReuse {
+    pub derivation_index: u32,
+    pub usages: Vec<KeyUsage>,
+}
+
+/// Winternitz derivation indexes used by one or more protocols, with the leaves using each of
+/// them, see `Protocol::key_usage` and `KeyUsageReport::from_protocols`. Winternitz keys are
+/// one-time keys, so an index used for two distinct messages is a critical bug.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct KeyUsageReport {
+    pub usages: BTreeMap<u32, Vec<KeyUsage>>,
+}
+
+impl KeyUsageReport {
+    pub fn add(&mut self, derivation_index: u32, usage: KeyUsage) {
+        self.usages.entry(derivation_index).or_default().push(usage);
+    }
+
+    pub fn merge(&mut self, other: KeyUsageReport) {
+        for (derivation_index, usages) in other.usages {
+            self.usages
+                .entry(derivation_index)
+                .or_default()
+                .extend(usages);
+        }
+    }
+
+    /// Derivation indexes used, in ascending order.
+    pub fn derivation_indexes(&self) -> Vec<u32> {
+        self.usages.keys().copied().collect()
+    }
+
+    pub fn usages(&self, derivation_index: u32) -> &[KeyUsage] {
+        self.usages
+            .get(&derivation_index)
+            .map(|usages| usages.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Keys used for more than one message, see `KeyUsage::same_key` and
+    /// `KeyUsage::same_message`.
+    pub fn reuses(&self) -> Vec<KeyReuse> {
+        let mut reuses = vec![];
+        for (derivation_index, usages) in self.usages.iter() {
+            let mut keys: Vec<Vec<KeyUsage>> = vec![];
+            for usage in usages {
+                match keys.iter_mut().find(|key| key[0].same_key(usage)) {
+                    Some(key) => key.push(usage.clone()),
+                    None => keys.push(vec![usage.clone()]),
+                }
+            }
+
+            reuses.extend(
+                keys.into_iter()
+                    .filter(|key| key.iter().any(|usage| !usage.same_message(&key[0])))
+                    .map(|usages| KeyReuse {
+                        derivation_index: *derivation_index,
+                        usages,
+                    }),
+            );
+        }
+        reuses
+    }
+
+    pub fn is_safe(&self) -> bool {
+        self.reuses().is_empty()
+    }
+}
+
+impl Display for KeyUsageReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reused: Vec<u32> = self
+            .reuses()
+            .iter()
+            .map(|reuse| reuse.derivation_index)
+            .collect();
+
+        for (derivation_index, usages) in self.usages.iter() {
+            let flag = if reused.contains(derivation_index) {
+                " REUSED"
+            } else {
+                ""
+            };
+            writeln!(f, "{}:{}", derivation_index, flag)?;
+            for usage in usages {
+                writeln!(f, "  {}: {}", usage.protocol_name, usage)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use bitcoin::hashes::sha256;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::SerializationError;
+
+use super::serialization::{deserialize, serialize, SerializationFormat};
+
+/// Template hash of a script committed in an output: a taproot leaf, or the script of a P2WSH
+/// output with leaf 0. See `ProtocolScript::template_hash`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LeafTemplate {
+    pub transaction_name: String,
+    pub output_index: usize,
+    pub leaf: usize,
+    pub hash: sha256::Hash,
+}
+
+/// Template hashes of every script of a protocol, exchanged between the parties so each one can
+/// check that the others see the same scripts behind each leaf, and not just the same labels
+/// and indexes. See `Protocol::leaf_templates` and `Protocol::agree_leaf_templates`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TemplateManifest {
+    pub protocol_name: String,
+    pub leaves: Vec<LeafTemplate>,
+}
+
+impl TemplateManifest {
+    pub fn new(protocol_name: &str) -> Self {
+        Self {
+            protocol_name: protocol_name.to_string(),
+            leaves: vec![],
+        }
+    }
+
+    pub fn get(
+        &self,
+        transaction_name: &str,
+        output_index: usize,
+        leaf: usize,
+    ) -> Option<&LeafTemplate> {
+        self.leaves.iter().find(|template| {
+            template.transaction_name == transaction_name
+                && template.output_index == output_index
+                && template.leaf == leaf
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn to_bytes(&self, format: SerializationFormat) -> Result<Vec<u8>, SerializationError> {
+        serialize(self, format)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        deserialize(bytes)
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+/// Hard caps on the size of a protocol, for services building protocols from untrusted
+/// specifications. Every cap is disabled by default.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ProtocolLimits {
+    pub max_transactions: Option<usize>,
+    pub max_inputs_per_transaction: Option<usize>,
+    pub max_outputs_per_transaction: Option<usize>,
+    pub max_leaves_per_output: Option<usize>,
+    pub max_total_leaves: Option<usize>,
+    /// Maximum size in bytes of the encoded protocol (see `Protocol::to_bytes`), or of its JSON
+    /// serialization while it is built.
+    pub max_serialized_size: Option<usize>,
+}
+
+impl ProtocolLimits {
+    pub fn with_max_transactions(mut self, max: usize) -> Self {
+        self.max_transactions = Some(max);
+        self
+    }
+
+    pub fn with_max_inputs_per_transaction(mut self, max: usize) -> Self {
+        self.max_inputs_per_transaction = Some(max);
+        self
+    }
+
+    pub fn with_max_outputs_per_transaction(mut self, max: usize) -> Self {
+        self.max_outputs_per_transaction = Some(max);
+        self
+    }
+
+    pub fn with_max_leaves_per_output(mut self, max: usize) -> Self {
+        self.max_leaves_per_output = Some(max);
+        self
+    }
+
+    pub fn with_max_total_leaves(mut self, max: usize) -> Self {
+        self.max_total_leaves = Some(max);
+        self
+    }
+
+    pub fn with_max_serialized_size(mut self, max: usize) -> Self {
+        self.max_serialized_size = Some(max);
+        self
+    }
+
+    pub fn get(&self, limit: &ProtocolLimit) -> Option<usize> {
+        match limit {
+            ProtocolLimit::Transactions => self.max_transactions,
+            ProtocolLimit::InputsPerTransaction => self.max_inputs_per_transaction,
+            ProtocolLimit::OutputsPerTransaction => self.max_outputs_per_transaction,
+            ProtocolLimit::LeavesPerOutput => self.max_leaves_per_output,
+            ProtocolLimit::TotalLeaves => self.max_total_leaves,
+            ProtocolLimit::SerializedSize => self.max_serialized_size,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ProtocolLimit {
+    Transactions,
+    InputsPerTransaction,
+    OutputsPerTransaction,
+    LeavesPerOutput,
+    TotalLeaves,
+    SerializedSize,
+}
+
+impl Display for ProtocolLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolLimit::Transactions => write!(f, "transactions"),
+            ProtocolLimit::InputsPerTransaction => write!(f, "inputs per transaction"),
+            ProtocolLimit::OutputsPerTransaction => write!(f, "outputs per transaction"),
+            ProtocolLimit::LeavesPerOutput => write!(f, "leaves per output"),
+            ProtocolLimit::TotalLeaves => write!(f, "total leaves"),
+            ProtocolLimit::SerializedSize => write!(f, "serialized size"),
+        }
+    }
+}
@@ -1,5 +1,37 @@
+pub mod audit;
+pub mod broadcast_rule;
+pub mod bundle;
+pub mod change;
+pub mod commitment;
 pub mod connection;
+pub mod custom;
+pub mod diff;
+pub mod dust;
+pub mod event;
+pub mod explorer;
+pub mod external;
+pub mod funding;
+pub mod handle;
 pub mod input;
+pub mod inspect;
+pub mod key_usage;
+pub mod leaf_template;
+pub mod limits;
+pub mod nary_rounds;
+pub mod nonces;
 pub mod output;
+pub mod ownership;
+pub mod plan;
+pub mod registry;
+pub mod sequence;
+pub mod serialization;
+pub mod signer;
+pub mod signing_request;
+pub mod skeleton;
+pub mod spec;
+pub mod trace;
+pub mod trace_step;
+pub mod witness_args;
+pub mod witness_preview;
 
-pub use self::{input::InputArgs, output::OutputType, output::Utxo};
+pub use self::{input::InputArgs, output::OutputType, output::Utxo, signer::Signer};
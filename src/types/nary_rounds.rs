@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Transactions created by `ProtocolBuilder::connect_nary_rounds`, addressed by their path: the
+/// branch taken in each round, so `[2, 0]` is the first branch of the third branch of the root.
+/// The empty path is the root transaction.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct NaryRounds {
+    arity: usize,
+    rounds: u32,
+    transactions: BTreeMap<Vec<usize>, String>,
+}
+
+impl NaryRounds {
+    pub(crate) fn new(root: &str, arity: usize, rounds: u32) -> Self {
+        Self {
+            arity,
+            rounds,
+            transactions: BTreeMap::from([(vec![], root.to_string())]),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, path: Vec<usize>, name: String) {
+        self.transactions.insert(path, name);
+    }
+
+    /// Name given to the transaction at a path, `{connection_name}_{branch}_{branch}...`.
+    pub fn transaction_name(connection_name: &str, path: &[usize]) -> String {
+        path.iter()
+            .fold(connection_name.to_string(), |name, branch| {
+                format!("{}_{}", name, branch)
+            })
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn rounds(&self) -> u32 {
+        self.rounds
+    }
+
+    pub fn root(&self) -> &str {
+        &self.transactions[&vec![]]
+    }
+
+    pub fn transaction(&self, path: &[usize]) -> Option<&str> {
+        self.transactions.get(path).map(String::as_str)
+    }
+
+    pub fn path(&self, transaction_name: &str) -> Option<&[usize]> {
+        self.transactions
+            .iter()
+            .find(|(_, name)| *name == transaction_name)
+            .map(|(path, _)| path.as_slice())
+    }
+
+    /// Transactions spending the output of the one at the path, by branch. Empty for the last
+    /// round.
+    pub fn children(&self, path: &[usize]) -> Vec<&str> {
+        (0..self.arity)
+            .filter_map(|branch| {
+                let mut child = path.to_vec();
+                child.push(branch);
+                self.transaction(&child)
+            })
+            .collect()
+    }
+
+    /// Transactions of a round, from 1 to `rounds`, ordered by path.
+    pub fn round(&self, round: u32) -> Vec<&str> {
+        self.transactions
+            .iter()
+            .filter(|(path, _)| path.len() == round as usize)
+            .map(|(_, name)| name.as_str())
+            .collect()
+    }
+
+    /// Number of transactions created, without the root.
+    pub fn len(&self) -> usize {
+        self.transactions.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
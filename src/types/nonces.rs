@@ -0,0 +1,84 @@
+use bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::SerializationError;
+
+use super::serialization::{deserialize, serialize, SerializationFormat};
+
+/// MuSig2 public nonce of a participant for a message signed with an aggregated key.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BundledNonce {
+    pub aggregated_key: PublicKey,
+    /// Message id as used by the key manager, see `MessageId::new_string_id`.
+    pub message_id: String,
+    /// Serialized public nonce (66 bytes).
+    pub nonce: Vec<u8>,
+}
+
+/// Public nonces generated by a participant for every aggregated message of a protocol, sent
+/// to the other participants so they can complete the MuSig2 sessions. See
+/// `Protocol::export_nonces` and `Protocol::import_peer_nonces`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct NonceBundle {
+    pub protocol_name: String,
+    pub id: String,
+    pub participant: PublicKey,
+    pub nonces: Vec<BundledNonce>,
+}
+
+impl NonceBundle {
+    pub fn len(&self) -> usize {
+        self.nonces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nonces.is_empty()
+    }
+
+    pub fn to_bytes(&self, format: SerializationFormat) -> Result<Vec<u8>, SerializationError> {
+        serialize(self, format)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        deserialize(bytes)
+    }
+}
+
+/// MuSig2 partial signature of a participant for a message signed with an aggregated key.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BundledPartialSignature {
+    pub aggregated_key: PublicKey,
+    /// Message id as used by the key manager, see `MessageId::new_string_id`.
+    pub message_id: String,
+    /// Serialized partial signature (32 bytes).
+    pub partial_signature: Vec<u8>,
+}
+
+/// Partial signatures computed by a participant for every aggregated message of a protocol once
+/// the nonces of all the participants were aggregated. See `Protocol::export_partial_signatures`
+/// and `Protocol::import_peer_partial_signatures`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PartialSignatureBundle {
+    pub protocol_name: String,
+    pub id: String,
+    pub participant: PublicKey,
+    pub partial_signatures: Vec<BundledPartialSignature>,
+}
+
+impl PartialSignatureBundle {
+    pub fn len(&self) -> usize {
+        self.partial_signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partial_signatures.is_empty()
+    }
+
+    pub fn to_bytes(&self, format: SerializationFormat) -> Result<Vec<u8>, SerializationError> {
+        serialize(self, format)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        deserialize(bytes)
+    }
+}
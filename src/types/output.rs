@@ -1,24 +1,31 @@
-use std::fmt;
+use std::{collections::BTreeMap, fmt};
 
 use bitcoin::{
+    opcodes::all::OP_CHECKSIG,
     secp256k1::{self, Message},
-    sighash::{self, SighashCache},
     taproot::{LeafVersion, TaprootSpendInfo},
-    Amount, EcdsaSighashType, PublicKey, ScriptBuf, TapLeafHash, TapSighashType, TapTweakHash,
-    Transaction, TxOut, Txid, WScriptHash, XOnlyPublicKey,
-};
-use key_manager::{
-    key_manager::KeyManager, verifier::SignatureVerifier, winternitz::WinternitzSignature,
+    Amount, EcdsaSighashType, PublicKey, Script, ScriptBuf, TapLeafHash, TapSighashType,
+    TapTweakHash, Transaction, TxOut, Txid, WScriptHash, XOnlyPublicKey,
 };
+use key_manager::{verifier::SignatureVerifier, winternitz::WinternitzSignature};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     errors::ProtocolBuilderError,
-    scripts::{self, ProtocolScript, SignMode},
+    helpers::{
+        descriptors::with_checksum,
+        sighash::{p2wpkh_sighash, p2wsh_sighash, taproot_key_sighash, taproot_script_sighash},
+        spend_info_cache::cached_spend_info,
+    },
+    scripts::{self, ProtocolScript, SignMode, TapTreeLayout},
     types::input::Signature,
 };
 
-use super::input::SpendMode;
+use super::{
+    custom::{BoxedCustomOutput, CustomOutput},
+    input::SpendMode,
+    signer::Signer,
+};
 
 pub const AUTO_AMOUNT: u64 = 1;
 pub const RECOVER_AMOUNT: u64 = 2;
@@ -100,6 +107,150 @@ impl SpeedupData {
             leaf_identification: leaf_id,
         }
     }
+
+    /// Speedup through a P2A anchor of the transaction, see `OutputType::pay_to_anchor`. The
+    /// anchor is spent with an empty witness, so no key has to be kept for it.
+    pub fn new_anchor(txid: Txid, vout: u32, amount: u64) -> Self {
+        let mut anchor = OutputType::pay_to_anchor();
+        anchor.set_value(Amount::from_sat(amount));
+
+        Self {
+            utxo: None,
+            partial_utxo: Some((txid, vout, amount)),
+            output_type: Some(anchor),
+            wots_sigs: None,
+            leaf_index: None,
+            leaf_identification: false,
+        }
+    }
+
+    pub fn is_anchor(&self) -> bool {
+        matches!(self.output_type, Some(OutputType::PayToAnchor { .. }))
+    }
+
+    /// Checks that exactly the fields of one kind of speedup are set, and returns that kind.
+    pub fn kind(&self) -> Result<SpeedupKind, ProtocolBuilderError> {
+        let invalid = |reason: &str| Err(ProtocolBuilderError::InvalidSpeedupData(reason.into()));
+        let leaf_fields = self.wots_sigs.is_some() || self.leaf_identification;
+
+        if self.utxo.is_some() {
+            if self.partial_utxo.is_some() || self.output_type.is_some() {
+                return invalid("a keyed anchor cannot also have a partial utxo or output type");
+            }
+            if self.leaf_index.is_some() || leaf_fields {
+                return invalid("a keyed anchor cannot have leaf spending data");
+            }
+            return Ok(SpeedupKind::KeyedAnchor);
+        }
+
+        let (Some((_, _, amount)), Some(output_type)) = (&self.partial_utxo, &self.output_type)
+        else {
+            return invalid("either a utxo or a partial utxo and its output type are required");
+        };
+        if output_type.get_value().to_sat() != *amount {
+            return invalid("the amount of the partial utxo does not match its output type");
+        }
+
+        match output_type {
+            OutputType::PayToAnchor { .. } => {
+                if self.leaf_index.is_some() || leaf_fields {
+                    return invalid("a pay to anchor output cannot have leaf spending data");
+                }
+                Ok(SpeedupKind::PayToAnchor)
+            }
+            OutputType::Taproot { leaves, .. } => match self.leaf_index {
+                Some(leaf_index) if leaf_index < leaves.len() => Ok(SpeedupKind::TaprootLeafAnchor),
+                Some(_) => invalid("the leaf index is out of the leaves of the output"),
+                None => invalid("a taproot leaf anchor needs a leaf index"),
+            },
+            _ => invalid("only taproot and pay to anchor outputs can be spent by leaf"),
+        }
+    }
+}
+
+/// Kind of output spent by a `SpeedupData`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpeedupKind {
+    /// P2WPKH output, signed with the key of the utxo.
+    KeyedAnchor,
+    /// Leaf of a taproot output, with optional Winternitz signatures.
+    TaprootLeafAnchor,
+    /// Keyless P2A anchor, spent with an empty witness.
+    PayToAnchor,
+}
+
+/// Builds a `SpeedupData` of one kind, validated by `build` so `speedup_transactions` never
+/// finds missing fields.
+#[derive(Debug, Clone)]
+pub struct SpeedupDataBuilder {
+    data: SpeedupData,
+}
+
+impl SpeedupDataBuilder {
+    pub fn keyed_anchor(utxo: Utxo) -> Self {
+        Self {
+            data: SpeedupData::new(utxo),
+        }
+    }
+
+    /// Spend of a leaf of a taproot output. The amount is the value of the output.
+    pub fn taproot_leaf_anchor(
+        txid: Txid,
+        vout: u32,
+        output_type: &OutputType,
+        leaf_index: usize,
+    ) -> Self {
+        Self {
+            data: SpeedupData {
+                utxo: None,
+                partial_utxo: Some((txid, vout, output_type.get_value().to_sat())),
+                output_type: Some(output_type.clone()),
+                wots_sigs: None,
+                leaf_index: Some(leaf_index),
+                leaf_identification: false,
+            },
+        }
+    }
+
+    pub fn pay_to_anchor(txid: Txid, vout: u32, amount: u64) -> Self {
+        Self {
+            data: SpeedupData::new_anchor(txid, vout, amount),
+        }
+    }
+
+    /// Winternitz signatures pushed before the taproot signature of the leaf.
+    pub fn with_winternitz_signatures(mut self, signatures: Vec<WinternitzSignature>) -> Self {
+        self.data.wots_sigs = Some(signatures);
+        self
+    }
+
+    /// Pushes the leaf index after the signature, for leaves asserting their own id.
+    pub fn with_leaf_identification(mut self) -> Self {
+        self.data.leaf_identification = true;
+        self
+    }
+
+    pub fn build(self) -> Result<SpeedupData, ProtocolBuilderError> {
+        self.data.kind()?;
+        Ok(self.data)
+    }
+}
+
+/// Unconfirmed transaction sped up by a CPFP child, whose size and fee count towards the
+/// feerate of the package.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct UnconfirmedParent {
+    pub vsize: u64,
+    pub fee: u64,
+}
+
+impl UnconfirmedParent {
+    pub fn new(transaction: &Transaction, fee: u64) -> Self {
+        Self {
+            vsize: transaction.vsize() as u64,
+            fee,
+        }
+    }
 }
 
 impl Utxo {
@@ -118,6 +269,33 @@ impl Into<SpeedupData> for Utxo {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct OutputDescriptor {
+    pub transaction_name: String,
+    pub output_index: u32,
+    pub descriptor: String,
+}
+
+/// Location of a script that embeds a protocol constant.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConstantUsage {
+    pub transaction_name: String,
+    pub output_index: usize,
+    pub script_index: usize,
+}
+
+/// Spending data of a taproot leaf, see `OutputType::leaf_map`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LeafInfo {
+    /// Position of the leaf in the leaves of the output, used as leaf index everywhere else.
+    pub index: usize,
+    pub leaf_hash: TapLeafHash,
+    /// Serialized control block proving the leaf is committed in the output.
+    pub control_block: Vec<u8>,
+    /// Leaf id asserted by the script, see `ProtocolScript::set_assert_leaf_id`.
+    pub assert_leaf_id: Option<u32>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OutputType {
     Taproot {
@@ -125,6 +303,8 @@ pub enum OutputType {
         internal_key: PublicKey,
         script_pubkey: ScriptBuf,
         leaves: Vec<ProtocolScript>,
+        #[serde(default)]
+        layout: TapTreeLayout,
     },
     SegwitPublicKey {
         value: Amount,
@@ -143,6 +323,16 @@ pub enum OutputType {
     ExternalUnknown {
         script_pubkey: ScriptBuf,
     },
+    /// Pay-to-anchor output (`OP_1 <0x4e73>`), spent by anyone with an empty witness. Used as
+    /// an ephemeral anchor to bump the fee of a zero-fee transaction with CPFP package relay.
+    PayToAnchor {
+        value: Amount,
+        script_pubkey: ScriptBuf,
+    },
+    /// Output kind defined outside this crate, see `CustomOutput`.
+    Custom {
+        output: BoxedCustomOutput,
+    },
 }
 
 impl OutputType {
@@ -150,9 +340,20 @@ impl OutputType {
         value: u64,
         internal_key: &PublicKey,
         leaves: &[ProtocolScript],
+    ) -> Result<Self, ProtocolBuilderError> {
+        Self::taproot_with_layout(value, internal_key, leaves, &TapTreeLayout::Balanced)
+    }
+
+    /// Taproot output with a custom taptree shape, e.g. a Huffman tree of the expected spend
+    /// weights of the leaves. Leaf indexes still follow the order of `leaves`.
+    pub fn taproot_with_layout(
+        value: u64,
+        internal_key: &PublicKey,
+        leaves: &[ProtocolScript],
+        layout: &TapTreeLayout,
     ) -> Result<Self, ProtocolBuilderError> {
         let secp = secp256k1::Secp256k1::new();
-        let spend_info = Self::compute_spend_info(internal_key, leaves)?;
+        let spend_info = Self::compute_spend_info(internal_key, leaves, layout)?;
 
         let script_pubkey =
             ScriptBuf::new_p2tr(&secp, spend_info.internal_key(), spend_info.merkle_root());
@@ -162,6 +363,7 @@ impl OutputType {
             internal_key: *internal_key,
             script_pubkey,
             leaves: leaves.to_vec(),
+            layout: layout.clone(),
         })
     }
 
@@ -196,6 +398,20 @@ impl OutputType {
         })
     }
 
+    /// Zero value P2A output, see `OutputType::PayToAnchor`.
+    pub fn pay_to_anchor() -> Self {
+        OutputType::PayToAnchor {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_p2a(),
+        }
+    }
+
+    pub fn custom<T: CustomOutput + 'static>(output: T) -> Self {
+        OutputType::Custom {
+            output: BoxedCustomOutput::new(output),
+        }
+    }
+
     // TODO: for a more precise estimation we can set different dust limits for different output types
     pub fn dust_limit(&self) -> Amount {
         match self {
@@ -204,6 +420,8 @@ impl OutputType {
             OutputType::SegwitScript { .. } => Amount::from_sat(540),
             OutputType::SegwitUnspendable { .. } => Amount::from_sat(540),
             OutputType::ExternalUnknown { .. } => Amount::from_sat(540),
+            OutputType::PayToAnchor { .. } => Amount::ZERO,
+            OutputType::Custom { output } => output.dust_limit(),
         }
     }
 
@@ -214,6 +432,8 @@ impl OutputType {
             OutputType::SegwitScript { .. } => "SegwitScript",
             OutputType::SegwitUnspendable { .. } => "SegwitUnspendable",
             OutputType::ExternalUnknown { .. } => "ExternalUnknown",
+            OutputType::PayToAnchor { .. } => "PayToAnchor",
+            OutputType::Custom { .. } => "Custom",
         }
     }
 
@@ -222,7 +442,9 @@ impl OutputType {
             OutputType::Taproot { value, .. }
             | OutputType::SegwitPublicKey { value, .. }
             | OutputType::SegwitScript { value, .. }
-            | OutputType::SegwitUnspendable { value, .. } => *value,
+            | OutputType::SegwitUnspendable { value, .. }
+            | OutputType::PayToAnchor { value, .. } => *value,
+            OutputType::Custom { output } => output.value(),
             OutputType::ExternalUnknown { .. } => Amount::from_sat(0), /*TODO: FIX  {
                                                                            panic!("Cannot get value of ExternalUnknown output type")
                                                                        }*/
@@ -235,7 +457,9 @@ impl OutputType {
             OutputType::SegwitPublicKey { value, .. } => *value = new_value,
             OutputType::SegwitScript { value, .. } => *value = new_value,
             OutputType::SegwitUnspendable { value, .. } => *value = new_value,
+            OutputType::PayToAnchor { value, .. } => *value = new_value,
             OutputType::ExternalUnknown { .. } => { /* No value field to set */ }
+            OutputType::Custom { output } => output.set_value(new_value),
         }
     }
 
@@ -244,8 +468,10 @@ impl OutputType {
             OutputType::Taproot { value, .. }
             | OutputType::SegwitPublicKey { value, .. }
             | OutputType::SegwitScript { value, .. }
-            | OutputType::SegwitUnspendable { value, .. } => value.to_sat() == AUTO_AMOUNT,
+            | OutputType::SegwitUnspendable { value, .. }
+            | OutputType::PayToAnchor { value, .. } => value.to_sat() == AUTO_AMOUNT,
             OutputType::ExternalUnknown { .. } => false,
+            OutputType::Custom { output } => output.value().to_sat() == AUTO_AMOUNT,
         }
     }
 
@@ -254,8 +480,10 @@ impl OutputType {
             OutputType::Taproot { value, .. }
             | OutputType::SegwitPublicKey { value, .. }
             | OutputType::SegwitScript { value, .. }
-            | OutputType::SegwitUnspendable { value, .. } => value.to_sat() == RECOVER_AMOUNT,
+            | OutputType::SegwitUnspendable { value, .. }
+            | OutputType::PayToAnchor { value, .. } => value.to_sat() == RECOVER_AMOUNT,
             OutputType::ExternalUnknown { .. } => false,
+            OutputType::Custom { output } => output.value().to_sat() == RECOVER_AMOUNT,
         }
     }
 
@@ -265,7 +493,9 @@ impl OutputType {
             | OutputType::SegwitPublicKey { script_pubkey, .. }
             | OutputType::SegwitScript { script_pubkey, .. }
             | OutputType::ExternalUnknown { script_pubkey} //FIX
-            | OutputType::SegwitUnspendable { script_pubkey, .. } => script_pubkey,
+            | OutputType::SegwitUnspendable { script_pubkey, .. }
+            | OutputType::PayToAnchor { script_pubkey, .. } => script_pubkey,
+            OutputType::Custom { output } => output.script_pubkey(),
         }
     }
 
@@ -274,14 +504,165 @@ impl OutputType {
             OutputType::Taproot {
                 leaves,
                 internal_key,
+                layout,
                 ..
-            } => Ok(Some(Self::compute_spend_info(internal_key, leaves)?)),
+            } => Ok(Some(Self::compute_spend_info(
+                internal_key,
+                leaves,
+                layout,
+            )?)),
             _ => Ok(None),
         }
     }
 
+    /// Shape of the taptree of taproot outputs, balanced for other outputs.
+    pub fn taproot_layout(&self) -> TapTreeLayout {
+        match self {
+            OutputType::Taproot { layout, .. } => layout.clone(),
+            _ => TapTreeLayout::Balanced,
+        }
+    }
+
+    /// Sighash types the input spending this output is signed with: `sighash_type` for the key
+    /// path, and the effective sighash type of each leaf selected by the spend mode, see
+    /// `ProtocolScript::sighash_type_or`. Outputs without leaves are signed with `sighash_type`.
+    pub fn effective_sighash_types(
+        &self,
+        spend_mode: &SpendMode,
+        sighash_type: TapSighashType,
+    ) -> Result<Vec<TapSighashType>, ProtocolBuilderError> {
+        let OutputType::Taproot { leaves, .. } = self else {
+            return Ok(vec![sighash_type]);
+        };
+
+        let (key_path, _, _, selected_leaves) = spend_mode_params(leaves, spend_mode)?;
+        let mut sighash_types: Vec<TapSighashType> = selected_leaves
+            .unwrap_or_default()
+            .iter()
+            .map(|(_, leaf)| leaf.sighash_type_or(sighash_type))
+            .collect();
+        if key_path {
+            sighash_types.push(sighash_type);
+        }
+
+        Ok(sighash_types)
+    }
+
+    /// Returns the scripts committed in this output along with their index (leaf index for
+    /// taproot outputs, 0 for P2WSH outputs).
+    pub fn get_scripts(&self) -> Vec<(usize, &ProtocolScript)> {
+        match self {
+            OutputType::Taproot { leaves, .. } => leaves.iter().enumerate().collect(),
+            OutputType::SegwitScript { script, .. } => vec![(0, script)],
+            _ => vec![],
+        }
+    }
+
+    /// Returns the index of the script in this output, the same index `get_scripts` returns.
+    pub fn leaf_index_of(&self, script: &Script) -> Option<usize> {
+        self.get_scripts()
+            .into_iter()
+            .find(|(_, leaf)| leaf.get_script().as_script() == script)
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the index of the script with the given name, see `ProtocolScript::set_name`.
+    pub fn leaf_index_by_name(&self, name: &str) -> Option<usize> {
+        self.get_scripts()
+            .into_iter()
+            .find(|(_, leaf)| leaf.get_name() == Some(name))
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the index, leaf hash and control block of every leaf of a taproot output, keyed
+    /// by the leaf name. Leaves without a name are named `L<index>`. Other outputs have no
+    /// leaves.
+    pub fn leaf_map(&self) -> Result<BTreeMap<String, LeafInfo>, ProtocolBuilderError> {
+        let (OutputType::Taproot { leaves, .. }, Some(spend_info)) =
+            (self, self.get_taproot_spend_info()?)
+        else {
+            return Ok(BTreeMap::new());
+        };
+
+        leaves
+            .iter()
+            .enumerate()
+            .map(|(index, leaf)| {
+                let script = (leaf.get_script().clone(), LeafVersion::TapScript);
+                let control_block = spend_info
+                    .control_block(&script)
+                    .ok_or(ProtocolBuilderError::InvalidLeaf(index))?;
+
+                let name = match leaf.get_name() {
+                    Some(name) => name.to_string(),
+                    None => format!("L{}", index),
+                };
+
+                Ok((
+                    name,
+                    LeafInfo {
+                        index,
+                        leaf_hash: TapLeafHash::from_script(&script.0, script.1),
+                        control_block: control_block.serialize(),
+                        assert_leaf_id: leaf.assert_leaf_id(),
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// Returns an output descriptor (with checksum) that watch-only wallets can import to track
+    /// this output. Descriptors only describe scripts written in miniscript, so taproot trees
+    /// with other leaves are exported as `rawtr()` of the output key, and P2WSH scripts as
+    /// `raw()` of the script pubkey. ExternalUnknown outputs have no known script and return
+    /// None.
+    pub fn to_descriptor(&self) -> Option<String> {
+        let descriptor = match self {
+            OutputType::Taproot {
+                internal_key,
+                leaves,
+                layout,
+                script_pubkey,
+                ..
+            } => {
+                let internal_key = XOnlyPublicKey::from(*internal_key);
+                if leaves.is_empty() {
+                    format!("tr({})", internal_key)
+                } else if leaves.iter().all(|leaf| pk_descriptor(leaf, 32).is_some()) {
+                    let order = layout.tree_order(leaves.len()).ok()?;
+                    let mut leaves_iter = order
+                        .into_iter()
+                        .map(|(leaf_index, depth)| (&leaves[leaf_index], depth))
+                        .peekable();
+                    let tree = descriptor_tree(0, &mut leaves_iter);
+                    format!("tr({},{})", internal_key, tree)
+                } else {
+                    // The witness program of a taproot output is its output key
+                    format!("rawtr({})", hex::encode(&script_pubkey.as_bytes()[2..]))
+                }
+            }
+            OutputType::SegwitPublicKey { public_key, .. } => format!("wpkh({})", public_key),
+            OutputType::SegwitScript {
+                script,
+                script_pubkey,
+                ..
+            } => match pk_descriptor(script, 33) {
+                Some(pk) => format!("wsh({})", pk),
+                None => format!("raw({})", hex::encode(script_pubkey.as_bytes())),
+            },
+            OutputType::SegwitUnspendable { script_pubkey, .. }
+            | OutputType::PayToAnchor { script_pubkey, .. } => {
+                format!("raw({})", hex::encode(script_pubkey.as_bytes()))
+            }
+            OutputType::ExternalUnknown { .. } => return None,
+            OutputType::Custom { output } => return output.to_descriptor(),
+        };
+
+        with_checksum(&descriptor)
+    }
+
     #[allow(clippy::too_many_arguments)]
-    pub fn compute_taproot_sighash(
+    pub fn compute_taproot_sighash<S: Signer + ?Sized>(
         &self,
         transaction: &Transaction,
         transaction_name: &str,
@@ -289,7 +670,7 @@ impl OutputType {
         prevouts: &[TxOut],
         spend_mode: &SpendMode,
         tap_sighash_type: &TapSighashType,
-        key_manager: &KeyManager,
+        signer: &S,
         id: &str,
     ) -> Result<Vec<Option<Message>>, ProtocolBuilderError> {
         let messages = match self {
@@ -306,7 +687,7 @@ impl OutputType {
                 internal_key,
                 leaves,
                 spend_mode,
-                key_manager,
+                signer,
                 id,
             )?,
             _ => {
@@ -349,7 +730,8 @@ impl OutputType {
                 value,
                 script,
             )?,
-            OutputType::SegwitUnspendable { .. } => {
+            // Spent with an empty witness
+            OutputType::SegwitUnspendable { .. } | OutputType::PayToAnchor { .. } => {
                 vec![None]
             }
             _ => {
@@ -364,14 +746,14 @@ impl OutputType {
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn compute_taproot_signature(
+    pub fn compute_taproot_signature<S: Signer + ?Sized>(
         &self,
         transaction_name: &str,
         input_index: usize,
         hashed_messages: &[Option<Message>],
         spend_mode: &SpendMode,
         tap_sighash_type: &TapSighashType,
-        key_manager: &KeyManager,
+        signer: &S,
         id: &str,
     ) -> Result<Vec<Option<Signature>>, ProtocolBuilderError> {
         let signatures = match self {
@@ -387,7 +769,7 @@ impl OutputType {
                 internal_key,
                 leaves,
                 spend_mode,
-                key_manager,
+                signer,
                 id,
             )?,
             _ => {
@@ -401,33 +783,28 @@ impl OutputType {
         Ok(signatures)
     }
 
-    pub fn compute_ecdsa_signature(
+    pub fn compute_ecdsa_signature<S: Signer + ?Sized>(
         &self,
         _transaction_name: &str,
         _input_index: usize,
         hashed_messages: &[Option<Message>],
         spend_mode: &SpendMode,
         ecdsa_sighash_type: &EcdsaSighashType,
-        key_manager: &KeyManager,
+        signer: &S,
     ) -> Result<Vec<Option<Signature>>, ProtocolBuilderError> {
         if spend_mode.is_none() {
             return Ok(vec![None]);
         }
 
         let signatures = match self {
-            OutputType::SegwitPublicKey { public_key, .. } => self.ecdsa_key_signature(
-                hashed_messages,
-                ecdsa_sighash_type,
-                key_manager,
-                public_key,
-            )?,
-            OutputType::SegwitScript { script, .. } => self.ecdsa_script_signature(
-                hashed_messages,
-                ecdsa_sighash_type,
-                key_manager,
-                script,
-            )?,
-            OutputType::SegwitUnspendable { .. } => {
+            OutputType::SegwitPublicKey { public_key, .. } => {
+                self.ecdsa_key_signature(hashed_messages, ecdsa_sighash_type, signer, public_key)?
+            }
+            OutputType::SegwitScript { script, .. } => {
+                self.ecdsa_script_signature(hashed_messages, ecdsa_sighash_type, signer, script)?
+            }
+            // Spent with an empty witness
+            OutputType::SegwitUnspendable { .. } | OutputType::PayToAnchor { .. } => {
                 vec![None]
             }
             _ => {
@@ -444,15 +821,18 @@ impl OutputType {
     fn compute_spend_info(
         internal_key: &PublicKey,
         leaves: &[ProtocolScript],
+        layout: &TapTreeLayout,
     ) -> Result<TaprootSpendInfo, ProtocolBuilderError> {
-        let secp = secp256k1::Secp256k1::new();
-        let spend_info =
-            scripts::build_taproot_spend_info(&secp, &XOnlyPublicKey::from(*internal_key), leaves)?;
+        let internal_key = XOnlyPublicKey::from(*internal_key);
+        let spend_info = cached_spend_info(&internal_key, leaves, layout, || {
+            let secp = secp256k1::Secp256k1::new();
+            scripts::build_taproot_spend_info_with_layout(&secp, &internal_key, leaves, layout)
+        })?;
         Ok(spend_info)
     }
 
     #[allow(clippy::too_many_arguments)]
-    fn taproot_sighash(
+    fn taproot_sighash<S: Signer + ?Sized>(
         &self,
         transaction: &Transaction,
         transaction_name: &str,
@@ -462,7 +842,7 @@ impl OutputType {
         internal_key: &PublicKey,
         leaves: &[ProtocolScript],
         spend_mode: &SpendMode,
-        key_manager: &KeyManager,
+        signer: &S,
         id: &str,
     ) -> Result<Vec<Option<Message>>, ProtocolBuilderError> {
         let (key_path, scripts_path, key_path_sign_mode, selected_leaves) =
@@ -481,7 +861,7 @@ impl OutputType {
                 &key_path_sign_mode.unwrap(),
                 internal_key,
                 leaves,
-                key_manager,
+                signer,
                 id,
             )?;
 
@@ -501,7 +881,7 @@ impl OutputType {
                     tap_sighash_type,
                     leaf,
                     *leaf_index,
-                    key_manager,
+                    signer,
                     id,
                 )?;
 
@@ -514,7 +894,7 @@ impl OutputType {
     }
 
     #[allow(clippy::too_many_arguments)]
-    fn taproot_script_only_sighash(
+    fn taproot_script_only_sighash<S: Signer + ?Sized>(
         &self,
         transaction: &Transaction,
         transaction_name: &str,
@@ -523,20 +903,19 @@ impl OutputType {
         tap_sighash_type: &TapSighashType,
         leaf: &ProtocolScript,
         leaf_index: usize,
-        key_manager: &KeyManager,
+        signer: &S,
         id: &str,
     ) -> Result<Option<Message>, ProtocolBuilderError> {
-        let mut hasher = SighashCache::new(transaction);
-
-        let hashed_message = Message::from(hasher.taproot_script_spend_signature_hash(
+        let hashed_message = taproot_script_sighash(
+            transaction,
             input_index,
-            &sighash::Prevouts::All(prevouts),
-            TapLeafHash::from_script(leaf.get_script(), LeafVersion::TapScript),
-            *tap_sighash_type,
-        )?);
+            prevouts,
+            leaf.get_script(),
+            leaf.sighash_type_or(*tap_sighash_type),
+        )?;
 
         if leaf.aggregate_signing() && leaf.get_verifying_key().is_some() {
-            key_manager.generate_nonce(
+            signer.generate_nonce(
                 MessageId::new_string_id(transaction_name, input_index as u32, leaf_index as u32)
                     .as_str(),
                 hashed_message.as_ref().to_vec(),
@@ -550,7 +929,7 @@ impl OutputType {
     }
 
     #[allow(clippy::too_many_arguments)]
-    fn taproot_key_only_sighash(
+    fn taproot_key_only_sighash<S: Signer + ?Sized>(
         &self,
         transaction: &Transaction,
         transaction_name: &str,
@@ -560,20 +939,16 @@ impl OutputType {
         key_path_sign_mode: &SignMode,
         internal_key: &PublicKey,
         leaves: &[ProtocolScript],
-        key_manager: &KeyManager,
+        signer: &S,
         id: &str,
     ) -> Result<Option<Message>, ProtocolBuilderError> {
-        let mut hasher = SighashCache::new(transaction);
-
         // Compute a sighash for the key spend path.
-        let key_path_hashed_message = Message::from(hasher.taproot_key_spend_signature_hash(
-            input_index,
-            &sighash::Prevouts::All(prevouts),
-            *tap_sighash_type,
-        )?);
+        let key_path_hashed_message =
+            taproot_key_sighash(transaction, input_index, prevouts, *tap_sighash_type)?;
 
         if *key_path_sign_mode == SignMode::Aggregate {
-            let spend_info = Self::compute_spend_info(internal_key, leaves)?;
+            let spend_info =
+                Self::compute_spend_info(internal_key, leaves, &self.taproot_layout())?;
 
             let tweak = TapTweakHash::from_key_and_tweak(
                 XOnlyPublicKey::from(*internal_key),
@@ -583,7 +958,7 @@ impl OutputType {
             let musig2_tweak =
                 musig2::secp256k1::Scalar::from_be_bytes(tweak.to_be_bytes()).unwrap();
 
-            key_manager.generate_nonce(
+            signer.generate_nonce(
                 MessageId::new_string_id(transaction_name, input_index as u32, leaves.len() as u32)
                     .as_str(),
                 key_path_hashed_message.as_ref().to_vec(),
@@ -604,17 +979,13 @@ impl OutputType {
         value: &Amount,
         public_key: &PublicKey,
     ) -> Result<Vec<Option<Message>>, ProtocolBuilderError> {
-        let wpkh = public_key.wpubkey_hash().expect("key is compressed");
-        let script_pubkey = ScriptBuf::new_p2wpkh(&wpkh);
-
-        let mut sighasher = SighashCache::new(transaction);
-
-        Ok(vec![Some(Message::from(sighasher.p2wpkh_signature_hash(
+        Ok(vec![Some(p2wpkh_sighash(
+            transaction,
             input_index,
-            &script_pubkey,
+            public_key,
             *value,
             *ecdsa_sighash_type,
-        )?))])
+        )?)])
     }
 
     fn ecdsa_script_sighash(
@@ -625,23 +996,17 @@ impl OutputType {
         value: &Amount,
         script: &ProtocolScript,
     ) -> Result<Vec<Option<Message>>, ProtocolBuilderError> {
-        let script_hash = WScriptHash::from(script.get_script().clone());
-        let script_pubkey = ScriptBuf::new_p2wsh(&script_hash);
-
-        let mut sighasher = SighashCache::new(transaction);
-
-        let hashed_message = Message::from(sighasher.p2wsh_signature_hash(
+        Ok(vec![Some(p2wsh_sighash(
+            transaction,
             input_index,
-            &script_pubkey,
+            script.get_script(),
             *value,
             *ecdsa_sighash_type,
-        )?);
-
-        Ok(vec![Some(hashed_message)])
+        )?)])
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn taproot_signature(
+    pub fn taproot_signature<S: Signer + ?Sized>(
         &self,
         transaction_name: &str,
         input_index: usize,
@@ -650,7 +1015,7 @@ impl OutputType {
         internal_key: &PublicKey,
         leaves: &[ProtocolScript],
         spend_mode: &SpendMode,
-        key_manager: &KeyManager,
+        signer: &S,
         id: &str,
     ) -> Result<Vec<Option<Signature>>, ProtocolBuilderError> {
         assert!(
@@ -674,7 +1039,7 @@ impl OutputType {
                 &key_path_sign_mode.unwrap(),
                 internal_key,
                 leaves,
-                key_manager,
+                signer,
                 id,
             )?;
 
@@ -693,7 +1058,7 @@ impl OutputType {
                     tap_sighash_type,
                     leaf,
                     *leaf_index,
-                    key_manager,
+                    signer,
                     id,
                 )?;
 
@@ -705,7 +1070,7 @@ impl OutputType {
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn taproot_script_only_signature(
+    pub fn taproot_script_only_signature<S: Signer + ?Sized>(
         &self,
         transaction_name: &str,
         input_index: usize,
@@ -713,7 +1078,7 @@ impl OutputType {
         tap_sighash_type: &TapSighashType,
         leaf: &ProtocolScript,
         leaf_index: usize,
-        key_manager: &KeyManager,
+        signer: &S,
         id: &str,
     ) -> Result<Option<Signature>, ProtocolBuilderError> {
         if leaf.skip_signing() {
@@ -723,16 +1088,19 @@ impl OutputType {
         let schnorr_signature = if leaf.aggregate_signing() {
             let message_id =
                 MessageId::new_string_id(transaction_name, input_index as u32, leaf_index as u32);
-            key_manager.get_aggregated_signature(
-                &leaf.get_verifying_key().unwrap(),
-                id,
-                &message_id,
-            )?
+            signer.get_aggregated_signature(&leaf.get_verifying_key().unwrap(), id, &message_id)?
+        } else if leaf.is_multisig() {
+            // The first slot is the verifying key, which may belong to another party
+            let hashed_message = hashed_messages[leaf_index].unwrap();
+            match sign_multisig_slots(&hashed_message, leaf, leaf_index, signer)?[0] {
+                Some(schnorr_signature) => schnorr_signature,
+                None => return Ok(None),
+            }
         } else {
             let hashed_message = hashed_messages[leaf_index].unwrap();
 
-            let schnorr_signature = key_manager
-                .sign_schnorr_message(&hashed_message, &leaf.get_verifying_key().unwrap())?;
+            let schnorr_signature =
+                signer.sign_schnorr(&hashed_message, &leaf.get_verifying_key().unwrap())?;
 
             // Verify the signature.
             if !SignatureVerifier::new().verify_schnorr_signature(
@@ -752,12 +1120,56 @@ impl OutputType {
 
         Ok(Some(Signature::Taproot(bitcoin::taproot::Signature {
             signature: schnorr_signature,
-            sighash_type: *tap_sighash_type,
+            sighash_type: leaf.sighash_type_or(*tap_sighash_type),
         })))
     }
 
+    /// Signatures of every slot of the multisig leaves selected by the spend mode, by leaf index.
+    /// Slots whose key is not held by the signer are left empty.
+    pub fn compute_taproot_multisig_signatures<S: Signer + ?Sized>(
+        &self,
+        hashed_messages: &[Option<Message>],
+        spend_mode: &SpendMode,
+        tap_sighash_type: &TapSighashType,
+        signer: &S,
+    ) -> Result<BTreeMap<usize, Vec<Option<Signature>>>, ProtocolBuilderError> {
+        let OutputType::Taproot { leaves, .. } = self else {
+            return Ok(BTreeMap::new());
+        };
+
+        let (_, scripts_path, _, selected_leaves) = spend_mode_params(leaves, spend_mode)?;
+        if !scripts_path {
+            return Ok(BTreeMap::new());
+        }
+
+        let mut signatures = BTreeMap::new();
+        for (leaf_index, leaf) in selected_leaves.unwrap_or_default() {
+            if !leaf.is_multisig() || leaf.skip_signing() {
+                continue;
+            }
+            let Some(Some(hashed_message)) = hashed_messages.get(leaf_index) else {
+                continue;
+            };
+
+            let slots = sign_multisig_slots(hashed_message, &leaf, leaf_index, signer)?
+                .into_iter()
+                .map(|signature| {
+                    signature.map(|signature| {
+                        Signature::Taproot(bitcoin::taproot::Signature {
+                            signature,
+                            sighash_type: leaf.sighash_type_or(*tap_sighash_type),
+                        })
+                    })
+                })
+                .collect();
+            signatures.insert(leaf_index, slots);
+        }
+
+        Ok(signatures)
+    }
+
     #[allow(clippy::too_many_arguments)]
-    pub fn taproot_key_only_signature(
+    pub fn taproot_key_only_signature<S: Signer + ?Sized>(
         &self,
         transaction_name: &str,
         input_index: usize,
@@ -766,7 +1178,7 @@ impl OutputType {
         key_path_sign_mode: &SignMode,
         internal_key: &PublicKey,
         leaves: &[ProtocolScript],
-        key_manager: &KeyManager,
+        signer: &S,
         id: &str,
     ) -> Result<Option<Signature>, ProtocolBuilderError> {
         // Compute a signature for the key spend path.
@@ -777,11 +1189,12 @@ impl OutputType {
             let message_id =
                 MessageId::new_string_id(transaction_name, input_index as u32, leaves.len() as u32);
 
-            key_manager.get_aggregated_signature(internal_key, id, &message_id)?
+            signer.get_aggregated_signature(internal_key, id, &message_id)?
         } else {
-            let spend_info = Self::compute_spend_info(internal_key, leaves)?;
+            let spend_info =
+                Self::compute_spend_info(internal_key, leaves, &self.taproot_layout())?;
 
-            let (schnorr_signature, output_key) = key_manager.sign_schnorr_message_with_tap_tweak(
+            let (schnorr_signature, output_key) = signer.sign_schnorr_with_tweak(
                 &key_path_hashed_message,
                 internal_key,
                 spend_info.merkle_root(),
@@ -808,11 +1221,11 @@ impl OutputType {
         })))
     }
 
-    pub fn ecdsa_key_signature(
+    pub fn ecdsa_key_signature<S: Signer + ?Sized>(
         &self,
         hashed_messages: &[Option<Message>],
         ecdsa_sighash_type: &EcdsaSighashType,
-        key_manager: &KeyManager,
+        signer: &S,
         public_key: &PublicKey,
     ) -> Result<Vec<Option<Signature>>, ProtocolBuilderError> {
         assert!(
@@ -820,8 +1233,7 @@ impl OutputType {
             "Expected only one message to sign"
         );
         assert!(hashed_messages[0].is_some(), "Expected a message to sign");
-        let ecdsa_signature =
-            key_manager.sign_ecdsa_message(&hashed_messages[0].unwrap(), public_key)?;
+        let ecdsa_signature = signer.sign_ecdsa(&hashed_messages[0].unwrap(), public_key)?;
         let signature = Signature::Ecdsa(bitcoin::ecdsa::Signature {
             signature: ecdsa_signature,
             sighash_type: *ecdsa_sighash_type,
@@ -829,11 +1241,11 @@ impl OutputType {
         Ok(vec![Some(signature)])
     }
 
-    pub fn ecdsa_script_signature(
+    pub fn ecdsa_script_signature<S: Signer + ?Sized>(
         &self,
         hashed_messages: &[Option<Message>],
         ecdsa_sighash_type: &EcdsaSighashType,
-        key_manager: &KeyManager,
+        signer: &S,
         script: &ProtocolScript,
     ) -> Result<Vec<Option<Signature>>, ProtocolBuilderError> {
         assert!(
@@ -843,7 +1255,7 @@ impl OutputType {
         Ok(if script.skip_signing() {
             vec![None]
         } else {
-            let ecdsa_signature = key_manager.sign_ecdsa_message(
+            let ecdsa_signature = signer.sign_ecdsa(
                 &hashed_messages[0].unwrap(),
                 &script.get_verifying_key().unwrap(),
             )?;
@@ -858,7 +1270,7 @@ impl OutputType {
 }
 
 #[allow(clippy::type_complexity)]
-fn spend_mode_params(
+pub(crate) fn spend_mode_params(
     leaves: &[ProtocolScript],
     spend_mode: &SpendMode,
 ) -> Result<
@@ -887,6 +1299,13 @@ fn spend_mode_params(
             (false, true, None, Some(select_leaves(leaves, indexes)))
         }
         SpendMode::Script { leaf } => (false, true, None, Some(select_leaves(leaves, &[*leaf]))),
+        SpendMode::ScriptByName(name) => {
+            let leaf = leaves
+                .iter()
+                .position(|leaf| leaf.get_name() == Some(name.as_str()))
+                .ok_or(ProtocolBuilderError::UnknownLeafName(name.clone()))?;
+            (false, true, None, Some(select_leaves(leaves, &[leaf])))
+        }
         SpendMode::None => (false, false, None, None),
         SpendMode::Segwit => {
             return Err(ProtocolBuilderError::InvalidSpendMode(
@@ -898,6 +1317,35 @@ fn spend_mode_params(
     Ok((key_path, scripts_path, key_path_sign_mode, selected_leaves))
 }
 
+// Describes a `<key> OP_CHECKSIG` script of `key_size` byte keys as `pk(<key>)`.
+fn pk_descriptor(script: &ProtocolScript, key_size: usize) -> Option<String> {
+    let bytes = script.get_script().as_bytes();
+    let is_pk = bytes.len() == key_size + 2
+        && bytes[0] as usize == key_size
+        && bytes[key_size + 1] == OP_CHECKSIG.to_u8();
+    is_pk.then(|| format!("pk({})", hex::encode(&bytes[1..=key_size])))
+}
+
+// Renders the leaves of a taptree in descriptor notation, e.g. `{pk(..),{pk(..),pk(..)}}`.
+// Leaves are given in depth-first order along with their depth in the tree.
+fn descriptor_tree<'a, I>(depth: u8, leaves: &mut std::iter::Peekable<I>) -> String
+where
+    I: Iterator<Item = (&'a ProtocolScript, u8)>,
+{
+    match leaves.peek() {
+        Some((_, leaf_depth)) if *leaf_depth == depth => {
+            let (leaf, _) = leaves.next().unwrap();
+            pk_descriptor(leaf, 32).unwrap_or_default()
+        }
+        None => String::new(),
+        _ => {
+            let left = descriptor_tree(depth + 1, leaves);
+            let right = descriptor_tree(depth + 1, leaves);
+            format!("{{{},{}}}", left, right)
+        }
+    }
+}
+
 fn select_leaves(leaves: &[ProtocolScript], indexes: &[usize]) -> Vec<(usize, ProtocolScript)> {
     if indexes.is_empty() {
         return leaves
@@ -912,3 +1360,33 @@ fn select_leaves(leaves: &[ProtocolScript], indexes: &[usize]) -> Vec<(usize, Pr
         .map(|&leaf_index| (leaf_index, leaves[leaf_index].clone()))
         .collect::<Vec<(usize, ProtocolScript)>>()
 }
+
+// Signs the message with each key of a multisig leaf held by the signer. The keys of the other
+// parties cannot be used to sign, so their slots are left empty and filled by
+// `Protocol::import_multisig_signature`.
+fn sign_multisig_slots<S: Signer + ?Sized>(
+    hashed_message: &Message,
+    leaf: &ProtocolScript,
+    leaf_index: usize,
+    signer: &S,
+) -> Result<Vec<Option<secp256k1::schnorr::Signature>>, ProtocolBuilderError> {
+    let verifier = SignatureVerifier::new();
+    let mut signatures = vec![];
+
+    for (slot, key) in leaf.get_signers().iter().enumerate() {
+        if !signer.holds_key(key)? {
+            signatures.push(None);
+            continue;
+        }
+
+        let signature = signer.sign_schnorr(hashed_message, key)?;
+        if !verifier.verify_schnorr_signature(&signature, hashed_message, *key) {
+            return Err(ProtocolBuilderError::InvalidMultisigSlotSignature(
+                leaf_index, slot,
+            ));
+        }
+        signatures.push(Some(signature));
+    }
+
+    Ok(signatures)
+}
@@ -0,0 +1,34 @@
+use bitcoin::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::scripts::SignMode;
+
+/// Party owning an input of a protocol transaction, or a single leaf of the taproot output it
+/// spends. Leaf owners take precedence over the owner of the input.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct InputOwner {
+    pub transaction_name: String,
+    pub input_index: usize,
+    pub leaf: Option<usize>,
+    pub party: String,
+}
+
+/// Signature a party has to produce: the segwit signature or taproot key path signature of an
+/// input when `leaf` is None, or the signature of a leaf.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SigningTask {
+    pub transaction_name: String,
+    pub input_index: usize,
+    pub leaf: Option<usize>,
+    pub public_key: Option<PublicKey>,
+    pub sign_mode: SignMode,
+    /// Sighash to sign, available once the protocol is built.
+    pub message: Option<Vec<u8>>,
+}
+
+/// Signatures owned by a party, see `Protocol::signing_manifest`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SigningManifest {
+    pub party: String,
+    pub tasks: Vec<SigningTask>,
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+/// Output spent by several transactions, of which at most one can be mined.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConflictSet {
+    pub transaction_name: String,
+    pub output_index: usize,
+    pub spenders: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PlanStep {
+    pub transaction_name: String,
+    /// Transactions spent by this one, which must be mined first.
+    pub depends_on: Vec<String>,
+    /// Transactions spending an output also spent by this one.
+    pub conflicts_with: Vec<String>,
+    /// Transactions that can no longer be mined once this one is, because they (or one of their
+    /// ancestors) conflict with this transaction or one of its ancestors.
+    pub excludes: Vec<String>,
+}
+
+/// Transactions of a protocol in topological order, annotated with the branch choices they
+/// imply, see `Protocol::execution_plan`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ExecutionPlan {
+    pub steps: Vec<PlanStep>,
+    pub conflicts: Vec<ConflictSet>,
+}
+
+impl ExecutionPlan {
+    pub fn step(&self, transaction_name: &str) -> Option<&PlanStep> {
+        self.steps
+            .iter()
+            .find(|step| step.transaction_name == transaction_name)
+    }
+
+    /// Returns true if both transactions belong to different branches, so at most one of them
+    /// can be mined.
+    pub fn are_exclusive(&self, a: &str, b: &str) -> bool {
+        self.step(a)
+            .is_some_and(|step| step.excludes.iter().any(|name| name == b))
+    }
+
+    /// Transactions that can still be mined after the given ones, in plan order.
+    pub fn remaining(&self, mined: &[String]) -> Vec<String> {
+        self.steps
+            .iter()
+            .filter(|step| !mined.contains(&step.transaction_name))
+            .filter(|step| {
+                !mined
+                    .iter()
+                    .any(|name| self.are_exclusive(name, &step.transaction_name))
+            })
+            .map(|step| step.transaction_name.clone())
+            .collect()
+    }
+}
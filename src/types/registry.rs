@@ -0,0 +1,60 @@
+use std::{
+    collections::BTreeSet,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Lifecycle of a protocol instance managed with `ProtocolRegistry`. The registry never
+/// changes it on its own, see `ProtocolRegistry::set_status`.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ProtocolStatus {
+    #[default]
+    Created,
+    Signed,
+    Running,
+    Settled,
+    Abandoned,
+}
+
+/// Metadata kept by `ProtocolRegistry` for each saved protocol.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProtocolRecord {
+    pub name: String,
+    /// Unix time the protocol was first saved in the registry.
+    pub created_at: u64,
+    /// Unix time of the last save.
+    pub updated_at: u64,
+    pub participants: Vec<String>,
+    pub status: ProtocolStatus,
+    pub tags: BTreeSet<String>,
+}
+
+impl ProtocolRecord {
+    pub fn new(name: &str, participants: Vec<String>) -> Self {
+        let now = now();
+        Self {
+            name: name.to_string(),
+            created_at: now,
+            updated_at: now,
+            participants,
+            status: ProtocolStatus::default(),
+            tags: BTreeSet::new(),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    pub(crate) fn touch(&mut self) {
+        self.updated_at = now();
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
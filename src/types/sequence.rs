@@ -0,0 +1,26 @@
+use bitcoin::{absolute::LockTime, Sequence};
+use serde::{Deserialize, Serialize};
+
+/// Sequence given to the inputs of a transaction that have no relative timelock. See
+/// `Protocol::set_sequence_policy`.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SequencePolicy {
+    /// Signals BIP 125 replaceability (`ENABLE_RBF_NO_LOCKTIME`).
+    #[default]
+    Replaceable,
+    /// Final sequence, or `ENABLE_LOCKTIME_NO_RBF` when the transaction has an absolute
+    /// locktime so it is still enforced.
+    Final,
+    Fixed(Sequence),
+}
+
+impl SequencePolicy {
+    pub fn sequence(&self, lock_time: LockTime) -> Sequence {
+        match self {
+            SequencePolicy::Replaceable => Sequence::ENABLE_RBF_NO_LOCKTIME,
+            SequencePolicy::Final if lock_time == LockTime::ZERO => Sequence::MAX,
+            SequencePolicy::Final => Sequence::ENABLE_LOCKTIME_NO_RBF,
+            SequencePolicy::Fixed(sequence) => *sequence,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::errors::SerializationError;
+
+/// Bytes identifying data encoded with `serialize`.
+const MAGIC: [u8; 4] = *b"BVMX";
+
+/// Version of the header and envelope layout. Readers reject data written with a newer version.
+pub const SERIALIZATION_VERSION: u8 = 1;
+
+/// Magic bytes, version and format.
+const HEADER_SIZE: usize = MAGIC.len() + 2;
+
+/// Encoding used to persist protocols and to exchange them between parties.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SerializationFormat {
+    /// Human readable, the largest encoding.
+    #[default]
+    Json,
+    /// Compact binary encoding, fastest to read and write.
+    Bincode,
+    /// Compact self-describing binary encoding (RFC 8949).
+    Cbor,
+}
+
+impl SerializationFormat {
+    fn tag(&self) -> u8 {
+        match self {
+            SerializationFormat::Json => 0,
+            SerializationFormat::Bincode => 1,
+            SerializationFormat::Cbor => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self, SerializationError> {
+        match tag {
+            0 => Ok(SerializationFormat::Json),
+            1 => Ok(SerializationFormat::Bincode),
+            2 => Ok(SerializationFormat::Cbor),
+            _ => Err(SerializationError::UnknownFormat(tag)),
+        }
+    }
+
+    /// Reads the format from the header of data encoded with `serialize`.
+    pub fn from_header(bytes: &[u8]) -> Result<Self, SerializationError> {
+        read_header(bytes)
+    }
+}
+
+impl fmt::Display for SerializationFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializationFormat::Json => write!(f, "json"),
+            SerializationFormat::Bincode => write!(f, "bincode"),
+            SerializationFormat::Cbor => write!(f, "cbor"),
+        }
+    }
+}
+
+/// Encodes the value with the given format, prefixed by a header with the format and version so
+/// `deserialize` does not need to know how the data was written.
+pub fn serialize<T: Serialize>(
+    value: &T,
+    format: SerializationFormat,
+) -> Result<Vec<u8>, SerializationError> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE);
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(SERIALIZATION_VERSION);
+    bytes.push(format.tag());
+
+    let encode_error = |error: String| SerializationError::EncodeError(format.to_string(), error);
+
+    match format {
+        SerializationFormat::Json => serde_json::to_writer(&mut bytes, value)
+            .map_err(|error| encode_error(error.to_string()))?,
+        SerializationFormat::Bincode => bincode::serialize_into(&mut bytes, value)
+            .map_err(|error| encode_error(error.to_string()))?,
+        SerializationFormat::Cbor => ciborium::into_writer(value, &mut bytes)
+            .map_err(|error| encode_error(error.to_string()))?,
+    }
+
+    Ok(bytes)
+}
+
+/// Decodes data encoded with `serialize`, in any of the supported formats.
+pub fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, SerializationError> {
+    let (format, payload) = split_header(bytes)?;
+    let decode_error = |error: String| SerializationError::DecodeError(format.to_string(), error);
+
+    match format {
+        SerializationFormat::Json => {
+            serde_json::from_slice(payload).map_err(|error| decode_error(error.to_string()))
+        }
+        SerializationFormat::Bincode => {
+            bincode::deserialize(payload).map_err(|error| decode_error(error.to_string()))
+        }
+        SerializationFormat::Cbor => {
+            ciborium::from_reader(payload).map_err(|error| decode_error(error.to_string()))
+        }
+    }
+}
+
+/// Format and encoded payload of data encoded with `serialize`.
+pub(crate) fn split_header(
+    bytes: &[u8],
+) -> Result<(SerializationFormat, &[u8]), SerializationError> {
+    let format = read_header(bytes)?;
+    Ok((format, &bytes[HEADER_SIZE..]))
+}
+
+// Newer versions may add formats, so the version is checked before the format.
+fn read_header(bytes: &[u8]) -> Result<SerializationFormat, SerializationError> {
+    if bytes.len() < HEADER_SIZE || bytes[..MAGIC.len()] != MAGIC {
+        return Err(SerializationError::InvalidHeader);
+    }
+
+    let version = bytes[MAGIC.len()];
+    if version > SERIALIZATION_VERSION {
+        return Err(SerializationError::UnsupportedVersion(
+            version,
+            SERIALIZATION_VERSION,
+        ));
+    }
+
+    SerializationFormat::from_tag(bytes[MAGIC.len() + 1])
+}
@@ -0,0 +1,459 @@
+use std::{collections::HashMap, rc::Rc, sync::Arc};
+
+use bitcoin::{
+    secp256k1::{ecdsa, schnorr, Message},
+    PublicKey, TapNodeHash,
+};
+use key_manager::{
+    key_manager::KeyManager,
+    winternitz::{WinternitzSignature, WinternitzType},
+};
+use musig2::{secp256k1::Scalar, PartialSignature, PubNonce};
+
+use crate::errors::ProtocolBuilderError;
+
+/// Source of the signatures of a protocol. `build`, `sign` and `compute_signatures` only talk to
+/// the keys through this trait, so the protocol can be signed by something other than the
+/// `KeyManager`, e.g. an HSM or a remote signing service.
+///
+/// Implementations that fail for reasons of their own should return
+/// `ProtocolBuilderError::ExternalSignerError`.
+pub trait Signer {
+    /// Signs a taproot script path message with the key `public_key`.
+    fn sign_schnorr(
+        &self,
+        message: &Message,
+        public_key: &PublicKey,
+    ) -> Result<schnorr::Signature, ProtocolBuilderError>;
+
+    /// Signs a taproot key path message with `internal_key` tweaked with `merkle_root`. Returns
+    /// the signature along with the tweaked key it verifies against.
+    fn sign_schnorr_with_tweak(
+        &self,
+        message: &Message,
+        internal_key: &PublicKey,
+        merkle_root: Option<TapNodeHash>,
+    ) -> Result<(schnorr::Signature, PublicKey), ProtocolBuilderError>;
+
+    fn sign_ecdsa(
+        &self,
+        message: &Message,
+        public_key: &PublicKey,
+    ) -> Result<ecdsa::Signature, ProtocolBuilderError>;
+
+    /// Signs `message` with the Winternitz key of the given type and derivation index.
+    fn sign_winternitz(
+        &self,
+        message: &[u8],
+        key_type: WinternitzType,
+        derivation_index: u32,
+    ) -> Result<WinternitzSignature, ProtocolBuilderError>;
+
+    /// MuSig2 hook called for every message signed with `aggregated_key` while computing the
+    /// sighashes of the protocol.
+    fn generate_nonce(
+        &self,
+        message_id: &str,
+        message: Vec<u8>,
+        aggregated_key: &PublicKey,
+        id: &str,
+        tweak: Option<Scalar>,
+    ) -> Result<(), ProtocolBuilderError>;
+
+    /// MuSig2 hook returning the public nonces generated for `aggregated_key`, by message id.
+    fn get_pub_nonces(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+    ) -> Result<Vec<(String, PubNonce)>, ProtocolBuilderError>;
+
+    /// MuSig2 hook receiving the public nonces of the other participants, by participant key.
+    fn aggregate_nonces(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+        nonces: HashMap<PublicKey, Vec<(String, PubNonce)>>,
+    ) -> Result<(), ProtocolBuilderError>;
+
+    /// MuSig2 hook returning the partial signatures of the signer for `aggregated_key`, by
+    /// message id, once the nonces of every participant were aggregated.
+    fn get_partial_signatures(
+        &self,
+        _aggregated_key: &PublicKey,
+        _id: &str,
+    ) -> Result<Vec<(String, PartialSignature)>, ProtocolBuilderError> {
+        Err(ProtocolBuilderError::ExternalSignerError(
+            "partial signatures are not supported by this signer".to_string(),
+        ))
+    }
+
+    /// MuSig2 hook receiving the partial signatures of the other participants, by participant
+    /// key, so the signer can aggregate the signatures of the session.
+    fn aggregate_partial_signatures(
+        &self,
+        _aggregated_key: &PublicKey,
+        _id: &str,
+        _partial_signatures: HashMap<PublicKey, Vec<(String, PartialSignature)>>,
+    ) -> Result<(), ProtocolBuilderError> {
+        Err(ProtocolBuilderError::ExternalSignerError(
+            "partial signatures are not supported by this signer".to_string(),
+        ))
+    }
+
+    /// MuSig2 hook returning the aggregated signature of a message once the session completes.
+    fn get_aggregated_signature(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+        message_id: &str,
+    ) -> Result<schnorr::Signature, ProtocolBuilderError>;
+
+    /// MuSig2 hook dropping the nonces generated for `aggregated_key` in the session `id`, e.g.
+    /// when they expire before the session completes. Signers not keeping nonces do nothing.
+    fn discard_nonces(
+        &self,
+        _aggregated_key: &PublicKey,
+        _id: &str,
+    ) -> Result<(), ProtocolBuilderError> {
+        Ok(())
+    }
+
+    /// Whether the signer can sign with `public_key`. Used for the slots of multisig leaves,
+    /// which hold the keys of every party, so only the slots of held keys are signed and the
+    /// errors of the signer are not mistaken for keys of other parties.
+    fn holds_key(&self, _public_key: &PublicKey) -> Result<bool, ProtocolBuilderError> {
+        Ok(true)
+    }
+}
+
+impl Signer for KeyManager {
+    fn sign_schnorr(
+        &self,
+        message: &Message,
+        public_key: &PublicKey,
+    ) -> Result<schnorr::Signature, ProtocolBuilderError> {
+        Ok(self.sign_schnorr_message(message, public_key)?)
+    }
+
+    fn sign_schnorr_with_tweak(
+        &self,
+        message: &Message,
+        internal_key: &PublicKey,
+        merkle_root: Option<TapNodeHash>,
+    ) -> Result<(schnorr::Signature, PublicKey), ProtocolBuilderError> {
+        Ok(self.sign_schnorr_message_with_tap_tweak(message, internal_key, merkle_root)?)
+    }
+
+    fn sign_ecdsa(
+        &self,
+        message: &Message,
+        public_key: &PublicKey,
+    ) -> Result<ecdsa::Signature, ProtocolBuilderError> {
+        Ok(self.sign_ecdsa_message(message, public_key)?)
+    }
+
+    fn sign_winternitz(
+        &self,
+        message: &[u8],
+        key_type: WinternitzType,
+        derivation_index: u32,
+    ) -> Result<WinternitzSignature, ProtocolBuilderError> {
+        Ok(self.sign_winternitz_message(message, key_type, derivation_index)?)
+    }
+
+    fn generate_nonce(
+        &self,
+        message_id: &str,
+        message: Vec<u8>,
+        aggregated_key: &PublicKey,
+        id: &str,
+        tweak: Option<Scalar>,
+    ) -> Result<(), ProtocolBuilderError> {
+        Ok(KeyManager::generate_nonce(
+            self,
+            message_id,
+            message,
+            aggregated_key,
+            id,
+            tweak,
+        )?)
+    }
+
+    fn get_pub_nonces(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+    ) -> Result<Vec<(String, PubNonce)>, ProtocolBuilderError> {
+        Ok(self.get_my_pub_nonces(aggregated_key, id)?)
+    }
+
+    fn aggregate_nonces(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+        nonces: HashMap<PublicKey, Vec<(String, PubNonce)>>,
+    ) -> Result<(), ProtocolBuilderError> {
+        Ok(KeyManager::aggregate_nonces(
+            self,
+            aggregated_key,
+            id,
+            nonces,
+        )?)
+    }
+
+    fn get_partial_signatures(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+    ) -> Result<Vec<(String, PartialSignature)>, ProtocolBuilderError> {
+        Ok(self.get_my_partial_signatures(aggregated_key, id)?)
+    }
+
+    fn aggregate_partial_signatures(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+        partial_signatures: HashMap<PublicKey, Vec<(String, PartialSignature)>>,
+    ) -> Result<(), ProtocolBuilderError> {
+        Ok(self.save_partial_signatures(aggregated_key, id, partial_signatures)?)
+    }
+
+    fn get_aggregated_signature(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+        message_id: &str,
+    ) -> Result<schnorr::Signature, ProtocolBuilderError> {
+        Ok(KeyManager::get_aggregated_signature(
+            self,
+            aggregated_key,
+            id,
+            message_id,
+        )?)
+    }
+
+    fn discard_nonces(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+    ) -> Result<(), ProtocolBuilderError> {
+        Ok(self.remove_musig2_session(aggregated_key, id)?)
+    }
+
+    fn holds_key(&self, public_key: &PublicKey) -> Result<bool, ProtocolBuilderError> {
+        // The key manager has no lookup, so the key is probed by signing a fixed digest
+        let probe = Message::from_digest([1; 32]);
+        Ok(self.sign_schnorr_message(&probe, public_key).is_ok())
+    }
+}
+
+macro_rules! forward_signer {
+    ($($pointer:ty),*) => {
+        $(
+            impl<S: Signer + ?Sized> Signer for $pointer {
+                fn sign_schnorr(
+                    &self,
+                    message: &Message,
+                    public_key: &PublicKey,
+                ) -> Result<schnorr::Signature, ProtocolBuilderError> {
+                    (**self).sign_schnorr(message, public_key)
+                }
+
+                fn sign_schnorr_with_tweak(
+                    &self,
+                    message: &Message,
+                    internal_key: &PublicKey,
+                    merkle_root: Option<TapNodeHash>,
+                ) -> Result<(schnorr::Signature, PublicKey), ProtocolBuilderError> {
+                    (**self).sign_schnorr_with_tweak(message, internal_key, merkle_root)
+                }
+
+                fn sign_ecdsa(
+                    &self,
+                    message: &Message,
+                    public_key: &PublicKey,
+                ) -> Result<ecdsa::Signature, ProtocolBuilderError> {
+                    (**self).sign_ecdsa(message, public_key)
+                }
+
+                fn sign_winternitz(
+                    &self,
+                    message: &[u8],
+                    key_type: WinternitzType,
+                    derivation_index: u32,
+                ) -> Result<WinternitzSignature, ProtocolBuilderError> {
+                    (**self).sign_winternitz(message, key_type, derivation_index)
+                }
+
+                fn generate_nonce(
+                    &self,
+                    message_id: &str,
+                    message: Vec<u8>,
+                    aggregated_key: &PublicKey,
+                    id: &str,
+                    tweak: Option<Scalar>,
+                ) -> Result<(), ProtocolBuilderError> {
+                    (**self).generate_nonce(message_id, message, aggregated_key, id, tweak)
+                }
+
+                fn get_pub_nonces(
+                    &self,
+                    aggregated_key: &PublicKey,
+                    id: &str,
+                ) -> Result<Vec<(String, PubNonce)>, ProtocolBuilderError> {
+                    (**self).get_pub_nonces(aggregated_key, id)
+                }
+
+                fn aggregate_nonces(
+                    &self,
+                    aggregated_key: &PublicKey,
+                    id: &str,
+                    nonces: HashMap<PublicKey, Vec<(String, PubNonce)>>,
+                ) -> Result<(), ProtocolBuilderError> {
+                    (**self).aggregate_nonces(aggregated_key, id, nonces)
+                }
+
+                fn get_partial_signatures(
+                    &self,
+                    aggregated_key: &PublicKey,
+                    id: &str,
+                ) -> Result<Vec<(String, PartialSignature)>, ProtocolBuilderError> {
+                    (**self).get_partial_signatures(aggregated_key, id)
+                }
+
+                fn aggregate_partial_signatures(
+                    &self,
+                    aggregated_key: &PublicKey,
+                    id: &str,
+                    partial_signatures: HashMap<PublicKey, Vec<(String, PartialSignature)>>,
+                ) -> Result<(), ProtocolBuilderError> {
+                    (**self).aggregate_partial_signatures(aggregated_key, id, partial_signatures)
+                }
+
+                fn get_aggregated_signature(
+                    &self,
+                    aggregated_key: &PublicKey,
+                    id: &str,
+                    message_id: &str,
+                ) -> Result<schnorr::Signature, ProtocolBuilderError> {
+                    (**self).get_aggregated_signature(aggregated_key, id, message_id)
+                }
+
+                fn discard_nonces(
+                    &self,
+                    aggregated_key: &PublicKey,
+                    id: &str,
+                ) -> Result<(), ProtocolBuilderError> {
+                    (**self).discard_nonces(aggregated_key, id)
+                }
+
+                fn holds_key(&self, public_key: &PublicKey) -> Result<bool, ProtocolBuilderError> {
+                    (**self).holds_key(public_key)
+                }
+            }
+        )*
+    };
+}
+
+// Signers are usually shared, e.g. the `Rc<KeyManager>` passed to `Protocol::build`
+forward_signer!(&S, Rc<S>, Arc<S>, Box<S>);
+
+/// Asynchronous counterpart of `Signer`, for signers that are awaited over the network (e.g. a
+/// network HSM or an MPC coordinator). Used by `Protocol::sign_async`, which requests the
+/// signatures of every input concurrently. MuSig2 nonces are still generated by `build`, which
+/// needs a `Signer`.
+///
+/// Every `Signer` is also an `AsyncSigner` whose futures are ready at once.
+#[cfg(feature = "async")]
+pub trait AsyncSigner {
+    fn sign_schnorr(
+        &self,
+        message: &Message,
+        public_key: &PublicKey,
+    ) -> impl std::future::Future<Output = Result<schnorr::Signature, ProtocolBuilderError>>;
+
+    fn sign_schnorr_with_tweak(
+        &self,
+        message: &Message,
+        internal_key: &PublicKey,
+        merkle_root: Option<TapNodeHash>,
+    ) -> impl std::future::Future<Output = Result<(schnorr::Signature, PublicKey), ProtocolBuilderError>>;
+
+    fn sign_ecdsa(
+        &self,
+        message: &Message,
+        public_key: &PublicKey,
+    ) -> impl std::future::Future<Output = Result<ecdsa::Signature, ProtocolBuilderError>>;
+
+    fn sign_winternitz(
+        &self,
+        message: &[u8],
+        key_type: WinternitzType,
+        derivation_index: u32,
+    ) -> impl std::future::Future<Output = Result<WinternitzSignature, ProtocolBuilderError>>;
+
+    fn get_aggregated_signature(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+        message_id: &str,
+    ) -> impl std::future::Future<Output = Result<schnorr::Signature, ProtocolBuilderError>>;
+
+    /// Whether the signer can sign with `public_key`, see `Signer::holds_key`.
+    fn holds_key(
+        &self,
+        _public_key: &PublicKey,
+    ) -> impl std::future::Future<Output = Result<bool, ProtocolBuilderError>> {
+        async { Ok(true) }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<S: Signer + ?Sized> AsyncSigner for S {
+    async fn sign_schnorr(
+        &self,
+        message: &Message,
+        public_key: &PublicKey,
+    ) -> Result<schnorr::Signature, ProtocolBuilderError> {
+        Signer::sign_schnorr(self, message, public_key)
+    }
+
+    async fn sign_schnorr_with_tweak(
+        &self,
+        message: &Message,
+        internal_key: &PublicKey,
+        merkle_root: Option<TapNodeHash>,
+    ) -> Result<(schnorr::Signature, PublicKey), ProtocolBuilderError> {
+        Signer::sign_schnorr_with_tweak(self, message, internal_key, merkle_root)
+    }
+
+    async fn sign_ecdsa(
+        &self,
+        message: &Message,
+        public_key: &PublicKey,
+    ) -> Result<ecdsa::Signature, ProtocolBuilderError> {
+        Signer::sign_ecdsa(self, message, public_key)
+    }
+
+    async fn sign_winternitz(
+        &self,
+        message: &[u8],
+        key_type: WinternitzType,
+        derivation_index: u32,
+    ) -> Result<WinternitzSignature, ProtocolBuilderError> {
+        Signer::sign_winternitz(self, message, key_type, derivation_index)
+    }
+
+    async fn get_aggregated_signature(
+        &self,
+        aggregated_key: &PublicKey,
+        id: &str,
+        message_id: &str,
+    ) -> Result<schnorr::Signature, ProtocolBuilderError> {
+        Signer::get_aggregated_signature(self, aggregated_key, id, message_id)
+    }
+
+    async fn holds_key(&self, public_key: &PublicKey) -> Result<bool, ProtocolBuilderError> {
+        Signer::holds_key(self, public_key)
+    }
+}
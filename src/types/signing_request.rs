@@ -0,0 +1,142 @@
+use std::fmt;
+
+use bitcoin::{secp256k1::Message, PublicKey, TapNodeHash};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::SerializationError;
+
+use super::{
+    input::{SighashType, Signature},
+    serialization::{deserialize, serialize, SerializationFormat},
+};
+
+/// Identifies a signature of a protocol input.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SignatureId {
+    pub transaction_name: String,
+    pub input_index: usize,
+    /// Leaf signed, or `None` for a taproot key path or a segwit input.
+    pub leaf: Option<usize>,
+    /// Signer slot of a multisig leaf.
+    pub slot: Option<usize>,
+}
+
+impl fmt::Display for SignatureId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.transaction_name, self.input_index)?;
+        match self.leaf {
+            Some(leaf) => write!(f, " leaf {}", leaf)?,
+            None => write!(f, " key path")?,
+        }
+        if let Some(slot) = self.slot {
+            write!(f, " slot {}", slot)?;
+        }
+        Ok(())
+    }
+}
+
+/// Key a sighash must be signed with.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum SigningKey {
+    /// Schnorr signature with the key as is, for taproot leaves.
+    Schnorr(PublicKey),
+    /// Schnorr signature with `internal_key` tweaked with `merkle_root`, for taproot key paths.
+    TweakedSchnorr {
+        internal_key: PublicKey,
+        merkle_root: Option<TapNodeHash>,
+    },
+    Ecdsa(PublicKey),
+}
+
+/// Sighash to be signed by an offline signer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SigningRequest {
+    pub id: SignatureId,
+    pub sighash: [u8; 32],
+    pub key: SigningKey,
+    pub sighash_type: SighashType,
+}
+
+impl SigningRequest {
+    pub fn message(&self) -> Message {
+        Message::from_digest(self.sighash)
+    }
+
+    /// Response answering this request with `signature`.
+    pub fn respond(&self, signature: Signature) -> SigningResponse {
+        SigningResponse {
+            id: self.id.clone(),
+            signature,
+        }
+    }
+}
+
+/// Every sighash of a protocol that can be signed offline, so an air-gapped signer only needs
+/// its keys to sign them. See `Protocol::export_signing_requests`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SigningPackage {
+    pub protocol_name: String,
+    pub requests: Vec<SigningRequest>,
+}
+
+impl SigningPackage {
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Empty set of responses to fill in while signing the package.
+    pub fn responses(&self) -> SigningResponses {
+        SigningResponses {
+            protocol_name: self.protocol_name.clone(),
+            responses: vec![],
+        }
+    }
+
+    pub fn to_bytes(&self, format: SerializationFormat) -> Result<Vec<u8>, SerializationError> {
+        serialize(self, format)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        deserialize(bytes)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SigningResponse {
+    pub id: SignatureId,
+    pub signature: Signature,
+}
+
+/// Signatures made by an offline signer for a `SigningPackage`. See
+/// `Protocol::import_signing_responses`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SigningResponses {
+    pub protocol_name: String,
+    pub responses: Vec<SigningResponse>,
+}
+
+impl SigningResponses {
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+
+    pub fn push(&mut self, response: SigningResponse) {
+        self.responses.push(response);
+    }
+
+    pub fn to_bytes(&self, format: SerializationFormat) -> Result<Vec<u8>, SerializationError> {
+        serialize(self, format)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        deserialize(bytes)
+    }
+}
@@ -0,0 +1,52 @@
+use bitcoin::{OutPoint, Sequence, Txid};
+use serde::{Deserialize, Serialize};
+
+/// Unsigned transaction exported for external fee-bumping and accelerator services. The
+/// transaction is serialized with empty witnesses and every input carries a placeholder with the
+/// estimated weight of its witness, so the service can compute the final size and fee.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TransactionSkeleton {
+    pub name: String,
+    pub txid: Txid,
+    pub version: i32,
+    pub lock_time: u32,
+    /// Consensus serialization of the transaction with empty witnesses.
+    pub unsigned_hex: String,
+    /// Estimated weight of the fully signed transaction.
+    pub estimated_weight: u64,
+    /// Estimated virtual size of the fully signed transaction.
+    pub estimated_vsize: u64,
+    pub inputs: Vec<SkeletonInput>,
+    pub outputs: Vec<SkeletonOutput>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SkeletonInput {
+    pub previous_output: OutPoint,
+    pub sequence: Sequence,
+    /// Value of the output spent by this input, in satoshis.
+    pub amount: u64,
+    pub sighash_type: String,
+    /// When true, other inputs can be added to the transaction without invalidating the
+    /// signature of this input.
+    pub anyone_can_pay: bool,
+    /// Estimated weight units of the witness of this input.
+    pub witness_weight: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SkeletonOutput {
+    pub amount: u64,
+    pub script_pubkey: String,
+}
+
+/// Result of importing a re-signed variant of a protocol transaction, see
+/// `Protocol::import_resigned_transaction`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ResignedImport {
+    pub txid: Txid,
+    /// Indexes, in the variant, of the inputs added by the external service. The outputs they
+    /// spend are unknown to the protocol, so neither their witnesses nor their amounts are
+    /// verified, and the fee of the variant cannot be computed from the protocol alone.
+    pub unverified_inputs: Vec<usize>,
+}
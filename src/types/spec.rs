@@ -0,0 +1,178 @@
+use std::path::Path;
+
+use bitcoin::Txid;
+use config::{File, FileFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::ProtocolBuilderError, scripts::SignMode, types::input::SpendMode};
+
+/// Declarative description of a protocol, built with `Protocol::from_spec`. Keys are declared
+/// once and referred to by name from outputs and leaves, and transactions are created by the
+/// connections that reference them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProtocolSpec {
+    #[serde(default)]
+    pub keys: Vec<SpecKey>,
+    #[serde(default)]
+    pub connections: Vec<SpecConnection>,
+    /// Outputs not spent by any transaction of the protocol.
+    #[serde(default)]
+    pub outputs: Vec<SpecTransactionOutput>,
+}
+
+/// A key derived by the key manager from its derivation index, or a given public key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpecKey {
+    pub name: String,
+    pub index: Option<u32>,
+    #[serde(default)]
+    pub key_type: SpecKeyType,
+    pub public_key: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpecKeyType {
+    P2wpkh,
+    #[default]
+    P2tr,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpecConnection {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    pub output: SpecOutput,
+    /// Set for connections from transactions outside the protocol.
+    pub txid: Option<Txid>,
+    #[serde(default = "default_spend_mode")]
+    pub spend_mode: SpendMode,
+    /// Sighash type name, e.g. `SIGHASH_ALL`. Taproot or ECDSA depending on the spent output.
+    #[serde(default = "default_sighash_type")]
+    pub sighash_type: String,
+    pub timelock: Option<u16>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpecTransactionOutput {
+    pub transaction: String,
+    pub output: SpecOutput,
+}
+
+/// A new output, optionally labeled, or an output of the transaction added before.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SpecOutput {
+    Taproot {
+        value: u64,
+        internal_key: String,
+        #[serde(default)]
+        leaves: Vec<SpecLeaf>,
+        label: Option<String>,
+    },
+    SegwitKey {
+        value: u64,
+        key: String,
+        label: Option<String>,
+    },
+    SegwitScript {
+        value: u64,
+        leaf: SpecLeaf,
+        label: Option<String>,
+    },
+    /// Unspendable output carrying the given hex data.
+    OpReturn {
+        data: String,
+    },
+    Index {
+        index: usize,
+    },
+    Label {
+        label: String,
+    },
+}
+
+/// A leaf built by one of the script templates of `crate::scripts`, by name and parameters.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "template", rename_all = "snake_case")]
+pub enum SpecLeaf {
+    CheckSignature {
+        key: String,
+        #[serde(default = "default_sign_mode")]
+        sign_mode: SignMode,
+    },
+    Timelock {
+        blocks: u16,
+        key: String,
+        #[serde(default = "default_sign_mode")]
+        sign_mode: SignMode,
+    },
+    TimelockAbsolute {
+        height: u32,
+        key: String,
+        #[serde(default = "default_sign_mode")]
+        sign_mode: SignMode,
+    },
+    /// Hex SHA-256 of the secret revealed by the spender.
+    RevealSecret {
+        hashed_secret: String,
+        key: String,
+        #[serde(default = "default_sign_mode")]
+        sign_mode: SignMode,
+    },
+    /// A script given in hex, verified with the given key.
+    Raw {
+        script: String,
+        key: String,
+        #[serde(default = "default_sign_mode")]
+        sign_mode: SignMode,
+    },
+}
+
+impl ProtocolSpec {
+    /// Parses a YAML or JSON spec.
+    pub fn parse(content: &str, format: SpecFormat) -> Result<Self, ProtocolBuilderError> {
+        let format = match format {
+            SpecFormat::Yaml => FileFormat::Yaml,
+            SpecFormat::Json => FileFormat::Json,
+        };
+
+        config::Config::builder()
+            .add_source(File::from_str(content, format))
+            .build()
+            .and_then(|settings| settings.try_deserialize())
+            .map_err(|error| ProtocolBuilderError::InvalidSpec(error.to_string()))
+    }
+
+    /// Reads a spec, in JSON for `.json` files and in YAML otherwise.
+    pub fn load(path: &Path) -> Result<Self, ProtocolBuilderError> {
+        let content = std::fs::read_to_string(path).map_err(|error| {
+            ProtocolBuilderError::InvalidSpec(format!("{}: {}", path.display(), error))
+        })?;
+
+        let format = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => SpecFormat::Json,
+            _ => SpecFormat::Yaml,
+        };
+        Self::parse(&content, format)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpecFormat {
+    Yaml,
+    Json,
+}
+
+fn default_spend_mode() -> SpendMode {
+    SpendMode::ScriptsOnly
+}
+
+fn default_sighash_type() -> String {
+    "SIGHASH_ALL".to_string()
+}
+
+fn default_sign_mode() -> SignMode {
+    SignMode::Single
+}
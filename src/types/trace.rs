@@ -0,0 +1,94 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+/// Participant used in diagrams for transactions without an assigned party.
+pub const UNASSIGNED_PARTY: &str = "Unassigned";
+
+/// Participant representing the blockchain in sequence diagrams.
+const CHAIN_PARTICIPANT: &str = "Bitcoin";
+
+/// Transaction broadcasted during a protocol execution.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TraceStep {
+    pub transaction_name: String,
+    /// Party that broadcasts the transaction.
+    pub party: String,
+    /// Block, relative to the first transaction of the trace, where the transaction is mined (or
+    /// can be mined at the earliest, for traces of a chosen branch).
+    pub block: u32,
+    /// Taproot leaf used by each input spending a script path, as (input index, leaf index).
+    pub leaves: Vec<(usize, usize)>,
+}
+
+/// Ordered transactions of a protocol execution, see `Protocol::execution_trace` and
+/// `Protocol::recorded_trace`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ExecutionTrace {
+    pub steps: Vec<TraceStep>,
+}
+
+impl ExecutionTrace {
+    /// Parties in order of first appearance.
+    pub fn parties(&self) -> Vec<&str> {
+        let mut parties: Vec<&str> = vec![];
+        for step in self.steps.iter() {
+            if !parties.contains(&step.party.as_str()) {
+                parties.push(&step.party);
+            }
+        }
+        parties
+    }
+
+    /// Exports the trace as a Mermaid sequence diagram where each party broadcasts its
+    /// transactions to the chain, with notes for the relative block of each group of
+    /// transactions.
+    pub fn to_mermaid(&self) -> String {
+        let mut diagram = String::from("sequenceDiagram\n");
+
+        for party in self.parties() {
+            writeln!(diagram, "    participant {}", party).unwrap();
+        }
+        writeln!(diagram, "    participant {}", CHAIN_PARTICIPANT).unwrap();
+
+        let mut current_block = None;
+        for step in self.steps.iter() {
+            if current_block != Some(step.block) {
+                writeln!(
+                    diagram,
+                    "    Note over {}: block +{}",
+                    CHAIN_PARTICIPANT, step.block
+                )
+                .unwrap();
+                current_block = Some(step.block);
+            }
+
+            writeln!(
+                diagram,
+                "    {}->>{}: {}{}",
+                step.party,
+                CHAIN_PARTICIPANT,
+                step.transaction_name,
+                leaves_label(&step.leaves)
+            )
+            .unwrap();
+        }
+
+        diagram
+    }
+}
+
+fn leaves_label(leaves: &[(usize, usize)]) -> String {
+    match leaves {
+        [] => String::new(),
+        [(0, leaf)] => format!(" (leaf {})", leaf),
+        _ => {
+            let leaves = leaves
+                .iter()
+                .map(|(input, leaf)| format!("input {} leaf {}", input, leaf))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(" ({})", leaves)
+        }
+    }
+}
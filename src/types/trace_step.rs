@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::ProtocolBuilderError, scripts::ProtocolScript};
+
+/// Source of the per-step scripts of a dispute over an execution trace, implemented by the
+/// prover of the VM (e.g. bitvmx-cpu) so its commitments plug into
+/// `ProtocolBuilder::connect_trace_steps` without deployment specific glue.
+///
+/// Steps are addressed by their position in the dispute, from 0 to `step_count() - 1`, which
+/// `step_number` maps to the step of the execution they commit to.
+pub trait StepCommitments {
+    /// Number of steps committed on chain in the dispute.
+    fn step_count(&self) -> usize;
+
+    /// Step of the execution committed by the given position, by default the position itself.
+    fn step_number(&self, index: usize) -> u64 {
+        index as u64
+    }
+
+    /// Leaves spent by the prover to commit the state of the given step, usually Winternitz
+    /// signatures of the step values. At least one is required.
+    fn commitment_leaves(&self, index: usize) -> Result<Vec<ProtocolScript>, ProtocolBuilderError>;
+
+    /// Leaves spent by the verifier to challenge the state committed at the given step. Steps
+    /// without challenge leaves cannot be challenged.
+    fn challenge_leaves(&self, index: usize) -> Result<Vec<ProtocolScript>, ProtocolBuilderError>;
+}
+
+/// Transactions and leaves created for a step by `ProtocolBuilder::connect_trace_steps`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TraceStep {
+    pub index: usize,
+    pub step_number: u64,
+    /// Transaction committing the step, spending the commitment leaves of the previous output.
+    pub transaction_name: String,
+    /// Transaction challenging the step, if it has challenge leaves.
+    pub challenge_name: Option<String>,
+    /// Indexes of the commitment leaves in the output spent by `transaction_name`.
+    pub commitment_leaves: Vec<usize>,
+    /// Indexes of the challenge leaves in output 0 of `transaction_name`.
+    pub challenge_leaves: Vec<usize>,
+}
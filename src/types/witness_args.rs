@@ -0,0 +1,60 @@
+use std::path::Path;
+
+use key_manager::winternitz::{WinternitzSignature, WinternitzType};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ProtocolBuilderError;
+
+/// Extra witness items for the inputs of a transaction, pushed under the taproot signature
+/// computed by the protocol, see `Protocol::input_args_for`. Inputs without an entry get no extra
+/// items.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WitnessArgs {
+    #[serde(default)]
+    pub inputs: Vec<InputWitnessArgs>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InputWitnessArgs {
+    pub input: usize,
+    /// Leaf spent by a taproot input, which is spent through the key path if no leaf is given
+    /// here or for the whole transaction.
+    pub leaf: Option<usize>,
+    #[serde(default)]
+    pub items: Vec<WitnessItem>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WitnessItem {
+    /// Raw item in hex.
+    Hex { data: String },
+    /// Winternitz signature computed elsewhere, as serialized by the key manager.
+    WinternitzSignature { signature: WinternitzSignature },
+    /// Hex message signed with the Winternitz key of the given derivation index.
+    WinternitzMessage {
+        message: String,
+        index: u32,
+        key_type: WinternitzType,
+    },
+}
+
+impl WitnessArgs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads witness args from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, ProtocolBuilderError> {
+        let content = std::fs::read_to_string(path).map_err(|error| {
+            ProtocolBuilderError::InvalidWitnessArgs(format!("{}: {}", path.display(), error))
+        })?;
+
+        serde_json::from_str(&content)
+            .map_err(|error| ProtocolBuilderError::InvalidWitnessArgs(error.to_string()))
+    }
+
+    pub fn get(&self, input: usize) -> Option<&InputWitnessArgs> {
+        self.inputs.iter().find(|args| args.input == input)
+    }
+}
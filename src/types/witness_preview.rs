@@ -0,0 +1,91 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+/// What an item of a witness is, as expected by the output it spends.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum WitnessItemRole {
+    SchnorrSignature,
+    EcdsaSignature,
+    WinternitzHash,
+    WinternitzDigit,
+    /// Item pushed by the args that the spent script does not describe, e.g. a preimage.
+    Data,
+    /// Public key of a P2WPKH spend.
+    PublicKey,
+    /// Witness script of a P2WSH spend or leaf script of a taproot script spend.
+    Script,
+    ControlBlock,
+}
+
+impl Display for WitnessItemRole {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let role = match self {
+            WitnessItemRole::SchnorrSignature => "schnorr signature",
+            WitnessItemRole::EcdsaSignature => "ecdsa signature",
+            WitnessItemRole::WinternitzHash => "winternitz hash",
+            WitnessItemRole::WinternitzDigit => "winternitz digit",
+            WitnessItemRole::Data => "data",
+            WitnessItemRole::PublicKey => "public key",
+            WitnessItemRole::Script => "script",
+            WitnessItemRole::ControlBlock => "control block",
+        };
+        write!(f, "{}", role)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PreviewedItem {
+    pub index: usize,
+    pub role: WitnessItemRole,
+    pub hex: String,
+    pub size: usize,
+}
+
+/// Witness an input would get from some args, see `Protocol::preview_witness`. Items are listed
+/// in witness order, so the first one is the bottom of the stack.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct WitnessPreview {
+    pub transaction_name: String,
+    pub input_index: usize,
+    pub items: Vec<PreviewedItem>,
+    /// Consensus encoding of the witness.
+    pub hex: String,
+}
+
+impl WitnessPreview {
+    pub fn roles(&self) -> Vec<WitnessItemRole> {
+        self.items.iter().map(|item| item.role).collect()
+    }
+
+    /// Items as rows of 32 bytes, each prefixed with its offset in the item.
+    pub fn hex_dump(&self) -> String {
+        let mut dump = String::new();
+        for item in self.items.iter() {
+            dump.push_str(&format!(
+                "#{} {} ({} bytes)\n",
+                item.index, item.role, item.size
+            ));
+            for (row, chunk) in item.hex.as_bytes().chunks(64).enumerate() {
+                // Hex strings are ascii
+                let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+                dump.push_str(&format!("  {:04x}  {}\n", row * 32, chunk));
+            }
+        }
+        dump
+    }
+}
+
+impl Display for WitnessPreview {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "witness of input {} of {}: {} items",
+            self.input_index,
+            self.transaction_name,
+            self.items.len()
+        )?;
+        write!(f, "{}", self.hex_dump())?;
+        write!(f, "hex: {}", self.hex)
+    }
+}
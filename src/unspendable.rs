@@ -1,22 +1,74 @@
 use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
     key::{rand::Rng, Parity, Secp256k1},
     secp256k1::{self, SecretKey},
-    PublicKey,
+    PublicKey, XOnlyPublicKey,
 };
 
 use crate::errors::UnspendableKeyError;
 
 const H: &str = "0250929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
 
-// TODO: Each taptree will have a different random unspendable key. We need both participants
-// to use the same unspendable keys. We can use a deterministic RNG and share the seed at the
-// beginning between the participants to generate the same keys.
+// BIP-340 style tag of the hash deriving r for deterministic unspendable keys
+const DETERMINISTIC_TAG: &[u8] = b"BitVMX/UnspendableKey";
+
+/// Generates a random unspendable key. Parties that must derive the same keys independently
+/// use `deterministic_unspendable_key` instead.
 pub fn unspendable_key<R: Rng + ?Sized>(rng: &mut R) -> Result<PublicKey, UnspendableKeyError> {
-    // Initialize the secp256k1 context
-    let secp = Secp256k1::new();
+    let (unspendable_key, _) = unspendable_key_with_derivation(rng)?;
+    Ok(unspendable_key)
+}
 
+/// Generates an unspendable key H + r * G and returns it together with r, the derivation data a
+/// counterparty needs to check it with `verify_unspendable`.
+pub fn unspendable_key_with_derivation<R: Rng + ?Sized>(
+    rng: &mut R,
+) -> Result<(PublicKey, [u8; 32]), UnspendableKeyError> {
     // Generate a random scalar (secret key) r using a cryptographically secure RNG
     let r = SecretKey::new(rng);
+    Ok((nums_point(&r)?, r.secret_bytes()))
+}
+
+/// Derives the unspendable key H + r * G with r the tagged hash of `context`, so every party
+/// derives the same key from the same context without exchanging randomness. Returns the key with
+/// r, which `verify_unspendable` accepts as derivation data.
+pub fn deterministic_unspendable_key(
+    context: &[u8],
+) -> Result<(PublicKey, [u8; 32]), UnspendableKeyError> {
+    let tag = sha256::Hash::hash(DETERMINISTIC_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    engine.input(context);
+    let tweak = sha256::Hash::from_engine(engine);
+
+    let r = SecretKey::from_slice(tweak.as_ref()).map_err(|_| {
+        UnspendableKeyError::FailedToBuildUnspendableKey {
+            reason: "Context hash is not a valid scalar".to_string(),
+        }
+    })?;
+    Ok((nums_point(&r)?, r.secret_bytes()))
+}
+
+/// Checks that a taproot internal key is the NUMS point H + r * G for the given r, so nobody
+/// knows its discrete logarithm and the key path cannot be spent.
+pub fn verify_unspendable(
+    key: &XOnlyPublicKey,
+    derivation_data: &[u8; 32],
+) -> Result<bool, UnspendableKeyError> {
+    let r = SecretKey::from_slice(derivation_data).map_err(|_| {
+        UnspendableKeyError::FailedToBuildUnspendableKey {
+            reason: "Invalid derivation data".to_string(),
+        }
+    })?;
+
+    let (expected, _) = nums_point(&r)?.inner.x_only_public_key();
+    Ok(expected == *key)
+}
+
+fn nums_point(r: &SecretKey) -> Result<PublicKey, UnspendableKeyError> {
+    // Initialize the secp256k1 context
+    let secp = Secp256k1::new();
 
     // Convert H value to byte array
     let h = hex::decode(H).map_err(|_| UnspendableKeyError::HexDecodeError)?;
@@ -29,7 +81,7 @@ pub fn unspendable_key<R: Rng + ?Sized>(rng: &mut R) -> Result<PublicKey, Unspen
     })?;
 
     // Compute r * G, which gives a point on the curve
-    let r_times_g = secp256k1::PublicKey::from_secret_key(&secp, &r);
+    let r_times_g = secp256k1::PublicKey::from_secret_key(&secp, r);
 
     // Add H and r * G together to compute H + r * G
     let result = h_point.combine(&r_times_g).map_err(|_| {